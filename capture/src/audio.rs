@@ -1,8 +1,13 @@
-use crate::{AudioCaptureSourceDescription, CaptureHandler, Source, SourceType};
+use crate::{AudioCaptureSourceDescription, CaptureHandler, FrameArrived, Source, SourceType};
 
-use cpal::{traits::*, Host, Stream, StreamConfig};
-use hylarana_common::frame::AudioFrame;
-use hylarana_resample::AudioResampler;
+use cpal::{traits::*, Device, Host, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig};
+
+use hylarana_common::{
+    clock::MediaClock,
+    frame::{AudioFrame, VideoRotation},
+};
+
+use hylarana_resample::{AudioResampler, AudioSample};
 use once_cell::sync::Lazy;
 use parking_lot::Mutex;
 use thiserror::Error;
@@ -21,6 +26,12 @@ pub enum AudioCaptureError {
     #[error(transparent)]
     DefaultStreamConfigError(#[from] cpal::DefaultStreamConfigError),
     #[error(transparent)]
+    SupportedStreamConfigsError(#[from] cpal::SupportedStreamConfigsError),
+    #[error("the audio device does not support the sample rate or channels")]
+    NotSupportedConfig,
+    #[error("the audio device does not support the sample format, format={0}")]
+    NotSupportedSampleFormat(SampleFormat),
+    #[error(transparent)]
     BuildStreamError(#[from] cpal::BuildStreamError),
     #[error(transparent)]
     PlayStreamError(#[from] cpal::PlayStreamError),
@@ -64,6 +75,8 @@ impl CaptureHandler for AudioCapture {
                 name: device.name()?,
                 kind: SourceType::Audio,
                 is_default: device.name().ok() == default_name,
+                rotation: VideoRotation::Rotate0,
+                backend: None,
                 index,
             });
         }
@@ -71,10 +84,10 @@ impl CaptureHandler for AudioCapture {
         Ok(sources)
     }
 
-    fn start<S: crate::FrameArrived<Frame = Self::Frame> + 'static>(
+    fn start<S: FrameArrived<Frame = Self::Frame> + 'static>(
         &self,
         options: Self::CaptureOptions,
-        mut arrived: S,
+        arrived: S,
     ) -> Result<(), Self::Error> {
         // Find devices with matching names
         let (device, kind) = HOST
@@ -88,57 +101,53 @@ impl CaptureHandler for AudioCapture {
             })
             .ok_or_else(|| AudioCaptureError::NotFoundAudioSource)?;
 
-        let config: StreamConfig = match kind {
-            DeviceKind::Input => device.default_input_config()?.into(),
-            DeviceKind::Output => device.default_output_config()?.into(),
+        // The device is opened in its own sample format, some interfaces refuse the
+        // 16-bit streams in shared mode, the samples are converted in the resampler.
+        let default = match kind {
+            DeviceKind::Input => device.default_input_config()?,
+            DeviceKind::Output => device.default_output_config()?,
         };
 
-        let mut frame = AudioFrame::default();
-        frame.sample_rate = options.sample_rate;
-
-        let mut playing = true;
-        let mut resampler = None;
-        let stream = device.build_input_stream(
-            &config,
-            move |data: &[i16], _| {
-                // When any problem occurs in the process, you should not continue processing.
-                // If the cpal bottom layer continues to push audio samples, it should be
-                // ignored here and the process should not continue.
-                if !playing {
-                    return;
-                }
+        let format = default.sample_format();
+        let mut config: StreamConfig = default.into();
+        if let Some(sample_rate) = options.config.sample_rate {
+            config.sample_rate = SampleRate(sample_rate);
+        }
 
-                // Creating a resampler requires knowing the fixed number of input samples, but
-                // in cpal the number of samples can only be known after the first frame is
-                // obtained. There may be a question here, whether the number of
-                // samples for each sample is fixed. It is currently observed that it is fixed,
-                // so the default number of samples is fixed here.
-                if resampler.is_none() {
-                    if let Ok(sampler) = AudioResampler::new(
-                        config.sample_rate.0 as f64,
-                        options.sample_rate as f64,
-                        data.len() / config.channels as usize,
-                    ) {
-                        resampler = Some(sampler);
-                    }
-                }
+        if let Some(channels) = options.config.channels {
+            config.channels = channels;
+        }
 
-                if let Some(sampler) = &mut resampler {
-                    if let Ok(sample) = sampler.resample(data, config.channels.into()) {
-                        frame.frames = sample.len() as u32;
-                        frame.data = sample.as_ptr();
+        if options.config != Default::default() {
+            let mut supported = match kind {
+                DeviceKind::Input => device.supported_input_configs()?.collect::<Vec<_>>(),
+                DeviceKind::Output => device.supported_output_configs()?.collect::<Vec<_>>(),
+            }
+            .into_iter();
+
+            if !supported.any(|it| {
+                it.channels() == config.channels
+                    && it.sample_format() == format
+                    && it.min_sample_rate() <= config.sample_rate
+                    && it.max_sample_rate() >= config.sample_rate
+            }) {
+                return Err(AudioCaptureError::NotSupportedConfig);
+            }
+        }
 
-                        playing = arrived.sink(&frame);
-                    }
-                }
-            },
-            |e| {
-                // An error has occurred, but there is nothing you can do at this moment except
-                // output the error log.
-                log::error!("audio capture callback error={:?}", e);
-            },
-            None,
-        )?;
+        log::info!(
+            "audio capture open device, name={}, format={}, config={:?}",
+            options.source.name,
+            format,
+            config
+        );
+
+        let stream = match format {
+            SampleFormat::I16 => build_stream::<i16, _>(&device, config, options, arrived)?,
+            SampleFormat::I32 => build_stream::<i32, _>(&device, config, options, arrived)?,
+            SampleFormat::F32 => build_stream::<f32, _>(&device, config, options, arrived)?,
+            format => return Err(AudioCaptureError::NotSupportedSampleFormat(format)),
+        };
 
         stream.play()?;
 
@@ -160,3 +169,70 @@ impl CaptureHandler for AudioCapture {
         Ok(())
     }
 }
+
+fn build_stream<T, S>(
+    device: &Device,
+    config: StreamConfig,
+    options: AudioCaptureSourceDescription,
+    mut arrived: S,
+) -> Result<Stream, AudioCaptureError>
+where
+    T: SizedSample + AudioSample,
+    S: FrameArrived<Frame = AudioFrame> + 'static,
+{
+    let mut frame = AudioFrame::default();
+    frame.sample_rate = options.sample_rate;
+
+    let channels = config.channels as usize;
+    let sample_rate = config.sample_rate.0 as f64;
+
+    let mut playing = true;
+    let mut resampler = None;
+    Ok(device.build_input_stream(
+        &config,
+        move |data: &[T], _| {
+            // When any problem occurs in the process, you should not continue processing.
+            // If the cpal bottom layer continues to push audio samples, it should be
+            // ignored here and the process should not continue.
+            if !playing {
+                return;
+            }
+
+            // Creating a resampler requires knowing the fixed number of input samples, but
+            // in cpal the number of samples can only be known after the first frame is
+            // obtained. There may be a question here, whether the number of
+            // samples for each sample is fixed. It is currently observed that it is fixed,
+            // so the default number of samples is fixed here.
+            if resampler.is_none() {
+                if let Ok(sampler) = AudioResampler::new(
+                    sample_rate,
+                    options.sample_rate as f64,
+                    data.len() / channels,
+                ) {
+                    resampler = Some(sampler);
+                }
+            }
+
+            if let Some(sampler) = &mut resampler {
+                if let Ok(sample) = sampler.resample(data, channels) {
+                    frame.frames = sample.len() as u32;
+                    frame.data = sample.as_ptr();
+
+                    // The callback is called when the buffer is filled, the first sample
+                    // was captured one buffer duration ago.
+                    frame.timestamp = MediaClock::now().saturating_sub(
+                        sample.len() as u64 * 1_000_000 / options.sample_rate.max(1) as u64,
+                    );
+
+                    playing = arrived.sink(&frame);
+                }
+            }
+        },
+        |e| {
+            // An error has occurred, but there is nothing you can do at this moment except
+            // output the error log.
+            log::error!("audio capture callback error={:?}", e);
+        },
+        None,
+    )?)
+}
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use hylarana_common::frame::{AudioFrame, VideoFrame};
+use once_cell::sync::Lazy;
+use parking_lot::RwLock;
+
+use crate::{
+    AudioCapture, AudioCaptureSourceDescription, CameraCapture, CaptureError, CaptureHandler,
+    FrameArrived, ScreenCapture, Source, SourceType, VideoCaptureSourceDescription,
+};
+
+/// The name of the built-in backend of the screens.
+pub const SCREEN_BACKEND: &str = "screen";
+
+/// The name of the built-in backend of the cameras.
+pub const CAMERA_BACKEND: &str = "camera";
+
+/// The name of the built-in backend of the audio devices.
+pub const AUDIO_BACKEND: &str = "audio";
+
+/// A provider of capture sources, such as the screens of the system, or the
+/// inputs of a capture card that is supported by another crate.
+///
+/// The screen, camera and audio captures of this crate are backends as well,
+/// and the backends that are registered with `Capture::register_backend` are
+/// used in the same way, see `Capture::get_sources` and `Capture::start`.
+pub trait CaptureBackend: Sync + Send {
+    /// The unique name of the backend, the sources of the backend carry it so
+    /// that they are captured by the backend that listed them.
+    fn name(&self) -> &str;
+
+    /// Get the sources of a type, the backend returns an empty list for the
+    /// types that it does not provide.
+    fn sources(&self, kind: SourceType) -> Result<Vec<Source>, CaptureError>;
+
+    /// Start capturing a video source of the backend, the capture runs on its
+    /// own until the returned stream is closed.
+    #[allow(unused_variables)]
+    fn start_video(
+        &self,
+        description: VideoCaptureSourceDescription,
+        arrived: Box<dyn FrameArrived<Frame = VideoFrame>>,
+    ) -> Result<Box<dyn CaptureStream>, CaptureError> {
+        Err(CaptureError::NotSupported(self.name().to_string()))
+    }
+
+    /// Start capturing an audio source of the backend, the capture runs on its
+    /// own until the returned stream is closed.
+    #[allow(unused_variables)]
+    fn start_audio(
+        &self,
+        description: AudioCaptureSourceDescription,
+        arrived: Box<dyn FrameArrived<Frame = AudioFrame>>,
+    ) -> Result<Box<dyn CaptureStream>, CaptureError> {
+        Err(CaptureError::NotSupported(self.name().to_string()))
+    }
+}
+
+/// A source that is being captured by a backend.
+pub trait CaptureStream: Sync + Send {
+    /// Stop capturing, no frames are passed to the sink after this returns.
+    fn close(&self) -> Result<(), CaptureError>;
+}
+
+impl<T> CaptureStream for T
+where
+    T: CaptureHandler,
+    CaptureError: From<T::Error>,
+{
+    fn close(&self) -> Result<(), CaptureError> {
+        Ok(self.stop()?)
+    }
+}
+
+impl CaptureBackend for ScreenCapture {
+    fn name(&self) -> &str {
+        SCREEN_BACKEND
+    }
+
+    fn sources(&self, kind: SourceType) -> Result<Vec<Source>, CaptureError> {
+        Ok(match kind {
+            SourceType::Screen => Self::get_sources()?,
+            _ => Vec::new(),
+        })
+    }
+
+    fn start_video(
+        &self,
+        description: VideoCaptureSourceDescription,
+        arrived: Box<dyn FrameArrived<Frame = VideoFrame>>,
+    ) -> Result<Box<dyn CaptureStream>, CaptureError> {
+        let capture = Self::default();
+        capture.start(description, arrived)?;
+        Ok(Box::new(capture))
+    }
+}
+
+impl CaptureBackend for CameraCapture {
+    fn name(&self) -> &str {
+        CAMERA_BACKEND
+    }
+
+    fn sources(&self, kind: SourceType) -> Result<Vec<Source>, CaptureError> {
+        Ok(match kind {
+            SourceType::Camera => Self::get_sources()?,
+            _ => Vec::new(),
+        })
+    }
+
+    fn start_video(
+        &self,
+        description: VideoCaptureSourceDescription,
+        arrived: Box<dyn FrameArrived<Frame = VideoFrame>>,
+    ) -> Result<Box<dyn CaptureStream>, CaptureError> {
+        let capture = Self::default();
+        capture.start(description, arrived)?;
+        Ok(Box::new(capture))
+    }
+}
+
+impl CaptureBackend for AudioCapture {
+    fn name(&self) -> &str {
+        AUDIO_BACKEND
+    }
+
+    fn sources(&self, kind: SourceType) -> Result<Vec<Source>, CaptureError> {
+        Ok(match kind {
+            SourceType::Audio => Self::get_sources()?,
+            _ => Vec::new(),
+        })
+    }
+
+    fn start_audio(
+        &self,
+        description: AudioCaptureSourceDescription,
+        arrived: Box<dyn FrameArrived<Frame = AudioFrame>>,
+    ) -> Result<Box<dyn CaptureStream>, CaptureError> {
+        let capture = Self::default();
+        capture.start(description, arrived)?;
+        Ok(Box::new(capture))
+    }
+}
+
+struct Backend {
+    backend: Arc<dyn CaptureBackend>,
+    // The errors of the built-in backends are returned, the errors of the
+    // registered backends only hide their own sources.
+    builtin: bool,
+}
+
+static BACKENDS: Lazy<RwLock<Vec<Backend>>> = Lazy::new(|| {
+    let builtin = |backend: Arc<dyn CaptureBackend>| Backend {
+        builtin: true,
+        backend,
+    };
+
+    RwLock::new(vec![
+        builtin(Arc::new(ScreenCapture::default())),
+        builtin(Arc::new(CameraCapture::default())),
+        builtin(Arc::new(AudioCapture::default())),
+    ])
+});
+
+// A backend with the same name is replaced, so a built-in backend can be
+// replaced as well.
+pub(crate) fn register(backend: Arc<dyn CaptureBackend>) {
+    log::info!("capture register backend, name={}", backend.name());
+
+    let mut backends = BACKENDS.write();
+    backends.retain(|it| it.backend.name() != backend.name());
+    backends.push(Backend {
+        builtin: false,
+        backend,
+    });
+}
+
+pub(crate) fn unregister(name: &str) -> bool {
+    log::info!("capture unregister backend, name={}", name);
+
+    let mut backends = BACKENDS.write();
+    let count = backends.len();
+    backends.retain(|it| it.backend.name() != name);
+    backends.len() != count
+}
+
+pub(crate) fn get_sources(kind: SourceType) -> Result<Vec<Source>, CaptureError> {
+    // The backends are listed without the lock, so that a backend can register
+    // another one while it lists its sources.
+    let backends = BACKENDS
+        .read()
+        .iter()
+        .map(|it| (it.backend.clone(), it.builtin))
+        .collect::<Vec<_>>();
+
+    let mut sources = Vec::new();
+    for (backend, builtin) in backends {
+        match backend.sources(kind) {
+            Ok(items) => {
+                sources.extend(items.into_iter().map(|mut it| {
+                    it.backend = Some(backend.name().to_string());
+                    it
+                }));
+            }
+            Err(e) if builtin => return Err(e),
+            Err(e) => {
+                log::warn!(
+                    "capture backend failed to get sources, name={}, err={:?}",
+                    backend.name(),
+                    e
+                );
+            }
+        }
+    }
+
+    Ok(sources)
+}
+
+// The sources without a backend are from before the backends were added, such
+// as the sources that are passed through the ffi, they belong to the built-in
+// backend of their type.
+pub(crate) fn find(source: &Source) -> Result<Arc<dyn CaptureBackend>, CaptureError> {
+    let name = match &source.backend {
+        Some(name) => name.as_str(),
+        None => match source.kind {
+            SourceType::Screen => SCREEN_BACKEND,
+            SourceType::Camera => CAMERA_BACKEND,
+            SourceType::Audio => AUDIO_BACKEND,
+        },
+    };
+
+    BACKENDS
+        .read()
+        .iter()
+        .find(|it| it.backend.name() == name)
+        .map(|it| it.backend.clone())
+        .ok_or_else(|| CaptureError::BackendNotFound(name.to_string()))
+}
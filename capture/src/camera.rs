@@ -0,0 +1,86 @@
+use hylarana_common::Size;
+use serde::{Deserialize, Serialize};
+
+/// The pixel format of the frames that a camera delivers in a mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraPixelFormat {
+    NV12,
+    YUYV,
+    MJPEG,
+    /// A format that the capture does not convert, such as H264 of some
+    /// webcams, the modes of this format are never picked.
+    Other,
+}
+
+/// A mode that a camera can capture in, see `Capture::get_camera_formats`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CameraFormat {
+    pub size: Size,
+    /// The highest frame rate of the mode.
+    pub fps: f64,
+    pub pixel_format: CameraPixelFormat,
+}
+
+/// The controls of a camera that can be changed through `CameraControls`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraControl {
+    Exposure,
+    Focus,
+    Zoom,
+}
+
+/// The values that a control of a camera accepts. The values are in the
+/// units of the driver, which differ between the platforms and the devices,
+/// such as the exposure in log2 seconds on windows and in 100 µs on linux.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CameraControlRange {
+    pub min: i32,
+    pub max: i32,
+    pub step: i32,
+    pub default: i32,
+    /// Whether the camera can adjust the control by itself.
+    pub auto: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraControlValue {
+    /// The camera adjusts the control by itself.
+    Auto,
+    Manual(i32),
+}
+
+// Picks the mode that is closest to the size and the frame rate of the capture,
+// out of the pixel formats that the platform converts, which are given in the
+// order of preference. A mode that reaches the frame rate goes before a mode
+// that reaches the size, the frames are scaled to the size anyway, and the
+// smallest mode that covers the size goes before the larger ones.
+#[cfg(not(target_os = "macos"))]
+pub(crate) fn select_format(
+    formats: &[CameraFormat],
+    size: Size,
+    fps: u8,
+    accepted: &[CameraPixelFormat],
+) -> Option<usize> {
+    let area = |size: Size| size.width as u64 * size.height as u64;
+
+    formats
+        .iter()
+        .enumerate()
+        .filter_map(|(index, format)| {
+            let preference = accepted.iter().position(|it| *it == format.pixel_format)?;
+            let covers = format.size.width >= size.width && format.size.height >= size.height;
+
+            Some((
+                (
+                    format.fps + 0.5 < fps as f64,
+                    !covers,
+                    area(format.size).abs_diff(area(size)),
+                    preference,
+                    ((format.fps - fps as f64).abs() * 100.0) as u64,
+                ),
+                index,
+            ))
+        })
+        .min_by_key(|(key, _)| *key)
+        .map(|(_, index)| index)
+}
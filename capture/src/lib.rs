@@ -1,48 +1,81 @@
 mod audio;
+mod backend;
+mod camera;
+mod mixer;
+mod pacing;
 
 #[cfg(target_os = "windows")]
 mod win32 {
     pub mod camera;
+    pub mod display;
+    pub mod input;
+    pub mod permission;
     pub mod screen;
 }
 
 #[cfg(target_os = "linux")]
 mod linux {
     pub mod camera;
+    pub mod input;
+    pub mod permission;
     pub mod screen;
 }
 
 #[cfg(target_os = "macos")]
 mod macos {
     pub mod camera;
+    pub mod input;
+    pub mod permission;
     pub mod screen;
 }
 
-pub use self::audio::{AudioCapture, AudioCaptureError};
+pub use self::{
+    audio::{AudioCapture, AudioCaptureError},
+    backend::{CaptureBackend, CaptureStream, AUDIO_BACKEND, CAMERA_BACKEND, SCREEN_BACKEND},
+    camera::{
+        CameraControl, CameraControlRange, CameraControlValue, CameraFormat, CameraPixelFormat,
+    },
+    mixer::{AudioMixer, AudioMixerInput, AudioMixerTrack},
+    pacing::FramePacer,
+};
 
 #[cfg(target_os = "windows")]
 pub use self::win32::{
-    camera::{CameraCapture, CameraCaptureError},
+    camera::{CameraCapture, CameraCaptureError, CameraControls},
+    display::{VirtualDisplay, VirtualDisplayError},
     screen::{ScreenCapture, ScreenCaptureError},
 };
 
+#[cfg(target_os = "windows")]
+use self::win32::{input, permission};
+
 #[cfg(target_os = "linux")]
 pub use self::linux::{
-    camera::{CameraCapture, CameraCaptureError},
+    camera::{CameraCapture, CameraCaptureError, CameraControls},
     screen::{ScreenCapture, ScreenCaptureError},
 };
 
+#[cfg(target_os = "linux")]
+use self::linux::{input, permission};
+
 #[cfg(target_os = "macos")]
 pub use self::macos::{
-    camera::{CameraCapture, CameraCaptureError},
+    camera::{CameraCapture, CameraCaptureError, CameraControls},
     screen::{ScreenCapture, ScreenCaptureError},
 };
 
+#[cfg(target_os = "macos")]
+use self::macos::{input, permission};
+
 use hylarana_common::{
-    frame::{AudioFrame, VideoFrame},
+    frame::{AudioFrame, VideoFrame, VideoRotation},
+    input::InputEvent,
     Size,
 };
 
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[cfg(target_os = "windows")]
@@ -63,6 +96,13 @@ pub enum CaptureError {
     ScreenCaptureError(#[from] ScreenCaptureError),
     #[error(transparent)]
     CameraCaptureError(#[from] CameraCaptureError),
+    #[error("capture backend not found, name={0}")]
+    BackendNotFound(String),
+    #[error("capture backend does not support the source type, name={0}")]
+    NotSupported(String),
+    /// The errors of the backends that are registered by other crates.
+    #[error(transparent)]
+    BackendError(#[from] Box<dyn std::error::Error + Send + Sync>),
 }
 
 pub trait FrameArrived: Sync + Send {
@@ -72,6 +112,35 @@ pub trait FrameArrived: Sync + Send {
     /// This method is called when the capture source captures new data. If it
     /// returns false, the source stops capturing.
     fn sink(&mut self, frame: &Self::Frame) -> bool;
+
+    /// This method is called when the state of the capture source changes,
+    /// such as the display being unplugged.
+    #[allow(unused_variables)]
+    fn event(&mut self, event: SourceEvent) {}
+}
+
+impl<T: FrameArrived + ?Sized> FrameArrived for Box<T> {
+    type Frame = T::Frame;
+
+    fn sink(&mut self, frame: &Self::Frame) -> bool {
+        (**self).sink(frame)
+    }
+
+    fn event(&mut self, event: SourceEvent) {
+        (**self).event(event)
+    }
+}
+
+/// Changes of the capture source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SourceEvent {
+    /// The size of the source has changed, such as the resolution of the
+    /// display, the capture is restarted internally, and the frames keep the
+    /// configured size.
+    Resized(Size),
+    /// The source has been removed, such as the display being unplugged, and
+    /// the capture is stopped.
+    Removed,
 }
 
 pub trait CaptureHandler: Sync + Send {
@@ -100,7 +169,7 @@ pub trait CaptureHandler: Sync + Send {
 }
 
 /// Video source type or Audio source type.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SourceType {
     /// Camera or video capture card and other devices (and support virtual
     /// camera)
@@ -112,8 +181,23 @@ pub enum SourceType {
     Audio,
 }
 
+/// Whether the process is allowed to capture a type of source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionState {
+    Granted,
+    /// The user has denied the permission, it can only be changed in the
+    /// settings of the system.
+    Denied,
+    /// The user has not been asked yet, the system asks the user when the
+    /// permission is requested or the source is captured for the first time.
+    NotDetermined,
+    /// The source cannot be captured in the current environment, such as the
+    /// screen in a wayland session.
+    Unsupported,
+}
+
 /// Video source or Audio source.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Source {
     /// Device ID, usually the symbolic link to the device or the address of the
     /// device file handle.
@@ -127,6 +211,14 @@ pub struct Source {
     /// Whether or not it is the default device, normally used to indicate
     /// whether or not it is the master device.
     pub is_default: bool,
+    /// The clockwise rotation that needs to be applied to the pictures of this
+    /// source to display them upright, only meaningful for video sources.
+    pub rotation: VideoRotation,
+    /// The name of the backend that provides the source, it is filled in by
+    /// `Capture::get_sources`. `None` is taken as the built-in backend of the
+    /// type of the source.
+    #[serde(default)]
+    pub backend: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -137,15 +229,40 @@ pub struct VideoCaptureSourceDescription {
     /// not, it should be noted that internally it will just output hardware
     /// frames to the best of its ability and may also output software frames.
     pub hardware: bool,
+    /// Capture the source as 10-bit HDR10 (P010) frames, this is currently only
+    /// supported by the screen capture on windows, other sources ignore it and
+    /// output 8-bit frames.
+    pub hdr: bool,
     pub source: Source,
     pub size: Size,
+    /// The frame rate of the output frames, the frames that arrive faster than
+    /// this are dropped.
     pub fps: u8,
+    /// Reduce the capture rate when the sink cannot keep up with the frame
+    /// rate, such as when the encoder queue backs up.
+    pub adaptive_pacing: bool,
+}
+
+/// The configuration that an audio device is opened with, the device is
+/// opened in its own sample format, and the samples are resampled to the
+/// sample rate of the description anyway.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AudioCaptureConfig {
+    /// The sample rate of the device, `None` uses the default rate of the
+    /// device.
+    #[serde(default)]
+    pub sample_rate: Option<u32>,
+    /// The number of channels of the device, only the first channel is
+    /// captured. `None` uses the default channels of the device.
+    #[serde(default)]
+    pub channels: Option<u16>,
 }
 
 #[derive(Debug, Clone)]
 pub struct AudioCaptureSourceDescription {
     pub source: Source,
     pub sample_rate: u32,
+    pub config: AudioCaptureConfig,
 }
 
 pub struct SourceCaptureOptions<T, P> {
@@ -159,7 +276,9 @@ where
     A: FrameArrived<Frame = AudioFrame>,
 {
     pub video: Option<SourceCaptureOptions<V, VideoCaptureSourceDescription>>,
-    pub audio: Option<SourceCaptureOptions<A, AudioCaptureSourceDescription>>,
+    /// Multiple audio sources can be captured at the same time, such as the
+    /// system audio and a microphone, each source has its own sink.
+    pub audio: Vec<SourceCaptureOptions<A, AudioCaptureSourceDescription>>,
 }
 
 impl<V, A> Default for CaptureOptions<V, A>
@@ -170,34 +289,94 @@ where
     fn default() -> Self {
         Self {
             video: None,
-            audio: None,
+            audio: Vec::new(),
         }
     }
 }
 
-enum CaptureImplement {
-    Camera(CameraCapture),
-    Screen(ScreenCapture),
-    Audio(AudioCapture),
-}
-
 /// Capture implementations for audio devices and video devices.
 #[derive(Default)]
-pub struct Capture(Vec<CaptureImplement>);
+pub struct Capture(Vec<Box<dyn CaptureStream>>);
 
 impl Capture {
+    /// Register a capture backend, its sources are listed by `get_sources`
+    /// and captured by `start` like the sources of the built-in backends. A
+    /// backend with the same name is replaced, including the built-in ones.
+    pub fn register_backend<T: CaptureBackend + 'static>(backend: T) {
+        backend::register(Arc::new(backend));
+    }
+
+    /// Remove a capture backend by its name, returns whether it was
+    /// registered. The captures that have been started are not stopped.
+    pub fn unregister_backend(name: &str) -> bool {
+        backend::unregister(name)
+    }
+
     /// Get all sources that can be used for capture by specifying the type,
     /// which is usually an audio or video device.
-    #[allow(unreachable_patterns)]
+    ///
+    /// The sources of all backends are listed, the built-in backends come
+    /// first. A registered backend that fails only leaves out its own sources.
     pub fn get_sources(kind: SourceType) -> Result<Vec<Source>, CaptureError> {
         log::info!("capture get sources, kind={:?}", kind);
 
-        Ok(match kind {
-            SourceType::Camera => CameraCapture::get_sources()?,
-            SourceType::Screen => ScreenCapture::get_sources()?,
-            SourceType::Audio => AudioCapture::get_sources()?,
-            _ => Vec::new(),
-        })
+        backend::get_sources(kind)
+    }
+
+    /// Get the modes that a camera can capture in, the capture picks the mode
+    /// that is closest to the size and the frame rate of the description.
+    pub fn get_camera_formats(source: &Source) -> Result<Vec<CameraFormat>, CaptureError> {
+        let formats = CameraCapture::get_formats(source)?;
+        log::info!(
+            "capture get camera formats, id={}, formats={:?}",
+            source.id,
+            formats
+        );
+
+        Ok(formats)
+    }
+
+    /// Open the controls of a camera, such as the exposure and the focus, the
+    /// controls can be changed while the camera is captured.
+    pub fn get_camera_controls(source: &Source) -> Result<CameraControls, CaptureError> {
+        Ok(CameraCapture::get_controls(source)?)
+    }
+
+    /// Check whether the process is allowed to capture the type of source
+    /// without prompting the user, for audio this is the permission of the
+    /// microphone.
+    pub fn check_permissions(kind: SourceType) -> PermissionState {
+        let state = permission::check(kind);
+        log::info!(
+            "capture check permissions, kind={:?}, state={:?}",
+            kind,
+            state
+        );
+
+        state
+    }
+
+    /// Request the permission to capture the type of source, this shows the
+    /// prompt of the system if the platform has one, otherwise it is the same
+    /// as `check_permissions`.
+    pub fn request_permissions(kind: SourceType) -> PermissionState {
+        let state = permission::request(kind);
+        log::info!(
+            "capture request permissions, kind={:?}, state={:?}",
+            kind,
+            state
+        );
+
+        state
+    }
+
+    /// Inject a mouse or keyboard event into the system, as if it came from
+    /// the local input devices.
+    ///
+    /// On linux this uses the XTest extension of x11, and on macos the process
+    /// needs the accessibility permission.
+    pub fn inject_input(event: &InputEvent) -> Result<(), std::io::Error> {
+        input::inject(event)
     }
 
     /// Create a capture and start capturing audio and video frames by
@@ -209,36 +388,24 @@ impl Capture {
         V: FrameArrived<Frame = VideoFrame> + 'static,
         A: FrameArrived<Frame = AudioFrame> + 'static,
     {
-        let mut devices = Vec::with_capacity(3);
+        let mut devices = Vec::with_capacity(audio.len() + 1);
 
         if let Some(SourceCaptureOptions {
             description,
             arrived,
         }) = video
         {
-            match description.source.kind {
-                SourceType::Camera => {
-                    let camera = CameraCapture::default();
-                    camera.start(description, arrived)?;
-                    devices.push(CaptureImplement::Camera(camera));
-                }
-                SourceType::Screen => {
-                    let screen = ScreenCapture::default();
-                    screen.start(description, arrived)?;
-                    devices.push(CaptureImplement::Screen(screen));
-                }
-                _ => (),
-            }
+            let backend = backend::find(&description.source)?;
+            devices.push(backend.start_video(description, Box::new(arrived))?);
         }
 
-        if let Some(SourceCaptureOptions {
+        for SourceCaptureOptions {
             description,
             arrived,
-        }) = audio
+        } in audio
         {
-            let audio = AudioCapture::default();
-            audio.start(description, arrived)?;
-            devices.push(CaptureImplement::Audio(audio));
+            let backend = backend::find(&description.source)?;
+            devices.push(backend.start_audio(description, Box::new(arrived))?);
         }
 
         Ok(Self(devices))
@@ -247,11 +414,7 @@ impl Capture {
     /// Stop capturing and turn off internal audio/video frame pushing.
     pub fn close(&self) -> Result<(), CaptureError> {
         for item in self.0.iter() {
-            match item {
-                CaptureImplement::Screen(it) => it.stop()?,
-                CaptureImplement::Camera(it) => it.stop()?,
-                CaptureImplement::Audio(it) => it.stop()?,
-            };
+            item.close()?;
         }
 
         log::info!("close capture");
@@ -1,14 +1,19 @@
-use crate::{CaptureHandler, FrameArrived, Source, SourceType, VideoCaptureSourceDescription};
+use crate::{
+    camera::select_format, CameraControl, CameraControlRange, CameraControlValue, CameraFormat,
+    CameraPixelFormat, CaptureHandler, FrameArrived, FramePacer, Source, SourceType,
+    VideoCaptureSourceDescription,
+};
 
 use std::{
     ptr::{null, null_mut},
     sync::{atomic::AtomicBool, Arc},
-    thread,
+    time::Instant,
 };
 
 use hylarana_common::{
     atomic::EasyAtomic,
-    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    frame::{FieldOrder, VideoFormat, VideoFrame, VideoRotation, VideoSubFormat},
+    runtime::{self, ThreadKind},
     Size,
 };
 
@@ -18,22 +23,225 @@ use v4l::{
     buffer::Type,
     capability::Flags,
     context::enum_devices,
+    control::{Control, Description, Value},
+    format::{FieldOrder as V4lFieldOrder, Format},
+    frameinterval::FrameIntervalEnum,
+    framesize::FrameSizeEnum,
     io::{mmap::stream::Stream, traits::CaptureStream},
-    video::Capture,
+    video::{capture::Parameters, Capture},
     Device, FourCC,
 };
 
+// The controls of the camera class of v4l2.
+const V4L2_CID_EXPOSURE_AUTO: u32 = 0x009a0901;
+const V4L2_CID_EXPOSURE_ABSOLUTE: u32 = 0x009a0902;
+const V4L2_CID_FOCUS_ABSOLUTE: u32 = 0x009a090a;
+const V4L2_CID_FOCUS_AUTO: u32 = 0x009a090c;
+const V4L2_CID_ZOOM_ABSOLUTE: u32 = 0x009a090d;
+
+// The menu items of `V4L2_CID_EXPOSURE_AUTO`, most webcams only have the
+// manual mode and the aperture priority mode.
+const V4L2_EXPOSURE_AUTO: i64 = 0;
+const V4L2_EXPOSURE_MANUAL: i64 = 1;
+const V4L2_EXPOSURE_APERTURE_PRIORITY: i64 = 3;
+
 #[derive(Error, Debug)]
 pub enum CameraCaptureError {
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error("failed to create sw scale context")]
     CreateSWSWScaleContextError,
+    #[error("failed to create mjpeg decoder")]
+    CreateMjpegDecoderError,
+    #[error("camera does not support the control")]
+    UnsupportedControl,
+}
+
+fn get_pixel_format(fourcc: FourCC) -> CameraPixelFormat {
+    match &fourcc.repr {
+        b"NV12" => CameraPixelFormat::NV12,
+        b"YUYV" => CameraPixelFormat::YUYV,
+        b"MJPG" => CameraPixelFormat::MJPEG,
+        _ => CameraPixelFormat::Other,
+    }
+}
+
+// The modes of the device, the stepwise sizes and frame rates of the drivers
+// are given by their bounds.
+fn get_formats(device: &Device) -> Result<Vec<(FourCC, CameraFormat)>, CameraCaptureError> {
+    let mut formats = Vec::with_capacity(20);
+    for description in device.enum_formats()? {
+        let fourcc = description.fourcc;
+
+        let mut sizes = Vec::with_capacity(10);
+        for item in device.enum_framesizes(fourcc)? {
+            match item.size {
+                FrameSizeEnum::Discrete(it) => sizes.push((it.width, it.height)),
+                FrameSizeEnum::Stepwise(it) => {
+                    sizes.push((it.min_width, it.min_height));
+                    sizes.push((it.max_width, it.max_height));
+                }
+            }
+        }
+
+        for (width, height) in sizes {
+            for item in device.enum_frameintervals(fourcc, width, height)? {
+                let interval = match item.interval {
+                    FrameIntervalEnum::Discrete(it) => it,
+                    FrameIntervalEnum::Stepwise(it) => it.min,
+                };
+
+                if interval.numerator == 0 {
+                    continue;
+                }
+
+                formats.push((
+                    fourcc,
+                    CameraFormat {
+                        size: Size { width, height },
+                        fps: interval.denominator as f64 / interval.numerator as f64,
+                        pixel_format: get_pixel_format(fourcc),
+                    },
+                ));
+            }
+        }
+    }
+
+    Ok(formats)
 }
 
 #[derive(Default)]
 pub struct CameraCapture(Arc<AtomicBool>);
 
+impl CameraCapture {
+    pub fn get_formats(source: &Source) -> Result<Vec<CameraFormat>, CameraCaptureError> {
+        let device = Device::with_path(&source.id)?;
+        Ok(get_formats(&device)?
+            .into_iter()
+            .map(|(_, it)| it)
+            .collect())
+    }
+
+    pub fn get_controls(source: &Source) -> Result<CameraControls, CameraCaptureError> {
+        Ok(CameraControls(Device::with_path(&source.id)?))
+    }
+}
+
+/// The controls of a camera, the device is opened again for the controls, the
+/// drivers allow that while the camera is captured.
+pub struct CameraControls(Device);
+
+impl CameraControls {
+    // The control of the value and the control that switches it to automatic.
+    fn ids(control: CameraControl) -> (u32, Option<u32>) {
+        match control {
+            CameraControl::Exposure => (V4L2_CID_EXPOSURE_ABSOLUTE, Some(V4L2_CID_EXPOSURE_AUTO)),
+            CameraControl::Focus => (V4L2_CID_FOCUS_ABSOLUTE, Some(V4L2_CID_FOCUS_AUTO)),
+            CameraControl::Zoom => (V4L2_CID_ZOOM_ABSOLUTE, None),
+        }
+    }
+
+    fn description(&self, id: u32) -> Option<Description> {
+        self.0
+            .query_controls()
+            .ok()?
+            .into_iter()
+            .find(|it| it.id == id)
+    }
+
+    pub fn range(&self, control: CameraControl) -> Option<CameraControlRange> {
+        let (id, auto) = Self::ids(control);
+        let description = self.description(id)?;
+
+        Some(CameraControlRange {
+            min: description.minimum as i32,
+            max: description.maximum as i32,
+            step: description.step as i32,
+            default: description.default as i32,
+            auto: auto.and_then(|it| self.description(it)).is_some(),
+        })
+    }
+
+    pub fn get(&self, control: CameraControl) -> Result<CameraControlValue, CameraCaptureError> {
+        let (id, auto) = Self::ids(control);
+        if let Some(auto) = auto.and_then(|it| self.0.control(it).ok()) {
+            let is_auto = match auto.value {
+                Value::Integer(it) => it != V4L2_EXPOSURE_MANUAL,
+                Value::Boolean(it) => it,
+                _ => false,
+            };
+
+            if is_auto {
+                return Ok(CameraControlValue::Auto);
+            }
+        }
+
+        match self.0.control(id).map(|it| it.value) {
+            Ok(Value::Integer(it)) => Ok(CameraControlValue::Manual(it as i32)),
+            _ => Err(CameraCaptureError::UnsupportedControl),
+        }
+    }
+
+    pub fn set(
+        &self,
+        control: CameraControl,
+        value: CameraControlValue,
+    ) -> Result<(), CameraCaptureError> {
+        let (id, auto) = Self::ids(control);
+        let auto = auto.and_then(|it| self.description(it));
+
+        // The value can only be set while the camera does not adjust it.
+        let (auto_value, manual_value) = match control {
+            CameraControl::Exposure => {
+                let items = auto
+                    .as_ref()
+                    .and_then(|it| it.items.as_ref())
+                    .map(|it| {
+                        it.iter()
+                            .map(|(index, _)| *index as i64)
+                            .collect::<Vec<_>>()
+                    })
+                    .unwrap_or_default();
+
+                (
+                    Value::Integer(if items.contains(&V4L2_EXPOSURE_AUTO) {
+                        V4L2_EXPOSURE_AUTO
+                    } else {
+                        V4L2_EXPOSURE_APERTURE_PRIORITY
+                    }),
+                    Value::Integer(V4L2_EXPOSURE_MANUAL),
+                )
+            }
+            _ => (Value::Boolean(true), Value::Boolean(false)),
+        };
+
+        match value {
+            CameraControlValue::Auto => {
+                let auto = auto.ok_or(CameraCaptureError::UnsupportedControl)?;
+                self.0.set_control(Control {
+                    id: auto.id,
+                    value: auto_value,
+                })?;
+            }
+            CameraControlValue::Manual(value) => {
+                if let Some(auto) = auto {
+                    self.0.set_control(Control {
+                        id: auto.id,
+                        value: manual_value,
+                    })?;
+                }
+
+                self.0.set_control(Control {
+                    id,
+                    value: Value::Integer(value as i64),
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl CaptureHandler for CameraCapture {
     type Frame = VideoFrame;
     type Error = CameraCaptureError;
@@ -57,6 +265,8 @@ impl CaptureHandler for CameraCapture {
                                 index: item.index(),
                                 kind: SourceType::Camera,
                                 is_default: item.index() == 0,
+                                rotation: VideoRotation::Rotate0,
+                                backend: None,
                                 name,
                                 id,
                             });
@@ -77,48 +287,109 @@ impl CaptureHandler for CameraCapture {
         let status = Arc::downgrade(&self.0);
         self.0.update(true);
 
-        // Fixed to YUYV, there may be compatibility issues here as not all devices may
-        // support YUYV.
-        let device = Device::with_path(options.source.id)?;
-        {
+        // The device captures in the mode closest to the encoder, the frames are
+        // decoded, converted and scaled to NV12 of the size. YUYV at the size is
+        // requested if the device does not list its modes.
+        let device = Device::with_path(&options.source.id)?;
+        let formats = get_formats(&device).unwrap_or_default();
+        let (fourcc, size) = select_format(
+            &formats.iter().map(|(_, it)| *it).collect::<Vec<_>>(),
+            options.size,
+            options.fps,
+            &[
+                CameraPixelFormat::NV12,
+                CameraPixelFormat::YUYV,
+                CameraPixelFormat::MJPEG,
+            ],
+        )
+        .map(|index| (formats[index].0, formats[index].1.size))
+        .unwrap_or((FourCC::new(b"YUYV"), options.size));
+
+        let (format, field_order) = {
             let mut format = device.format()?;
-            format.width = options.size.width;
-            format.height = options.size.height;
-            format.fourcc = FourCC::new(b"YUYV");
-            device.set_format(&format)?;
+            format.width = size.width;
+            format.height = size.height;
+            format.fourcc = fourcc;
+            format.field_order = V4lFieldOrder::Any;
+
+            // The driver picks the field order and returns it, the capture cards keep
+            // the fields of an interlaced signal.
+            let format = device.set_format(&format)?;
+            let field_order = match format.field_order {
+                V4lFieldOrder::InterlacedTB => FieldOrder::TopFieldFirst,
+                V4lFieldOrder::InterlacedBT => FieldOrder::BottomFieldFirst,
+                // The order follows the standard of the signal, NTSC is bottom field
+                // first and the others are top field first.
+                V4lFieldOrder::Interlaced if format.height == 480 => FieldOrder::BottomFieldFirst,
+                V4lFieldOrder::Interlaced => FieldOrder::TopFieldFirst,
+                _ => FieldOrder::Progressive,
+            };
+
+            (format, field_order)
+        };
+
+        // The driver rounds the frame rate to the closest one of the mode.
+        if let Err(e) = device.set_params(&Parameters::with_fps(options.fps as u32)) {
+            log::warn!("failed to set camera frame rate, err={:?}", e);
         }
 
-        let mut swscale = SWScale::new(options.size)?;
+        log::info!(
+            "linux camera capture format, id={}, fourcc={}, width={}, height={}",
+            options.source.id,
+            format.fourcc,
+            format.width,
+            format.height
+        );
+
+        let mut swscale = SWScale::new(&format, options.size)?;
         let mut stream = Stream::new(&device, Type::VideoCapture)?;
-        thread::Builder::new()
-            .name("LinuxCameraCaptureThread".to_string())
-            .spawn(move || {
-                let mut frame = VideoFrame::default();
-                frame.width = options.size.width;
-                frame.height = options.size.height;
-                frame.sub_format = VideoSubFormat::SW;
-                frame.format = VideoFormat::NV12;
-
-                while let Ok((buffer, _)) = stream.next() {
-                    if let Some(status) = status.upgrade() {
-                        if !status.get() {
-                            break;
-                        }
-                    } else {
+        runtime::spawn(ThreadKind::Capture, "CameraCapture", move || {
+            let mut frame = VideoFrame::default();
+            frame.width = options.size.width;
+            frame.height = options.size.height;
+            frame.sub_format = VideoSubFormat::SW;
+            frame.format = VideoFormat::NV12;
+            frame.rotation = options.source.rotation;
+            frame.field_order = field_order;
+
+            let mut pacer = FramePacer::new(options.fps, options.adaptive_pacing);
+            while let Ok((buffer, _)) = stream.next() {
+                if let Some(status) = status.upgrade() {
+                    if !status.get() {
                         break;
                     }
+                } else {
+                    break;
+                }
 
-                    let scaled = swscale.scale(buffer);
-                    for i in 0..2 {
-                        frame.data[i] = scaled.data[i] as _;
-                        frame.linesize[i] = scaled.linesize[i] as usize;
-                    }
+                // The device may deliver more frames than requested, the extra frames
+                // are dropped.
+                frame.timestamp = if let Some(timestamp) = pacer.accept() {
+                    timestamp
+                } else {
+                    continue;
+                };
+
+                let time = Instant::now();
+                // A corrupted MJPEG frame is dropped.
+                let scaled = if let Some(it) = swscale.scale(buffer) {
+                    it
+                } else {
+                    continue;
+                };
+
+                for i in 0..2 {
+                    frame.data[i] = scaled.data[i] as _;
+                    frame.linesize[i] = scaled.linesize[i] as usize;
+                }
 
-                    if !arrived.sink(&frame) {
-                        break;
-                    }
+                if !arrived.sink(&frame) {
+                    break;
                 }
-            })?;
+
+                pacer.feedback(time.elapsed());
+            }
+        })?;
 
         Ok(())
     }
@@ -133,17 +404,26 @@ struct SWScale {
     sws_ctx: *mut SwsContext,
     frame: *mut AVFrame,
     scaled_frame: *mut AVFrame,
+    // The second plane of NV12 follows the first one in the buffer.
+    plane_offset: Option<usize>,
+    // The MJPEG frames are decoded into the input frame, the scale context
+    // is created for the format of the decoded frames.
+    decoder: Option<MjpegDecoder>,
+    size: Size,
 }
 
 unsafe impl Send for SWScale {}
 unsafe impl Sync for SWScale {}
 
 impl SWScale {
-    fn new(size: Size) -> Result<Self, CameraCaptureError> {
+    fn new(format: &Format, size: Size) -> Result<Self, CameraCaptureError> {
         let mut this = Self {
             scaled_frame: unsafe { av_frame_alloc() },
             frame: unsafe { av_frame_alloc() },
             sws_ctx: null_mut(),
+            plane_offset: None,
+            decoder: None,
+            size,
         };
 
         unsafe {
@@ -162,19 +442,42 @@ impl SWScale {
             );
         }
 
-        // The captures are all YUYV, here converted to NV12.
+        if format.fourcc == FourCC::new(b"MJPG") {
+            this.decoder = Some(MjpegDecoder::new()?);
+
+            return Ok(this);
+        }
+
+        let (input_format, plane_offset) = if format.fourcc == FourCC::new(b"NV12") {
+            (
+                AVPixelFormat::AV_PIX_FMT_NV12,
+                Some(format.stride as usize * format.height as usize),
+            )
+        } else {
+            (AVPixelFormat::AV_PIX_FMT_YUYV422, None)
+        };
+
+        this.plane_offset = plane_offset;
+
+        // The captures are YUYV or NV12, here converted to NV12 of the size.
         unsafe {
             let frame_mut = &mut *this.frame;
-            frame_mut.format = AVPixelFormat::AV_PIX_FMT_YUYV422 as i32;
-            frame_mut.width = size.width as i32;
-            frame_mut.height = size.height as i32;
+            frame_mut.format = input_format as i32;
+            frame_mut.width = format.width as i32;
+            frame_mut.height = format.height as i32;
+            frame_mut.linesize[0] = format.stride as i32;
+            frame_mut.linesize[1] = if plane_offset.is_some() {
+                format.stride as i32
+            } else {
+                0
+            };
         }
 
         this.sws_ctx = unsafe {
             sws_getContext(
-                size.width as i32,
-                size.height as i32,
-                AVPixelFormat::AV_PIX_FMT_YUYV422,
+                format.width as i32,
+                format.height as i32,
+                input_format,
                 size.width as i32,
                 size.height as i32,
                 AVPixelFormat::AV_PIX_FMT_NV12,
@@ -192,11 +495,43 @@ impl SWScale {
         Ok(this)
     }
 
-    fn scale(&mut self, buffer: &[u8]) -> &AVFrame {
-        unsafe {
-            let frame_mut = &mut *self.frame;
-            frame_mut.linesize[0] = frame_mut.width * 2;
-            frame_mut.data[0] = buffer.as_ptr() as *mut _;
+    fn scale(&mut self, buffer: &[u8]) -> Option<&AVFrame> {
+        if let Some(decoder) = self.decoder.as_mut() {
+            if !decoder.decode(buffer, self.frame) {
+                return None;
+            }
+
+            // The decoded frames are usually YUVJ422P, the context is only created
+            // again if the format changes.
+            self.sws_ctx = unsafe {
+                let frame = &*self.frame;
+                sws_getCachedContext(
+                    self.sws_ctx,
+                    frame.width,
+                    frame.height,
+                    std::mem::transmute::<_, AVPixelFormat>(frame.format),
+                    self.size.width as i32,
+                    self.size.height as i32,
+                    AVPixelFormat::AV_PIX_FMT_NV12,
+                    SWS_FAST_BILINEAR,
+                    null_mut(),
+                    null_mut(),
+                    null(),
+                )
+            };
+
+            if self.sws_ctx.is_null() {
+                return None;
+            }
+        } else {
+            unsafe {
+                let frame_mut = &mut *self.frame;
+                frame_mut.data[0] = buffer.as_ptr() as *mut _;
+
+                if let Some(offset) = self.plane_offset {
+                    frame_mut.data[1] = buffer.as_ptr().add(offset) as *mut _;
+                }
+            }
         }
 
         unsafe {
@@ -213,7 +548,7 @@ impl SWScale {
             );
         }
 
-        unsafe { &*self.scaled_frame }
+        Some(unsafe { &*self.scaled_frame })
     }
 }
 
@@ -238,3 +573,76 @@ impl Drop for SWScale {
         }
     }
 }
+
+// Many UVC cameras only deliver the larger sizes at the full frame rate as
+// MJPEG, each buffer of the device is a complete JPEG image.
+struct MjpegDecoder {
+    context: *mut AVCodecContext,
+    packet: *mut AVPacket,
+}
+
+impl MjpegDecoder {
+    fn new() -> Result<Self, CameraCaptureError> {
+        let mut this = Self {
+            context: null_mut(),
+            packet: unsafe { av_packet_alloc() },
+        };
+
+        if this.packet.is_null() {
+            return Err(CameraCaptureError::CreateMjpegDecoderError);
+        }
+
+        let codec = unsafe { avcodec_find_decoder(AVCodecID::AV_CODEC_ID_MJPEG) };
+        if codec.is_null() {
+            return Err(CameraCaptureError::CreateMjpegDecoderError);
+        }
+
+        this.context = unsafe { avcodec_alloc_context3(codec) };
+        if this.context.is_null() {
+            return Err(CameraCaptureError::CreateMjpegDecoderError);
+        }
+
+        // The low delay flag keeps the decoder from holding the frames back for the
+        // frame threads, the slices are still decoded in parallel.
+        let context_mut = unsafe { &mut *this.context };
+        context_mut.thread_count = 0;
+        context_mut.flags |= AV_CODEC_FLAG_LOW_DELAY as i32;
+
+        if unsafe { avcodec_open2(this.context, codec, null_mut()) } != 0 {
+            return Err(CameraCaptureError::CreateMjpegDecoderError);
+        }
+
+        Ok(this)
+    }
+
+    fn decode(&mut self, buffer: &[u8], frame: *mut AVFrame) -> bool {
+        // The packet does not own the buffer, the decoder copies it.
+        unsafe {
+            let packet_mut = &mut *self.packet;
+            packet_mut.data = buffer.as_ptr() as *mut _;
+            packet_mut.size = buffer.len() as i32;
+        }
+
+        if unsafe { avcodec_send_packet(self.context, self.packet) } != 0 {
+            return false;
+        }
+
+        unsafe { avcodec_receive_frame(self.context, frame) == 0 }
+    }
+}
+
+impl Drop for MjpegDecoder {
+    fn drop(&mut self) {
+        if !self.packet.is_null() {
+            unsafe {
+                av_packet_free(&mut self.packet);
+            }
+        }
+
+        if !self.context.is_null() {
+            unsafe {
+                avcodec_free_context(&mut self.context);
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::{
+    ffi::{c_char, c_int, c_uint, c_ulong, c_void},
+    io::{Error, ErrorKind},
+    ptr::null,
+};
+
+use hylarana_common::input::{InputEvent, MouseButton};
+use parking_lot::Mutex;
+
+#[link(name = "X11")]
+extern "C" {
+    fn XOpenDisplay(name: *const c_char) -> *mut c_void;
+    fn XDefaultScreen(display: *mut c_void) -> c_int;
+    fn XDisplayWidth(display: *mut c_void, screen: c_int) -> c_int;
+    fn XDisplayHeight(display: *mut c_void, screen: c_int) -> c_int;
+    fn XFlush(display: *mut c_void) -> c_int;
+}
+
+#[link(name = "Xtst")]
+extern "C" {
+    fn XTestFakeMotionEvent(
+        display: *mut c_void,
+        screen: c_int,
+        x: c_int,
+        y: c_int,
+        delay: c_ulong,
+    ) -> c_int;
+    fn XTestFakeButtonEvent(
+        display: *mut c_void,
+        button: c_uint,
+        is_press: c_int,
+        delay: c_ulong,
+    ) -> c_int;
+    fn XTestFakeKeyEvent(
+        display: *mut c_void,
+        keycode: c_uint,
+        is_press: c_int,
+        delay: c_ulong,
+    ) -> c_int;
+}
+
+struct Display(*mut c_void);
+
+unsafe impl Send for Display {}
+
+// Xlib is not thread safe, the connection is opened on first use and all
+// events are injected under the lock.
+static DISPLAY: Mutex<Option<Display>> = Mutex::new(None);
+
+// The extended scan codes do not map to the linux key codes directly like the
+// basic scan codes.
+fn extended_key_code(code: u8) -> Option<u32> {
+    Some(match code {
+        0x1C => 96,  // KEY_KPENTER
+        0x1D => 97,  // KEY_RIGHTCTRL
+        0x35 => 98,  // KEY_KPSLASH
+        0x38 => 100, // KEY_RIGHTALT
+        0x47 => 102, // KEY_HOME
+        0x48 => 103, // KEY_UP
+        0x49 => 104, // KEY_PAGEUP
+        0x4B => 105, // KEY_LEFT
+        0x4D => 106, // KEY_RIGHT
+        0x4F => 107, // KEY_END
+        0x50 => 108, // KEY_DOWN
+        0x51 => 109, // KEY_PAGEDOWN
+        0x52 => 110, // KEY_INSERT
+        0x53 => 111, // KEY_DELETE
+        0x5B => 125, // KEY_LEFTMETA
+        0x5C => 126, // KEY_RIGHTMETA
+        0x5D => 127, // KEY_COMPOSE
+        _ => return None,
+    })
+}
+
+pub fn inject(event: &InputEvent) -> Result<(), Error> {
+    let mut display = DISPLAY.lock();
+    if display.is_none() {
+        let ptr = unsafe { XOpenDisplay(null()) };
+        if ptr.is_null() {
+            return Err(Error::new(
+                ErrorKind::NotFound,
+                "failed to open x11 display",
+            ));
+        }
+
+        display.replace(Display(ptr));
+    }
+
+    let display = display.as_ref().unwrap().0;
+    unsafe {
+        match *event {
+            InputEvent::MouseMove { x, y } => {
+                let screen = XDefaultScreen(display);
+                XTestFakeMotionEvent(
+                    display,
+                    screen,
+                    (x.clamp(0.0, 1.0) * XDisplayWidth(display, screen) as f32) as c_int,
+                    (y.clamp(0.0, 1.0) * XDisplayHeight(display, screen) as f32) as c_int,
+                    0,
+                );
+            }
+            InputEvent::MouseButton { button, pressed } => {
+                let button = match button {
+                    MouseButton::Left => 1,
+                    MouseButton::Middle => 2,
+                    MouseButton::Right => 3,
+                };
+
+                XTestFakeButtonEvent(display, button, pressed as c_int, 0);
+            }
+            // The wheel is the buttons 4 and 5, and the horizontal wheel is the buttons
+            // 6 and 7, each click of the button scrolls one notch.
+            InputEvent::MouseWheel { x, y } => {
+                for (delta, positive, negative) in [(y, 4, 5), (x, 7, 6)] {
+                    let button = if delta > 0 { positive } else { negative };
+                    for _ in 0..delta.unsigned_abs() {
+                        XTestFakeButtonEvent(display, button, 1, 0);
+                        XTestFakeButtonEvent(display, button, 0, 0);
+                    }
+                }
+            }
+            // The key codes of x11 are the linux key codes offset by 8.
+            InputEvent::Key { code, pressed } => {
+                let key = if code & 0xFF00 == 0xE000 {
+                    extended_key_code(code as u8)
+                } else if code < 0x80 {
+                    Some(code as u32)
+                } else {
+                    None
+                };
+
+                if let Some(key) = key {
+                    XTestFakeKeyEvent(display, key + 8, pressed as c_int, 0);
+                } else {
+                    return Err(Error::new(ErrorKind::InvalidInput, "unknown scan code"));
+                }
+            }
+        }
+
+        XFlush(display);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use crate::{PermissionState, SourceType};
+
+use std::{env, fs::OpenOptions, io::ErrorKind};
+
+use v4l::context::enum_devices;
+
+pub fn check(kind: SourceType) -> PermissionState {
+    match kind {
+        // The screen is captured with x11grab, under wayland only the windows of
+        // xwayland can be captured, and capturing the screen needs the screen cast
+        // portal, which is not supported.
+        SourceType::Screen => {
+            if env::var("XDG_SESSION_TYPE").ok().as_deref() == Some("wayland")
+                || env::var_os("DISPLAY").is_none()
+            {
+                PermissionState::Unsupported
+            } else {
+                PermissionState::Granted
+            }
+        }
+        // Access to the camera devices is controlled by the permissions of the device
+        // files, usually the user needs to be in the video group.
+        SourceType::Camera => {
+            let mut state = PermissionState::NotDetermined;
+            for item in enum_devices() {
+                match OpenOptions::new().read(true).write(true).open(item.path()) {
+                    Ok(_) => return PermissionState::Granted,
+                    Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                        state = PermissionState::Denied;
+                    }
+                    Err(_) => (),
+                }
+            }
+
+            state
+        }
+        SourceType::Audio => PermissionState::Granted,
+    }
+}
+
+// There is no permission prompt on linux.
+pub fn request(kind: SourceType) -> PermissionState {
+    check(kind)
+}
@@ -1,16 +1,20 @@
-use crate::{CaptureHandler, FrameArrived, Source, SourceType, VideoCaptureSourceDescription};
+use crate::{
+    CaptureHandler, FrameArrived, FramePacer, Source, SourceEvent, SourceType,
+    VideoCaptureSourceDescription,
+};
 
 use std::{
     ptr::{null, null_mut},
     sync::{atomic::AtomicBool, Arc},
-    thread::{self, sleep},
-    time::Duration,
+    time::Instant,
 };
 
 use hylarana_common::{
     atomic::EasyAtomic,
-    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    frame::{VideoFormat, VideoFrame, VideoRotation, VideoSubFormat},
+    runtime::{self, ThreadKind},
     strings::PSTR,
+    Size,
 };
 
 use mirror_ffmpeg_sys::*;
@@ -55,6 +59,8 @@ impl CaptureHandler for ScreenCapture {
         Ok(vec![Source {
             index: 0,
             is_default: true,
+            rotation: VideoRotation::Rotate0,
+            backend: None,
             kind: SourceType::Screen,
             id: ":0.0".to_string(),
             name: "default display".to_string(),
@@ -71,42 +77,72 @@ impl CaptureHandler for ScreenCapture {
         let status = Arc::downgrade(&self.0);
         self.0.update(true);
 
-        thread::Builder::new()
-            .name("LinuxScreenCaptureThread".to_string())
-            .spawn(move || {
-                let mut frame = VideoFrame::default();
-                frame.width = options.size.width;
-                frame.height = options.size.height;
-                frame.sub_format = VideoSubFormat::SW;
-                frame.format = VideoFormat::NV12;
-
-                while let Some(avframe) = capture.read() {
-                    if let Some(status) = status.upgrade() {
-                        if !status.get() {
-                            break;
-                        }
-                    } else {
+        runtime::spawn(ThreadKind::Capture, "ScreenCapture", move || {
+            let mut frame = VideoFrame::default();
+            frame.width = options.size.width;
+            frame.height = options.size.height;
+            frame.sub_format = VideoSubFormat::SW;
+            frame.format = VideoFormat::NV12;
+
+            let mut pacer = FramePacer::new(options.fps, options.adaptive_pacing);
+
+            loop {
+                frame.timestamp = pacer.wait();
+
+                if let Some(status) = status.upgrade() {
+                    if !status.get() {
                         break;
                     }
+                } else {
+                    break;
+                }
 
-                    let format = unsafe { std::mem::transmute::<_, AVPixelFormat>(avframe.format) };
-                    match format {
-                        AVPixelFormat::AV_PIX_FMT_NV12 => {
-                            for i in 0..2 {
-                                frame.data[i] = avframe.data[i] as _;
-                                frame.linesize[i] = avframe.linesize[i] as usize;
+                let time = Instant::now();
+                let Some(avframe) = capture.read() else {
+                    // x11grab stops reading when the resolution of the display changes or
+                    // the display is gone, try to open the display again.
+                    match Capture::new(&options) {
+                        Ok(it) => {
+                            if it.size != capture.size {
+                                log::info!(
+                                    "linux screen capture source resized, size={}x{}",
+                                    it.size.width,
+                                    it.size.height
+                                );
+
+                                arrived.event(SourceEvent::Resized(it.size));
                             }
 
-                            if !arrived.sink(&frame) {
-                                break;
-                            }
+                            capture = it;
+                            continue;
+                        }
+                        Err(e) => {
+                            log::warn!("linux screen capture reopen error={:?}", e);
+
+                            arrived.event(SourceEvent::Removed);
+                            break;
                         }
-                        _ => unimplemented!("not supports capture pix fmt = {:?}", format),
                     }
+                };
+
+                let format = unsafe { std::mem::transmute::<_, AVPixelFormat>(avframe.format) };
+                match format {
+                    AVPixelFormat::AV_PIX_FMT_NV12 => {
+                        for i in 0..2 {
+                            frame.data[i] = avframe.data[i] as _;
+                            frame.linesize[i] = avframe.linesize[i] as usize;
+                        }
 
-                    sleep(Duration::from_millis(1000 / options.fps as u64));
+                        if !arrived.sink(&frame) {
+                            break;
+                        }
+                    }
+                    _ => unimplemented!("not supports capture pix fmt = {:?}", format),
                 }
-            })?;
+
+                pacer.feedback(time.elapsed());
+            }
+        })?;
 
         Ok(())
     }
@@ -124,6 +160,8 @@ struct Capture {
     packet: *mut AVPacket,
     frame: *mut AVFrame,
     scaled_frame: *mut AVFrame,
+    // The size of the display.
+    size: Size,
 }
 
 unsafe impl Send for Capture {}
@@ -138,6 +176,10 @@ impl Capture {
             sws_ctx: null_mut(),
             codec_ctx: null_mut(),
             fmt_ctx: null_mut(),
+            size: Size {
+                width: 0,
+                height: 0,
+            },
         };
 
         // Currently you can only capture the screen in the x11 desktop environment.
@@ -189,6 +231,11 @@ impl Capture {
         let stream = unsafe { &*(streams[0]) };
         let codecpar = unsafe { &*stream.codecpar };
 
+        this.size = Size {
+            width: codecpar.width as u32,
+            height: codecpar.height as u32,
+        };
+
         let codec = unsafe { avcodec_find_decoder(codecpar.codec_id) };
         if codec.is_null() {
             return Err(ScreenCaptureError::NotFoundDecoder);
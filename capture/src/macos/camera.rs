@@ -1,14 +1,50 @@
-use crate::{CaptureHandler, FrameArrived, Source, VideoCaptureSourceDescription};
+use crate::{
+    CameraControl, CameraControlRange, CameraControlValue, CameraFormat, CaptureHandler,
+    FrameArrived, Source, VideoCaptureSourceDescription,
+};
 
 use hylarana_common::frame::VideoFrame;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
-pub enum CameraCaptureError {}
+pub enum CameraCaptureError {
+    #[error("camera capture is not supported on macos")]
+    NotSupported,
+}
 
 #[derive(Default)]
 pub struct CameraCapture;
 
+impl CameraCapture {
+    pub fn get_formats(_source: &Source) -> Result<Vec<CameraFormat>, CameraCaptureError> {
+        Ok(Vec::new())
+    }
+
+    pub fn get_controls(_source: &Source) -> Result<CameraControls, CameraCaptureError> {
+        Err(CameraCaptureError::NotSupported)
+    }
+}
+
+pub struct CameraControls;
+
+impl CameraControls {
+    pub fn range(&self, _control: CameraControl) -> Option<CameraControlRange> {
+        None
+    }
+
+    pub fn get(&self, _control: CameraControl) -> Result<CameraControlValue, CameraCaptureError> {
+        Err(CameraCaptureError::NotSupported)
+    }
+
+    pub fn set(
+        &self,
+        _control: CameraControl,
+        _value: CameraControlValue,
+    ) -> Result<(), CameraCaptureError> {
+        Err(CameraCaptureError::NotSupported)
+    }
+}
+
 impl CaptureHandler for CameraCapture {
     type Frame = VideoFrame;
     type Error = CameraCaptureError;
@@ -23,10 +59,10 @@ impl CaptureHandler for CameraCapture {
         _options: Self::CaptureOptions,
         mut _arrived: S,
     ) -> Result<(), Self::Error> {
-        todo!("camera capture is not supported on macos")
+        Err(CameraCaptureError::NotSupported)
     }
 
     fn stop(&self) -> Result<(), Self::Error> {
-        todo!("camera capture is not supported on macos")
+        Ok(())
     }
 }
@@ -0,0 +1,246 @@
+use std::{
+    ffi::c_void,
+    io::{Error, ErrorKind},
+    ptr::null,
+};
+
+use hylarana_common::input::{InputEvent, MouseButton};
+use parking_lot::Mutex;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGPoint {
+    x: f64,
+    y: f64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGRect {
+    origin: CGPoint,
+    size: CGPoint,
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGMainDisplayID() -> u32;
+    fn CGDisplayBounds(display: u32) -> CGRect;
+    fn CGEventCreate(source: *const c_void) -> *mut c_void;
+    fn CGEventGetLocation(event: *mut c_void) -> CGPoint;
+    fn CGEventCreateMouseEvent(
+        source: *const c_void,
+        kind: u32,
+        position: CGPoint,
+        button: u32,
+    ) -> *mut c_void;
+    fn CGEventCreateScrollWheelEvent(
+        source: *const c_void,
+        units: u32,
+        count: u32,
+        wheel1: i32,
+        ...
+    ) -> *mut c_void;
+    fn CGEventCreateKeyboardEvent(source: *const c_void, key: u16, down: bool) -> *mut c_void;
+    fn CGEventPost(tap: u32, event: *mut c_void);
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFRelease(object: *const c_void);
+}
+
+// CGEventType
+const LEFT_MOUSE_DOWN: u32 = 1;
+const LEFT_MOUSE_UP: u32 = 2;
+const RIGHT_MOUSE_DOWN: u32 = 3;
+const RIGHT_MOUSE_UP: u32 = 4;
+const MOUSE_MOVED: u32 = 5;
+const LEFT_MOUSE_DRAGGED: u32 = 6;
+const RIGHT_MOUSE_DRAGGED: u32 = 7;
+const OTHER_MOUSE_DOWN: u32 = 25;
+const OTHER_MOUSE_UP: u32 = 26;
+const OTHER_MOUSE_DRAGGED: u32 = 27;
+
+// kCGHIDEventTap
+const HID_EVENT_TAP: u32 = 0;
+
+// kCGScrollEventUnitLine
+const SCROLL_UNIT_LINE: u32 = 1;
+
+// A move with a button pressed is a drag on macos, so the pressed button
+// needs to be remembered.
+static PRESSED: Mutex<Option<MouseButton>> = Mutex::new(None);
+
+// Maps the scan codes of the PC keyboard to the virtual key codes of macos.
+fn key_code(code: u16) -> Option<u16> {
+    Some(match code {
+        0x01 => 0x35,
+        0x02 => 0x12,
+        0x03 => 0x13,
+        0x04 => 0x14,
+        0x05 => 0x15,
+        0x06 => 0x17,
+        0x07 => 0x16,
+        0x08 => 0x1A,
+        0x09 => 0x1C,
+        0x0A => 0x19,
+        0x0B => 0x1D,
+        0x0C => 0x1B,
+        0x0D => 0x18,
+        0x0E => 0x33,
+        0x0F => 0x30,
+        0x10 => 0x0C,
+        0x11 => 0x0D,
+        0x12 => 0x0E,
+        0x13 => 0x0F,
+        0x14 => 0x11,
+        0x15 => 0x10,
+        0x16 => 0x20,
+        0x17 => 0x22,
+        0x18 => 0x1F,
+        0x19 => 0x23,
+        0x1A => 0x21,
+        0x1B => 0x1E,
+        0x1C => 0x24,
+        0x1D => 0x3B,
+        0x1E => 0x00,
+        0x1F => 0x01,
+        0x20 => 0x02,
+        0x21 => 0x03,
+        0x22 => 0x05,
+        0x23 => 0x04,
+        0x24 => 0x26,
+        0x25 => 0x28,
+        0x26 => 0x25,
+        0x27 => 0x29,
+        0x28 => 0x27,
+        0x29 => 0x32,
+        0x2A => 0x38,
+        0x2B => 0x2A,
+        0x2C => 0x06,
+        0x2D => 0x07,
+        0x2E => 0x08,
+        0x2F => 0x09,
+        0x30 => 0x0B,
+        0x31 => 0x2D,
+        0x32 => 0x2E,
+        0x33 => 0x2B,
+        0x34 => 0x2F,
+        0x35 => 0x2C,
+        0x36 => 0x3C,
+        0x37 => 0x43,
+        0x38 => 0x3A,
+        0x39 => 0x31,
+        0x3A => 0x39,
+        0x3B => 0x7A,
+        0x3C => 0x78,
+        0x3D => 0x63,
+        0x3E => 0x76,
+        0x3F => 0x60,
+        0x40 => 0x61,
+        0x41 => 0x62,
+        0x42 => 0x64,
+        0x43 => 0x65,
+        0x44 => 0x6D,
+        0x45 => 0x47,
+        0x47 => 0x59,
+        0x48 => 0x5B,
+        0x49 => 0x5C,
+        0x4A => 0x4E,
+        0x4B => 0x56,
+        0x4C => 0x57,
+        0x4D => 0x58,
+        0x4E => 0x45,
+        0x4F => 0x53,
+        0x50 => 0x54,
+        0x51 => 0x55,
+        0x52 => 0x52,
+        0x53 => 0x41,
+        0x57 => 0x67,
+        0x58 => 0x6F,
+        0xE01C => 0x4C,
+        0xE01D => 0x3E,
+        0xE035 => 0x4B,
+        0xE038 => 0x3D,
+        0xE047 => 0x73,
+        0xE048 => 0x7E,
+        0xE049 => 0x74,
+        0xE04B => 0x7B,
+        0xE04D => 0x7C,
+        0xE04F => 0x77,
+        0xE050 => 0x7D,
+        0xE051 => 0x79,
+        0xE052 => 0x72,
+        0xE053 => 0x75,
+        0xE05B => 0x37,
+        0xE05C => 0x36,
+        _ => return None,
+    })
+}
+
+fn post(event: *mut c_void) -> Result<(), Error> {
+    if event.is_null() {
+        return Err(Error::other("failed to create cg event"));
+    }
+
+    unsafe {
+        CGEventPost(HID_EVENT_TAP, event);
+        CFRelease(event);
+    }
+
+    Ok(())
+}
+
+fn cursor_location() -> CGPoint {
+    unsafe {
+        let event = CGEventCreate(null());
+        let location = CGEventGetLocation(event);
+        CFRelease(event);
+        location
+    }
+}
+
+pub fn inject(event: &InputEvent) -> Result<(), Error> {
+    match *event {
+        InputEvent::MouseMove { x, y } => {
+            let bounds = unsafe { CGDisplayBounds(CGMainDisplayID()) };
+            let position = CGPoint {
+                x: bounds.origin.x + x.clamp(0.0, 1.0) as f64 * bounds.size.x,
+                y: bounds.origin.y + y.clamp(0.0, 1.0) as f64 * bounds.size.y,
+            };
+
+            let (kind, button) = match *PRESSED.lock() {
+                None => (MOUSE_MOVED, 0),
+                Some(MouseButton::Left) => (LEFT_MOUSE_DRAGGED, 0),
+                Some(MouseButton::Right) => (RIGHT_MOUSE_DRAGGED, 1),
+                Some(MouseButton::Middle) => (OTHER_MOUSE_DRAGGED, 2),
+            };
+
+            post(unsafe { CGEventCreateMouseEvent(null(), kind, position, button) })
+        }
+        InputEvent::MouseButton { button, pressed } => {
+            let (kind, index) = match (button, pressed) {
+                (MouseButton::Left, true) => (LEFT_MOUSE_DOWN, 0),
+                (MouseButton::Left, false) => (LEFT_MOUSE_UP, 0),
+                (MouseButton::Right, true) => (RIGHT_MOUSE_DOWN, 1),
+                (MouseButton::Right, false) => (RIGHT_MOUSE_UP, 1),
+                (MouseButton::Middle, true) => (OTHER_MOUSE_DOWN, 2),
+                (MouseButton::Middle, false) => (OTHER_MOUSE_UP, 2),
+            };
+
+            *PRESSED.lock() = if pressed { Some(button) } else { None };
+            post(unsafe { CGEventCreateMouseEvent(null(), kind, cursor_location(), index) })
+        }
+        // The horizontal wheel of macos scrolls left with positive values.
+        InputEvent::MouseWheel { x, y } => {
+            post(unsafe { CGEventCreateScrollWheelEvent(null(), SCROLL_UNIT_LINE, 2, y, -x) })
+        }
+        InputEvent::Key { code, pressed } => {
+            if let Some(key) = key_code(code) {
+                post(unsafe { CGEventCreateKeyboardEvent(null(), key, pressed) })
+            } else {
+                Err(Error::new(ErrorKind::InvalidInput, "unknown scan code"))
+            }
+        }
+    }
+}
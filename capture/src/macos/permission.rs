@@ -0,0 +1,76 @@
+use crate::{PermissionState, SourceType};
+
+use std::ffi::{c_char, c_void};
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGPreflightScreenCaptureAccess() -> bool;
+    fn CGRequestScreenCaptureAccess() -> bool;
+}
+
+#[link(name = "AVFoundation", kind = "framework")]
+extern "C" {
+    static AVMediaTypeVideo: *const c_void;
+    static AVMediaTypeAudio: *const c_void;
+}
+
+#[link(name = "objc")]
+extern "C" {
+    fn objc_getClass(name: *const c_char) -> *mut c_void;
+    fn sel_registerName(name: *const c_char) -> *mut c_void;
+    fn objc_msgSend();
+}
+
+// [AVCaptureDevice authorizationStatusForMediaType:]
+fn get_authorization_status(media_type: *const c_void) -> PermissionState {
+    // objc_msgSend must be called with the signature of the method.
+    let send: unsafe extern "C" fn(*mut c_void, *mut c_void, *const c_void) -> isize =
+        unsafe { std::mem::transmute(objc_msgSend as unsafe extern "C" fn()) };
+
+    let status = unsafe {
+        send(
+            objc_getClass(c"AVCaptureDevice".as_ptr()),
+            sel_registerName(c"authorizationStatusForMediaType:".as_ptr()),
+            media_type,
+        )
+    };
+
+    // AVAuthorizationStatus: NotDetermined = 0, Restricted = 1, Denied = 2,
+    // Authorized = 3.
+    match status {
+        0 => PermissionState::NotDetermined,
+        3 => PermissionState::Granted,
+        _ => PermissionState::Denied,
+    }
+}
+
+pub fn check(kind: SourceType) -> PermissionState {
+    match kind {
+        SourceType::Screen => {
+            if unsafe { CGPreflightScreenCaptureAccess() } {
+                PermissionState::Granted
+            } else {
+                PermissionState::Denied
+            }
+        }
+        SourceType::Camera => get_authorization_status(unsafe { AVMediaTypeVideo }),
+        SourceType::Audio => get_authorization_status(unsafe { AVMediaTypeAudio }),
+    }
+}
+
+pub fn request(kind: SourceType) -> PermissionState {
+    match kind {
+        // This shows the screen recording prompt the first time, the permission only
+        // takes effect after the process is restarted.
+        SourceType::Screen => {
+            if unsafe { CGRequestScreenCaptureAccess() } {
+                PermissionState::Granted
+            } else {
+                PermissionState::Denied
+            }
+        }
+        // The prompt of the camera and the microphone is shown by the system when the
+        // device is opened for the first time.
+        _ => check(kind),
+    }
+}
@@ -1,13 +1,500 @@
-use crate::{CaptureHandler, FrameArrived, Source, VideoCaptureSourceDescription};
+use crate::{
+    CaptureHandler, FrameArrived, FramePacer, Source, SourceEvent, SourceType,
+    VideoCaptureSourceDescription,
+};
 
-use hylarana_common::frame::VideoFrame;
+use std::{
+    ffi::{c_char, c_void},
+    ptr::{null, null_mut},
+    sync::{atomic::AtomicBool, mpsc, Arc},
+    time::{Duration, Instant},
+};
+
+use hylarana_common::{
+    atomic::EasyAtomic,
+    frame::{VideoFormat, VideoFrame, VideoRotation, VideoSubFormat},
+};
+
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
-pub enum ScreenCaptureError {}
+pub enum ScreenCaptureError {
+    #[error("failed to get the shareable content, the screen recording may not be permitted")]
+    NotFoundShareableContent,
+    #[error("not found a screen source")]
+    NotFoundScreenSource,
+    #[error("failed to create the capture stream")]
+    CreateStreamError,
+    #[error("failed to add the output of the capture stream")]
+    AddStreamOutputError,
+    #[error("failed to start the capture stream")]
+    StartCaptureError,
+}
+
+type Id = *mut c_void;
+type Sel = *mut c_void;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CMTime {
+    value: i64,
+    timescale: i32,
+    flags: u32,
+    epoch: i64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CGRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+#[link(name = "objc")]
+extern "C" {
+    fn objc_getClass(name: *const c_char) -> Id;
+    fn objc_getProtocol(name: *const c_char) -> *mut c_void;
+    fn objc_allocateClassPair(superclass: Id, name: *const c_char, extra: usize) -> Id;
+    fn objc_registerClassPair(class: Id);
+    fn objc_autoreleasePoolPush() -> *mut c_void;
+    fn objc_autoreleasePoolPop(pool: *mut c_void);
+    fn class_addIvar(
+        class: Id,
+        name: *const c_char,
+        size: usize,
+        alignment: u8,
+        types: *const c_char,
+    ) -> bool;
+    fn class_addMethod(class: Id, name: Sel, imp: *const c_void, types: *const c_char) -> bool;
+    fn class_addProtocol(class: Id, protocol: *mut c_void) -> bool;
+    fn class_getInstanceVariable(class: Id, name: *const c_char) -> *mut c_void;
+    fn object_getIvar(object: Id, ivar: *mut c_void) -> Id;
+    fn object_setIvar(object: Id, ivar: *mut c_void, value: Id);
+    fn sel_registerName(name: *const c_char) -> Sel;
+    fn objc_msgSend();
+}
+
+// The blocks and the dispatch queues are part of libSystem.
+extern "C" {
+    static _NSConcreteStackBlock: [usize; 32];
+
+    fn dispatch_queue_create(label: *const c_char, attr: *const c_void) -> *mut c_void;
+    fn dispatch_sync_f(
+        queue: *mut c_void,
+        context: *mut c_void,
+        work: unsafe extern "C" fn(*mut c_void),
+    );
+    fn dispatch_release(object: *mut c_void);
+}
+
+#[link(name = "ScreenCaptureKit", kind = "framework")]
+extern "C" {
+    static SCStreamFrameInfoStatus: Id;
+}
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGMainDisplayID() -> u32;
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFArrayGetCount(array: *const c_void) -> isize;
+    fn CFArrayGetValueAtIndex(array: *const c_void, index: isize) -> *const c_void;
+    fn CFDictionaryGetValue(dictionary: *const c_void, key: *const c_void) -> *const c_void;
+}
+
+#[link(name = "CoreMedia", kind = "framework")]
+extern "C" {
+    fn CMSampleBufferGetImageBuffer(buffer: *mut c_void) -> *mut c_void;
+    fn CMSampleBufferGetSampleAttachmentsArray(buffer: *mut c_void, create: bool) -> *const c_void;
+}
+
+#[link(name = "CoreVideo", kind = "framework")]
+extern "C" {
+    fn CVPixelBufferLockBaseAddress(buffer: *mut c_void, flags: u64) -> i32;
+    fn CVPixelBufferUnlockBaseAddress(buffer: *mut c_void, flags: u64) -> i32;
+    fn CVPixelBufferGetWidth(buffer: *mut c_void) -> usize;
+    fn CVPixelBufferGetHeight(buffer: *mut c_void) -> usize;
+    fn CVPixelBufferGetBaseAddressOfPlane(buffer: *mut c_void, plane: usize) -> *const c_void;
+    fn CVPixelBufferGetBytesPerRowOfPlane(buffer: *mut c_void, plane: usize) -> usize;
+}
+
+// kCVPixelFormatType_420YpCbCr8BiPlanarVideoRange, that is, NV12.
+const PIXEL_FORMAT_NV12: u32 = u32::from_be_bytes(*b"420v");
+
+const PIXEL_BUFFER_READ_ONLY: u64 = 1;
+
+// SCStreamOutputTypeScreen
+const OUTPUT_TYPE_SCREEN: isize = 0;
+
+// SCFrameStatusComplete, the other frames do not carry a new picture.
+const FRAME_STATUS_COMPLETE: isize = 0;
+
+// How long the completion handlers of ScreenCaptureKit are waited for.
+const COMPLETION_TIMEOUT: Duration = Duration::from_secs(5);
+
+// objc_msgSend must be called with the signature of the method.
+macro_rules! send {
+    ($receiver:expr, $selector:literal $(, $arg:expr => $ty:ty)* ; $ret:ty) => {{
+        let send: unsafe extern "C" fn(Id, Sel $(, $ty)*) -> $ret =
+            std::mem::transmute(objc_msgSend as unsafe extern "C" fn());
+
+        send($receiver, sel_registerName($selector.as_ptr()) $(, $arg)*)
+    }};
+}
+
+unsafe fn release(object: Id) {
+    if !object.is_null() {
+        send!(object, c"release"; ())
+    }
+}
+
+// The objects that are autoreleased by the calls are released when the pool
+// is dropped, the threads of the capture do not have a pool of their own.
+struct AutoreleasePool(*mut c_void);
+
+impl AutoreleasePool {
+    fn new() -> Self {
+        Self(unsafe { objc_autoreleasePoolPush() })
+    }
+}
+
+impl Drop for AutoreleasePool {
+    fn drop(&mut self) {
+        unsafe { objc_autoreleasePoolPop(self.0) }
+    }
+}
+
+#[repr(C)]
+struct BlockDescriptor {
+    reserved: usize,
+    size: usize,
+}
+
+// The layout of a block literal without captures that need to be copied or
+// disposed, the completion handlers are copied by ScreenCaptureKit before the
+// call returns, so the block can live on the stack.
+#[repr(C)]
+struct Block<T> {
+    isa: *const c_void,
+    flags: i32,
+    reserved: i32,
+    invoke: *const c_void,
+    descriptor: *const BlockDescriptor,
+    context: *mut mpsc::Sender<T>,
+}
+
+static BLOCK_DESCRIPTOR: BlockDescriptor = BlockDescriptor {
+    reserved: 0,
+    size: size_of::<Block<()>>(),
+};
+
+// Calls an asynchronous method with a completion handler and waits for the
+// result that the handler sends, `None` if the handler is not called in time.
+unsafe fn complete<T, F>(invoke: *const c_void, call: F) -> Option<T>
+where
+    F: FnOnce(*mut c_void),
+{
+    let (tx, rx) = mpsc::channel();
+    let mut block = Block {
+        isa: _NSConcreteStackBlock.as_ptr() as *const c_void,
+        flags: 0,
+        reserved: 0,
+        invoke,
+        descriptor: &BLOCK_DESCRIPTOR,
+        // The sender is taken back by the handler, it is leaked if the handler is never
+        // called.
+        context: Box::into_raw(Box::new(tx)),
+    };
+
+    call(&mut block as *mut Block<T> as *mut c_void);
+    rx.recv_timeout(COMPLETION_TIMEOUT).ok()
+}
+
+// ^(SCShareableContent *content, NSError *error)
+unsafe extern "C" fn content_handler(block: *mut Block<usize>, content: Id, _error: Id) {
+    let tx = Box::from_raw((*block).context);
+
+    // The content is only valid in the handler without being retained.
+    if !content.is_null() {
+        send!(content, c"retain"; Id);
+    }
+
+    let _ = tx.send(content as usize);
+}
+
+// ^(NSError *error)
+unsafe extern "C" fn error_handler(block: *mut Block<bool>, error: Id) {
+    let tx = Box::from_raw((*block).context);
+    let _ = tx.send(error.is_null());
+}
+
+// The displays that can be captured, the content is released when it is
+// dropped.
+struct ShareableContent(Id);
+
+impl ShareableContent {
+    fn get() -> Result<Self, ScreenCaptureError> {
+        let content = unsafe {
+            complete::<usize, _>(content_handler as *const c_void, |block| {
+                send!(
+                    objc_getClass(c"SCShareableContent".as_ptr()),
+                    c"getShareableContentWithCompletionHandler:",
+                    block => *mut c_void;
+                    ()
+                )
+            })
+        };
+
+        match content {
+            Some(content) if content != 0 => Ok(Self(content as Id)),
+            _ => Err(ScreenCaptureError::NotFoundShareableContent),
+        }
+    }
+
+    // NSArray<SCDisplay *>
+    fn displays(&self) -> Vec<Id> {
+        unsafe {
+            let displays = send!(self.0, c"displays"; Id);
+            let count = send!(displays, c"count"; usize);
+
+            (0..count)
+                .map(|i| send!(displays, c"objectAtIndex:", i => usize; Id))
+                .collect()
+        }
+    }
+}
+
+impl Drop for ShareableContent {
+    fn drop(&mut self) {
+        unsafe { release(self.0) }
+    }
+}
+
+// The state that the stream output shares with the capture, the output only
+// holds a pointer to it, which stays valid until the session is dropped.
+struct Context {
+    arrived: Mutex<Box<dyn FrameArrived<Frame = VideoFrame>>>,
+    pacer: Mutex<FramePacer>,
+    status: AtomicBool,
+    display_width: u32,
+    display_height: u32,
+}
+
+impl Context {
+    fn process(&self, buffer: *mut c_void) {
+        if !self.status.get() || !is_complete(buffer) {
+            return;
+        }
 
+        let image = unsafe { CMSampleBufferGetImageBuffer(buffer) };
+        if image.is_null() {
+            return;
+        }
+
+        // ScreenCaptureKit delivers the frames at the minimum frame interval, the
+        // pacer only drops the frames when the capture rate is reduced.
+        let Some(timestamp) = self.pacer.lock().accept() else {
+            return;
+        };
+
+        if unsafe { CVPixelBufferLockBaseAddress(image, PIXEL_BUFFER_READ_ONLY) } != 0 {
+            return;
+        }
+
+        let time = Instant::now();
+
+        let mut frame = unsafe {
+            VideoFrame {
+                format: VideoFormat::NV12,
+                sub_format: VideoSubFormat::SW,
+                width: CVPixelBufferGetWidth(image) as u32,
+                height: CVPixelBufferGetHeight(image) as u32,
+                display_width: self.display_width,
+                display_height: self.display_height,
+                timestamp,
+                ..Default::default()
+            }
+        };
+
+        for i in 0..2 {
+            unsafe {
+                frame.data[i] = CVPixelBufferGetBaseAddressOfPlane(image, i);
+                frame.linesize[i] = CVPixelBufferGetBytesPerRowOfPlane(image, i);
+            }
+        }
+
+        let accepted = self.arrived.lock().sink(&frame);
+        unsafe {
+            CVPixelBufferUnlockBaseAddress(image, PIXEL_BUFFER_READ_ONLY);
+        }
+
+        if accepted {
+            self.pacer.lock().feedback(time.elapsed());
+        } else {
+            self.status.update(false);
+        }
+    }
+}
+
+// Whether the frame has a new picture, ScreenCaptureKit also delivers the
+// frames of a screen that has not changed, which have no picture.
+fn is_complete(buffer: *mut c_void) -> bool {
+    unsafe {
+        let attachments = CMSampleBufferGetSampleAttachmentsArray(buffer, false);
+        if attachments.is_null() || CFArrayGetCount(attachments) == 0 {
+            return false;
+        }
+
+        let status = CFDictionaryGetValue(
+            CFArrayGetValueAtIndex(attachments, 0),
+            SCStreamFrameInfoStatus as *const c_void,
+        );
+
+        !status.is_null() && send!(status as Id, c"integerValue"; isize) == FRAME_STATUS_COMPLETE
+    }
+}
+
+// The class of the object that is both the output and the delegate of the
+// stream, the context is kept in an instance variable.
+struct OutputClass {
+    class: usize,
+    context: usize,
+}
+
+static OUTPUT_CLASS: Lazy<OutputClass> = Lazy::new(|| unsafe {
+    let class = objc_allocateClassPair(
+        objc_getClass(c"NSObject".as_ptr()),
+        c"HylaranaScreenCaptureOutput".as_ptr(),
+        0,
+    );
+
+    class_addIvar(
+        class,
+        c"context".as_ptr(),
+        size_of::<*const c_void>(),
+        size_of::<*const c_void>().trailing_zeros() as u8,
+        c"^v".as_ptr(),
+    );
+
+    class_addMethod(
+        class,
+        sel_registerName(c"stream:didOutputSampleBuffer:ofType:".as_ptr()),
+        did_output_sample_buffer as *const c_void,
+        c"v@:@^{opaqueCMSampleBuffer=}q".as_ptr(),
+    );
+
+    class_addMethod(
+        class,
+        sel_registerName(c"stream:didStopWithError:".as_ptr()),
+        did_stop_with_error as *const c_void,
+        c"v@:@@".as_ptr(),
+    );
+
+    for name in [c"SCStreamOutput", c"SCStreamDelegate"] {
+        let protocol = objc_getProtocol(name.as_ptr());
+        if !protocol.is_null() {
+            class_addProtocol(class, protocol);
+        }
+    }
+
+    objc_registerClassPair(class);
+
+    OutputClass {
+        context: class_getInstanceVariable(class, c"context".as_ptr()) as usize,
+        class: class as usize,
+    }
+});
+
+unsafe fn get_context<'a>(this: Id) -> Option<&'a Context> {
+    (object_getIvar(this, OUTPUT_CLASS.context as *mut c_void) as *const Context).as_ref()
+}
+
+// - (void)stream:(SCStream *)stream didOutputSampleBuffer:(CMSampleBufferRef)buffer
+//   ofType:(SCStreamOutputType)type
+unsafe extern "C" fn did_output_sample_buffer(
+    this: Id,
+    _cmd: Sel,
+    _stream: Id,
+    buffer: *mut c_void,
+    kind: isize,
+) {
+    if kind == OUTPUT_TYPE_SCREEN {
+        if let Some(context) = get_context(this) {
+            context.process(buffer);
+        }
+    }
+}
+
+// - (void)stream:(SCStream *)stream didStopWithError:(NSError *)error
+unsafe extern "C" fn did_stop_with_error(this: Id, _cmd: Sel, _stream: Id, _error: Id) {
+    if let Some(context) = get_context(this) {
+        log::warn!("macos screen capture stream stopped by the system");
+
+        // The stream is stopped when the display is gone, such as being unplugged.
+        if context.status.get() {
+            context.status.update(false);
+            context.arrived.lock().event(SourceEvent::Removed);
+        }
+    }
+}
+
+unsafe extern "C" fn drain(_: *mut c_void) {}
+
+struct Session {
+    stream: Id,
+    output: Id,
+    queue: *mut c_void,
+    context: *const Context,
+}
+
+unsafe impl Send for Session {}
+unsafe impl Sync for Session {}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        let _pool = AutoreleasePool::new();
+
+        unsafe {
+            (*self.context).status.update(false);
+
+            let stream = self.stream;
+            if !stream.is_null()
+                && complete::<bool, _>(error_handler as *const c_void, |block| {
+                send!(stream, c"stopCaptureWithCompletionHandler:", block => *mut c_void; ())
+            })
+            .is_none()
+            {
+                log::warn!("macos screen capture stream did not stop in time");
+            }
+
+            // The frames that are already on the queue are handled before the context is
+            // released.
+            dispatch_sync_f(self.queue, null_mut(), drain);
+
+            release(self.stream);
+            release(self.output);
+            dispatch_release(self.queue);
+            drop(Arc::from_raw(self.context));
+        }
+
+        log::info!("macos screen capture stopped");
+    }
+}
+
+/// The screen capture of macOS with ScreenCaptureKit, the frames are NV12 in
+/// the memory that are encoded by VideoToolbox.
+///
+/// The frames are always software frames, the hardware option is ignored.
+/// ScreenCaptureKit scales the display to the configured size, so the frames
+/// keep the size when the resolution of the display changes.
 #[derive(Default)]
-pub struct ScreenCapture;
+pub struct ScreenCapture(Mutex<Option<Session>>);
 
 impl CaptureHandler for ScreenCapture {
     type Frame = VideoFrame;
@@ -15,18 +502,174 @@ impl CaptureHandler for ScreenCapture {
     type CaptureOptions = VideoCaptureSourceDescription;
 
     fn get_sources() -> Result<Vec<Source>, Self::Error> {
-        Ok(Vec::new())
+        let _pool = AutoreleasePool::new();
+
+        let content = ShareableContent::get()?;
+        let main = unsafe { CGMainDisplayID() };
+
+        Ok(content
+            .displays()
+            .into_iter()
+            .enumerate()
+            .map(|(index, display)| {
+                let id = unsafe { send!(display, c"displayID"; u32) };
+
+                Source {
+                    index,
+                    is_default: id == main,
+                    rotation: VideoRotation::Rotate0,
+                    backend: None,
+                    kind: SourceType::Screen,
+                    id: id.to_string(),
+                    name: format!("display {}", id),
+                }
+            })
+            .collect())
     }
 
     fn start<S: FrameArrived<Frame = Self::Frame> + 'static>(
         &self,
-        _options: Self::CaptureOptions,
-        mut _arrived: S,
+        options: Self::CaptureOptions,
+        arrived: S,
     ) -> Result<(), Self::Error> {
-        todo!("screen capture is not supported on macos")
+        let _pool = AutoreleasePool::new();
+
+        let content = ShareableContent::get()?;
+        let display = content
+            .displays()
+            .into_iter()
+            .find(|it| unsafe { send!(*it, c"displayID"; u32) }.to_string() == options.source.id)
+            .ok_or(ScreenCaptureError::NotFoundScreenSource)?;
+
+        // The 4:2:0 frames have an even size, the picture is drawn to the top left
+        // corner and the rest is padding.
+        let coded_size = options.size.align(2);
+
+        let context = Arc::into_raw(Arc::new(Context {
+            arrived: Mutex::new(Box::new(arrived)),
+            pacer: Mutex::new(FramePacer::new(options.fps, options.adaptive_pacing)),
+            status: AtomicBool::new(true),
+            display_width: options.size.width,
+            display_height: options.size.height,
+        }));
+
+        let session = unsafe {
+            let filter = send!(objc_getClass(c"SCContentFilter".as_ptr()), c"alloc"; Id);
+            let filter = send!(
+                filter,
+                c"initWithDisplay:excludingWindows:",
+                display => Id,
+                send!(objc_getClass(c"NSArray".as_ptr()), c"array"; Id) => Id;
+                Id
+            );
+
+            let config = send!(objc_getClass(c"SCStreamConfiguration".as_ptr()), c"alloc"; Id);
+            let config = send!(config, c"init"; Id);
+            send!(config, c"setWidth:", coded_size.width as usize => usize; ());
+            send!(config, c"setHeight:", coded_size.height as usize => usize; ());
+            send!(config, c"setPixelFormat:", PIXEL_FORMAT_NV12 => u32; ());
+            send!(config, c"setShowsCursor:", true => bool; ());
+            send!(
+                config,
+                c"setMinimumFrameInterval:",
+                CMTime {
+                    value: 1,
+                    timescale: options.fps.max(1) as i32,
+                    // kCMTimeFlags_Valid
+                    flags: 1,
+                    epoch: 0,
+                } => CMTime;
+                ()
+            );
+
+            if coded_size != options.size {
+                send!(
+                    config,
+                    c"setDestinationRect:",
+                    CGRect {
+                        x: 0.0,
+                        y: 0.0,
+                        width: options.size.width as f64,
+                        height: options.size.height as f64,
+                    } => CGRect;
+                    ()
+                );
+            }
+
+            let output = send!(OUTPUT_CLASS.class as Id, c"new"; Id);
+            object_setIvar(
+                output,
+                OUTPUT_CLASS.context as *mut c_void,
+                context as *mut c_void,
+            );
+
+            let stream = send!(objc_getClass(c"SCStream".as_ptr()), c"alloc"; Id);
+            let stream = send!(
+                stream,
+                c"initWithFilter:configuration:delegate:",
+                filter => Id,
+                config => Id,
+                output => Id;
+                Id
+            );
+
+            release(filter);
+            release(config);
+
+            // The session releases all of them from now on, including when the stream
+            // fails to start.
+            let session = Session {
+                queue: dispatch_queue_create(c"HylaranaScreenCapture".as_ptr(), null()),
+                context,
+                output,
+                stream,
+            };
+
+            if stream.is_null() {
+                return Err(ScreenCaptureError::CreateStreamError);
+            }
+
+            let mut error: Id = null_mut();
+            if !send!(
+                stream,
+                c"addStreamOutput:type:sampleHandlerQueue:error:",
+                output => Id,
+                OUTPUT_TYPE_SCREEN => isize,
+                session.queue => *mut c_void,
+                &mut error => *mut Id;
+                bool
+            ) {
+                return Err(ScreenCaptureError::AddStreamOutputError);
+            }
+
+            let started = complete::<bool, _>(
+                error_handler as *const c_void,
+                |block| send!(stream, c"startCaptureWithCompletionHandler:", block => *mut c_void; ()),
+            );
+
+            if started != Some(true) {
+                return Err(ScreenCaptureError::StartCaptureError);
+            }
+
+            session
+        };
+
+        log::info!(
+            "macos screen capture started, id={}, size={}x{}",
+            options.source.id,
+            options.size.width,
+            options.size.height
+        );
+
+        // The previous session is stopped if the capture is started again.
+        drop(self.0.lock().replace(session));
+
+        Ok(())
     }
 
     fn stop(&self) -> Result<(), Self::Error> {
-        todo!("screen capture is not supported on macos")
+        drop(self.0.lock().take());
+
+        Ok(())
     }
 }
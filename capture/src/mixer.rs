@@ -0,0 +1,197 @@
+use std::{
+    collections::{vec_deque::Drain, VecDeque},
+    sync::Arc,
+};
+
+use crate::FrameArrived;
+
+use hylarana_common::frame::AudioFrame;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// The settings of an input of the mixer, they can be changed while mixing.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AudioMixerInput {
+    /// The gain applied to the input, `1.0` keeps the original volume.
+    pub gain: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioMixerInput {
+    fn default() -> Self {
+        Self {
+            gain: 1.0,
+            muted: false,
+        }
+    }
+}
+
+// The sample buffer of an input, the timestamp is the capture time of the first
+// sample in the buffer.
+struct InputBuffer {
+    input: AudioMixerInput,
+    samples: VecDeque<i16>,
+    timestamp: u64,
+}
+
+impl InputBuffer {
+    fn consume(&mut self, count: usize, sample_rate: u32) -> Drain<'_, i16> {
+        let count = count.min(self.samples.len());
+        self.timestamp += count as u64 * 1_000_000 / sample_rate.max(1) as u64;
+        self.samples.drain(..count)
+    }
+}
+
+struct Mixer<S> {
+    sink: S,
+    inputs: Vec<InputBuffer>,
+    mixed: Vec<f32>,
+    output: Vec<i16>,
+}
+
+impl<S: FrameArrived<Frame = AudioFrame>> Mixer<S> {
+    fn push(&mut self, index: usize, frame: &AudioFrame) -> bool {
+        // A single input at the original volume does not need to be mixed.
+        if self.inputs.len() == 1 && self.inputs[0].input == AudioMixerInput::default() {
+            return self.sink.sink(frame);
+        }
+
+        let size = frame.frames as usize;
+        let sample_rate = frame.sample_rate;
+        {
+            let input = &mut self.inputs[index];
+            if input.samples.is_empty() {
+                input.timestamp = frame.timestamp;
+            }
+
+            input
+                .samples
+                .extend(unsafe { std::slice::from_raw_parts(frame.data, size) });
+
+            // An input that runs ahead of the others does not accumulate more than half a
+            // second of latency, the oldest samples are dropped.
+            let limit = sample_rate as usize / 2;
+            if input.samples.len() > limit {
+                let count = input.samples.len() - limit;
+                input.consume(count, sample_rate);
+            }
+        }
+
+        while size > 0 && self.inputs.iter().any(|it| it.samples.len() >= size * 2) {
+            let timestamp = self
+                .inputs
+                .iter()
+                .max_by_key(|it| it.samples.len())
+                .map(|it| it.timestamp)
+                .unwrap_or(0);
+
+            self.mixed.clear();
+            self.mixed.resize(size, 0.0);
+
+            for input in self.inputs.iter_mut() {
+                // The muted inputs are still consumed, so that they stay in sync with the
+                // other inputs.
+                let gain = if input.input.muted {
+                    0.0
+                } else {
+                    input.input.gain
+                };
+
+                for (i, sample) in input.consume(size, sample_rate).enumerate() {
+                    self.mixed[i] += sample as f32 * gain;
+                }
+            }
+
+            self.output.clear();
+            self.output.extend(
+                self.mixed
+                    .iter()
+                    .map(|it| it.clamp(i16::MIN as f32, i16::MAX as f32) as i16),
+            );
+
+            if !self.sink.sink(&AudioFrame {
+                data: self.output.as_ptr(),
+                frames: size as u32,
+                sample_rate,
+                timestamp,
+            }) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Mixes multiple audio sources into one stream.
+///
+/// Each source is captured with its own `AudioMixerTrack`, the sources must be
+/// captured at the same sample rate, which the audio capture does by
+/// resampling. The mixed frames are passed to the sink.
+///
+/// The devices deliver the samples at different times and in different sizes,
+/// and some devices, such as the loopback of the system audio, deliver nothing
+/// while there is no sound. So a frame is mixed when any source has buffered
+/// two frames, and the sources that have fewer samples are padded with
+/// silence.
+pub struct AudioMixer<S>(Arc<Mutex<Mixer<S>>>);
+
+impl<S> Clone for AudioMixer<S> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<S: FrameArrived<Frame = AudioFrame>> AudioMixer<S> {
+    pub fn new(sink: S, inputs: &[AudioMixerInput]) -> Self {
+        Self(Arc::new(Mutex::new(Mixer {
+            inputs: inputs
+                .iter()
+                .map(|input| InputBuffer {
+                    samples: VecDeque::with_capacity(48000),
+                    input: *input,
+                    timestamp: 0,
+                })
+                .collect(),
+            mixed: Vec::with_capacity(48000),
+            output: Vec::with_capacity(48000),
+            sink,
+        })))
+    }
+
+    /// Create the sink for the input of the index, which is passed to the
+    /// audio capture of the source.
+    pub fn track(&self, index: usize) -> AudioMixerTrack<S> {
+        AudioMixerTrack {
+            mixer: self.clone(),
+            index,
+        }
+    }
+
+    /// Get the settings of the inputs.
+    pub fn inputs(&self) -> Vec<AudioMixerInput> {
+        self.0.lock().inputs.iter().map(|it| it.input).collect()
+    }
+
+    /// Change the settings of an input, the change applies to the next mixed
+    /// frame, an index out of range is ignored.
+    pub fn set_input(&self, index: usize, input: AudioMixerInput) {
+        if let Some(buffer) = self.0.lock().inputs.get_mut(index) {
+            buffer.input = input;
+        }
+    }
+}
+
+/// The sink of an input of the mixer.
+pub struct AudioMixerTrack<S> {
+    mixer: AudioMixer<S>,
+    index: usize,
+}
+
+impl<S: FrameArrived<Frame = AudioFrame>> FrameArrived for AudioMixerTrack<S> {
+    type Frame = AudioFrame;
+
+    fn sink(&mut self, frame: &Self::Frame) -> bool {
+        self.mixer.0.lock().push(self.index, frame)
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use hylarana_common::clock::MediaClock;
+
+// The capture rate is never reduced below a quarter of the configured frame
+// rate.
+const MAX_DIVISOR: u32 = 4;
+
+// The number of consecutive fast frames required before the capture rate is
+// raised again.
+const RECOVER_FRAMES: u32 = 30;
+
+/// Frame rate limiter for the capture sources.
+///
+/// The pacer keeps the frames at the configured frame rate, it drops the
+/// frames that arrive earlier than the next frame time, and generates
+/// timestamps on the media clock that are aligned to the frame interval, so
+/// that the encoder does not have to care about how the source delivers the
+/// frames.
+///
+/// In adaptive mode, when the sink takes longer than the frame interval to
+/// process a frame, which usually means that the encoder queue backs up, the
+/// capture rate is reduced, and is restored step by step after the sink
+/// catches up.
+pub struct FramePacer {
+    interval: Duration,
+    adaptive: bool,
+    start: Instant,
+    // The media clock time of the start.
+    base: u64,
+    // The next frame time, as the number of intervals since the start.
+    index: u64,
+    divisor: u32,
+    fast_frames: u32,
+}
+
+impl FramePacer {
+    pub fn new(fps: u8, adaptive: bool) -> Self {
+        Self {
+            interval: Duration::from_secs(1) / fps.max(1) as u32,
+            base: MediaClock::now(),
+            start: Instant::now(),
+            fast_frames: 0,
+            divisor: 1,
+            index: 0,
+            adaptive,
+        }
+    }
+
+    /// The current capture interval, which is larger than the configured frame
+    /// interval when the capture rate is reduced.
+    pub fn interval(&self) -> Duration {
+        self.interval * self.divisor
+    }
+
+    /// Used by the sources that pull frames, blocks until the next frame time
+    /// and returns the timestamp of the frame in microseconds.
+    ///
+    /// If the caller is late by more than one interval, the missed frame times
+    /// are skipped instead of being caught up in a burst.
+    pub fn wait(&mut self) -> u64 {
+        let now = Instant::now();
+        let deadline = self.deadline(self.index);
+        if deadline > now {
+            thread::sleep(deadline - now);
+        } else {
+            self.index = self.index.max(self.elapsed_index(now));
+        }
+
+        self.advance()
+    }
+
+    /// Used by the sources that push frames, such as cameras, returns the
+    /// timestamp of the frame in microseconds if the frame that arrived now
+    /// should be delivered, otherwise the frame is dropped.
+    pub fn accept(&mut self) -> Option<u64> {
+        let now = Instant::now();
+        if self.deadline(self.index) > now {
+            return None;
+        }
+
+        self.index = self.index.max(self.elapsed_index(now));
+        Some(self.advance())
+    }
+
+    /// Reports the time the sink spent on the last frame, this is only used in
+    /// adaptive mode.
+    pub fn feedback(&mut self, elapsed: Duration) {
+        if !self.adaptive {
+            return;
+        }
+
+        if elapsed > self.interval() {
+            self.fast_frames = 0;
+
+            if self.divisor < MAX_DIVISOR {
+                self.divisor += 1;
+
+                log::info!("capture pacer reduce rate, interval={:?}", self.interval());
+            }
+        } else if self.divisor > 1 {
+            self.fast_frames += 1;
+
+            if self.fast_frames >= RECOVER_FRAMES {
+                self.fast_frames = 0;
+                self.divisor -= 1;
+
+                log::info!("capture pacer restore rate, interval={:?}", self.interval());
+            }
+        }
+    }
+
+    // The time of the frame relative to the start.
+    fn offset(&self, index: u64) -> Duration {
+        Duration::from_nanos(self.interval.as_nanos() as u64 * index)
+    }
+
+    fn deadline(&self, index: u64) -> Instant {
+        self.start + self.offset(index)
+    }
+
+    fn elapsed_index(&self, now: Instant) -> u64 {
+        (now.duration_since(self.start).as_nanos() / self.interval.as_nanos()) as u64
+    }
+
+    fn advance(&mut self) -> u64 {
+        let timestamp = self.base + self.offset(self.index).as_micros() as u64;
+        self.index += self.divisor as u64;
+        timestamp
+    }
+}
@@ -1,31 +1,53 @@
-use crate::{CaptureHandler, FrameArrived, Source, SourceType, VideoCaptureSourceDescription};
+use crate::{
+    camera::select_format, CameraControl, CameraControlRange, CameraControlValue, CameraFormat,
+    CameraPixelFormat, CaptureHandler, FrameArrived, FramePacer, Source, SourceType,
+    VideoCaptureSourceDescription,
+};
 
 use std::{
     ptr::null_mut,
     slice::from_raw_parts,
     sync::{atomic::AtomicBool, Arc},
-    thread,
+    time::Instant,
 };
 
 use hylarana_common::{
     atomic::EasyAtomic,
-    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    frame::{FieldOrder, VideoFormat, VideoFrame, VideoRotation, VideoSubFormat},
+    runtime::{self, ThreadKind},
     win32::{IMFValue, MediaFoundationIMFAttributesSetHelper, MediaThreadClass},
+    Size,
 };
 
 use thiserror::Error;
 use windows::{
     core::Interface,
-    Win32::Media::MediaFoundation::{
-        IMF2DBuffer, IMFAttributes, IMFMediaSource, IMFSample, IMFSourceReader, MFCreateAttributes,
-        MFCreateDeviceSource, MFCreateMediaType, MFCreateSourceReaderFromMediaSource,
-        MFEnumDeviceSources, MFMediaType_Video, MFVideoFormat_NV12,
-        MF_DEVSOURCE_ATTRIBUTE_FRIENDLY_NAME, MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE,
-        MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_GUID,
-        MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_SYMBOLIC_LINK, MF_MT_DEFAULT_STRIDE,
-        MF_MT_FRAME_RATE, MF_MT_FRAME_SIZE, MF_MT_MAJOR_TYPE, MF_MT_SUBTYPE,
-        MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, MF_SOURCE_READER_ENABLE_ADVANCED_VIDEO_PROCESSING,
-        MF_SOURCE_READER_FIRST_VIDEO_STREAM,
+    Win32::{
+        Foundation::BOOL,
+        Media::{
+            DirectShow::{
+                CameraControl_Exposure, CameraControl_Flags_Auto, CameraControl_Flags_Manual,
+                CameraControl_Focus, CameraControl_Zoom, IAMCameraControl,
+            },
+            MediaFoundation::{
+                IMF2DBuffer, IMFAttributes, IMFMediaSource, IMFMediaType, IMFMediaTypeHandler,
+                IMFSample, IMFSourceReader, MFCreateAttributes, MFCreateDeviceSource,
+                MFCreateMediaType, MFCreateSourceReaderFromMediaSource, MFEnumDeviceSources,
+                MFMediaType_Video, MFSampleExtension_BottomFieldFirst,
+                MFSampleExtension_Interlaced, MFVideoFormat_MJPG, MFVideoFormat_NV12,
+                MFVideoFormat_YUY2, MFVideoInterlaceMode,
+                MFVideoInterlace_FieldInterleavedLowerFirst,
+                MFVideoInterlace_FieldInterleavedUpperFirst,
+                MFVideoInterlace_MixedInterlaceOrProgressive, MFVideoInterlace_Progressive,
+                MF_DEVSOURCE_ATTRIBUTE_FRIENDLY_NAME, MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE,
+                MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_GUID,
+                MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_SYMBOLIC_LINK, MF_MT_DEFAULT_STRIDE,
+                MF_MT_FRAME_RATE, MF_MT_FRAME_SIZE, MF_MT_INTERLACE_MODE, MF_MT_MAJOR_TYPE,
+                MF_MT_SUBTYPE, MF_MT_VIDEO_ROTATION, MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS,
+                MF_SOURCE_READER_ENABLE_ADVANCED_VIDEO_PROCESSING,
+                MF_SOURCE_READER_FIRST_VIDEO_STREAM,
+            },
+        },
     },
 };
 
@@ -43,6 +65,10 @@ pub enum CameraCaptureError {
     Lock2DError,
     #[error("FrameArrived sink return false")]
     FrameArrivedStoped,
+    #[error("camera has no video stream")]
+    NotFoundVideoStream,
+    #[error("camera does not support the control")]
+    UnsupportedControl,
 }
 
 /// Creates an empty attribute store.
@@ -53,6 +79,76 @@ fn create_attributes() -> Result<IMFAttributes, CameraCaptureError> {
     Ok(attributes)
 }
 
+/// Creates a media source for a hardware capture device.
+fn create_device(id: &str) -> Result<IMFMediaSource, CameraCaptureError> {
+    let mut attributes = create_attributes()?;
+    attributes.set(
+        MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE,
+        IMFValue::GUID(MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_GUID),
+    )?;
+    attributes.set(
+        MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_SYMBOLIC_LINK,
+        IMFValue::String(id.to_string()),
+    )?;
+
+    Ok(unsafe { MFCreateDeviceSource(&attributes)? })
+}
+
+/// The native media types of the video stream of the device, and the modes
+/// they describe.
+fn get_media_types(
+    device: &IMFMediaSource,
+) -> Result<(IMFMediaTypeHandler, Vec<(IMFMediaType, CameraFormat)>), CameraCaptureError> {
+    let handler = unsafe {
+        let mut selected = BOOL::default();
+        let mut stream = None;
+        device
+            .CreatePresentationDescriptor()?
+            .GetStreamDescriptorByIndex(0, &mut selected, &mut stream)?;
+
+        stream
+            .ok_or_else(|| CameraCaptureError::NotFoundVideoStream)?
+            .GetMediaTypeHandler()?
+    };
+
+    let count = unsafe { handler.GetMediaTypeCount()? };
+    let mut types = Vec::with_capacity(count as usize);
+    for index in 0..count {
+        let media_type = unsafe { handler.GetMediaTypeByIndex(index)? };
+
+        // The size and the frame rate are packed into the high and low 32 bits.
+        let (size, rate, subtype) = unsafe {
+            (
+                media_type.GetUINT64(&MF_MT_FRAME_SIZE)?,
+                media_type.GetUINT64(&MF_MT_FRAME_RATE).unwrap_or(0),
+                media_type.GetGUID(&MF_MT_SUBTYPE)?,
+            )
+        };
+
+        let format = CameraFormat {
+            size: Size {
+                width: (size >> 32) as u32,
+                height: size as u32,
+            },
+            fps: if rate as u32 == 0 {
+                0.0
+            } else {
+                (rate >> 32) as f64 / (rate as u32) as f64
+            },
+            pixel_format: match subtype {
+                MFVideoFormat_NV12 => CameraPixelFormat::NV12,
+                MFVideoFormat_YUY2 => CameraPixelFormat::YUYV,
+                MFVideoFormat_MJPG => CameraPixelFormat::MJPEG,
+                _ => CameraPixelFormat::Other,
+            },
+        };
+
+        types.push((media_type, format));
+    }
+
+    Ok((handler, types))
+}
+
 trait SampleIterator {
     type Item;
 
@@ -88,6 +184,9 @@ struct Context<T> {
     device: IMFMediaSource,
     reader: IMFSourceReader,
     frame: VideoFrame,
+    // The field order of the device, the samples may override it.
+    field_order: FieldOrder,
+    pacer: FramePacer,
     arrived: T,
 }
 
@@ -111,6 +210,30 @@ impl<T: FrameArrived<Frame = VideoFrame>> Context<T> {
             return Err(CameraCaptureError::CaptureIsStoped);
         }
 
+        // The device may deliver more frames than requested, the extra frames are
+        // dropped.
+        self.frame.timestamp = if let Some(timestamp) = self.pacer.accept() {
+            timestamp
+        } else {
+            return Ok(());
+        };
+
+        // A device of mixed content marks each sample, a device that does not mark
+        // them delivers the fields in its own order.
+        if self.field_order != FieldOrder::Progressive {
+            self.frame.field_order = unsafe {
+                if sample.GetUINT32(&MFSampleExtension_Interlaced).unwrap_or(1) == 0 {
+                    FieldOrder::Progressive
+                } else {
+                    match sample.GetUINT32(&MFSampleExtension_BottomFieldFirst) {
+                        Ok(0) => FieldOrder::TopFieldFirst,
+                        Ok(_) => FieldOrder::BottomFieldFirst,
+                        Err(_) => self.field_order,
+                    }
+                }
+            };
+        }
+
         // Converts a sample with multiple buffers into a sample with a single buffer.
         let buffer = unsafe { sample.ConvertToContiguousBuffer()? };
 
@@ -134,10 +257,14 @@ impl<T: FrameArrived<Frame = VideoFrame>> Context<T> {
         self.frame.data[1] =
             unsafe { data.add(stride as usize * self.frame.height as usize) as *const _ };
         self.frame.linesize = [stride as usize, stride as usize, 0];
+
+        let time = Instant::now();
         if !self.arrived.sink(&self.frame) {
             return Err(CameraCaptureError::FrameArrivedStoped);
         }
 
+        self.pacer.feedback(time.elapsed());
+
         // Unlocks a buffer that was previously locked.
         unsafe { texture.Unlock2D()? };
         Ok(())
@@ -158,6 +285,114 @@ impl<T> Drop for Context<T> {
 #[derive(Default)]
 pub struct CameraCapture(Arc<AtomicBool>);
 
+impl CameraCapture {
+    pub fn get_formats(source: &Source) -> Result<Vec<CameraFormat>, CameraCaptureError> {
+        let device = create_device(&source.id)?;
+        let formats = get_media_types(&device)
+            .map(|(_, types)| types.into_iter().map(|(_, it)| it).collect());
+
+        unsafe { device.Shutdown()? };
+        formats
+    }
+
+    pub fn get_controls(source: &Source) -> Result<CameraControls, CameraCaptureError> {
+        let device = create_device(&source.id)?;
+        let control = device.cast::<IAMCameraControl>()?;
+
+        Ok(CameraControls { device, control })
+    }
+}
+
+/// The controls of a camera, the controls are on a separate media source of
+/// the device, which is not started.
+pub struct CameraControls {
+    device: IMFMediaSource,
+    control: IAMCameraControl,
+}
+
+unsafe impl Sync for CameraControls {}
+unsafe impl Send for CameraControls {}
+
+impl CameraControls {
+    fn property(control: CameraControl) -> i32 {
+        match control {
+            CameraControl::Exposure => CameraControl_Exposure.0,
+            CameraControl::Focus => CameraControl_Focus.0,
+            CameraControl::Zoom => CameraControl_Zoom.0,
+        }
+    }
+
+    pub fn range(&self, control: CameraControl) -> Option<CameraControlRange> {
+        let (mut min, mut max, mut step, mut default, mut flags) = (0, 0, 0, 0, 0);
+        unsafe {
+            self.control
+                .GetRange(
+                    Self::property(control),
+                    &mut min,
+                    &mut max,
+                    &mut step,
+                    &mut default,
+                    &mut flags,
+                )
+                .ok()?;
+        }
+
+        Some(CameraControlRange {
+            auto: flags & CameraControl_Flags_Auto.0 != 0,
+            min,
+            max,
+            step,
+            default,
+        })
+    }
+
+    pub fn get(&self, control: CameraControl) -> Result<CameraControlValue, CameraCaptureError> {
+        let (mut value, mut flags) = (0, 0);
+        unsafe {
+            self.control
+                .Get(Self::property(control), &mut value, &mut flags)
+                .map_err(|_| CameraCaptureError::UnsupportedControl)?;
+        }
+
+        Ok(if flags & CameraControl_Flags_Auto.0 != 0 {
+            CameraControlValue::Auto
+        } else {
+            CameraControlValue::Manual(value)
+        })
+    }
+
+    pub fn set(
+        &self,
+        control: CameraControl,
+        value: CameraControlValue,
+    ) -> Result<(), CameraCaptureError> {
+        let range = self
+            .range(control)
+            .ok_or(CameraCaptureError::UnsupportedControl)?;
+
+        // The value is ignored while the camera adjusts the control by itself.
+        let (value, flags) = match value {
+            CameraControlValue::Auto if range.auto => (range.default, CameraControl_Flags_Auto),
+            CameraControlValue::Auto => return Err(CameraCaptureError::UnsupportedControl),
+            CameraControlValue::Manual(value) => (value, CameraControl_Flags_Manual),
+        };
+
+        unsafe {
+            self.control.Set(Self::property(control), value, flags.0)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for CameraControls {
+    fn drop(&mut self) {
+        if let Err(e) = unsafe { self.device.Shutdown() } {
+            log::warn!("camera controls device shutdown error={:?}", e);
+        }
+    }
+}
+
 impl CaptureHandler for CameraCapture {
     type Frame = VideoFrame;
     type Error = CameraCaptureError;
@@ -190,6 +425,8 @@ impl CaptureHandler for CameraCapture {
                 ) {
                     sources.push(Source {
                         is_default: sources.len() == 0,
+                        rotation: VideoRotation::Rotate0,
+                        backend: None,
                         kind: SourceType::Camera,
                         index: sources.len(),
                         name,
@@ -210,7 +447,7 @@ impl CaptureHandler for CameraCapture {
     ) -> Result<(), Self::Error> {
         let mut attributes = create_attributes()?;
         attributes.set(MF_READWRITE_ENABLE_HARDWARE_TRANSFORMS, IMFValue::U32(1))?;
-        attributes.set(MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_SYMBOLIC_LINK, IMFValue::String(opt.source.id))?;
+        attributes.set(MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_SYMBOLIC_LINK, IMFValue::String(opt.source.id.clone()))?;
         attributes.set(MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE, IMFValue::GUID(MF_DEVSOURCE_ATTRIBUTE_SOURCE_TYPE_VIDCAP_GUID))?;
         attributes.set(MF_SOURCE_READER_ENABLE_ADVANCED_VIDEO_PROCESSING, IMFValue::U32(1))?;
 
@@ -225,9 +462,49 @@ impl CaptureHandler for CameraCapture {
         // Creates a media source for a hardware capture device.
         let device = unsafe { MFCreateDeviceSource(&attributes)? };
 
+        // The device captures in the mode closest to the encoder instead of its
+        // default mode, the source reader converts the mode to the output type, and
+        // decodes it if it is MJPEG.
+        let (handler, types) = get_media_types(&device)?;
+        let native = select_format(
+            &types.iter().map(|(_, it)| *it).collect::<Vec<_>>(),
+            opt.size,
+            opt.fps,
+            &[CameraPixelFormat::NV12, CameraPixelFormat::YUYV, CameraPixelFormat::MJPEG],
+        )
+        .map(|index| &types[index]);
+
+        if let Some((media_type, format)) = native {
+            log::info!("windows camera capture format, id={}, format={:?}", opt.source.id, format);
+
+            unsafe { handler.SetCurrentMediaType(media_type)? };
+        }
+
         // Creates the source reader from a media source.
         let reader = unsafe { MFCreateSourceReaderFromMediaSource(&device, &attributes)? };
 
+        // The source reader deinterlaces the frames of an interlaced device unless the
+        // output keeps the fields, the sender deinterlaces them if it is configured to.
+        let interlace = unsafe {
+            match native {
+                Some((media_type, _)) => media_type.GetUINT32(&MF_MT_INTERLACE_MODE),
+                None => reader
+                    .GetNativeMediaType(MF_SOURCE_READER_FIRST_VIDEO_STREAM.0 as u32, 0)
+                    .and_then(|it| it.GetUINT32(&MF_MT_INTERLACE_MODE)),
+            }
+            .unwrap_or(MFVideoInterlace_Progressive.0 as u32)
+        };
+
+        let field_order = match MFVideoInterlaceMode(interlace as i32) {
+            MFVideoInterlace_FieldInterleavedUpperFirst | MFVideoInterlace_MixedInterlaceOrProgressive => FieldOrder::TopFieldFirst,
+            MFVideoInterlace_FieldInterleavedLowerFirst => FieldOrder::BottomFieldFirst,
+            _ => FieldOrder::Progressive,
+        };
+
+        if field_order != FieldOrder::Progressive {
+            media_type.set(MF_MT_INTERLACE_MODE, IMFValue::U32(interlace))?;
+        }
+
         // Sets the media type for a stream.
         //
         // This media type defines that format that the Source Reader produces as
@@ -240,43 +517,54 @@ impl CaptureHandler for CameraCapture {
             )?;
         }
 
+        // The native media type of the device describes the mounting orientation of the
+        // camera, which is combined with the rotation specified by the source.
+        let rotation = unsafe {
+            reader
+                .GetNativeMediaType(MF_SOURCE_READER_FIRST_VIDEO_STREAM.0 as u32, 0)
+                .and_then(|it| it.GetUINT32(&MF_MT_VIDEO_ROTATION))
+                .unwrap_or(0)
+        };
+
         let mut frame = VideoFrame::default();
         frame.height = opt.size.height;
         frame.width = opt.size.width;
         frame.format = VideoFormat::NV12;
         frame.sub_format = VideoSubFormat::SW;
+        frame.rotation = VideoRotation::from_degrees(opt.source.rotation.degrees() + rotation as i32);
+        frame.field_order = field_order;
 
         let mut ctx = Context {
+            pacer: FramePacer::new(opt.fps, opt.adaptive_pacing),
             status: self.0.clone(),
             arrived,
             reader,
             device,
             frame,
+            field_order,
         };
 
         // Create a thread to continuously process the video frames read from the 
         // device and pass them to the receiver.
         self.0.update(true);
-        thread::Builder::new()
-            .name("WindowsCameraCaptureThread".to_string())
-            .spawn(move || {
-                let thread_class_guard = MediaThreadClass::Capture.join().ok();
+        runtime::spawn(ThreadKind::Capture, "CameraCapture", move || {
+            let thread_class_guard = MediaThreadClass::Capture.join().ok();
 
-                loop {
-                    if let Err(e) = ctx.poll() {
-                        log::error!("WindowsCameraCaptureThread error={}", e);
+            loop {
+                if let Err(e) = ctx.poll() {
+                    log::error!("WindowsCameraCaptureThread error={}", e);
 
-                        break;
-                    }
+                    break;
                 }
+            }
 
-                log::info!("WindowsCameraCaptureThread stop");
-                ctx.status.update(false);
+            log::info!("WindowsCameraCaptureThread stop");
+            ctx.status.update(false);
 
-                if let Some(guard) = thread_class_guard {
-                    drop(guard)
-                }
-            })?;
+            if let Some(guard) = thread_class_guard {
+                drop(guard)
+            }
+        })?;
 
         Ok(())
     }
@@ -0,0 +1,326 @@
+use crate::{CaptureHandler, ScreenCapture, ScreenCaptureError, Source};
+
+use std::{
+    ffi::c_void,
+    mem::size_of,
+    sync::{atomic::AtomicBool, Arc},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use hylarana_common::{atomic::EasyAtomic, Size};
+use parking_lot::Mutex;
+use thiserror::Error;
+use windows::{
+    core::{GUID, HSTRING, PCWSTR},
+    Win32::{
+        Devices::DeviceAndDriverInstallation::{
+            SetupDiDestroyDeviceInfoList, SetupDiEnumDeviceInterfaces, SetupDiGetClassDevsW,
+            SetupDiGetDeviceInterfaceDetailW, DIGCF_DEVICEINTERFACE, DIGCF_PRESENT,
+            SP_DEVICE_INTERFACE_DATA, SP_DEVICE_INTERFACE_DETAIL_DATA_W,
+        },
+        Foundation::{CloseHandle, ERROR_IO_PENDING, GENERIC_READ, GENERIC_WRITE, HANDLE, HWND},
+        Graphics::Gdi::{
+            ChangeDisplaySettingsExW, CDS_TYPE, DEVMODEW, DISP_CHANGE_SUCCESSFUL, DM_PELSHEIGHT,
+            DM_PELSWIDTH,
+        },
+        Storage::FileSystem::{
+            CreateFileW, FILE_ATTRIBUTE_NORMAL, FILE_FLAG_NO_BUFFERING, FILE_FLAG_OVERLAPPED,
+            FILE_FLAG_WRITE_THROUGH, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
+        },
+        System::{
+            Threading::CreateEventW,
+            IO::{DeviceIoControl, GetOverlappedResultEx, OVERLAPPED},
+        },
+    },
+};
+
+// The device interface and the control codes of the Parsec virtual display
+// adapter, an indirect display driver that adds monitors on request.
+const ADAPTER_GUID: GUID = GUID::from_u128(0x00b41627_04c4_429e_a26e_0265cf50c8fa);
+
+const IOCTL_ADD: u32 = 0x0022e004;
+const IOCTL_REMOVE: u32 = 0x0022a008;
+const IOCTL_UPDATE: u32 = 0x0022a00c;
+
+// The driver removes the monitors that are not kept alive for a second.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_millis(100);
+
+// How long the control requests and the monitor to show up are waited for.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum VirtualDisplayError {
+    #[error(transparent)]
+    CreateThreadError(#[from] std::io::Error),
+    #[error(transparent)]
+    Win32Error(#[from] windows::core::Error),
+    #[error(transparent)]
+    ScreenCaptureError(#[from] ScreenCaptureError),
+    #[error("not found an indirect display driver, the driver may not be installed")]
+    NotFoundDriver,
+    #[error("the virtual display is not added to the desktop")]
+    NotFoundDisplay,
+}
+
+struct Handle(HANDLE);
+
+unsafe impl Sync for Handle {}
+unsafe impl Send for Handle {}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseHandle(self.0) };
+    }
+}
+
+struct Adapter(Handle);
+
+impl Adapter {
+    fn open() -> Result<Self, VirtualDisplayError> {
+        let path = unsafe {
+            let devices = SetupDiGetClassDevsW(
+                Some(&ADAPTER_GUID),
+                PCWSTR::null(),
+                HWND::default(),
+                DIGCF_PRESENT | DIGCF_DEVICEINTERFACE,
+            )?;
+
+            let path = Self::get_path(devices);
+            let _ = SetupDiDestroyDeviceInfoList(devices);
+            path
+        }
+        .ok_or(VirtualDisplayError::NotFoundDriver)?;
+
+        Ok(Self(Handle(unsafe {
+            CreateFileW(
+                &HSTRING::from(path),
+                (GENERIC_READ | GENERIC_WRITE).0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                None,
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL
+                    | FILE_FLAG_NO_BUFFERING
+                    | FILE_FLAG_OVERLAPPED
+                    | FILE_FLAG_WRITE_THROUGH,
+                HANDLE::default(),
+            )?
+        })))
+    }
+
+    unsafe fn get_path(
+        devices: windows::Win32::Devices::DeviceAndDriverInstallation::HDEVINFO,
+    ) -> Option<String> {
+        let mut interface = SP_DEVICE_INTERFACE_DATA {
+            cbSize: size_of::<SP_DEVICE_INTERFACE_DATA>() as u32,
+            ..Default::default()
+        };
+
+        SetupDiEnumDeviceInterfaces(devices, None, &ADAPTER_GUID, 0, &mut interface).ok()?;
+
+        // The first call only gets the size of the detail, which is a header followed
+        // by the path of the device.
+        let mut size = 0;
+        let _ =
+            SetupDiGetDeviceInterfaceDetailW(devices, &interface, None, 0, Some(&mut size), None);
+
+        let mut buffer = vec![0u32; (size as usize).div_ceil(4).max(2)];
+        let detail = buffer.as_mut_ptr() as *mut SP_DEVICE_INTERFACE_DETAIL_DATA_W;
+        (*detail).cbSize = size_of::<SP_DEVICE_INTERFACE_DETAIL_DATA_W>() as u32;
+
+        SetupDiGetDeviceInterfaceDetailW(devices, &interface, Some(detail), size, None, None)
+            .ok()?;
+
+        PCWSTR((*detail).DevicePath.as_ptr()).to_string().ok()
+    }
+
+    // The requests take a buffer of 32 bytes and return a 32 bits value.
+    fn control(&self, code: u32, data: &[u8]) -> Result<u32, VirtualDisplayError> {
+        let mut input = [0u8; 32];
+        input[..data.len()].copy_from_slice(data);
+
+        let mut output = 0u32;
+        unsafe {
+            let event = Handle(CreateEventW(None, true, false, PCWSTR::null())?);
+            let mut overlapped = OVERLAPPED {
+                hEvent: event.0,
+                ..Default::default()
+            };
+
+            if let Err(e) = DeviceIoControl(
+                self.0 .0,
+                code,
+                Some(input.as_ptr() as *const c_void),
+                input.len() as u32,
+                Some(&mut output as *mut u32 as *mut c_void),
+                size_of::<u32>() as u32,
+                None,
+                Some(&mut overlapped),
+            ) {
+                if e.code() != ERROR_IO_PENDING.to_hresult() {
+                    return Err(e.into());
+                }
+            }
+
+            let mut transferred = 0;
+            GetOverlappedResultEx(
+                self.0 .0,
+                &overlapped,
+                &mut transferred,
+                TIMEOUT.as_millis() as u32,
+                false,
+            )?;
+        }
+
+        Ok(output)
+    }
+}
+
+// An added monitor that is kept alive until it is dropped, which removes it.
+struct Monitor {
+    adapter: Arc<Mutex<Adapter>>,
+    status: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+    index: u32,
+}
+
+impl Monitor {
+    fn add(adapter: Adapter) -> Result<Self, VirtualDisplayError> {
+        let index = adapter.control(IOCTL_ADD, &[])?;
+        adapter.control(IOCTL_UPDATE, &[])?;
+
+        let mut monitor = Self {
+            adapter: Arc::new(Mutex::new(adapter)),
+            status: Arc::new(AtomicBool::new(true)),
+            thread: None,
+            index,
+        };
+
+        let adapter = Arc::downgrade(&monitor.adapter);
+        let status = monitor.status.clone();
+        monitor.thread = Some(
+            thread::Builder::new()
+                .name("VirtualDisplayKeepaliveThread".to_string())
+                .spawn(move || {
+                    while status.get() {
+                        thread::sleep(KEEPALIVE_INTERVAL);
+
+                        if let Some(adapter) = adapter.upgrade() {
+                            if let Err(e) = adapter.lock().control(IOCTL_UPDATE, &[]) {
+                                log::warn!("failed to keep the virtual display alive, err={:?}", e);
+                            }
+                        }
+                    }
+                })?,
+        );
+
+        Ok(monitor)
+    }
+}
+
+impl Drop for Monitor {
+    fn drop(&mut self) {
+        self.status.update(false);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+
+        // The index is passed in big endian.
+        let adapter = self.adapter.lock();
+        if let Err(e) = adapter
+            .control(IOCTL_REMOVE, &(self.index as u16).to_be_bytes())
+            .and_then(|_| adapter.control(IOCTL_UPDATE, &[]))
+        {
+            log::warn!("failed to remove the virtual display, err={:?}", e);
+        }
+
+        log::info!("virtual display removed, index={}", self.index);
+    }
+}
+
+/// A monitor that is added to the desktop by an indirect display driver, so
+/// that a receiver can be an extra monitor of the sender, that is, the extend
+/// mode, instead of mirroring an existing monitor.
+///
+/// The monitor is a screen source like the others, the sender captures it
+/// and the receiver shows the frames. The driver is not shipped with the sdk,
+/// the Parsec virtual display driver must be installed on the system.
+///
+/// The monitor is removed when this is dropped.
+pub struct VirtualDisplay {
+    monitor: Monitor,
+    source: Source,
+}
+
+impl VirtualDisplay {
+    /// Add a monitor to the desktop and set its resolution to the size, the
+    /// monitor keeps the resolution of the driver if the size is not one of
+    /// the modes of the driver.
+    pub fn create(size: Size) -> Result<Self, VirtualDisplayError> {
+        let adapter = Adapter::open()?;
+        let sources = ScreenCapture::get_sources()?;
+        let monitor = Monitor::add(adapter)?;
+
+        // The monitor shows up on the desktop some time after it is added, it is the
+        // screen source that was not there before.
+        let time = Instant::now();
+        let source = loop {
+            if let Some(source) = ScreenCapture::get_sources()?
+                .into_iter()
+                .find(|it| !sources.iter().any(|source| source.id == it.id))
+            {
+                break source;
+            }
+
+            if time.elapsed() > TIMEOUT {
+                return Err(VirtualDisplayError::NotFoundDisplay);
+            }
+
+            thread::sleep(KEEPALIVE_INTERVAL);
+        };
+
+        let display = Self { monitor, source };
+        display.set_size(size);
+
+        log::info!(
+            "virtual display added, index={}, source={:?}",
+            display.monitor.index,
+            display.source
+        );
+
+        Ok(display)
+    }
+
+    /// The screen source of the monitor, which is passed to the sender.
+    pub fn source(&self) -> &Source {
+        &self.source
+    }
+
+    fn set_size(&self, size: Size) {
+        let mode = DEVMODEW {
+            dmSize: size_of::<DEVMODEW>() as u16,
+            dmFields: DM_PELSWIDTH | DM_PELSHEIGHT,
+            dmPelsWidth: size.width,
+            dmPelsHeight: size.height,
+            ..Default::default()
+        };
+
+        let code = unsafe {
+            ChangeDisplaySettingsExW(
+                &HSTRING::from(self.source.id.as_str()),
+                Some(&mode),
+                HWND::default(),
+                CDS_TYPE(0),
+                None,
+            )
+        };
+
+        if code != DISP_CHANGE_SUCCESSFUL {
+            log::warn!(
+                "failed to set the resolution of the virtual display, size={:?}, code={:?}",
+                size,
+                code
+            );
+        }
+    }
+}
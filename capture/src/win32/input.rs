@@ -0,0 +1,105 @@
+use std::{io::Error, mem::size_of};
+
+use hylarana_common::input::{InputEvent, MouseButton};
+use windows::Win32::UI::Input::KeyboardAndMouse::{
+    SendInput, INPUT, INPUT_0, INPUT_KEYBOARD, INPUT_MOUSE, KEYBDINPUT, KEYBD_EVENT_FLAGS,
+    KEYEVENTF_EXTENDEDKEY, KEYEVENTF_KEYUP, KEYEVENTF_SCANCODE, MOUSEEVENTF_ABSOLUTE,
+    MOUSEEVENTF_HWHEEL, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP, MOUSEEVENTF_MIDDLEDOWN,
+    MOUSEEVENTF_MIDDLEUP, MOUSEEVENTF_MOVE, MOUSEEVENTF_RIGHTDOWN, MOUSEEVENTF_RIGHTUP,
+    MOUSEEVENTF_WHEEL, MOUSEINPUT, MOUSE_EVENT_FLAGS, VIRTUAL_KEY,
+};
+
+// The distance of one notch of the mouse wheel.
+const WHEEL_DELTA: i32 = 120;
+
+fn mouse(dx: i32, dy: i32, data: i32, flags: MOUSE_EVENT_FLAGS) -> INPUT {
+    INPUT {
+        r#type: INPUT_MOUSE,
+        Anonymous: INPUT_0 {
+            mi: MOUSEINPUT {
+                dx,
+                dy,
+                mouseData: data as u32,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+fn keyboard(scan: u16, flags: KEYBD_EVENT_FLAGS) -> INPUT {
+    INPUT {
+        r#type: INPUT_KEYBOARD,
+        Anonymous: INPUT_0 {
+            ki: KEYBDINPUT {
+                wVk: VIRTUAL_KEY(0),
+                wScan: scan,
+                dwFlags: flags,
+                time: 0,
+                dwExtraInfo: 0,
+            },
+        },
+    }
+}
+
+pub fn inject(event: &InputEvent) -> Result<(), Error> {
+    let input = match *event {
+        // The absolute coordinates of the primary display are normalized to 0 -
+        // 65535.
+        InputEvent::MouseMove { x, y } => mouse(
+            (x.clamp(0.0, 1.0) * 65535.0) as i32,
+            (y.clamp(0.0, 1.0) * 65535.0) as i32,
+            0,
+            MOUSEEVENTF_MOVE | MOUSEEVENTF_ABSOLUTE,
+        ),
+        InputEvent::MouseButton { button, pressed } => mouse(
+            0,
+            0,
+            0,
+            match (button, pressed) {
+                (MouseButton::Left, true) => MOUSEEVENTF_LEFTDOWN,
+                (MouseButton::Left, false) => MOUSEEVENTF_LEFTUP,
+                (MouseButton::Right, true) => MOUSEEVENTF_RIGHTDOWN,
+                (MouseButton::Right, false) => MOUSEEVENTF_RIGHTUP,
+                (MouseButton::Middle, true) => MOUSEEVENTF_MIDDLEDOWN,
+                (MouseButton::Middle, false) => MOUSEEVENTF_MIDDLEUP,
+            },
+        ),
+        InputEvent::MouseWheel { x, y } => {
+            if x != 0 {
+                send(&[mouse(0, 0, x * WHEEL_DELTA, MOUSEEVENTF_HWHEEL)])?;
+            }
+
+            if y == 0 {
+                return Ok(());
+            }
+
+            mouse(0, 0, y * WHEEL_DELTA, MOUSEEVENTF_WHEEL)
+        }
+        InputEvent::Key { code, pressed } => {
+            let mut flags = KEYEVENTF_SCANCODE;
+            if code & 0xFF00 == 0xE000 {
+                flags |= KEYEVENTF_EXTENDEDKEY;
+            }
+
+            if !pressed {
+                flags |= KEYEVENTF_KEYUP;
+            }
+
+            keyboard(code & 0xFF, flags)
+        }
+    };
+
+    send(&[input])
+}
+
+fn send(inputs: &[INPUT]) -> Result<(), Error> {
+    // SendInput returns the number of events inserted, it is zero if the input is
+    // blocked by another thread or by UIPI.
+    if unsafe { SendInput(inputs, size_of::<INPUT>() as i32) } as usize != inputs.len() {
+        return Err(Error::last_os_error());
+    }
+
+    Ok(())
+}
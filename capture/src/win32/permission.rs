@@ -0,0 +1,65 @@
+use crate::{PermissionState, SourceType};
+
+use windows::{
+    core::{w, HSTRING, PCWSTR},
+    Win32::{
+        Foundation::ERROR_SUCCESS,
+        System::Registry::{RegGetValueW, HKEY_CURRENT_USER, RRF_RT_REG_SZ},
+    },
+};
+
+// The privacy settings of windows are stored in the consent store of the
+// capability access manager, the value is "Allow" or "Deny".
+fn get_consent(key: &str) -> Option<bool> {
+    let key = HSTRING::from(format!(
+        "Software\\Microsoft\\Windows\\CurrentVersion\\CapabilityAccessManager\\ConsentStore\\{}",
+        key
+    ));
+
+    let mut value = [0u16; 16];
+    let mut size = std::mem::size_of_val(&value) as u32;
+    if unsafe {
+        RegGetValueW(
+            HKEY_CURRENT_USER,
+            PCWSTR(key.as_ptr()),
+            w!("Value"),
+            RRF_RT_REG_SZ,
+            None,
+            Some(value.as_mut_ptr() as *mut _),
+            Some(&mut size),
+        )
+    } != ERROR_SUCCESS
+    {
+        return None;
+    }
+
+    let len = value.iter().position(|it| *it == 0).unwrap_or(value.len());
+    Some(String::from_utf16_lossy(&value[..len]) == "Allow")
+}
+
+pub fn check(kind: SourceType) -> PermissionState {
+    let capability = match kind {
+        SourceType::Camera => "webcam",
+        SourceType::Audio => "microphone",
+        // Capturing the displays with windows graphics capture does not require
+        // permission.
+        SourceType::Screen => return PermissionState::Granted,
+    };
+
+    // Desktop applications are controlled by the global switch of the capability
+    // and the switch for desktop applications, both must be allowed.
+    match (
+        get_consent(capability),
+        get_consent(&format!("{}\\NonPackaged", capability)),
+    ) {
+        (Some(false), _) | (_, Some(false)) => PermissionState::Denied,
+        (Some(true), Some(true)) => PermissionState::Granted,
+        _ => PermissionState::NotDetermined,
+    }
+}
+
+// Windows does not prompt desktop applications, the user can only change the
+// privacy settings.
+pub fn request(kind: SourceType) -> PermissionState {
+    check(kind)
+}
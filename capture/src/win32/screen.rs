@@ -1,15 +1,19 @@
-use crate::{CaptureHandler, FrameArrived, Source, SourceType, VideoCaptureSourceDescription};
+use crate::{
+    CaptureHandler, FrameArrived, FramePacer, Source, SourceEvent, SourceType,
+    VideoCaptureSourceDescription,
+};
 
 use std::{
-    sync::{atomic::AtomicBool, Arc},
+    sync::{atomic::AtomicBool, Arc, Weak},
     thread,
-    time::Duration,
+    time::Instant,
 };
 
 use hylarana_common::{
     atomic::EasyAtomic,
-    frame::{VideoFormat, VideoFrame, VideoSubFormat},
-    win32::{EasyTexture, MediaThreadClass},
+    frame::{VideoFormat, VideoFrame, VideoRotation, VideoSubFormat},
+    runtime::{self, ThreadKind},
+    win32::{EasyTexture, MediaThreadClass, TexturePool},
     Size,
 };
 
@@ -18,12 +22,19 @@ use parking_lot::Mutex;
 use thiserror::Error;
 use windows::{
     core::Interface,
-    Win32::Graphics::{
-        Direct3D11::{
-            ID3D11DeviceContext, ID3D11Texture2D, D3D11_RESOURCE_MISC_SHARED, D3D11_TEXTURE2D_DESC,
-            D3D11_USAGE_DEFAULT,
+    Win32::{
+        Foundation::RECT,
+        Graphics::{
+            Direct3D11::{
+                ID3D11DeviceContext, ID3D11Texture2D, D3D11_RESOURCE_MISC_SHARED,
+                D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
+            },
+            Dxgi::Common::{
+                DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709,
+                DXGI_COLOR_SPACE_YCBCR_STUDIO_G2084_LEFT_P2020, DXGI_FORMAT_NV12, DXGI_FORMAT_P010,
+                DXGI_FORMAT_R16G16B16A16_FLOAT, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_SAMPLE_DESC,
+            },
         },
-        Dxgi::Common::{DXGI_FORMAT_NV12, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_SAMPLE_DESC},
     },
 };
 
@@ -53,15 +64,24 @@ pub enum ScreenCaptureError {
     StartCaptureError(String),
 }
 
+// The encoders read the textures of the frames asynchronously, QSV keeps up to
+// four frames in flight, so the captured frames are processed into a pool of
+// textures rather than into one texture.
+const TEXTURE_POOL_SIZE: usize = 6;
+
 struct Surface(ID3D11Texture2D);
 
 unsafe impl Sync for Surface {}
 unsafe impl Send for Surface {}
 
+type SharedFrameArrived = Arc<Mutex<Box<dyn FrameArrived<Frame = VideoFrame>>>>;
+
 struct WindowsCapture {
     texture: ID3D11Texture2D,
     device_context: ID3D11DeviceContext,
     status: Arc<AtomicBool>,
+    session: Weak<Mutex<Option<Session>>>,
+    size: Size,
 }
 
 impl GraphicsCaptureApiHandler for WindowsCapture {
@@ -76,6 +96,19 @@ impl GraphicsCaptureApiHandler for WindowsCapture {
         }: Context<Self::Flags>,
     ) -> Result<Self, Self::Error> {
         let status: Arc<AtomicBool> = Arc::new(AtomicBool::new(true));
+        let session = flags.session.clone();
+        let size = Size {
+            width: flags.source.width()?,
+            height: flags.source.height()?,
+        };
+
+        // When capturing HDR, windows-capture outputs 16-bit float frames in scRGB, and
+        // the video processor converts it to 10-bit P010.
+        let (input_format, output_format) = if flags.options.hdr {
+            (DXGI_FORMAT_R16G16B16A16_FLOAT, DXGI_FORMAT_P010)
+        } else {
+            (DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_NV12)
+        };
 
         // Because windows-capture and this library implementation use different devices
         // and contexts, the problem needs to be solved with an intermediate texture,
@@ -86,11 +119,11 @@ impl GraphicsCaptureApiHandler for WindowsCapture {
         // from this intermediate texture.
         let (texture, surface) = {
             let desc = D3D11_TEXTURE2D_DESC {
-                Width: flags.source.width()?,
-                Height: flags.source.height()?,
+                Width: size.width,
+                Height: size.height,
                 MipLevels: 1,
                 ArraySize: 1,
-                Format: DXGI_FORMAT_R8G8B8A8_UNORM,
+                Format: input_format,
                 SampleDesc: DXGI_SAMPLE_DESC {
                     Count: 1,
                     Quality: 0,
@@ -117,10 +150,21 @@ impl GraphicsCaptureApiHandler for WindowsCapture {
             (texture, Surface(surface))
         };
 
+        // NV12 and P010 textures need an even size, the frames are padded to it and
+        // the picture is drawn to the top left corner.
+        let coded_size = flags.options.size.align(2);
+
         let mut frame = VideoFrame::default();
-        frame.width = flags.options.size.width;
-        frame.height = flags.options.size.height;
-        frame.format = VideoFormat::NV12;
+        frame.width = coded_size.width;
+        frame.height = coded_size.height;
+        frame.display_width = flags.options.size.width;
+        frame.display_height = flags.options.size.height;
+        frame.format = if flags.options.hdr {
+            VideoFormat::P010
+        } else {
+            VideoFormat::NV12
+        };
+
         frame.sub_format = if flags.options.hardware {
             VideoSubFormat::D3D11
         } else {
@@ -129,83 +173,116 @@ impl GraphicsCaptureApiHandler for WindowsCapture {
 
         // Convert texture formats and scale sizes.
         let mut transform = VideoResampler::new(VideoResamplerOptions {
-            direct3d: flags.options.direct3d,
-            input: Resource::Default(
-                DXGI_FORMAT_R8G8B8A8_UNORM,
-                Size {
-                    width: flags.source.width()?,
-                    height: flags.source.height()?,
-                },
-            ),
-            output: Resource::Default(
-                DXGI_FORMAT_NV12,
-                Size {
-                    width: flags.options.size.width,
-                    height: flags.options.size.height,
-                },
-            ),
+            direct3d: flags.options.direct3d.clone(),
+            input: Resource::Default(input_format, size),
+            output: Resource::Default(output_format, coded_size),
         })?;
 
+        if coded_size != flags.options.size {
+            transform.set_output_rect(RECT {
+                left: 0,
+                top: 0,
+                right: flags.options.size.width as i32,
+                bottom: flags.options.size.height as i32,
+            });
+        }
+
+        if flags.options.hdr {
+            transform.set_color_space(
+                DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709,
+                DXGI_COLOR_SPACE_YCBCR_STUDIO_G2084_LEFT_P2020,
+            )?;
+        }
+
+        // The textures of the pool have the same description as the output of the
+        // video processor.
+        let pool = TexturePool::new(
+            flags.options.direct3d.clone(),
+            transform.get_output().desc(),
+            TEXTURE_POOL_SIZE,
+        );
+
         let status_ = Arc::downgrade(&status);
-        thread::Builder::new()
-            .name("WindowsScreenCaptureThread".to_string())
-            .spawn(move || {
-                let thread_class_guard = MediaThreadClass::Capture.join().ok();
-
-                let mut func = || {
-                    loop {
-                        let view = transform.create_input_view(&surface.0, 0)?;
-                        transform.process(Some(view))?;
-
-                        if frame.sub_format == VideoSubFormat::D3D11 {
-                            frame.data[0] = transform.get_output().as_raw();
-                            frame.data[1] = 0 as *const _;
-
-                            if !flags.arrived.sink(&frame) {
-                                break;
-                            }
-                        } else {
-                            let texture = transform.get_output_buffer()?;
-                            frame.data[0] = texture.buffer() as *const _;
-                            frame.data[1] = unsafe {
-                                texture
-                                    .buffer()
-                                    .add(frame.width as usize * frame.height as usize)
-                            } as *const _;
-
-                            frame.linesize[0] = texture.stride();
-                            frame.linesize[1] = texture.stride();
-
-                            if !flags.arrived.sink(&frame) {
-                                break;
-                            }
-                        }
+        runtime::spawn(ThreadKind::Capture, "ScreenCapture", move || {
+            let thread_class_guard = MediaThreadClass::Capture.join().ok();
+
+            // WGC delivers the frames at the refresh rate of the display, the frames are
+            // only copied to the intermediate texture, and the pacer decides when the
+            // texture is processed and sent.
+            let mut pacer = FramePacer::new(flags.options.fps, flags.options.adaptive_pacing);
 
-                        thread::sleep(Duration::from_millis(1000 / flags.options.fps as u64));
+            let mut func = || {
+                // The intermediate texture does not change, neither does its view.
+                let view = transform.create_input_view(&surface.0, 0)?;
+
+                loop {
+                    frame.timestamp = pacer.wait();
+
+                    // The capture session has been stopped or re-created.
+                    if !status_.upgrade().map(|it| it.get()).unwrap_or(false) {
+                        break;
                     }
 
-                    Ok::<_, ScreenCaptureError>(())
-                };
+                    let time = Instant::now();
 
-                if let Err(e) = func() {
-                    log::error!("WindowsScreenCaptureThread stop, error={:?}", e);
-                } else {
-                    log::info!("WindowsScreenCaptureThread stop");
-                }
+                    if frame.sub_format == VideoSubFormat::D3D11 {
+                        // The texture goes back to the pool after the frame has been sent, the
+                        // pool does not hand it out again until the GPU has finished with it.
+                        let texture = transform.process_into(Some(view.clone()), &pool)?;
+                        frame.data[0] = texture.texture().as_raw();
+                        frame.data[1] = 0 as *const _;
 
-                if let Some(status) = status_.upgrade() {
-                    status.update(false);
-                }
+                        if !flags.arrived.lock().sink(&frame) {
+                            break;
+                        }
+                    } else {
+                        transform.process(Some(view.clone()))?;
+
+                        // The UV plane follows the Y plane with the same stride, which also
+                        // covers the 16 bits samples of P010.
+                        let texture = transform.get_output_buffer()?;
+                        frame.data[0] = texture.buffer() as *const _;
+                        frame.data[1] = unsafe {
+                            texture
+                                .buffer()
+                                .add(texture.stride() * frame.height as usize)
+                        } as *const _;
+
+                        frame.linesize[0] = texture.stride();
+                        frame.linesize[1] = texture.stride();
+
+                        if !flags.arrived.lock().sink(&frame) {
+                            break;
+                        }
+                    }
 
-                if let Some(guard) = thread_class_guard {
-                    drop(guard)
+                    pacer.feedback(time.elapsed());
                 }
-            })?;
+
+                Ok::<_, ScreenCaptureError>(())
+            };
+
+            if let Err(e) = func() {
+                log::error!("WindowsScreenCaptureThread stop, error={:?}", e);
+            } else {
+                log::info!("WindowsScreenCaptureThread stop");
+            }
+
+            if let Some(status) = status_.upgrade() {
+                status.update(false);
+            }
+
+            if let Some(guard) = thread_class_guard {
+                drop(guard)
+            }
+        })?;
 
         Ok(Self {
             device_context,
+            session,
             status,
             texture,
+            size,
         })
     }
 
@@ -214,35 +291,155 @@ impl GraphicsCaptureApiHandler for WindowsCapture {
         frame: &mut Frame,
         control: InternalCaptureControl,
     ) -> Result<(), Self::Error> {
-        if self.status.get() {
+        if !self.status.get() {
+            log::info!("windows screen capture control stop");
+
+            control.stop();
+        } else if self.size.width != frame.width() || self.size.height != frame.height() {
+            // The resolution of the display has changed, the intermediate texture and the
+            // video processor are created with the size of the display, so the session is
+            // re-created.
+            log::info!(
+                "windows screen capture source resized, size={}x{}",
+                frame.width(),
+                frame.height()
+            );
+
+            self.status.update(false);
+            control.stop();
+
+            restart(self.session.clone());
+        } else {
             // Updates the texture in the frame to the middle texture.
             unsafe {
                 self.device_context
                     .CopyResource(&self.texture, frame.as_raw_texture());
             }
-        } else {
-            log::info!("windows screen capture control stop");
-
-            control.stop();
         }
 
         Ok(())
     }
 
+    // The capture item is closed, such as the display being unplugged or
+    // re-enumerated, try to capture the display again.
     fn on_closed(&mut self) -> Result<(), Self::Error> {
-        self.status.update(false);
+        log::info!("windows screen capture source closed");
+
+        // The session is already being stopped or re-created.
+        if self.status.get() {
+            self.status.update(false);
+            restart(self.session.clone());
+        }
+
         Ok(())
     }
 }
 
 struct CaptureContext {
-    arrived: Box<dyn FrameArrived<Frame = VideoFrame>>,
+    arrived: SharedFrameArrived,
     options: VideoCaptureSourceDescription,
+    session: Weak<Mutex<Option<Session>>>,
     source: Monitor,
 }
 
+struct Session {
+    arrived: SharedFrameArrived,
+    options: VideoCaptureSourceDescription,
+    control: Option<CaptureControl<WindowsCapture, ScreenCaptureError>>,
+    // The size of the display.
+    size: Size,
+}
+
+fn start_session(
+    arrived: &SharedFrameArrived,
+    options: &VideoCaptureSourceDescription,
+    session: Weak<Mutex<Option<Session>>>,
+) -> Result<(CaptureControl<WindowsCapture, ScreenCaptureError>, Size), ScreenCaptureError> {
+    let source = Monitor::enumerate()?
+        .into_iter()
+        .find(|it| it.name().ok() == Some(options.source.name.clone()))
+        .ok_or_else(|| ScreenCaptureError::NotFoundScreenSource)?;
+
+    let size = Size {
+        width: source.width()?,
+        height: source.height()?,
+    };
+
+    // Start capturing the screen. This runs in a free thread. If it runs in the
+    // current thread, you will encounter problems with Winrt runtime
+    // initialization.
+    let control = WindowsCapture::start_free_threaded(Settings::new(
+        source,
+        CursorCaptureSettings::WithoutCursor,
+        DrawBorderSettings::Default,
+        if options.hdr {
+            ColorFormat::Rgba16F
+        } else {
+            ColorFormat::Rgba8
+        },
+        CaptureContext {
+            arrived: arrived.clone(),
+            options: options.clone(),
+            session,
+            source,
+        },
+    ))
+    .map_err(|e| ScreenCaptureError::StartCaptureError(e.to_string()))?;
+
+    Ok((control, size))
+}
+
+// Re-create the capture session of the display, this is called from the
+// callbacks of the capture, which cannot wait for the capture to stop, so it is
+// done in a new thread.
+fn restart(session: Weak<Mutex<Option<Session>>>) {
+    let func = move || {
+        let Some(session_) = session.upgrade() else {
+            return;
+        };
+
+        let mut session_ = session_.lock();
+
+        // The capture has been stopped by the caller.
+        let Some(current) = session_.as_mut() else {
+            return;
+        };
+
+        if let Some(control) = current.control.take() {
+            if let Err(e) = control.stop() {
+                log::warn!("windows screen capture stop error={:?}", e);
+            }
+        }
+
+        match start_session(&current.arrived, &current.options, session.clone()) {
+            Ok((control, size)) => {
+                current.control = Some(control);
+
+                if current.size != size {
+                    current.size = size;
+                    current.arrived.lock().event(SourceEvent::Resized(size));
+                }
+            }
+            Err(e) => {
+                log::warn!("windows screen capture restart error={:?}", e);
+
+                // The display cannot be found again, it has been removed.
+                current.arrived.lock().event(SourceEvent::Removed);
+                session_.take();
+            }
+        }
+    };
+
+    if let Err(e) = thread::Builder::new()
+        .name("WindowsScreenCaptureRestartThread".to_string())
+        .spawn(func)
+    {
+        log::error!("failed to create restart thread, error={:?}", e);
+    }
+}
+
 #[derive(Default)]
-pub struct ScreenCapture(Mutex<Option<CaptureControl<WindowsCapture, ScreenCaptureError>>>);
+pub struct ScreenCapture(Arc<Mutex<Option<Session>>>);
 
 impl CaptureHandler for ScreenCapture {
     type Frame = VideoFrame;
@@ -260,6 +457,8 @@ impl CaptureHandler for ScreenCapture {
                 id: item.device_name()?,
                 kind: SourceType::Screen,
                 is_default: item.name()? == primary_name,
+                rotation: VideoRotation::Rotate0,
+                backend: None,
             });
         }
 
@@ -271,38 +470,29 @@ impl CaptureHandler for ScreenCapture {
         options: Self::CaptureOptions,
         arrived: S,
     ) -> Result<(), Self::Error> {
-        let source = Monitor::enumerate()?
-            .into_iter()
-            .find(|it| it.name().ok() == Some(options.source.name.clone()))
-            .ok_or_else(|| ScreenCaptureError::NotFoundScreenSource)?;
-
-        // Start capturing the screen. This runs in a free thread. If it runs in the
-        // current thread, you will encounter problems with Winrt runtime
-        // initialization.
-        if let Some(control) = self.0.lock().replace(
-            WindowsCapture::start_free_threaded(Settings::new(
-                source,
-                CursorCaptureSettings::WithoutCursor,
-                DrawBorderSettings::Default,
-                ColorFormat::Rgba8,
-                CaptureContext {
-                    arrived: Box::new(arrived),
-                    options,
-                    source,
-                },
-            ))
-            .map_err(|e| ScreenCaptureError::StartCaptureError(e.to_string()))?,
-        ) {
-            control
-                .stop()
-                .map_err(|e| ScreenCaptureError::CaptureControlError(e.to_string()))?;
+        let arrived: SharedFrameArrived = Arc::new(Mutex::new(Box::new(arrived)));
+        let (control, size) = start_session(&arrived, &options, Arc::downgrade(&self.0))?;
+
+        if let Some(session) = self.0.lock().replace(Session {
+            control: Some(control),
+            arrived,
+            options,
+            size,
+        }) {
+            if let Some(control) = session.control {
+                control
+                    .stop()
+                    .map_err(|e| ScreenCaptureError::CaptureControlError(e.to_string()))?;
+            }
         }
 
         Ok(())
     }
 
     fn stop(&self) -> Result<(), Self::Error> {
-        if let Some(control) = self.0.lock().take() {
+        // Take the session first, so that it is not re-created while stopping.
+        let session = self.0.lock().take();
+        if let Some(control) = session.and_then(|it| it.control) {
             control
                 .stop()
                 .map_err(|e| ScreenCaptureError::CaptureControlError(e.to_string()))?;
@@ -164,6 +164,10 @@ impl AudioDecoder {
         self.frame.frames = frame.nb_samples as u32;
         self.frame.data = frame.data[0] as *const _;
 
+        // The timestamp of the packet is the capture time on the sender, it is zero if
+        // the decoder cannot determine it.
+        self.frame.timestamp = frame.best_effort_timestamp.max(0) as u64;
+
         Some(&self.frame)
     }
 }
@@ -317,8 +321,23 @@ impl AudioEncoder {
             );
         }
 
-        av_frame.pts = self.pts;
-        self.pts += context_ref.sample_rate as i64 / 10;
+        // The capture timestamp is converted to the time base of the encoder, which
+        // is the sample rate. The pts never goes backwards, and the frames without a
+        // timestamp simply follow the previous frame.
+        av_frame.pts = if frame.timestamp > 0 {
+            unsafe {
+                av_rescale_q(
+                    frame.timestamp as i64,
+                    av_make_q(1, 1_000_000),
+                    context_ref.time_base,
+                )
+            }
+            .max(self.pts)
+        } else {
+            self.pts
+        };
+
+        self.pts = av_frame.pts + frame.frames as i64;
 
         true
     }
@@ -340,11 +359,19 @@ impl AudioEncoder {
             return None;
         }
 
+        // The timestamps in the transport are on the media clock, in microseconds.
         let packet_ref = unsafe { &*self.packet };
         Some((
             unsafe { std::slice::from_raw_parts(packet_ref.data, packet_ref.size as usize) },
             packet_ref.flags,
-            packet_ref.pts as u64,
+            unsafe {
+                av_rescale_q(
+                    packet_ref.pts,
+                    { &*self.context }.time_base,
+                    av_make_q(1, 1_000_000),
+                )
+            }
+            .max(0) as u64,
         ))
     }
 }
@@ -371,6 +398,191 @@ impl Drop for AudioEncoder {
     }
 }
 
+/// Encodes the mono audio into AAC-LC, for the containers and the services
+/// that do not take Opus, such as RTMP. The encoder takes frames of any
+/// length, the samples are buffered up to the frame size of AAC.
+pub struct AacEncoder {
+    context: *mut AVCodecContext,
+    packet: *mut AVPacket,
+    frame: *mut AVFrame,
+    samples: Vec<i16>,
+    // The pts of the first buffered sample, in the time base of the encoder.
+    pts: i64,
+}
+
+unsafe impl Sync for AacEncoder {}
+unsafe impl Send for AacEncoder {}
+
+impl AacEncoder {
+    pub fn new(options: AudioEncoderSettings) -> Result<Self, AudioEncoderError> {
+        let codec = unsafe { avcodec_find_encoder_by_name(PSTR::from("aac").as_ptr()) };
+        if codec.is_null() {
+            return Err(AudioEncoderError::NotFoundAVCodec);
+        }
+
+        let mut this = Self {
+            context: null_mut(),
+            packet: null_mut(),
+            frame: null_mut(),
+            samples: Vec::new(),
+            pts: 0,
+        };
+
+        this.context = unsafe { avcodec_alloc_context3(codec) };
+        if this.context.is_null() {
+            return Err(AudioEncoderError::AllocAVContextError);
+        }
+
+        let context_mut = unsafe { &mut *this.context };
+        let ch_layout = AVChannelLayout {
+            order: AVChannelOrder::AV_CHANNEL_ORDER_NATIVE,
+            nb_channels: 1,
+            u: AVChannelLayout__bindgen_ty_1 {
+                mask: AV_CH_LAYOUT_MONO,
+            },
+            opaque: null_mut(),
+        };
+
+        // The AAC encoder of ffmpeg only takes planar floats.
+        context_mut.sample_fmt = AVSampleFormat::AV_SAMPLE_FMT_FLTP;
+        context_mut.ch_layout = ch_layout;
+        context_mut.bit_rate = options.bit_rate as i64;
+        context_mut.sample_rate = options.sample_rate as i32;
+        context_mut.time_base = unsafe { av_make_q(1, options.sample_rate as i32) };
+
+        // The AudioSpecificConfig is put in the extradata instead of the packets,
+        // the containers take it from there.
+        context_mut.flags |= AV_CODEC_FLAG_GLOBAL_HEADER as i32;
+
+        if unsafe { avcodec_open2(this.context, codec, null_mut()) } != 0 {
+            return Err(AudioEncoderError::OpenAVCodecError);
+        }
+
+        this.packet = unsafe { av_packet_alloc() };
+        if this.packet.is_null() {
+            return Err(AudioEncoderError::AllocAVPacketError);
+        }
+
+        this.frame = unsafe { av_frame_alloc() };
+        if this.frame.is_null() {
+            return Err(AudioEncoderError::AllocAVFrameError);
+        }
+
+        Ok(this)
+    }
+
+    /// The AudioSpecificConfig of the stream.
+    pub fn extradata(&self) -> &[u8] {
+        let context_ref = unsafe { &*self.context };
+        if context_ref.extradata.is_null() {
+            return &[];
+        }
+
+        unsafe {
+            std::slice::from_raw_parts(context_ref.extradata, context_ref.extradata_size as usize)
+        }
+    }
+
+    /// Encode a frame, the sample rate of the frame has to be the sample rate of
+    /// the encoder. The packets are taken with `read`.
+    pub fn encode(&mut self, frame: &AudioFrame) -> Result<(), AudioEncoderError> {
+        if frame.data.is_null() || frame.frames == 0 {
+            return Ok(());
+        }
+
+        let context_ref = unsafe { &*self.context };
+        if self.samples.is_empty() && frame.timestamp > 0 {
+            self.pts = unsafe {
+                av_rescale_q(
+                    frame.timestamp as i64,
+                    av_make_q(1, 1_000_000),
+                    context_ref.time_base,
+                )
+            }
+            .max(self.pts);
+        }
+
+        self.samples.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(frame.data, frame.frames as usize)
+        });
+
+        let frame_size = context_ref.frame_size.max(1) as usize;
+        while self.samples.len() >= frame_size {
+            let av_frame = unsafe { &mut *self.frame };
+            av_frame.nb_samples = frame_size as i32;
+            av_frame.format = context_ref.sample_fmt as i32;
+            av_frame.ch_layout = context_ref.ch_layout;
+            av_frame.pts = self.pts;
+
+            if unsafe { av_frame_get_buffer(self.frame, 0) } != 0 {
+                return Err(AudioEncoderError::AllocAVFrameError);
+            }
+
+            let plane =
+                unsafe { std::slice::from_raw_parts_mut(av_frame.data[0] as *mut f32, frame_size) };
+
+            for (output, sample) in plane.iter_mut().zip(self.samples.drain(..frame_size)) {
+                *output = sample as f32 / 32768.0;
+            }
+
+            let result = unsafe { avcodec_send_frame(self.context, self.frame) };
+            unsafe {
+                av_frame_unref(self.frame);
+            }
+
+            if result != 0 {
+                return Err(AudioEncoderError::EncodeFrameError);
+            }
+
+            self.pts += frame_size as i64;
+        }
+
+        Ok(())
+    }
+
+    pub fn read<'a>(&'a mut self) -> Option<(&'a [u8], i32, u64)> {
+        if unsafe { avcodec_receive_packet(self.context, self.packet) } != 0 {
+            return None;
+        }
+
+        let packet_ref = unsafe { &*self.packet };
+        Some((
+            unsafe { std::slice::from_raw_parts(packet_ref.data, packet_ref.size as usize) },
+            packet_ref.flags,
+            unsafe {
+                av_rescale_q(
+                    packet_ref.pts,
+                    { &*self.context }.time_base,
+                    av_make_q(1, 1_000_000),
+                )
+            }
+            .max(0) as u64,
+        ))
+    }
+}
+
+impl Drop for AacEncoder {
+    fn drop(&mut self) {
+        if !self.packet.is_null() {
+            unsafe {
+                av_packet_free(&mut self.packet);
+            }
+        }
+
+        if !self.context.is_null() {
+            unsafe {
+                avcodec_free_context(&mut self.context);
+            }
+        }
+
+        if !self.frame.is_null() {
+            unsafe {
+                av_frame_free(&mut self.frame);
+            }
+        }
+    }
+}
+
 /// Header Packets
 ///
 ///    An Ogg Opus logical stream contains exactly two mandatory header
@@ -2,6 +2,7 @@ use std::str::FromStr;
 
 use hylarana_common::strings::PSTR;
 use mirror_ffmpeg_sys::*;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 #[cfg(any(target_os = "windows", target_os = "macos"))]
@@ -60,6 +61,16 @@ pub enum VideoDecoderType {
     /// VideoToolbox is a low-level framework that provides direct access to
     /// hardware encoders and decoders.
     VideoToolBox,
+    /// [HEVC D3D11VA](https://learn.microsoft.com/en-us/windows/win32/medfound/direct3d-11-video-apis)
+    ///
+    /// Accelerated HEVC Main10 decoding using Direct3D 11 Video APIs, the
+    /// output is a 10-bit P010 texture.
+    HevcD3D11,
+    /// [HEVC QSV](https://en.wikipedia.org/wiki/Intel_Quick_Sync_Video)
+    ///
+    /// HEVC Main10 decoding on the Intel Quick Sync Video hardware core, the
+    /// output is a 10-bit P010 texture.
+    HevcQsv,
 }
 
 impl ToString for VideoDecoderType {
@@ -69,6 +80,8 @@ impl ToString for VideoDecoderType {
             Self::D3D11 => "d3d11va",
             Self::Qsv => "h264_qsv",
             Self::VideoToolBox => "h264_videotoolbox",
+            Self::HevcD3D11 => "hevc_d3d11va",
+            Self::HevcQsv => "hevc_qsv",
         }
         .to_string()
     }
@@ -83,13 +96,15 @@ impl FromStr for VideoDecoderType {
             "d3d11va" => Self::D3D11,
             "h264_qsv" => Self::Qsv,
             "h264_videotoolbox" => Self::VideoToolBox,
+            "hevc_d3d11va" => Self::HevcD3D11,
+            "hevc_qsv" => Self::HevcQsv,
             _ => return Err(CodecError::NotSupportCodec),
         })
     }
 }
 
 /// Video encoder type.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum VideoEncoderType {
     /// [X264](https://www.videolan.org/developers/x264.html)
     ///
@@ -107,6 +122,17 @@ pub enum VideoEncoderType {
     /// VideoToolbox is a low-level framework that provides direct access to
     /// hardware encoders and decoders.
     VideoToolBox,
+    /// [X265](https://www.videolan.org/developers/x265.html)
+    ///
+    /// x265 is a free software library for encoding video streams into the
+    /// H.265/HEVC compression format, used here with the Main10 profile for
+    /// HDR10 content.
+    X265,
+    /// [HEVC QSV](https://en.wikipedia.org/wiki/Intel_Quick_Sync_Video)
+    ///
+    /// HEVC Main10 encoding on the Intel Quick Sync Video hardware core, used
+    /// for HDR10 content.
+    HevcQsv,
 }
 
 impl ToString for VideoEncoderType {
@@ -115,11 +141,45 @@ impl ToString for VideoEncoderType {
             Self::X264 => "libx264",
             Self::Qsv => "h264_qsv",
             Self::VideoToolBox => "h264_videotoolbox",
+            Self::X265 => "libx265",
+            Self::HevcQsv => "hevc_qsv",
         }
         .to_string()
     }
 }
 
+impl VideoEncoderType {
+    /// The encoder that is tried next when this encoder cannot be created or
+    /// fails while encoding, the chain ends at the software encoders.
+    ///
+    /// The fallback always produces the same codec, the receivers only switch
+    /// between H264 and HEVC when the sender switches the codec for them.
+    pub fn fallback(&self) -> Option<Self> {
+        match self {
+            Self::Qsv | Self::VideoToolBox => Some(Self::X264),
+            Self::HevcQsv => Some(Self::X265),
+            Self::X264 | Self::X265 => None,
+        }
+    }
+
+    /// The hardware encoder of the same codec on this platform, the hardware
+    /// encoders return themselves. The hardware encoder may still not be
+    /// available on the machine, it falls back to the software encoder then.
+    pub fn hardware(&self) -> Option<Self> {
+        match self {
+            Self::Qsv | Self::VideoToolBox | Self::HevcQsv => Some(*self),
+            #[cfg(target_os = "macos")]
+            Self::X264 => Some(Self::VideoToolBox),
+            #[cfg(not(target_os = "macos"))]
+            Self::X264 => Some(Self::Qsv),
+            #[cfg(not(target_os = "macos"))]
+            Self::X265 => Some(Self::HevcQsv),
+            #[cfg(target_os = "macos")]
+            Self::X265 => None,
+        }
+    }
+}
+
 impl FromStr for VideoEncoderType {
     type Err = CodecError;
 
@@ -128,6 +188,8 @@ impl FromStr for VideoEncoderType {
             "libx264" => Self::X264,
             "h264_qsv" => Self::Qsv,
             "h264_videotoolbox" => Self::VideoToolBox,
+            "libx265" => Self::X265,
+            "hevc_qsv" => Self::HevcQsv,
             _ => return Err(CodecError::NotSupportCodec),
         })
     }
@@ -158,9 +220,11 @@ impl CodecType {
                 if cfg!(target_os = "windows") {
                     *kind != VideoEncoderType::VideoToolBox
                 } else if cfg!(target_os = "linux") {
-                    *kind == VideoEncoderType::X264
+                    *kind == VideoEncoderType::X264 || *kind == VideoEncoderType::X265
                 } else {
-                    *kind == VideoEncoderType::X264 || *kind == VideoEncoderType::VideoToolBox
+                    *kind == VideoEncoderType::X264
+                        || *kind == VideoEncoderType::X265
+                        || *kind == VideoEncoderType::VideoToolBox
                 }
             }
             CodecType::Decoder(kind) => {
@@ -185,15 +249,44 @@ impl CodecType {
 
     pub fn is_qsv(self) -> bool {
         match self {
-            CodecType::Encoder(kind) => kind == VideoEncoderType::Qsv,
-            CodecType::Decoder(kind) => kind == VideoDecoderType::Qsv,
+            CodecType::Encoder(kind) => {
+                kind == VideoEncoderType::Qsv || kind == VideoEncoderType::HevcQsv
+            }
+            CodecType::Decoder(kind) => {
+                kind == VideoDecoderType::Qsv || kind == VideoDecoderType::HevcQsv
+            }
         }
     }
 
     pub fn is_hardware(&self) -> bool {
         match self {
             Self::Decoder(codec) => *codec != VideoDecoderType::H264,
-            Self::Encoder(codec) => *codec != VideoEncoderType::X264,
+            Self::Encoder(codec) => {
+                *codec != VideoEncoderType::X264 && *codec != VideoEncoderType::X265
+            }
+        }
+    }
+
+    /// HEVC codecs are always used with the Main10 profile, the frames are
+    /// 10-bit P010 and carry HDR10 (BT.2020 + PQ) content.
+    pub fn is_10bit(&self) -> bool {
+        match self {
+            Self::Decoder(codec) => {
+                *codec == VideoDecoderType::HevcD3D11 || *codec == VideoDecoderType::HevcQsv
+            }
+            Self::Encoder(codec) => {
+                *codec == VideoEncoderType::X265 || *codec == VideoEncoderType::HevcQsv
+            }
+        }
+    }
+
+    /// The software pixel format of the frames passed to or returned from the
+    /// codec.
+    pub fn sw_format(&self) -> AVPixelFormat {
+        if self.is_10bit() {
+            AVPixelFormat::AV_PIX_FMT_P010LE
+        } else {
+            AVPixelFormat::AV_PIX_FMT_NV12
         }
     }
 
@@ -205,6 +298,8 @@ impl CodecType {
             Self::Decoder(kind) => {
                 if *kind == VideoDecoderType::D3D11 || *kind == VideoDecoderType::VideoToolBox {
                     avcodec_find_decoder(AVCodecID::AV_CODEC_ID_H264)
+                } else if *kind == VideoDecoderType::HevcD3D11 {
+                    avcodec_find_decoder(AVCodecID::AV_CODEC_ID_HEVC)
                 } else {
                     avcodec_find_decoder_by_name(PSTR::from(kind.to_string()).as_ptr())
                 }
@@ -302,7 +397,7 @@ pub fn create_video_context(
                 let size = size.expect("encoder needs init hardware frame for size");
                 unsafe {
                     let frames_ctx = &mut *((&mut *hw_frames_ctx).data as *mut AVHWFramesContext);
-                    frames_ctx.sw_format = AVPixelFormat::AV_PIX_FMT_NV12;
+                    frames_ctx.sw_format = kind.sw_format();
                     frames_ctx.format = AVPixelFormat::AV_PIX_FMT_QSV;
                     frames_ctx.width = size.width as i32;
                     frames_ctx.height = size.height as i32;
@@ -388,7 +483,7 @@ pub fn create_video_context(
             let size = size.expect("encoder needs init hardware frame for size");
             unsafe {
                 let frames_ctx = &mut *((&mut *hw_frames_ctx).data as *mut AVHWFramesContext);
-                frames_ctx.sw_format = AVPixelFormat::AV_PIX_FMT_NV12;
+                frames_ctx.sw_format = kind.sw_format();
                 frames_ctx.format = AVPixelFormat::AV_PIX_FMT_VIDEOTOOLBOX;
                 frames_ctx.width = size.width as i32;
                 frames_ctx.height = size.height as i32;
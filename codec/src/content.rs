@@ -0,0 +1,302 @@
+use crate::MaskArea;
+
+use hylarana_common::frame::{VideoFormat, VideoFrame, VideoSubFormat};
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::{
+    win32::{
+        d3d_texture_borrowed_raw,
+        windows::Win32::Graphics::Dxgi::Common::{DXGI_FORMAT, DXGI_FORMAT_NV12, DXGI_FORMAT_P010},
+        Direct3DDevice,
+    },
+    Size,
+};
+
+#[cfg(target_os = "windows")]
+use hylarana_resample::win32::{Resource, VideoResampler, VideoResamplerOptions};
+
+#[derive(Debug, Clone)]
+pub struct ContentDetectorSettings {
+    /// The 8 bits luma that the black frames do not exceed, the black of the
+    /// limited range is 16.
+    pub threshold: u8,
+    #[cfg(target_os = "windows")]
+    pub direct3d: Direct3DDevice,
+}
+
+#[derive(Error, Debug)]
+pub enum ContentDetectorError {
+    #[error("unsupported video frame format")]
+    NotSupportFormat,
+    #[cfg(target_os = "windows")]
+    #[error(transparent)]
+    WindowsError(#[from] hylarana_common::win32::windows::core::Error),
+}
+
+/// What a frame shows, see `ContentDetector`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct FrameContent {
+    /// The frame is black, only a few pixels of it are brighter than the
+    /// threshold, such as the mouse cursor.
+    pub black: bool,
+    /// The frame is the same as the previous frame.
+    pub unchanged: bool,
+    /// The fraction of the samples that have changed noticeably from the
+    /// previous frame, from `0.0` to `1.0`, a hard cut changes most of them.
+    pub changed: f32,
+}
+
+/// Tells the black frames, the frames that have not changed from the previous
+/// frame, and how much the frames have changed apart, from the luma of the
+/// frames.
+///
+/// The software frames are compared pixel by pixel. The Direct3D11 textures
+/// are scaled down to a quarter of the size and read back, so a change of a
+/// few pixels may not be seen in them.
+pub struct ContentDetector {
+    settings: ContentDetectorSettings,
+    samples: Vec<u8>,
+    previous: Vec<u8>,
+    // The width and height of the samples and of the frame they are taken from,
+    // of the current and the previous frame.
+    size: (usize, usize, u32, u32),
+    previous_size: (usize, usize, u32, u32),
+    #[cfg(target_os = "windows")]
+    hardware: Option<(VideoResampler, Size, VideoFormat)>,
+}
+
+unsafe impl Sync for ContentDetector {}
+unsafe impl Send for ContentDetector {}
+
+impl ContentDetector {
+    // The frame is black if no more than one in this many samples is brighter
+    // than the threshold.
+    const BRIGHT_RATIO: usize = 1000;
+
+    // The difference of the luma that is taken as a change, the noise of the
+    // cameras and the dithering stay below it.
+    const CHANGE_THRESHOLD: u8 = 16;
+
+    pub fn new(settings: ContentDetectorSettings) -> Self {
+        Self {
+            #[cfg(target_os = "windows")]
+            hardware: None,
+            samples: Vec::new(),
+            previous: Vec::new(),
+            size: (0, 0, 0, 0),
+            previous_size: (0, 0, 0, 0),
+            settings,
+        }
+    }
+
+    pub fn process(&mut self, frame: &VideoFrame) -> Result<FrameContent, ContentDetectorError> {
+        std::mem::swap(&mut self.samples, &mut self.previous);
+        self.previous_size = self.size;
+        self.samples.clear();
+        self.size = (0, 0, 0, 0);
+
+        match frame.sub_format {
+            #[cfg(target_os = "windows")]
+            VideoSubFormat::D3D11 => self.sample_d3d11(frame)?,
+            VideoSubFormat::SW => self.sample_software(frame)?,
+            #[allow(unreachable_patterns)]
+            _ => return Err(ContentDetectorError::NotSupportFormat),
+        }
+
+        let bright = self
+            .samples
+            .iter()
+            .filter(|it| **it > self.settings.threshold)
+            .count();
+
+        let unchanged = !self.samples.is_empty() && self.samples == self.previous;
+
+        // The frames of another size have nothing to compare with.
+        let changed = if unchanged || self.samples.len() != self.previous.len() {
+            0.0
+        } else {
+            self.samples
+                .iter()
+                .zip(self.previous.iter())
+                .filter(|(a, b)| a.abs_diff(**b) > Self::CHANGE_THRESHOLD)
+                .count() as f32
+                / self.samples.len() as f32
+        };
+
+        Ok(FrameContent {
+            black: bright * Self::BRIGHT_RATIO <= self.samples.len(),
+            unchanged,
+            changed,
+        })
+    }
+
+    /// The tiles of the last processed frame that differ from the previous
+    /// frame, in the pixels of the frame, the tiles are squares of the size
+    /// and the neighbouring tiles of a row are joined. Returns `None` if the
+    /// previous frame has another size and there is nothing to compare with.
+    ///
+    /// Only the frames that are compared pixel by pixel have the tiles, the
+    /// scaled down samples of the Direct3D11 textures may miss a change and
+    /// the tile would be taken as static, so `None` is returned for them.
+    pub fn damage(&self, tile: u32) -> Option<Vec<MaskArea>> {
+        let (width, height, frame_width, frame_height) = self.size;
+        if self.samples.is_empty() || self.size != self.previous_size {
+            return None;
+        }
+
+        if width != frame_width as usize || height != frame_height as usize {
+            return None;
+        }
+
+        let tile = tile.max(1);
+        let columns = frame_width.div_ceil(tile) as usize;
+
+        let mut dirty = vec![false; columns * frame_height.div_ceil(tile) as usize];
+        for y in 0..height {
+            let row = y / tile as usize;
+            let offset = y * width;
+
+            for x in 0..width {
+                if self.samples[offset + x] != self.previous[offset + x] {
+                    dirty[row * columns + x / tile as usize] = true;
+                }
+            }
+        }
+
+        let mut areas = Vec::new();
+        for (row, line) in dirty.chunks(columns).enumerate() {
+            let mut column = 0;
+            while column < columns {
+                if !line[column] {
+                    column += 1;
+                    continue;
+                }
+
+                let start = column;
+                while column < columns && line[column] {
+                    column += 1;
+                }
+
+                let (x, y) = (start as u32 * tile, row as u32 * tile);
+                areas.push(MaskArea {
+                    width: (column as u32 * tile).min(frame_width) - x,
+                    height: (y + tile).min(frame_height) - y,
+                    x,
+                    y,
+                });
+            }
+        }
+
+        Some(areas)
+    }
+
+    // The luma is the first plane of all the supported formats, the samples of 16
+    // bits keep the value in the high bits.
+    fn sample_software(&mut self, frame: &VideoFrame) -> Result<(), ContentDetectorError> {
+        let depth = match frame.format {
+            VideoFormat::NV12 | VideoFormat::I420 => 1,
+            VideoFormat::P010 => 2,
+            _ => return Err(ContentDetectorError::NotSupportFormat),
+        };
+
+        if frame.data[0].is_null() {
+            return Err(ContentDetectorError::NotSupportFormat);
+        }
+
+        self.samples
+            .reserve(frame.width as usize * frame.height as usize);
+        self.size = (
+            frame.width as usize,
+            frame.height as usize,
+            frame.width,
+            frame.height,
+        );
+        for y in 0..frame.height as usize {
+            let line = unsafe {
+                std::slice::from_raw_parts(
+                    (frame.data[0] as *const u8).add(y * frame.linesize[0]),
+                    frame.width as usize * depth,
+                )
+            };
+
+            if depth == 1 {
+                self.samples.extend_from_slice(line);
+            } else {
+                self.samples.extend(line.chunks_exact(2).map(|it| it[1]));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    fn sample_d3d11(&mut self, frame: &VideoFrame) -> Result<(), ContentDetectorError> {
+        let (format, depth): (DXGI_FORMAT, usize) = match frame.format {
+            VideoFormat::NV12 => (DXGI_FORMAT_NV12, 1),
+            VideoFormat::P010 => (DXGI_FORMAT_P010, 2),
+            _ => return Err(ContentDetectorError::NotSupportFormat),
+        };
+
+        let size = Size {
+            width: frame.width,
+            height: frame.height,
+        };
+
+        if self
+            .hardware
+            .as_ref()
+            .map(|(_, it, format)| *it != size || *format != frame.format)
+            .unwrap_or(true)
+        {
+            let resampler = VideoResampler::new(VideoResamplerOptions {
+                direct3d: self.settings.direct3d.clone(),
+                input: Resource::Default(format, size),
+                output: Resource::Default(
+                    format,
+                    Size {
+                        width: (size.width / 4).max(2) & !1,
+                        height: (size.height / 4).max(2) & !1,
+                    },
+                ),
+            })?;
+
+            self.hardware = Some((resampler, size, frame.format));
+
+            log::info!(
+                "content detector create d3d11 processor, size={}x{}",
+                size.width,
+                size.height
+            );
+        }
+
+        let (resampler, size, _) = self.hardware.as_mut().unwrap();
+        let (width, height) = (
+            ((size.width / 4).max(2) & !1) as usize,
+            ((size.height / 4).max(2) & !1) as usize,
+        );
+
+        // The texture of the frame is borrowed, it must not be released here.
+        let texture = d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
+            .ok_or_else(|| ContentDetectorError::NotSupportFormat)?;
+
+        let view = resampler.create_input_view(texture, frame.data[1] as u32)?;
+        resampler.process(Some(view))?;
+
+        let buffer = resampler.get_output_buffer()?;
+        self.size = (width, height, frame.width, frame.height);
+        for y in 0..height {
+            let line = unsafe {
+                std::slice::from_raw_parts(buffer.buffer().add(y * buffer.stride()), width * depth)
+            };
+
+            if depth == 1 {
+                self.samples.extend_from_slice(line);
+            } else {
+                self.samples.extend(line.chunks_exact(2).map(|it| it[1]));
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,472 @@
+use crate::scale::get_pixel_format;
+
+use std::ptr::{null, null_mut};
+
+use hylarana_common::{
+    frame::{FieldOrder, VideoFormat, VideoFrame, VideoSubFormat},
+    strings::PSTR,
+    Size,
+};
+
+use mirror_ffmpeg_sys::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::{
+    d3d_texture_borrowed_raw,
+    windows::{
+        core::Interface,
+        Win32::Graphics::Dxgi::Common::{DXGI_FORMAT_NV12, DXGI_FORMAT_P010},
+    },
+    Direct3DDevice,
+};
+
+#[cfg(target_os = "windows")]
+use hylarana_resample::win32::{Resource, VideoResampler, VideoResamplerOptions};
+
+/// The method that the interlaced frames are deinterlaced with, each frame
+/// gives one progressive frame, so the frame rate stays the same.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DeinterlaceMethod {
+    /// The bwdif filter of FFmpeg, which keeps more detail than yadif.
+    #[default]
+    Bwdif,
+    /// The yadif filter of FFmpeg, which is cheaper than bwdif.
+    Yadif,
+    /// The video processor of Direct3D11, the software frames are uploaded to
+    /// the GPU. This is only supported on windows, the other platforms use
+    /// bwdif.
+    VideoProcessor,
+}
+
+#[derive(Debug, Clone)]
+pub struct DeinterlacerSettings {
+    pub method: DeinterlaceMethod,
+    #[cfg(target_os = "windows")]
+    pub direct3d: Direct3DDevice,
+}
+
+#[derive(Error, Debug)]
+pub enum DeinterlacerError {
+    #[error("unsupported video frame format")]
+    NotSupportFormat,
+    #[error("failed to alloc av frame")]
+    AllocAVFrameError,
+    #[error("failed to create filter graph")]
+    CreateFilterGraphError,
+    #[error("failed to filter the frame")]
+    FilterError,
+    #[cfg(target_os = "windows")]
+    #[error(transparent)]
+    WindowsError(#[from] hylarana_common::win32::windows::core::Error),
+}
+
+/// Deinterlaces the frames of the interlaced sources, such as the capture
+/// cards, before they are scaled and encoded. The progressive frames do not
+/// need to be passed through it.
+///
+/// The Direct3D11 textures are always deinterlaced with the video processor,
+/// the software frames with the configured method.
+pub struct Deinterlacer {
+    settings: DeinterlacerSettings,
+    software: Option<SoftwareDeinterlacer>,
+    #[cfg(target_os = "windows")]
+    hardware: Option<(VideoResampler, Size, VideoFormat)>,
+    // The software frames that are uploaded to the video processor, the planes
+    // have to follow each other with the same stride.
+    #[cfg(target_os = "windows")]
+    buffer: Vec<u8>,
+    frame: VideoFrame,
+}
+
+unsafe impl Sync for Deinterlacer {}
+unsafe impl Send for Deinterlacer {}
+
+impl Deinterlacer {
+    pub fn new(settings: DeinterlacerSettings) -> Self {
+        Self {
+            #[cfg(target_os = "windows")]
+            hardware: None,
+            #[cfg(target_os = "windows")]
+            buffer: Vec::new(),
+            frame: VideoFrame::default(),
+            software: None,
+            settings,
+        }
+    }
+
+    /// Whether the frame needs to be passed through the deinterlacer.
+    pub fn is_required(&self, frame: &VideoFrame) -> bool {
+        frame.field_order != FieldOrder::Progressive
+    }
+
+    /// Deinterlace the frame, `None` is returned while the software filters
+    /// wait for the next frame, they look one frame ahead, so their output is
+    /// one frame behind the input.
+    pub fn process(
+        &mut self,
+        frame: &VideoFrame,
+    ) -> Result<Option<&VideoFrame>, DeinterlacerError> {
+        match frame.sub_format {
+            #[cfg(target_os = "windows")]
+            VideoSubFormat::D3D11 => self.process_d3d11(frame)?,
+            #[cfg(target_os = "windows")]
+            VideoSubFormat::SW if self.settings.method == DeinterlaceMethod::VideoProcessor => {
+                self.process_upload(frame)?
+            }
+            VideoSubFormat::SW => {
+                if !self.process_software(frame)? {
+                    return Ok(None);
+                }
+            }
+            #[allow(unreachable_patterns)]
+            _ => return Err(DeinterlacerError::NotSupportFormat),
+        }
+
+        self.frame.width = frame.width;
+        self.frame.height = frame.height;
+        self.frame.display_width = frame.display_width;
+        self.frame.display_height = frame.display_height;
+        self.frame.rotation = frame.rotation;
+        self.frame.mirror = frame.mirror;
+        self.frame.field_order = FieldOrder::Progressive;
+        Ok(Some(&self.frame))
+    }
+
+    #[cfg(target_os = "windows")]
+    fn video_processor(
+        &mut self,
+        frame: &VideoFrame,
+    ) -> Result<&mut VideoResampler, DeinterlacerError> {
+        let format = match frame.format {
+            VideoFormat::NV12 => DXGI_FORMAT_NV12,
+            VideoFormat::P010 => DXGI_FORMAT_P010,
+            _ => return Err(DeinterlacerError::NotSupportFormat),
+        };
+
+        let size = Size {
+            width: frame.width,
+            height: frame.height,
+        };
+
+        if self
+            .hardware
+            .as_ref()
+            .map(|(_, it, format)| *it != size || *format != frame.format)
+            .unwrap_or(true)
+        {
+            self.hardware = Some((
+                VideoResampler::new(VideoResamplerOptions {
+                    direct3d: self.settings.direct3d.clone(),
+                    input: Resource::Default(format, size),
+                    output: Resource::Default(format, size),
+                })?,
+                size,
+                frame.format,
+            ));
+
+            log::info!(
+                "deinterlacer create d3d11 processor, size={}x{}",
+                size.width,
+                size.height
+            );
+        }
+
+        let (resampler, _, _) = self.hardware.as_mut().unwrap();
+
+        // The field order may change from frame to frame, such as for a source
+        // that switches between interlaced and progressive content.
+        resampler.set_field_order(frame.field_order);
+        Ok(resampler)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn process_d3d11(&mut self, frame: &VideoFrame) -> Result<(), DeinterlacerError> {
+        // The texture of the frame is borrowed, it must not be released here.
+        let texture = d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
+            .ok_or_else(|| DeinterlacerError::NotSupportFormat)?;
+
+        let resampler = self.video_processor(frame)?;
+        let view = resampler.create_input_view(texture, frame.data[1] as u32)?;
+        resampler.process(Some(view))?;
+
+        let output = resampler.get_output().as_raw();
+        self.set_d3d11_output(frame, output);
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    fn process_upload(&mut self, frame: &VideoFrame) -> Result<(), DeinterlacerError> {
+        // Both planes are uploaded from one buffer, the UV plane has half of the
+        // rows of the Y plane.
+        let stride = frame.linesize[0];
+        let size = stride * frame.height as usize;
+
+        self.buffer.clear();
+        unsafe {
+            self.buffer
+                .extend_from_slice(std::slice::from_raw_parts(frame.data[0] as *const u8, size));
+
+            for row in 0..frame.height as usize / 2 {
+                self.buffer.extend_from_slice(std::slice::from_raw_parts(
+                    (frame.data[1] as *const u8).add(row * frame.linesize[1]),
+                    stride.min(frame.linesize[1]),
+                ));
+
+                self.buffer.resize(size + (row + 1) * stride, 0);
+            }
+        }
+
+        let buffer = self.buffer.as_ptr();
+        let resampler = self.video_processor(frame)?;
+        resampler.update_input_from_buffer(buffer, stride as u32)?;
+        resampler.process(None)?;
+
+        let output = resampler.get_output().as_raw();
+        self.set_d3d11_output(frame, output);
+        Ok(())
+    }
+
+    #[cfg(target_os = "windows")]
+    fn set_d3d11_output(&mut self, frame: &VideoFrame, output: *mut std::ffi::c_void) {
+        self.frame.format = frame.format;
+        self.frame.sub_format = VideoSubFormat::D3D11;
+        self.frame.data = [output as *const _, null(), null()];
+        self.frame.linesize = [0; 3];
+        self.frame.timestamp = frame.timestamp;
+    }
+
+    // Returns false if the filter has no output yet.
+    fn process_software(&mut self, frame: &VideoFrame) -> Result<bool, DeinterlacerError> {
+        let size = Size {
+            width: frame.width,
+            height: frame.height,
+        };
+
+        if self
+            .software
+            .as_ref()
+            .map(|it| it.size != size || it.format != frame.format)
+            .unwrap_or(true)
+        {
+            let method = match self.settings.method {
+                DeinterlaceMethod::Yadif => DeinterlaceMethod::Yadif,
+                _ => DeinterlaceMethod::Bwdif,
+            };
+
+            // The frames that are held by the old filter are lost.
+            self.software = Some(SoftwareDeinterlacer::new(method, size, frame.format)?);
+
+            log::info!(
+                "deinterlacer create filter graph, method={:?}, size={}x{}, format={:?}",
+                method,
+                size.width,
+                size.height,
+                frame.format
+            );
+        }
+
+        let Some(output) = self.software.as_mut().unwrap().filter(frame)? else {
+            return Ok(false);
+        };
+
+        for i in 0..3 {
+            self.frame.data[i] = output.data[i] as *const _;
+            self.frame.linesize[i] = output.linesize[i] as usize;
+        }
+
+        self.frame.format = frame.format;
+        self.frame.sub_format = VideoSubFormat::SW;
+        self.frame.timestamp = output.pts as u64;
+        Ok(true)
+    }
+}
+
+// A filter graph of `buffer -> bwdif/yadif -> format -> buffersink`, the frames
+// keep their format, the filters of other formats are converted back and forth
+// by the graph.
+struct SoftwareDeinterlacer {
+    graph: *mut AVFilterGraph,
+    source: *mut AVFilterContext,
+    sink: *mut AVFilterContext,
+    input: *mut AVFrame,
+    output: *mut AVFrame,
+    size: Size,
+    format: VideoFormat,
+}
+
+impl SoftwareDeinterlacer {
+    fn new(
+        method: DeinterlaceMethod,
+        size: Size,
+        format: VideoFormat,
+    ) -> Result<Self, DeinterlacerError> {
+        let mut this = Self {
+            graph: unsafe { avfilter_graph_alloc() },
+            input: unsafe { av_frame_alloc() },
+            output: unsafe { av_frame_alloc() },
+            source: null_mut(),
+            sink: null_mut(),
+            format,
+            size,
+        };
+
+        if this.input.is_null() || this.output.is_null() {
+            return Err(DeinterlacerError::AllocAVFrameError);
+        }
+
+        if this.graph.is_null() {
+            return Err(DeinterlacerError::CreateFilterGraphError);
+        }
+
+        let pixel_format = get_pixel_format(format);
+        let pixel_format_name = PSTR::from(unsafe { av_get_pix_fmt_name(pixel_format) })
+            .to_string()
+            .map_err(|_| DeinterlacerError::NotSupportFormat)?;
+
+        let args = PSTR::from(format!(
+            "video_size={}x{}:pix_fmt={}:time_base=1/1000000:pixel_aspect=1/1",
+            size.width, size.height, pixel_format as i32
+        ));
+
+        // Only the frames that are marked as interlaced are deinterlaced, the
+        // parity is taken from the frames.
+        let filters = PSTR::from(format!(
+            "{}=mode=send_frame:parity=auto:deint=interlaced,format={}",
+            match method {
+                DeinterlaceMethod::Yadif => "yadif",
+                _ => "bwdif",
+            },
+            pixel_format_name
+        ));
+
+        unsafe {
+            if avfilter_graph_create_filter(
+                &mut this.source,
+                avfilter_get_by_name(PSTR::from("buffer").as_ptr()),
+                PSTR::from("in").as_ptr(),
+                args.as_ptr(),
+                null_mut(),
+                this.graph,
+            ) < 0
+            {
+                return Err(DeinterlacerError::CreateFilterGraphError);
+            }
+
+            if avfilter_graph_create_filter(
+                &mut this.sink,
+                avfilter_get_by_name(PSTR::from("buffersink").as_ptr()),
+                PSTR::from("out").as_ptr(),
+                null(),
+                null_mut(),
+                this.graph,
+            ) < 0
+            {
+                return Err(DeinterlacerError::CreateFilterGraphError);
+            }
+
+            // The open ends of the parsed filters are connected to the source and the
+            // sink, the output of the source is the input of the filters.
+            let mut outputs = avfilter_inout_alloc();
+            let mut inputs = avfilter_inout_alloc();
+            if outputs.is_null() || inputs.is_null() {
+                avfilter_inout_free(&mut outputs);
+                avfilter_inout_free(&mut inputs);
+
+                return Err(DeinterlacerError::CreateFilterGraphError);
+            }
+
+            (*outputs).name = av_strdup(PSTR::from("in").as_ptr());
+            (*outputs).filter_ctx = this.source;
+            (*outputs).pad_idx = 0;
+            (*outputs).next = null_mut();
+
+            (*inputs).name = av_strdup(PSTR::from("out").as_ptr());
+            (*inputs).filter_ctx = this.sink;
+            (*inputs).pad_idx = 0;
+            (*inputs).next = null_mut();
+
+            let result = avfilter_graph_parse_ptr(
+                this.graph,
+                filters.as_ptr(),
+                &mut inputs,
+                &mut outputs,
+                null_mut(),
+            );
+
+            avfilter_inout_free(&mut outputs);
+            avfilter_inout_free(&mut inputs);
+
+            if result < 0 || avfilter_graph_config(this.graph, null_mut()) < 0 {
+                return Err(DeinterlacerError::CreateFilterGraphError);
+            }
+        }
+
+        Ok(this)
+    }
+
+    fn filter(&mut self, frame: &VideoFrame) -> Result<Option<&AVFrame>, DeinterlacerError> {
+        unsafe {
+            let input = &mut *self.input;
+            input.format = get_pixel_format(frame.format) as i32;
+            input.width = frame.width as i32;
+            input.height = frame.height as i32;
+            input.pts = frame.timestamp as i64;
+
+            for i in 0..3 {
+                input.data[i] = frame.data[i] as *mut _;
+                input.linesize[i] = frame.linesize[i] as i32;
+            }
+
+            input.flags = match frame.field_order {
+                FieldOrder::Progressive => 0,
+                FieldOrder::TopFieldFirst => {
+                    (AV_FRAME_FLAG_INTERLACED | AV_FRAME_FLAG_TOP_FIELD_FIRST) as i32
+                }
+                FieldOrder::BottomFieldFirst => AV_FRAME_FLAG_INTERLACED as i32,
+            };
+
+            // The frame is not reference counted, the filters keep the previous
+            // frames, so the planes are copied into the buffers of the graph.
+            let result = av_buffersrc_add_frame_flags(
+                self.source,
+                self.input,
+                AV_BUFFERSRC_FLAG_KEEP_REF as i32,
+            );
+
+            input.data = [null_mut(); 8];
+            if result < 0 {
+                return Err(DeinterlacerError::FilterError);
+            }
+
+            av_frame_unref(self.output);
+
+            // The filter has no output until it has seen the next frame.
+            if av_buffersink_get_frame(self.sink, self.output) < 0 {
+                return Ok(None);
+            }
+
+            Ok(Some(&*self.output))
+        }
+    }
+}
+
+impl Drop for SoftwareDeinterlacer {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.input.is_null() {
+                av_frame_free(&mut self.input);
+            }
+
+            if !self.output.is_null() {
+                av_frame_free(&mut self.output);
+            }
+
+            // The filters are released with the graph.
+            if !self.graph.is_null() {
+                avfilter_graph_free(&mut self.graph);
+            }
+        }
+    }
+}
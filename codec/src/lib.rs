@@ -1,6 +1,16 @@
 mod audio;
 mod codec;
+mod content;
+mod deinterlace;
+mod mask;
+mod muxer;
+mod planes;
+mod probe;
+mod reader;
+mod scale;
+mod thumbnail;
 mod video;
+mod watermark;
 
 use std::ffi::{c_char, c_int, c_void};
 
@@ -10,16 +20,29 @@ use mirror_ffmpeg_sys::*;
 
 pub use self::{
     audio::{
-        create_opus_identification_header, AudioDecoder, AudioDecoderError, AudioEncoder,
-        AudioEncoderError, AudioEncoderSettings,
+        create_opus_identification_header, AacEncoder, AudioDecoder, AudioDecoderError,
+        AudioEncoder, AudioEncoderError, AudioEncoderSettings,
     },
     codec::{
         CodecError, CodecType, CreateVideoContextError, CreateVideoFrameError, VideoDecoderType,
         VideoEncoderType,
     },
+    content::{ContentDetector, ContentDetectorError, ContentDetectorSettings, FrameContent},
+    deinterlace::{DeinterlaceMethod, Deinterlacer, DeinterlacerError, DeinterlacerSettings},
+    mask::{MaskArea, PrivacyMaskStyle, PrivacyMasker, PrivacyMaskerError, PrivacyMaskerSettings},
+    muxer::{MuxerStream, StreamMuxer, StreamMuxerError, StreamMuxerSettings},
+    probe::{probe, CodecCapabilities, CodecCapability, CodecStatus},
+    reader::{MediaFrame, MediaReader, MediaReaderError, MediaReaderSettings},
+    scale::{VideoScaler, VideoScalerError, VideoScalerSettings},
+    thumbnail::{ThumbnailEncoder, ThumbnailEncoderError, ThumbnailEncoderSettings},
     video::{
-        VideoDecoder, VideoDecoderError, VideoDecoderSettings, VideoEncoder, VideoEncoderError,
-        VideoEncoderSettings,
+        ContentHint, DecodedVideoFrame, RateControl, VideoDecoder, VideoDecoderError,
+        VideoDecoderSettings, VideoEncoder, VideoEncoderError, VideoEncoderSettings,
+        VideoEncoderTuning, VideoProfile, MAX_TAKEN_FRAMES,
+    },
+    watermark::{
+        WatermarkContent, WatermarkOptions, WatermarkPosition, Watermarker, WatermarkerError,
+        WatermarkerSettings,
     },
 };
 
@@ -0,0 +1,485 @@
+use crate::planes::PlaneBuffer;
+
+use hylarana_common::{
+    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    Size,
+};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+use std::ptr::null;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::{
+    d3d_texture_borrowed_raw,
+    windows::{
+        core::Interface,
+        Win32::Graphics::{
+            Direct3D11::{
+                ID3D11Texture2D, D3D11_BOX, D3D11_SUBRESOURCE_DATA, D3D11_TEXTURE2D_DESC,
+                D3D11_USAGE_DEFAULT,
+            },
+            Dxgi::Common::{DXGI_FORMAT, DXGI_FORMAT_NV12, DXGI_FORMAT_P010},
+        },
+    },
+    Direct3DDevice,
+};
+
+#[cfg(target_os = "windows")]
+use hylarana_resample::win32::{Resource, VideoResampler, VideoResamplerOptions};
+
+/// How the masked areas of the frames are hidden.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PrivacyMaskStyle {
+    /// The areas are filled with black.
+    #[default]
+    Black,
+    /// The areas are blurred so much that the text in them cannot be read,
+    /// only the rough shapes and colors remain.
+    Blur,
+}
+
+/// An area of a frame in pixels from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaskArea {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrivacyMaskerSettings {
+    #[cfg(target_os = "windows")]
+    pub direct3d: Direct3DDevice,
+}
+
+#[derive(Error, Debug)]
+pub enum PrivacyMaskerError {
+    #[error("unsupported video frame format")]
+    NotSupportFormat,
+    #[cfg(target_os = "windows")]
+    #[error(transparent)]
+    WindowsError(#[from] hylarana_common::win32::windows::core::Error),
+}
+
+/// Hides areas of the frames before they are encoded, such as the
+/// notifications or a panel with sensitive content. The output is a copy of
+/// the frame, the frame of the source is not changed.
+///
+/// The Direct3D11 textures are masked on the GPU, the areas are copied from a
+/// black texture, or from a copy of the frame that the video processor has
+/// scaled down and up again. The software frames are masked in the memory.
+pub struct PrivacyMasker {
+    #[allow(unused)]
+    settings: PrivacyMaskerSettings,
+    planes: PlaneBuffer,
+    #[cfg(target_os = "windows")]
+    hardware: Option<HardwareMasker>,
+    frame: VideoFrame,
+}
+
+unsafe impl Sync for PrivacyMasker {}
+unsafe impl Send for PrivacyMasker {}
+
+impl PrivacyMasker {
+    // The blurred copy of the frame is scaled down by this factor.
+    const BLUR_FACTOR: u32 = 16;
+
+    pub fn new(settings: PrivacyMaskerSettings) -> Self {
+        Self {
+            #[cfg(target_os = "windows")]
+            hardware: None,
+            planes: PlaneBuffer::default(),
+            frame: VideoFrame::default(),
+            settings,
+        }
+    }
+
+    /// Hide the areas of the frame, the areas are clipped to the frame and
+    /// grown to even pixels, so that they cover whole samples of the chroma.
+    pub fn process(
+        &mut self,
+        frame: &VideoFrame,
+        areas: &[MaskArea],
+        style: PrivacyMaskStyle,
+    ) -> Result<&VideoFrame, PrivacyMaskerError> {
+        let areas = areas
+            .iter()
+            .filter_map(|it| clip_area(it, frame.width, frame.height))
+            .collect::<Vec<_>>();
+
+        match frame.sub_format {
+            #[cfg(target_os = "windows")]
+            VideoSubFormat::D3D11 => self.process_d3d11(frame, &areas, style)?,
+            VideoSubFormat::SW => self.process_software(frame, &areas, style)?,
+            #[allow(unreachable_patterns)]
+            _ => return Err(PrivacyMaskerError::NotSupportFormat),
+        }
+
+        self.frame.width = frame.width;
+        self.frame.height = frame.height;
+        self.frame.display_width = frame.display_width;
+        self.frame.display_height = frame.display_height;
+        self.frame.rotation = frame.rotation;
+        self.frame.mirror = frame.mirror;
+        self.frame.field_order = frame.field_order;
+        self.frame.timestamp = frame.timestamp;
+        Ok(&self.frame)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn process_d3d11(
+        &mut self,
+        frame: &VideoFrame,
+        areas: &[MaskArea],
+        style: PrivacyMaskStyle,
+    ) -> Result<(), PrivacyMaskerError> {
+        let format = match frame.format {
+            VideoFormat::NV12 => DXGI_FORMAT_NV12,
+            VideoFormat::P010 => DXGI_FORMAT_P010,
+            _ => return Err(PrivacyMaskerError::NotSupportFormat),
+        };
+
+        let size = Size {
+            width: frame.width,
+            height: frame.height,
+        };
+
+        if self
+            .hardware
+            .as_ref()
+            .map(|it| it.size != size || it.format != frame.format)
+            .unwrap_or(true)
+        {
+            self.hardware = Some(HardwareMasker::new(
+                &self.settings.direct3d,
+                format,
+                frame.format,
+                size,
+            )?);
+
+            log::info!(
+                "privacy masker create d3d11 processor, size={}x{}",
+                size.width,
+                size.height
+            );
+        }
+
+        let hardware = self.hardware.as_mut().unwrap();
+
+        // The texture of the frame is borrowed, it must not be released here.
+        let texture = d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
+            .ok_or_else(|| PrivacyMaskerError::NotSupportFormat)?;
+
+        let view = hardware
+            .copy
+            .create_input_view(texture, frame.data[1] as u32)?;
+        hardware.copy.process(Some(view))?;
+
+        if !areas.is_empty() {
+            let source = match style {
+                PrivacyMaskStyle::Black => hardware.black.clone(),
+                PrivacyMaskStyle::Blur => {
+                    let view = hardware
+                        .down
+                        .create_input_view(texture, frame.data[1] as u32)?;
+                    hardware.down.process(Some(view))?;
+                    hardware.up.process(None)?;
+                    hardware.up.get_output().clone()
+                }
+            };
+
+            let output = hardware.copy.get_output();
+            for area in areas {
+                unsafe {
+                    self.settings.direct3d.context.CopySubresourceRegion(
+                        output,
+                        0,
+                        area.x,
+                        area.y,
+                        0,
+                        &source,
+                        0,
+                        Some(&D3D11_BOX {
+                            left: area.x,
+                            top: area.y,
+                            front: 0,
+                            right: area.x + area.width,
+                            bottom: area.y + area.height,
+                            back: 1,
+                        }),
+                    );
+                }
+            }
+        }
+
+        self.frame.format = frame.format;
+        self.frame.sub_format = VideoSubFormat::D3D11;
+        self.frame.data = [
+            hardware.copy.get_output().as_raw() as *const _,
+            null(),
+            null(),
+        ];
+
+        self.frame.linesize = [0; 3];
+        Ok(())
+    }
+
+    fn process_software(
+        &mut self,
+        frame: &VideoFrame,
+        areas: &[MaskArea],
+        style: PrivacyMaskStyle,
+    ) -> Result<(), PrivacyMaskerError> {
+        if !self.planes.copy(frame) {
+            return Err(PrivacyMaskerError::NotSupportFormat);
+        }
+
+        // The blocks of the blur are about as large as a line of text.
+        let block = ((frame.height / 36).max(8) + 1) & !1;
+        let (depth, interleaved) = match frame.format {
+            VideoFormat::NV12 => (1, true),
+            VideoFormat::P010 => (2, true),
+            _ => (1, false),
+        };
+
+        // The planes with the number of the components in them, the U and V of NV12
+        // and P010 take turns in one plane. The chroma has half of the pixels.
+        let [luma, u, v] = self.planes.planes_mut();
+        let mut planes = vec![(Plane::new(luma, frame.linesize[0], depth, 1), 0, 1)];
+        if interleaved {
+            planes.push((Plane::new(u, frame.linesize[1], depth, 2), 1, 2));
+        } else {
+            planes.push((Plane::new(u, frame.linesize[1], depth, 1), 1, 1));
+            planes.push((Plane::new(v, frame.linesize[2], depth, 1), 1, 1));
+        }
+
+        for (plane, shift, components) in planes.iter_mut() {
+            for component in 0..*components {
+                plane.offset = component;
+
+                for area in areas {
+                    let area = MaskArea {
+                        x: area.x >> *shift,
+                        y: area.y >> *shift,
+                        width: area.width >> *shift,
+                        height: area.height >> *shift,
+                    };
+
+                    match style {
+                        PrivacyMaskStyle::Black => {
+                            plane.fill(&area, if *shift == 0 { 16 } else { 128 })
+                        }
+                        PrivacyMaskStyle::Blur => plane.pixelate(&area, block >> *shift),
+                    }
+                }
+            }
+        }
+
+        self.planes.output(&mut self.frame);
+        Ok(())
+    }
+}
+
+fn clip_area(area: &MaskArea, width: u32, height: u32) -> Option<MaskArea> {
+    let x = area.x.min(width) & !1;
+    let y = area.y.min(height) & !1;
+    let right = (area.x.saturating_add(area.width).min(width) + 1) & !1;
+    let bottom = (area.y.saturating_add(area.height).min(height) + 1) & !1;
+
+    // The frames of an odd size have no sample for the last odd pixel.
+    let right = right.min(width & !1);
+    let bottom = bottom.min(height & !1);
+
+    if right > x && bottom > y {
+        Some(MaskArea {
+            x,
+            y,
+            width: right - x,
+            height: bottom - y,
+        })
+    } else {
+        None
+    }
+}
+
+// The samples of a component in a plane, they are `step` samples apart, such as
+// the U and V of NV12, which take turns. The samples of 16 bits keep the value
+// in the high bits.
+struct Plane<'a> {
+    data: &'a mut [u8],
+    linesize: usize,
+    depth: usize,
+    step: usize,
+    offset: usize,
+}
+
+impl<'a> Plane<'a> {
+    fn new(data: &'a mut [u8], linesize: usize, depth: usize, step: usize) -> Self {
+        Self {
+            data,
+            linesize,
+            depth,
+            step,
+            offset: 0,
+        }
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        y as usize * self.linesize + (x as usize * self.step + self.offset) * self.depth
+    }
+
+    // The values are always of 8 bits.
+    fn get(&self, x: u32, y: u32) -> u32 {
+        let index = self.index(x, y);
+        match self.data.get(index..index + self.depth) {
+            Some([it]) => *it as u32,
+            Some([low, high]) => u16::from_le_bytes([*low, *high]) as u32 >> 8,
+            _ => 0,
+        }
+    }
+
+    fn set(&mut self, x: u32, y: u32, value: u32) {
+        let index = self.index(x, y);
+        match self.data.get_mut(index..index + self.depth) {
+            Some([it]) => *it = value as u8,
+            Some(it) => it.copy_from_slice(&((value as u16) << 8).to_le_bytes()),
+            _ => (),
+        }
+    }
+
+    fn fill(&mut self, area: &MaskArea, value: u32) {
+        for y in area.y..area.y + area.height {
+            for x in area.x..area.x + area.width {
+                self.set(x, y, value);
+            }
+        }
+    }
+
+    // Each block of the area is filled with its average.
+    fn pixelate(&mut self, area: &MaskArea, block: u32) {
+        let block = block.max(1);
+        let (right, bottom) = (area.x + area.width, area.y + area.height);
+
+        for top in (area.y..bottom).step_by(block as usize) {
+            for left in (area.x..right).step_by(block as usize) {
+                let (width, height) = ((right - left).min(block), (bottom - top).min(block));
+
+                let mut sum = 0;
+                for y in top..top + height {
+                    for x in left..left + width {
+                        sum += self.get(x, y);
+                    }
+                }
+
+                self.fill(
+                    &MaskArea {
+                        x: left,
+                        y: top,
+                        width,
+                        height,
+                    },
+                    sum / (width * height),
+                );
+            }
+        }
+    }
+}
+
+// The video processors of a size and format of the frames, the first one copies
+// the frame that is masked, the other two blur it.
+#[cfg(target_os = "windows")]
+struct HardwareMasker {
+    size: Size,
+    format: VideoFormat,
+    copy: VideoResampler,
+    down: VideoResampler,
+    up: VideoResampler,
+    black: ID3D11Texture2D,
+}
+
+#[cfg(target_os = "windows")]
+impl HardwareMasker {
+    fn new(
+        direct3d: &Direct3DDevice,
+        dxgi_format: DXGI_FORMAT,
+        format: VideoFormat,
+        size: Size,
+    ) -> Result<Self, PrivacyMaskerError> {
+        let small = Size {
+            width: (size.width / PrivacyMasker::BLUR_FACTOR).max(2) & !1,
+            height: (size.height / PrivacyMasker::BLUR_FACTOR).max(2) & !1,
+        };
+
+        let copy = VideoResampler::new(VideoResamplerOptions {
+            direct3d: direct3d.clone(),
+            input: Resource::Default(dxgi_format, size),
+            output: Resource::Default(dxgi_format, size),
+        })?;
+
+        let down = VideoResampler::new(VideoResamplerOptions {
+            direct3d: direct3d.clone(),
+            input: Resource::Default(dxgi_format, size),
+            output: Resource::Default(dxgi_format, small),
+        })?;
+
+        let up = VideoResampler::new(VideoResamplerOptions {
+            direct3d: direct3d.clone(),
+            input: Resource::Texture(down.get_output().clone()),
+            output: Resource::Default(dxgi_format, size),
+        })?;
+
+        // The black of the limited range, the luma is 16 and the chroma is 128, the
+        // UV plane follows the Y plane with the same stride.
+        let (width, height) = (size.width as usize, size.height as usize);
+        let chroma = height / 2 + height % 2;
+        let pixels = if format == VideoFormat::P010 {
+            [16u16 << 8]
+                .repeat(width * height)
+                .into_iter()
+                .chain([128u16 << 8].repeat(width * chroma))
+                .flat_map(|it| it.to_le_bytes())
+                .collect::<Vec<u8>>()
+        } else {
+            let mut pixels = vec![16u8; width * height];
+            pixels.resize(width * (height + chroma), 128);
+            pixels
+        };
+
+        let black = unsafe {
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            desc.Width = size.width;
+            desc.Height = size.height;
+            desc.MipLevels = 1;
+            desc.ArraySize = 1;
+            desc.Format = dxgi_format;
+            desc.SampleDesc.Count = 1;
+            desc.SampleDesc.Quality = 0;
+            desc.Usage = D3D11_USAGE_DEFAULT;
+
+            let data = D3D11_SUBRESOURCE_DATA {
+                pSysMem: pixels.as_ptr() as *const _,
+                SysMemPitch: (pixels.len() / (height + chroma)) as u32,
+                SysMemSlicePitch: 0,
+            };
+
+            let mut texture = None;
+            direct3d
+                .device
+                .CreateTexture2D(&desc, Some(&data), Some(&mut texture))?;
+            texture.unwrap()
+        };
+
+        Ok(Self {
+            size,
+            format,
+            copy,
+            down,
+            up,
+            black,
+        })
+    }
+}
@@ -0,0 +1,329 @@
+use std::{
+    ffi::{c_int, c_void},
+    ptr::{null, null_mut},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Once,
+    },
+};
+
+use hylarana_common::strings::PSTR;
+use mirror_ffmpeg_sys::*;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum StreamMuxerError {
+    #[error("failed to alloc av format context")]
+    AllocAVFormatContextError,
+    #[error("failed to alloc av packet")]
+    AllocAVPacketError,
+    #[error("not found av codec descriptor")]
+    NotFoundAVCodec,
+    #[error("failed to create av stream")]
+    CreateAVStreamError,
+    #[error("the streams cannot be added after the header")]
+    HeaderWritten,
+    #[error("failed to open output, url={0}")]
+    OpenOutputError(String),
+    #[error("failed to write header")]
+    WriteHeaderError,
+    #[error("failed to write packet")]
+    WritePacketError,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StreamMuxerSettings {
+    /// The path or the url of the output, such as `rtmp://` or `udp://`, the
+    /// protocols are the protocols of ffmpeg.
+    pub url: String,
+    /// The name of the container format, such as `flv` or `mpegts`, `None`
+    /// guesses the format from the url.
+    pub format: Option<String>,
+    /// The options of the muxer and of the protocol, such as `rw_timeout`.
+    pub options: Vec<(String, String)>,
+    /// Setting it aborts the blocking operations of the protocol, the muxer
+    /// returns an error from then on.
+    pub interrupt: Option<Arc<AtomicBool>>,
+}
+
+/// A track of the muxer, the codecs are the names of the codec descriptors of
+/// ffmpeg, such as `h264`, `hevc`, `aac` and `opus`.
+#[derive(Debug, Clone, Copy)]
+pub enum MuxerStream<'a> {
+    Video {
+        codec: &'a str,
+        width: u32,
+        height: u32,
+        /// The video configuration can be in the Annex B format, the muxers
+        /// convert it when the container needs it.
+        extradata: &'a [u8],
+    },
+    Audio {
+        codec: &'a str,
+        sample_rate: u32,
+        channels: u16,
+        extradata: &'a [u8],
+    },
+}
+
+static NETWORK: Once = Once::new();
+
+unsafe extern "C" fn interrupt_callback(opaque: *mut c_void) -> c_int {
+    { &*(opaque as *const AtomicBool) }.load(Ordering::Relaxed) as c_int
+}
+
+/// Writes the encoded packets into a container, to a file or to a network
+/// output of ffmpeg. The streams are added before the header is written, the
+/// timestamps of the packets are in microseconds.
+pub struct StreamMuxer {
+    settings: StreamMuxerSettings,
+    context: *mut AVFormatContext,
+    packet: *mut AVPacket,
+    // The last timestamp of each stream, in the time base of the stream, the
+    // muxers do not take timestamps that go backwards.
+    timestamps: Vec<i64>,
+    header: bool,
+}
+
+unsafe impl Sync for StreamMuxer {}
+unsafe impl Send for StreamMuxer {}
+
+impl StreamMuxer {
+    pub fn new(settings: StreamMuxerSettings) -> Result<Self, StreamMuxerError> {
+        NETWORK.call_once(|| unsafe {
+            avformat_network_init();
+        });
+
+        let mut this = Self {
+            context: null_mut(),
+            packet: null_mut(),
+            timestamps: Vec::new(),
+            header: false,
+            settings,
+        };
+
+        let format = this.settings.format.as_deref().map(PSTR::from);
+        let url = PSTR::from(this.settings.url.as_str());
+        if unsafe {
+            avformat_alloc_output_context2(
+                &mut this.context,
+                null(),
+                format.as_ref().map(|it| it.as_ptr()).unwrap_or(null()),
+                url.as_ptr(),
+            )
+        } < 0
+            || this.context.is_null()
+        {
+            return Err(StreamMuxerError::AllocAVFormatContextError);
+        }
+
+        if let Some(interrupt) = this.settings.interrupt.as_ref() {
+            let context_mut = unsafe { &mut *this.context };
+            context_mut.interrupt_callback.callback = Some(interrupt_callback);
+            context_mut.interrupt_callback.opaque = Arc::as_ptr(interrupt) as *mut _;
+        }
+
+        this.packet = unsafe { av_packet_alloc() };
+        if this.packet.is_null() {
+            return Err(StreamMuxerError::AllocAVPacketError);
+        }
+
+        Ok(this)
+    }
+
+    /// Add a stream, returns the index of the stream for `write`.
+    pub fn add_stream(&mut self, stream: MuxerStream) -> Result<usize, StreamMuxerError> {
+        if self.header {
+            return Err(StreamMuxerError::HeaderWritten);
+        }
+
+        let (codec, extradata) = match stream {
+            MuxerStream::Video {
+                codec, extradata, ..
+            }
+            | MuxerStream::Audio {
+                codec, extradata, ..
+            } => (codec, extradata),
+        };
+
+        let descriptor = unsafe { avcodec_descriptor_get_by_name(PSTR::from(codec).as_ptr()) };
+        if descriptor.is_null() {
+            return Err(StreamMuxerError::NotFoundAVCodec);
+        }
+
+        let av_stream = unsafe { avformat_new_stream(self.context, null_mut()) };
+        if av_stream.is_null() {
+            return Err(StreamMuxerError::CreateAVStreamError);
+        }
+
+        let av_stream = unsafe { &mut *av_stream };
+        av_stream.time_base = unsafe { av_make_q(1, 1_000_000) };
+
+        let parameters = unsafe { &mut *av_stream.codecpar };
+        parameters.codec_id = unsafe { &*descriptor }.id;
+
+        match stream {
+            MuxerStream::Video { width, height, .. } => {
+                parameters.codec_type = AVMediaType::AVMEDIA_TYPE_VIDEO;
+                parameters.width = width as i32;
+                parameters.height = height as i32;
+            }
+            MuxerStream::Audio {
+                sample_rate,
+                channels,
+                ..
+            } => {
+                parameters.codec_type = AVMediaType::AVMEDIA_TYPE_AUDIO;
+                parameters.sample_rate = sample_rate as i32;
+
+                unsafe {
+                    av_channel_layout_default(&mut parameters.ch_layout, channels as i32);
+                }
+            }
+        }
+
+        // The extradata is owned by the parameters, it is freed with the context.
+        if !extradata.is_empty() {
+            parameters.extradata =
+                unsafe { av_mallocz(extradata.len() + AV_INPUT_BUFFER_PADDING_SIZE as usize) }
+                    as *mut u8;
+
+            if parameters.extradata.is_null() {
+                return Err(StreamMuxerError::CreateAVStreamError);
+            }
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(
+                    extradata.as_ptr(),
+                    parameters.extradata,
+                    extradata.len(),
+                );
+            }
+
+            parameters.extradata_size = extradata.len() as i32;
+        }
+
+        self.timestamps.push(i64::MIN);
+        Ok(av_stream.index as usize)
+    }
+
+    /// Open the output and write the header, for the network outputs this
+    /// connects to the server.
+    pub fn write_header(&mut self) -> Result<(), StreamMuxerError> {
+        let mut options = null_mut();
+        for (key, value) in &self.settings.options {
+            unsafe {
+                av_dict_set(
+                    &mut options,
+                    PSTR::from(key.as_str()).as_ptr(),
+                    PSTR::from(value.as_str()).as_ptr(),
+                    0,
+                );
+            }
+        }
+
+        let result = self.open(&mut options);
+        unsafe {
+            av_dict_free(&mut options);
+        }
+
+        result
+    }
+
+    fn open(&mut self, options: &mut *mut AVDictionary) -> Result<(), StreamMuxerError> {
+        let context_mut = unsafe { &mut *self.context };
+        if { unsafe { &*context_mut.oformat } }.flags & AVFMT_NOFILE as i32 == 0 {
+            if unsafe {
+                avio_open2(
+                    &mut context_mut.pb,
+                    PSTR::from(self.settings.url.as_str()).as_ptr(),
+                    AVIO_FLAG_WRITE as i32,
+                    &context_mut.interrupt_callback,
+                    options,
+                )
+            } < 0
+            {
+                return Err(StreamMuxerError::OpenOutputError(self.settings.url.clone()));
+            }
+        }
+
+        if unsafe { avformat_write_header(self.context, options) } < 0 {
+            return Err(StreamMuxerError::WriteHeaderError);
+        }
+
+        self.header = true;
+        Ok(())
+    }
+
+    /// Write a packet of a stream, the timestamp is in microseconds.
+    pub fn write(
+        &mut self,
+        stream: usize,
+        buf: &[u8],
+        key_frame: bool,
+        timestamp: u64,
+    ) -> Result<(), StreamMuxerError> {
+        if !self.header || stream >= self.timestamps.len() {
+            return Err(StreamMuxerError::WritePacketError);
+        }
+
+        if unsafe { av_new_packet(self.packet, buf.len() as c_int) } != 0 {
+            return Err(StreamMuxerError::AllocAVPacketError);
+        }
+
+        let packet_mut = unsafe { &mut *self.packet };
+        unsafe {
+            std::ptr::copy_nonoverlapping(buf.as_ptr(), packet_mut.data, buf.len());
+        }
+
+        // The time base of the stream is chosen by the muxer in the header.
+        let time_base = unsafe { &**{ &*self.context }.streams.add(stream) }.time_base;
+        let pts = unsafe { av_rescale_q(timestamp as i64, av_make_q(1, 1_000_000), time_base) }
+            .max(self.timestamps[stream].saturating_add(1));
+
+        self.timestamps[stream] = pts;
+        packet_mut.stream_index = stream as c_int;
+        packet_mut.pts = pts;
+        packet_mut.dts = pts;
+
+        if key_frame {
+            packet_mut.flags |= AV_PKT_FLAG_KEY as c_int;
+        }
+
+        // The packet is taken by the muxer, it is blank again after this.
+        if unsafe { av_interleaved_write_frame(self.context, self.packet) } != 0 {
+            return Err(StreamMuxerError::WritePacketError);
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for StreamMuxer {
+    fn drop(&mut self) {
+        if !self.context.is_null() {
+            let context_mut = unsafe { &mut *self.context };
+            if self.header {
+                unsafe {
+                    av_write_trailer(self.context);
+                }
+            }
+
+            if { unsafe { &*context_mut.oformat } }.flags & AVFMT_NOFILE as i32 == 0 {
+                unsafe {
+                    avio_closep(&mut context_mut.pb);
+                }
+            }
+
+            unsafe {
+                avformat_free_context(self.context);
+            }
+        }
+
+        if !self.packet.is_null() {
+            unsafe {
+                av_packet_free(&mut self.packet);
+            }
+        }
+    }
+}
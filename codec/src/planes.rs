@@ -0,0 +1,70 @@
+use std::ptr::null;
+
+use hylarana_common::frame::{VideoFormat, VideoFrame, VideoSubFormat};
+
+// The planes of a software frame of 4:2:0 copied with their strides into one
+// buffer, so that they can be drawn on without changing the frame of the
+// source, which may still be used by the others, such as the preview.
+#[derive(Default)]
+pub(crate) struct PlaneBuffer {
+    buffer: Vec<u8>,
+    sizes: [usize; 3],
+    linesize: [usize; 3],
+    format: Option<VideoFormat>,
+}
+
+impl PlaneBuffer {
+    // Returns false if the format is not 4:2:0.
+    pub(crate) fn copy(&mut self, frame: &VideoFrame) -> bool {
+        // The chroma planes of 4:2:0 have half of the rows.
+        let chroma = frame.height as usize / 2 + frame.height as usize % 2;
+        let rows = match frame.format {
+            VideoFormat::NV12 | VideoFormat::P010 => [frame.height as usize, chroma, 0],
+            VideoFormat::I420 => [frame.height as usize, chroma, chroma],
+            _ => return false,
+        };
+
+        self.sizes = [0, 1, 2].map(|i| frame.linesize[i] * rows[i]);
+        self.linesize = frame.linesize;
+        self.format = Some(frame.format);
+
+        self.buffer.clear();
+        for i in 0..3 {
+            if self.sizes[i] > 0 {
+                self.buffer.extend_from_slice(unsafe {
+                    std::slice::from_raw_parts(frame.data[i] as *const u8, self.sizes[i])
+                });
+            }
+        }
+
+        true
+    }
+
+    pub(crate) fn planes_mut(&mut self) -> [&mut [u8]; 3] {
+        let (first, rest) = self.buffer.split_at_mut(self.sizes[0]);
+        let (second, third) = rest.split_at_mut(self.sizes[1]);
+        [first, second, third]
+    }
+
+    // Points the frame at the planes of the buffer, the frame is valid until the
+    // next copy.
+    pub(crate) fn output(&self, frame: &mut VideoFrame) {
+        let mut offset = 0;
+        for i in 0..3 {
+            frame.data[i] = if self.sizes[i] > 0 {
+                unsafe { self.buffer.as_ptr().add(offset) as *const _ }
+            } else {
+                null()
+            };
+
+            frame.linesize[i] = self.linesize[i];
+            offset += self.sizes[i];
+        }
+
+        if let Some(format) = self.format {
+            frame.format = format;
+        }
+
+        frame.sub_format = VideoSubFormat::SW;
+    }
+}
@@ -0,0 +1,124 @@
+use crate::{
+    codec::{CodecType, VideoDecoderType, VideoEncoderType},
+    video::{VideoDecoder, VideoDecoderSettings, VideoEncoder, VideoEncoderSettings},
+};
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::Direct3DDevice;
+
+/// Whether a codec can be used on this machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecStatus {
+    /// The codec can be used.
+    Available,
+    /// The codec is not supported on this platform.
+    Unsupported,
+    /// The codec is not built into the linked ffmpeg.
+    NotFound,
+    /// The codec is present but a session could not be opened, such as when
+    /// the driver of the hardware is missing.
+    SessionFailed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecCapability<T> {
+    pub codec: T,
+    pub status: CodecStatus,
+}
+
+/// The report of `probe`, all encoders and decoders are listed, including the
+/// ones that are not available.
+#[derive(Debug, Clone)]
+pub struct CodecCapabilities {
+    pub encoders: Vec<CodecCapability<VideoEncoderType>>,
+    pub decoders: Vec<CodecCapability<VideoDecoderType>>,
+}
+
+impl CodecCapabilities {
+    pub fn is_encoder_available(&self, codec: VideoEncoderType) -> bool {
+        self.encoders
+            .iter()
+            .any(|it| it.codec == codec && it.status == CodecStatus::Available)
+    }
+
+    pub fn is_decoder_available(&self, codec: VideoDecoderType) -> bool {
+        self.decoders
+            .iter()
+            .any(|it| it.codec == codec && it.status == CodecStatus::Available)
+    }
+}
+
+const ENCODERS: [VideoEncoderType; 5] = [
+    VideoEncoderType::X264,
+    VideoEncoderType::Qsv,
+    VideoEncoderType::VideoToolBox,
+    VideoEncoderType::X265,
+    VideoEncoderType::HevcQsv,
+];
+
+const DECODERS: [VideoDecoderType; 6] = [
+    VideoDecoderType::H264,
+    VideoDecoderType::D3D11,
+    VideoDecoderType::Qsv,
+    VideoDecoderType::VideoToolBox,
+    VideoDecoderType::HevcD3D11,
+    VideoDecoderType::HevcQsv,
+];
+
+/// Check which encoders and decoders can actually be used on this machine,
+/// each codec that is present is opened with a small session, so this takes
+/// some time and the result should be cached by the caller.
+pub fn probe(#[cfg(target_os = "windows")] direct3d: Direct3DDevice) -> CodecCapabilities {
+    let status = |kind: CodecType, open: &dyn Fn() -> bool| {
+        if !kind.is_supported() {
+            CodecStatus::Unsupported
+        } else if unsafe { kind.find_av_codec() }.is_null() {
+            CodecStatus::NotFound
+        } else if open() {
+            CodecStatus::Available
+        } else {
+            CodecStatus::SessionFailed
+        }
+    };
+
+    let encoders = ENCODERS
+        .iter()
+        .map(|codec| CodecCapability {
+            codec: *codec,
+            status: status(CodecType::from(*codec), &|| {
+                VideoEncoder::new(VideoEncoderSettings {
+                    codec: *codec,
+                    frame_rate: 30,
+                    width: 640,
+                    height: 360,
+                    bit_rate: 500 * 1024,
+                    key_frame_interval: 30,
+                    tuning: Default::default(),
+                    scene_cut: false,
+                    #[cfg(target_os = "windows")]
+                    direct3d: Some(direct3d.clone()),
+                })
+                .map_err(|e| log::info!("probe video encoder={:?}, error={:?}", codec, e))
+                .is_ok()
+            }),
+        })
+        .collect();
+
+    let decoders = DECODERS
+        .iter()
+        .map(|codec| CodecCapability {
+            codec: *codec,
+            status: status(CodecType::from(*codec), &|| {
+                VideoDecoder::new(VideoDecoderSettings {
+                    codec: *codec,
+                    #[cfg(target_os = "windows")]
+                    direct3d: Some(direct3d.clone()),
+                })
+                .map_err(|e| log::info!("probe video decoder={:?}, error={:?}", codec, e))
+                .is_ok()
+            }),
+        })
+        .collect();
+
+    CodecCapabilities { encoders, decoders }
+}
@@ -0,0 +1,557 @@
+use std::{
+    ffi::{c_int, c_void},
+    ptr::{null, null_mut},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Once,
+    },
+};
+
+use hylarana_common::{
+    frame::{AudioFrame, VideoFormat, VideoFrame, VideoSubFormat},
+    strings::PSTR,
+};
+
+use mirror_ffmpeg_sys::*;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MediaReaderError {
+    #[error("failed to open input, url={0}")]
+    OpenInputError(String),
+    #[error("not found input stream")]
+    NotFoundInputStream,
+    #[error("not found av codec")]
+    NotFoundAVCodec,
+    #[error("failed to alloc av context")]
+    AllocAVContextError,
+    #[error("failed to open av codec")]
+    OpenAVCodecError,
+    #[error("failed to alloc av packet")]
+    AllocAVPacketError,
+    #[error("failed to alloc av frame")]
+    AllocAVFrameError,
+    #[error("failed to read packet")]
+    ReadPacketError,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MediaReaderSettings {
+    /// The path or the url of the input, such as `udp://` or `rtp://`, the
+    /// protocols are the protocols of ffmpeg.
+    pub url: String,
+    /// The name of the container format, such as `mpegts`, `None` probes the
+    /// format of the input.
+    pub format: Option<String>,
+    /// The options of the demuxer and of the protocol, such as `timeout`.
+    pub options: Vec<(String, String)>,
+    /// Setting it aborts the blocking operations of the protocol, the reader
+    /// returns an error from then on.
+    pub interrupt: Option<Arc<AtomicBool>>,
+}
+
+/// A decoded frame of a `MediaReader`, the timestamps are the timestamps of
+/// the input in microseconds. The frame can be changed before it is passed
+/// on, such as to stamp it with another clock.
+pub enum MediaFrame<'a> {
+    Video(&'a mut VideoFrame),
+    Audio(&'a mut AudioFrame),
+}
+
+// `AVERROR_EOF` is a macro that is not in the bindings.
+const AVERROR_EOF: c_int = -0x20464F45;
+
+static NETWORK: Once = Once::new();
+
+unsafe extern "C" fn interrupt_callback(opaque: *mut c_void) -> c_int {
+    { &*(opaque as *const AtomicBool) }.load(Ordering::Relaxed) as c_int
+}
+
+struct Track {
+    index: i32,
+    context: *mut AVCodecContext,
+}
+
+impl Track {
+    fn new(stream: &AVStream) -> Result<Self, MediaReaderError> {
+        let codec = unsafe { avcodec_find_decoder({ &*stream.codecpar }.codec_id) };
+        if codec.is_null() {
+            return Err(MediaReaderError::NotFoundAVCodec);
+        }
+
+        let this = Self {
+            index: stream.index,
+            context: unsafe { avcodec_alloc_context3(codec) },
+        };
+
+        if this.context.is_null() {
+            return Err(MediaReaderError::AllocAVContextError);
+        }
+
+        if unsafe { avcodec_parameters_to_context(this.context, stream.codecpar) } < 0 {
+            return Err(MediaReaderError::OpenAVCodecError);
+        }
+
+        let context_mut = unsafe { &mut *this.context };
+        context_mut.pkt_timebase = stream.time_base;
+        context_mut.flags |= AV_CODEC_FLAG_LOW_DELAY as i32;
+
+        if unsafe { avcodec_open2(this.context, codec, null_mut()) } != 0 {
+            return Err(MediaReaderError::OpenAVCodecError);
+        }
+
+        Ok(this)
+    }
+
+    fn timestamp(&self, frame: &AVFrame) -> u64 {
+        unsafe {
+            av_rescale_q(
+                frame.best_effort_timestamp,
+                { &*self.context }.pkt_timebase,
+                av_make_q(1, 1_000_000),
+            )
+        }
+        .max(0) as u64
+    }
+}
+
+impl Drop for Track {
+    fn drop(&mut self) {
+        if !self.context.is_null() {
+            unsafe {
+                avcodec_free_context(&mut self.context);
+            }
+        }
+    }
+}
+
+/// Reads a media input of ffmpeg, such as an MPEG-TS stream over UDP, and
+/// decodes its first video and audio streams.
+///
+/// The video frames are software frames, the frames that are not in a format
+/// of the frames are converted to I420. The audio frames are converted to
+/// mono at the sample rate that is set with `set_audio_sample_rate`, the
+/// sample rate of the input by default.
+pub struct MediaReader {
+    context: *mut AVFormatContext,
+    packet: *mut AVPacket,
+    av_frame: *mut AVFrame,
+    video: Option<Track>,
+    audio: Option<Track>,
+    // The track that the last packet was sent to, it may have more frames.
+    pending: Option<i32>,
+    sws: *mut SwsContext,
+    // The planes of the converted video frames.
+    picture: ([*mut u8; 4], [c_int; 4], (i32, i32)),
+    video_frame: VideoFrame,
+    swr: *mut SwrContext,
+    swr_input: (i32, i32),
+    sample_rate: Option<u32>,
+    samples: Vec<i16>,
+    audio_frame: AudioFrame,
+}
+
+unsafe impl Sync for MediaReader {}
+unsafe impl Send for MediaReader {}
+
+impl MediaReader {
+    pub fn new(settings: MediaReaderSettings) -> Result<Self, MediaReaderError> {
+        NETWORK.call_once(|| unsafe {
+            avformat_network_init();
+        });
+
+        let mut this = Self {
+            context: unsafe { avformat_alloc_context() },
+            packet: null_mut(),
+            av_frame: null_mut(),
+            video: None,
+            audio: None,
+            pending: None,
+            sws: null_mut(),
+            picture: ([null_mut(); 4], [0; 4], (0, 0)),
+            video_frame: VideoFrame::default(),
+            swr: null_mut(),
+            swr_input: (0, 0),
+            sample_rate: None,
+            samples: Vec::new(),
+            audio_frame: AudioFrame::default(),
+        };
+
+        if this.context.is_null() {
+            return Err(MediaReaderError::OpenInputError(settings.url));
+        }
+
+        if let Some(interrupt) = settings.interrupt.as_ref() {
+            let context_mut = unsafe { &mut *this.context };
+            context_mut.interrupt_callback.callback = Some(interrupt_callback);
+            context_mut.interrupt_callback.opaque = Arc::as_ptr(interrupt) as *mut _;
+        }
+
+        let format = match settings.format.as_deref() {
+            Some(format) => {
+                let format = unsafe { av_find_input_format(PSTR::from(format).as_ptr()) };
+                if format.is_null() {
+                    return Err(MediaReaderError::OpenInputError(settings.url));
+                }
+
+                format
+            }
+            None => null(),
+        };
+
+        let mut options = null_mut();
+        for (key, value) in &settings.options {
+            unsafe {
+                av_dict_set(
+                    &mut options,
+                    PSTR::from(key.as_str()).as_ptr(),
+                    PSTR::from(value.as_str()).as_ptr(),
+                    0,
+                );
+            }
+        }
+
+        // The context is freed by ffmpeg when the input fails to open.
+        let result = unsafe {
+            avformat_open_input(
+                &mut this.context,
+                PSTR::from(settings.url.as_str()).as_ptr(),
+                format,
+                &mut options,
+            )
+        };
+
+        unsafe {
+            av_dict_free(&mut options);
+        }
+
+        if result != 0 {
+            return Err(MediaReaderError::OpenInputError(settings.url));
+        }
+
+        if unsafe { avformat_find_stream_info(this.context, null_mut()) } < 0 {
+            return Err(MediaReaderError::NotFoundInputStream);
+        }
+
+        for kind in [
+            AVMediaType::AVMEDIA_TYPE_VIDEO,
+            AVMediaType::AVMEDIA_TYPE_AUDIO,
+        ] {
+            let index = unsafe { av_find_best_stream(this.context, kind, -1, -1, null_mut(), 0) };
+            if index < 0 {
+                continue;
+            }
+
+            let stream = unsafe { &**{ &*this.context }.streams.add(index as usize) };
+            let track = Track::new(stream)?;
+            if kind == AVMediaType::AVMEDIA_TYPE_VIDEO {
+                this.video = Some(track);
+            } else {
+                this.audio = Some(track);
+            }
+        }
+
+        if this.video.is_none() && this.audio.is_none() {
+            return Err(MediaReaderError::NotFoundInputStream);
+        }
+
+        this.packet = unsafe { av_packet_alloc() };
+        if this.packet.is_null() {
+            return Err(MediaReaderError::AllocAVPacketError);
+        }
+
+        this.av_frame = unsafe { av_frame_alloc() };
+        if this.av_frame.is_null() {
+            return Err(MediaReaderError::AllocAVFrameError);
+        }
+
+        Ok(this)
+    }
+
+    pub fn has_video(&self) -> bool {
+        self.video.is_some()
+    }
+
+    pub fn has_audio(&self) -> bool {
+        self.audio.is_some()
+    }
+
+    /// Set the sample rate of the output audio frames, `None` keeps the
+    /// sample rate of the input.
+    pub fn set_audio_sample_rate(&mut self, sample_rate: Option<u32>) {
+        if self.sample_rate != sample_rate {
+            self.sample_rate = sample_rate;
+            self.swr_input = (0, 0);
+        }
+    }
+
+    /// Read the next frame, `None` is the end of the input.
+    pub fn read(&mut self) -> Result<Option<MediaFrame>, MediaReaderError> {
+        loop {
+            if let Some(index) = self.pending {
+                let track = [self.video.as_ref(), self.audio.as_ref()]
+                    .into_iter()
+                    .flatten()
+                    .find(|it| it.index == index)
+                    .map(|it| it.context);
+
+                if let Some(context) = track {
+                    if unsafe { avcodec_receive_frame(context, self.av_frame) } == 0 {
+                        // The frames that cannot be converted are skipped.
+                        if self.video.as_ref().map(|it| it.index) == Some(index) {
+                            if self.convert_video() {
+                                return Ok(Some(MediaFrame::Video(&mut self.video_frame)));
+                            }
+                        } else if self.convert_audio() {
+                            return Ok(Some(MediaFrame::Audio(&mut self.audio_frame)));
+                        }
+
+                        continue;
+                    }
+                }
+
+                self.pending = None;
+            }
+
+            unsafe {
+                av_packet_unref(self.packet);
+            }
+
+            match unsafe { av_read_frame(self.context, self.packet) } {
+                0 => (),
+                AVERROR_EOF => return Ok(None),
+                _ => return Err(MediaReaderError::ReadPacketError),
+            }
+
+            let index = unsafe { &*self.packet }.stream_index;
+            for track in [self.video.as_ref(), self.audio.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                // A broken packet only affects its own frames, the stream goes on.
+                if track.index == index
+                    && unsafe { avcodec_send_packet(track.context, self.packet) } == 0
+                {
+                    self.pending = Some(index);
+                }
+            }
+        }
+    }
+
+    fn convert_video(&mut self) -> bool {
+        let Some(track) = self.video.as_ref() else {
+            return false;
+        };
+        let av_frame = unsafe { &*self.av_frame };
+        let format = match unsafe { std::mem::transmute::<i32, AVPixelFormat>(av_frame.format) } {
+            AVPixelFormat::AV_PIX_FMT_YUV420P | AVPixelFormat::AV_PIX_FMT_YUVJ420P => {
+                Some(VideoFormat::I420)
+            }
+            AVPixelFormat::AV_PIX_FMT_NV12 => Some(VideoFormat::NV12),
+            AVPixelFormat::AV_PIX_FMT_P010LE => Some(VideoFormat::P010),
+            _ => None,
+        };
+
+        let frame = &mut self.video_frame;
+        frame.sub_format = VideoSubFormat::SW;
+        frame.width = av_frame.width as u32;
+        frame.height = av_frame.height as u32;
+        frame.timestamp = track.timestamp(av_frame);
+
+        if let Some(format) = format {
+            frame.format = format;
+            for i in 0..3 {
+                frame.data[i] = av_frame.data[i] as *const _;
+                frame.linesize[i] = av_frame.linesize[i].max(0) as usize;
+            }
+
+            return true;
+        }
+
+        // The other formats are converted to I420 with the size of the frame.
+        let size = (av_frame.width, av_frame.height);
+        self.sws = unsafe {
+            sws_getCachedContext(
+                self.sws,
+                av_frame.width,
+                av_frame.height,
+                std::mem::transmute(av_frame.format),
+                av_frame.width,
+                av_frame.height,
+                AVPixelFormat::AV_PIX_FMT_YUV420P,
+                SWS_BILINEAR,
+                null_mut(),
+                null_mut(),
+                null(),
+            )
+        };
+
+        if self.sws.is_null() {
+            log::error!("failed to create sws context for the media reader");
+
+            return false;
+        }
+
+        let (planes, linesize, allocated) = &mut self.picture;
+        if *allocated != size {
+            unsafe {
+                av_freep(planes.as_mut_ptr() as *mut c_void);
+            }
+
+            if unsafe {
+                av_image_alloc(
+                    planes.as_mut_ptr(),
+                    linesize.as_mut_ptr(),
+                    size.0,
+                    size.1,
+                    AVPixelFormat::AV_PIX_FMT_YUV420P,
+                    32,
+                )
+            } < 0
+            {
+                *allocated = (0, 0);
+                return false;
+            }
+
+            *allocated = size;
+        }
+
+        unsafe {
+            sws_scale(
+                self.sws,
+                av_frame.data.as_ptr() as _,
+                av_frame.linesize.as_ptr(),
+                0,
+                av_frame.height,
+                planes.as_mut_ptr(),
+                linesize.as_mut_ptr(),
+            );
+        }
+
+        frame.format = VideoFormat::I420;
+        for i in 0..3 {
+            frame.data[i] = planes[i] as *const _;
+            frame.linesize[i] = linesize[i] as usize;
+        }
+
+        true
+    }
+
+    fn convert_audio(&mut self) -> bool {
+        let Some(track) = self.audio.as_ref() else {
+            return false;
+        };
+        let av_frame = unsafe { &*self.av_frame };
+        let sample_rate = self.sample_rate.unwrap_or(av_frame.sample_rate as u32);
+
+        // The resampler is created again when the input changes.
+        let input = (av_frame.format, av_frame.sample_rate);
+        if self.swr.is_null() || self.swr_input != input {
+            unsafe {
+                swr_free(&mut self.swr);
+            }
+
+            let mut layout = unsafe { std::mem::zeroed::<AVChannelLayout>() };
+            unsafe {
+                av_channel_layout_default(&mut layout, 1);
+            }
+
+            if unsafe {
+                swr_alloc_set_opts2(
+                    &mut self.swr,
+                    &layout,
+                    AVSampleFormat::AV_SAMPLE_FMT_S16,
+                    sample_rate as i32,
+                    &av_frame.ch_layout,
+                    std::mem::transmute(av_frame.format),
+                    av_frame.sample_rate,
+                    0,
+                    null_mut(),
+                )
+            } < 0
+                || unsafe { swr_init(self.swr) } < 0
+            {
+                log::error!("failed to create swr context for the media reader");
+
+                unsafe {
+                    swr_free(&mut self.swr);
+                }
+
+                return false;
+            }
+
+            self.swr_input = input;
+        }
+
+        let capacity = unsafe { swr_get_out_samples(self.swr, av_frame.nb_samples) };
+        if capacity <= 0 {
+            return false;
+        }
+
+        self.samples.resize(capacity as usize, 0);
+        let mut output = self.samples.as_mut_ptr() as *mut u8;
+        let count = unsafe {
+            swr_convert(
+                self.swr,
+                &mut output,
+                capacity,
+                av_frame.extended_data as *const *const u8,
+                av_frame.nb_samples,
+            )
+        };
+
+        if count <= 0 {
+            return false;
+        }
+
+        self.audio_frame.sample_rate = sample_rate;
+        self.audio_frame.frames = count as u32;
+        self.audio_frame.data = self.samples.as_ptr();
+        self.audio_frame.timestamp = track.timestamp(av_frame);
+
+        true
+    }
+}
+
+impl Drop for MediaReader {
+    fn drop(&mut self) {
+        self.video = None;
+        self.audio = None;
+
+        if !self.context.is_null() {
+            unsafe {
+                avformat_close_input(&mut self.context);
+            }
+        }
+
+        if !self.packet.is_null() {
+            unsafe {
+                av_packet_free(&mut self.packet);
+            }
+        }
+
+        if !self.av_frame.is_null() {
+            unsafe {
+                av_frame_free(&mut self.av_frame);
+            }
+        }
+
+        if !self.sws.is_null() {
+            unsafe {
+                sws_freeContext(self.sws);
+            }
+        }
+
+        if !self.picture.0[0].is_null() {
+            unsafe {
+                av_freep(self.picture.0.as_mut_ptr() as *mut c_void);
+            }
+        }
+
+        if !self.swr.is_null() {
+            unsafe {
+                swr_free(&mut self.swr);
+            }
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use std::ptr::{null, null_mut};
+
+use hylarana_common::{
+    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    Size,
+};
+
+use mirror_ffmpeg_sys::*;
+use thiserror::Error;
+
+use crate::video::ContentHint;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::{
+    d3d_texture_borrowed_raw,
+    windows::{
+        core::Interface,
+        Win32::{
+            Foundation::RECT,
+            Graphics::Dxgi::Common::{DXGI_FORMAT_NV12, DXGI_FORMAT_P010},
+        },
+    },
+    Direct3DDevice, EasyTexture, PooledTexture, TexturePool,
+};
+
+#[cfg(target_os = "windows")]
+use hylarana_resample::win32::{Resource, VideoResampler, VideoResamplerOptions};
+
+#[derive(Debug, Clone)]
+pub struct VideoScalerSettings {
+    /// The size of the picture of the output frames, the frames are padded to
+    /// an even size for the 4:2:0 formats, see `VideoFrame::display_size`.
+    pub size: Size,
+    /// The format of the output software frames, the hardware frames keep the
+    /// format of the input.
+    pub format: VideoFormat,
+    /// Whether the encoder takes hardware frames, if it does not, such as
+    /// after falling back to a software encoder, the hardware frames are
+    /// copied to the memory.
+    pub hardware: bool,
+    /// With `ContentHint::Detail`, the software frames are scaled with a
+    /// sharper filter and the chroma is interpolated at full resolution, so
+    /// that the colored edges of small text do not bleed.
+    pub content_hint: Option<ContentHint>,
+    #[cfg(target_os = "windows")]
+    pub direct3d: Direct3DDevice,
+}
+
+#[derive(Error, Debug)]
+pub enum VideoScalerError {
+    #[error("unsupported video frame format")]
+    NotSupportFormat,
+    #[error("failed to create sws context")]
+    CreateSwsContextError,
+    #[error("failed to alloc av frame")]
+    AllocAVFrameError,
+    #[cfg(target_os = "windows")]
+    #[error(transparent)]
+    WindowsError(#[from] hylarana_common::win32::windows::core::Error),
+}
+
+/// Scales the captured frames to the size of the encoder, so that the source
+/// can be captured at a different resolution than the one that is encoded,
+/// such as capturing 4K and encoding 1080p.
+///
+/// Direct3D11 textures are scaled on the GPU with the video processor, the
+/// software frames are scaled with swscale, which also converts them to the
+/// format of the encoder.
+///
+/// Only the visible area of the input frames is scaled, and the picture is
+/// placed in the top left corner of the output frames, the padding to the
+/// even size is black.
+pub struct VideoScaler {
+    settings: VideoScalerSettings,
+    // The size of the output frames, with the padding.
+    coded_size: Size,
+    software: Option<SoftwareScaler>,
+    #[cfg(target_os = "windows")]
+    hardware: Option<(VideoResampler, TexturePool, Size, VideoFormat)>,
+    // The texture of the output frame, it goes back to the pool when the next
+    // frame is processed.
+    #[cfg(target_os = "windows")]
+    texture: Option<PooledTexture>,
+    // The hardware frames copied to the memory.
+    #[cfg(target_os = "windows")]
+    buffer: Vec<u8>,
+    frame: VideoFrame,
+}
+
+unsafe impl Sync for VideoScaler {}
+unsafe impl Send for VideoScaler {}
+
+impl VideoScaler {
+    // The encoders read the textures of the frames asynchronously, QSV keeps up to
+    // four frames in flight.
+    #[cfg(target_os = "windows")]
+    const TEXTURE_POOL_SIZE: usize = 6;
+
+    pub fn new(settings: VideoScalerSettings) -> Self {
+        let coded_size = settings.size.align(2);
+
+        let mut frame = VideoFrame::default();
+        frame.width = coded_size.width;
+        frame.height = coded_size.height;
+        frame.display_width = settings.size.width;
+        frame.display_height = settings.size.height;
+
+        Self {
+            #[cfg(target_os = "windows")]
+            hardware: None,
+            #[cfg(target_os = "windows")]
+            texture: None,
+            #[cfg(target_os = "windows")]
+            buffer: Vec::new(),
+            software: None,
+            coded_size,
+            settings,
+            frame,
+        }
+    }
+
+    /// Whether the frame needs to be passed through the scaler before it is
+    /// given to the encoder.
+    pub fn is_required(&self, frame: &VideoFrame) -> bool {
+        frame.width != self.coded_size.width
+            || frame.height != self.coded_size.height
+            || frame.display_size() != self.settings.size
+            || (frame.sub_format == VideoSubFormat::SW && frame.format != self.settings.format)
+            || (frame.sub_format == VideoSubFormat::D3D11 && !self.settings.hardware)
+    }
+
+    pub fn process(&mut self, frame: &VideoFrame) -> Result<&VideoFrame, VideoScalerError> {
+        match frame.sub_format {
+            #[cfg(target_os = "windows")]
+            VideoSubFormat::D3D11 => self.process_d3d11(frame)?,
+            VideoSubFormat::SW => self.process_software(frame)?,
+            #[allow(unreachable_patterns)]
+            _ => return Err(VideoScalerError::NotSupportFormat),
+        }
+
+        self.frame.rotation = frame.rotation;
+        self.frame.mirror = frame.mirror;
+        self.frame.timestamp = frame.timestamp;
+        Ok(&self.frame)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn process_d3d11(&mut self, frame: &VideoFrame) -> Result<(), VideoScalerError> {
+        let format = match frame.format {
+            VideoFormat::NV12 => DXGI_FORMAT_NV12,
+            VideoFormat::P010 => DXGI_FORMAT_P010,
+            _ => return Err(VideoScalerError::NotSupportFormat),
+        };
+
+        let size = Size {
+            width: frame.width,
+            height: frame.height,
+        };
+
+        // The video processor is created for the size of the input, the source may
+        // change its size, such as when the resolution of the display changes.
+        if self
+            .hardware
+            .as_ref()
+            .map(|(_, _, it, format)| *it != size || *format != frame.format)
+            .unwrap_or(true)
+        {
+            let mut resampler = VideoResampler::new(VideoResamplerOptions {
+                direct3d: self.settings.direct3d.clone(),
+                input: Resource::Default(format, size),
+                output: Resource::Default(format, self.coded_size),
+            })?;
+
+            // The padding of the output is filled with the background color.
+            if self.settings.size != self.coded_size {
+                resampler.set_output_rect(RECT {
+                    left: 0,
+                    top: 0,
+                    right: self.settings.size.width as i32,
+                    bottom: self.settings.size.height as i32,
+                });
+            }
+
+            // The textures of the pool have the same description as the output of the
+            // video processor.
+            let pool = TexturePool::new(
+                self.settings.direct3d.clone(),
+                resampler.get_output().desc(),
+                Self::TEXTURE_POOL_SIZE,
+            );
+
+            self.hardware = Some((resampler, pool, size, frame.format));
+
+            log::info!(
+                "video scaler create d3d11 processor, input={}x{}, output={}x{}",
+                size.width,
+                size.height,
+                self.settings.size.width,
+                self.settings.size.height
+            );
+        }
+
+        let (resampler, pool, _, _) = self.hardware.as_mut().unwrap();
+
+        // The padding of the input is cropped.
+        let display_size = frame.display_size();
+        resampler.set_input_rect(RECT {
+            left: 0,
+            top: 0,
+            right: display_size.width as i32,
+            bottom: display_size.height as i32,
+        });
+
+        // The texture of the frame is borrowed, it must not be released here.
+        let texture = d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
+            .ok_or_else(|| VideoScalerError::NotSupportFormat)?;
+
+        let view = resampler.create_input_view(texture, frame.data[1] as u32)?;
+
+        self.frame.format = frame.format;
+
+        if self.settings.hardware {
+            // The texture of the previous frame goes back to the pool, the encoder may
+            // still be reading it, which the pool waits for.
+            let texture = self
+                .texture
+                .insert(resampler.process_into(Some(view), pool)?);
+
+            self.frame.sub_format = VideoSubFormat::D3D11;
+            self.frame.data[0] = texture.texture().as_raw();
+            self.frame.data[1] = null();
+        } else {
+            resampler.process(Some(view))?;
+
+            // The texture is mapped only while the buffer is alive, so the planes are
+            // copied out, the UV plane follows the Y plane with the same stride.
+            let texture = resampler.get_output_buffer()?;
+            let stride = texture.stride();
+            let size = stride * self.coded_size.height as usize;
+
+            self.buffer.clear();
+            self.buffer.extend_from_slice(unsafe {
+                std::slice::from_raw_parts(texture.buffer(), size + size / 2)
+            });
+
+            self.frame.sub_format = VideoSubFormat::SW;
+            self.frame.data[0] = self.buffer.as_ptr() as *const _;
+            self.frame.data[1] = unsafe { self.buffer.as_ptr().add(size) } as *const _;
+            self.frame.linesize = [stride, stride, 0];
+        }
+
+        Ok(())
+    }
+
+    fn process_software(&mut self, frame: &VideoFrame) -> Result<(), VideoScalerError> {
+        let input = get_pixel_format(frame.format);
+        let size = frame.display_size();
+
+        if self
+            .software
+            .as_ref()
+            .map(|it| it.size != size || it.format != input)
+            .unwrap_or(true)
+        {
+            self.software = Some(SoftwareScaler::new(
+                size,
+                input,
+                self.settings.size,
+                self.coded_size,
+                get_pixel_format(self.settings.format),
+                if self.settings.content_hint == Some(ContentHint::Detail) {
+                    SWS_LANCZOS | SWS_FULL_CHR_H_INT | SWS_ACCURATE_RND
+                } else {
+                    SWS_BILINEAR
+                },
+            )?);
+
+            log::info!(
+                "video scaler create sws context, input={}x{}, output={}x{}",
+                size.width,
+                size.height,
+                self.settings.size.width,
+                self.settings.size.height
+            );
+        }
+
+        let scaled = self.software.as_mut().unwrap().scale(frame);
+        for i in 0..3 {
+            self.frame.data[i] = scaled.data[i] as *const _;
+            self.frame.linesize[i] = scaled.linesize[i] as usize;
+        }
+
+        self.frame.format = self.settings.format;
+        self.frame.sub_format = VideoSubFormat::SW;
+        Ok(())
+    }
+}
+
+pub(crate) fn get_pixel_format(format: VideoFormat) -> AVPixelFormat {
+    match format {
+        VideoFormat::BGRA => AVPixelFormat::AV_PIX_FMT_BGRA,
+        VideoFormat::RGBA => AVPixelFormat::AV_PIX_FMT_RGBA,
+        VideoFormat::NV12 => AVPixelFormat::AV_PIX_FMT_NV12,
+        VideoFormat::I420 => AVPixelFormat::AV_PIX_FMT_YUV420P,
+        VideoFormat::P010 => AVPixelFormat::AV_PIX_FMT_P010LE,
+    }
+}
+
+struct SoftwareScaler {
+    context: *mut SwsContext,
+    frame: *mut AVFrame,
+    size: Size,
+    format: AVPixelFormat,
+}
+
+impl SoftwareScaler {
+    fn new(
+        size: Size,
+        format: AVPixelFormat,
+        output_size: Size,
+        coded_size: Size,
+        output_format: AVPixelFormat,
+        flags: i32,
+    ) -> Result<Self, VideoScalerError> {
+        let mut this = Self {
+            frame: unsafe { av_frame_alloc() },
+            context: null_mut(),
+            format,
+            size,
+        };
+
+        if this.frame.is_null() {
+            return Err(VideoScalerError::AllocAVFrameError);
+        }
+
+        // The buffers of the output frame are owned by the frame, and are released
+        // together with it. The picture is only scaled into the top left corner, the
+        // padding stays black.
+        unsafe {
+            let frame_mut = &mut *this.frame;
+            frame_mut.format = output_format as i32;
+            frame_mut.width = coded_size.width as i32;
+            frame_mut.height = coded_size.height as i32;
+
+            if av_frame_get_buffer(this.frame, 32) != 0 {
+                return Err(VideoScalerError::AllocAVFrameError);
+            }
+
+            let linesize = frame_mut.linesize.map(|it| it as isize);
+            av_image_fill_black(
+                frame_mut.data.as_mut_ptr(),
+                linesize.as_ptr() as _,
+                output_format,
+                AVColorRange::AVCOL_RANGE_MPEG,
+                frame_mut.width,
+                frame_mut.height,
+            );
+        }
+
+        this.context = unsafe {
+            sws_getContext(
+                size.width as i32,
+                size.height as i32,
+                format,
+                output_size.width as i32,
+                output_size.height as i32,
+                output_format,
+                flags,
+                null_mut(),
+                null_mut(),
+                null(),
+            )
+        };
+
+        if this.context.is_null() {
+            return Err(VideoScalerError::CreateSwsContextError);
+        }
+
+        Ok(this)
+    }
+
+    fn scale(&mut self, frame: &VideoFrame) -> &AVFrame {
+        let linesize = [
+            frame.linesize[0] as i32,
+            frame.linesize[1] as i32,
+            frame.linesize[2] as i32,
+            0,
+        ];
+
+        let data = [
+            frame.data[0] as *const u8,
+            frame.data[1] as *const u8,
+            frame.data[2] as *const u8,
+            null(),
+        ];
+
+        unsafe {
+            let frame_mut = &mut *self.frame;
+            sws_scale(
+                self.context,
+                data.as_ptr() as _,
+                linesize.as_ptr(),
+                0,
+                self.size.height as i32,
+                frame_mut.data.as_mut_ptr(),
+                frame_mut.linesize.as_mut_ptr(),
+            );
+        }
+
+        unsafe { &*self.frame }
+    }
+}
+
+impl Drop for SoftwareScaler {
+    fn drop(&mut self) {
+        if !self.frame.is_null() {
+            unsafe {
+                av_frame_free(&mut self.frame);
+            }
+        }
+
+        if !self.context.is_null() {
+            unsafe {
+                sws_freeContext(self.context);
+            }
+        }
+    }
+}
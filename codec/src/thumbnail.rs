@@ -0,0 +1,231 @@
+use crate::scale::{VideoScaler, VideoScalerError, VideoScalerSettings};
+
+use std::ptr::null_mut;
+
+use hylarana_common::{
+    frame::{VideoFormat, VideoFrame},
+    strings::PSTR,
+    Size,
+};
+
+use mirror_ffmpeg_sys::*;
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::Direct3DDevice;
+
+#[derive(Debug, Clone)]
+pub struct ThumbnailEncoderSettings {
+    /// The width of the thumbnails, the height follows the aspect ratio of the
+    /// frames. The frames that are narrower are not scaled up.
+    pub width: u32,
+    /// The quality of the JPEG images, from 1 to 100.
+    pub quality: u8,
+    #[cfg(target_os = "windows")]
+    pub direct3d: Direct3DDevice,
+}
+
+#[derive(Error, Debug)]
+pub enum ThumbnailEncoderError {
+    #[error("not found jpeg av codec")]
+    NotFoundAVCodec,
+    #[error("failed to alloc av context")]
+    AllocAVContextError,
+    #[error("failed to open av codec")]
+    OpenAVCodecError,
+    #[error("failed to alloc av packet")]
+    AllocAVPacketError,
+    #[error("failed to alloc av frame")]
+    AllocAVFrameError,
+    #[error("send frame to codec failed")]
+    EncodeFrameError,
+    #[error(transparent)]
+    VideoScalerError(#[from] VideoScalerError),
+}
+
+// The codec and the scalers are created for the size of the source, and again
+// when the size of the source changes.
+struct JpegContext {
+    context: *mut AVCodecContext,
+    source: Size,
+    size: Size,
+    scaler: VideoScaler,
+    // The textures are copied out of the GPU as NV12, they are converted again.
+    converter: VideoScaler,
+}
+
+impl Drop for JpegContext {
+    fn drop(&mut self) {
+        if !self.context.is_null() {
+            unsafe {
+                avcodec_free_context(&mut self.context);
+            }
+        }
+    }
+}
+
+/// Encodes the frames into small JPEG images, such as for the previews of the
+/// streams in a list. Unlike the video encoder, every image stands on its own.
+pub struct ThumbnailEncoder {
+    settings: ThumbnailEncoderSettings,
+    jpeg: Option<JpegContext>,
+    packet: *mut AVPacket,
+    frame: *mut AVFrame,
+}
+
+unsafe impl Sync for ThumbnailEncoder {}
+unsafe impl Send for ThumbnailEncoder {}
+
+impl ThumbnailEncoder {
+    pub fn new(settings: ThumbnailEncoderSettings) -> Result<Self, ThumbnailEncoderError> {
+        let mut this = Self {
+            packet: null_mut(),
+            frame: null_mut(),
+            jpeg: None,
+            settings,
+        };
+
+        this.packet = unsafe { av_packet_alloc() };
+        if this.packet.is_null() {
+            return Err(ThumbnailEncoderError::AllocAVPacketError);
+        }
+
+        this.frame = unsafe { av_frame_alloc() };
+        if this.frame.is_null() {
+            return Err(ThumbnailEncoderError::AllocAVFrameError);
+        }
+
+        Ok(this)
+    }
+
+    /// Encode a frame, returns the JPEG image.
+    pub fn encode(&mut self, frame: &VideoFrame) -> Result<&[u8], ThumbnailEncoderError> {
+        // The padding of the frame is not part of the picture.
+        let source = frame.display_size();
+
+        if self
+            .jpeg
+            .as_ref()
+            .map(|it| it.source != source)
+            .unwrap_or(true)
+        {
+            self.jpeg = Some(self.create_context(source)?);
+        }
+
+        let jpeg = self.jpeg.as_mut().unwrap();
+        let scaled = jpeg.scaler.process(frame)?;
+        let scaled = if jpeg.converter.is_required(scaled) {
+            jpeg.converter.process(scaled)?
+        } else {
+            scaled
+        };
+
+        // The planes of the scaler are not reference counted, the codec copies them
+        // when the frame is sent.
+        let context_ref = unsafe { &*jpeg.context };
+        let av_frame = unsafe { &mut *self.frame };
+        av_frame.format = context_ref.pix_fmt as i32;
+        av_frame.width = jpeg.size.width as i32;
+        av_frame.height = jpeg.size.height as i32;
+        av_frame.quality = context_ref.global_quality;
+        av_frame.pts = 0;
+
+        for i in 0..3 {
+            av_frame.data[i] = scaled.data[i] as *mut _;
+            av_frame.linesize[i] = scaled.linesize[i] as i32;
+        }
+
+        if unsafe { avcodec_send_frame(jpeg.context, self.frame) } != 0 {
+            return Err(ThumbnailEncoderError::EncodeFrameError);
+        }
+
+        if unsafe { avcodec_receive_packet(jpeg.context, self.packet) } != 0 {
+            return Err(ThumbnailEncoderError::EncodeFrameError);
+        }
+
+        let packet_ref = unsafe { &*self.packet };
+        Ok(unsafe { std::slice::from_raw_parts(packet_ref.data, packet_ref.size as usize) })
+    }
+
+    fn create_context(&self, source: Size) -> Result<JpegContext, ThumbnailEncoderError> {
+        // The chroma of 4:2:0 needs an even size.
+        let width = self.settings.width.min(source.width).max(2) & !1;
+        let size = Size {
+            height: ((source.height as u64 * width as u64 / source.width.max(1) as u64) as u32)
+                .max(2)
+                & !1,
+            width,
+        };
+
+        let scaler = |format| {
+            VideoScaler::new(VideoScalerSettings {
+                format,
+                hardware: false,
+                content_hint: None,
+                #[cfg(target_os = "windows")]
+                direct3d: self.settings.direct3d.clone(),
+                size,
+            })
+        };
+
+        let mut jpeg = JpegContext {
+            context: null_mut(),
+            scaler: scaler(VideoFormat::I420),
+            converter: scaler(VideoFormat::I420),
+            source,
+            size,
+        };
+
+        let codec = unsafe { avcodec_find_encoder_by_name(PSTR::from("mjpeg").as_ptr()) };
+        if codec.is_null() {
+            return Err(ThumbnailEncoderError::NotFoundAVCodec);
+        }
+
+        jpeg.context = unsafe { avcodec_alloc_context3(codec) };
+        if jpeg.context.is_null() {
+            return Err(ThumbnailEncoderError::AllocAVContextError);
+        }
+
+        // The quality maps to the quantizer of the encoder, which is 2 for the best
+        // quality and 31 for the worst.
+        let qscale = 31 - (self.settings.quality.clamp(1, 100) as i32 - 1) * 29 / 99;
+
+        let context_mut = unsafe { &mut *jpeg.context };
+        context_mut.width = size.width as i32;
+        context_mut.height = size.height as i32;
+        context_mut.pix_fmt = AVPixelFormat::AV_PIX_FMT_YUVJ420P;
+        context_mut.time_base = unsafe { av_make_q(1, 1) };
+        context_mut.flags |= AV_CODEC_FLAG_QSCALE as i32;
+        context_mut.global_quality = FF_QP2LAMBDA as i32 * qscale;
+
+        if unsafe { avcodec_open2(jpeg.context, codec, null_mut()) } != 0 {
+            return Err(ThumbnailEncoderError::OpenAVCodecError);
+        }
+
+        log::info!(
+            "thumbnail encoder create jpeg context, input={}x{}, output={}x{}",
+            source.width,
+            source.height,
+            size.width,
+            size.height
+        );
+
+        Ok(jpeg)
+    }
+}
+
+impl Drop for ThumbnailEncoder {
+    fn drop(&mut self) {
+        if !self.packet.is_null() {
+            unsafe {
+                av_packet_free(&mut self.packet);
+            }
+        }
+
+        if !self.frame.is_null() {
+            unsafe {
+                av_frame_free(&mut self.frame);
+            }
+        }
+    }
+}
@@ -1,17 +1,23 @@
-use crate::codec::{
-    create_video_context, create_video_frame, set_option, set_str_option, CodecError, CodecType,
-    CreateVideoContextError, CreateVideoFrameError, VideoDecoderType, VideoEncoderType,
+use crate::{
+    codec::{
+        create_video_context, create_video_frame, set_option, set_str_option, CodecError,
+        CodecType, CreateVideoContextError, CreateVideoFrameError, VideoDecoderType,
+        VideoEncoderType,
+    },
+    MaskArea,
 };
 
-use std::{ffi::c_int, ptr::null_mut};
+use std::{ffi::c_int, ops::Deref, ptr::null_mut};
+
+use hylarana_common::{
+    frame::{VideoFormat, VideoFrame, VideoRotation, VideoSubFormat},
+    Size,
+};
 
-use hylarana_common::frame::{VideoFormat, VideoFrame, VideoSubFormat};
 use mirror_ffmpeg_sys::*;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-#[cfg(any(target_os = "windows", target_os = "macos"))]
-use hylarana_common::Size;
-
 #[cfg(target_os = "windows")]
 use hylarana_common::win32::Direct3DDevice;
 
@@ -49,6 +55,11 @@ pub enum VideoDecoderError {
     AllocAVFrameError,
 }
 
+/// The number of the frames that may be taken from a decoder with
+/// `VideoDecoder::take` and held at the same time, the hardware decoders keep
+/// as many extra surfaces for them.
+pub const MAX_TAKEN_FRAMES: usize = 3;
+
 pub struct VideoDecoder {
     context: *mut AVCodecContext,
     parser: *mut AVCodecParserContext,
@@ -96,13 +107,19 @@ impl VideoDecoder {
         context_mut.flags |= AV_CODEC_FLAG_LOW_DELAY as i32;
         context_mut.flags2 |= AV_CODEC_FLAG2_FAST as i32;
         context_mut.hwaccel_flags |= AV_HWACCEL_FLAG_IGNORE_LEVEL as i32;
+        context_mut.extra_hw_frames = MAX_TAKEN_FRAMES as i32;
+
+        // With intra refresh the stream may start from a recovery point instead of an
+        // IDR frame, the decoder only outputs the frames after the picture has
+        // recovered instead of the partially refreshed ones.
+        context_mut.flags &= !(AV_CODEC_FLAG_OUTPUT_CORRUPT as i32);
 
         #[cfg(target_os = "windows")]
         {
             context_mut.hwaccel_flags |= AV_HWACCEL_FLAG_UNSAFE_OUTPUT as i32;
         }
 
-        if options.codec == VideoDecoderType::Qsv {
+        if CodecType::from(options.codec).is_qsv() {
             set_option(context_mut, "async_depth", 1);
         }
 
@@ -170,6 +187,13 @@ impl VideoDecoder {
         Ok(())
     }
 
+    /// The orientation is not part of the bitstream, it is carried by the
+    /// transport, and all frames read after this call use this orientation.
+    pub fn set_orientation(&mut self, rotation: VideoRotation, mirror: bool) {
+        self.frame.rotation = rotation;
+        self.frame.mirror = mirror;
+    }
+
     pub fn read<'a>(&'a mut self) -> Option<&'a VideoFrame> {
         // When decoding, each video frame uses a newly created one.
         if !self.av_frame.is_null() {
@@ -187,10 +211,16 @@ impl VideoDecoder {
             return None;
         }
 
+        // The size of the picture, the textures of the hardware decoders may be larger,
+        // such as 1088 rows for 1080p, the renderer only draws the picture.
         let frame = unsafe { &*self.av_frame };
         self.frame.width = frame.width as u32;
         self.frame.height = frame.height as u32;
 
+        // The timestamp of the packet is the capture time on the sender, it is zero if
+        // the decoder cannot determine it.
+        self.frame.timestamp = frame.best_effort_timestamp.max(0) as u64;
+
         let format = unsafe { std::mem::transmute::<_, AVPixelFormat>(frame.format) };
         match format {
             // mfxFrameSurface1.Data.MemId contains a pointer to the mfxHDLPair structure
@@ -214,7 +244,7 @@ impl VideoDecoder {
                 self.frame.data[1] = hdl.second;
 
                 self.frame.sub_format = VideoSubFormat::D3D11;
-                self.frame.format = VideoFormat::NV12;
+                self.frame.format = get_hardware_frame_format(frame);
             }
             // The d3d11va video frame texture has no stride.
             #[cfg(target_os = "windows")]
@@ -224,7 +254,16 @@ impl VideoDecoder {
                 }
 
                 self.frame.sub_format = VideoSubFormat::D3D11;
-                self.frame.format = VideoFormat::NV12;
+                self.frame.format = get_hardware_frame_format(frame);
+            }
+            AVPixelFormat::AV_PIX_FMT_P010LE => {
+                for i in 0..2 {
+                    self.frame.data[i] = frame.data[i] as *const _;
+                    self.frame.linesize[i] = frame.linesize[i] as usize;
+                }
+
+                self.frame.sub_format = VideoSubFormat::SW;
+                self.frame.format = VideoFormat::P010;
             }
             AVPixelFormat::AV_PIX_FMT_YUV420P => {
                 for i in 0..3 {
@@ -240,13 +279,69 @@ impl VideoDecoder {
                 self.frame.data[0] = frame.data[3] as _;
 
                 self.frame.sub_format = VideoSubFormat::CvPixelBufferRef;
-                self.frame.format = VideoFormat::BGRA;
+                self.frame.format = get_hardware_frame_format(frame);
             }
             _ => unimplemented!("unsupported video frame format = {:?}", format),
         };
 
         Some(&self.frame)
     }
+
+    /// Like `read`, but the frame is taken out of the decoder, so that it can
+    /// be passed to another thread and kept while the next frames are decoded.
+    /// Holding more than `MAX_TAKEN_FRAMES` of them may stall the hardware
+    /// decoders.
+    pub fn take(&mut self) -> Option<DecodedVideoFrame> {
+        self.read()?;
+
+        Some(DecodedVideoFrame {
+            av_frame: std::mem::replace(&mut self.av_frame, null_mut()),
+            frame: VideoFrame { ..self.frame },
+        })
+    }
+}
+
+/// A frame taken from the decoder, see `VideoDecoder::take`, the buffers or
+/// the surface of the frame are released when it is dropped.
+pub struct DecodedVideoFrame {
+    av_frame: *mut AVFrame,
+    frame: VideoFrame,
+}
+
+unsafe impl Sync for DecodedVideoFrame {}
+unsafe impl Send for DecodedVideoFrame {}
+
+impl Deref for DecodedVideoFrame {
+    type Target = VideoFrame;
+
+    fn deref(&self) -> &Self::Target {
+        &self.frame
+    }
+}
+
+impl Drop for DecodedVideoFrame {
+    fn drop(&mut self) {
+        if !self.av_frame.is_null() {
+            unsafe {
+                av_frame_free(&mut self.av_frame);
+            }
+        }
+    }
+}
+
+// The hardware frame itself does not describe the texture format, the format of
+// the texture is the software format of the hardware frame context, which is
+// P010 for HEVC Main10 and NV12 for everything else.
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+fn get_hardware_frame_format(frame: &AVFrame) -> VideoFormat {
+    if !frame.hw_frames_ctx.is_null() {
+        let frames_ctx = unsafe { &*((&*frame.hw_frames_ctx).data as *const AVHWFramesContext) };
+        if frames_ctx.sw_format == AVPixelFormat::AV_PIX_FMT_P010LE {
+            return VideoFormat::P010;
+        }
+    }
+
+    VideoFormat::NV12
 }
 
 impl Drop for VideoDecoder {
@@ -290,6 +385,92 @@ impl Drop for VideoDecoder {
     }
 }
 
+/// The profile of the H264 encoders, the HEVC encoders always use Main10.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoProfile {
+    Baseline,
+    Main,
+    High,
+}
+
+/// How the encoder controls the bit rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RateControl {
+    /// Constant bit rate, the stream is kept at the bit rate, which is required
+    /// by some hardware decoders.
+    Cbr,
+    /// Variable bit rate, the average is the bit rate and the peaks are limited
+    /// to the maximum bit rate.
+    Vbr { max_bit_rate: u64 },
+    /// Constant quantizer, the bit rate is ignored, a lower value has better
+    /// quality.
+    Cqp { qp: u8 },
+    /// The picture is reproduced exactly, for the content that must not be
+    /// changed by the compression, such as design reviews. The bit rate is
+    /// ignored and the frames are many times larger. x264 and x265 are
+    /// lossless apart from the 4:2:0 chroma, the hardware encoders only use
+    /// the lowest quantizer. Lossless H264 uses the High 4:4:4 Predictive
+    /// profile, which the hardware decoders usually cannot decode.
+    Lossless,
+}
+
+/// The kind of the content that is encoded, the encoders are tuned for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ContentHint {
+    /// Camera and video content, the motion is kept smooth.
+    Motion,
+    /// Screen content such as text, spreadsheets and code, the edges and the
+    /// colors of small text are kept sharp.
+    Detail,
+}
+
+/// The advanced parameters of the video encoder, the default keeps the low
+/// latency settings that are used when nothing is specified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct VideoEncoderTuning {
+    /// `None` uses the baseline profile.
+    pub profile: Option<VideoProfile>,
+    /// The level multiplied by 10, such as `41` for level 4.1, `None` lets the
+    /// encoder choose.
+    pub level: Option<u8>,
+    /// `None` caps the bit rate without strictly enforcing it.
+    pub rate_control: Option<RateControl>,
+    /// The number of consecutive B-frames, B-frames add latency, so this is
+    /// usually only useful when `low_latency` is off.
+    pub b_frames: u8,
+    /// The number of slices per frame, `0` lets the encoder choose.
+    pub slices: u8,
+    /// Tune the encoder for the lowest latency, such as no lookahead and no
+    /// frame buffering.
+    pub low_latency: bool,
+    /// Refresh the picture gradually with a column of intra blocks that moves
+    /// across the frames of the key frame interval, instead of sending an IDR
+    /// frame at each interval, which avoids the spikes of the key frames on
+    /// constrained links. The first frame of each refresh cycle is a recovery
+    /// point, the receivers that join or lose packets resume from it. This is
+    /// not supported by VideoToolbox.
+    pub intra_refresh: bool,
+    /// `None` uses the default tuning of the encoder. x264 and QSV have tunings
+    /// for the content, the other encoders ignore it.
+    pub content_hint: Option<ContentHint>,
+}
+
+impl Default for VideoEncoderTuning {
+    fn default() -> Self {
+        Self {
+            profile: None,
+            level: None,
+            rate_control: None,
+            b_frames: 0,
+            slices: 0,
+            low_latency: true,
+            intra_refresh: false,
+            content_hint: None,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct VideoEncoderSettings {
     /// Name of the codec implementation.
@@ -299,7 +480,8 @@ pub struct VideoEncoderSettings {
     /// to find a codec from the user perspective.
     pub codec: VideoEncoderType,
     pub frame_rate: u8,
-    /// picture width / height
+    /// picture width / height, any size is accepted, the frames are coded at
+    /// the size aligned to 2, see `VideoEncoder::coded_size`.
     pub width: u32,
     /// picture width / height
     pub height: u32,
@@ -307,6 +489,11 @@ pub struct VideoEncoderSettings {
     pub bit_rate: u64,
     /// the number of pictures in a group of pictures, or 0 for intra_only
     pub key_frame_interval: u32,
+    pub tuning: VideoEncoderTuning,
+    /// The caller requests the key frames at the scene changes, the encoders
+    /// that detect the scene changes themselves do not insert key frames for
+    /// them.
+    pub scene_cut: bool,
     #[cfg(target_os = "windows")]
     pub direct3d: Option<Direct3DDevice>,
 }
@@ -332,12 +519,45 @@ pub struct VideoEncoder {
     packet: *mut AVPacket,
     frame: *mut AVFrame,
     initialized: bool,
+    // The next frame is encoded as an IDR frame.
+    key_frame: bool,
+    intra_refresh: bool,
+    // The length of the intra refresh cycle and the number of the frames that
+    // have been read, the encoders other than x264 do not mark the recovery
+    // points as key frames.
+    recovery_points: Option<(u64, u64)>,
+    // The areas of the next frame that have changed, see `set_damage`.
+    damage: Option<Vec<MaskArea>>,
+    // The number of the frames that have been sent since the last key frame was
+    // read.
+    since_key_frame: i64,
+    // The capture timestamp of the updated frame, in microseconds.
+    timestamp: u64,
+    pts: i64,
 }
 
 unsafe impl Sync for VideoEncoder {}
 unsafe impl Send for VideoEncoder {}
 
 impl VideoEncoder {
+    // The encoders limit the number of the regions, QSV takes up to 256.
+    const MAX_REGIONS: usize = 256;
+
+    // The chroma of the 4:2:0 formats is subsampled by 2 in both directions.
+    const ALIGNMENT: u32 = 2;
+
+    /// The size that the frames of the settings are coded at, the frames given
+    /// to the encoder have this size, with the picture in the top left corner
+    /// and the rest padded. The hardware encoders align it further internally,
+    /// and crop it in the bitstream.
+    pub fn coded_size(options: &VideoEncoderSettings) -> Size {
+        Size {
+            width: options.width,
+            height: options.height,
+        }
+        .align(Self::ALIGNMENT)
+    }
+
     pub fn new(options: VideoEncoderSettings) -> Result<Self, VideoEncoderError> {
         if !CodecType::from(options.codec).is_supported() {
             return Err(VideoEncoderError::CodecError(CodecError::NotSupportCodec));
@@ -348,16 +568,22 @@ impl VideoEncoder {
             packet: null_mut(),
             frame: null_mut(),
             initialized: false,
+            key_frame: false,
+            intra_refresh: false,
+            recovery_points: None,
+            damage: None,
+            since_key_frame: 0,
+            timestamp: 0,
+            pts: -1,
         };
 
+        let size = Self::coded_size(&options);
+
         #[cfg(target_os = "windows")]
         let codec = create_video_context(
             &mut this.context,
             CodecType::from(options.codec),
-            Some(Size {
-                width: options.width,
-                height: options.height,
-            }),
+            Some(size),
             options.direct3d,
         )?;
 
@@ -368,36 +594,67 @@ impl VideoEncoder {
         let codec = create_video_context(
             &mut this.context,
             CodecType::from(options.codec),
-            Some(Size {
-                width: options.width,
-                height: options.height,
-            }),
+            Some(size),
         )?;
 
+        let tuning = options.tuning;
         let context_mut = unsafe { &mut *this.context };
-        context_mut.delay = 0;
         context_mut.max_samples = 1;
-        context_mut.has_b_frames = 0;
-        context_mut.max_b_frames = 0;
+        context_mut.max_b_frames = tuning.b_frames as i32;
+        context_mut.has_b_frames = (tuning.b_frames > 0) as i32;
         context_mut.flags2 |= AV_CODEC_FLAG2_FAST as i32;
-        context_mut.flags |= AV_CODEC_FLAG_LOW_DELAY as i32 | AV_CODEC_FLAG_GLOBAL_HEADER as i32;
-        context_mut.profile = FF_PROFILE_H264_BASELINE as i32;
+        context_mut.flags |= AV_CODEC_FLAG_GLOBAL_HEADER as i32;
+
+        if tuning.low_latency {
+            context_mut.delay = 0;
+            context_mut.flags |= AV_CODEC_FLAG_LOW_DELAY as i32;
+        }
+
+        if tuning.slices > 0 {
+            context_mut.slices = tuning.slices as i32;
+        }
+
+        if let Some(level) = tuning.level {
+            context_mut.level = level as i32;
+        }
+
+        // HEVC is only used for HDR10, which requires the Main10 profile and the
+        // BT.2020 color description with the PQ transfer function, so that the
+        // receiver can restore the original brightness.
+        let kind = CodecType::from(options.codec);
+        if kind.is_10bit() {
+            context_mut.profile = FF_PROFILE_HEVC_MAIN_10 as i32;
+            context_mut.color_primaries = AVColorPrimaries::AVCOL_PRI_BT2020;
+            context_mut.color_trc = AVColorTransferCharacteristic::AVCOL_TRC_SMPTE2084;
+            context_mut.colorspace = AVColorSpace::AVCOL_SPC_BT2020_NCL;
+            context_mut.color_range = AVColorRange::AVCOL_RANGE_MPEG;
+        } else if tuning.rate_control == Some(RateControl::Lossless) {
+            // The lossless coding of x264 is only in the High 4:4:4 Predictive
+            // profile, which x264 chooses by itself.
+            context_mut.profile = FF_PROFILE_UNKNOWN;
+        } else {
+            context_mut.profile = match tuning.profile.unwrap_or(VideoProfile::Baseline) {
+                VideoProfile::Baseline => FF_PROFILE_H264_BASELINE,
+                VideoProfile::Main => FF_PROFILE_H264_MAIN,
+                VideoProfile::High => FF_PROFILE_H264_HIGH,
+            } as i32;
+        }
 
         // The QSV encoder can only use qsv frames. Although the internal structure is a
         // platform-specific hardware texture, you cannot directly tell qsv a specific
         // format.
-        if options.codec == VideoEncoderType::Qsv {
+        if kind.is_qsv() {
             context_mut.pix_fmt = AVPixelFormat::AV_PIX_FMT_QSV;
         } else {
             context_mut.thread_count = 4;
             context_mut.thread_type = FF_THREAD_SLICE as i32;
-            context_mut.pix_fmt = AVPixelFormat::AV_PIX_FMT_NV12;
+            context_mut.pix_fmt = kind.sw_format();
         }
 
         // The bitrate of qsv is always too high, so if it is qsv, using half of the
         // current base bitrate is enough.
         let mut bit_rate = options.bit_rate as i64;
-        if options.codec == VideoEncoderType::Qsv {
+        if kind.is_qsv() {
             bit_rate = bit_rate / 2;
         }
 
@@ -406,30 +663,180 @@ impl VideoEncoder {
         context_mut.rc_buffer_size = bit_rate as i32;
         context_mut.bit_rate_tolerance = bit_rate as i32;
         context_mut.rc_initial_buffer_occupancy = (bit_rate * 3 / 4) as i32;
+
+        match tuning.rate_control {
+            // The encoders of ffmpeg use constant bit rate when the minimum and maximum
+            // rates are the same as the bit rate.
+            Some(RateControl::Cbr) => {
+                context_mut.rc_min_rate = bit_rate;
+            }
+            Some(RateControl::Vbr { max_bit_rate }) => {
+                let mut max_bit_rate = (max_bit_rate as i64).max(bit_rate);
+                if kind.is_qsv() {
+                    max_bit_rate = max_bit_rate / 2;
+                }
+
+                context_mut.rc_max_rate = max_bit_rate;
+                context_mut.rc_buffer_size = max_bit_rate as i32;
+            }
+            Some(RateControl::Cqp { .. }) | Some(RateControl::Lossless) => {
+                let qp = match tuning.rate_control {
+                    Some(RateControl::Cqp { qp }) => qp,
+                    _ => 0,
+                };
+
+                context_mut.bit_rate = 0;
+                context_mut.rc_max_rate = 0;
+                context_mut.rc_buffer_size = 0;
+                context_mut.flags |= AV_CODEC_FLAG_QSCALE as i32;
+                context_mut.global_quality = qp as i32 * FF_QP2LAMBDA as i32;
+            }
+            None => (),
+        }
         context_mut.framerate = unsafe { av_make_q(options.frame_rate as i32, 1) };
         context_mut.time_base = unsafe { av_make_q(1, options.frame_rate as i32) };
         context_mut.pkt_timebase = unsafe { av_make_q(1, options.frame_rate as i32) };
         context_mut.gop_size = options.key_frame_interval as i32 / 2;
-        context_mut.height = options.height as i32;
-        context_mut.width = options.width as i32;
+
+        // With intra refresh, the key frame interval is the length of the refresh
+        // cycle, only the first frame is an IDR frame.
+        if tuning.intra_refresh && options.codec != VideoEncoderType::VideoToolBox {
+            context_mut.gop_size = options.key_frame_interval as i32;
+            this.intra_refresh = true;
+
+            if options.codec != VideoEncoderType::X264 {
+                this.recovery_points = Some((options.key_frame_interval.max(1) as u64, 0));
+            }
+        }
+        context_mut.height = size.height as i32;
+        context_mut.width = size.width as i32;
 
         match options.codec {
             VideoEncoderType::X264 => {
                 set_str_option(context_mut, "preset", "superfast");
-                set_str_option(context_mut, "tune", "zerolatency");
-                set_option(context_mut, "nal-hrd", 2);
                 set_option(
                     context_mut,
                     "sc_threshold",
-                    options.key_frame_interval as i64,
+                    if options.scene_cut {
+                        0
+                    } else {
+                        options.key_frame_interval as i64
+                    },
                 );
+
+                // Without this, the forced intra frames are not IDR frames.
+                set_option(context_mut, "forced-idr", 1);
+
+                // x264 marks the frames with the recovery point SEI as key frames.
+                if tuning.intra_refresh {
+                    set_option(context_mut, "intra-refresh", 1);
+                }
+
+                // x264 takes one psychovisual tune, which can be combined with
+                // zerolatency.
+                let tune = [
+                    match tuning.content_hint {
+                        Some(ContentHint::Motion) => Some("film"),
+                        Some(ContentHint::Detail) => Some("stillimage"),
+                        None => None,
+                    },
+                    tuning.low_latency.then_some("zerolatency"),
+                ]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>()
+                .join(",");
+
+                if !tune.is_empty() {
+                    set_str_option(context_mut, "tune", &tune);
+                }
+
+                // The HRD signalling of x264, 1 is vbr and 2 is cbr.
+                match tuning.rate_control {
+                    Some(RateControl::Cqp { qp }) => set_option(context_mut, "qp", qp as i64),
+                    Some(RateControl::Lossless) => set_option(context_mut, "qp", 0),
+                    Some(RateControl::Vbr { .. }) => set_option(context_mut, "nal-hrd", 1),
+                    _ => set_option(context_mut, "nal-hrd", 2),
+                }
             }
-            VideoEncoderType::Qsv => {
-                set_option(context_mut, "async_depth", 1);
+            VideoEncoderType::X265 => {
+                set_str_option(context_mut, "preset", "superfast");
+                set_option(context_mut, "forced-idr", 1);
+
+                if tuning.low_latency {
+                    set_str_option(context_mut, "tune", "zerolatency");
+                }
+
+                let mut params = "hdr10=1:hdr10-opt=1:repeat-headers=1".to_string();
+                match tuning.rate_control {
+                    Some(RateControl::Cqp { qp }) => params.push_str(&format!(":qp={}", qp)),
+                    Some(RateControl::Lossless) => params.push_str(":lossless=1"),
+                    _ => (),
+                }
+
+                if tuning.intra_refresh {
+                    params.push_str(":intra-refresh=1");
+                }
+
+                if options.scene_cut {
+                    params.push_str(":scenecut=0");
+                }
+
+                set_str_option(context_mut, "x265-params", &params);
+            }
+            VideoEncoderType::Qsv | VideoEncoderType::HevcQsv => {
                 set_option(context_mut, "low_power", 1);
-                set_option(context_mut, "vcm", 1);
+                set_option(context_mut, "forced_idr", 1);
+
+                if tuning.low_latency {
+                    set_option(context_mut, "async_depth", 1);
+                }
+
+                // The scenario lets the driver choose the coding tools for the content.
+                match tuning.content_hint {
+                    Some(ContentHint::Motion) => {
+                        set_str_option(context_mut, "scenario", "gamestreaming")
+                    }
+                    Some(ContentHint::Detail) => {
+                        set_str_option(context_mut, "scenario", "displayremoting")
+                    }
+                    None => (),
+                }
+
+                // The video conferencing mode is a rate control of its own, it is only
+                // used when no rate control is specified.
+                if options.codec == VideoEncoderType::Qsv && tuning.rate_control.is_none() {
+                    set_option(context_mut, "vcm", 1);
+                }
+
+                // The vertical refresh, the cycle is the key frame interval, and the
+                // decoders need the recovery point SEI to resume from the cycle.
+                if tuning.intra_refresh {
+                    set_option(context_mut, "int_ref_type", 1);
+                    set_option(
+                        context_mut,
+                        "int_ref_cycle_size",
+                        options.key_frame_interval as i64,
+                    );
+
+                    if options.codec == VideoEncoderType::Qsv {
+                        set_option(context_mut, "recovery_point_sei", 1);
+                    }
+                }
+            }
+            VideoEncoderType::VideoToolBox => {
+                if tuning.intra_refresh {
+                    log::warn!("videotoolbox does not support intra refresh, ignore it");
+                }
+
+                if tuning.low_latency {
+                    set_option(context_mut, "realtime", 1);
+                }
+
+                if tuning.rate_control == Some(RateControl::Cbr) {
+                    set_option(context_mut, "constant_bit_rate", 1);
+                }
             }
-            VideoEncoderType::VideoToolBox => {}
         };
 
         if unsafe { avcodec_open2(this.context, codec, null_mut()) } != 0 {
@@ -452,7 +859,36 @@ impl VideoEncoder {
         Ok(this)
     }
 
+    /// Create the encoder, if it cannot be created, such as the driver of the
+    /// hardware encoder missing, the encoders of the fallback chain are tried
+    /// in order. Returns the encoder and the type of the encoder that is used.
+    pub fn with_fallback(
+        mut options: VideoEncoderSettings,
+    ) -> Result<(Self, VideoEncoderType), VideoEncoderError> {
+        loop {
+            match Self::new(options.clone()) {
+                Ok(encoder) => return Ok((encoder, options.codec)),
+                Err(e) => {
+                    if let Some(codec) = options.codec.fallback() {
+                        log::warn!(
+                            "failed to create video encoder={:?}, fall back to {:?}, error={:?}",
+                            options.codec,
+                            codec,
+                            e
+                        );
+
+                        options.codec = codec;
+                    } else {
+                        return Err(e);
+                    }
+                }
+            }
+        }
+    }
+
     pub fn update(&mut self, frame: &VideoFrame) -> bool {
+        self.timestamp = frame.timestamp;
+
         let av_frame = unsafe { &mut *self.frame };
         match frame.sub_format {
             // mfxFrameSurface1.Data.MemId contains a pointer to the mfxHDLPair structure
@@ -510,15 +946,77 @@ impl VideoEncoder {
         true
     }
 
+    /// Encode the next frame as an IDR frame, such as when a receiver has
+    /// joined in the middle of the stream and cannot wait for the next key
+    /// frame of the interval.
+    ///
+    /// This is ignored with intra refresh, the receivers resume from the
+    /// recovery point of the next refresh cycle without the spike of an IDR
+    /// frame.
+    pub fn request_key_frame(&mut self) {
+        if !self.intra_refresh {
+            self.key_frame = true;
+        }
+    }
+
+    /// The areas of the next frame that have changed from the previous frame,
+    /// the rest of the frame is encoded with a coarser quantizer, so that the
+    /// bits are spent on the changes instead of refining the static content.
+    /// `None` encodes the whole frame alike.
+    ///
+    /// This is ignored for the key frames and with intra refresh, which code
+    /// the whole picture again, the static areas would stay coarse until the
+    /// next key frame. The encoders that do not support the regions of
+    /// interest, such as VideoToolbox, ignore it.
+    pub fn set_damage(&mut self, damage: Option<&[MaskArea]>) {
+        self.damage = damage.map(|it| it.to_vec());
+    }
+
     pub fn encode(&mut self) -> Result<(), VideoEncoderError> {
-        let av_frame = unsafe { &mut *self.frame };
-        av_frame.pts = unsafe {
-            let context_ref = &*self.context;
+        // The time base of the encoder is the frame interval, the capture timestamp is
+        // converted to it. The pacing of the capture may skip frame times, so the
+        // timestamp is used instead of the frame number, and the frames without a
+        // timestamp simply advance by one frame.
+        self.pts = unsafe {
             av_rescale_q(
-                context_ref.frame_num,
-                context_ref.pkt_timebase,
-                context_ref.time_base,
+                self.timestamp as i64,
+                av_make_q(1, 1_000_000),
+                { &*self.context }.time_base,
             )
+        }
+        .max(self.pts + 1);
+
+        let key_frame = std::mem::take(&mut self.key_frame);
+        if key_frame {
+            self.since_key_frame = 0;
+        }
+
+        // The frame is reused, the regions of the previous frame are removed first.
+        unsafe {
+            av_frame_remove_side_data(
+                self.frame,
+                AVFrameSideDataType::AV_FRAME_DATA_REGIONS_OF_INTEREST,
+            );
+        }
+
+        // The encoders insert the key frames of the interval by themselves, the frame
+        // that is due for it is expected to be one.
+        self.since_key_frame += 1;
+        if let Some(damage) = self.damage.take() {
+            if !key_frame
+                && !self.intra_refresh
+                && self.since_key_frame < unsafe { &*self.context }.gop_size as i64
+            {
+                self.set_regions_of_interest(&damage);
+            }
+        }
+
+        let av_frame = unsafe { &mut *self.frame };
+        av_frame.pts = self.pts;
+        av_frame.pict_type = if key_frame {
+            AVPictureType::AV_PICTURE_TYPE_I
+        } else {
+            AVPictureType::AV_PICTURE_TYPE_NONE
         };
 
         if unsafe { avcodec_send_frame(self.context, self.frame) } != 0 {
@@ -528,6 +1026,60 @@ impl VideoEncoder {
         Ok(())
     }
 
+    // The regions are ordered by priority, the changed areas come first and keep
+    // the quantizer, the whole frame is last and takes the rest. The frames with
+    // too many areas are encoded alike.
+    fn set_regions_of_interest(&mut self, damage: &[MaskArea]) {
+        if damage.len() >= Self::MAX_REGIONS {
+            return;
+        }
+
+        let size = std::mem::size_of::<AVRegionOfInterest>();
+        let side_data = unsafe {
+            av_frame_new_side_data(
+                self.frame,
+                AVFrameSideDataType::AV_FRAME_DATA_REGIONS_OF_INTEREST,
+                size * (damage.len() + 1),
+            )
+        };
+
+        if side_data.is_null() {
+            return;
+        }
+
+        let (width, height) = {
+            let context_ref = unsafe { &*self.context };
+            (context_ref.width, context_ref.height)
+        };
+
+        let regions = unsafe {
+            std::slice::from_raw_parts_mut(
+                (*side_data).data as *mut AVRegionOfInterest,
+                damage.len() + 1,
+            )
+        };
+
+        for (region, area) in regions.iter_mut().zip(damage.iter()) {
+            *region = AVRegionOfInterest {
+                self_size: size as u32,
+                top: area.y as i32,
+                bottom: (area.y + area.height) as i32,
+                left: area.x as i32,
+                right: (area.x + area.width) as i32,
+                qoffset: AVRational { num: 0, den: 1 },
+            };
+        }
+
+        regions[damage.len()] = AVRegionOfInterest {
+            self_size: size as u32,
+            top: 0,
+            bottom: height,
+            left: 0,
+            right: width,
+            qoffset: AVRational { num: 1, den: 2 },
+        };
+    }
+
     pub fn read<'a>(&'a mut self) -> Option<(&'a [u8], i32, u64)> {
         let packet_ref = unsafe { &*self.packet };
         let context_ref = unsafe { &*self.context };
@@ -554,10 +1106,34 @@ impl VideoEncoder {
             return None;
         }
 
+        if packet_ref.flags & AV_PKT_FLAG_KEY as i32 != 0 {
+            self.since_key_frame = 0;
+        }
+
+        // The first frame of each refresh cycle is marked as a key frame, so that the
+        // transport inserts the configuration in front of it and the receivers can
+        // resume from it.
+        let mut flags = packet_ref.flags;
+        if let Some((cycle, count)) = self.recovery_points.as_mut() {
+            if *count % *cycle == 0 {
+                flags |= AV_PKT_FLAG_KEY as i32;
+            }
+
+            *count += 1;
+        }
+
+        // The timestamps in the transport are on the media clock, in microseconds.
         Some((
             unsafe { std::slice::from_raw_parts(packet_ref.data, packet_ref.size as usize) },
-            packet_ref.flags,
-            packet_ref.pts as u64,
+            flags,
+            unsafe {
+                av_rescale_q(
+                    packet_ref.pts,
+                    context_ref.time_base,
+                    av_make_q(1, 1_000_000),
+                )
+            }
+            .max(0) as u64,
         ))
     }
 }
@@ -0,0 +1,871 @@
+use crate::planes::PlaneBuffer;
+
+use std::{
+    path::PathBuf,
+    ptr::{null, null_mut},
+};
+
+use hylarana_common::{
+    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    Size,
+};
+
+use mirror_ffmpeg_sys::*;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::{
+    d3d_texture_borrowed_raw,
+    windows::{
+        core::Interface,
+        Win32::{
+            Foundation::RECT,
+            Graphics::{
+                Direct3D11::{
+                    ID3D11Texture2D, D3D11_BIND_RENDER_TARGET, D3D11_SUBRESOURCE_DATA,
+                    D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
+                },
+                Dxgi::Common::{DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_FORMAT_NV12, DXGI_FORMAT_P010},
+            },
+        },
+    },
+    Direct3DDevice,
+};
+
+#[cfg(target_os = "windows")]
+use hylarana_resample::win32::{Resource, VideoResampler, VideoResamplerOptions};
+
+/// What the watermark draws.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum WatermarkContent {
+    /// A PNG image, such as a logo, it is read and decoded when the watermark
+    /// is created.
+    Png { path: PathBuf },
+    /// An RGBA image, the rows follow each other without padding.
+    Rgba {
+        width: u32,
+        height: u32,
+        data: Vec<u8>,
+    },
+    /// A line of text, such as the id of the user, that is repeated over the
+    /// whole frame, so that it cannot be cropped out. The text is drawn with
+    /// a built-in font of the printable ASCII characters, the other characters
+    /// are drawn as `?`.
+    Text {
+        text: String,
+        /// The height of the letters in pixels, the font is scaled by whole
+        /// multiples of its height of 8 pixels. `0` follows the height of
+        /// the frame.
+        size: u32,
+    },
+}
+
+/// The corner of the frame that the image is drawn at, the text is repeated
+/// over the whole frame and ignores it.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+    Center,
+}
+
+/// A watermark that is drawn into the frames before they are encoded, so that
+/// the content that is recorded or relayed can be traced back to its source.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WatermarkOptions {
+    pub content: WatermarkContent,
+    #[serde(default)]
+    pub position: WatermarkPosition,
+    /// The distance of the image from the edges of the frame in pixels.
+    #[serde(default)]
+    pub margin: u32,
+    /// The opacity of the watermark, from 0.0 to 1.0, it is multiplied by the
+    /// alpha of the image.
+    pub opacity: f32,
+}
+
+#[derive(Debug, Clone)]
+pub struct WatermarkerSettings {
+    pub options: WatermarkOptions,
+    #[cfg(target_os = "windows")]
+    pub direct3d: Direct3DDevice,
+}
+
+#[derive(Error, Debug)]
+pub enum WatermarkerError {
+    #[error("unsupported video frame format")]
+    NotSupportFormat,
+    #[error("invalid watermark image")]
+    InvalidImage,
+    #[error("failed to read watermark image")]
+    ReadImageError(#[from] std::io::Error),
+    #[error("failed to decode watermark image")]
+    DecodeImageError,
+    #[cfg(target_os = "windows")]
+    #[error(transparent)]
+    WindowsError(#[from] hylarana_common::win32::windows::core::Error),
+}
+
+/// Draws the watermark into the frames before they are encoded, the output
+/// is a copy of the frame, the frame of the source is not changed.
+///
+/// The Direct3D11 textures are blended on the GPU with the video processor,
+/// the watermark is the second stream of the processor. The software frames
+/// are blended in the memory.
+pub struct Watermarker {
+    settings: WatermarkerSettings,
+    // The decoded image, `None` for the text.
+    image: Option<Image>,
+    layer: Option<Layer>,
+    #[cfg(target_os = "windows")]
+    hardware: Option<(VideoResampler, ID3D11Texture2D, Size, VideoFormat)>,
+    planes: PlaneBuffer,
+    frame: VideoFrame,
+}
+
+unsafe impl Sync for Watermarker {}
+unsafe impl Send for Watermarker {}
+
+impl Watermarker {
+    pub fn new(settings: WatermarkerSettings) -> Result<Self, WatermarkerError> {
+        let image = match &settings.options.content {
+            WatermarkContent::Png { path } => Some(Image::decode_png(&std::fs::read(path)?)?),
+            WatermarkContent::Rgba {
+                width,
+                height,
+                data,
+            } => {
+                if *width == 0
+                    || *height == 0
+                    || data.len() < *width as usize * *height as usize * 4
+                {
+                    return Err(WatermarkerError::InvalidImage);
+                }
+
+                Some(Image {
+                    width: *width,
+                    height: *height,
+                    data: data.clone(),
+                })
+            }
+            WatermarkContent::Text { .. } => None,
+        };
+
+        Ok(Self {
+            #[cfg(target_os = "windows")]
+            hardware: None,
+            planes: PlaneBuffer::default(),
+            frame: VideoFrame::default(),
+            layer: None,
+            settings,
+            image,
+        })
+    }
+
+    pub fn process(&mut self, frame: &VideoFrame) -> Result<&VideoFrame, WatermarkerError> {
+        let size = Size {
+            width: frame.width,
+            height: frame.height,
+        };
+
+        // The watermark is placed again when the size of the frames changes.
+        if self
+            .layer
+            .as_ref()
+            .map(|it| it.frame != size)
+            .unwrap_or(true)
+        {
+            self.layer = Some(Layer::new(
+                &self.settings.options,
+                self.image.as_ref(),
+                size,
+            ));
+
+            #[cfg(target_os = "windows")]
+            {
+                self.hardware = None;
+            }
+        }
+
+        match frame.sub_format {
+            #[cfg(target_os = "windows")]
+            VideoSubFormat::D3D11 => self.process_d3d11(frame)?,
+            VideoSubFormat::SW => self.process_software(frame)?,
+            #[allow(unreachable_patterns)]
+            _ => return Err(WatermarkerError::NotSupportFormat),
+        }
+
+        self.frame.width = frame.width;
+        self.frame.height = frame.height;
+        self.frame.display_width = frame.display_width;
+        self.frame.display_height = frame.display_height;
+        self.frame.rotation = frame.rotation;
+        self.frame.mirror = frame.mirror;
+        self.frame.field_order = frame.field_order;
+        self.frame.timestamp = frame.timestamp;
+        Ok(&self.frame)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn process_d3d11(&mut self, frame: &VideoFrame) -> Result<(), WatermarkerError> {
+        let format = match frame.format {
+            VideoFormat::NV12 => DXGI_FORMAT_NV12,
+            VideoFormat::P010 => DXGI_FORMAT_P010,
+            _ => return Err(WatermarkerError::NotSupportFormat),
+        };
+
+        let size = Size {
+            width: frame.width,
+            height: frame.height,
+        };
+
+        if self
+            .hardware
+            .as_ref()
+            .map(|(_, _, it, format)| *it != size || *format != frame.format)
+            .unwrap_or(true)
+        {
+            let layer = self.layer.as_ref().unwrap();
+            let direct3d = &self.settings.direct3d;
+
+            // The video processor takes BGRA, the channels of the layer are swapped.
+            let pixels = layer
+                .rgba
+                .chunks_exact(4)
+                .flat_map(|it| [it[2], it[1], it[0], it[3]])
+                .collect::<Vec<u8>>();
+
+            let texture = unsafe {
+                let mut desc = D3D11_TEXTURE2D_DESC::default();
+                desc.Width = layer.width.max(1);
+                desc.Height = layer.height.max(1);
+                desc.MipLevels = 1;
+                desc.ArraySize = 1;
+                desc.Format = DXGI_FORMAT_B8G8R8A8_UNORM;
+                desc.SampleDesc.Count = 1;
+                desc.SampleDesc.Quality = 0;
+                desc.Usage = D3D11_USAGE_DEFAULT;
+                desc.BindFlags = D3D11_BIND_RENDER_TARGET.0 as u32;
+
+                // An empty layer still needs one transparent pixel.
+                let empty = [0u8; 4];
+                let data = D3D11_SUBRESOURCE_DATA {
+                    pSysMem: if pixels.is_empty() {
+                        empty.as_ptr()
+                    } else {
+                        pixels.as_ptr()
+                    } as *const _,
+                    SysMemPitch: desc.Width * 4,
+                    SysMemSlicePitch: 0,
+                };
+
+                let mut texture = None;
+                direct3d
+                    .device
+                    .CreateTexture2D(&desc, Some(&data), Some(&mut texture))?;
+                texture.unwrap()
+            };
+
+            let mut resampler = VideoResampler::new(VideoResamplerOptions {
+                direct3d: direct3d.clone(),
+                input: Resource::Default(format, size),
+                output: Resource::Default(format, size),
+            })?;
+
+            resampler.set_overlay(Some((
+                &texture,
+                RECT {
+                    left: layer.x as i32,
+                    top: layer.y as i32,
+                    right: (layer.x + layer.width.max(1)) as i32,
+                    bottom: (layer.y + layer.height.max(1)) as i32,
+                },
+                self.settings.options.opacity,
+            )))?;
+
+            self.hardware = Some((resampler, texture, size, frame.format));
+
+            log::info!(
+                "watermarker create d3d11 processor, size={}x{}",
+                size.width,
+                size.height
+            );
+        }
+
+        let (resampler, _, _, _) = self.hardware.as_mut().unwrap();
+
+        // The texture of the frame is borrowed, it must not be released here.
+        let texture = d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
+            .ok_or_else(|| WatermarkerError::NotSupportFormat)?;
+
+        let view = resampler.create_input_view(texture, frame.data[1] as u32)?;
+        resampler.process(Some(view))?;
+
+        self.frame.format = frame.format;
+        self.frame.sub_format = VideoSubFormat::D3D11;
+        self.frame.data = [resampler.get_output().as_raw() as *const _, null(), null()];
+        self.frame.linesize = [0; 3];
+        Ok(())
+    }
+
+    fn process_software(&mut self, frame: &VideoFrame) -> Result<(), WatermarkerError> {
+        if !self.planes.copy(frame) {
+            return Err(WatermarkerError::NotSupportFormat);
+        }
+
+        let layer = self.layer.as_ref().unwrap();
+        let opacity = (self.settings.options.opacity.clamp(0.0, 1.0) * 255.0) as u32;
+        let [luma, u, v] = self.planes.planes_mut();
+        match frame.format {
+            VideoFormat::NV12 => layer.blend_nv12(luma, u, frame.linesize, opacity),
+            VideoFormat::P010 => layer.blend_p010(luma, u, frame.linesize, opacity),
+            _ => layer.blend_i420(luma, u, v, frame.linesize, opacity),
+        }
+
+        self.planes.output(&mut self.frame);
+        Ok(())
+    }
+}
+
+struct Image {
+    width: u32,
+    height: u32,
+    data: Vec<u8>,
+}
+
+impl Image {
+    fn decode_png(bytes: &[u8]) -> Result<Self, WatermarkerError> {
+        let codec = unsafe { avcodec_find_decoder(AVCodecID::AV_CODEC_ID_PNG) };
+        if codec.is_null() {
+            return Err(WatermarkerError::DecodeImageError);
+        }
+
+        let mut context = unsafe { avcodec_alloc_context3(codec) };
+        let mut packet = unsafe { av_packet_alloc() };
+        let mut frame = unsafe { av_frame_alloc() };
+
+        let result = (|| {
+            if context.is_null() || packet.is_null() || frame.is_null() {
+                return Err(WatermarkerError::DecodeImageError);
+            }
+
+            if unsafe { avcodec_open2(context, codec, null_mut()) } != 0 {
+                return Err(WatermarkerError::DecodeImageError);
+            }
+
+            // The packet owns a copy of the file, the decoder reads past the end, so
+            // it has the padding of FFmpeg.
+            if unsafe { av_new_packet(packet, bytes.len() as i32) } != 0 {
+                return Err(WatermarkerError::DecodeImageError);
+            }
+
+            unsafe {
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), (*packet).data, bytes.len());
+            }
+
+            if unsafe { avcodec_send_packet(context, packet) } != 0
+                || unsafe { avcodec_receive_frame(context, frame) } != 0
+            {
+                return Err(WatermarkerError::DecodeImageError);
+            }
+
+            let frame_ref = unsafe { &*frame };
+            if frame_ref.width <= 0 || frame_ref.height <= 0 {
+                return Err(WatermarkerError::InvalidImage);
+            }
+
+            // The PNG images come in many formats, such as with a palette, they are
+            // all converted to RGBA.
+            let (width, height) = (frame_ref.width as u32, frame_ref.height as u32);
+            let sws = unsafe {
+                sws_getContext(
+                    frame_ref.width,
+                    frame_ref.height,
+                    std::mem::transmute::<i32, AVPixelFormat>(frame_ref.format),
+                    frame_ref.width,
+                    frame_ref.height,
+                    AVPixelFormat::AV_PIX_FMT_RGBA,
+                    SWS_POINT,
+                    null_mut(),
+                    null_mut(),
+                    null(),
+                )
+            };
+
+            if sws.is_null() {
+                return Err(WatermarkerError::DecodeImageError);
+            }
+
+            let mut data = vec![0u8; width as usize * height as usize * 4];
+            let output = [data.as_mut_ptr(), null_mut(), null_mut(), null_mut()];
+            let linesize = [width as i32 * 4, 0, 0, 0];
+
+            unsafe {
+                sws_scale(
+                    sws,
+                    frame_ref.data.as_ptr() as _,
+                    frame_ref.linesize.as_ptr(),
+                    0,
+                    frame_ref.height,
+                    output.as_ptr(),
+                    linesize.as_ptr(),
+                );
+
+                sws_freeContext(sws);
+            }
+
+            Ok(Self {
+                width,
+                height,
+                data,
+            })
+        })();
+
+        unsafe {
+            if !frame.is_null() {
+                av_frame_free(&mut frame);
+            }
+
+            if !packet.is_null() {
+                av_packet_free(&mut packet);
+            }
+
+            if !context.is_null() {
+                avcodec_free_context(&mut context);
+            }
+        }
+
+        result
+    }
+}
+
+// The watermark drawn at its place in the frames of a size, it is converted to
+// the colors of the software frames beforehand. The place is aligned to even
+// pixels, so that it covers whole samples of the chroma.
+struct Layer {
+    frame: Size,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    rgba: Vec<u8>,
+    // The luma and the alpha of each pixel, and the chroma and the alpha of each
+    // block of 2x2 pixels.
+    luma: Vec<[u8; 2]>,
+    chroma: Vec<[u8; 3]>,
+}
+
+impl Layer {
+    fn new(options: &WatermarkOptions, image: Option<&Image>, frame: Size) -> Self {
+        let (x, y, width, height, rgba) = if let Some(image) = image {
+            let place = |size: u32, image: u32, start: bool, end: bool| {
+                let offset = if start {
+                    options.margin
+                } else if end {
+                    size.saturating_sub(image + options.margin)
+                } else {
+                    size.saturating_sub(image) / 2
+                };
+
+                offset.min(size) & !1
+            };
+
+            let x = place(
+                frame.width,
+                image.width,
+                matches!(
+                    options.position,
+                    WatermarkPosition::TopLeft | WatermarkPosition::BottomLeft
+                ),
+                matches!(
+                    options.position,
+                    WatermarkPosition::TopRight | WatermarkPosition::BottomRight
+                ),
+            );
+
+            let y = place(
+                frame.height,
+                image.height,
+                matches!(
+                    options.position,
+                    WatermarkPosition::TopLeft | WatermarkPosition::TopRight
+                ),
+                matches!(
+                    options.position,
+                    WatermarkPosition::BottomLeft | WatermarkPosition::BottomRight
+                ),
+            );
+
+            // The image that does not fit into the frame is cropped.
+            let width = image.width.min(frame.width - x);
+            let height = image.height.min(frame.height - y);
+
+            let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+            for row in 0..height as usize {
+                let start = row * image.width as usize * 4;
+                rgba.extend_from_slice(&image.data[start..start + width as usize * 4]);
+            }
+
+            (x, y, width, height, rgba)
+        } else {
+            let (text, size) = match &options.content {
+                WatermarkContent::Text { text, size } => (text.as_str(), *size),
+                _ => ("", 0),
+            };
+
+            (
+                0,
+                0,
+                frame.width,
+                frame.height,
+                draw_text_tiles(text, size, frame),
+            )
+        };
+
+        let mut this = Self {
+            luma: Vec::with_capacity(width as usize * height as usize),
+            chroma: Vec::with_capacity(width.div_ceil(2) as usize * height.div_ceil(2) as usize),
+            frame,
+            x,
+            y,
+            width,
+            height,
+            rgba,
+        };
+
+        this.convert();
+        this
+    }
+
+    // BT.709 with the limited range, which is what the encoders are configured
+    // with.
+    fn convert(&mut self) {
+        let (width, height) = (self.width as usize, self.height as usize);
+        let pixel = |x: usize, y: usize| {
+            let it = &self.rgba[(y * width + x) * 4..][..4];
+            (it[0] as f32, it[1] as f32, it[2] as f32, it[3] as f32)
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let (r, g, b, a) = pixel(x, y);
+                let luma = 16.0 + 0.1826 * r + 0.6142 * g + 0.0620 * b;
+                self.luma.push([luma.round() as u8, a as u8]);
+            }
+        }
+
+        for y in (0..height).step_by(2) {
+            for x in (0..width).step_by(2) {
+                // The chroma is the average of the block weighted by the alpha, the
+                // transparent pixels do not tint it.
+                let (mut u, mut v, mut alpha, mut count) = (0.0, 0.0, 0.0, 0.0);
+                for (x, y) in [(x, y), (x + 1, y), (x, y + 1), (x + 1, y + 1)] {
+                    if x < width && y < height {
+                        let (r, g, b, a) = pixel(x, y);
+                        u += (-0.1006 * r - 0.3386 * g + 0.4392 * b) * a;
+                        v += (0.4392 * r - 0.3989 * g - 0.0403 * b) * a;
+                        alpha += a;
+                        count += 1.0;
+                    }
+                }
+
+                let (u, v) = if alpha > 0.0 {
+                    (128.0 + u / alpha, 128.0 + v / alpha)
+                } else {
+                    (128.0, 128.0)
+                };
+
+                self.chroma
+                    .push([u.round() as u8, v.round() as u8, (alpha / count) as u8]);
+            }
+        }
+    }
+
+    fn blend_nv12(&self, luma: &mut [u8], uv: &mut [u8], linesize: [usize; 3], opacity: u32) {
+        self.blend_luma(luma, linesize[0], 1, opacity, |it, value, alpha| {
+            it[0] = blend(it[0] as u32, value as u32, alpha, 255) as u8;
+        });
+
+        self.blend_chroma(opacity, |x, y, [u, v], alpha| {
+            let offset = y * linesize[1] + x * 2;
+            if let Some(it) = uv.get_mut(offset..offset + 2) {
+                it[0] = blend(it[0] as u32, u as u32, alpha, 255) as u8;
+                it[1] = blend(it[1] as u32, v as u32, alpha, 255) as u8;
+            }
+        });
+    }
+
+    fn blend_i420(
+        &self,
+        luma: &mut [u8],
+        u: &mut [u8],
+        v: &mut [u8],
+        linesize: [usize; 3],
+        opacity: u32,
+    ) {
+        self.blend_luma(luma, linesize[0], 1, opacity, |it, value, alpha| {
+            it[0] = blend(it[0] as u32, value as u32, alpha, 255) as u8;
+        });
+
+        self.blend_chroma(opacity, |x, y, [value_u, value_v], alpha| {
+            if let Some(it) = u.get_mut(y * linesize[1] + x) {
+                *it = blend(*it as u32, value_u as u32, alpha, 255) as u8;
+            }
+
+            if let Some(it) = v.get_mut(y * linesize[2] + x) {
+                *it = blend(*it as u32, value_v as u32, alpha, 255) as u8;
+            }
+        });
+    }
+
+    // The samples of P010 are in the high 10 bits of little-endian 16-bit words,
+    // the colors of the layer are scaled from 8 bits to 16 bits.
+    fn blend_p010(&self, luma: &mut [u8], uv: &mut [u8], linesize: [usize; 3], opacity: u32) {
+        let sample = |it: &mut [u8], value: u8, alpha: u32| {
+            let current = u16::from_le_bytes([it[0], it[1]]) as u32;
+            let value = blend(current, value as u32 * 257, alpha, 0xFFFF) as u16 & 0xFFC0;
+            it.copy_from_slice(&value.to_le_bytes());
+        };
+
+        self.blend_luma(luma, linesize[0], 2, opacity, |it, value, alpha| {
+            sample(it, value, alpha);
+        });
+
+        self.blend_chroma(opacity, |x, y, [u, v], alpha| {
+            let offset = y * linesize[1] + x * 4;
+            if let Some(it) = uv.get_mut(offset..offset + 4) {
+                sample(&mut it[..2], u, alpha);
+                sample(&mut it[2..], v, alpha);
+            }
+        });
+    }
+
+    // Calls the function with the samples of the plane that are covered by the
+    // pixels of the layer, the alpha is multiplied by the opacity.
+    fn blend_luma<F>(
+        &self,
+        plane: &mut [u8],
+        linesize: usize,
+        bytes: usize,
+        opacity: u32,
+        mut func: F,
+    ) where
+        F: FnMut(&mut [u8], u8, u32),
+    {
+        for y in 0..self.height as usize {
+            for x in 0..self.width as usize {
+                let [value, alpha] = self.luma[y * self.width as usize + x];
+                let alpha = alpha as u32 * opacity / 255;
+                if alpha == 0 {
+                    continue;
+                }
+
+                let offset = (self.y as usize + y) * linesize + (self.x as usize + x) * bytes;
+                if let Some(it) = plane.get_mut(offset..offset + bytes) {
+                    func(it, value, alpha);
+                }
+            }
+        }
+    }
+
+    fn blend_chroma<F>(&self, opacity: u32, mut func: F)
+    where
+        F: FnMut(usize, usize, [u8; 2], u32),
+    {
+        let width = self.width.div_ceil(2) as usize;
+        for y in 0..self.height.div_ceil(2) as usize {
+            for x in 0..width {
+                let [u, v, alpha] = self.chroma[y * width + x];
+                let alpha = alpha as u32 * opacity / 255;
+                if alpha > 0 {
+                    func(
+                        self.x as usize / 2 + x,
+                        self.y as usize / 2 + y,
+                        [u, v],
+                        alpha,
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn blend(current: u32, value: u32, alpha: u32, max: u32) -> u32 {
+    ((current * (255 - alpha) + value * alpha + 127) / 255).min(max)
+}
+
+// The glyphs of the printable ASCII characters in 5x7 pixels, each byte is a
+// column from the left, the lowest bit is the top row.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5F, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7F, 0x14, 0x7F, 0x14],
+    [0x24, 0x2A, 0x7F, 0x2A, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1C, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1C, 0x00],
+    [0x14, 0x08, 0x3E, 0x08, 0x14],
+    [0x08, 0x08, 0x3E, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3E, 0x51, 0x49, 0x45, 0x3E],
+    [0x00, 0x42, 0x7F, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4B, 0x31],
+    [0x18, 0x14, 0x12, 0x7F, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3C, 0x4A, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1E],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3E],
+    [0x7E, 0x11, 0x11, 0x11, 0x7E],
+    [0x7F, 0x49, 0x49, 0x49, 0x36],
+    [0x3E, 0x41, 0x41, 0x41, 0x22],
+    [0x7F, 0x41, 0x41, 0x22, 0x1C],
+    [0x7F, 0x49, 0x49, 0x49, 0x41],
+    [0x7F, 0x09, 0x09, 0x09, 0x01],
+    [0x3E, 0x41, 0x49, 0x49, 0x7A],
+    [0x7F, 0x08, 0x08, 0x08, 0x7F],
+    [0x00, 0x41, 0x7F, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3F, 0x01],
+    [0x7F, 0x08, 0x14, 0x22, 0x41],
+    [0x7F, 0x40, 0x40, 0x40, 0x40],
+    [0x7F, 0x02, 0x0C, 0x02, 0x7F],
+    [0x7F, 0x04, 0x08, 0x10, 0x7F],
+    [0x3E, 0x41, 0x41, 0x41, 0x3E],
+    [0x7F, 0x09, 0x09, 0x09, 0x06],
+    [0x3E, 0x41, 0x51, 0x21, 0x5E],
+    [0x7F, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7F, 0x01, 0x01],
+    [0x3F, 0x40, 0x40, 0x40, 0x3F],
+    [0x1F, 0x20, 0x40, 0x20, 0x1F],
+    [0x3F, 0x40, 0x38, 0x40, 0x3F],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7F, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7F, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7F, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7F],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7E, 0x09, 0x01, 0x02],
+    [0x0C, 0x52, 0x52, 0x52, 0x3E],
+    [0x7F, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7D, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3D, 0x00],
+    [0x7F, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7F, 0x40, 0x00],
+    [0x7C, 0x04, 0x18, 0x04, 0x78],
+    [0x7C, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7C, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7C],
+    [0x7C, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3F, 0x44, 0x40, 0x20],
+    [0x3C, 0x40, 0x40, 0x20, 0x7C],
+    [0x1C, 0x20, 0x40, 0x20, 0x1C],
+    [0x3C, 0x40, 0x30, 0x40, 0x3C],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x0C, 0x50, 0x50, 0x50, 0x3C],
+    [0x44, 0x64, 0x54, 0x4C, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7F, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+// Draws the text repeatedly over a layer of the size of the frame, the rows
+// are shifted by half of the text, like the bricks of a wall. The letters are
+// white with a dark shadow, so that they can be seen on any content.
+fn draw_text_tiles(text: &str, size: u32, frame: Size) -> Vec<u8> {
+    let (width, height) = (frame.width as usize, frame.height as usize);
+    let mut rgba = vec![0u8; width * height * 4];
+    if text.is_empty() {
+        return rgba;
+    }
+
+    let scale = if size == 0 { frame.height / 40 } else { size } as usize / 8;
+    let scale = scale.max(1);
+
+    // Each character takes a cell of 6x8 pixels of the font.
+    let glyphs = text
+        .chars()
+        .map(|it| {
+            if (' '..='~').contains(&it) {
+                FONT[it as usize - 0x20]
+            } else {
+                FONT['?' as usize - 0x20]
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let text_width = glyphs.len() * 6 * scale;
+    let step_x = text_width + 16 * scale;
+    let step_y = 8 * scale * 4;
+
+    let mut draw = |left: isize, top: isize, color: u8| {
+        for (index, glyph) in glyphs.iter().enumerate() {
+            for (column, bits) in glyph.iter().enumerate() {
+                for row in 0..7 {
+                    if bits & (1 << row) == 0 {
+                        continue;
+                    }
+
+                    let x = left + ((index * 6 + column) * scale) as isize;
+                    let y = top + (row * scale) as isize;
+                    for dy in 0..scale as isize {
+                        for dx in 0..scale as isize {
+                            let (x, y) = (x + dx, y + dy);
+                            if x < 0 || y < 0 || x as usize >= width || y as usize >= height {
+                                continue;
+                            }
+
+                            let offset = (y as usize * width + x as usize) * 4;
+                            rgba[offset..offset + 4].copy_from_slice(&[color, color, color, 255]);
+                        }
+                    }
+                }
+            }
+        }
+    };
+
+    // The letters are drawn after the shadows and cover them.
+    let shadow = scale.div_ceil(2) as isize;
+    for pass in [0u8, 255] {
+        let offset = if pass == 0 { shadow } else { 0 };
+        for (index, top) in (0..height + step_y).step_by(step_y).enumerate() {
+            let shift = if index % 2 == 1 { step_x / 2 } else { 0 };
+            let mut left = shift as isize - step_x as isize;
+            while left < width as isize {
+                draw(left + offset, top as isize + offset, pass);
+                left += step_x as isize;
+            }
+        }
+    }
+
+    rgba
+}
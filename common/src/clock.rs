@@ -0,0 +1,40 @@
+use std::{
+    sync::OnceLock,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// The monotonic clock of the media pipeline.
+///
+/// All timestamps of the audio and video frames are taken from this clock, the
+/// capture, the encoders and the transport use the same time base, which is
+/// microseconds since the clock was first used in the process, so that the
+/// timestamps of the audio and video streams can be compared with each other.
+pub struct MediaClock;
+
+impl MediaClock {
+    /// The current time of the clock in microseconds.
+    ///
+    /// The clock never returns zero, zero is reserved for frames that have no
+    /// timestamp.
+    pub fn now() -> u64 {
+        EPOCH.get_or_init(Instant::now).elapsed().as_micros() as u64 + 1
+    }
+
+    /// The time that has passed since the timestamp, in microseconds.
+    pub fn elapsed(timestamp: u64) -> u64 {
+        Self::now().saturating_sub(timestamp)
+    }
+
+    /// The system time since the unix epoch in microseconds, it is not
+    /// monotonic, and is only used to relate the media clocks of different
+    /// devices, which requires the system clocks of the devices to be
+    /// synchronized.
+    pub fn system() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|it| it.as_micros() as u64)
+            .unwrap_or(0)
+    }
+}
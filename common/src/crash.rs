@@ -0,0 +1,204 @@
+//! The crash handler of the process, a panic on any thread, such as a capture
+//! thread, is logged with its backtrace and written to a report, so that the
+//! crashes of the host application leave something to look at.
+
+use std::{
+    backtrace::Backtrace,
+    cell::Cell,
+    fmt::Write as _,
+    fs,
+    panic::{self, PanicHookInfo},
+    path::{Path, PathBuf},
+    sync::{Arc, Once, RwLock},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+#[derive(Debug, Default, Clone)]
+pub struct CrashHandlerOptions {
+    /// The directory of the reports, the report of a crash is
+    /// `crash-{timestamp}.txt`. `None` only logs the crashes.
+    pub directory: Option<PathBuf>,
+    /// Also write a minidump of the process next to the report, which can be
+    /// opened with a debugger. Only on Windows, it is ignored on the other
+    /// platforms.
+    pub minidump: bool,
+}
+
+/// A crash that has happened, it is passed to the callback of `set_callback`.
+#[derive(Debug, Clone)]
+pub struct CrashReport {
+    /// The name of the thread that has panicked, such as
+    /// `HylaranaScreenCaptureThread`.
+    pub thread: String,
+    pub message: String,
+    /// The file and the line of the panic.
+    pub location: Option<String>,
+    pub backtrace: String,
+    /// The time of the crash in milliseconds since the unix epoch.
+    pub timestamp: u64,
+    /// The files that have been written, they are `None` if the directory is
+    /// not set or the file cannot be written.
+    pub report: Option<PathBuf>,
+    pub minidump: Option<PathBuf>,
+}
+
+impl CrashReport {
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(text, "thread: {}", self.thread);
+        let _ = writeln!(text, "message: {}", self.message);
+        let _ = writeln!(
+            text,
+            "location: {}",
+            self.location.as_deref().unwrap_or("unknown")
+        );
+
+        let _ = writeln!(text, "timestamp: {}", self.timestamp);
+        let _ = writeln!(text, "backtrace:\n{}", self.backtrace);
+        text
+    }
+}
+
+type Callback = Arc<dyn Fn(&CrashReport) + Send + Sync>;
+
+static OPTIONS: RwLock<Option<CrashHandlerOptions>> = RwLock::new(None);
+static CALLBACK: RwLock<Option<Callback>> = RwLock::new(None);
+static INSTALL: Once = Once::new();
+
+thread_local! {
+    // A panic in the handler itself is left to the default hook.
+    static IN_HANDLER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Install the crash handler, it replaces the panic hook of the process and
+/// calls the previous hook after the crash has been handled. Installing it
+/// again only changes the options.
+pub fn install(options: CrashHandlerOptions) {
+    log::info!("install crash handler, options={:?}", options);
+
+    OPTIONS
+        .write()
+        .unwrap_or_else(|e| e.into_inner())
+        .replace(options);
+
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !IN_HANDLER.replace(true) {
+                handle(info);
+                IN_HANDLER.set(false);
+            }
+
+            previous(info);
+        }));
+    });
+}
+
+/// Call the callback when the process crashes, after the report has been
+/// written, such as to tell the user or to upload the report. The process
+/// is aborted after the callback in the release builds, so the callback
+/// should not take long. `None` removes the callback.
+pub fn set_callback<F>(callback: Option<F>)
+where
+    F: Fn(&CrashReport) + Send + Sync + 'static,
+{
+    *CALLBACK.write().unwrap_or_else(|e| e.into_inner()) =
+        callback.map(|it| Arc::new(it) as Callback);
+}
+
+fn handle(info: &PanicHookInfo) {
+    let payload = info.payload();
+    let message = payload
+        .downcast_ref::<&str>()
+        .map(|it| it.to_string())
+        .or_else(|| payload.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    let mut report = CrashReport {
+        thread: thread::current().name().unwrap_or("unnamed").to_string(),
+        location: info.location().map(|it| it.to_string()),
+        backtrace: Backtrace::force_capture().to_string(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|it| it.as_millis() as u64)
+            .unwrap_or_default(),
+        report: None,
+        minidump: None,
+        message,
+    };
+
+    log::error!(
+        "panic: thread={}, location={:?}, message={}\n{}",
+        report.thread,
+        report.location,
+        report.message,
+        report.backtrace
+    );
+
+    let options = OPTIONS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .clone()
+        .unwrap_or_default();
+
+    if let Some(directory) = options.directory.as_deref() {
+        if let Err(e) = write(directory, options.minidump, &mut report) {
+            log::error!("failed to write crash report, err={:?}", e);
+        }
+    }
+
+    let callback = CALLBACK.read().unwrap_or_else(|e| e.into_inner()).clone();
+    if let Some(callback) = callback {
+        callback(&report);
+    }
+}
+
+#[allow(unused_variables)]
+fn write(directory: &Path, minidump: bool, report: &mut CrashReport) -> std::io::Result<()> {
+    fs::create_dir_all(directory)?;
+
+    let path = directory.join(format!("crash-{}.txt", report.timestamp));
+    fs::write(&path, report.to_text())?;
+    report.report = Some(path);
+
+    #[cfg(target_os = "windows")]
+    if minidump {
+        let path = directory.join(format!("crash-{}.dmp", report.timestamp));
+        match write_minidump(&path) {
+            Ok(_) => report.minidump = Some(path),
+            Err(e) => log::error!("failed to write minidump, err={:?}", e),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn write_minidump(path: &Path) -> std::io::Result<()> {
+    use std::{fs::File, os::windows::io::AsRawHandle};
+
+    use windows::Win32::{
+        Foundation::HANDLE,
+        System::{
+            Diagnostics::Debug::{
+                MiniDumpNormal, MiniDumpWithThreadInfo, MiniDumpWriteDump, MINIDUMP_TYPE,
+            },
+            Threading::{GetCurrentProcess, GetCurrentProcessId},
+        },
+    };
+
+    let file = File::create(path)?;
+    unsafe {
+        MiniDumpWriteDump(
+            GetCurrentProcess(),
+            GetCurrentProcessId(),
+            HANDLE(file.as_raw_handle()),
+            MINIDUMP_TYPE(MiniDumpNormal.0 | MiniDumpWithThreadInfo.0),
+            None,
+            None,
+            None,
+        )
+    }
+    .map_err(std::io::Error::other)
+}
@@ -6,6 +6,11 @@
 //! references, and there will be no situation where a static structure is
 //! passed.
 //!
+//! The buffers that frames are copied into should be taken from a `FramePool`,
+//! `VideoFrameBuffer` and `AudioFrameBuffer` are reference counted and return
+//! their memory to the pool when the last reference is dropped, so that the
+//! steady state of a stream does not allocate for each frame.
+//!
 //! # Audio
 //!
 //! Pulse-code modulation
@@ -50,8 +55,24 @@
 //! now Netflix allows BT.2020 primaries (since 2021). The same happens with
 //! JPEG: it has BT.601 matrix derived from System M primaries, yet the
 //! primaries of most images are BT.709.
+//!
+//! P010
+//!
+//! P010 has the same layout as NV12, but each sample takes 16 bits, of which
+//! the high 10 bits are valid. This is the usual layout for HDR10 content,
+//! where the colors use BT.2020 primaries and the SMPTE ST 2084 (PQ) transfer
+//! function.
+
+use crate::Size;
 
-use std::{ffi::c_void, ptr::null};
+use std::{
+    ffi::c_void,
+    ops::{Deref, DerefMut},
+    ptr::null,
+    sync::{Arc, Mutex, OnceLock, Weak},
+};
+
+use serde::{Deserialize, Serialize};
 
 /// A sample from the audio stream.
 #[repr(C)]
@@ -62,6 +83,9 @@ pub struct AudioFrame {
     pub frames: u32,
     /// Pointer to the sample raw buffer.
     pub data: *const i16,
+    /// The capture time of the first sample in microseconds, taken from the
+    /// media clock, this is zero if the source does not provide it.
+    pub timestamp: u64,
 }
 
 unsafe impl Sync for AudioFrame {}
@@ -73,6 +97,7 @@ impl Default for AudioFrame {
             frames: 0,
             data: null(),
             sample_rate: 0,
+            timestamp: 0,
         }
     }
 }
@@ -85,6 +110,9 @@ pub enum VideoFormat {
     RGBA,
     NV12,
     I420,
+    /// 10-bit semi-planar YCbCr, the samples are stored in the high 10 bits of
+    /// little-endian 16-bit words, used for HDR10 (BT.2020 + PQ).
+    P010,
 }
 
 /// Subtype of the video frame.
@@ -99,6 +127,78 @@ pub enum VideoSubFormat {
     /// Video frames contain buffers that can be accessed directly through
     /// software.
     SW,
+    /// The planes of the frame are DMA-BUF file descriptors, see `DmaBuf`, a
+    /// type exclusive to the Linux platform.
+    DmaBuf,
+}
+
+/// The DMA-BUF planes of a frame, such as the surfaces of VAAPI or the
+/// buffers of PipeWire, the strides of the planes are the `linesize` of the
+/// frame. The file descriptors are owned by the source of the frame.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DmaBuf {
+    /// The file descriptors of the planes, the planes may share one file
+    /// descriptor at different offsets, this is `-1` for the planes that the
+    /// format does not have.
+    pub fd: [i32; 3],
+    pub offset: [u32; 3],
+    /// The DRM format modifier of the buffers, which describes the tiling of
+    /// the GPU, `0` is linear.
+    pub modifier: u64,
+}
+
+impl Default for DmaBuf {
+    fn default() -> Self {
+        Self {
+            fd: [-1; 3],
+            offset: [0; 3],
+            modifier: 0,
+        }
+    }
+}
+
+/// The clockwise rotation that needs to be applied to the picture to display
+/// it upright, such as a phone held in portrait or a camera mounted sideways.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VideoRotation {
+    #[default]
+    Rotate0,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl VideoRotation {
+    /// Create a rotation from degrees, the degrees are rounded to the nearest
+    /// quarter turn.
+    pub fn from_degrees(degrees: i32) -> Self {
+        match (degrees.rem_euclid(360) + 45) / 90 % 4 {
+            1 => Self::Rotate90,
+            2 => Self::Rotate180,
+            3 => Self::Rotate270,
+            _ => Self::Rotate0,
+        }
+    }
+
+    pub fn degrees(&self) -> i32 {
+        *self as i32 * 90
+    }
+}
+
+/// The order of the fields of an interlaced frame, both fields are woven
+/// into the frame line by line, such as the frames of an SDI or HDMI capture
+/// card with a 1080i source.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FieldOrder {
+    #[default]
+    Progressive,
+    /// The even lines are the field that is captured first.
+    TopFieldFirst,
+    /// The odd lines are the field that is captured first.
+    BottomFieldFirst,
 }
 
 /// A frame in a video stream.
@@ -107,6 +207,10 @@ pub enum VideoSubFormat {
 pub struct VideoFrame {
     pub format: VideoFormat,
     pub sub_format: VideoSubFormat,
+    /// The coded size of the frame, it may be padded to the alignment of the
+    /// encoder, the picture is then only the display size in the top left
+    /// corner. The textures may be larger still, such as the textures of the
+    /// hardware decoders.
     pub width: u32,
     pub height: u32,
     /// If the subformat is SW, the data layout is determined according to the
@@ -114,6 +218,24 @@ pub struct VideoFrame {
     /// format, All other sub formats use `data[0]`.
     pub data: [*const c_void; 3],
     pub linesize: [usize; 3],
+    /// The clockwise rotation that needs to be applied when displaying.
+    pub rotation: VideoRotation,
+    /// Whether the picture needs to be flipped horizontally when displaying,
+    /// this is usually the case for front cameras.
+    pub mirror: bool,
+    /// The capture time of the frame in microseconds, taken from the media
+    /// clock, this is zero if the source does not provide it.
+    pub timestamp: u64,
+    /// Whether the frame is interlaced, the sender deinterlaces the frames
+    /// before they are encoded if it is configured to.
+    pub field_order: FieldOrder,
+    /// The planes of the frame if the subformat is `DmaBuf`.
+    pub dma_buf: DmaBuf,
+    /// The visible area of the frame from the top left corner, the rest is
+    /// padding that is not displayed. Zero means the whole frame is visible,
+    /// see `display_size`.
+    pub display_width: u32,
+    pub display_height: u32,
 }
 
 unsafe impl Sync for VideoFrame {}
@@ -128,6 +250,345 @@ impl Default for VideoFrame {
             data: [null(), null(), null()],
             format: VideoFormat::RGBA,
             sub_format: VideoSubFormat::SW,
+            rotation: VideoRotation::Rotate0,
+            mirror: false,
+            timestamp: 0,
+            field_order: FieldOrder::Progressive,
+            dma_buf: DmaBuf::default(),
+            display_width: 0,
+            display_height: 0,
+        }
+    }
+}
+
+/// A pool of reusable buffers.
+///
+/// The buffers are returned to the pool when they are dropped, at most `limit`
+/// buffers are kept, the rest are released, which bounds the memory kept by
+/// the pool after a burst.
+pub struct FramePool<T> {
+    buffers: Mutex<Vec<Vec<T>>>,
+    limit: usize,
+}
+
+impl<T: Copy + Default> FramePool<T> {
+    pub fn new(limit: usize) -> Arc<Self> {
+        Arc::new(Self {
+            buffers: Mutex::new(Vec::with_capacity(limit)),
+            limit,
+        })
+    }
+
+    /// Take a buffer of the size from the pool, the content of the buffer is
+    /// whatever the previous user left in it.
+    pub fn get(self: &Arc<Self>, size: usize) -> PooledBuffer<T> {
+        let mut data = self
+            .buffers
+            .lock()
+            .ok()
+            .and_then(|mut it| it.pop())
+            .unwrap_or_default();
+
+        data.resize(size, T::default());
+        PooledBuffer {
+            pool: Arc::downgrade(self),
+            data,
+        }
+    }
+}
+
+/// A buffer taken from a `FramePool`.
+pub struct PooledBuffer<T> {
+    pool: Weak<FramePool<T>>,
+    data: Vec<T>,
+}
+
+impl<T> Deref for PooledBuffer<T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        &self.data
+    }
+}
+
+impl<T> DerefMut for PooledBuffer<T> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.data
+    }
+}
+
+impl<T> Drop for PooledBuffer<T> {
+    fn drop(&mut self) {
+        if let Some(pool) = self.pool.upgrade() {
+            if let Ok(mut buffers) = pool.buffers.lock() {
+                if buffers.len() < pool.limit {
+                    buffers.push(std::mem::take(&mut self.data));
+                }
+            }
+        }
+    }
+}
+
+/// The line size and the number of rows of each plane of a software frame,
+/// the planes are packed without padding.
+pub fn get_planes(format: VideoFormat, width: u32, height: u32) -> [(usize, usize); 3] {
+    let (width, height) = (width as usize, height as usize);
+    let (half_width, half_height) = (width.div_ceil(2), height.div_ceil(2));
+
+    match format {
+        VideoFormat::BGRA | VideoFormat::RGBA => [(width * 4, height), (0, 0), (0, 0)],
+        VideoFormat::NV12 => [(width, height), (half_width * 2, half_height), (0, 0)],
+        VideoFormat::I420 => [
+            (width, height),
+            (half_width, half_height),
+            (half_width, half_height),
+        ],
+        VideoFormat::P010 => [(width * 2, height), (half_width * 4, half_height), (0, 0)],
+    }
+}
+
+/// A software video frame whose planes are stored in a buffer of a
+/// `FramePool`, the frame is reference counted and cheap to clone.
+#[derive(Clone)]
+pub struct VideoFrameBuffer(Arc<VideoFrameBufferInner>);
+
+struct VideoFrameBufferInner {
+    buffer: PooledBuffer<u8>,
+    offsets: [usize; 3],
+    frame: VideoFrame,
+}
+
+impl VideoFrameBuffer {
+    /// Copy the planes of a software frame into a buffer of the pool, the
+    /// frames of the other sub formats are not in the memory and cannot be
+    /// copied, `None` is returned for them.
+    pub fn copy_from(pool: &Arc<FramePool<u8>>, frame: &VideoFrame) -> Option<Self> {
+        if frame.sub_format != VideoSubFormat::SW {
+            return None;
+        }
+
+        let planes = get_planes(frame.format, frame.width, frame.height);
+        let mut offsets = [0; 3];
+        let mut size = 0;
+        for (i, (linesize, rows)) in planes.iter().enumerate() {
+            offsets[i] = size;
+            size += linesize * rows;
         }
+
+        let mut buffer = pool.get(size);
+        for (i, (linesize, rows)) in planes.iter().enumerate() {
+            if *rows == 0 {
+                continue;
+            }
+
+            if frame.data[i].is_null() || frame.linesize[i] < *linesize {
+                return None;
+            }
+
+            // The source rows may be padded, they are copied one by one.
+            for row in 0..*rows {
+                let src = unsafe {
+                    std::slice::from_raw_parts(
+                        (frame.data[i] as *const u8).add(row * frame.linesize[i]),
+                        *linesize,
+                    )
+                };
+
+                let start = offsets[i] + row * linesize;
+                buffer[start..start + linesize].copy_from_slice(src);
+            }
+        }
+
+        let mut data = [null(); 3];
+        let mut linesize = [0; 3];
+        for (i, (size, rows)) in planes.iter().enumerate() {
+            if *rows > 0 {
+                data[i] = unsafe { buffer.as_ptr().add(offsets[i]) } as *const _;
+                linesize[i] = *size;
+            }
+        }
+
+        Some(Self(Arc::new(VideoFrameBufferInner {
+            frame: VideoFrame {
+                format: frame.format,
+                sub_format: VideoSubFormat::SW,
+                width: frame.width,
+                height: frame.height,
+                rotation: frame.rotation,
+                mirror: frame.mirror,
+                timestamp: frame.timestamp,
+                field_order: frame.field_order,
+                dma_buf: DmaBuf::default(),
+                display_width: frame.display_width,
+                display_height: frame.display_height,
+                linesize,
+                data,
+            },
+            offsets,
+            buffer,
+        })))
+    }
+
+    /// The frame that points to the planes of the buffer, the pointers stay
+    /// valid as long as the buffer is alive.
+    pub fn as_frame(&self) -> &VideoFrame {
+        &self.0.frame
+    }
+
+    /// The packed bytes of the plane.
+    pub fn plane(&self, index: usize) -> &[u8] {
+        let inner = &self.0;
+        let start = inner.offsets[index];
+        let rows = get_planes(inner.frame.format, inner.frame.width, inner.frame.height)[index].1;
+
+        &inner.buffer[start..start + inner.frame.linesize[index] * rows]
+    }
+}
+
+/// An audio frame whose samples are stored in a buffer of a `FramePool`, the
+/// frame is reference counted and cheap to clone.
+#[derive(Clone)]
+pub struct AudioFrameBuffer(Arc<AudioFrameBufferInner>);
+
+struct AudioFrameBufferInner {
+    buffer: PooledBuffer<i16>,
+    frame: AudioFrame,
+}
+
+impl AudioFrameBuffer {
+    /// Copy the samples of the frame into a buffer of the pool.
+    pub fn copy_from(pool: &Arc<FramePool<i16>>, frame: &AudioFrame) -> Self {
+        let mut buffer = pool.get(frame.frames as usize);
+        if !frame.data.is_null() {
+            buffer.copy_from_slice(unsafe {
+                std::slice::from_raw_parts(frame.data, frame.frames as usize)
+            });
+        }
+
+        Self(Arc::new(AudioFrameBufferInner {
+            frame: AudioFrame {
+                sample_rate: frame.sample_rate,
+                frames: frame.frames,
+                data: buffer.as_ptr(),
+                timestamp: frame.timestamp,
+            },
+            buffer,
+        }))
+    }
+
+    /// The frame that points to the samples of the buffer, the pointer stays
+    /// valid as long as the buffer is alive.
+    pub fn as_frame(&self) -> &AudioFrame {
+        &self.0.frame
+    }
+
+    pub fn samples(&self) -> &[i16] {
+        &self.0.buffer
+    }
+}
+
+// The pools used by `to_owned`, the limits cover a few frames that are kept by
+// the sinks at the same time.
+static VIDEO_POOL: OnceLock<Arc<FramePool<u8>>> = OnceLock::new();
+static AUDIO_POOL: OnceLock<Arc<FramePool<i16>>> = OnceLock::new();
+
+impl VideoFrame {
+    /// The size of the visible area of the frame, which is the coded size if
+    /// the frame has no padding.
+    pub fn display_size(&self) -> Size {
+        Size {
+            width: if self.display_width > 0 {
+                self.display_width.min(self.width)
+            } else {
+                self.width
+            },
+            height: if self.display_height > 0 {
+                self.display_height.min(self.height)
+            } else {
+                self.height
+            },
+        }
+    }
+
+    /// Copy the planes of the frame so that it can be kept after the callback
+    /// has returned, only the software frames can be copied, `None` is
+    /// returned for the textures.
+    pub fn to_owned(&self) -> Option<OwnedVideoFrame> {
+        self.to_owned_in(VIDEO_POOL.get_or_init(|| FramePool::new(8)))
+    }
+
+    /// Same as `to_owned`, but the planes are copied into a buffer of the
+    /// pool.
+    pub fn to_owned_in(&self, pool: &Arc<FramePool<u8>>) -> Option<OwnedVideoFrame> {
+        VideoFrameBuffer::copy_from(pool, self).map(OwnedVideoFrame)
+    }
+}
+
+impl AudioFrame {
+    /// Copy the samples of the frame so that it can be kept after the
+    /// callback has returned.
+    pub fn to_owned(&self) -> OwnedAudioFrame {
+        self.to_owned_in(AUDIO_POOL.get_or_init(|| FramePool::new(32)))
+    }
+
+    /// Same as `to_owned`, but the samples are copied into a buffer of the
+    /// pool.
+    pub fn to_owned_in(&self, pool: &Arc<FramePool<i16>>) -> OwnedAudioFrame {
+        OwnedAudioFrame(AudioFrameBuffer::copy_from(pool, self))
+    }
+}
+
+/// A video frame that owns its planes, it can be kept and sent to other
+/// threads, and cloning it does not copy the planes.
+///
+/// It dereferences to a `VideoFrame` that points to its planes, so it can be
+/// passed to anything that takes a `VideoFrame`.
+#[derive(Clone)]
+pub struct OwnedVideoFrame(VideoFrameBuffer);
+
+impl OwnedVideoFrame {
+    /// The packed bytes of the plane, the planes that are not used by the
+    /// format are empty.
+    pub fn plane(&self, index: usize) -> &[u8] {
+        self.0.plane(index)
+    }
+}
+
+impl Deref for OwnedVideoFrame {
+    type Target = VideoFrame;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_frame()
+    }
+}
+
+impl From<VideoFrameBuffer> for OwnedVideoFrame {
+    fn from(value: VideoFrameBuffer) -> Self {
+        Self(value)
+    }
+}
+
+/// An audio frame that owns its samples, it can be kept and sent to other
+/// threads, and cloning it does not copy the samples.
+#[derive(Clone)]
+pub struct OwnedAudioFrame(AudioFrameBuffer);
+
+impl OwnedAudioFrame {
+    pub fn samples(&self) -> &[i16] {
+        self.0.samples()
+    }
+}
+
+impl Deref for OwnedAudioFrame {
+    type Target = AudioFrame;
+
+    fn deref(&self) -> &Self::Target {
+        self.0.as_frame()
+    }
+}
+
+impl From<AudioFrameBuffer> for OwnedAudioFrame {
+    fn from(value: AudioFrameBuffer) -> Self {
+        Self(value)
     }
 }
@@ -0,0 +1,107 @@
+/// Mouse buttons of the input events.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+}
+
+impl MouseButton {
+    fn from_u8(value: u8) -> Option<Self> {
+        Some(match value {
+            0 => Self::Left,
+            1 => Self::Right,
+            2 => Self::Middle,
+            _ => return None,
+        })
+    }
+}
+
+/// Mouse and keyboard events sent by the receiver and injected into the
+/// system of the sender.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputEvent {
+    /// Move the cursor, the position is normalized to the size of the primary
+    /// display, `0.0` is the left or top edge and `1.0` is the right or bottom
+    /// edge.
+    MouseMove {
+        x: f32,
+        y: f32,
+    },
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    /// Scroll the wheel, the delta is in notches, positive values scroll up or
+    /// right.
+    MouseWheel {
+        x: i32,
+        y: i32,
+    },
+    /// Press or release a key, the code is the scan code of the PC keyboard
+    /// (set 1), the extended keys, such as the arrow keys, are prefixed with
+    /// `0xE0`, for example the up arrow is `0xE048`.
+    Key {
+        code: u16,
+        pressed: bool,
+    },
+}
+
+impl InputEvent {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(9);
+        match *self {
+            Self::MouseMove { x, y } => {
+                bytes.push(0);
+                bytes.extend_from_slice(&x.to_be_bytes());
+                bytes.extend_from_slice(&y.to_be_bytes());
+            }
+            Self::MouseButton { button, pressed } => {
+                bytes.push(1);
+                bytes.push(button as u8);
+                bytes.push(pressed as u8);
+            }
+            Self::MouseWheel { x, y } => {
+                bytes.push(2);
+                bytes.extend_from_slice(&x.to_be_bytes());
+                bytes.extend_from_slice(&y.to_be_bytes());
+            }
+            Self::Key { code, pressed } => {
+                bytes.push(3);
+                bytes.extend_from_slice(&code.to_be_bytes());
+                bytes.push(pressed as u8);
+            }
+        }
+
+        bytes
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (kind, bytes) = bytes.split_first()?;
+        let u32_at = |index: usize| -> Option<u32> {
+            Some(u32::from_be_bytes(
+                bytes.get(index..index + 4)?.try_into().ok()?,
+            ))
+        };
+
+        Some(match kind {
+            0 => Self::MouseMove {
+                x: f32::from_bits(u32_at(0)?),
+                y: f32::from_bits(u32_at(4)?),
+            },
+            1 => Self::MouseButton {
+                button: MouseButton::from_u8(*bytes.first()?)?,
+                pressed: *bytes.get(1)? != 0,
+            },
+            2 => Self::MouseWheel {
+                x: u32_at(0)? as i32,
+                y: u32_at(4)? as i32,
+            },
+            3 => Self::Key {
+                code: u16::from_be_bytes(bytes.get(0..2)?.try_into().ok()?),
+                pressed: *bytes.get(2)? != 0,
+            },
+            _ => return None,
+        })
+    }
+}
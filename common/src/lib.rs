@@ -1,6 +1,11 @@
 pub mod atomic;
+pub mod clock;
+pub mod crash;
 pub mod frame;
+pub mod input;
 pub mod logger;
+pub mod power;
+pub mod runtime;
 pub mod strings;
 
 #[cfg(target_os = "windows")]
@@ -9,8 +14,37 @@ pub mod win32;
 #[cfg(target_os = "macos")]
 pub mod macos;
 
-#[derive(Debug, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Size {
     pub width: u32,
     pub height: u32,
 }
+
+impl Size {
+    /// Round the width and height up to a multiple of the alignment, such as
+    /// the 2 pixels that the 4:2:0 formats require.
+    pub fn align(self, alignment: u32) -> Self {
+        Self {
+            width: self.width.next_multiple_of(alignment),
+            height: self.height.next_multiple_of(alignment),
+        }
+    }
+}
+
+/// Which graphics adapter is used when the system has multiple GPUs, the
+/// capture, the codecs and the renderer should use the same adapter, otherwise
+/// the textures are copied between the adapters.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AdapterPreference {
+    /// Prefer the adapter with the lowest power consumption, usually the
+    /// integrated GPU.
+    #[default]
+    LowPower,
+    /// Prefer the adapter with the highest performance, usually the discrete
+    /// GPU.
+    HighPerformance,
+    /// The adapter with the PCI vendor id and device id.
+    Device { vendor: u32, device: u32 },
+}
@@ -1,22 +1,23 @@
-#[cfg(all(not(debug_assertions)))]
-use std::fs::{create_dir, metadata};
+use std::{
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
 #[cfg(target_os = "android")]
 use std::ffi::{c_char, c_int};
 
 use fern::Dispatch;
-use log::LevelFilter;
+use log::{Level, LevelFilter, Log, Metadata, Record};
 use thiserror::Error;
 
-#[cfg(not(debug_assertions))]
 use chrono::Local;
 
 #[cfg(debug_assertions)]
 use fern::colors::{Color, ColoredLevelConfig};
 
-#[cfg(all(not(debug_assertions)))]
-use fern::DateBased;
-
 #[derive(Debug, Error)]
 pub enum LoggerInitError {
     #[error(transparent)]
@@ -25,24 +26,298 @@ pub enum LoggerInitError {
     IoError(#[from] std::io::Error),
 }
 
-#[allow(unused_variables)]
-pub fn init_logger(level: LevelFilter, path: Option<&str>) -> Result<(), LoggerInitError> {
-    let mut logger = Dispatch::new()
-        .level(level)
-        .level_for("wgpu", LevelFilter::Warn)
-        .level_for("wgpu_core", LevelFilter::Warn)
-        .level_for("wgpu_hal", LevelFilter::Warn)
-        .level_for("wgpu_hal::auxil::dxgi::exception", LevelFilter::Error);
+/// A log record that is passed to the callback of `set_callback`, the
+/// message is not formatted, so that the application can format and store
+/// the records itself.
+#[derive(Debug, Clone)]
+pub struct LogRecord<'a> {
+    pub level: Level,
+    /// The module that has written the record, such as `hylarana::sender`.
+    pub target: &'a str,
+    pub file: Option<&'a str>,
+    pub line: Option<u32>,
+    pub message: String,
+    /// The time of the record in milliseconds since the unix epoch.
+    pub timestamp: u64,
+}
+
+/// Write the logs to files in a directory, the file is rotated when it
+/// reaches the size.
+#[derive(Debug, Clone)]
+pub struct FileSinkOptions {
+    pub directory: PathBuf,
+    /// The name of the files, the current file is `{name}.log`, the rotated
+    /// files are `{name}.1.log`, `{name}.2.log` and so on, the larger the
+    /// number the older the file. `hylarana` by default.
+    pub name: String,
+    /// 10 MB by default.
+    pub max_size: u64,
+    /// How many rotated files are kept besides the current file, the older
+    /// files are deleted. 5 by default.
+    pub max_files: usize,
+}
+
+impl Default for FileSinkOptions {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::new(),
+            name: "hylarana".to_string(),
+            max_size: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoggerOptions {
+    pub level: LevelFilter,
+    /// The levels of the modules and their sub modules, they take precedence
+    /// over the level, such as `("hylarana_transport", LevelFilter::Debug)`.
+    /// The graphics modules of wgpu only log the warnings by default.
+    pub modules: Vec<(String, LevelFilter)>,
+    /// Print the logs to the standard output.
+    pub stdout: bool,
+    pub file: Option<FileSinkOptions>,
+}
+
+impl Default for LoggerOptions {
+    fn default() -> Self {
+        Self {
+            level: LevelFilter::Info,
+            modules: vec![
+                ("wgpu".to_string(), LevelFilter::Warn),
+                ("wgpu_core".to_string(), LevelFilter::Warn),
+                ("wgpu_hal".to_string(), LevelFilter::Warn),
+                (
+                    "wgpu_hal::auxil::dxgi::exception".to_string(),
+                    LevelFilter::Error,
+                ),
+            ],
+            stdout: true,
+            file: None,
+        }
+    }
+}
+
+type Callback = Arc<dyn Fn(&LogRecord) + Send + Sync>;
+
+struct Levels {
+    level: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Levels {
+    // The level of the module with the longest matching path.
+    fn get(&self, target: &str) -> LevelFilter {
+        self.modules
+            .iter()
+            .filter(|(module, _)| {
+                target == module
+                    || (target.starts_with(module.as_str())
+                        && target[module.len()..].starts_with("::"))
+            })
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, level)| *level)
+            .unwrap_or(self.level)
+    }
+
+    fn apply(&self) {
+        log::set_max_level(
+            self.modules
+                .iter()
+                .map(|(_, level)| *level)
+                .fold(self.level, Ord::max),
+        );
+    }
+}
+
+// The levels can be changed after the logger is installed, the logger passes
+// all records to the filter, which reads the current levels.
+static LEVELS: RwLock<Levels> = RwLock::new(Levels {
+    level: LevelFilter::Info,
+    modules: Vec::new(),
+});
 
-    #[cfg(debug_assertions)]
+static CALLBACK: RwLock<Option<Callback>> = RwLock::new(None);
+
+fn enabled(metadata: &Metadata) -> bool {
+    metadata.level()
+        <= LEVELS
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(metadata.target())
+}
+
+/// Change the level of the logs at runtime, the levels of the modules are
+/// kept.
+pub fn set_level(level: LevelFilter) {
+    let mut levels = LEVELS.write().unwrap_or_else(|e| e.into_inner());
+    levels.level = level;
+    levels.apply();
+}
+
+/// Change the level of a module and its sub modules at runtime, `None`
+/// removes the level of the module, so that it follows the level of the
+/// logs again.
+pub fn set_module_level(module: &str, level: Option<LevelFilter>) {
+    let mut levels = LEVELS.write().unwrap_or_else(|e| e.into_inner());
+    levels.modules.retain(|(it, _)| it != module);
+
+    if let Some(level) = level {
+        levels.modules.push((module.to_string(), level));
+    }
+
+    levels.apply();
+}
+
+/// Pass the records to the callback, in addition to the other sinks, such as
+/// to the log of a host application. `None` removes the callback. The
+/// callback is called on the thread that writes the record, it should not
+/// block, and the logs that it writes itself are dropped.
+pub fn set_callback<F>(callback: Option<F>)
+where
+    F: Fn(&LogRecord) + Send + Sync + 'static,
+{
+    *CALLBACK.write().unwrap_or_else(|e| e.into_inner()) =
+        callback.map(|it| Arc::new(it) as Callback);
+}
+
+thread_local! {
+    static IN_CALLBACK: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+fn callback(record: &Record) {
+    let Some(callback) = CALLBACK.read().unwrap_or_else(|e| e.into_inner()).clone() else {
+        return;
+    };
+
+    if IN_CALLBACK.replace(true) {
+        return;
+    }
+
+    callback(&LogRecord {
+        level: record.level(),
+        target: record.target(),
+        file: record.file(),
+        line: record.line(),
+        message: record.args().to_string(),
+        timestamp: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|it| it.as_millis() as u64)
+            .unwrap_or_default(),
+    });
+
+    IN_CALLBACK.set(false);
+}
+
+struct CallbackSink;
+
+impl Log for CallbackSink {
+    fn enabled(&self, _: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        callback(record);
+    }
+
+    fn flush(&self) {}
+}
+
+// A log file that is rotated when it reaches the size, the rotation happens
+// between the records, fern flushes the writer after every record.
+struct RotatingFile {
+    options: FileSinkOptions,
+    file: BufWriter<File>,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn new(options: FileSinkOptions) -> io::Result<Self> {
+        fs::create_dir_all(&options.directory)?;
+
+        let path = Self::path(&options, 0);
+        let file = File::options().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self {
+            file: BufWriter::new(file),
+            options,
+            size,
+        })
+    }
+
+    fn path(options: &FileSinkOptions, index: usize) -> PathBuf {
+        options.directory.join(if index == 0 {
+            format!("{}.log", options.name)
+        } else {
+            format!("{}.{}.log", options.name, index)
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        // The oldest file is replaced by the one before it.
+        for index in (0..self.options.max_files).rev() {
+            let from = Self::path(&self.options, index);
+            if Path::new(&from).exists() {
+                fs::rename(&from, Self::path(&self.options, index + 1))?;
+            }
+        }
+
+        if self.options.max_files == 0 {
+            fs::remove_file(Self::path(&self.options, 0))?;
+        }
+
+        self.file = BufWriter::new(File::create(Self::path(&self.options, 0))?);
+        self.size = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.file.write(buf)?;
+        self.size += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+
+        if self.size >= self.options.max_size {
+            self.rotate()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Install the logger with the sinks of the options, the logger can only be
+/// installed once in a process, the levels and the callback can be changed
+/// afterwards.
+pub fn init(options: LoggerOptions) -> Result<(), LoggerInitError> {
     {
-        let colors = ColoredLevelConfig::new()
-            .info(Color::Blue)
-            .warn(Color::Yellow)
-            .error(Color::Red);
+        let mut levels = LEVELS.write().unwrap_or_else(|e| e.into_inner());
+        levels.level = options.level;
+        levels.modules = options.modules;
+    }
+
+    let mut logger = Dispatch::new()
+        .level(LevelFilter::Trace)
+        .filter(enabled)
+        .chain(Box::new(CallbackSink) as Box<dyn Log>);
 
-        logger = logger
-            .format(move |out, message, record| {
+    if options.stdout {
+        #[cfg(debug_assertions)]
+        let stdout = {
+            let colors = ColoredLevelConfig::new()
+                .info(Color::Blue)
+                .warn(Color::Yellow)
+                .error(Color::Red);
+
+            Dispatch::new().format(move |out, message, record| {
                 out.finish(format_args!(
                     "[{}] - ({}) - {}",
                     colors.color(record.level()),
@@ -50,46 +325,55 @@ pub fn init_logger(level: LevelFilter, path: Option<&str>) -> Result<(), LoggerI
                     message
                 ))
             })
-            .chain(std::io::stdout());
-    }
+        };
 
-    #[cfg(not(debug_assertions))]
-    {
-        logger = logger.format(move |out, message, record| {
-            out.finish(format_args!(
-                "{} - [{}] - ({}) - {}",
-                Local::now().format("%m-%d %H:%M:%S"),
-                record.level(),
-                record.file_static().unwrap_or("*"),
-                message
-            ))
-        });
-
-        if let Some(path) = path {
-            if metadata(path).is_err() {
-                create_dir(path)?;
-            }
+        #[cfg(not(debug_assertions))]
+        let stdout = format(Dispatch::new());
 
-            logger = logger.chain(DateBased::new(path, "%Y-%m-%d-hylarana.log"))
-        } else {
-            logger = logger.chain(std::io::stdout());
-        }
+        logger = logger.chain(stdout.chain(std::io::stdout()));
     }
 
-    logger.apply()?;
-
-    #[cfg(not(debug_assertions))]
-    std::panic::set_hook(Box::new(|info| {
-        log::error!(
-            "pnaic: location={:?}, message={:?}",
-            info.location(),
-            info.payload().downcast_ref::<String>(),
+    if let Some(file) = options.file {
+        logger = logger.chain(
+            format(Dispatch::new())
+                .chain(Box::new(RotatingFile::new(file)?) as Box<dyn Write + Send>),
         );
-    }));
+    }
+
+    logger.apply()?;
+    LEVELS.read().unwrap_or_else(|e| e.into_inner()).apply();
 
     Ok(())
 }
 
+// The format of the files, and of the standard output in release builds.
+fn format(dispatch: Dispatch) -> Dispatch {
+    dispatch.format(move |out, message, record| {
+        out.finish(format_args!(
+            "{} - [{}] - ({}) - {}",
+            Local::now().format("%m-%d %H:%M:%S"),
+            record.level(),
+            record.file_static().unwrap_or("*"),
+            message
+        ))
+    })
+}
+
+/// Install the logger with the level, the logs are written to the files in
+/// the directory of the path if it is set, otherwise to the standard output,
+/// see `init`.
+pub fn init_logger(level: LevelFilter, path: Option<&str>) -> Result<(), LoggerInitError> {
+    init(LoggerOptions {
+        level,
+        stdout: path.is_none(),
+        file: path.map(|path| FileSinkOptions {
+            directory: PathBuf::from(path),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
 #[repr(C)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AndroidLogLevel {
@@ -135,12 +419,18 @@ pub struct AndroidLogger {
 
 impl log::Log for AndroidLogger {
     fn flush(&self) {}
-    fn enabled(&self, _: &log::Metadata) -> bool {
-        true
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        enabled(metadata)
     }
 
     #[allow(unused_variables)]
     fn log(&self, record: &log::Record) {
+        if !enabled(record.metadata()) {
+            return;
+        }
+
+        callback(record);
+
         #[cfg(target_os = "android")]
         unsafe {
             android_log_write(
@@ -158,17 +448,9 @@ impl log::Log for AndroidLogger {
 }
 
 pub fn init_with_android(package: &str, level: LevelFilter) {
-    log::set_max_level(level);
+    set_level(level);
     log::set_boxed_logger(Box::new(AndroidLogger {
         package: package.to_string(),
     }))
     .unwrap();
-
-    std::panic::set_hook(Box::new(|info| {
-        log::error!(
-            "pnaic: location={:?}, message={:?}",
-            info.location(),
-            info.payload().downcast_ref::<String>(),
-        );
-    }));
 }
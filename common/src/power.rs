@@ -0,0 +1,180 @@
+use std::{
+    io,
+    sync::mpsc::{channel, RecvTimeoutError, Sender},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// Where the system takes its power from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PowerSource {
+    /// The system is plugged in, the systems without a battery, such as the
+    /// desktops, are always on AC power.
+    Ac,
+    Battery,
+}
+
+/// Get the power source of the system, `None` if the system does not tell.
+pub fn power_source() -> Option<PowerSource> {
+    match source() {
+        Ok(it) => it,
+        Err(e) => {
+            log::warn!("failed to get power source, err={:?}", e);
+
+            None
+        }
+    }
+}
+
+/// Watches the power source of the system, the handler is called on the
+/// thread of the monitor when the source changes. The monitor stops when it is
+/// dropped.
+pub struct PowerMonitor(#[allow(dead_code)] Sender<()>);
+
+impl PowerMonitor {
+    // The systems do not notify the changes of the power source in the same way,
+    // the source is polled at this interval on all of them.
+    const INTERVAL: Duration = Duration::from_secs(5);
+
+    pub fn new<F>(handler: F) -> io::Result<Self>
+    where
+        F: Fn(PowerSource) + Send + 'static,
+    {
+        let (tx, rx) = channel::<()>();
+        thread::Builder::new()
+            .name("HylaranaPowerMonitorThread".to_string())
+            .spawn(move || {
+                let mut current = power_source();
+
+                // The channel is disconnected when the monitor is dropped.
+                while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(Self::INTERVAL) {
+                    // The failure has been logged by the first call.
+                    let source = source().ok().flatten();
+                    if source != current {
+                        log::info!("power source changed, source={:?}", source);
+
+                        current = source;
+                        if let Some(source) = source {
+                            handler(source);
+                        }
+                    }
+                }
+            })?;
+
+        Ok(Self(tx))
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn source() -> io::Result<Option<PowerSource>> {
+    use windows::Win32::System::Power::{GetSystemPowerStatus, SYSTEM_POWER_STATUS};
+
+    let mut status = SYSTEM_POWER_STATUS::default();
+    unsafe { GetSystemPowerStatus(&mut status) }.map_err(io::Error::other)?;
+
+    // 128 of the battery flag is no system battery, 255 of the line status is
+    // unknown.
+    Ok(if status.BatteryFlag == 128 {
+        Some(PowerSource::Ac)
+    } else {
+        match status.ACLineStatus {
+            0 => Some(PowerSource::Battery),
+            1 => Some(PowerSource::Ac),
+            _ => None,
+        }
+    })
+}
+
+#[cfg(target_os = "macos")]
+fn source() -> io::Result<Option<PowerSource>> {
+    use std::ffi::{c_char, c_void, CStr};
+
+    #[link(name = "IOKit", kind = "framework")]
+    extern "C" {
+        fn IOPSCopyPowerSourcesInfo() -> *const c_void;
+        fn IOPSGetProvidingPowerSourceType(snapshot: *const c_void) -> *const c_void;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(cf: *const c_void);
+        fn CFStringGetCString(
+            string: *const c_void,
+            buffer: *mut c_char,
+            size: isize,
+            encoding: u32,
+        ) -> u8;
+    }
+
+    const K_CF_STRING_ENCODING_UTF8: u32 = 0x08000100;
+
+    unsafe {
+        let snapshot = IOPSCopyPowerSourcesInfo();
+        if snapshot.is_null() {
+            return Err(io::Error::other("failed to copy power sources info"));
+        }
+
+        // The type is owned by the snapshot.
+        let kind = IOPSGetProvidingPowerSourceType(snapshot);
+        let mut buffer = [0 as c_char; 64];
+        let ok = !kind.is_null()
+            && CFStringGetCString(
+                kind,
+                buffer.as_mut_ptr(),
+                buffer.len() as isize,
+                K_CF_STRING_ENCODING_UTF8,
+            ) != 0;
+
+        CFRelease(snapshot);
+
+        if !ok {
+            return Ok(None);
+        }
+
+        Ok(match CStr::from_ptr(buffer.as_ptr()).to_bytes() {
+            b"Battery Power" => Some(PowerSource::Battery),
+            b"AC Power" | b"UPS Power" => Some(PowerSource::Ac),
+            _ => None,
+        })
+    }
+}
+
+// UPower takes the power source from the power supplies of the kernel, they
+// are read here directly, so that there is no dependency on D-Bus.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn source() -> io::Result<Option<PowerSource>> {
+    use std::fs;
+
+    let read = |path: &std::path::Path, name: &str| {
+        fs::read_to_string(path.join(name))
+            .map(|it| it.trim().to_string())
+            .unwrap_or_default()
+    };
+
+    // The virtual machines usually have no power supplies at all.
+    let entries = match fs::read_dir("/sys/class/power_supply") {
+        Ok(it) => it,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Some(PowerSource::Ac)),
+        Err(e) => return Err(e),
+    };
+
+    let mut battery = false;
+    let mut online = false;
+    for entry in entries {
+        let path = entry?.path();
+        match read(&path, "type").as_str() {
+            // The batteries of the peripherals, such as a mouse, have the device scope.
+            "Battery" => battery |= read(&path, "scope") != "Device",
+            "Mains" | "USB" => online |= read(&path, "online") == "1",
+            _ => (),
+        }
+    }
+
+    Ok(Some(if battery && !online {
+        PowerSource::Battery
+    } else {
+        PowerSource::Ac
+    }))
+}
@@ -0,0 +1,272 @@
+use std::{
+    io,
+    sync::RwLock,
+    thread::{self, JoinHandle},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The threads of the media pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ThreadKind {
+    /// The threads of the capture sources.
+    Capture,
+    /// The threads that encode the frames of the sender. The frames are
+    /// encoded on the threads of the capture sources that deliver them, these
+    /// threads take the options of this kind instead of the capture ones when
+    /// they encode their first frame.
+    Encode,
+    /// The decoder threads of the receiver, the audio frames are also passed
+    /// to the sink on them.
+    Decode,
+    /// The threads that send and receive the packets.
+    Transport,
+    /// The threads that render the frames, the video frames of the receiver
+    /// are passed to the sink on such a thread, apart from the decoder. The
+    /// threads of the application, such as the render loop of a game engine,
+    /// can also apply the options with `apply_thread_options`.
+    Render,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ThreadPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    /// The real-time scheduling of the system, such as `SCHED_RR` on Linux,
+    /// which usually needs the privileges of an administrator, the thread
+    /// keeps its priority if it is not allowed.
+    Realtime,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThreadOptions {
+    /// `None` keeps the priority that the thread is created with.
+    #[serde(default)]
+    pub priority: Option<ThreadPriority>,
+    /// The indexes of the CPUs that the thread runs on, `None` lets the
+    /// system choose. It is ignored on macOS, which does not support it.
+    #[serde(default)]
+    pub affinity: Option<Vec<usize>>,
+}
+
+/// The options of the threads of the pipeline, see `set_runtime_options`.
+/// The threads that are already running keep their options.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeOptions {
+    #[serde(default)]
+    pub capture: ThreadOptions,
+    #[serde(default)]
+    pub encode: ThreadOptions,
+    #[serde(default)]
+    pub decode: ThreadOptions,
+    #[serde(default)]
+    pub transport: ThreadOptions,
+    #[serde(default)]
+    pub render: ThreadOptions,
+}
+
+impl RuntimeOptions {
+    pub fn get(&self, kind: ThreadKind) -> &ThreadOptions {
+        match kind {
+            ThreadKind::Capture => &self.capture,
+            ThreadKind::Encode => &self.encode,
+            ThreadKind::Decode => &self.decode,
+            ThreadKind::Transport => &self.transport,
+            ThreadKind::Render => &self.render,
+        }
+    }
+}
+
+static OPTIONS: RwLock<RuntimeOptions> = RwLock::new(RuntimeOptions {
+    capture: ThreadOptions {
+        priority: None,
+        affinity: None,
+    },
+    encode: ThreadOptions {
+        priority: None,
+        affinity: None,
+    },
+    decode: ThreadOptions {
+        priority: None,
+        affinity: None,
+    },
+    transport: ThreadOptions {
+        priority: None,
+        affinity: None,
+    },
+    render: ThreadOptions {
+        priority: None,
+        affinity: None,
+    },
+});
+
+/// Set the priority and the affinity of the threads of the pipeline, the
+/// options are applied to the threads that are created afterwards.
+pub fn set_runtime_options(options: RuntimeOptions) {
+    log::info!("set runtime options, options={:?}", options);
+
+    *OPTIONS.write().unwrap_or_else(|e| e.into_inner()) = options;
+}
+
+pub fn runtime_options() -> RuntimeOptions {
+    OPTIONS.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// Apply the options of the kind to the current thread, the threads of the
+/// pipeline do it when they start. The failures are logged, the thread keeps
+/// running with the options of the system.
+pub fn apply_thread_options(kind: ThreadKind) {
+    let options = OPTIONS
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .get(kind)
+        .clone();
+
+    if let Some(priority) = options.priority {
+        if let Err(e) = set_thread_priority(priority) {
+            log::warn!(
+                "failed to set thread priority, kind={:?}, priority={:?}, err={:?}",
+                kind,
+                priority,
+                e
+            );
+        }
+    }
+
+    if let Some(affinity) = options.affinity.as_ref() {
+        if let Err(e) = set_thread_affinity(affinity) {
+            log::warn!(
+                "failed to set thread affinity, kind={:?}, affinity={:?}, err={:?}",
+                kind,
+                affinity,
+                e
+            );
+        }
+    }
+}
+
+/// Spawn a thread of the pipeline, the thread is named `Hylarana{name}Thread`
+/// and the options of the kind are applied to it before the function runs.
+pub fn spawn<F, T>(kind: ThreadKind, name: &str, func: F) -> io::Result<JoinHandle<T>>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    thread::Builder::new()
+        .name(format!("Hylarana{}Thread", name))
+        .spawn(move || {
+            apply_thread_options(kind);
+
+            func()
+        })
+}
+
+#[cfg(target_os = "windows")]
+fn set_thread_priority(priority: ThreadPriority) -> io::Result<()> {
+    use windows::Win32::System::Threading::{
+        GetCurrentThread, SetThreadPriority, THREAD_PRIORITY_BELOW_NORMAL, THREAD_PRIORITY_HIGHEST,
+        THREAD_PRIORITY_NORMAL, THREAD_PRIORITY_TIME_CRITICAL,
+    };
+
+    unsafe {
+        SetThreadPriority(
+            GetCurrentThread(),
+            match priority {
+                ThreadPriority::Low => THREAD_PRIORITY_BELOW_NORMAL,
+                ThreadPriority::Normal => THREAD_PRIORITY_NORMAL,
+                ThreadPriority::High => THREAD_PRIORITY_HIGHEST,
+                ThreadPriority::Realtime => THREAD_PRIORITY_TIME_CRITICAL,
+            },
+        )
+    }
+    .map_err(io::Error::other)
+}
+
+#[cfg(target_os = "windows")]
+fn set_thread_affinity(affinity: &[usize]) -> io::Result<()> {
+    use windows::Win32::System::Threading::{GetCurrentThread, SetThreadAffinityMask};
+
+    let mask = affinity
+        .iter()
+        .filter(|it| **it < usize::BITS as usize)
+        .fold(0usize, |mask, it| mask | (1 << it));
+
+    if unsafe { SetThreadAffinityMask(GetCurrentThread(), mask) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_thread_priority(priority: ThreadPriority) -> io::Result<()> {
+    // The nice value of Linux is per thread, a negative value needs
+    // CAP_SYS_NICE.
+    let nice = match priority {
+        ThreadPriority::Low => 5,
+        ThreadPriority::Normal => 0,
+        ThreadPriority::High => -10,
+        ThreadPriority::Realtime => {
+            let param = libc::sched_param { sched_priority: 10 };
+            if unsafe { libc::sched_setscheduler(0, libc::SCHED_RR, &param) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            return Ok(());
+        }
+    };
+
+    let tid = unsafe { libc::gettid() };
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, tid as _, nice) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn set_thread_affinity(affinity: &[usize]) -> io::Result<()> {
+    unsafe {
+        // CPU_SET panics on the indexes that the set cannot hold.
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for cpu in affinity
+            .iter()
+            .filter(|it| **it < libc::CPU_SETSIZE as usize)
+        {
+            libc::CPU_SET(*cpu, &mut set);
+        }
+
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn set_thread_priority(priority: ThreadPriority) -> io::Result<()> {
+    // The threads of macOS are scheduled by their quality of service.
+    let class = match priority {
+        ThreadPriority::Low => libc::qos_class_t::QOS_CLASS_UTILITY,
+        ThreadPriority::Normal => libc::qos_class_t::QOS_CLASS_DEFAULT,
+        ThreadPriority::High => libc::qos_class_t::QOS_CLASS_USER_INITIATED,
+        ThreadPriority::Realtime => libc::qos_class_t::QOS_CLASS_USER_INTERACTIVE,
+    };
+
+    let code = unsafe { libc::pthread_set_qos_class_self_np(class, 0) };
+    if code != 0 {
+        return Err(io::Error::from_raw_os_error(code));
+    }
+
+    Ok(())
+}
+
+// macOS has no thread affinity, the threads are only placed by their quality
+// of service, so the affinity is ignored instead of warning on every thread.
+#[cfg(target_os = "macos")]
+fn set_thread_affinity(_: &[usize]) -> io::Result<()> {
+    Ok(())
+}
@@ -1,30 +1,52 @@
-use std::{cell::Cell, ffi::c_void};
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    ffi::c_void,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        mpsc::channel,
+        Arc, Mutex, PoisonError, Weak,
+    },
+    thread,
+};
 
-use crate::Size;
+use crate::{atomic::EasyAtomic, AdapterPreference, Size};
 
 pub use windows;
 
 use windows::{
-    core::{s, Interface, Result, GUID, HSTRING, PCSTR, PCWSTR, PWSTR},
+    core::{s, w, Interface, Result, GUID, HSTRING, PCSTR, PCWSTR, PWSTR},
     Win32::{
-        Foundation::{HANDLE, HWND, RECT},
+        Foundation::{BOOL, HANDLE, HWND, LPARAM, LRESULT, RECT, WPARAM},
         Graphics::{
             Direct3D::{
-                D3D_DRIVER_TYPE_HARDWARE, D3D_FEATURE_LEVEL, D3D_FEATURE_LEVEL_11_0,
-                D3D_FEATURE_LEVEL_11_1,
+                D3D_DRIVER_TYPE_HARDWARE, D3D_DRIVER_TYPE_UNKNOWN, D3D_FEATURE_LEVEL,
+                D3D_FEATURE_LEVEL_11_0, D3D_FEATURE_LEVEL_11_1,
             },
             Direct3D11::{
                 D3D11CreateDevice, ID3D11Device, ID3D11DeviceContext, ID3D11Multithread,
-                ID3D11Texture2D, D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_SDK_VERSION,
-                D3D11_TEXTURE2D_DESC,
+                ID3D11Query, ID3D11Texture2D, D3D11_ASYNC_GETDATA_DONOTFLUSH,
+                D3D11_CREATE_DEVICE_BGRA_SUPPORT, D3D11_QUERY_DESC, D3D11_QUERY_EVENT,
+                D3D11_SDK_VERSION, D3D11_TEXTURE2D_DESC,
+            },
+            Dxgi::{
+                CreateDXGIFactory1, IDXGIAdapter, IDXGIFactory1, IDXGIFactory6, IDXGIResource,
+                DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE, DXGI_GPU_PREFERENCE_MINIMUM_POWER,
+            },
+            Gdi::{
+                EnumDisplayMonitors, GetMonitorInfoW, HDC, HMONITOR, MONITORINFO, MONITORINFOEXW,
             },
-            Dxgi::IDXGIResource,
         },
         Media::MediaFoundation::{
             IMFActivate, IMFAttributes, IMFMediaType, MFShutdown, MFStartup, MF_VERSION,
         },
         System::{
             Com::{CoInitializeEx, CoUninitialize, COINIT_MULTITHREADED},
+            LibraryLoader::GetModuleHandleW,
+            RemoteDesktop::{
+                WTSRegisterSessionNotification, WTSUnRegisterSessionNotification,
+                NOTIFY_FOR_THIS_SESSION,
+            },
             Threading::{
                 AvRevertMmThreadCharacteristics, AvSetMmThreadCharacteristicsA, GetCurrentProcess,
                 SetPriorityClass, BELOW_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS,
@@ -32,7 +54,14 @@ use windows::{
                 REALTIME_PRIORITY_CLASS,
             },
         },
-        UI::WindowsAndMessaging::GetClientRect,
+        UI::WindowsAndMessaging::{
+            CreateWindowExW, DefWindowProcW, DestroyWindow, DispatchMessageW, GetClientRect,
+            GetMessageW, GetWindowLongPtrW, GetWindowRect, IsIconic, IsWindowVisible, PostMessageW,
+            PostQuitMessage, RegisterClassW, SetWindowLongPtrW, TranslateMessage, GWLP_USERDATA,
+            MSG, PBT_APMRESUMEAUTOMATIC, PBT_APMSUSPEND, WINDOW_EX_STYLE, WINDOW_STYLE, WM_CLOSE,
+            WM_DESTROY, WM_POWERBROADCAST, WM_WTSSESSION_CHANGE, WNDCLASSW, WTS_SESSION_LOCK,
+            WTS_SESSION_UNLOCK,
+        },
     },
 };
 
@@ -48,6 +77,71 @@ pub fn get_hwnd_size(hwnd: HWND) -> Result<Size> {
     })
 }
 
+/// Get the area that a window covers on a display, in the pixels of the
+/// display from its top left corner, and the size of the display. The display
+/// is the device name of the monitor, such as `\\.\DISPLAY1`, which is the id
+/// of the screen sources. `None` if the window is hidden or minimized, or if
+/// the display does not exist.
+pub fn get_window_area(hwnd: HWND, display: &str) -> Option<(RECT, Size)> {
+    unsafe {
+        if !IsWindowVisible(hwnd).as_bool() || IsIconic(hwnd).as_bool() {
+            return None;
+        }
+    }
+
+    let mut window = RECT::default();
+    unsafe { GetWindowRect(hwnd, &mut window) }.ok()?;
+
+    unsafe extern "system" fn find(monitor: HMONITOR, _: HDC, _: *mut RECT, data: LPARAM) -> BOOL {
+        let (display, found) = &mut *(data.0 as *mut (&str, Option<RECT>));
+
+        let mut info = MONITORINFOEXW::default();
+        info.monitorInfo.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+        if GetMonitorInfoW(monitor, &mut info as *mut _ as *mut MONITORINFO).as_bool() {
+            let size = info
+                .szDevice
+                .iter()
+                .position(|it| *it == 0)
+                .unwrap_or(info.szDevice.len());
+
+            if String::from_utf16_lossy(&info.szDevice[..size]) == *display {
+                found.replace(info.monitorInfo.rcMonitor);
+
+                // Stops the enumeration.
+                return false.into();
+            }
+        }
+
+        true.into()
+    }
+
+    let mut search = (display, None::<RECT>);
+    unsafe {
+        let _ = EnumDisplayMonitors(
+            HDC::default(),
+            None,
+            Some(find),
+            LPARAM(&mut search as *mut _ as isize),
+        );
+    }
+
+    // The window and the monitor are in the same coordinates of the virtual
+    // screen.
+    let monitor = search.1?;
+    Some((
+        RECT {
+            left: window.left - monitor.left,
+            top: window.top - monitor.top,
+            right: window.right - monitor.left,
+            bottom: window.bottom - monitor.top,
+        },
+        Size {
+            width: (monitor.right - monitor.left) as u32,
+            height: (monitor.bottom - monitor.top) as u32,
+        },
+    ))
+}
+
 /// Initializes Microsoft Media Foundation.
 pub fn startup() -> Result<()> {
     unsafe {
@@ -232,13 +326,26 @@ pub struct Direct3DDevice {
 
 impl Direct3DDevice {
     pub fn new() -> Result<Direct3DDevice> {
+        Self::with_adapter(AdapterPreference::default())
+    }
+
+    /// Create the device on the adapter chosen by the preference, the default
+    /// adapter of the system is used if no adapter matches the preference.
+    pub fn with_adapter(preference: AdapterPreference) -> Result<Direct3DDevice> {
+        let adapter = find_adapter(preference)?;
+
         unsafe {
             let (mut d3d_device, mut d3d_context, mut feature_level) =
                 (None, None, D3D_FEATURE_LEVEL::default());
 
+            // The driver type must be unknown when the adapter is specified.
             D3D11CreateDevice(
-                None,
-                D3D_DRIVER_TYPE_HARDWARE,
+                adapter.as_ref(),
+                if adapter.is_some() {
+                    D3D_DRIVER_TYPE_UNKNOWN
+                } else {
+                    D3D_DRIVER_TYPE_HARDWARE
+                },
                 None,
                 D3D11_CREATE_DEVICE_BGRA_SUPPORT,
                 Some(&[D3D_FEATURE_LEVEL_11_1, D3D_FEATURE_LEVEL_11_0]),
@@ -255,6 +362,13 @@ impl Direct3DDevice {
         }
     }
 
+    /// Whether the device has been removed, such as by a GPU reset or a driver
+    /// update. A removed device cannot be used anymore, everything that was
+    /// created on it has to be created again on a new device.
+    pub fn is_removed(&self) -> bool {
+        unsafe { self.device.GetDeviceRemovedReason() }.is_err()
+    }
+
     pub fn set_multithread_protected(&self, value: bool) -> Result<()> {
         let multithread = self.device.cast::<ID3D11Multithread>()?;
         let _ = unsafe { multithread.SetMultithreadProtected(value) };
@@ -271,6 +385,198 @@ impl Direct3DDevice {
     }
 }
 
+fn find_adapter(preference: AdapterPreference) -> Result<Option<IDXGIAdapter>> {
+    let factory = unsafe { CreateDXGIFactory1::<IDXGIFactory1>()? };
+
+    Ok(match preference {
+        AdapterPreference::LowPower | AdapterPreference::HighPerformance => {
+            // Choosing the adapter by the power preference requires DXGI 1.6, which is
+            // not available on older systems.
+            factory
+                .cast::<IDXGIFactory6>()
+                .ok()
+                .and_then(|factory| unsafe {
+                    factory
+                        .EnumAdapterByGpuPreference::<IDXGIAdapter>(
+                            0,
+                            if preference == AdapterPreference::LowPower {
+                                DXGI_GPU_PREFERENCE_MINIMUM_POWER
+                            } else {
+                                DXGI_GPU_PREFERENCE_HIGH_PERFORMANCE
+                            },
+                        )
+                        .ok()
+                })
+        }
+        AdapterPreference::Device { vendor, device } => {
+            let mut index = 0;
+            loop {
+                // Returns an error when the index is out of range.
+                let Ok(adapter) = (unsafe { factory.EnumAdapters1(index) }) else {
+                    break None;
+                };
+
+                let desc = unsafe { adapter.GetDesc1()? };
+                if desc.VendorId == vendor && desc.DeviceId == device {
+                    break Some(adapter.cast::<IDXGIAdapter>()?);
+                }
+
+                index += 1;
+            }
+        }
+    })
+}
+
+/// A change of the session or the power state of the system, the screen
+/// cannot be captured while the session is locked, because the lock screen is
+/// on the secure desktop, or while the system is suspended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SystemEvent {
+    Locked,
+    Unlocked,
+    Suspended,
+    Resumed,
+}
+
+type SystemEventHandler = Box<dyn Fn(SystemEvent) + Send + Sync>;
+
+/// Listens to the session and power notifications of the system on a hidden
+/// window, which has its own thread and message loop. The handler is called on
+/// that thread, and the listener stops when it is dropped.
+pub struct SystemEventListener(isize);
+
+impl SystemEventListener {
+    pub fn new<F>(handler: F) -> std::io::Result<Self>
+    where
+        F: Fn(SystemEvent) + Send + Sync + 'static,
+    {
+        let handler: SystemEventHandler = Box::new(handler);
+
+        let (tx, rx) = channel();
+        thread::Builder::new()
+            .name("SystemEventListenerThread".to_string())
+            .spawn(move || {
+                // The window keeps a pointer to the handler, the handler lives on this
+                // thread until the window is destroyed.
+                let hwnd = match unsafe { create_system_event_window(&handler) } {
+                    Ok(it) => it,
+                    Err(e) => {
+                        let _ = tx.send(Err(e));
+                        return;
+                    }
+                };
+
+                let _ = tx.send(Ok(hwnd.0 as isize));
+
+                let mut message = MSG::default();
+                unsafe {
+                    while GetMessageW(&mut message, None, 0, 0).as_bool() {
+                        let _ = TranslateMessage(&message);
+                        DispatchMessageW(&message);
+                    }
+                }
+            })?;
+
+        Ok(Self(
+            rx.recv()
+                .map_err(|_| std::io::Error::other("system event listener thread exited"))?
+                .map_err(std::io::Error::other)?,
+        ))
+    }
+}
+
+impl Drop for SystemEventListener {
+    fn drop(&mut self) {
+        // The window is destroyed on its own thread, which ends the message loop.
+        if let Err(e) = unsafe { PostMessageW(HWND(self.0 as _), WM_CLOSE, WPARAM(0), LPARAM(0)) } {
+            log::warn!("failed to close system event listener, err={:?}", e);
+        }
+    }
+}
+
+unsafe fn create_system_event_window(handler: &SystemEventHandler) -> Result<HWND> {
+    let instance = GetModuleHandleW(None)?;
+    let class = w!("HylaranaSystemEventWindow");
+
+    // The class is registered once per process, registering it again fails, which
+    // is not an error.
+    RegisterClassW(&WNDCLASSW {
+        lpfnWndProc: Some(system_event_proc),
+        hInstance: instance.into(),
+        lpszClassName: class,
+        ..Default::default()
+    });
+
+    // A message-only window does not receive the broadcasts of the power
+    // notifications, so this is a top-level window that is never shown.
+    let hwnd = CreateWindowExW(
+        WINDOW_EX_STYLE::default(),
+        class,
+        w!(""),
+        WINDOW_STYLE::default(),
+        0,
+        0,
+        0,
+        0,
+        None,
+        None,
+        instance,
+        None,
+    )?;
+
+    SetWindowLongPtrW(
+        hwnd,
+        GWLP_USERDATA,
+        handler as *const SystemEventHandler as isize,
+    );
+
+    if let Err(e) = WTSRegisterSessionNotification(hwnd, NOTIFY_FOR_THIS_SESSION) {
+        let _ = DestroyWindow(hwnd);
+
+        return Err(e);
+    }
+
+    Ok(hwnd)
+}
+
+unsafe extern "system" fn system_event_proc(
+    hwnd: HWND,
+    message: u32,
+    wparam: WPARAM,
+    lparam: LPARAM,
+) -> LRESULT {
+    let event = match message {
+        WM_WTSSESSION_CHANGE => match wparam.0 as u32 {
+            WTS_SESSION_LOCK => Some(SystemEvent::Locked),
+            WTS_SESSION_UNLOCK => Some(SystemEvent::Unlocked),
+            _ => None,
+        },
+        // The automatic resume is always sent, the resume by the user only follows it
+        // when the user is present.
+        WM_POWERBROADCAST => match wparam.0 as u32 {
+            PBT_APMSUSPEND => Some(SystemEvent::Suspended),
+            PBT_APMRESUMEAUTOMATIC => Some(SystemEvent::Resumed),
+            _ => None,
+        },
+        WM_DESTROY => {
+            let _ = WTSUnRegisterSessionNotification(hwnd);
+            PostQuitMessage(0);
+
+            return LRESULT(0);
+        }
+        _ => None,
+    };
+
+    if let Some(event) = event {
+        let handler = GetWindowLongPtrW(hwnd, GWLP_USERDATA) as *const SystemEventHandler;
+        if let Some(handler) = handler.as_ref() {
+            handler(event);
+        }
+    }
+
+    DefWindowProcW(hwnd, message, wparam, lparam)
+}
+
 #[inline]
 pub fn d3d_texture_borrowed_raw<'a>(raw: &'a *mut c_void) -> Option<&'a ID3D11Texture2D> {
     unsafe { ID3D11Texture2D::from_raw_borrowed(raw) }
@@ -307,3 +613,202 @@ impl EasyTexture for ID3D11Texture2D {
         desc
     }
 }
+
+static TEXTURES_CREATED: AtomicU64 = AtomicU64::new(0);
+static TEXTURES_REUSED: AtomicU64 = AtomicU64::new(0);
+static TEXTURE_WAITS: AtomicU64 = AtomicU64::new(0);
+
+/// The counters of all the texture pools of the process, they only increase,
+/// see `TexturePool`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TexturePoolStats {
+    /// The textures that have been created, because the pools had none that
+    /// the GPU had finished with.
+    pub created: u64,
+    /// The textures that have been taken from the pools again.
+    pub reused: u64,
+    /// The times that a texture was taken before the GPU had finished with
+    /// it, and the CPU waited for the GPU.
+    pub waits: u64,
+}
+
+impl TexturePoolStats {
+    pub fn take() -> Self {
+        Self {
+            created: TEXTURES_CREATED.get(),
+            reused: TEXTURES_REUSED.get(),
+            waits: TEXTURE_WAITS.get(),
+        }
+    }
+}
+
+/// A pool of Direct3D11 textures of the same description, the capture, the
+/// video processor and the encoder pass these textures between them instead of
+/// creating or copying a texture for each frame.
+///
+/// A texture is returned to the pool when the `PooledTexture` is dropped, an
+/// event query is issued on the device then, and the texture is not written
+/// again until the GPU has passed the query, so the work that reads it, such as
+/// the encoder, has finished with it. The pool creates up to `limit` textures,
+/// after that the oldest returned texture is waited for.
+#[derive(Clone)]
+pub struct TexturePool(Arc<TexturePoolInner>);
+
+unsafe impl Sync for TexturePool {}
+unsafe impl Send for TexturePool {}
+
+struct TexturePoolInner {
+    direct3d: Direct3DDevice,
+    desc: D3D11_TEXTURE2D_DESC,
+    limit: usize,
+    // The textures that exist, in the pool or taken from it.
+    count: AtomicUsize,
+    // The returned textures and their fences, the oldest first.
+    textures: Mutex<VecDeque<(ID3D11Texture2D, ID3D11Query)>>,
+}
+
+impl TexturePoolInner {
+    // Whether the GPU has passed the fence, the commands are flushed to the GPU
+    // when waiting, otherwise the query may never be passed.
+    fn is_finished(&self, fence: &ID3D11Query, flush: bool) -> bool {
+        let mut finished = BOOL::default();
+        unsafe {
+            self.direct3d.context.GetData(
+                fence,
+                Some(&mut finished as *mut _ as *mut _),
+                std::mem::size_of::<BOOL>() as u32,
+                if flush {
+                    0
+                } else {
+                    D3D11_ASYNC_GETDATA_DONOTFLUSH.0 as u32
+                },
+            )
+        }
+        .is_ok()
+            && finished.as_bool()
+    }
+}
+
+impl TexturePool {
+    pub fn new(direct3d: Direct3DDevice, desc: D3D11_TEXTURE2D_DESC, limit: usize) -> Self {
+        Self(Arc::new(TexturePoolInner {
+            textures: Mutex::new(VecDeque::with_capacity(limit)),
+            count: AtomicUsize::new(0),
+            direct3d,
+            desc,
+            limit,
+        }))
+    }
+
+    /// Take a texture that the GPU has finished with, the content of the
+    /// texture is whatever the previous user left in it.
+    pub fn get(&self) -> Result<PooledTexture> {
+        let (texture, waited) = {
+            let mut textures = self
+                .0
+                .textures
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+
+            if let Some(index) = textures
+                .iter()
+                .position(|(_, fence)| self.0.is_finished(fence, false))
+            {
+                (textures.remove(index), false)
+            } else if self.0.count.get() < self.0.limit || textures.is_empty() {
+                (None, false)
+            } else {
+                (textures.pop_front(), true)
+            }
+        };
+
+        let texture = if let Some((texture, fence)) = texture {
+            // The lock is not held while waiting, the other textures can still be
+            // returned.
+            if waited {
+                TEXTURE_WAITS.fetch_add(1, Ordering::Relaxed);
+
+                while !self.0.is_finished(&fence, true) {
+                    thread::yield_now();
+                }
+            }
+
+            TEXTURES_REUSED.fetch_add(1, Ordering::Relaxed);
+            (texture, fence)
+        } else {
+            let texture = unsafe {
+                let mut texture = None;
+                self.0
+                    .direct3d
+                    .device
+                    .CreateTexture2D(&self.0.desc, None, Some(&mut texture))?;
+                texture.unwrap()
+            };
+
+            let fence = unsafe {
+                let mut query = None;
+                self.0.direct3d.device.CreateQuery(
+                    &D3D11_QUERY_DESC {
+                        Query: D3D11_QUERY_EVENT,
+                        MiscFlags: 0,
+                    },
+                    Some(&mut query),
+                )?;
+                query.unwrap()
+            };
+
+            self.0.count.fetch_add(1, Ordering::Relaxed);
+            TEXTURES_CREATED.fetch_add(1, Ordering::Relaxed);
+
+            log::info!(
+                "texture pool create texture, size={}x{}, count={}",
+                self.0.desc.Width,
+                self.0.desc.Height,
+                self.0.count.get()
+            );
+
+            (texture, fence)
+        };
+
+        Ok(PooledTexture {
+            pool: Arc::downgrade(&self.0),
+            texture: Some(texture),
+        })
+    }
+}
+
+/// A texture taken from a `TexturePool`.
+pub struct PooledTexture {
+    pool: Weak<TexturePoolInner>,
+    texture: Option<(ID3D11Texture2D, ID3D11Query)>,
+}
+
+unsafe impl Sync for PooledTexture {}
+unsafe impl Send for PooledTexture {}
+
+impl PooledTexture {
+    pub fn texture(&self) -> &ID3D11Texture2D {
+        &self.texture.as_ref().unwrap().0
+    }
+}
+
+impl Drop for PooledTexture {
+    fn drop(&mut self) {
+        let (Some(pool), Some((texture, fence))) = (self.pool.upgrade(), self.texture.take())
+        else {
+            return;
+        };
+
+        // The fence follows all the work that has been submitted for the texture.
+        unsafe {
+            pool.direct3d.context.End(&fence);
+        }
+
+        let mut textures = pool.textures.lock().unwrap_or_else(PoisonError::into_inner);
+        if textures.len() < pool.limit {
+            textures.push_back((texture, fence));
+        } else {
+            pool.count.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
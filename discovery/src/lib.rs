@@ -5,6 +5,12 @@ use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 use uuid::Uuid;
 
+const SERVICE_TYPE: &str = "_hylarana._udp.local.";
+
+// The service of the Google Cast devices, such as Chromecast and the TVs with
+// Cast built in.
+const CAST_SERVICE_TYPE: &str = "_googlecast._tcp.local.";
+
 #[derive(Debug, Error)]
 pub enum DiscoveryError {
     #[error(transparent)]
@@ -13,11 +19,46 @@ pub enum DiscoveryError {
     JsonError(#[from] serde_json::Error),
 }
 
+/// An event of browsing the registered services.
+#[derive(Debug, Clone)]
+pub enum DiscoveryEvent<P> {
+    /// A service has been published or its addresses have changed, the name
+    /// identifies the service among the events.
+    Resolved {
+        name: String,
+        addrs: Vec<Ipv4Addr>,
+        properties: P,
+    },
+    /// A service has been unregistered or has expired.
+    Removed { name: String },
+}
+
+/// A Google Cast device, the properties of the `DiscoveryEvent` of
+/// `DiscoveryService::browse_cast`.
+#[derive(Debug, Clone)]
+pub struct CastDevice {
+    /// The unique ID of the device.
+    pub id: String,
+    /// The name of the device that is shown to the user, such as "Living Room
+    /// TV".
+    pub friendly_name: String,
+    /// The model of the device, such as "Chromecast".
+    pub model: String,
+    /// The port of the Cast protocol, which is usually 8009.
+    pub port: u16,
+}
+
 /// LAN service discovery.
 ///
 /// which exposes its services through the MDNS protocol
 /// and can allow other nodes or clients to discover the current service.
-pub struct DiscoveryService(ServiceDaemon);
+pub struct DiscoveryService {
+    mdns: ServiceDaemon,
+    // The full name of the registered service.
+    fullname: Option<String>,
+    // The type of the browsed service.
+    service_type: &'static str,
+}
 
 impl DiscoveryService {
     /// Register the service, the service type is fixed, you can customize the
@@ -30,18 +71,21 @@ impl DiscoveryService {
         let mdns = ServiceDaemon::new()?;
         mdns.disable_interface(IfKind::IPv6)?;
 
+        // Each sender is its own instance of the service, so that the browsers can
+        // tell which of them has been removed.
         let id = Uuid::new_v4().to_string();
-        mdns.register(
-            ServiceInfo::new(
-                "_hylarana._udp.local.",
-                "sender",
-                &format!("{}._hylarana._udp.local.", id),
-                "",
-                port,
-                &[("properties", serde_json::to_string(properties)?)][..],
-            )?
-            .enable_addr_auto(),
-        )?;
+        let info = ServiceInfo::new(
+            SERVICE_TYPE,
+            &id,
+            &format!("{}.{}", id, SERVICE_TYPE),
+            "",
+            port,
+            &[("properties", serde_json::to_string(properties)?)][..],
+        )?
+        .enable_addr_auto();
+
+        let fullname = info.get_fullname().to_string();
+        mdns.register(info)?;
 
         log::info!(
             "discovery service register sender, port={}, id={}, properties={:?}",
@@ -50,7 +94,11 @@ impl DiscoveryService {
             properties
         );
 
-        Ok(Self(mdns))
+        Ok(Self {
+            mdns,
+            fullname: Some(fullname),
+            service_type: SERVICE_TYPE,
+        })
     }
 
     /// Query the registered service, the service type is fixed, when the query
@@ -59,14 +107,71 @@ impl DiscoveryService {
     pub fn query<P: DeserializeOwned + Debug, T: Fn(Vec<Ipv4Addr>, P) + Send + 'static>(
         func: T,
     ) -> Result<Self, DiscoveryError> {
+        Self::browse(move |event| {
+            if let DiscoveryEvent::Resolved {
+                addrs, properties, ..
+            } = event
+            {
+                func(addrs, properties);
+            }
+        })
+    }
+
+    /// Same as `query`, but the callback is also told when a service is
+    /// removed. A service can be resolved several times, such as when its
+    /// addresses change.
+    pub fn browse<P: DeserializeOwned + Debug, T: Fn(DiscoveryEvent<P>) + Send + 'static>(
+        func: T,
+    ) -> Result<Self, DiscoveryError> {
+        Self::browse_service(
+            SERVICE_TYPE,
+            |info| serde_json::from_str(info.get_property("properties")?.val_str()).ok(),
+            func,
+        )
+    }
+
+    /// Browse the Google Cast devices on the network, such as Chromecast, the
+    /// devices are resolved and removed like the services of `browse`.
+    pub fn browse_cast<T: Fn(DiscoveryEvent<CastDevice>) + Send + 'static>(
+        func: T,
+    ) -> Result<Self, DiscoveryError> {
+        Self::browse_service(
+            CAST_SERVICE_TYPE,
+            |info| {
+                let property = |key: &str| {
+                    info.get_property(key)
+                        .map(|it| it.val_str().to_string())
+                        .unwrap_or_default()
+                };
+
+                Some(CastDevice {
+                    id: property("id"),
+                    friendly_name: property("fn"),
+                    model: property("md"),
+                    port: info.get_port(),
+                })
+            },
+            func,
+        )
+    }
+
+    fn browse_service<P, F, T>(
+        service_type: &'static str,
+        parse: F,
+        func: T,
+    ) -> Result<Self, DiscoveryError>
+    where
+        P: Debug,
+        F: Fn(&ServiceInfo) -> Option<P> + Send + 'static,
+        T: Fn(DiscoveryEvent<P>) + Send + 'static,
+    {
         let mdns = ServiceDaemon::new()?;
         mdns.disable_interface(IfKind::IPv6)?;
 
-        let receiver = mdns.browse("_hylarana._udp.local.")?;
+        let receiver = mdns.browse(service_type)?;
         thread::spawn(move || {
             let process = |info: ServiceInfo| {
-                let properties =
-                    serde_json::from_str(info.get_property("properties")?.val_str()).ok()?;
+                let properties = parse(&info)?;
                 let addrs = info
                     .get_addresses_v4()
                     .into_iter()
@@ -74,22 +179,30 @@ impl DiscoveryService {
                     .collect::<Vec<_>>();
 
                 log::info!(
-                    "discovery service query a sender, host={}, address={:?}, properties={:?}",
+                    "discovery service query a service, host={}, address={:?}, properties={:?}",
                     info.get_hostname(),
                     addrs,
                     properties,
                 );
 
-                func(addrs, properties);
+                func(DiscoveryEvent::Resolved {
+                    name: info.get_fullname().to_string(),
+                    addrs,
+                    properties,
+                });
+
                 Some(())
             };
 
             loop {
                 match receiver.recv() {
                     Ok(ServiceEvent::ServiceResolved(info)) => {
-                        if info.get_fullname() == "sender._hylarana._udp.local." {
-                            process(info);
-                        }
+                        process(info);
+                    }
+                    Ok(ServiceEvent::ServiceRemoved(_, name)) => {
+                        log::info!("discovery service remove a service, name={}", name);
+
+                        func(DiscoveryEvent::Removed { name });
                     }
                     Err(_) => break,
                     Ok(event) => {
@@ -99,13 +212,20 @@ impl DiscoveryService {
             }
         });
 
-        Ok(Self(mdns))
+        Ok(Self {
+            mdns,
+            fullname: None,
+            service_type,
+        })
     }
 }
 
 impl Drop for DiscoveryService {
     fn drop(&mut self) {
-        let _ = self.0.unregister("_hylarana._udp.local.");
-        let _ = self.0.stop_browse("_hylarana._udp.local.");
+        if let Some(fullname) = self.fullname.as_ref() {
+            let _ = self.mdns.unregister(fullname);
+        } else {
+            let _ = self.mdns.stop_browse(self.service_type);
+        }
     }
 }
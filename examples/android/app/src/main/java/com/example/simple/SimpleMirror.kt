@@ -197,7 +197,7 @@ class SimpleHylaranaService : Service() {
                                 val audioConfig = Audio.getAudioCodecConfigure()
                                 receiver = HylaranaService.createReceiver(
                                     sdp.id,
-                                    HylaranaOptions(strategy = sdp.strategy, mtu = 1500),
+                                    HylaranaOptions(strategy = sdp.strategy, mtu = 0),
                                     object : HylaranaReceiverObserver() {
                                         override val surface = outputSurface!!
                                         override val track =
@@ -257,7 +257,7 @@ class SimpleHylaranaService : Service() {
             strategy?.let {
                 HylaranaService.createSender(
                     object : HylaranaSenderConfigure {
-                        override val options = HylaranaOptions(strategy = it, mtu = 1500)
+                        override val options = HylaranaOptions(strategy = it, mtu = 0)
 
                         override val video =
                             object : Video.VideoEncoder.VideoEncoderConfigure {
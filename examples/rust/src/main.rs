@@ -8,11 +8,11 @@ use anyhow::{anyhow, Result};
 use clap::Parser;
 use hylarana::{
     shutdown, startup, AVFrameObserver, AVFrameStreamPlayer, AVFrameStreamPlayerOptions,
-    AudioOptions, Capture, DiscoveryService, Hylarana, HylaranaReceiver,
-    HylaranaReceiverCodecOptions, HylaranaReceiverOptions, HylaranaSender,
-    HylaranaSenderMediaOptions, HylaranaSenderOptions, HylaranaSenderTrackOptions, Size,
-    SourceType, TransportOptions, TransportStrategy, VideoDecoderType, VideoEncoderType,
-    VideoOptions, VideoRenderBackend, VideoRenderOptions,
+    AudioOptions, Capture, DiscoveryService, FitMode, FramePacingOptions, Hylarana,
+    HylaranaReceiver, HylaranaReceiverCodecOptions, HylaranaReceiverOptions, HylaranaSender,
+    HylaranaSenderMediaOptions, HylaranaSenderOptions, HylaranaSenderTrackOptions, ScaleFilter,
+    Size, SourceType, StreamMetadata, TransportOptions, TransportStrategy, VideoDecoderType,
+    VideoEncoderType, VideoOptions, VideoRenderBackend, VideoRenderOptions,
 };
 
 use parking_lot::Mutex;
@@ -42,6 +42,8 @@ impl Into<Properties> for StreamInfo {
                 TransportStrategy::Direct(_) => 0,
                 TransportStrategy::Relay(_) => 1,
                 TransportStrategy::Multicast(_) => 2,
+                TransportStrategy::Loopback(_) => 3,
+                TransportStrategy::SharedMemory(_) => 4,
             }
             .to_string(),
         );
@@ -52,6 +54,9 @@ impl Into<Properties> for StreamInfo {
             | TransportStrategy::Multicast(addr) => {
                 map.insert("address".to_string(), addr.to_string());
             }
+            // The loopback sender can only be found in the same process, and the
+            // shared memory sender on the same machine.
+            TransportStrategy::Loopback(_) | TransportStrategy::SharedMemory(_) => (),
         }
 
         map
@@ -120,13 +125,16 @@ impl Sender {
         }
 
         // Get the first audio input device that can be captured.
-        let mut audio = None;
+        let mut audio = Vec::with_capacity(1);
         if let Some(source) = Capture::get_sources(SourceType::Audio)?.get(0) {
-            audio = Some(HylaranaSenderTrackOptions {
+            audio.push(HylaranaSenderTrackOptions {
                 source: source.clone(),
                 options: AudioOptions {
                     sample_rate: 48000,
                     bit_rate: 64000,
+                    gain: 1.0,
+                    capture: Default::default(),
+                    dtx: None,
                 },
             });
         }
@@ -136,15 +144,37 @@ impl Sender {
             HylaranaSenderOptions {
                 transport: TransportOptions {
                     strategy,
-                    mtu: 1500,
+                    mtu: 0,
+                    multicast: Default::default(),
+                    dscp: None,
+                    resume: None,
+                    pacing: None,
+                    keepalive: None,
+                    latency: None,
                 },
-                media: HylaranaSenderMediaOptions { video, audio },
+                media: HylaranaSenderMediaOptions {
+                    graphics: Default::default(),
+                    sandbox: None,
+                    video,
+                    audio,
+                },
+                metadata: StreamMetadata {
+                    title: "hylarana example".to_string(),
+                    ..Default::default()
+                },
+                id: None,
+                energy_saver: None,
+                pairing: None,
             },
             AVFrameStreamPlayer::new(
                 AVFrameStreamPlayerOptions::OnlyVideo(VideoRenderOptions {
                     backend: VideoRenderBackend::WebGPU,
+                    fit: FitMode::Contain,
+                    filter: ScaleFilter::Bilinear,
                     size: window.size(),
                     target: window,
+                    graphics: Default::default(),
+                    pacing: None,
                 }),
                 ViewObserver,
             )?,
@@ -176,6 +206,17 @@ impl Receiver {
     fn new(configure: &Configure, window: Arc<Window>) -> Result<Self> {
         let video_decoder = configure.decoder;
 
+        // Pace the received frames to the refresh rate of the monitor that shows the
+        // window.
+        let pacing = FramePacingOptions {
+            refresh_rate: window
+                .current_monitor()
+                .and_then(|it| it.refresh_rate_millihertz())
+                .map(|it| it as f64 / 1000.0)
+                .unwrap_or(60.0),
+            ..Default::default()
+        };
+
         let receiver = Arc::new(Mutex::new(None));
         let receiver_ = Arc::downgrade(&receiver);
 
@@ -201,17 +242,32 @@ impl Receiver {
                     HylaranaReceiverOptions {
                         codec: HylaranaReceiverCodecOptions {
                             video: video_decoder,
+                            graphics: Default::default(),
+                            thumbnail_only: false,
+                            passthrough: false,
                         },
                         transport: TransportOptions {
                             strategy: properties.strategy,
-                            mtu: 1500,
+                            mtu: 0,
+                            multicast: Default::default(),
+                            dscp: None,
+                            resume: None,
+                            pacing: None,
+                            keepalive: None,
+                            latency: None,
                         },
+                        tracks: Default::default(),
+                        pairing_code: None,
                     },
                     AVFrameStreamPlayer::new(
                         AVFrameStreamPlayerOptions::All(VideoRenderOptions {
                             backend: VideoRenderBackend::WebGPU,
+                            fit: FitMode::Contain,
+                            filter: ScaleFilter::Bilinear,
                             size: window.size(),
                             target: window.clone(),
+                            graphics: Default::default(),
+                            pacing: Some(pacing),
                         }),
                         ViewObserver,
                     )
@@ -272,6 +328,24 @@ impl ApplicationHandler for App {
 
                 event_loop.exit();
             }
+            // The window size is in physical pixels, so the renderer is resized to match
+            // the window, including when the scale factor of the display changes.
+            WindowEvent::Resized(size) => {
+                let size = Size {
+                    width: size.width,
+                    height: size.height,
+                };
+
+                if let Some(Sender { sender, .. }) = &self.sender {
+                    let _ = sender.get_sink().resize(size);
+                }
+
+                if let Some(Receiver { receiver, .. }) = &self.receiver {
+                    if let Some(receiver) = receiver.lock().as_ref() {
+                        let _ = receiver.get_sink().resize(size);
+                    }
+                }
+            }
             WindowEvent::KeyboardInput { event, .. } => {
                 if !event.repeat && event.state == ElementState::Released {
                     if let PhysicalKey::Code(key) = event.physical_key {
@@ -385,6 +459,16 @@ impl Configure {
             height: self.height,
             bit_rate: 500 * 1024 * 8,
             key_frame_interval: 21,
+            adaptive_pacing: false,
+            capture_size: None,
+            thumbnail: None,
+            deinterlace: None,
+            watermark: None,
+            privacy_masks: None,
+            content_detection: None,
+            scene_cut: None,
+            dirty_regions: None,
+            tuning: Default::default(),
         }
     }
 }
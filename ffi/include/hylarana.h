@@ -33,6 +33,11 @@ typedef enum
     VIDEO_FORMAT_RGBA,
     VIDEO_FORMAT_NV12,
     VIDEO_FORMAT_I420,
+    /**
+     * 10-bit semi-planar YCbCr, the samples are stored in the high 10 bits of 
+     * little-endian 16-bit words, used for HDR10 (BT.2020 + PQ).
+     */
+    VIDEO_FORMAT_P010,
 } HylaranaVideoFormat;
 
 /**
@@ -52,8 +57,60 @@ typedef enum
      * Video frames contain buffers that can be accessed directly through software.
      */
     VIDEO_SUB_FORMAT_SW,
+    /**
+     * The planes of the frame are DMA-BUF file descriptors, a type exclusive to the 
+     * Linux platform.
+     */
+    VIDEO_SUB_FORMAT_DMA_BUF,
 } HylaranaVideoSubFormat;
 
+/**
+ * The clockwise rotation that needs to be applied to the picture to display 
+ * it upright, such as a phone held in portrait or a camera mounted sideways.
+ */
+typedef enum
+{
+    VIDEO_ROTATION_0,
+    VIDEO_ROTATION_90,
+    VIDEO_ROTATION_180,
+    VIDEO_ROTATION_270,
+} HylaranaVideoRotation;
+
+/**
+ * The order of the fields of an interlaced frame, both fields are woven into 
+ * the frame line by line.
+ */
+typedef enum
+{
+    FIELD_ORDER_PROGRESSIVE,
+    /**
+     * The even lines are the field that is captured first.
+     */
+    FIELD_ORDER_TOP_FIELD_FIRST,
+    /**
+     * The odd lines are the field that is captured first.
+     */
+    FIELD_ORDER_BOTTOM_FIELD_FIRST,
+} HylaranaFieldOrder;
+
+/**
+ * The DMA-BUF planes of a frame, the file descriptors are owned by the source 
+ * of the frame.
+ */
+typedef struct
+{
+    /**
+     * The planes may share one file descriptor at different offsets, this is -1 
+     * for the planes that the format does not have.
+     */
+    int fd[3];
+    uint32_t offset[3];
+    /**
+     * The DRM format modifier of the buffers, 0 is linear.
+     */
+    uint64_t modifier;
+} HylaranaDmaBuf;
+
 typedef struct
 {
     HylaranaVideoFormat format;
@@ -67,6 +124,36 @@ typedef struct
      */
     void* data[3];
     size_t linesize[3];
+    /**
+     * The clockwise rotation that needs to be applied when displaying.
+     */
+    HylaranaVideoRotation rotation;
+    /**
+     * Whether the picture needs to be flipped horizontally when displaying, 
+     * this is usually the case for front cameras.
+     */
+    bool mirror;
+    /**
+     * The capture time of the frame in microseconds, taken from the media 
+     * clock, this is zero if the source does not provide it.
+     */
+    uint64_t timestamp;
+    /**
+     * Whether the frame is interlaced, the sender deinterlaces the frames 
+     * before they are encoded if it is configured to.
+     */
+    HylaranaFieldOrder field_order;
+    /**
+     * The planes of the frame if the sub format is VIDEO_SUB_FORMAT_DMA_BUF, the 
+     * strides of the planes are the linesize.
+     */
+    HylaranaDmaBuf dma_buf;
+    /**
+     * The visible area of the frame from the top left corner, the rest is 
+     * padding that is not displayed, zero means the whole frame is visible.
+     */
+    uint32_t display_width;
+    uint32_t display_height;
 } HylaranaVideoFrame;
 
 /**
@@ -83,6 +170,11 @@ typedef struct
      * Pointer to the sample raw buffer.
      */
     int16_t* data;
+    /**
+     * The capture time of the first sample in microseconds, taken from the 
+     * media clock, this is zero if the source does not provide it.
+     */
+    uint64_t timestamp;
 } HylaranaAudioFrame;
 
 /**
@@ -127,6 +219,11 @@ typedef struct
      * whether or not it is the master device.
      */
     bool is_default;
+    /**
+     * The clockwise rotation that needs to be applied to the pictures of this 
+     * source to display them upright, only meaningful for video sources.
+     */
+    HylaranaVideoRotation rotation;
 } HylaranaSource;
 
 typedef struct
@@ -166,6 +263,20 @@ typedef enum {
      * hardware encoders and decoders.
      */
     VIDEO_DECODER_VIDEOTOOLBOX,
+    /**
+     * see: https://learn.microsoft.com/en-us/windows/win32/medfound/direct3d-11-video-apis
+     * 
+     * Accelerated HEVC Main10 decoding using Direct3D 11 Video APIs, the output 
+     * is a 10-bit P010 texture.
+     */
+    VIDEO_DECODER_HEVC_D3D11,
+    /**
+     * see: https://en.wikipedia.org/wiki/Intel_Quick_Sync_Video
+     * 
+     * HEVC Main10 decoding on the Intel Quick Sync Video hardware core, the 
+     * output is a 10-bit P010 texture.
+     */
+    VIDEO_DECODER_HEVC_QSV,
 } HylaranaVideoDecoderType;
 
 /**
@@ -195,8 +306,115 @@ typedef enum
      * hardware encoders and decoders.
      */
     VIDEO_ENCODER_VIDEOTOOLBOX,
+    /**
+     * see: https://www.videolan.org/developers/x265.html
+     * 
+     * x265 is a free software library for encoding video streams into the 
+     * H.265/HEVC compression format, used here with the Main10 profile for 
+     * HDR10 content.
+     */
+    VIDEO_ENCODER_X265,
+    /**
+     * see: https://en.wikipedia.org/wiki/Intel_Quick_Sync_Video
+     * 
+     * HEVC Main10 encoding on the Intel Quick Sync Video hardware core, used 
+     * for HDR10 content.
+     */
+    VIDEO_ENCODER_HEVC_QSV,
 } HylaranaVideoEncoderType;
 
+/**
+ * The profile of the H264 encoders, the HEVC encoders always use Main10.
+ */
+typedef enum
+{
+    /**
+     * Use the baseline profile.
+     */
+    VIDEO_PROFILE_DEFAULT,
+    VIDEO_PROFILE_BASELINE,
+    VIDEO_PROFILE_MAIN,
+    VIDEO_PROFILE_HIGH,
+} HylaranaVideoProfile;
+
+/**
+ * How the encoder controls the bit rate.
+ */
+typedef enum
+{
+    /**
+     * Cap the bit rate without strictly enforcing it.
+     */
+    RATE_CONTROL_DEFAULT,
+    /**
+     * Constant bit rate.
+     */
+    RATE_CONTROL_CBR,
+    /**
+     * Variable bit rate, the peaks are limited to `max_bit_rate`.
+     */
+    RATE_CONTROL_VBR,
+    /**
+     * Constant quantizer `qp`, the bit rate is ignored.
+     */
+    RATE_CONTROL_CQP,
+    /**
+     * The picture is reproduced exactly, the bit rate is ignored.
+     */
+    RATE_CONTROL_LOSSLESS,
+} HylaranaRateControl;
+
+/**
+ * The kind of the content that is encoded, the encoders are tuned for it.
+ */
+typedef enum
+{
+    /**
+     * Use the default tuning of the encoder.
+     */
+    CONTENT_HINT_DEFAULT,
+    /**
+     * Camera and video content, the motion is kept smooth.
+     */
+    CONTENT_HINT_MOTION,
+    /**
+     * Screen content such as text, spreadsheets and code, small text is kept
+     * sharp.
+     */
+    CONTENT_HINT_DETAIL,
+} HylaranaContentHint;
+
+/**
+ * Whether a codec can be used on this machine.
+ */
+typedef enum
+{
+    CODEC_STATUS_AVAILABLE,
+    /**
+     * The codec is not supported on this platform.
+     */
+    CODEC_STATUS_UNSUPPORTED,
+    /**
+     * The codec is not built into the linked ffmpeg.
+     */
+    CODEC_STATUS_NOT_FOUND,
+    /**
+     * The codec is present but a session could not be opened, such as when
+     * the driver of the hardware is missing.
+     */
+    CODEC_STATUS_SESSION_FAILED,
+} HylaranaCodecStatus;
+
+/**
+ * The status of each codec, indexed by HylaranaVideoEncoderType and
+ * HylaranaVideoDecoderType.
+ */
+typedef struct
+{
+    HylaranaCodecStatus encoders[5];
+    HylaranaCodecStatus decoders[6];
+} HylaranaCodecCapabilities;
+
 /**
  * Back-end implementation of graphics.
  */
@@ -215,6 +433,37 @@ typedef enum
     RENDER_BACKEND_WEBGPU,
 } HylaranaVideoRenderBackend;
 
+/**
+ * How the video is fitted into the window.
+ */
+typedef enum
+{
+    /**
+     * Stretch the video to fill the window, the aspect ratio is not kept.
+     */
+    FIT_MODE_STRETCH,
+    /**
+     * Scale the video to fit inside the window, the rest of the window is left
+     * black.
+     */
+    FIT_MODE_CONTAIN,
+    /**
+     * Scale the video to fill the window, the parts of the video outside the
+     * window are cropped.
+     */
+    FIT_MODE_COVER,
+} HylaranaFitMode;
+
+/**
+ * The filter used when scaling the video, only the WebGPU backend supports it.
+ */
+typedef enum
+{
+    SCALE_FILTER_NEAREST,
+    SCALE_FILTER_BILINEAR,
+    SCALE_FILTER_BICUBIC,
+} HylaranaScaleFilter;
+
 /**
  * Transport layer strategies.
  */
@@ -267,7 +516,7 @@ typedef struct
     /**
      * The size of the maximum transmission unit of the network, which is
      * related to the settings of network devices such as routers or switches,
-     * the recommended value is 1400.
+     * zero discovers the MTU of the path to the peer.
      */
     size_t mtu;
 } HylaranaTransportOptions;
@@ -300,6 +549,54 @@ typedef struct
      * keyframe.
      */
     uint32_t key_frame_interval;
+    /**
+     * Reduce the capture frame rate when the encoder cannot keep up, instead 
+     * of accumulating latency.
+     */
+    bool adaptive_pacing;
+    /**
+     * The size the source is captured at, the frames are scaled to the width
+     * and height before encoding, zero captures at the encoded size.
+     */
+    uint32_t capture_width;
+    uint32_t capture_height;
+    /**
+     * The profile of the H264 encoders.
+     */
+    HylaranaVideoProfile profile;
+    /**
+     * The level multiplied by 10, such as 41 for level 4.1, zero lets the
+     * encoder choose.
+     */
+    uint8_t level;
+    HylaranaRateControl rate_control;
+    /**
+     * The peak bit rate of `RATE_CONTROL_VBR`.
+     */
+    uint64_t max_bit_rate;
+    /**
+     * The quantizer of `RATE_CONTROL_CQP`, a lower value has better quality.
+     */
+    uint8_t qp;
+    /**
+     * The number of consecutive B-frames, which add latency.
+     */
+    uint8_t b_frames;
+    /**
+     * The number of slices per frame, zero lets the encoder choose.
+     */
+    uint8_t slices;
+    /**
+     * Tune the encoder for the lowest latency, the recommended value is true.
+     */
+    bool low_latency;
+    /**
+     * Refresh the picture gradually over the key frame interval instead of
+     * sending periodic IDR frames, which avoids the bit rate spikes of the key
+     * frames on constrained links. Not supported by VideoToolbox.
+     */
+    bool intra_refresh;
+    HylaranaContentHint content_hint;
 } HylaranaVideoEncoderOptions;
 
 /**
@@ -472,6 +769,8 @@ typedef struct
 {    
     HylaranaWindowOptions window;
     HylaranaVideoRenderBackend backend;
+    HylaranaFitMode fit;
+    HylaranaScaleFilter filter;
 } HylaranaVideoRenderOptions;
 
 typedef enum
@@ -608,6 +907,188 @@ EXPORT void hylarana_shutdown();
 
 #endif // !WIN32
 
+typedef enum
+{
+    LOG_LEVEL_OFF,
+    LOG_LEVEL_ERROR,
+    LOG_LEVEL_WARN,
+    LOG_LEVEL_INFO,
+    LOG_LEVEL_DEBUG,
+    LOG_LEVEL_TRACE,
+} HylaranaLogLevel;
+
+/**
+ * Change the level of the logs at runtime.
+ */
+EXPORT void hylarana_set_log_level(HylaranaLogLevel level);
+
+/**
+ * Change the level of a module and its sub modules at runtime, such as
+ * "hylarana_transport".
+ */
+EXPORT void hylarana_set_log_module_level(const char* module, HylaranaLogLevel level);
+
+/**
+ * Pass the log records to the callback, the callback can be null, which
+ * removes it. The target is the module that has written the record, the
+ * strings are only valid in the callback.
+ */
+EXPORT void hylarana_set_log_callback(void (*callback)(void* ctx, HylaranaLogLevel level, const char* target, const char* message), void* ctx);
+
+/**
+ * Write the reports of the crashes to the directory, and call the callback
+ * when the process crashes, such as a panic on a capture thread, the directory
+ * and the callback can be null. The minidump is only written on Windows. The
+ * strings are only valid in the callback, the report is null if it has not
+ * been written.
+ */
+EXPORT void hylarana_set_crash_handler(const char* directory, bool minidump, void (*callback)(void* ctx, const char* message, const char* report), void* ctx);
+
+typedef enum
+{
+    THREAD_KIND_CAPTURE,
+    THREAD_KIND_DECODE,
+    THREAD_KIND_TRANSPORT,
+    THREAD_KIND_RENDER,
+    /**
+     * The frames are encoded on the capture threads, which take these
+     * options once they encode.
+     */
+    THREAD_KIND_ENCODE,
+} HylaranaThreadKind;
+
+/**
+ * The priorities of the threads, they are prefixed because windows.h defines
+ * the THREAD_PRIORITY macros.
+ */
+typedef enum
+{
+    /**
+     * Keep the priority that the thread is created with.
+     */
+    HYLARANA_THREAD_PRIORITY_DEFAULT,
+    HYLARANA_THREAD_PRIORITY_LOW,
+    HYLARANA_THREAD_PRIORITY_NORMAL,
+    HYLARANA_THREAD_PRIORITY_HIGH,
+    /**
+     * The real-time scheduling of the system, which usually needs the
+     * privileges of an administrator.
+     */
+    HYLARANA_THREAD_PRIORITY_REALTIME,
+} HylaranaThreadPriority;
+
+/**
+ * Set the priority and the CPUs of a kind of the threads of the pipeline, the
+ * threads that are created afterwards use them. The affinity is a list of the
+ * indexes of the CPUs, it can be null, which lets the system choose. The
+ * affinity is ignored on macOS.
+ */
+EXPORT void hylarana_set_thread_options(HylaranaThreadKind kind, HylaranaThreadPriority priority, const size_t* affinity, size_t affinity_size);
+
+/**
+ * Apply the options of the kind to the calling thread, such as the render
+ * thread of the application.
+ */
+EXPORT void hylarana_apply_thread_options(HylaranaThreadKind kind);
+
+/**
+ * The error of the last call that failed, the code and the id are stable, so
+ * that the application can show the error in its own language.
+ */
+typedef struct
+{
+    /**
+     * The code of the error, the codes are grouped by the hundreds: 1xx
+     * capture, 2xx codec, 3xx network, 4xx stream, 5xx system, 0 is unknown.
+     */
+    uint32_t code;
+    /**
+     * The id of the code, such as "capture.permission_denied", which can be
+     * used as the key of the translations.
+     */
+    const char* id;
+    /**
+     * The type of the capture source, only valid if has_source_type is true.
+     */
+    HylaranaSourceType source_type;
+    bool has_source_type;
+    /**
+     * The name of the capture backend, can be null.
+     */
+    const char* name;
+    /**
+     * The error code of the operating system, only valid if has_os_error is
+     * true.
+     */
+    int32_t os_error;
+    bool has_os_error;
+    /**
+     * The English text of the error, for the logs, it is not meant to be shown
+     * to the user.
+     */
+    const char* message;
+} HylaranaErrorContext;
+
+/**
+ * Get the error of the last call of this thread that failed. The strings are
+ * valid until the next call that fails on this thread. Returns false if no
+ * call has failed.
+ */
+EXPORT bool hylarana_get_last_error(HylaranaErrorContext* context);
+
+typedef enum
+{
+    /**
+     * Prefer the adapter with the lowest power consumption, usually the
+     * integrated GPU.
+     */
+    ADAPTER_PREFERENCE_LOW_POWER,
+    /**
+     * Prefer the adapter with the highest performance, usually the discrete
+     * GPU.
+     */
+    ADAPTER_PREFERENCE_HIGH_PERFORMANCE,
+    /**
+     * The adapter with the PCI vendor id and device id.
+     */
+    ADAPTER_PREFERENCE_DEVICE,
+} HylaranaAdapterPreference;
+
+/**
+ * Set the graphics adapter used by the capture, the codecs and the renderers,
+ * so that they all run on the same GPU. The vendor id and device id are only
+ * used by the device preference.
+ */
+EXPORT void hylarana_set_adapter_preference(HylaranaAdapterPreference preference, uint32_t vendor, uint32_t device);
+
+/**
+ * Check which video encoders and decoders can be used on this machine, each
+ * codec is opened once, so this is slow and the result should be cached.
+ */
+EXPORT HylaranaCodecCapabilities hylarana_codec_capabilities();
+
+typedef const void* HylaranaProfiles;
+
+/**
+ * Load the profiles, the built-in profiles are always available, the profiles
+ * in the file are added on top of them and replace the built-in profiles with
+ * the same name. The path can be null, and the file can be toml or json.
+ */
+EXPORT HylaranaProfiles hylarana_profiles_load(const char* path);
+
+/**
+ * Get the options of the profile with the name, the options that the profile
+ * has are written into the non-null pointers. Returns false if the profile
+ * does not exist. The transport is not included, because the address depends
+ * on the network of the application.
+ */
+EXPORT bool hylarana_profiles_get(HylaranaProfiles profiles, const char* name, HylaranaVideoEncoderOptions* video, HylaranaAudioEncoderOptions* audio);
+
+/**
+ * Destroy the profiles.
+ */
+EXPORT void hylarana_profiles_destroy(HylaranaProfiles profiles);
+
 /**
  * Get capture sources.
  */
@@ -633,6 +1114,78 @@ EXPORT HylaranaSender hylarana_create_sender(HylaranaSenderOptions options, Hyla
  */
 EXPORT void hylarana_sender_destroy(HylaranaSender sender);
 
+/**
+ * Pause the sender, the capture is stopped and the receivers keep the last
+ * frame until the sender resumes.
+ */
+EXPORT bool hylarana_sender_pause(HylaranaSender sender);
+
+/**
+ * Resume the paused sender.
+ */
+EXPORT bool hylarana_sender_resume(HylaranaSender sender);
+
+typedef struct
+{
+    /**
+     * The id of the connection, it is unique within the sender.
+     */
+    uint64_t id;
+    /**
+     * The address of the receiver, such as "192.168.1.2:50000".
+     */
+    const char* addr;
+    /**
+     * The time the receiver has connected, in milliseconds since the unix
+     * epoch.
+     */
+    uint64_t joined;
+    /**
+     * The round trip time in microseconds, zero if it is not known.
+     */
+    uint64_t rtt;
+} HylaranaReceiverInfo;
+
+typedef struct
+{
+    HylaranaReceiverInfo* items;
+    size_t capacity;
+    size_t size;
+} HylaranaReceivers;
+
+/**
+ * Get the receivers that are attached to the sender, only the direct mode
+ * knows its receivers. The list needs to be released with
+ * `hylarana_receivers_destroy`.
+ */
+EXPORT HylaranaReceivers hylarana_sender_get_receivers(HylaranaSender sender);
+
+/**
+ * Disconnect a receiver of the sender, the receiver closes its stream instead
+ * of waiting to reattach. Returns false if the receiver is not attached.
+ */
+EXPORT bool hylarana_sender_disconnect(HylaranaSender sender, uint64_t id);
+
+/**
+ * Require the receivers to submit the pairing code of the sender, only the
+ * direct mode supports it. A new code is generated when the pairing is turned
+ * on, and again after a number of wrong codes, see
+ * `hylarana_sender_get_pairing_code`.
+ */
+EXPORT bool hylarana_sender_set_pairing(HylaranaSender sender, bool enabled);
+
+/**
+ * Get the pairing code of the sender, which the application shows to the
+ * presenter. The buffer of the code needs to be at least 16 bytes. Returns
+ * false if the pairing is off.
+ */
+EXPORT bool hylarana_sender_get_pairing_code(HylaranaSender sender, char* code);
+
+/**
+ * Release the list of the receivers.
+ */
+EXPORT void hylarana_receivers_destroy(HylaranaReceivers* receivers);
+
 /**
  * Create the sender. the difference is that this function creates the player together, 
  * you don't need to implement the stream sink manually, the player manages it automatically.
@@ -644,6 +1197,55 @@ EXPORT HylaranaSender hylarana_create_sender_with_player(HylaranaSenderOptions o
  */
 EXPORT void hylarana_sender_with_player_destroy(HylaranaSender sender);
 
+/**
+ * Pause the sender with player, the capture is stopped and the receivers keep the last
+ * frame until the sender resumes.
+ */
+EXPORT bool hylarana_sender_with_player_pause(HylaranaSender sender);
+
+/**
+ * Resume the paused sender with player.
+ */
+EXPORT bool hylarana_sender_with_player_resume(HylaranaSender sender);
+
+/**
+ * Get the receivers that are attached to the sender with player, see
+ * `hylarana_sender_get_receivers`.
+ */
+EXPORT HylaranaReceivers hylarana_sender_with_player_get_receivers(HylaranaSender sender);
+
+/**
+ * Disconnect a receiver of the sender with player, see
+ * `hylarana_sender_disconnect`.
+ */
+EXPORT bool hylarana_sender_with_player_disconnect(HylaranaSender sender, uint64_t id);
+
+/**
+ * Turn the pairing of the sender with player on or off, see
+ * `hylarana_sender_set_pairing`.
+ */
+EXPORT bool hylarana_sender_with_player_set_pairing(HylaranaSender sender, bool enabled);
+
+/**
+ * Get the pairing code of the sender with player, see
+ * `hylarana_sender_get_pairing_code`.
+ */
+EXPORT bool hylarana_sender_with_player_get_pairing_code(HylaranaSender sender, char* code);
+
+/**
+ * Resize the renderer of the sender's player, this needs to be called when
+ * the window size changes. The size is in physical pixels.
+ */
+EXPORT bool hylarana_sender_renderer_resize(HylaranaSender sender, uint32_t width, uint32_t height);
+
+/**
+ * Read back the last frame rendered by the sender's player as RGBA, the size
+ * of the frame is written to `width` and `height`, and the frame is copied to
+ * the buffer only if the buffer is large enough, that is, at least
+ * `width * height * 4` bytes.
+ */
+EXPORT bool hylarana_sender_renderer_snapshot(HylaranaSender sender, uint8_t* buf, size_t len, uint32_t* width, uint32_t* height);
+
 typedef const void* HylaranaReceiver;
 
 /**
@@ -652,6 +1254,14 @@ typedef const void* HylaranaReceiver;
  */
 EXPORT HylaranaReceiver hylarana_create_receiver(const char* id, HylaranaReceiverOptions options, HylaranaFrameSink sink);
 
+/**
+ * Create a receiver that submits the pairing code to a sender that requires
+ * it, the code can be null. The receiver is not created if the code is missing
+ * or wrong, the error is "network.pairing_failed", see
+ * `hylarana_get_last_error`.
+ */
+EXPORT HylaranaReceiver hylarana_create_paired_receiver(const char* id, const char* code, HylaranaReceiverOptions options, HylaranaFrameSink sink);
+
 /**
  * Destroy receiver.
  */
@@ -668,6 +1278,141 @@ EXPORT HylaranaReceiver hylarana_create_receiver_with_player(const char* id, Hyl
  */
 EXPORT void hylarana_receiver_with_player_destroy(HylaranaReceiver receiver);
 
+/**
+ * Resize the renderer of the receiver's player, this needs to be called when
+ * the window size changes. The size is in physical pixels.
+ */
+EXPORT bool hylarana_receiver_renderer_resize(HylaranaReceiver receiver, uint32_t width, uint32_t height);
+
+/**
+ * Read back the last frame rendered by the receiver's player as RGBA, the size
+ * of the frame is written to `width` and `height`, and the frame is copied to
+ * the buffer only if the buffer is large enough, that is, at least
+ * `width * height * 4` bytes.
+ */
+EXPORT bool hylarana_receiver_renderer_snapshot(HylaranaReceiver receiver, uint8_t* buf, size_t len, uint32_t* width, uint32_t* height);
+
+typedef const void* HylaranaPullReceiver;
+
+/**
+ * Create a receiver in the pull mode, the frames are kept in queues of the
+ * capacity and are taken by the application, such as on the render thread of
+ * a game engine, instead of being called back on the codec threads.
+ */
+EXPORT HylaranaPullReceiver hylarana_create_pull_receiver(const char* id, HylaranaReceiverOptions options, size_t capacity);
+
+/**
+ * Take the next video frame, waits up to the timeout in milliseconds if there
+ * is no frame, zero does not wait. The frame is valid until the next call of
+ * this function or until the receiver is destroyed. Returns false if there is
+ * no frame or the stream is closed.
+ */
+EXPORT bool hylarana_pull_receiver_next_video_frame(HylaranaPullReceiver receiver, uint32_t timeout, HylaranaVideoFrame* frame);
+
+/**
+ * Take the next audio frame, waits up to the timeout in milliseconds if there
+ * is no frame, zero does not wait. The frame is valid until the next call of
+ * this function or until the receiver is destroyed. Returns false if there is
+ * no frame or the stream is closed.
+ */
+EXPORT bool hylarana_pull_receiver_next_audio_frame(HylaranaPullReceiver receiver, uint32_t timeout, HylaranaAudioFrame* frame);
+
+/**
+ * Whether the stream of the pull receiver has been closed.
+ */
+EXPORT bool hylarana_pull_receiver_is_closed(HylaranaPullReceiver receiver);
+
+/**
+ * Destroy the pull receiver, the frames taken from it are no longer valid.
+ */
+EXPORT void hylarana_pull_receiver_destroy(HylaranaPullReceiver receiver);
+
+typedef enum
+{
+    PIXEL_ORDER_RGBA,
+    PIXEL_ORDER_BGRA,
+} HylaranaPixelOrder;
+
+/**
+ * A pixel buffer, it has the same layout as `FlutterDesktopPixelBuffer`.
+ */
+typedef struct
+{
+    const uint8_t* buffer;
+    size_t width;
+    size_t height;
+    void (*release_callback)(void* ctx);
+    void* release_context;
+} HylaranaPixelBuffer;
+
+typedef const void* HylaranaTextureReceiver;
+
+/**
+ * Create a receiver whose video is copied into the pixel buffers of an
+ * external texture, such as a texture of Flutter. The callback is called on
+ * the decoder thread when a new frame is available, the close callback can be
+ * null. Only the software frames are converted, so the receiver should use a
+ * software decoder. The audio is not played.
+ */
+EXPORT HylaranaTextureReceiver hylarana_create_texture_receiver(const char* id, HylaranaReceiverOptions options, HylaranaPixelOrder order, void (*callback)(void* ctx), void (*close)(void* ctx), void* ctx);
+
+/**
+ * Copy the newest frame into the pixel buffer, the requested size is ignored.
+ * Returns null if no frame has been received. The release callback of the
+ * buffer has to be called before the next copy. The signature is the same as
+ * `FlutterDesktopPixelBufferTextureCallback` with the receiver as user data.
+ */
+EXPORT const HylaranaPixelBuffer* hylarana_texture_receiver_copy_pixel_buffer(size_t width, size_t height, HylaranaTextureReceiver receiver);
+
+/**
+ * Destroy the texture receiver, unregister the texture first.
+ */
+EXPORT void hylarana_texture_receiver_destroy(HylaranaTextureReceiver receiver);
+
+typedef const void* HylaranaUnityReceiver;
+
+/**
+ * The render event function of the Unity native plugin, it is passed to
+ * `GL.IssuePluginEvent` with the event id of a receiver, and draws the newest
+ * frame of the receiver to the bound texture on the render thread of Unity.
+ */
+EXPORT void (*hylarana_unity_get_render_event_func())(int event);
+
+/**
+ * Create a receiver whose video is drawn to a texture of Unity, only the
+ * newest frame is kept, so the receiver should use a software decoder. The
+ * audio is not played.
+ */
+EXPORT HylaranaUnityReceiver hylarana_unity_create_receiver(const char* id, HylaranaReceiverOptions options);
+
+/**
+ * The event id of the receiver that is passed to `GL.IssuePluginEvent`.
+ */
+EXPORT int hylarana_unity_receiver_get_event_id(HylaranaUnityReceiver receiver);
+
+/**
+ * Bind the native texture of a Unity texture to the receiver, which is the
+ * value of `Texture.GetNativeTexturePtr`. On D3D11 the video is scaled into
+ * the texture by the fit mode, on Metal the texture is BGRA32 and the video is
+ * copied into it as is. Passing a null texture unbinds the texture.
+ */
+EXPORT bool hylarana_unity_receiver_bind_texture(HylaranaUnityReceiver receiver, void* texture, HylaranaFitMode fit);
+
+/**
+ * Get the size of the video, returns false if there is no frame yet.
+ */
+EXPORT bool hylarana_unity_receiver_get_video_size(HylaranaUnityReceiver receiver, uint32_t* width, uint32_t* height);
+
+/**
+ * Whether the stream of the receiver has been closed.
+ */
+EXPORT bool hylarana_unity_receiver_is_closed(HylaranaUnityReceiver receiver);
+
+/**
+ * Destroy the receiver.
+ */
+EXPORT void hylarana_unity_receiver_destroy(HylaranaUnityReceiver receiver);
+
 typedef const void* HylaranaProperties;
 
 /**
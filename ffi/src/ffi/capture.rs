@@ -3,12 +3,12 @@ use std::{
     mem::ManuallyDrop,
 };
 
-use hylarana::{Capture, Source, SourceType};
+use hylarana::{Capture, Source, SourceType, VideoRotation};
 use hylarana_common::strings::PSTR;
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
-enum RawSourceType {
+pub(crate) enum RawSourceType {
     Camera,
     Screen,
     Audio,
@@ -41,6 +41,7 @@ pub(crate) struct RawSource {
     id: *const c_char,
     name: *const c_char,
     is_default: bool,
+    rotation: VideoRotation,
 }
 
 impl TryInto<Source> for &RawSource {
@@ -51,6 +52,8 @@ impl TryInto<Source> for &RawSource {
             name: PSTR::from(self.name).to_string()?,
             id: PSTR::from(self.id).to_string()?,
             is_default: self.is_default,
+            rotation: self.rotation,
+            backend: None,
             kind: self.kind.into(),
             index: self.index,
         })
@@ -79,6 +82,7 @@ extern "C" fn hylarana_get_sources(kind: RawSourceType) -> RawSources {
                 RawSource {
                     index: item.index,
                     is_default: item.is_default,
+                    rotation: item.rotation,
                     kind: RawSourceType::from(item.kind),
                     id: CString::new(item.id).unwrap().into_raw(),
                     name: CString::new(item.name).unwrap().into_raw(),
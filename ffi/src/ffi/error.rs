@@ -0,0 +1,115 @@
+use std::{
+    any::Any,
+    cell::RefCell,
+    ffi::{c_char, CString},
+    ptr::null,
+};
+
+use hylarana::{
+    ErrorCatalog, ErrorContext, HylaranaError, HylaranaReceiverError, HylaranaSenderError,
+};
+
+use super::capture::RawSourceType;
+
+// The error of the last call that failed, with the strings that the raw
+// context points to.
+struct LastError {
+    context: ErrorContext,
+    id: CString,
+    name: Option<CString>,
+    message: CString,
+}
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<LastError>> = const { RefCell::new(None) };
+}
+
+fn catalog(error: &(dyn std::error::Error + 'static)) -> Option<ErrorContext> {
+    if let Some(e) = error.downcast_ref::<HylaranaSenderError>() {
+        Some(e.error_context())
+    } else if let Some(e) = error.downcast_ref::<HylaranaReceiverError>() {
+        Some(e.error_context())
+    } else if let Some(e) = error.downcast_ref::<HylaranaError>() {
+        Some(e.error_context())
+    } else {
+        error
+            .downcast_ref::<std::io::Error>()
+            .map(|e| e.error_context())
+    }
+}
+
+// Records the error for `hylarana_get_last_error`, the errors that are not in
+// the catalog are recorded with the unknown code.
+pub(crate) fn set_last_error(error: &dyn Any, message: String) {
+    let context = if let Some(e) = error.downcast_ref::<anyhow::Error>() {
+        e.chain().find_map(catalog)
+    } else if let Some(e) = error.downcast_ref::<HylaranaSenderError>() {
+        Some(e.error_context())
+    } else if let Some(e) = error.downcast_ref::<HylaranaReceiverError>() {
+        Some(e.error_context())
+    } else if let Some(e) = error.downcast_ref::<HylaranaError>() {
+        Some(e.error_context())
+    } else {
+        None
+    }
+    .unwrap_or_else(|| ErrorContext {
+        message,
+        ..Default::default()
+    });
+
+    let cstring = |it: &str| CString::new(it.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with_borrow_mut(|it| {
+        it.replace(LastError {
+            id: cstring(context.code.id()),
+            name: context.params.name.as_deref().map(cstring),
+            message: cstring(&context.message),
+            context,
+        });
+    });
+}
+
+#[repr(C)]
+struct RawErrorContext {
+    code: u32,
+    id: *const c_char,
+    source_type: RawSourceType,
+    has_source_type: bool,
+    name: *const c_char,
+    os_error: i32,
+    has_os_error: bool,
+    message: *const c_char,
+}
+
+/// Get the error of the last call of this thread that failed, the code and
+/// the id are stable, so that the application can show the error in its own
+/// language. The strings are valid until the next call that fails on this
+/// thread. Returns false if no call has failed.
+#[no_mangle]
+extern "C" fn hylarana_get_last_error(context: *mut RawErrorContext) -> bool {
+    assert!(!context.is_null());
+
+    LAST_ERROR.with_borrow(|it| {
+        let Some(error) = it else {
+            return false;
+        };
+
+        let params = &error.context.params;
+        unsafe {
+            context.write(RawErrorContext {
+                code: error.context.code as u32,
+                id: error.id.as_ptr(),
+                source_type: params
+                    .source_type
+                    .map(RawSourceType::from)
+                    .unwrap_or(RawSourceType::Camera),
+                has_source_type: params.source_type.is_some(),
+                name: error.name.as_ref().map(|it| it.as_ptr()).unwrap_or(null()),
+                os_error: params.os_error.unwrap_or_default(),
+                has_os_error: params.os_error.is_some(),
+                message: error.message.as_ptr(),
+            });
+        }
+
+        true
+    })
+}
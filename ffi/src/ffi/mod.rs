@@ -1,9 +1,22 @@
 mod capture;
 mod discovery;
+mod error;
 mod observer;
+mod pixels;
 mod player;
-
-use std::{ffi::c_char, fmt::Debug, net::SocketAddr, ptr::null_mut};
+mod profile;
+mod pull;
+mod texture;
+mod unity;
+
+use std::{
+    ffi::{c_char, c_void, CString},
+    fmt::Debug,
+    mem::ManuallyDrop,
+    net::SocketAddr,
+    ptr::{null, null_mut},
+    time::UNIX_EPOCH,
+};
 
 use self::{
     capture::RawSource,
@@ -12,28 +25,37 @@ use self::{
 };
 
 use hylarana::{
-    shutdown, startup, AudioOptions, Hylarana, HylaranaReceiver, HylaranaReceiverCodecOptions,
-    HylaranaReceiverOptions, HylaranaSender, HylaranaSenderMediaOptions, HylaranaSenderOptions,
-    HylaranaSenderTrackOptions, TransportOptions, TransportStrategy, VideoDecoderType,
-    VideoEncoderType, VideoOptions,
+    set_adapter_preference, shutdown, startup, AdapterPreference, AudioOptions, CodecStatus,
+    ContentHint, Hylarana, HylaranaReceiver, HylaranaReceiverCodecOptions, HylaranaReceiverOptions,
+    HylaranaSender, HylaranaSenderMediaOptions, HylaranaSenderOptions, HylaranaSenderTrackOptions,
+    PairingOptions, RateControl, ReceiverInfo, Size, TransportOptions, TransportStrategy,
+    VideoDecoderType, VideoEncoderTuning, VideoEncoderType, VideoOptions, VideoProfile,
 };
 
-use hylarana_common::{logger, strings::PSTR};
+use hylarana_common::{
+    crash::{self, CrashHandlerOptions},
+    logger,
+    runtime::{self, ThreadKind, ThreadPriority},
+    strings::PSTR,
+};
 
 // In fact, this is a package that is convenient for recording errors. If the
-// result is an error message, it is output to the log. This function does not
-// make any changes to the result.
+// result is an error message, it is output to the log and kept for
+// `hylarana_get_last_error`. This function does not make any changes to the
+// result.
 #[inline]
-fn log_error<T, E: Debug>(result: Result<T, E>) -> Result<T, E> {
+fn log_error<T, E: Debug + 'static>(result: Result<T, E>) -> Result<T, E> {
     if let Err(e) = &result {
         log::error!("{:?}", e);
+
+        error::set_last_error(e, format!("{:?}", e));
     }
 
     result
 }
 
 /// Windows yes! The Windows dynamic library has an entry, so just
-/// initialize the logger and set the process priority at the entry.
+/// initialize the logger at the entry.
 #[no_mangle]
 #[allow(non_snake_case)]
 #[cfg(target_os = "windows")]
@@ -61,6 +83,7 @@ extern "system" fn DllMain(
 extern "C" fn hylarana_startup() -> bool {
     log_error((|| {
         logger::init_logger(log::LevelFilter::Info, None)?;
+        crash::install(CrashHandlerOptions::default());
 
         startup()?;
         Ok::<_, anyhow::Error>(())
@@ -77,6 +100,248 @@ extern "C" fn hylarana_shutdown() {
     let _ = log_error(shutdown());
 }
 
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy)]
+enum RawLogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl From<RawLogLevel> for log::LevelFilter {
+    fn from(value: RawLogLevel) -> Self {
+        match value {
+            RawLogLevel::Off => Self::Off,
+            RawLogLevel::Error => Self::Error,
+            RawLogLevel::Warn => Self::Warn,
+            RawLogLevel::Info => Self::Info,
+            RawLogLevel::Debug => Self::Debug,
+            RawLogLevel::Trace => Self::Trace,
+        }
+    }
+}
+
+impl From<log::Level> for RawLogLevel {
+    fn from(value: log::Level) -> Self {
+        match value {
+            log::Level::Error => Self::Error,
+            log::Level::Warn => Self::Warn,
+            log::Level::Info => Self::Info,
+            log::Level::Debug => Self::Debug,
+            log::Level::Trace => Self::Trace,
+        }
+    }
+}
+
+/// Change the level of the logs at runtime.
+#[no_mangle]
+extern "C" fn hylarana_set_log_level(level: RawLogLevel) {
+    logger::set_level(level.into());
+}
+
+/// Change the level of a module and its sub modules at runtime, such as
+/// `hylarana_transport`.
+#[no_mangle]
+extern "C" fn hylarana_set_log_module_level(module: *const c_char, level: RawLogLevel) {
+    assert!(!module.is_null());
+
+    if let Ok(module) = log_error(PSTR::from(module).to_string()) {
+        logger::set_module_level(&module, Some(level.into()));
+    }
+}
+
+/// Pass the log records to the callback, the callback can be null, which
+/// removes it. The strings are only valid in the callback.
+#[no_mangle]
+extern "C" fn hylarana_set_log_callback(
+    callback: Option<
+        extern "C" fn(
+            ctx: *const c_void,
+            level: RawLogLevel,
+            target: *const c_char,
+            message: *const c_char,
+        ),
+    >,
+    ctx: *const c_void,
+) {
+    // The context is passed back to the application as it is.
+    let ctx = ctx as usize;
+
+    logger::set_callback(callback.map(|callback| {
+        move |record: &logger::LogRecord| {
+            let (Ok(target), Ok(message)) = (
+                CString::new(record.target),
+                CString::new(record.message.as_str()),
+            ) else {
+                return;
+            };
+
+            callback(
+                ctx as *const c_void,
+                record.level.into(),
+                target.as_ptr(),
+                message.as_ptr(),
+            );
+        }
+    }));
+}
+
+/// Write the reports of the crashes to the directory, and call the callback
+/// when the process crashes, the directory and the callback can be null. The
+/// minidump is only written on Windows. The strings are only valid in the
+/// callback, the report is null if it has not been written.
+#[no_mangle]
+extern "C" fn hylarana_set_crash_handler(
+    directory: *const c_char,
+    minidump: bool,
+    callback: Option<
+        extern "C" fn(ctx: *const c_void, message: *const c_char, report: *const c_char),
+    >,
+    ctx: *const c_void,
+) {
+    let directory = if !directory.is_null() {
+        match log_error(PSTR::from(directory).to_string()) {
+            Ok(it) => Some(it.into()),
+            Err(_) => return,
+        }
+    } else {
+        None
+    };
+
+    crash::install(CrashHandlerOptions {
+        directory,
+        minidump,
+    });
+
+    // The context is passed back to the application as it is.
+    let ctx = ctx as usize;
+
+    crash::set_callback(callback.map(|callback| {
+        move |report: &crash::CrashReport| {
+            let message = CString::new(report.message.replace('\0', "")).unwrap_or_default();
+            let path = report
+                .report
+                .as_ref()
+                .and_then(|it| CString::new(it.to_string_lossy().as_bytes()).ok());
+
+            callback(
+                ctx as *const c_void,
+                message.as_ptr(),
+                path.as_ref().map(|it| it.as_ptr()).unwrap_or(null()),
+            );
+        }
+    }));
+}
+
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy)]
+enum RawThreadKind {
+    Capture,
+    Decode,
+    Transport,
+    Render,
+    Encode,
+}
+
+impl From<RawThreadKind> for ThreadKind {
+    fn from(value: RawThreadKind) -> Self {
+        match value {
+            RawThreadKind::Capture => Self::Capture,
+            RawThreadKind::Decode => Self::Decode,
+            RawThreadKind::Transport => Self::Transport,
+            RawThreadKind::Render => Self::Render,
+            RawThreadKind::Encode => Self::Encode,
+        }
+    }
+}
+
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy)]
+enum RawThreadPriority {
+    Default,
+    Low,
+    Normal,
+    High,
+    Realtime,
+}
+
+impl From<RawThreadPriority> for Option<ThreadPriority> {
+    fn from(value: RawThreadPriority) -> Self {
+        match value {
+            RawThreadPriority::Default => None,
+            RawThreadPriority::Low => Some(ThreadPriority::Low),
+            RawThreadPriority::Normal => Some(ThreadPriority::Normal),
+            RawThreadPriority::High => Some(ThreadPriority::High),
+            RawThreadPriority::Realtime => Some(ThreadPriority::Realtime),
+        }
+    }
+}
+
+/// Set the priority and the CPUs of a kind of the threads of the pipeline, the
+/// threads that are created afterwards use them. The affinity can be null,
+/// which lets the system choose the CPUs.
+#[no_mangle]
+extern "C" fn hylarana_set_thread_options(
+    kind: RawThreadKind,
+    priority: RawThreadPriority,
+    affinity: *const usize,
+    affinity_size: usize,
+) {
+    let mut options = runtime::runtime_options();
+    let thread = match kind {
+        RawThreadKind::Capture => &mut options.capture,
+        RawThreadKind::Decode => &mut options.decode,
+        RawThreadKind::Transport => &mut options.transport,
+        RawThreadKind::Render => &mut options.render,
+        RawThreadKind::Encode => &mut options.encode,
+    };
+
+    thread.priority = priority.into();
+    thread.affinity = if !affinity.is_null() {
+        Some(unsafe { std::slice::from_raw_parts(affinity, affinity_size) }.to_vec())
+    } else {
+        None
+    };
+
+    runtime::set_runtime_options(options);
+}
+
+/// Apply the options of the kind to the calling thread, such as the render
+/// thread of the application.
+#[no_mangle]
+extern "C" fn hylarana_apply_thread_options(kind: RawThreadKind) {
+    runtime::apply_thread_options(kind.into());
+}
+
+#[repr(C)]
+#[allow(unused)]
+enum RawAdapterPreference {
+    LowPower,
+    HighPerformance,
+    Device,
+}
+
+/// Set the graphics adapter used by the capture, the codecs and the renderers,
+/// the vendor id and device id are only used by the device preference.
+#[no_mangle]
+extern "C" fn hylarana_set_adapter_preference(
+    preference: RawAdapterPreference,
+    vendor: u32,
+    device: u32,
+) {
+    set_adapter_preference(match preference {
+        RawAdapterPreference::LowPower => AdapterPreference::LowPower,
+        RawAdapterPreference::HighPerformance => AdapterPreference::HighPerformance,
+        RawAdapterPreference::Device => AdapterPreference::Device { vendor, device },
+    });
+}
+
 #[repr(C)]
 #[allow(unused)]
 enum RawTransportStrategy {
@@ -105,6 +370,12 @@ impl TryInto<TransportOptions> for RawTransportOptions {
                 RawTransportStrategy::Multicast => TransportStrategy::Multicast(address),
             },
             mtu: self.mtu,
+            multicast: Default::default(),
+            dscp: None,
+            resume: None,
+            pacing: None,
+            keepalive: None,
+            latency: None,
         })
     }
 }
@@ -116,6 +387,20 @@ enum RawVideoEncoderType {
     X264,
     Qsv,
     VideoToolBox,
+    X265,
+    HevcQsv,
+}
+
+impl From<VideoEncoderType> for RawVideoEncoderType {
+    fn from(value: VideoEncoderType) -> Self {
+        match value {
+            VideoEncoderType::X264 => Self::X264,
+            VideoEncoderType::Qsv => Self::Qsv,
+            VideoEncoderType::VideoToolBox => Self::VideoToolBox,
+            VideoEncoderType::X265 => Self::X265,
+            VideoEncoderType::HevcQsv => Self::HevcQsv,
+        }
+    }
 }
 
 impl Into<VideoEncoderType> for RawVideoEncoderType {
@@ -124,6 +409,80 @@ impl Into<VideoEncoderType> for RawVideoEncoderType {
             Self::X264 => VideoEncoderType::X264,
             Self::Qsv => VideoEncoderType::Qsv,
             Self::VideoToolBox => VideoEncoderType::VideoToolBox,
+            Self::X265 => VideoEncoderType::X265,
+            Self::HevcQsv => VideoEncoderType::HevcQsv,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(unused)]
+enum RawVideoProfile {
+    Default,
+    Baseline,
+    Main,
+    High,
+}
+
+impl Into<Option<VideoProfile>> for RawVideoProfile {
+    fn into(self) -> Option<VideoProfile> {
+        match self {
+            Self::Default => None,
+            Self::Baseline => Some(VideoProfile::Baseline),
+            Self::Main => Some(VideoProfile::Main),
+            Self::High => Some(VideoProfile::High),
+        }
+    }
+}
+
+impl From<Option<VideoProfile>> for RawVideoProfile {
+    fn from(value: Option<VideoProfile>) -> Self {
+        match value {
+            None => Self::Default,
+            Some(VideoProfile::Baseline) => Self::Baseline,
+            Some(VideoProfile::Main) => Self::Main,
+            Some(VideoProfile::High) => Self::High,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(unused)]
+enum RawRateControl {
+    Default,
+    Cbr,
+    Vbr,
+    Cqp,
+    Lossless,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+#[allow(unused)]
+enum RawContentHint {
+    Default,
+    Motion,
+    Detail,
+}
+
+impl Into<Option<ContentHint>> for RawContentHint {
+    fn into(self) -> Option<ContentHint> {
+        match self {
+            Self::Default => None,
+            Self::Motion => Some(ContentHint::Motion),
+            Self::Detail => Some(ContentHint::Detail),
+        }
+    }
+}
+
+impl From<Option<ContentHint>> for RawContentHint {
+    fn from(value: Option<ContentHint>) -> Self {
+        match value {
+            None => Self::Default,
+            Some(ContentHint::Motion) => Self::Motion,
+            Some(ContentHint::Detail) => Self::Detail,
         }
     }
 }
@@ -138,6 +497,19 @@ struct RawVideoOptions {
     height: u32,
     bit_rate: u64,
     key_frame_interval: u32,
+    adaptive_pacing: bool,
+    capture_width: u32,
+    capture_height: u32,
+    profile: RawVideoProfile,
+    level: u8,
+    rate_control: RawRateControl,
+    max_bit_rate: u64,
+    qp: u8,
+    b_frames: u8,
+    slices: u8,
+    low_latency: bool,
+    intra_refresh: bool,
+    content_hint: RawContentHint,
 }
 
 impl TryInto<VideoOptions> for RawVideoOptions {
@@ -151,10 +523,83 @@ impl TryInto<VideoOptions> for RawVideoOptions {
             width: self.width,
             height: self.height,
             bit_rate: self.bit_rate,
+            adaptive_pacing: self.adaptive_pacing,
+            thumbnail: None,
+            deinterlace: None,
+            watermark: None,
+            privacy_masks: None,
+            content_detection: None,
+            scene_cut: None,
+            dirty_regions: None,
+            capture_size: if self.capture_width > 0 && self.capture_height > 0 {
+                Some(Size {
+                    width: self.capture_width,
+                    height: self.capture_height,
+                })
+            } else {
+                None
+            },
+            tuning: VideoEncoderTuning {
+                profile: self.profile.into(),
+                level: if self.level > 0 {
+                    Some(self.level)
+                } else {
+                    None
+                },
+                rate_control: match self.rate_control {
+                    RawRateControl::Default => None,
+                    RawRateControl::Cbr => Some(RateControl::Cbr),
+                    RawRateControl::Vbr => Some(RateControl::Vbr {
+                        max_bit_rate: self.max_bit_rate,
+                    }),
+                    RawRateControl::Cqp => Some(RateControl::Cqp { qp: self.qp }),
+                    RawRateControl::Lossless => Some(RateControl::Lossless),
+                },
+                b_frames: self.b_frames,
+                slices: self.slices,
+                low_latency: self.low_latency,
+                intra_refresh: self.intra_refresh,
+                content_hint: self.content_hint.into(),
+            },
         })
     }
 }
 
+impl From<VideoOptions> for RawVideoOptions {
+    fn from(value: VideoOptions) -> Self {
+        let tuning = value.tuning;
+        let (rate_control, max_bit_rate, qp) = match tuning.rate_control {
+            None => (RawRateControl::Default, 0, 0),
+            Some(RateControl::Cbr) => (RawRateControl::Cbr, 0, 0),
+            Some(RateControl::Vbr { max_bit_rate }) => (RawRateControl::Vbr, max_bit_rate, 0),
+            Some(RateControl::Cqp { qp }) => (RawRateControl::Cqp, 0, qp),
+            Some(RateControl::Lossless) => (RawRateControl::Lossless, 0, 0),
+        };
+
+        Self {
+            codec: value.codec.into(),
+            frame_rate: value.frame_rate,
+            width: value.width,
+            height: value.height,
+            bit_rate: value.bit_rate,
+            key_frame_interval: value.key_frame_interval,
+            adaptive_pacing: value.adaptive_pacing,
+            capture_width: value.capture_size.map(|it| it.width).unwrap_or(0),
+            capture_height: value.capture_size.map(|it| it.height).unwrap_or(0),
+            profile: tuning.profile.into(),
+            level: tuning.level.unwrap_or(0),
+            rate_control,
+            max_bit_rate,
+            qp,
+            b_frames: tuning.b_frames,
+            slices: tuning.slices,
+            low_latency: tuning.low_latency,
+            intra_refresh: tuning.intra_refresh,
+            content_hint: tuning.content_hint.into(),
+        }
+    }
+}
+
 /// Audio Codec Configuration.
 #[repr(C)]
 #[derive(Clone, Copy)]
@@ -168,6 +613,18 @@ impl Into<AudioOptions> for RawAudioOptions {
         AudioOptions {
             sample_rate: self.sample_rate,
             bit_rate: self.bit_rate,
+            gain: 1.0,
+            capture: Default::default(),
+            dtx: None,
+        }
+    }
+}
+
+impl From<AudioOptions> for RawAudioOptions {
+    fn from(value: AudioOptions) -> Self {
+        Self {
+            sample_rate: value.sample_rate,
+            bit_rate: value.bit_rate,
         }
     }
 }
@@ -200,13 +657,15 @@ impl TryInto<HylaranaSenderMediaOptions> for RawSenderMediaOptions {
             },
             audio: if !self.audio.is_null() {
                 let audio = unsafe { &*self.audio };
-                Some(HylaranaSenderTrackOptions {
+                vec![HylaranaSenderTrackOptions {
                     source: unsafe { &*audio.source }.try_into()?,
                     options: audio.options.try_into()?,
-                })
+                }]
             } else {
-                None
+                Vec::new()
             },
+            graphics: Default::default(),
+            sandbox: None,
         })
     }
 }
@@ -227,6 +686,10 @@ impl TryInto<HylaranaSenderOptions> for RawSenderOptions {
         Ok(HylaranaSenderOptions {
             transport: self.transport.try_into()?,
             media: self.media.try_into()?,
+            metadata: Default::default(),
+            id: None,
+            energy_saver: None,
+            pairing: None,
         })
     }
 }
@@ -270,6 +733,141 @@ extern "C" fn hylarana_sender_destroy(sender: *mut RawSender) {
     drop(unsafe { Box::from_raw(sender) })
 }
 
+/// Pause the sender, the capture is stopped and the receivers keep the last
+/// frame until the sender resumes.
+#[no_mangle]
+extern "C" fn hylarana_sender_pause(sender: *const RawSender) -> bool {
+    assert!(!sender.is_null());
+
+    log_error(unsafe { &*sender }.0.pause()).is_ok()
+}
+
+/// Resume the paused sender.
+#[no_mangle]
+extern "C" fn hylarana_sender_resume(sender: *const RawSender) -> bool {
+    assert!(!sender.is_null());
+
+    log_error(unsafe { &*sender }.0.resume()).is_ok()
+}
+
+#[repr(C)]
+struct RawReceiverInfo {
+    id: u64,
+    addr: *const c_char,
+    // The time the receiver has connected, in milliseconds since the unix
+    // epoch.
+    joined: u64,
+    // The round trip time in microseconds, zero if it is not known.
+    rtt: u64,
+}
+
+#[repr(C)]
+struct RawReceivers {
+    items: *mut RawReceiverInfo,
+    capacity: usize,
+    size: usize,
+}
+
+impl From<Vec<ReceiverInfo>> for RawReceivers {
+    fn from(value: Vec<ReceiverInfo>) -> Self {
+        let mut items = ManuallyDrop::new(
+            value
+                .into_iter()
+                .map(|it| RawReceiverInfo {
+                    id: it.id,
+                    addr: CString::new(it.addr.to_string()).unwrap().into_raw(),
+                    joined: it
+                        .joined
+                        .duration_since(UNIX_EPOCH)
+                        .map(|it| it.as_millis() as u64)
+                        .unwrap_or_default(),
+                    rtt: it.rtt.map(|it| it.as_micros() as u64).unwrap_or_default(),
+                })
+                .collect::<Vec<_>>(),
+        );
+
+        Self {
+            items: items.as_mut_ptr(),
+            capacity: items.capacity(),
+            size: items.len(),
+        }
+    }
+}
+
+/// Get the receivers that are attached to the sender, only the direct mode
+/// knows its receivers. The list needs to be released with
+/// `hylarana_receivers_destroy`.
+#[no_mangle]
+extern "C" fn hylarana_sender_get_receivers(sender: *const RawSender) -> RawReceivers {
+    assert!(!sender.is_null());
+
+    unsafe { &*sender }.0.receivers().into()
+}
+
+/// Disconnect a receiver of the sender, the receiver closes its stream instead
+/// of waiting to reattach. Returns false if the receiver is not attached.
+#[no_mangle]
+extern "C" fn hylarana_sender_disconnect(sender: *const RawSender, id: u64) -> bool {
+    assert!(!sender.is_null());
+
+    unsafe { &*sender }.0.disconnect(id)
+}
+
+/// Require the receivers to submit the pairing code of the sender, only the
+/// direct mode supports it. A new code is generated when the pairing is turned
+/// on, and again after a number of wrong codes, see
+/// `hylarana_sender_get_pairing_code`.
+#[no_mangle]
+extern "C" fn hylarana_sender_set_pairing(sender: *const RawSender, enabled: bool) -> bool {
+    assert!(!sender.is_null());
+
+    log_error(
+        unsafe { &*sender }
+            .0
+            .set_pairing(enabled.then(PairingOptions::default)),
+    )
+    .is_ok()
+}
+
+/// Get the pairing code of the sender, which the application shows to the
+/// presenter. The buffer of the code needs to be at least 16 bytes. Returns
+/// false if the pairing is off.
+#[no_mangle]
+extern "C" fn hylarana_sender_get_pairing_code(
+    sender: *const RawSender,
+    code: *mut c_char,
+) -> bool {
+    assert!(!sender.is_null() && !code.is_null());
+
+    copy_pairing_code(unsafe { &*sender }.0.pairing_code(), code)
+}
+
+// The codes are digits only, they always fit in the 16 bytes of the buffer.
+fn copy_pairing_code(value: Option<String>, code: *mut c_char) -> bool {
+    let Some(value) = value.and_then(|it| CString::new(it).ok()) else {
+        return false;
+    };
+
+    let bytes = value.as_bytes_with_nul();
+    unsafe {
+        std::ptr::copy_nonoverlapping(bytes.as_ptr().cast(), code, bytes.len().min(16));
+    }
+
+    true
+}
+
+/// Release the list of the receivers.
+#[no_mangle]
+extern "C" fn hylarana_receivers_destroy(receivers: *const RawReceivers) {
+    assert!(!receivers.is_null());
+
+    let receivers = unsafe { &*receivers };
+    for item in unsafe { Vec::from_raw_parts(receivers.items, receivers.size, receivers.capacity) }
+    {
+        drop(unsafe { CString::from_raw(item.addr as *mut _) });
+    }
+}
+
 #[repr(C)]
 struct RawSenderWithPlayer(HylaranaSender<Player>);
 
@@ -309,6 +907,111 @@ extern "C" fn hylarana_sender_with_player_destroy(sender: *mut RawSenderWithPlay
     drop(unsafe { Box::from_raw(sender) })
 }
 
+/// Pause the sender with player, the capture is stopped and the receivers keep the last
+/// frame until the sender resumes.
+#[no_mangle]
+extern "C" fn hylarana_sender_with_player_pause(sender: *const RawSenderWithPlayer) -> bool {
+    assert!(!sender.is_null());
+
+    log_error(unsafe { &*sender }.0.pause()).is_ok()
+}
+
+/// Resume the paused sender with player.
+#[no_mangle]
+extern "C" fn hylarana_sender_with_player_resume(sender: *const RawSenderWithPlayer) -> bool {
+    assert!(!sender.is_null());
+
+    log_error(unsafe { &*sender }.0.resume()).is_ok()
+}
+
+/// Get the receivers that are attached to the sender with player, see
+/// `hylarana_sender_get_receivers`.
+#[no_mangle]
+extern "C" fn hylarana_sender_with_player_get_receivers(
+    sender: *const RawSenderWithPlayer,
+) -> RawReceivers {
+    assert!(!sender.is_null());
+
+    unsafe { &*sender }.0.receivers().into()
+}
+
+/// Disconnect a receiver of the sender with player, see
+/// `hylarana_sender_disconnect`.
+#[no_mangle]
+extern "C" fn hylarana_sender_with_player_disconnect(
+    sender: *const RawSenderWithPlayer,
+    id: u64,
+) -> bool {
+    assert!(!sender.is_null());
+
+    unsafe { &*sender }.0.disconnect(id)
+}
+
+/// Turn the pairing of the sender with player on or off, see
+/// `hylarana_sender_set_pairing`.
+#[no_mangle]
+extern "C" fn hylarana_sender_with_player_set_pairing(
+    sender: *const RawSenderWithPlayer,
+    enabled: bool,
+) -> bool {
+    assert!(!sender.is_null());
+
+    log_error(
+        unsafe { &*sender }
+            .0
+            .set_pairing(enabled.then(PairingOptions::default)),
+    )
+    .is_ok()
+}
+
+/// Get the pairing code of the sender with player, see
+/// `hylarana_sender_get_pairing_code`.
+#[no_mangle]
+extern "C" fn hylarana_sender_with_player_get_pairing_code(
+    sender: *const RawSenderWithPlayer,
+    code: *mut c_char,
+) -> bool {
+    assert!(!sender.is_null() && !code.is_null());
+
+    copy_pairing_code(unsafe { &*sender }.0.pairing_code(), code)
+}
+
+/// Resize the renderer of the sender's player, this needs to be called when
+/// the window size changes. The size is in physical pixels.
+#[no_mangle]
+extern "C" fn hylarana_sender_renderer_resize(
+    sender: *const RawSenderWithPlayer,
+    width: u32,
+    height: u32,
+) -> bool {
+    assert!(!sender.is_null());
+
+    log_error(
+        unsafe { &*sender }
+            .0
+            .get_sink()
+            .resize(Size { width, height }),
+    )
+    .is_ok()
+}
+
+/// Read back the last frame rendered by the sender's player as RGBA, the
+/// size of the frame is written to `width` and `height`, and the frame is
+/// copied to the buffer only if the buffer is large enough, that is, at least
+/// `width * height * 4` bytes.
+#[no_mangle]
+extern "C" fn hylarana_sender_renderer_snapshot(
+    sender: *const RawSenderWithPlayer,
+    buf: *mut u8,
+    len: usize,
+    width: *mut u32,
+    height: *mut u32,
+) -> bool {
+    assert!(!sender.is_null());
+
+    copy_snapshot(unsafe { &*sender }.0.get_sink(), buf, len, width, height)
+}
+
 #[repr(C)]
 #[allow(unused)]
 enum RawVideoDecoderType {
@@ -316,6 +1019,21 @@ enum RawVideoDecoderType {
     D3D11,
     Qsv,
     VideoToolBox,
+    HevcD3D11,
+    HevcQsv,
+}
+
+impl From<VideoDecoderType> for RawVideoDecoderType {
+    fn from(value: VideoDecoderType) -> Self {
+        match value {
+            VideoDecoderType::H264 => Self::H264,
+            VideoDecoderType::D3D11 => Self::D3D11,
+            VideoDecoderType::Qsv => Self::Qsv,
+            VideoDecoderType::VideoToolBox => Self::VideoToolBox,
+            VideoDecoderType::HevcD3D11 => Self::HevcD3D11,
+            VideoDecoderType::HevcQsv => Self::HevcQsv,
+        }
+    }
 }
 
 impl Into<VideoDecoderType> for RawVideoDecoderType {
@@ -325,10 +1043,62 @@ impl Into<VideoDecoderType> for RawVideoDecoderType {
             Self::D3D11 => VideoDecoderType::D3D11,
             Self::Qsv => VideoDecoderType::Qsv,
             Self::VideoToolBox => VideoDecoderType::VideoToolBox,
+            Self::HevcD3D11 => VideoDecoderType::HevcD3D11,
+            Self::HevcQsv => VideoDecoderType::HevcQsv,
         }
     }
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+enum RawCodecStatus {
+    Available,
+    Unsupported,
+    NotFound,
+    SessionFailed,
+}
+
+impl From<CodecStatus> for RawCodecStatus {
+    fn from(value: CodecStatus) -> Self {
+        match value {
+            CodecStatus::Available => Self::Available,
+            CodecStatus::Unsupported => Self::Unsupported,
+            CodecStatus::NotFound => Self::NotFound,
+            CodecStatus::SessionFailed => Self::SessionFailed,
+        }
+    }
+}
+
+/// The status of each codec, indexed by the encoder and decoder types.
+#[repr(C)]
+struct RawCodecCapabilities {
+    encoders: [RawCodecStatus; 5],
+    decoders: [RawCodecStatus; 6],
+}
+
+/// Check which video encoders and decoders can be used on this machine, each
+/// codec is opened once, so this is slow and the result should be cached.
+#[no_mangle]
+extern "C" fn hylarana_codec_capabilities() -> RawCodecCapabilities {
+    log::info!("extern api: hylarana codec capabilities");
+
+    let capabilities = Hylarana::probe_codecs();
+    let mut raw = RawCodecCapabilities {
+        encoders: [RawCodecStatus::Unsupported; 5],
+        decoders: [RawCodecStatus::Unsupported; 6],
+    };
+
+    for item in capabilities.encoders {
+        raw.encoders[RawVideoEncoderType::from(item.codec) as usize] = item.status.into();
+    }
+
+    for item in capabilities.decoders {
+        raw.decoders[RawVideoDecoderType::from(item.codec) as usize] = item.status.into();
+    }
+
+    raw
+}
+
 #[repr(C)]
 struct RawReceiverCodecOptions {
     video: RawVideoDecoderType,
@@ -350,6 +1120,20 @@ extern "C" fn hylarana_create_receiver(
     id: *const c_char,
     options: RawReceiverOptions,
     sink: RawAVFrameStream,
+) -> *const RawReceiver {
+    hylarana_create_paired_receiver(id, null(), options, sink)
+}
+
+/// Create a receiver that submits the pairing code to a sender that requires
+/// it, the code can be null. The receiver is not created if the code is
+/// missing or wrong, the error is "network.pairing_failed", see
+/// `hylarana_get_last_error`.
+#[no_mangle]
+extern "C" fn hylarana_create_paired_receiver(
+    id: *const c_char,
+    code: *const c_char,
+    options: RawReceiverOptions,
+    sink: RawAVFrameStream,
 ) -> *const RawReceiver {
     assert!(!id.is_null());
 
@@ -362,7 +1146,12 @@ extern "C" fn hylarana_create_receiver(
                 transport: options.transport.try_into()?,
                 codec: HylaranaReceiverCodecOptions {
                     video: options.codec.video.into(),
+                    graphics: Default::default(),
+                    thumbnail_only: false,
+                    passthrough: false,
                 },
+                tracks: Default::default(),
+                pairing_code: PSTR::from(code).to_string().ok(),
             },
             sink,
         )?)
@@ -404,7 +1193,12 @@ extern "C" fn hylarana_create_receiver_with_player(
                 transport: options.transport.try_into()?,
                 codec: HylaranaReceiverCodecOptions {
                     video: options.codec.video.into(),
+                    graphics: Default::default(),
+                    thumbnail_only: false,
+                    passthrough: false,
                 },
+                tracks: Default::default(),
+                pairing_code: None,
             },
             player_options.create_player()?,
         )?)
@@ -422,3 +1216,68 @@ extern "C" fn hylarana_receiver_with_player_destroy(receiver: *mut RawReceiverWi
 
     drop(unsafe { Box::from_raw(receiver) })
 }
+
+/// Resize the renderer of the receiver's player, this needs to be called when
+/// the window size changes. The size is in physical pixels.
+#[no_mangle]
+extern "C" fn hylarana_receiver_renderer_resize(
+    receiver: *const RawReceiverWithPlayer,
+    width: u32,
+    height: u32,
+) -> bool {
+    assert!(!receiver.is_null());
+
+    log_error(
+        unsafe { &*receiver }
+            .0
+            .get_sink()
+            .resize(Size { width, height }),
+    )
+    .is_ok()
+}
+
+/// Read back the last frame rendered by the receiver's player as RGBA, the
+/// size of the frame is written to `width` and `height`, and the frame is
+/// copied to the buffer only if the buffer is large enough, that is, at least
+/// `width * height * 4` bytes.
+#[no_mangle]
+extern "C" fn hylarana_receiver_renderer_snapshot(
+    receiver: *const RawReceiverWithPlayer,
+    buf: *mut u8,
+    len: usize,
+    width: *mut u32,
+    height: *mut u32,
+) -> bool {
+    assert!(!receiver.is_null());
+
+    copy_snapshot(unsafe { &*receiver }.0.get_sink(), buf, len, width, height)
+}
+
+fn copy_snapshot(
+    player: &Player,
+    buf: *mut u8,
+    len: usize,
+    width: *mut u32,
+    height: *mut u32,
+) -> bool {
+    assert!(!buf.is_null() && !width.is_null() && !height.is_null());
+
+    let Ok(Some(image)) = log_error(player.snapshot()) else {
+        return false;
+    };
+
+    unsafe {
+        *width = image.size.width;
+        *height = image.size.height;
+    }
+
+    if image.data.len() > len {
+        return false;
+    }
+
+    unsafe {
+        std::ptr::copy_nonoverlapping(image.data.as_ptr(), buf, image.data.len());
+    }
+
+    true
+}
@@ -0,0 +1,94 @@
+use hylarana::{VideoFormat, VideoFrame};
+
+/// The order of the channels of the converted pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PixelOrder {
+    Rgba,
+    Bgra,
+}
+
+/// Convert the top left area of the size of a software frame to 8 bits RGBA or
+/// BGRA pixels without padding, for the targets that only take RGB pixels
+/// from the memory, such as the textures of Flutter and the Metal textures of
+/// Unity.
+pub(crate) fn convert(
+    frame: &VideoFrame,
+    order: PixelOrder,
+    width: usize,
+    height: usize,
+    output: &mut Vec<u8>,
+) -> anyhow::Result<()> {
+    let plane = |i: usize| frame.data[i] as *const u8;
+    let sample = |i: usize, offset: usize| unsafe { *plane(i).add(offset) };
+
+    output.clear();
+    output.reserve(width * height * 4);
+
+    match frame.format {
+        VideoFormat::BGRA | VideoFormat::RGBA => {
+            let swap = (frame.format == VideoFormat::BGRA) != (order == PixelOrder::Bgra);
+            for y in 0..height {
+                let row = unsafe {
+                    std::slice::from_raw_parts(plane(0).add(y * frame.linesize[0]), width * 4)
+                };
+
+                if swap {
+                    for pixel in row.chunks_exact(4) {
+                        output.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+                    }
+                } else {
+                    output.extend_from_slice(row);
+                }
+            }
+        }
+        VideoFormat::NV12 => {
+            for y in 0..height {
+                for x in 0..width {
+                    let offset = (y / 2) * frame.linesize[1] + (x / 2) * 2;
+                    output.extend_from_slice(&yuv_to_rgb(
+                        order,
+                        sample(0, y * frame.linesize[0] + x),
+                        sample(1, offset),
+                        sample(1, offset + 1),
+                    ));
+                }
+            }
+        }
+        VideoFormat::I420 => {
+            for y in 0..height {
+                for x in 0..width {
+                    output.extend_from_slice(&yuv_to_rgb(
+                        order,
+                        sample(0, y * frame.linesize[0] + x),
+                        sample(1, (y / 2) * frame.linesize[1] + x / 2),
+                        sample(2, (y / 2) * frame.linesize[2] + x / 2),
+                    ));
+                }
+            }
+        }
+        format => anyhow::bail!("not supports the frame format = {:?}", format),
+    }
+
+    Ok(())
+}
+
+// The same conversion as the shaders of the renderer, BT.709 in the full range.
+fn yuv_to_rgb(order: PixelOrder, y: u8, u: u8, v: u8) -> [u8; 4] {
+    let (y, u, v) = (
+        y as f32 / 255.0,
+        u as f32 / 255.0 - 0.5,
+        v as f32 / 255.0 - 0.5,
+    );
+
+    let channel = |c: f32| (c.clamp(0.0, 1.0) * 255.0) as u8;
+    let (r, g, b) = (
+        channel(y + 1.5748 * v),
+        channel(y - 0.187324 * u - 0.468124 * v),
+        channel(y + 1.8556 * u),
+    );
+
+    match order {
+        PixelOrder::Rgba => [r, g, b, 255],
+        PixelOrder::Bgra => [b, g, r, 255],
+    }
+}
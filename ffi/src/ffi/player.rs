@@ -10,8 +10,8 @@ use hylarana::{
         RawDisplayHandle, RawWindowHandle, WaylandDisplayHandle, WaylandWindowHandle,
         Win32WindowHandle, WindowHandle, XlibDisplayHandle, XlibWindowHandle,
     },
-    AVFrameObserver, AVFrameStreamPlayer, AVFrameStreamPlayerOptions, Size, SurfaceTarget,
-    VideoRenderBackend, VideoRenderOptions,
+    AVFrameObserver, AVFrameStreamPlayer, AVFrameStreamPlayerOptions, FitMode, ScaleFilter, Size,
+    SurfaceTarget, VideoRenderBackend, VideoRenderOptions,
 };
 
 trait GetSize {
@@ -309,11 +309,58 @@ impl Into<VideoRenderBackend> for RawVideoRenderBackend {
     }
 }
 
+/// How the video is fitted into the window.
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RawFitMode {
+    /// Stretch the video to fill the window, the aspect ratio is not kept.
+    Stretch,
+    /// Scale the video to fit inside the window, the rest of the window is left
+    /// black.
+    Contain,
+    /// Scale the video to fill the window, the parts of the video outside the
+    /// window are cropped.
+    Cover,
+}
+
+impl Into<FitMode> for RawFitMode {
+    fn into(self) -> FitMode {
+        match self {
+            Self::Stretch => FitMode::Stretch,
+            Self::Contain => FitMode::Contain,
+            Self::Cover => FitMode::Cover,
+        }
+    }
+}
+
+/// The filter used when scaling the video.
+#[repr(C)]
+#[allow(unused)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RawScaleFilter {
+    Nearest,
+    Bilinear,
+    Bicubic,
+}
+
+impl Into<ScaleFilter> for RawScaleFilter {
+    fn into(self) -> ScaleFilter {
+        match self {
+            Self::Nearest => ScaleFilter::Nearest,
+            Self::Bilinear => ScaleFilter::Bilinear,
+            Self::Bicubic => ScaleFilter::Bicubic,
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy)]
 struct RawVideoRenderOptions {
     window: RawWindowOptions,
     backend: RawVideoRenderBackend,
+    fit: RawFitMode,
+    filter: RawScaleFilter,
 }
 
 impl Into<VideoRenderOptions<RawWindowOptions>> for RawVideoRenderOptions {
@@ -321,7 +368,11 @@ impl Into<VideoRenderOptions<RawWindowOptions>> for RawVideoRenderOptions {
         VideoRenderOptions {
             backend: self.backend.into(),
             size: self.window.size(),
+            fit: self.fit.into(),
+            filter: self.filter.into(),
             target: self.window,
+            graphics: Default::default(),
+            pacing: None,
         }
     }
 }
@@ -0,0 +1,73 @@
+use std::{ffi::c_char, ptr::null};
+
+use hylarana::Profiles;
+use hylarana_common::strings::PSTR;
+
+use super::{log_error, RawAudioOptions, RawVideoOptions};
+
+/// Load the profiles, the built-in profiles are always available, the
+/// profiles in the file are added on top of them and replace the built-in
+/// profiles with the same name. The path can be null, and the file can be
+/// toml or json.
+#[no_mangle]
+extern "C" fn hylarana_profiles_load(path: *const c_char) -> *const Profiles {
+    log::info!("extern api: hylarana profiles load");
+
+    let mut profiles = Profiles::builtin();
+    if !path.is_null() {
+        let loaded = log_error((|| {
+            let path = PSTR::from(path).to_string()?;
+            Ok::<_, anyhow::Error>(Profiles::load(path)?)
+        })());
+
+        match loaded {
+            Ok(it) => profiles.extend(it),
+            Err(_) => return null(),
+        }
+    }
+
+    Box::into_raw(Box::new(profiles))
+}
+
+/// Get the options of the profile with the name, the options that the
+/// profile has are written into the non-null pointers. Returns false if the
+/// profile does not exist. The transport is not included, because the address
+/// depends on the network of the application.
+#[no_mangle]
+extern "C" fn hylarana_profiles_get(
+    profiles: *const Profiles,
+    name: *const c_char,
+    video: *mut RawVideoOptions,
+    audio: *mut RawAudioOptions,
+) -> bool {
+    assert!(!profiles.is_null());
+    assert!(!name.is_null());
+
+    let name = if let Ok(it) = PSTR::from(name).to_string() {
+        it
+    } else {
+        return false;
+    };
+
+    if let Some(profile) = unsafe { &*profiles }.get(&name) {
+        if let (Some(options), false) = (profile.video.clone(), video.is_null()) {
+            unsafe { video.write(options.into()) };
+        }
+
+        if let (Some(options), false) = (profile.audio, audio.is_null()) {
+            unsafe { audio.write(options.into()) };
+        }
+
+        true
+    } else {
+        false
+    }
+}
+
+/// Destroy the profiles.
+#[no_mangle]
+extern "C" fn hylarana_profiles_destroy(profiles: *mut Profiles) {
+    assert!(!profiles.is_null());
+
+    drop(unsafe { Box::from_raw(profiles) });
+}
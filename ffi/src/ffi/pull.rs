@@ -0,0 +1,158 @@
+use std::{ffi::c_char, ptr::null_mut, time::Duration};
+
+use hylarana::{
+    AudioFrame, FrameQueue, Hylarana, HylaranaReceiver, HylaranaReceiverCodecOptions,
+    HylaranaReceiverOptions, OwnedAudioFrame, OwnedVideoFrame, VideoFrame,
+};
+
+use hylarana_common::strings::PSTR;
+use parking_lot::Mutex;
+
+use super::{log_error, RawReceiverOptions};
+
+// The frames last taken from the queues are kept here, so that the planes that
+// the application reads stay valid until it takes the next frame.
+struct RawPullReceiver {
+    receiver: HylaranaReceiver<FrameQueue>,
+    video: Mutex<Option<OwnedVideoFrame>>,
+    audio: Mutex<Option<OwnedAudioFrame>>,
+}
+
+/// Create a receiver in the pull mode, the frames are kept in queues of the
+/// capacity and are taken by the application, such as on the render thread of
+/// a game engine, instead of being called back on the codec threads.
+#[no_mangle]
+extern "C" fn hylarana_create_pull_receiver(
+    id: *const c_char,
+    options: RawReceiverOptions,
+    capacity: usize,
+) -> *const RawPullReceiver {
+    assert!(!id.is_null());
+
+    log::info!("extern api: hylarana create pull receiver");
+
+    log_error((|| {
+        Ok::<_, anyhow::Error>(Hylarana::create_receiver(
+            PSTR::from(id).to_string()?,
+            HylaranaReceiverOptions {
+                transport: options.transport.try_into()?,
+                codec: HylaranaReceiverCodecOptions {
+                    video: options.codec.video.into(),
+                    graphics: Default::default(),
+                    thumbnail_only: false,
+                    passthrough: false,
+                },
+                tracks: Default::default(),
+                pairing_code: None,
+            },
+            FrameQueue::new(capacity),
+        )?)
+    })())
+    .map(|receiver| {
+        Box::into_raw(Box::new(RawPullReceiver {
+            video: Mutex::new(None),
+            audio: Mutex::new(None),
+            receiver,
+        }))
+    })
+    .unwrap_or_else(|_| null_mut())
+}
+
+/// Take the next video frame, waits up to the timeout in milliseconds if there
+/// is no frame, zero does not wait. The frame is valid until the next call of
+/// this function or until the receiver is destroyed. Returns false if there is
+/// no frame or the stream is closed.
+#[no_mangle]
+extern "C" fn hylarana_pull_receiver_next_video_frame(
+    receiver: *const RawPullReceiver,
+    timeout: u32,
+    frame: *mut VideoFrame,
+) -> bool {
+    assert!(!receiver.is_null() && !frame.is_null());
+
+    let receiver = unsafe { &*receiver };
+    let Some(next) = (if timeout == 0 {
+        receiver.receiver.try_next_video_frame()
+    } else {
+        receiver
+            .receiver
+            .next_video_frame(Duration::from_millis(timeout as u64))
+    }) else {
+        return false;
+    };
+
+    unsafe {
+        *frame = VideoFrame {
+            format: next.format,
+            sub_format: next.sub_format,
+            width: next.width,
+            height: next.height,
+            data: next.data,
+            linesize: next.linesize,
+            rotation: next.rotation,
+            mirror: next.mirror,
+            timestamp: next.timestamp,
+            field_order: next.field_order,
+            dma_buf: next.dma_buf,
+            display_width: next.display_width,
+            display_height: next.display_height,
+        };
+    }
+
+    receiver.video.lock().replace(next);
+    true
+}
+
+/// Take the next audio frame, waits up to the timeout in milliseconds if there
+/// is no frame, zero does not wait. The frame is valid until the next call of
+/// this function or until the receiver is destroyed. Returns false if there is
+/// no frame or the stream is closed.
+#[no_mangle]
+extern "C" fn hylarana_pull_receiver_next_audio_frame(
+    receiver: *const RawPullReceiver,
+    timeout: u32,
+    frame: *mut AudioFrame,
+) -> bool {
+    assert!(!receiver.is_null() && !frame.is_null());
+
+    let receiver = unsafe { &*receiver };
+    let Some(next) = (if timeout == 0 {
+        receiver.receiver.try_next_audio_frame()
+    } else {
+        receiver
+            .receiver
+            .next_audio_frame(Duration::from_millis(timeout as u64))
+    }) else {
+        return false;
+    };
+
+    unsafe {
+        *frame = AudioFrame {
+            sample_rate: next.sample_rate,
+            frames: next.frames,
+            data: next.data,
+            timestamp: next.timestamp,
+        };
+    }
+
+    receiver.audio.lock().replace(next);
+    true
+}
+
+/// Whether the stream of the pull receiver has been closed.
+#[no_mangle]
+extern "C" fn hylarana_pull_receiver_is_closed(receiver: *const RawPullReceiver) -> bool {
+    assert!(!receiver.is_null());
+
+    unsafe { &*receiver }.receiver.is_closed()
+}
+
+/// Destroy the pull receiver, the frames taken from it are no longer valid.
+#[no_mangle]
+extern "C" fn hylarana_pull_receiver_destroy(receiver: *mut RawPullReceiver) {
+    assert!(!receiver.is_null());
+
+    log::info!("extern api: hylarana close pull receiver");
+
+    drop(unsafe { Box::from_raw(receiver) })
+}
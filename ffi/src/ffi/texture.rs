@@ -0,0 +1,212 @@
+use std::{
+    ffi::{c_char, c_void},
+    ptr::{null, null_mut},
+};
+
+use hylarana::{
+    AVFrameObserver, AVFrameSink, AVFrameStream, Hylarana, HylaranaReceiver,
+    HylaranaReceiverCodecOptions, HylaranaReceiverOptions, OwnedVideoFrame, VideoFrame,
+};
+
+use hylarana_common::strings::PSTR;
+use parking_lot::Mutex;
+
+use super::{
+    log_error,
+    pixels::{convert, PixelOrder},
+    RawReceiverOptions,
+};
+
+/// The order of the channels of the pixel buffers of a texture receiver.
+#[repr(C)]
+#[allow(unused)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum RawPixelOrder {
+    Rgba,
+    Bgra,
+}
+
+impl Into<PixelOrder> for RawPixelOrder {
+    fn into(self) -> PixelOrder {
+        match self {
+            Self::Rgba => PixelOrder::Rgba,
+            Self::Bgra => PixelOrder::Bgra,
+        }
+    }
+}
+
+/// A pixel buffer, it has the same layout as `FlutterDesktopPixelBuffer`, so
+/// the copy function can be used as the callback of a pixel buffer texture of
+/// Flutter directly.
+#[repr(C)]
+struct RawPixelBuffer {
+    buffer: *const u8,
+    width: usize,
+    height: usize,
+    release_callback: Option<extern "C" fn(ctx: *mut c_void)>,
+    release_context: *mut c_void,
+}
+
+struct TextureSink {
+    frame: Mutex<Option<OwnedVideoFrame>>,
+    callback: extern "C" fn(ctx: *const c_void),
+    close: Option<extern "C" fn(ctx: *const c_void)>,
+    ctx: *const c_void,
+}
+
+unsafe impl Send for TextureSink {}
+unsafe impl Sync for TextureSink {}
+
+impl AVFrameSink for TextureSink {
+    fn video(&self, frame: &VideoFrame) -> bool {
+        if let Some(frame) = frame.to_owned() {
+            self.frame.lock().replace(frame);
+
+            (self.callback)(self.ctx);
+        }
+
+        true
+    }
+}
+
+impl AVFrameObserver for TextureSink {
+    fn close(&self) {
+        if let Some(close) = self.close {
+            close(self.ctx);
+        }
+    }
+}
+
+impl AVFrameStream for TextureSink {}
+
+struct Pixels {
+    buffer: Vec<u8>,
+    raw: RawPixelBuffer,
+}
+
+// The pixels are locked from the copy until the engine releases the buffer.
+struct RawTextureReceiver {
+    receiver: HylaranaReceiver<TextureSink>,
+    pixels: Mutex<Pixels>,
+    order: PixelOrder,
+}
+
+/// Create a receiver whose video is copied into the pixel buffers of an
+/// external texture, such as a texture of Flutter. The callback is called on
+/// the decoder thread when a new frame is available, which is where the
+/// texture is marked as updated, and the frame is converted when the engine
+/// copies the pixel buffer. Only the software frames are converted, so the
+/// receiver should use a software decoder. The audio is not played.
+#[no_mangle]
+extern "C" fn hylarana_create_texture_receiver(
+    id: *const c_char,
+    options: RawReceiverOptions,
+    order: RawPixelOrder,
+    callback: extern "C" fn(ctx: *const c_void),
+    close: Option<extern "C" fn(ctx: *const c_void)>,
+    ctx: *const c_void,
+) -> *const RawTextureReceiver {
+    assert!(!id.is_null());
+
+    log::info!("extern api: hylarana create texture receiver");
+
+    log_error((|| {
+        Ok::<_, anyhow::Error>(Hylarana::create_receiver(
+            PSTR::from(id).to_string()?,
+            HylaranaReceiverOptions {
+                transport: options.transport.try_into()?,
+                codec: HylaranaReceiverCodecOptions {
+                    video: options.codec.video.into(),
+                    graphics: Default::default(),
+                    thumbnail_only: false,
+                    passthrough: false,
+                },
+                tracks: Default::default(),
+                pairing_code: None,
+            },
+            TextureSink {
+                frame: Mutex::new(None),
+                callback,
+                close,
+                ctx,
+            },
+        )?)
+    })())
+    .map(|receiver| {
+        Box::into_raw(Box::new(RawTextureReceiver {
+            pixels: Mutex::new(Pixels {
+                buffer: Vec::new(),
+                raw: RawPixelBuffer {
+                    buffer: null(),
+                    width: 0,
+                    height: 0,
+                    release_callback: None,
+                    release_context: null_mut(),
+                },
+            }),
+            order: order.into(),
+            receiver,
+        }))
+    })
+    .unwrap_or_else(|_| null_mut())
+}
+
+/// Copy the newest frame into the pixel buffer, the size that the engine asks
+/// for is ignored and the buffer has the size of the video. Returns null if
+/// no frame has been received. The buffer is valid until the release callback
+/// of the buffer is called, which has to be called before the next copy.
+#[no_mangle]
+extern "C" fn hylarana_texture_receiver_copy_pixel_buffer(
+    _width: usize,
+    _height: usize,
+    receiver: *const RawTextureReceiver,
+) -> *const RawPixelBuffer {
+    assert!(!receiver.is_null());
+
+    let receiver = unsafe { &*receiver };
+    let mut pixels = receiver.pixels.lock();
+
+    // The pixels of the previous frame are returned again if there is no new
+    // frame.
+    if let Some(frame) = receiver.receiver.get_sink().frame.lock().take() {
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        if let Err(e) = convert(&frame, receiver.order, width, height, &mut pixels.buffer) {
+            log::error!("texture receiver failed to convert the frame, err={:?}", e);
+
+            return null();
+        }
+
+        pixels.raw.width = width;
+        pixels.raw.height = height;
+    }
+
+    if pixels.buffer.is_empty() {
+        return null();
+    }
+
+    pixels.raw.buffer = pixels.buffer.as_ptr();
+    pixels.raw.release_callback = Some(release_pixel_buffer);
+    pixels.raw.release_context = receiver as *const _ as *mut c_void;
+
+    let raw = &pixels.raw as *const RawPixelBuffer;
+    std::mem::forget(pixels);
+
+    raw
+}
+
+extern "C" fn release_pixel_buffer(ctx: *mut c_void) {
+    unsafe {
+        (*(ctx as *const RawTextureReceiver)).pixels.force_unlock();
+    }
+}
+
+/// Destroy the texture receiver, the texture should be unregistered first, so
+/// that no pixel buffer is in use.
+#[no_mangle]
+extern "C" fn hylarana_texture_receiver_destroy(receiver: *mut RawTextureReceiver) {
+    assert!(!receiver.is_null());
+
+    log::info!("extern api: hylarana close texture receiver");
+
+    drop(unsafe { Box::from_raw(receiver) })
+}
@@ -0,0 +1,491 @@
+//! The native rendering plugin interface of Unity, the library is loaded by
+//! Unity as a native plugin, the video of a receiver is drawn directly to a
+//! texture of Unity on the render thread of Unity, so the frames do not pass
+//! through C#.
+//!
+//! See `sdk/unity` for the C# side.
+
+use std::{
+    collections::BTreeMap,
+    ffi::{c_char, c_void},
+    ptr::null_mut,
+    sync::{
+        atomic::{AtomicI32, AtomicPtr, Ordering},
+        Arc,
+    },
+};
+
+use hylarana::{
+    FitMode, FrameQueue, Hylarana, HylaranaReceiver, HylaranaReceiverCodecOptions,
+    HylaranaReceiverOptions, OwnedVideoFrame,
+};
+
+#[cfg(target_os = "windows")]
+use hylarana::VideoRender;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::{d3d_device_borrowed_raw, d3d_texture_borrowed_raw, Direct3DDevice};
+
+use hylarana_common::{atomic::EasyAtomic, strings::PSTR};
+use parking_lot::Mutex;
+
+use super::{log_error, player::RawFitMode, RawReceiverOptions};
+
+// The GUIDs of the interfaces in IUnityGraphics.h and IUnityGraphicsD3D11.h.
+const UNITY_GRAPHICS_GUID: (u64, u64) = (0x7CBA0A9CA4DDB544, 0x8C5AD4926EB17B11);
+#[cfg(target_os = "windows")]
+const UNITY_GRAPHICS_D3D11_GUID: (u64, u64) = (0xAAB37EF87A87D748, 0xBF76967F07EFB177);
+
+// UnityGfxRenderer and UnityGfxDeviceEventType.
+const UNITY_GFX_RENDERER_NULL: i32 = 4;
+#[cfg(target_os = "windows")]
+const UNITY_GFX_RENDERER_D3D11: i32 = 2;
+#[cfg(target_os = "macos")]
+const UNITY_GFX_RENDERER_METAL: i32 = 16;
+const UNITY_GFX_DEVICE_EVENT_INITIALIZE: i32 = 0;
+const UNITY_GFX_DEVICE_EVENT_SHUTDOWN: i32 = 1;
+
+// The number of the event ids that are reserved for the receivers.
+const MAX_RECEIVERS: i32 = 1024;
+
+type DeviceEventCallback = extern "system" fn(event: i32);
+
+#[repr(C)]
+struct IUnityInterfaces {
+    get_interface: *const c_void,
+    register_interface: *const c_void,
+    get_interface_split: extern "system" fn(high: u64, low: u64) -> *mut c_void,
+    register_interface_split: *const c_void,
+}
+
+#[repr(C)]
+struct IUnityGraphics {
+    get_renderer: extern "system" fn() -> i32,
+    register_device_event_callback: extern "system" fn(callback: DeviceEventCallback),
+    unregister_device_event_callback: extern "system" fn(callback: DeviceEventCallback),
+    reserve_event_id_range: extern "system" fn(count: i32) -> i32,
+}
+
+// Only the first function of the interface is used.
+#[cfg(target_os = "windows")]
+#[repr(C)]
+struct IUnityGraphicsD3D11 {
+    get_device: extern "system" fn() -> *mut c_void,
+}
+
+static INTERFACES: AtomicPtr<IUnityInterfaces> = AtomicPtr::new(null_mut());
+static GRAPHICS: AtomicPtr<IUnityGraphics> = AtomicPtr::new(null_mut());
+static RENDERER: AtomicI32 = AtomicI32::new(UNITY_GFX_RENDERER_NULL);
+
+#[cfg(target_os = "windows")]
+static DIRECT_3D_DEVICE: Mutex<Option<Direct3DDevice>> = Mutex::new(None);
+
+// The receivers are found by the event ids in the render events.
+static RECEIVERS: Mutex<BTreeMap<i32, Arc<UnityReceiver>>> = Mutex::new(BTreeMap::new());
+static EVENT_ID: AtomicI32 = AtomicI32::new(0);
+
+/// Called by Unity when the plugin is loaded.
+#[no_mangle]
+#[allow(non_snake_case)]
+extern "system" fn UnityPluginLoad(interfaces: *mut IUnityInterfaces) {
+    log::info!("extern api: unity plugin load");
+
+    INTERFACES.store(interfaces, Ordering::Release);
+
+    let graphics = unsafe {
+        ((*interfaces).get_interface_split)(UNITY_GRAPHICS_GUID.0, UNITY_GRAPHICS_GUID.1)
+    } as *mut IUnityGraphics;
+
+    if graphics.is_null() {
+        log::error!("unity graphics interface is not found");
+
+        return;
+    }
+
+    GRAPHICS.store(graphics, Ordering::Release);
+
+    // The event ids of the receivers are reserved, so that they do not conflict
+    // with the other plugins.
+    EVENT_ID.update(unsafe { ((*graphics).reserve_event_id_range)(MAX_RECEIVERS) });
+
+    unsafe {
+        ((*graphics).register_device_event_callback)(on_device_event);
+    }
+
+    // The device may already be initialized when the plugin is loaded.
+    on_device_event(UNITY_GFX_DEVICE_EVENT_INITIALIZE);
+}
+
+/// Called by Unity when the plugin is unloaded.
+#[no_mangle]
+#[allow(non_snake_case)]
+extern "system" fn UnityPluginUnload() {
+    log::info!("extern api: unity plugin unload");
+
+    let graphics = GRAPHICS.swap(null_mut(), Ordering::AcqRel);
+    if !graphics.is_null() {
+        unsafe {
+            ((*graphics).unregister_device_event_callback)(on_device_event);
+        }
+    }
+
+    on_device_event(UNITY_GFX_DEVICE_EVENT_SHUTDOWN);
+    INTERFACES.store(null_mut(), Ordering::Release);
+}
+
+extern "system" fn on_device_event(event: i32) {
+    match event {
+        UNITY_GFX_DEVICE_EVENT_INITIALIZE => {
+            let graphics = GRAPHICS.load(Ordering::Acquire);
+            if graphics.is_null() {
+                return;
+            }
+
+            let renderer = unsafe { ((*graphics).get_renderer)() };
+            RENDERER.update(renderer);
+
+            log::info!("unity graphics device initialize, renderer={}", renderer);
+
+            #[cfg(target_os = "windows")]
+            if renderer == UNITY_GFX_RENDERER_D3D11 {
+                let interfaces = INTERFACES.load(Ordering::Acquire);
+                let d3d11 = unsafe {
+                    ((*interfaces).get_interface_split)(
+                        UNITY_GRAPHICS_D3D11_GUID.0,
+                        UNITY_GRAPHICS_D3D11_GUID.1,
+                    )
+                } as *mut IUnityGraphicsD3D11;
+
+                if !d3d11.is_null() {
+                    let device = unsafe { ((*d3d11).get_device)() };
+                    if let Some(device) = d3d_device_borrowed_raw(&device) {
+                        if let Ok(context) = unsafe { device.GetImmediateContext() } {
+                            DIRECT_3D_DEVICE.lock().replace(Direct3DDevice {
+                                device: device.clone(),
+                                context,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        UNITY_GFX_DEVICE_EVENT_SHUTDOWN => {
+            log::info!("unity graphics device shutdown");
+
+            // The renderers hold the resources of the device.
+            for receiver in RECEIVERS.lock().values() {
+                receiver.texture.lock().take();
+            }
+
+            #[cfg(target_os = "windows")]
+            DIRECT_3D_DEVICE.lock().take();
+
+            RENDERER.update(UNITY_GFX_RENDERER_NULL);
+        }
+        _ => (),
+    }
+}
+
+/// The function that is passed to `GL.IssuePluginEvent` or
+/// `CommandBuffer.IssuePluginEvent` with the event id of a receiver, it draws
+/// the newest frame of the receiver to the bound texture on the render thread.
+#[no_mangle]
+extern "C" fn hylarana_unity_get_render_event_func() -> extern "system" fn(event: i32) {
+    on_render_event
+}
+
+extern "system" fn on_render_event(event: i32) {
+    let Some(receiver) = RECEIVERS.lock().get(&event).cloned() else {
+        return;
+    };
+
+    // Only the newest frame is drawn, the older ones are skipped.
+    let mut frame = None;
+    while let Some(next) = receiver.receiver.try_next_video_frame() {
+        frame.replace(next);
+    }
+
+    let Some(frame) = frame else {
+        return;
+    };
+
+    receiver.size.lock().replace((frame.width, frame.height));
+    if let Some(texture) = receiver.texture.lock().as_mut() {
+        if let Err(e) = texture.draw(&frame) {
+            log::error!("unity render event failed to draw, err={:?}", e);
+        }
+    }
+}
+
+// The texture of Unity that the video of a receiver is drawn to.
+enum UnityTexture {
+    #[cfg(target_os = "windows")]
+    Direct3D11(VideoRender<'static>),
+    #[cfg(target_os = "macos")]
+    Metal(metal::MetalTexture),
+}
+
+unsafe impl Send for UnityTexture {}
+
+impl UnityTexture {
+    // The video is copied as is on Metal, the fit mode is only used on D3D11.
+    #[cfg_attr(not(target_os = "windows"), allow(unused_variables))]
+    fn new(texture: *mut c_void, fit: FitMode) -> anyhow::Result<Self> {
+        match RENDERER.get() {
+            #[cfg(target_os = "windows")]
+            UNITY_GFX_RENDERER_D3D11 => {
+                let direct3d = DIRECT_3D_DEVICE
+                    .lock()
+                    .clone()
+                    .ok_or_else(|| anyhow::anyhow!("unity d3d11 device is not initialized"))?;
+
+                let texture = d3d_texture_borrowed_raw(&texture)
+                    .ok_or_else(|| anyhow::anyhow!("invalid d3d11 texture"))?
+                    .clone();
+
+                Ok(Self::Direct3D11(VideoRender::with_d3d11_texture(
+                    texture, fit, direct3d,
+                )?))
+            }
+            #[cfg(target_os = "macos")]
+            UNITY_GFX_RENDERER_METAL => Ok(Self::Metal(metal::MetalTexture::new(texture)?)),
+            renderer => Err(anyhow::anyhow!(
+                "not supports the unity graphics renderer = {}",
+                renderer
+            )),
+        }
+    }
+
+    fn draw(&mut self, frame: &OwnedVideoFrame) -> anyhow::Result<()> {
+        match self {
+            #[cfg(target_os = "windows")]
+            Self::Direct3D11(render) => render.send(frame)?,
+            #[cfg(target_os = "macos")]
+            Self::Metal(texture) => texture.draw(frame)?,
+            #[allow(unreachable_patterns)]
+            _ => (),
+        }
+
+        Ok(())
+    }
+}
+
+struct UnityReceiver {
+    receiver: HylaranaReceiver<FrameQueue>,
+    texture: Mutex<Option<UnityTexture>>,
+    size: Mutex<Option<(u32, u32)>>,
+}
+
+struct RawUnityReceiver {
+    event: i32,
+    receiver: Arc<UnityReceiver>,
+}
+
+/// Create a receiver whose video is drawn to a texture of Unity, only the
+/// newest frame is kept, so the receiver should use a software decoder. The
+/// audio is not played.
+#[no_mangle]
+extern "C" fn hylarana_unity_create_receiver(
+    id: *const c_char,
+    options: RawReceiverOptions,
+) -> *const RawUnityReceiver {
+    assert!(!id.is_null());
+
+    log::info!("extern api: hylarana unity create receiver");
+
+    log_error((|| {
+        Ok::<_, anyhow::Error>(Hylarana::create_receiver(
+            PSTR::from(id).to_string()?,
+            HylaranaReceiverOptions {
+                transport: options.transport.try_into()?,
+                codec: HylaranaReceiverCodecOptions {
+                    video: options.codec.video.into(),
+                    graphics: Default::default(),
+                    thumbnail_only: false,
+                    passthrough: false,
+                },
+                tracks: Default::default(),
+                pairing_code: None,
+            },
+            FrameQueue::new(1),
+        )?)
+    })())
+    .map(|receiver| {
+        let event = EVENT_ID.fetch_add(1, Ordering::Relaxed);
+        let receiver = Arc::new(UnityReceiver {
+            texture: Mutex::new(None),
+            size: Mutex::new(None),
+            receiver,
+        });
+
+        RECEIVERS.lock().insert(event, receiver.clone());
+        Box::into_raw(Box::new(RawUnityReceiver { event, receiver }))
+    })
+    .unwrap_or_else(|_| null_mut())
+}
+
+/// The event id of the receiver that is passed to `GL.IssuePluginEvent`
+/// together with the render event function.
+#[no_mangle]
+extern "C" fn hylarana_unity_receiver_get_event_id(receiver: *const RawUnityReceiver) -> i32 {
+    assert!(!receiver.is_null());
+
+    unsafe { &*receiver }.event
+}
+
+/// Bind the native texture of a Unity texture to the receiver, which is the
+/// value of `Texture.GetNativeTexturePtr`. On D3D11 it is a `RenderTexture`
+/// and the video is scaled into it by the fit mode, on Metal it is a
+/// `Texture2D` of the BGRA32 format, the video is copied into it as is, so
+/// it should have the size of the video. Passing a null texture unbinds the
+/// texture.
+#[no_mangle]
+extern "C" fn hylarana_unity_receiver_bind_texture(
+    receiver: *const RawUnityReceiver,
+    texture: *mut c_void,
+    fit: RawFitMode,
+) -> bool {
+    assert!(!receiver.is_null());
+
+    log::info!("extern api: hylarana unity receiver bind texture");
+
+    let receiver = unsafe { &*receiver };
+    if texture.is_null() {
+        receiver.receiver.texture.lock().take();
+
+        return true;
+    }
+
+    log_error(UnityTexture::new(texture, fit.into()))
+        .map(|texture| {
+            receiver.receiver.texture.lock().replace(texture);
+        })
+        .is_ok()
+}
+
+/// Get the size of the video, which is known after the first frame is
+/// received, returns false if there is no frame yet.
+#[no_mangle]
+extern "C" fn hylarana_unity_receiver_get_video_size(
+    receiver: *const RawUnityReceiver,
+    width: *mut u32,
+    height: *mut u32,
+) -> bool {
+    assert!(!receiver.is_null() && !width.is_null() && !height.is_null());
+
+    if let Some((w, h)) = *unsafe { &*receiver }.receiver.size.lock() {
+        unsafe {
+            *width = w;
+            *height = h;
+        }
+
+        true
+    } else {
+        false
+    }
+}
+
+/// Whether the stream of the receiver has been closed.
+#[no_mangle]
+extern "C" fn hylarana_unity_receiver_is_closed(receiver: *const RawUnityReceiver) -> bool {
+    assert!(!receiver.is_null());
+
+    unsafe { &*receiver }.receiver.receiver.is_closed()
+}
+
+/// Destroy the receiver, the render events that are already issued with the
+/// event id of the receiver do nothing.
+#[no_mangle]
+extern "C" fn hylarana_unity_receiver_destroy(receiver: *mut RawUnityReceiver) {
+    assert!(!receiver.is_null());
+
+    log::info!("extern api: hylarana unity close receiver");
+
+    let receiver = unsafe { Box::from_raw(receiver) };
+    RECEIVERS.lock().remove(&receiver.event);
+}
+
+#[cfg(target_os = "macos")]
+mod metal {
+    use std::ffi::{c_char, c_void};
+
+    use hylarana::OwnedVideoFrame;
+
+    use crate::ffi::pixels::{convert, PixelOrder};
+
+    #[link(name = "objc")]
+    extern "C" {
+        fn sel_registerName(name: *const c_char) -> *const c_void;
+        fn objc_msgSend();
+    }
+
+    #[repr(C)]
+    struct MTLRegion {
+        origin: [usize; 3],
+        size: [usize; 3],
+    }
+
+    /// A `MTLTexture` of the BGRA8Unorm format, the frames are converted to
+    /// BGRA and written with `replaceRegion`, which is done on the render
+    /// thread of Unity, so the texture is not in use by the GPU.
+    pub struct MetalTexture {
+        texture: *mut c_void,
+        width: usize,
+        height: usize,
+        buffer: Vec<u8>,
+    }
+
+    impl MetalTexture {
+        pub fn new(texture: *mut c_void) -> anyhow::Result<Self> {
+            let get: extern "C" fn(*mut c_void, *const c_void) -> usize =
+                unsafe { std::mem::transmute(objc_msgSend as *const c_void) };
+
+            let (width, height) = unsafe {
+                (
+                    get(texture, sel_registerName(c"width".as_ptr())),
+                    get(texture, sel_registerName(c"height".as_ptr())),
+                )
+            };
+
+            Ok(Self {
+                buffer: Vec::with_capacity(width * height * 4),
+                texture,
+                width,
+                height,
+            })
+        }
+
+        /// The area that exceeds the texture or the frame is not drawn.
+        pub fn draw(&mut self, frame: &OwnedVideoFrame) -> anyhow::Result<()> {
+            let width = self.width.min(frame.width as usize);
+            let height = self.height.min(frame.height as usize);
+
+            convert(frame, PixelOrder::Bgra, width, height, &mut self.buffer)?;
+
+            let replace: extern "C" fn(
+                *mut c_void,
+                *const c_void,
+                MTLRegion,
+                usize,
+                *const u8,
+                usize,
+            ) = unsafe { std::mem::transmute(objc_msgSend as *const c_void) };
+
+            unsafe {
+                replace(
+                    self.texture,
+                    sel_registerName(c"replaceRegion:mipmapLevel:withBytes:bytesPerRow:".as_ptr()),
+                    MTLRegion {
+                        origin: [0, 0, 0],
+                        size: [width, height, 1],
+                    },
+                    0,
+                    self.buffer.as_ptr(),
+                    width * 4,
+                );
+            }
+
+            Ok(())
+        }
+    }
+}
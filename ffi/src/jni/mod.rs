@@ -14,8 +14,12 @@ use std::{
 
 use anyhow::Result;
 use discovery::DiscoveryServiceObserver;
-use hylarana_common::logger;
+use hylarana_common::{
+    crash::{self, CrashHandlerOptions},
+    logger,
+};
 use hylarana_discovery::DiscoveryService;
+use hylarana_transport::BufferFlag;
 use jni::{
     objects::{JByteArray, JClass, JObject, JString},
     sys::{jint, JNI_VERSION_1_6},
@@ -102,6 +106,7 @@ pub(crate) fn get_current_env<'local>() -> JNIEnv<'local> {
 #[allow(non_snake_case)]
 extern "system" fn JNI_OnLoad(vm: JavaVM, _: *mut c_void) -> i32 {
     logger::init_with_android("com.github.mycrl.hylarana", log::LevelFilter::Info);
+    crash::install(CrashHandlerOptions::default());
     hylarana_transport::startup();
     JVM.lock().unwrap().replace(vm);
 
@@ -254,13 +259,15 @@ extern "system" fn Java_com_github_mycrl_hylarana_Hylarana_releaseTransportSende
 }
 
 /// Creates the receiver, the return value indicates whether the creation was
-/// successful or not.
+/// successful or not. The code is the pairing code of the sender, null if the
+/// sender does not require one.
 ///
 /// ```kt
 /// private external fun createTransportReceiver(
 ///     id: String,
 ///     options: TransportOptions,
 ///     observer: HylaranaReceiverAdapterObserver,
+///     code: String?,
 /// ): Long
 /// ```
 #[no_mangle]
@@ -271,9 +278,10 @@ extern "system" fn Java_com_github_mycrl_hylarana_Hylarana_createTransportReceiv
     id: JString,
     options: JObject,
     observer: JObject,
+    code: JString,
 ) -> *const Arc<Receiver> {
     ok_or_check(&mut env, |env| {
-        let receiver = Arc::new(Receiver::new(env, &id, &options, &observer)?);
+        let receiver = Arc::new(Receiver::new(env, &id, &options, &observer, &code)?);
 
         let adapter = receiver.get_adapter();
         let receiver_ = Arc::downgrade(&receiver);
@@ -282,6 +290,12 @@ extern "system" fn Java_com_github_mycrl_hylarana_Hylarana_createTransportReceiv
             .spawn(move || {
                 while let Some(receiver) = receiver_.upgrade() {
                     if let Some((buf, kind, flags, timestamp)) = adapter.next() {
+                        // The control messages of the stream are not media, and the
+                        // decoders of the java side cannot handle them.
+                        if flags & BufferFlag::CONTROL != 0 {
+                            continue;
+                        }
+
                         if receiver.sink(buf, kind, flags, timestamp).is_err() {
                             break;
                         }
@@ -108,6 +108,8 @@ impl TransformObject for TransportStrategy {
 //     val strategy: TransportStrategy,
 //     /**
 //      * see: [Maximum_transmission_unit](https://en.wikipedia.org/wiki/Maximum_transmission_unit)
+//      *
+//      * Zero discovers the MTU of the path to the peer.
 //      */
 //     val mtu: Int
 // )
@@ -123,6 +125,12 @@ impl TransformObject for TransportOptions {
         Ok(Self {
             strategy: TransportStrategy::from_object(env, &strategy)?,
             mtu: object.get_int(env, "mtu")? as usize,
+            multicast: Default::default(),
+            dscp: None,
+            resume: None,
+            pacing: None,
+            keepalive: None,
+            latency: None,
         })
     }
 }
@@ -3,7 +3,8 @@ use std::sync::Arc;
 use anyhow::{anyhow, Result};
 use bytes::Bytes;
 use hylarana_transport::{
-    create_mix_receiver, StreamKind, StreamReceiverAdapter, TransportOptions, TransportReceiver,
+    create_mix_receiver, StreamKind, StreamReceiverAdapter, StreamTracks, TransportOptions,
+    TransportReceiver,
 };
 
 use jni::{
@@ -43,11 +44,24 @@ impl Receiver {
         id: &JString,
         options: &JObject,
         observer: &JObject,
+        code: &JString,
     ) -> Result<Self> {
         let id: String = env.get_string(id)?.into();
 
+        // The code is optional, the java side passes null without a code.
+        let code: Option<String> = if code.is_null() {
+            None
+        } else {
+            Some(env.get_string(code)?.into())
+        };
+
         Ok(Self {
-            receiver: create_mix_receiver(id, TransportOptions::from_object(env, &options)?)?,
+            receiver: create_mix_receiver(
+                id,
+                TransportOptions::from_object(env, &options)?,
+                StreamTracks::All,
+                code,
+            )?,
             observer: env.new_global_ref(observer)?,
         })
     }
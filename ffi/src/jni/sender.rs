@@ -3,8 +3,8 @@ use std::sync::Arc;
 use anyhow::Result;
 use bytes::BytesMut;
 use hylarana_transport::{
-    create_sender, with_capacity as package_with_capacity, StreamBufferInfo, StreamSenderAdapter,
-    TransportOptions, TransportSender,
+    create_sender, PacketPool, StreamBufferInfo, StreamSenderAdapter, TransportOptions,
+    TransportSender,
 };
 
 use jni::{
@@ -17,6 +17,7 @@ use super::object::TransformObject;
 pub struct Sender {
     sender: TransportSender,
     adapter: Arc<StreamSenderAdapter>,
+    packets: PacketPool,
 }
 
 impl Sender {
@@ -24,6 +25,7 @@ impl Sender {
         let sender = create_sender(TransportOptions::from_object(env, &options)?)?;
         Ok(Self {
             adapter: sender.get_adapter(),
+            packets: PacketPool::default(),
             sender,
         })
     }
@@ -33,15 +35,19 @@ impl Sender {
     }
 
     pub fn sink(&self, env: &mut JNIEnv, info: JObject, buf: JByteArray) -> Result<bool> {
-        let buf = copy_from_byte_array(env, &buf)?;
+        let buf = copy_from_byte_array(env, &self.packets, &buf)?;
         let info = StreamBufferInfo::from_object(env, &info)?;
         Ok(self.adapter.send(buf, info))
     }
 }
 
-fn copy_from_byte_array(env: &JNIEnv, array: &JByteArray) -> Result<BytesMut> {
+fn copy_from_byte_array(
+    env: &JNIEnv,
+    packets: &PacketPool,
+    array: &JByteArray,
+) -> Result<BytesMut> {
     let size = env.get_array_length(array)? as usize;
-    let mut bytes = package_with_capacity(size);
+    let mut bytes = packets.with_capacity(size);
     let start = bytes.len() - size;
 
     env.get_byte_array_region(array, 0, unsafe {
@@ -15,6 +15,15 @@ pub enum InteropError {
     CreateMetalTextureCacheError,
     #[error("failed to create metal texture")]
     CreateMetalTextureError,
+    #[cfg(target_os = "linux")]
+    #[error(transparent)]
+    VulkanError(#[from] ash::vk::Result),
+    #[error("not found wgpu vulkan device")]
+    NotFoundVulkanBackend,
+    #[error("failed to import dma-buf")]
+    ImportDmaBufError,
+    #[error("not supports texture format")]
+    NotSupportTextureFormat,
 }
 
 #[cfg(target_os = "windows")]
@@ -57,7 +66,12 @@ pub mod win32 {
             }
         }
 
-        pub fn from_hal(
+        /// The D3D11 device that the textures are copied with.
+        pub fn direct3d(&self) -> &Direct3DDevice {
+            &self.direct3d
+        }
+
+        pub fn import(
             &mut self,
             texture: &ID3D11Texture2D,
             index: u32,
@@ -160,3 +174,569 @@ pub mod win32 {
         }
     }
 }
+
+#[cfg(target_os = "macos")]
+pub mod macos {
+    use std::{
+        ffi::c_void,
+        ptr::{null, null_mut},
+        sync::Arc,
+    };
+
+    use super::InteropError;
+
+    use hylarana_common::{macos::CVPixelBufferRef, Size};
+    use metal::{
+        foreign_types::{ForeignType, ForeignTypeRef},
+        MTLPixelFormat, MTLTextureType, TextureRef,
+    };
+    use wgpu::{
+        hal::{api::Metal, CopyExtent},
+        Device, Extent3d, Texture, TextureDescriptor, TextureDimension, TextureFormat,
+        TextureUsages,
+    };
+
+    type CVMetalTextureCacheRef = *mut c_void;
+    type CVMetalTextureRef = *mut c_void;
+
+    // kCVPixelFormatType_420YpCbCr8BiPlanarVideoRange and
+    // kCVPixelFormatType_420YpCbCr8BiPlanarFullRange.
+    const PIXEL_FORMAT_NV12: [u32; 2] = [0x34323076, 0x34323066];
+
+    // kCVPixelFormatType_420YpCbCr10BiPlanarVideoRange and
+    // kCVPixelFormatType_420YpCbCr10BiPlanarFullRange, the layout of P010.
+    const PIXEL_FORMAT_P010: [u32; 2] = [0x78343230, 0x78663230];
+
+    #[link(name = "CoreVideo", kind = "framework")]
+    extern "C" {
+        fn CVPixelBufferGetWidth(buffer: CVPixelBufferRef) -> usize;
+        fn CVPixelBufferGetHeight(buffer: CVPixelBufferRef) -> usize;
+        fn CVPixelBufferGetWidthOfPlane(buffer: CVPixelBufferRef, plane: usize) -> usize;
+        fn CVPixelBufferGetHeightOfPlane(buffer: CVPixelBufferRef, plane: usize) -> usize;
+        fn CVPixelBufferGetPixelFormatType(buffer: CVPixelBufferRef) -> u32;
+        fn CVMetalTextureCacheCreate(
+            allocator: *const c_void,
+            cache_attributes: *const c_void,
+            device: *mut c_void,
+            texture_attributes: *const c_void,
+            cache: *mut CVMetalTextureCacheRef,
+        ) -> i32;
+        fn CVMetalTextureCacheCreateTextureFromImage(
+            allocator: *const c_void,
+            cache: CVMetalTextureCacheRef,
+            image: CVPixelBufferRef,
+            texture_attributes: *const c_void,
+            format: MTLPixelFormat,
+            width: usize,
+            height: usize,
+            plane: usize,
+            texture: *mut CVMetalTextureRef,
+        ) -> i32;
+        fn CVMetalTextureCacheFlush(cache: CVMetalTextureCacheRef, options: u64);
+        fn CVMetalTextureGetTexture(texture: CVMetalTextureRef) -> *mut c_void;
+    }
+
+    #[link(name = "CoreFoundation", kind = "framework")]
+    extern "C" {
+        fn CFRelease(value: *const c_void);
+    }
+
+    /// The size of the pixel buffer, which does not need the buffer to be
+    /// locked.
+    pub fn pixel_buffer_size(buffer: CVPixelBufferRef) -> Size {
+        unsafe {
+            Size {
+                width: CVPixelBufferGetWidth(buffer) as u32,
+                height: CVPixelBufferGetHeight(buffer) as u32,
+            }
+        }
+    }
+
+    /// Imports the planes of the NV12 and P010 pixel buffers of VideoToolbox
+    /// as the textures of the Metal device of wgpu, the pixel buffers are
+    /// backed by IOSurface, so the planes are used by the GPU where they are.
+    pub struct Interop {
+        device: Arc<Device>,
+        cache: CVMetalTextureCacheRef,
+        // The Metal textures hold the pixel buffer until the GPU has read it, the
+        // textures of a frame are released when the next frame is imported.
+        textures: Vec<CVMetalTextureRef>,
+        planes: Vec<Texture>,
+    }
+
+    unsafe impl Sync for Interop {}
+    unsafe impl Send for Interop {}
+
+    impl Interop {
+        pub fn new(device: Arc<Device>) -> Self {
+            Self {
+                cache: null_mut(),
+                textures: Vec::with_capacity(2),
+                planes: Vec::with_capacity(2),
+                device,
+            }
+        }
+
+        /// The luma and the chroma planes of the pixel buffer, as `R8Unorm`
+        /// and `Rg8Unorm` textures, or as `R16Unorm` and `Rg16Unorm` textures
+        /// for the 10 bits pixel buffers.
+        pub fn import(&mut self, buffer: CVPixelBufferRef) -> Result<&[Texture], InteropError> {
+            let pixel_format = unsafe { CVPixelBufferGetPixelFormatType(buffer) };
+            let formats = if PIXEL_FORMAT_NV12.contains(&pixel_format) {
+                [
+                    (0, MTLPixelFormat::R8Unorm, TextureFormat::R8Unorm),
+                    (1, MTLPixelFormat::RG8Unorm, TextureFormat::Rg8Unorm),
+                ]
+            } else if PIXEL_FORMAT_P010.contains(&pixel_format) {
+                [
+                    (0, MTLPixelFormat::R16Unorm, TextureFormat::R16Unorm),
+                    (1, MTLPixelFormat::RG16Unorm, TextureFormat::Rg16Unorm),
+                ]
+            } else {
+                return Err(InteropError::NotSupportTextureFormat);
+            };
+
+            if self.cache.is_null() {
+                let device = unsafe {
+                    self.device
+                        .as_hal::<Metal, _, _>(|hdevice| {
+                            hdevice.map(|it| it.raw_device().lock().as_ptr() as *mut c_void)
+                        })
+                        .flatten()
+                        .ok_or(InteropError::NotFoundMetalBackend)?
+                };
+
+                if unsafe {
+                    CVMetalTextureCacheCreate(null(), null(), device, null(), &mut self.cache)
+                } != 0
+                {
+                    return Err(InteropError::CreateMetalTextureCacheError);
+                }
+            }
+
+            self.release();
+
+            for (plane, format, wgpu_format) in formats {
+                let (width, height) = unsafe {
+                    (
+                        CVPixelBufferGetWidthOfPlane(buffer, plane),
+                        CVPixelBufferGetHeightOfPlane(buffer, plane),
+                    )
+                };
+
+                let mut texture = null_mut();
+                if unsafe {
+                    CVMetalTextureCacheCreateTextureFromImage(
+                        null(),
+                        self.cache,
+                        buffer,
+                        null(),
+                        format,
+                        width,
+                        height,
+                        plane,
+                        &mut texture,
+                    )
+                } != 0
+                    || texture.is_null()
+                {
+                    return Err(InteropError::CreateMetalTextureError);
+                }
+
+                self.textures.push(texture);
+
+                // The texture is owned by the Metal texture of the cache, it is retained
+                // for wgpu.
+                let raw = unsafe { CVMetalTextureGetTexture(texture) };
+                if raw.is_null() {
+                    return Err(InteropError::CreateMetalTextureError);
+                }
+
+                let raw = unsafe { TextureRef::from_ptr(raw as *mut _) }.to_owned();
+                let desc = TextureDescriptor {
+                    label: None,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    usage: TextureUsages::TEXTURE_BINDING,
+                    format: wgpu_format,
+                    view_formats: &[],
+                    size: Extent3d {
+                        depth_or_array_layers: 1,
+                        width: width as u32,
+                        height: height as u32,
+                    },
+                };
+
+                self.planes.push(unsafe {
+                    self.device.create_texture_from_hal::<Metal>(
+                        <Metal as wgpu::hal::Api>::Device::texture_from_raw(
+                            raw,
+                            wgpu_format,
+                            MTLTextureType::D2,
+                            1,
+                            1,
+                            CopyExtent {
+                                width: width as u32,
+                                height: height as u32,
+                                depth: 1,
+                            },
+                        ),
+                        &desc,
+                    )
+                });
+            }
+
+            Ok(&self.planes)
+        }
+
+        fn release(&mut self) {
+            self.planes.clear();
+
+            for texture in self.textures.drain(..) {
+                unsafe {
+                    CFRelease(texture);
+                }
+            }
+        }
+    }
+
+    impl Drop for Interop {
+        fn drop(&mut self) {
+            self.release();
+
+            if !self.cache.is_null() {
+                unsafe {
+                    CVMetalTextureCacheFlush(self.cache, 0);
+                    CFRelease(self.cache);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+pub mod linux {
+    use std::{
+        ffi::CStr,
+        os::fd::{AsRawFd, BorrowedFd, IntoRawFd},
+        sync::Arc,
+    };
+
+    use super::InteropError;
+
+    use ash::{ext, khr, vk};
+    use hylarana_common::{
+        frame::{DmaBuf, VideoFormat},
+        Size,
+    };
+
+    use wgpu::{
+        hal::{api::Vulkan, vulkan, MemoryFlags, TextureUses},
+        Adapter, Device, DeviceDescriptor, Extent3d, Queue, Texture, TextureDescriptor,
+        TextureDimension, TextureFormat, TextureUsages,
+    };
+
+    // The extensions that import the DMA-BUF file descriptors as images.
+    const EXTENSIONS: [&CStr; 4] = [
+        khr::external_memory_fd::NAME,
+        khr::image_format_list::NAME,
+        ext::external_memory_dma_buf::NAME,
+        ext::image_drm_format_modifier::NAME,
+    ];
+
+    /// Request the device with the extensions that import the DMA-BUF planes,
+    /// which wgpu does not enable itself. Returns `None` if the adapter does
+    /// not support them.
+    pub fn request_device(adapter: &Adapter, desc: &DeviceDescriptor) -> Option<(Device, Queue)> {
+        let device = unsafe {
+            adapter.as_hal::<Vulkan, _, _>(|adapter| {
+                let adapter = adapter?;
+                let instance = adapter.shared_instance().raw_instance();
+                let physical_device = adapter.raw_physical_device();
+
+                let supported = instance
+                    .enumerate_device_extension_properties(physical_device)
+                    .ok()?;
+
+                if !EXTENSIONS.iter().all(|name| {
+                    supported
+                        .iter()
+                        .any(|it| it.extension_name_as_c_str() == Ok(*name))
+                }) {
+                    return None;
+                }
+
+                let mut extensions = adapter.required_device_extensions(desc.required_features);
+                for name in EXTENSIONS {
+                    if !extensions.contains(&name) {
+                        extensions.push(name);
+                    }
+                }
+
+                let mut features =
+                    adapter.physical_device_features(&extensions, desc.required_features);
+
+                // The same queue as wgpu uses when it opens the device itself.
+                let families = [vk::DeviceQueueCreateInfo::default()
+                    .queue_family_index(0)
+                    .queue_priorities(&[1.0])];
+
+                let names = extensions.iter().map(|it| it.as_ptr()).collect::<Vec<_>>();
+                let info = features.add_to_device_create(
+                    vk::DeviceCreateInfo::default()
+                        .queue_create_infos(&families)
+                        .enabled_extension_names(&names),
+                );
+
+                let device = instance.create_device(physical_device, &info, None).ok()?;
+
+                adapter
+                    .device_from_raw(
+                        device,
+                        None,
+                        &extensions,
+                        desc.required_features,
+                        &desc.memory_hints,
+                        0,
+                        0,
+                    )
+                    .ok()
+            })?
+        };
+
+        unsafe { adapter.create_device_from_hal(device, desc, None) }.ok()
+    }
+
+    /// Imports the DMA-BUF planes of the frames as the textures of the Vulkan
+    /// device of wgpu, each plane is imported as a texture of its own, so the
+    /// planes may be in different buffers.
+    ///
+    /// The device has to be requested with `request_device`.
+    pub struct Interop {
+        device: Arc<Device>,
+        planes: Vec<Texture>,
+    }
+
+    impl Interop {
+        pub fn new(device: Arc<Device>) -> Self {
+            Self {
+                planes: Vec::with_capacity(2),
+                device,
+            }
+        }
+
+        /// The textures of the planes, NV12 is imported as a `R8Unorm` and a
+        /// `Rg8Unorm` texture.
+        pub fn import(
+            &mut self,
+            format: VideoFormat,
+            size: Size,
+            linesize: &[usize; 3],
+            dma_buf: &DmaBuf,
+        ) -> Result<&[Texture], InteropError> {
+            let half = Size {
+                width: size.width / 2,
+                height: size.height / 2,
+            };
+
+            let planes: &[(vk::Format, TextureFormat, Size)] = match format {
+                VideoFormat::NV12 => &[
+                    (vk::Format::R8_UNORM, TextureFormat::R8Unorm, size),
+                    (vk::Format::R8G8_UNORM, TextureFormat::Rg8Unorm, half),
+                ],
+                VideoFormat::BGRA => {
+                    &[(vk::Format::B8G8R8A8_UNORM, TextureFormat::Bgra8Unorm, size)]
+                }
+                VideoFormat::RGBA => {
+                    &[(vk::Format::R8G8B8A8_UNORM, TextureFormat::Rgba8Unorm, size)]
+                }
+                _ => return Err(InteropError::NotSupportTextureFormat),
+            };
+
+            // The textures of the previous frame are destroyed by wgpu after the GPU has
+            // finished with them.
+            self.planes.clear();
+
+            for (i, (vk_format, format, size)) in planes.iter().enumerate() {
+                if dma_buf.fd[i] < 0 {
+                    return Err(InteropError::ImportDmaBufError);
+                }
+
+                let extent = Extent3d {
+                    depth_or_array_layers: 1,
+                    width: size.width,
+                    height: size.height,
+                };
+
+                let texture = unsafe {
+                    self.device
+                        .as_hal::<Vulkan, _, _>(|hdevice| {
+                            import_plane(
+                                hdevice.ok_or(InteropError::NotFoundVulkanBackend)?,
+                                Plane {
+                                    fd: dma_buf.fd[i],
+                                    offset: dma_buf.offset[i],
+                                    stride: linesize[i],
+                                    modifier: dma_buf.modifier,
+                                    format: *vk_format,
+                                },
+                                &wgpu::hal::TextureDescriptor {
+                                    label: None,
+                                    size: extent,
+                                    mip_level_count: 1,
+                                    sample_count: 1,
+                                    dimension: TextureDimension::D2,
+                                    format: *format,
+                                    usage: TextureUses::RESOURCE,
+                                    memory_flags: MemoryFlags::empty(),
+                                    view_formats: Vec::new(),
+                                },
+                            )
+                        })
+                        .ok_or(InteropError::NotFoundVulkanBackend)??
+                };
+
+                self.planes.push(unsafe {
+                    self.device.create_texture_from_hal::<Vulkan>(
+                        texture,
+                        &TextureDescriptor {
+                            label: None,
+                            mip_level_count: 1,
+                            sample_count: 1,
+                            dimension: TextureDimension::D2,
+                            usage: TextureUsages::TEXTURE_BINDING,
+                            format: *format,
+                            view_formats: &[],
+                            size: extent,
+                        },
+                    )
+                });
+            }
+
+            Ok(&self.planes)
+        }
+    }
+
+    struct Plane {
+        fd: i32,
+        offset: u32,
+        stride: usize,
+        modifier: u64,
+        format: vk::Format,
+    }
+
+    // Creates the image with the layout of the plane and binds it to the memory that
+    // is imported from the file descriptor, the image and the memory are released
+    // when wgpu destroys the texture.
+    unsafe fn import_plane(
+        device: &vulkan::Device,
+        plane: Plane,
+        desc: &wgpu::hal::TextureDescriptor,
+    ) -> Result<vulkan::Texture, InteropError> {
+        let raw = device.raw_device();
+
+        let layouts = [vk::SubresourceLayout {
+            offset: plane.offset as u64,
+            row_pitch: plane.stride as u64,
+            ..Default::default()
+        }];
+
+        let mut modifier = vk::ImageDrmFormatModifierExplicitCreateInfoEXT::default()
+            .drm_format_modifier(plane.modifier)
+            .plane_layouts(&layouts);
+
+        let mut external = vk::ExternalMemoryImageCreateInfo::default()
+            .handle_types(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT);
+
+        let image = raw.create_image(
+            &vk::ImageCreateInfo::default()
+                .image_type(vk::ImageType::TYPE_2D)
+                .format(plane.format)
+                .extent(vk::Extent3D {
+                    width: desc.size.width,
+                    height: desc.size.height,
+                    depth: 1,
+                })
+                .mip_levels(1)
+                .array_layers(1)
+                .samples(vk::SampleCountFlags::TYPE_1)
+                .tiling(vk::ImageTiling::DRM_FORMAT_MODIFIER_EXT)
+                .usage(vk::ImageUsageFlags::SAMPLED)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE)
+                .initial_layout(vk::ImageLayout::UNDEFINED)
+                .push_next(&mut external)
+                .push_next(&mut modifier),
+            None,
+        )?;
+
+        let memory = match import_memory(device, image, plane.fd) {
+            Ok(it) => it,
+            Err(e) => {
+                raw.destroy_image(image, None);
+                return Err(e);
+            }
+        };
+
+        let raw = raw.clone();
+        Ok(vulkan::Device::texture_from_raw(
+            image,
+            desc,
+            Some(Box::new(move || {
+                raw.destroy_image(image, None);
+                raw.free_memory(memory, None);
+            })),
+        ))
+    }
+
+    // Vulkan takes the file descriptor when the memory is imported, so a duplicate of
+    // it is imported, which is closed here if the import fails.
+    unsafe fn import_memory(
+        device: &vulkan::Device,
+        image: vk::Image,
+        fd: i32,
+    ) -> Result<vk::DeviceMemory, InteropError> {
+        let raw = device.raw_device();
+        let fd = BorrowedFd::borrow_raw(fd)
+            .try_clone_to_owned()
+            .map_err(|_| InteropError::ImportDmaBufError)?;
+
+        let mut properties = vk::MemoryFdPropertiesKHR::default();
+        khr::external_memory_fd::Device::new(device.shared_instance().raw_instance(), raw)
+            .get_memory_fd_properties(
+                vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT,
+                fd.as_raw_fd(),
+                &mut properties,
+            )?;
+
+        let requirements = raw.get_image_memory_requirements(image);
+        let types = requirements.memory_type_bits & properties.memory_type_bits;
+        if types == 0 {
+            return Err(InteropError::ImportDmaBufError);
+        }
+
+        let mut import = vk::ImportMemoryFdInfoKHR::default()
+            .handle_type(vk::ExternalMemoryHandleTypeFlags::DMA_BUF_EXT)
+            .fd(fd.as_raw_fd());
+
+        let mut dedicated = vk::MemoryDedicatedAllocateInfo::default().image(image);
+        let memory = raw.allocate_memory(
+            &vk::MemoryAllocateInfo::default()
+                .allocation_size(requirements.size)
+                .memory_type_index(types.trailing_zeros())
+                .push_next(&mut import)
+                .push_next(&mut dedicated),
+            None,
+        )?;
+
+        // The file descriptor is owned by the memory now.
+        let _ = fd.into_raw_fd();
+
+        if let Err(e) = raw.bind_image_memory(image, memory, 0) {
+            raw.free_memory(memory, None);
+            return Err(e.into());
+        }
+
+        Ok(memory)
+    }
+}
@@ -1,29 +1,49 @@
 mod interop;
+mod mosaic;
+mod overlay;
+mod post;
 mod texture;
 mod vertex;
 
-use std::sync::Arc;
+use std::sync::{mpsc::channel, Arc};
 
-use self::vertex::Vertex;
+use self::{overlay::Overlay, post::PostProcess, vertex::Vertex};
+
+pub use self::{
+    mosaic::{MosaicGrid, MosaicRenderer, MosaicRendererOptions},
+    overlay::OverlayRect,
+    post::PostProcessShader,
+};
 
 pub use self::texture::{
     FromNativeResourceError, Texture, Texture2DBuffer, Texture2DRaw, Texture2DResource,
 };
 
-use hylarana_common::Size;
+#[cfg(target_os = "linux")]
+pub use self::texture::DmaBufTexture;
+
+use hylarana_common::{frame::VideoRotation, AdapterPreference, Size};
 use pollster::FutureExt;
 use texture::{Texture2DSource, Texture2DSourceOptions};
 use thiserror::Error;
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    Backends, Buffer, BufferUsages, Color, CommandEncoderDescriptor, CompositeAlphaMode, Device,
-    DeviceDescriptor, IndexFormat, Instance, InstanceDescriptor, LoadOp, MemoryHints, Operations,
-    PowerPreference, PresentMode, Queue, RenderPassColorAttachment, RenderPassDescriptor,
-    RequestAdapterOptions, StoreOp, Surface, TextureFormat, TextureUsages, TextureViewDescriptor,
+    Adapter, Backends, BindGroup, Buffer, BufferAsyncError, BufferDescriptor, BufferUsages, Color,
+    CommandEncoder, CommandEncoderDescriptor, CompositeAlphaMode, Device, DeviceDescriptor,
+    DeviceType, Extent3d, ImageCopyBuffer, ImageCopyTexture, ImageDataLayout, IndexFormat,
+    Instance, InstanceDescriptor, LoadOp, Maintain, MapMode, MemoryHints, Operations, Origin3d,
+    PowerPreference, PresentMode, Queue, RenderPass, RenderPassColorAttachment,
+    RenderPassDescriptor, RequestAdapterOptions, StoreOp, Surface, SurfaceConfiguration,
+    SurfaceError, TextureAspect, TextureDescriptor, TextureDimension, TextureFormat, TextureUsages,
+    TextureView, TextureViewDescriptor, COPY_BYTES_PER_ROW_ALIGNMENT,
 };
 
 pub use wgpu::{rwh as raw_window_handle, SurfaceTarget};
 
+// The application has to use the same version of wgpu as the renderer.
+#[cfg(feature = "external")]
+pub use wgpu;
+
 #[derive(Debug, Error)]
 pub enum GraphicsError {
     #[error("not found graphics adaper")]
@@ -38,6 +58,113 @@ pub enum GraphicsError {
     CreateSurfaceError(#[from] wgpu::CreateSurfaceError),
     #[error(transparent)]
     FromNativeResourceError(#[from] FromNativeResourceError),
+    #[error(transparent)]
+    BufferAsyncError(#[from] wgpu::BufferAsyncError),
+    #[error("no frame has been rendered")]
+    NotFoundFrame,
+    #[error("post process shader error: {0}")]
+    PostProcessError(String),
+}
+
+/// An RGBA image read back from the renderer, the rows are tightly packed.
+#[derive(Debug, Clone)]
+pub struct RgbaImage {
+    pub size: Size,
+    pub data: Vec<u8>,
+}
+
+/// How the texture is fitted into the surface when their aspect ratios are
+/// different.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FitMode {
+    /// Stretch the texture to fill the surface, the aspect ratio is not kept.
+    Stretch,
+    /// Scale the texture to fit inside the surface, the rest of the surface is
+    /// left black (letterboxing or pillarboxing).
+    #[default]
+    Contain,
+    /// Scale the texture to fill the surface, the parts of the texture outside
+    /// the surface are cropped.
+    Cover,
+}
+
+/// The filter used when scaling the texture to the surface.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ScaleFilter {
+    /// The sharpest, but produces jagged edges when scaling.
+    Nearest,
+    #[default]
+    Bilinear,
+    /// The smoothest, this is done in the shader and costs more samples.
+    Bicubic,
+}
+
+/// The area of the surface that the texture is drawn to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Viewport {
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+}
+
+impl Viewport {
+    /// Calculate the area of the surface that the texture is drawn to, and the
+    /// visible fraction of the texture in both directions, the visible area is
+    /// centered.
+    ///
+    /// Both are in the display space, that is, after the rotation is applied.
+    fn fit(
+        surface: Size,
+        texture: Size,
+        rotation: VideoRotation,
+        mode: FitMode,
+    ) -> (Self, [f32; 2]) {
+        // A quarter turn swaps the width and height of the picture.
+        let texture = match rotation {
+            VideoRotation::Rotate90 | VideoRotation::Rotate270 => Size {
+                width: texture.height,
+                height: texture.width,
+            },
+            _ => texture,
+        };
+
+        let (sw, sh) = (surface.width as f32, surface.height as f32);
+        let (tw, th) = (texture.width.max(1) as f32, texture.height.max(1) as f32);
+
+        let full = Self {
+            x: 0.0,
+            y: 0.0,
+            width: sw,
+            height: sh,
+        };
+
+        match mode {
+            FitMode::Stretch => (full, [1.0, 1.0]),
+            FitMode::Contain => {
+                let scale = (sw / tw).min(sh / th);
+                let (width, height) = ((tw * scale).round(), (th * scale).round());
+
+                (
+                    Self {
+                        x: ((sw - width) / 2.0).floor(),
+                        y: ((sh - height) / 2.0).floor(),
+                        width,
+                        height,
+                    },
+                    [1.0, 1.0],
+                )
+            }
+            FitMode::Cover => {
+                let scale = (sw / tw).max(sh / th);
+
+                (
+                    full,
+                    [(sw / (tw * scale)).min(1.0), (sh / (th * scale)).min(1.0)],
+                )
+            }
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -45,72 +172,213 @@ pub struct RendererOptions<T> {
     #[cfg(target_os = "windows")]
     pub direct3d: hylarana_common::win32::Direct3DDevice,
     pub window: T,
+    /// The size of the window in physical pixels, that is, the logical size
+    /// multiplied by the scale factor of the display.
     pub size: Size,
+    pub fit: FitMode,
+    pub filter: ScaleFilter,
+    pub adapter: AdapterPreference,
 }
 
-/// Window Renderer.
-///
-/// Supports rendering RGBA or NV12 hardware or software textures to system
-/// native windows.
+/// A graphics adapter of the system, the vendor id and device id can be used
+/// to pin everything to the adapter with `AdapterPreference::Device`.
+#[derive(Debug, Clone)]
+pub struct GraphicsAdapter {
+    pub name: String,
+    pub vendor: u32,
+    pub device: u32,
+    /// Whether the adapter is a discrete GPU.
+    pub discrete: bool,
+}
+
+/// Enumerate the graphics adapters that are available to the renderer.
+pub fn enumerate_adapters() -> Vec<GraphicsAdapter> {
+    DeviceContext::instance()
+        .enumerate_adapters(DeviceContext::BACKENDS)
+        .into_iter()
+        .map(|adapter| {
+            let info = adapter.get_info();
+            GraphicsAdapter {
+                discrete: info.device_type == DeviceType::DiscreteGpu,
+                vendor: info.vendor,
+                device: info.device,
+                name: info.name,
+            }
+        })
+        .collect()
+}
+
+/// Options of a renderer without a window, the frames are rendered to an
+/// offscreen texture and can be read back through `snapshot`, such as for
+/// tests running in CI or for re-encoding the rendered frames.
+#[derive(Debug)]
+pub struct OffscreenRendererOptions {
+    #[cfg(target_os = "windows")]
+    pub direct3d: hylarana_common::win32::Direct3DDevice,
+    /// The size of the offscreen texture.
+    pub size: Size,
+    pub fit: FitMode,
+    pub filter: ScaleFilter,
+    pub adapter: AdapterPreference,
+}
+
+/// Options of a renderer that draws to a texture of a device of the
+/// application, such as the device of a game engine, so that the video can be
+/// shown on a surface in the scene without being read back to the memory.
 ///
-/// Note that the renderer uses a hardware implementation by default, i.e. it
-/// uses the underlying GPU device, and the use of software devices is not
-/// currently supported.
-pub struct Renderer<'a> {
-    surface: Surface<'a>,
-    device: Arc<Device>,
-    queue: Arc<Queue>,
-    vertex_buffer: Buffer,
-    index_buffer: Buffer,
-    source: Texture2DSource,
+/// The hardware frames of the decoder are shared with the device, this needs
+/// the device to use the same backend as the renderer, which is DX12 on
+/// windows, Vulkan on linux and Metal on macos, otherwise a software decoder
+/// has to be used.
+#[cfg(feature = "external")]
+#[derive(Debug)]
+pub struct ExternalRendererOptions {
+    #[cfg(target_os = "windows")]
+    pub direct3d: hylarana_common::win32::Direct3DDevice,
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    /// The size of the texture.
+    pub size: Size,
+    pub fit: FitMode,
+    pub filter: ScaleFilter,
 }
 
-impl<'a> Renderer<'a> {
-    pub fn new<T: Into<SurfaceTarget<'a>>>(
-        options: RendererOptions<T>,
-    ) -> Result<Self, GraphicsError> {
-        let instance = Instance::new(InstanceDescriptor {
-            backends: if cfg!(target_os = "windows") {
-                Backends::DX12
-            } else if cfg!(target_os = "linux") {
-                Backends::VULKAN
-            } else {
-                Backends::METAL
-            },
+/// The device that renders and the buffers of the quad that the textures are
+/// drawn to, which is shared by the renderers.
+pub(crate) struct DeviceContext {
+    pub device: Arc<Device>,
+    pub queue: Arc<Queue>,
+    pub vertex_buffer: Buffer,
+    pub index_buffer: Buffer,
+}
+
+impl DeviceContext {
+    pub const BACKENDS: Backends = if cfg!(target_os = "windows") {
+        Backends::DX12
+    } else if cfg!(target_os = "linux") {
+        Backends::VULKAN
+    } else {
+        Backends::METAL
+    };
+
+    pub fn instance() -> Instance {
+        Instance::new(InstanceDescriptor {
+            backends: Self::BACKENDS,
             ..Default::default()
+        })
+    }
+
+    /// Request a device from the adapter chosen by the preference, if there is
+    /// a surface, the adapter must be compatible with it.
+    pub fn new(
+        instance: &Instance,
+        surface: Option<&Surface>,
+        preference: AdapterPreference,
+    ) -> Result<(Adapter, Self), GraphicsError> {
+        let adapter = match preference {
+            AdapterPreference::Device { vendor, device } => instance
+                .enumerate_adapters(Self::BACKENDS)
+                .into_iter()
+                .find(|adapter| {
+                    let info = adapter.get_info();
+                    info.vendor == vendor
+                        && info.device == device
+                        && surface
+                            .map(|surface| adapter.is_surface_supported(surface))
+                            .unwrap_or(true)
+                }),
+            _ => None,
+        };
+
+        // Fall back to the power preference if the adapter is not found.
+        let adapter = match adapter {
+            Some(adapter) => adapter,
+            None => instance
+                .request_adapter(&RequestAdapterOptions {
+                    power_preference: if preference == AdapterPreference::HighPerformance {
+                        PowerPreference::HighPerformance
+                    } else {
+                        PowerPreference::LowPower
+                    },
+                    force_fallback_adapter: false,
+                    compatible_surface: surface,
+                })
+                .block_on()
+                .ok_or_else(|| GraphicsError::NotFoundAdapter)?,
+        };
+
+        let desc = DeviceDescriptor {
+            label: None,
+            memory_hints: MemoryHints::Performance,
+            required_features: adapter.features(),
+            required_limits: adapter.limits(),
+        };
+
+        // The DMA-BUF frames can only be imported by the device that has the external
+        // memory extensions enabled, which wgpu does not enable itself.
+        #[cfg(target_os = "linux")]
+        let device = interop::linux::request_device(&adapter, &desc);
+
+        #[cfg(not(target_os = "linux"))]
+        let device = None;
+
+        let (device, queue) = match device {
+            Some(it) => it,
+            None => adapter.request_device(&desc, None).block_on()?,
+        };
+
+        Ok((
+            adapter,
+            Self::with_device(Arc::new(device), Arc::new(queue)),
+        ))
+    }
+
+    /// Use a device that has already been created, such as the device of the
+    /// application.
+    pub fn with_device(device: Arc<Device>, queue: Arc<Queue>) -> Self {
+        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(Vertex::VERTICES),
+            usage: BufferUsages::VERTEX,
         });
 
-        let surface = instance.create_surface(options.window)?;
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                power_preference: PowerPreference::LowPower,
-                force_fallback_adapter: false,
-                compatible_surface: Some(&surface),
-                ..Default::default()
-            })
-            .block_on()
-            .ok_or_else(|| GraphicsError::NotFoundAdapter)?;
-
-        let (device, queue) = adapter
-            .request_device(
-                &DeviceDescriptor {
-                    label: None,
-                    memory_hints: MemoryHints::Performance,
-                    required_features: adapter.features(),
-                    required_limits: adapter.limits(),
-                },
-                None,
-            )
-            .block_on()?;
+        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(Vertex::INDICES),
+            usage: BufferUsages::INDEX,
+        });
 
-        let device = Arc::new(device);
-        let queue = Arc::new(queue);
+        Self {
+            vertex_buffer,
+            index_buffer,
+            device,
+            queue,
+        }
+    }
+}
+
+/// The surface of the window and the device that renders to it.
+pub(crate) struct SurfaceContext<'a> {
+    pub surface: Surface<'a>,
+    pub config: SurfaceConfiguration,
+    pub context: DeviceContext,
+}
+
+impl<'a> SurfaceContext<'a> {
+    pub fn new<T: Into<SurfaceTarget<'a>>>(
+        window: T,
+        size: Size,
+        preference: AdapterPreference,
+    ) -> Result<Self, GraphicsError> {
+        let instance = DeviceContext::instance();
+        let surface = instance.create_surface(window)?;
+        let (adapter, context) = DeviceContext::new(&instance, Some(&surface), preference)?;
 
         // Configure surface as BGRA, BGRA this format compatibility is the best, in
         // order to unnecessary trouble, directly fixed to BGRA is the best.
-        {
+        let config = {
             let mut config = surface
-                .get_default_config(&adapter, options.size.width, options.size.height)
+                .get_default_config(&adapter, size.width, size.height)
                 .ok_or_else(|| GraphicsError::NotFoundSurfaceDefaultConfig)?;
 
             config.present_mode = if cfg!(target_os = "windows") {
@@ -124,95 +392,541 @@ impl<'a> Renderer<'a> {
             config.format = TextureFormat::Bgra8Unorm;
             config.alpha_mode = CompositeAlphaMode::Opaque;
             config.usage = TextureUsages::RENDER_ATTACHMENT;
-            surface.configure(&device, &config);
+            surface.configure(&context.device, &config);
+            config
         };
 
-        let vertex_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(Vertex::VERTICES),
-            usage: BufferUsages::VERTEX,
-        });
+        Ok(Self {
+            surface,
+            config,
+            context,
+        })
+    }
+}
 
-        let index_buffer = device.create_buffer_init(&BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(Vertex::INDICES),
-            usage: BufferUsages::INDEX,
-        });
+/// Where the renderer draws to, either the surface of a window or an
+/// offscreen texture.
+enum RenderTarget<'a> {
+    Surface(Surface<'a>, SurfaceConfiguration),
+    Texture(wgpu::Texture),
+}
+
+/// Window Renderer.
+///
+/// Supports rendering RGBA or NV12 hardware or software textures to system
+/// native windows, or to an offscreen texture when there is no window.
+///
+/// Note that the renderer uses a hardware implementation by default, i.e. it
+/// uses the underlying GPU device, and the use of software devices is not
+/// currently supported.
+///
+/// The texture keeps its aspect ratio when the window does not match it, and
+/// the window size needs to be updated through `resize` when it changes.
+pub struct Renderer<'a> {
+    target: RenderTarget<'a>,
+    size: Size,
+    orientation: (VideoRotation, bool),
+    // The visible area of the textures, `None` is the whole texture.
+    display_size: Option<Size>,
+    fit: FitMode,
+    overlay: Overlay,
+    post_process: PostProcess,
+    frame: Option<(BindGroup, Size)>,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    source: Texture2DSource,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new<T: Into<SurfaceTarget<'a>>>(
+        options: RendererOptions<T>,
+    ) -> Result<Self, GraphicsError> {
+        let SurfaceContext {
+            surface,
+            config,
+            context,
+        } = SurfaceContext::new(options.window, options.size, options.adapter)?;
+
+        Self::with_target(
+            RenderTarget::Surface(surface, config),
+            options.size,
+            options.fit,
+            options.filter,
+            context,
+            #[cfg(target_os = "windows")]
+            options.direct3d,
+        )
+    }
+
+    /// Create a renderer without a window, the frames are rendered to an
+    /// offscreen texture, and they can be read back through `snapshot`.
+    pub fn offscreen(options: OffscreenRendererOptions) -> Result<Self, GraphicsError> {
+        let (_, context) = DeviceContext::new(&DeviceContext::instance(), None, options.adapter)?;
+
+        Self::with_target(
+            RenderTarget::Texture(create_offscreen_texture(&context.device, options.size)),
+            options.size,
+            options.fit,
+            options.filter,
+            context,
+            #[cfg(target_os = "windows")]
+            options.direct3d,
+        )
+    }
+
+    /// Create a renderer that draws to a texture of the device of the
+    /// application, see `texture` for the texture.
+    #[cfg(feature = "external")]
+    pub fn external(options: ExternalRendererOptions) -> Result<Self, GraphicsError> {
+        let context = DeviceContext::with_device(options.device, options.queue);
+
+        Self::with_target(
+            RenderTarget::Texture(create_offscreen_texture(&context.device, options.size)),
+            options.size,
+            options.fit,
+            options.filter,
+            context,
+            #[cfg(target_os = "windows")]
+            options.direct3d,
+        )
+    }
+
+    /// The texture that the frames are drawn to if the renderer does not have
+    /// a window, the format is BGRA and it can be sampled in the shaders of
+    /// the application. The texture is replaced when the renderer is resized.
+    pub fn texture(&self) -> Option<&wgpu::Texture> {
+        match &self.target {
+            RenderTarget::Texture(texture) => Some(texture),
+            RenderTarget::Surface(..) => None,
+        }
+    }
+
+    fn with_target(
+        target: RenderTarget<'a>,
+        size: Size,
+        fit: FitMode,
+        filter: ScaleFilter,
+        context: DeviceContext,
+        #[cfg(target_os = "windows")] direct3d: hylarana_common::win32::Direct3DDevice,
+    ) -> Result<Self, GraphicsError> {
+        let DeviceContext {
+            device,
+            queue,
+            vertex_buffer,
+            index_buffer,
+        } = context;
 
         Ok(Self {
+            overlay: Overlay::new(
+                device.clone(),
+                queue.clone(),
+                #[cfg(target_os = "windows")]
+                direct3d.clone(),
+            ),
+            post_process: PostProcess::new(device.clone(), queue.clone(), size),
             source: Texture2DSource::new(Texture2DSourceOptions {
                 #[cfg(target_os = "windows")]
-                direct3d: options.direct3d,
+                direct3d,
+                filter,
                 device: device.clone(),
                 queue: queue.clone(),
             })?,
+            orientation: (VideoRotation::Rotate0, false),
+            display_size: None,
+            frame: None,
             vertex_buffer,
             index_buffer,
-            surface,
+            target,
+            size,
+            fit,
             device,
             queue,
         })
     }
 
+    /// Reconfigure the surface when the size of the window changes, the size
+    /// is in physical pixels, the offscreen texture is recreated with the new
+    /// size.
+    ///
+    /// A size with a zero width or height, such as a minimized window, is
+    /// ignored.
+    pub fn resize(&mut self, size: Size) {
+        if size.width == 0 || size.height == 0 || size == self.size {
+            return;
+        }
+
+        match &mut self.target {
+            RenderTarget::Surface(surface, config) => {
+                config.width = size.width;
+                config.height = size.height;
+                surface.configure(&self.device, config);
+            }
+            RenderTarget::Texture(texture) => {
+                *texture = create_offscreen_texture(&self.device, size);
+            }
+        }
+
+        self.post_process.resize(size);
+        self.size = size;
+    }
+
+    /// Set the rotation and mirroring of the rendered texture, this is
+    /// applied to all textures submitted afterwards.
+    ///
+    /// A quarter turn swaps the width and height of the picture, the viewport
+    /// is fitted to the rotated size, except with `FitMode::Stretch`, which
+    /// fills the surface anyway, so the host has to resize the surface to the
+    /// rotated size to keep the aspect ratio.
+    pub fn set_orientation(&mut self, rotation: VideoRotation, mirror: bool) {
+        self.orientation = (rotation, mirror);
+    }
+
+    /// Set the size of the picture in the top left corner of the textures,
+    /// the rest of the textures is padding that is not drawn, such as the
+    /// rows that the decoders align the textures to. This is applied to all
+    /// textures submitted afterwards.
+    pub fn set_display_size(&mut self, size: Size) {
+        self.display_size = Some(size);
+    }
+
+    /// Whether the D3D11 device that the hardware textures are copied with has
+    /// been removed, such as by a GPU reset or a driver update.
+    #[cfg(target_os = "windows")]
+    pub fn is_device_removed(&self) -> bool {
+        self.source.is_device_removed()
+    }
+
+    /// Continue with a new D3D11 device after the previous one has been
+    /// removed, the textures of the new device are accepted from the next
+    /// frame.
+    #[cfg(target_os = "windows")]
+    pub fn set_direct3d(&mut self, direct3d: hylarana_common::win32::Direct3DDevice) {
+        self.overlay.set_direct3d(direct3d.clone());
+        self.source.set_direct3d(direct3d);
+    }
+
+    /// Set an RGBA layer that is composited over the video, such as a logo or
+    /// a badge, the overlay is kept until it is cleared. A software texture
+    /// is copied, so the overlay only needs to be set again when it changes.
+    pub fn set_overlay(
+        &mut self,
+        texture: Texture2DResource,
+        rect: OverlayRect,
+        alpha: f32,
+    ) -> Result<(), GraphicsError> {
+        Ok(self.overlay.update(&texture, rect, alpha)?)
+    }
+
+    pub fn clear_overlay(&mut self) {
+        self.overlay.clear();
+    }
+
+    /// Set the post-process passes that are applied in order to the video
+    /// after it is converted to RGB and scaled to the surface, such as
+    /// sharpening, an empty list removes them.
+    ///
+    /// The shaders are compiled here, and an error is returned if any of them
+    /// is invalid, in which case the current passes are kept.
+    pub fn set_post_process(&mut self, shaders: &[PostProcessShader]) -> Result<(), GraphicsError> {
+        self.post_process.set(shaders)
+    }
+
+    /// Update the parameters of the post-process pass at the index, such as
+    /// the strength of the sharpening, without recompiling the shader.
+    pub fn set_post_process_params(&mut self, index: usize, params: [f32; 4]) {
+        self.post_process.set_params(index, params);
+    }
+
     // Submit the texture to the renderer, it should be noted that the renderer will
     // not render this texture immediately, the processing flow will enter the
     // render queue and wait for the queue to automatically schedule the rendering
     // to the surface.
     pub fn submit(&mut self, texture: Texture) -> Result<(), GraphicsError> {
-        if let Some((pipeline, bind_group)) = self.source.get_view(texture)? {
-            let output = self.surface.get_current_texture()?;
-            let view = output
-                .texture
-                .create_view(&TextureViewDescriptor::default());
-
-            let mut encoder = self
-                .device
-                .create_command_encoder(&CommandEncoderDescriptor { label: None });
-
-            {
-                let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                    color_attachments: &[Some(RenderPassColorAttachment {
-                        view: &view,
-                        resolve_target: None,
-                        ops: Operations {
-                            load: LoadOp::Clear(Color::BLACK),
-                            store: StoreOp::Store,
-                        },
-                    })],
-                    ..Default::default()
-                });
+        let size = texture.size();
+        let viewport = self.update_transform(size);
+
+        if let Some((_, bind_group, _)) = self.source.get_view(texture)? {
+            // Keep the bind group of the last frame, the snapshot renders it again.
+            self.frame = Some((bind_group, size));
+
+            match &self.target {
+                RenderTarget::Surface(surface, config) => {
+                    let output = match surface.get_current_texture() {
+                        Ok(output) => output,
+                        // The window has been resized or the surface has been lost,
+                        // reconfigure the surface and skip this frame.
+                        Err(SurfaceError::Outdated | SurfaceError::Lost) => {
+                            surface.configure(&self.device, config);
+
+                            return Ok(());
+                        }
+                        Err(e) => return Err(e.into()),
+                    };
+
+                    let view = output
+                        .texture
+                        .create_view(&TextureViewDescriptor::default());
+
+                    let encoder = self.encode(&view, viewport);
+                    self.queue.submit(Some(encoder.finish()));
+                    output.present();
+                }
+                RenderTarget::Texture(texture) => {
+                    let view = texture.create_view(&TextureViewDescriptor::default());
+                    let encoder = self.encode(&view, viewport);
+                    self.queue.submit(Some(encoder.finish()));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Read back the last rendered frame as it is displayed on the surface,
+    /// including the letterboxing and the overlay, the size of the image is
+    /// the size of the surface.
+    ///
+    /// The frame is rendered again to an offscreen texture, so this does not
+    /// affect the surface, but it waits for the GPU to finish.
+    pub fn snapshot(&mut self) -> Result<RgbaImage, GraphicsError> {
+        let size = self
+            .frame
+            .as_ref()
+            .map(|(_, size)| *size)
+            .ok_or_else(|| GraphicsError::NotFoundFrame)?;
+
+        // The surface may have been resized since the last frame.
+        let viewport = self.update_transform(size);
+
+        let texture = create_offscreen_texture(&self.device, self.size);
+
+        // The rows of the buffer need to be aligned when copying from the texture.
+        let stride = (self.size.width * 4).div_ceil(COPY_BYTES_PER_ROW_ALIGNMENT)
+            * COPY_BYTES_PER_ROW_ALIGNMENT;
+
+        let buffer = self.device.create_buffer(&BufferDescriptor {
+            label: None,
+            size: stride as u64 * self.size.height as u64,
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = {
+            let view = texture.create_view(&TextureViewDescriptor::default());
+            self.encode(&view, viewport)
+        };
+
+        encoder.copy_texture_to_buffer(
+            ImageCopyTexture {
+                aspect: TextureAspect::All,
+                texture: &texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+            },
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(stride),
+                    rows_per_image: Some(self.size.height),
+                },
+            },
+            texture.size(),
+        );
+
+        self.queue.submit(Some(encoder.finish()));
+
+        let (tx, rx) = channel();
+        let slice = buffer.slice(..);
+        slice.map_async(MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+
+        self.device.poll(Maintain::Wait);
+        rx.recv().unwrap_or(Err(BufferAsyncError))?;
+
+        // The surface is BGRA, swap the red and blue channels and drop the padding
+        // of the rows.
+        let row = self.size.width as usize * 4;
+        let mut data = Vec::with_capacity(row * self.size.height as usize);
+        for line in slice.get_mapped_range().chunks(stride as usize) {
+            for pixel in line[..row].chunks_exact(4) {
+                data.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+            }
+        }
+
+        buffer.unmap();
+
+        Ok(RgbaImage {
+            size: self.size,
+            data,
+        })
+    }
+
+    /// Update the transform of the texture for the current surface, and return
+    /// the area of the surface that the texture is drawn to.
+    fn update_transform(&mut self, size: Size) -> Viewport {
+        let (rotation, mirror) = self.orientation;
+        let display_size = get_display_size(size, self.display_size);
+        let (viewport, crop) = Viewport::fit(self.size, display_size, rotation, self.fit);
+
+        self.source.set_transform(
+            rotation,
+            mirror,
+            crop,
+            [
+                display_size.width as f32 / size.width.max(1) as f32,
+                display_size.height as f32 / size.height.max(1) as f32,
+            ],
+        );
+
+        viewport
+    }
+
+    /// Encode the render pass of the last frame to the view.
+    fn encode(&self, view: &TextureView, viewport: Viewport) -> CommandEncoder {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+
+        // With post-process passes, the video is drawn to the input of the passes
+        // instead, and the last pass draws to the view.
+        let input = self.post_process.input();
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: input.unwrap_or(view),
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            if let (Some(pipeline), Some((bind_group, _))) = (self.source.pipeline(), &self.frame) {
+                render_pass.set_viewport(
+                    viewport.x,
+                    viewport.y,
+                    viewport.width,
+                    viewport.height,
+                    0.0,
+                    1.0,
+                );
 
                 render_pass.set_pipeline(pipeline);
-                render_pass.set_bind_group(0, Some(&bind_group), &[]);
+                render_pass.set_bind_group(0, Some(bind_group), &[]);
+                render_pass.set_bind_group(1, Some(self.source.transform()), &[]);
                 render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
                 render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
                 render_pass.draw_indexed(0..Vertex::INDICES.len() as u32, 0, 0..1);
+
+                if input.is_none() {
+                    self.draw_overlay(&mut render_pass);
+                }
             }
+        }
 
-            self.queue.submit(Some(encoder.finish()));
-            output.present();
+        if input.is_some() && self.frame.is_some() {
+            self.post_process.encode(&mut encoder);
+
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
+            self.post_process.draw_last(&mut render_pass);
+            self.draw_overlay(&mut render_pass);
         }
 
-        Ok(())
+        encoder
+    }
+
+    fn draw_overlay(&self, render_pass: &mut RenderPass) {
+        // The overlay is positioned relative to the whole surface, not the area of
+        // the video.
+        render_pass.set_viewport(
+            0.0,
+            0.0,
+            self.size.width as f32,
+            self.size.height as f32,
+            0.0,
+            1.0,
+        );
+
+        self.overlay.draw(render_pass);
+    }
+}
+
+fn create_offscreen_texture(device: &Device, size: Size) -> wgpu::Texture {
+    device.create_texture(&TextureDescriptor {
+        label: None,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Bgra8Unorm,
+        usage: TextureUsages::RENDER_ATTACHMENT
+            | TextureUsages::COPY_SRC
+            | TextureUsages::TEXTURE_BINDING,
+        view_formats: &[],
+        size: Extent3d {
+            width: size.width,
+            height: size.height,
+            depth_or_array_layers: 1,
+        },
+    })
+}
+
+// The picture is in the top left corner of the texture, it cannot be larger
+// than the texture.
+fn get_display_size(texture: Size, display: Option<Size>) -> Size {
+    match display {
+        Some(it) if it.width > 0 && it.height > 0 => Size {
+            width: it.width.min(texture.width),
+            height: it.height.min(texture.height),
+        },
+        _ => texture,
     }
 }
 
 #[cfg(target_os = "windows")]
 pub mod dx11 {
     use hylarana_common::{
+        frame::VideoRotation,
         win32::{
             windows::Win32::{
-                Foundation::HWND,
+                Foundation::{HWND, RECT},
                 Graphics::{
-                    Direct3D11::{ID3D11RenderTargetView, ID3D11Texture2D, D3D11_VIEWPORT},
+                    Direct3D11::{
+                        ID3D11RenderTargetView, ID3D11Texture2D, ID3D11VideoProcessorInputView,
+                        D3D11_VIEWPORT,
+                    },
                     Dxgi::{
-                        Common::{DXGI_FORMAT_NV12, DXGI_FORMAT_R8G8B8A8_UNORM},
+                        Common::{
+                            DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709,
+                            DXGI_COLOR_SPACE_YCBCR_STUDIO_G2084_LEFT_P2020, DXGI_FORMAT_NV12,
+                            DXGI_FORMAT_P010, DXGI_FORMAT_R8G8B8A8_UNORM, DXGI_FORMAT_UNKNOWN,
+                        },
                         CreateDXGIFactory, IDXGIFactory, IDXGISwapChain, DXGI_PRESENT,
-                        DXGI_SWAP_CHAIN_DESC, DXGI_USAGE_RENDER_TARGET_OUTPUT,
+                        DXGI_SWAP_CHAIN_DESC, DXGI_SWAP_CHAIN_FLAG,
+                        DXGI_USAGE_RENDER_TARGET_OUTPUT,
                     },
                 },
             },
-            Direct3DDevice,
+            Direct3DDevice, EasyTexture,
         },
         Size,
     };
@@ -220,56 +934,97 @@ pub mod dx11 {
     use hylarana_resample::win32::{Resource, VideoResampler, VideoResamplerOptions};
     use thiserror::Error;
 
-    use crate::{Texture, Texture2DRaw, Texture2DResource};
+    use crate::{
+        get_display_size, FitMode, RgbaImage, Texture, Texture2DRaw, Texture2DResource, Viewport,
+    };
 
     #[derive(Debug, Error)]
     pub enum Dx11GraphicsError {
         #[error(transparent)]
         WindowsError(#[from] hylarana_common::win32::windows::core::Error),
+        #[error("no frame has been rendered")]
+        NotFoundFrame,
+        #[error("the device of the render target texture has been removed")]
+        TextureDeviceRemoved,
+    }
+
+    // The swap chain of a window, or a texture of the application that the
+    // frames are drawn to, such as a texture of a game engine.
+    enum Dx11Target {
+        SwapChain(IDXGISwapChain, HWND),
+        Texture(ID3D11Texture2D),
+    }
+
+    impl Dx11Target {
+        fn buffer(&self) -> Result<ID3D11Texture2D, Dx11GraphicsError> {
+            Ok(match self {
+                Self::SwapChain(swap_chain, _) => unsafe { swap_chain.GetBuffer(0)? },
+                Self::Texture(texture) => texture.clone(),
+            })
+        }
     }
 
     pub struct Dx11Renderer {
         direct3d: Direct3DDevice,
-        swap_chain: IDXGISwapChain,
-        render_target_view: ID3D11RenderTargetView,
+        target: Dx11Target,
+        render_target_view: Option<ID3D11RenderTargetView>,
         video_processor: Option<VideoResampler>,
+        // The input of the last frame, `Some(None)` means that the input is the
+        // internal texture of the video processor.
+        input: Option<Option<ID3D11VideoProcessorInputView>>,
+        orientation: (VideoRotation, bool),
+        // The visible area of the textures, `None` is the whole texture.
+        display_size: Option<Size>,
+        size: Size,
+        fit: FitMode,
     }
 
     unsafe impl Send for Dx11Renderer {}
     unsafe impl Sync for Dx11Renderer {}
 
     impl Dx11Renderer {
+        /// The scaling of the video processor is chosen by the driver, so there
+        /// is no scaling filter option for this renderer.
         pub fn new(
             window: HWND,
             size: Size,
+            fit: FitMode,
             direct3d: Direct3DDevice,
         ) -> Result<Self, Dx11GraphicsError> {
-            let swap_chain = unsafe {
-                let dxgi_factory = CreateDXGIFactory::<IDXGIFactory>()?;
-
-                let mut desc = DXGI_SWAP_CHAIN_DESC::default();
-                desc.BufferCount = 1;
-                desc.BufferDesc.Width = size.width;
-                desc.BufferDesc.Height = size.height;
-                desc.BufferDesc.Format = DXGI_FORMAT_R8G8B8A8_UNORM;
-                desc.BufferUsage = DXGI_USAGE_RENDER_TARGET_OUTPUT;
-                desc.OutputWindow = window;
-                desc.SampleDesc.Count = 1;
-                desc.Windowed = true.into();
-
-                let mut swap_chain = None;
-                dxgi_factory
-                    .CreateSwapChain(&direct3d.device, &desc, &mut swap_chain)
-                    .ok()?;
-
-                swap_chain.unwrap()
-            };
+            let swap_chain = create_swap_chain(&direct3d, window, size)?;
+            let render_target_view = create_render_target_view(&direct3d, &swap_chain, size)?;
+
+            Ok(Self {
+                render_target_view: Some(render_target_view),
+                orientation: (VideoRotation::Rotate0, false),
+                display_size: None,
+                target: Dx11Target::SwapChain(swap_chain, window),
+                video_processor: None,
+                input: None,
+                direct3d,
+                size,
+                fit,
+            })
+        }
 
-            let back_buffer = unsafe { swap_chain.GetBuffer::<ID3D11Texture2D>(0)? };
+        /// Draw the frames to a texture of the application instead of a
+        /// window, the texture has to be created on the same device and bound
+        /// as a render target, the size of the renderer is the size of the
+        /// texture.
+        ///
+        /// The render targets and the viewports of the device context are not
+        /// changed, so the texture can be drawn to in the middle of the frame
+        /// of the application.
+        pub fn with_texture(
+            texture: ID3D11Texture2D,
+            fit: FitMode,
+            direct3d: Direct3DDevice,
+        ) -> Result<Self, Dx11GraphicsError> {
+            let desc = texture.desc();
             let render_target_view = unsafe {
                 let mut render_target_view = None;
                 direct3d.device.CreateRenderTargetView(
-                    &back_buffer,
+                    &texture,
                     None,
                     Some(&mut render_target_view),
                 )?;
@@ -277,36 +1032,127 @@ pub mod dx11 {
                 render_target_view.unwrap()
             };
 
-            unsafe {
-                direct3d
-                    .context
-                    .OMSetRenderTargets(Some(&[Some(render_target_view.clone())]), None);
+            Ok(Self {
+                size: Size {
+                    width: desc.Width,
+                    height: desc.Height,
+                },
+                render_target_view: Some(render_target_view),
+                orientation: (VideoRotation::Rotate0, false),
+                display_size: None,
+                target: Dx11Target::Texture(texture),
+                video_processor: None,
+                input: None,
+                direct3d,
+                fit,
+            })
+        }
+
+        /// Resize the swap chain when the size of the window changes, the size
+        /// is in physical pixels.
+        ///
+        /// A size with a zero width or height, such as a minimized window, is
+        /// ignored, so is the resize of a renderer that draws to a texture,
+        /// which has the fixed size of the texture.
+        pub fn resize(&mut self, size: Size) -> Result<(), Dx11GraphicsError> {
+            if size.width == 0 || size.height == 0 || size == self.size {
+                return Ok(());
             }
 
+            let Dx11Target::SwapChain(swap_chain, _) = &self.target else {
+                return Ok(());
+            };
+
+            // All references to the back buffer must be released before resizing the
+            // swap chain, the video processor also holds the back buffer as the output,
+            // so it is recreated on the next submit.
+            self.video_processor = None;
+            self.render_target_view = None;
+            self.input = None;
+
             unsafe {
-                let mut vp = D3D11_VIEWPORT::default();
-                vp.Width = size.width as f32;
-                vp.Height = size.height as f32;
-                vp.MinDepth = 0.0;
-                vp.MaxDepth = 1.0;
+                self.direct3d.context.OMSetRenderTargets(None, None);
+                swap_chain.ResizeBuffers(
+                    0,
+                    size.width,
+                    size.height,
+                    DXGI_FORMAT_UNKNOWN,
+                    DXGI_SWAP_CHAIN_FLAG(0),
+                )?;
+            }
+
+            self.render_target_view =
+                Some(create_render_target_view(&self.direct3d, swap_chain, size)?);
+
+            self.size = size;
+            Ok(())
+        }
+
+        /// Whether the device has been removed, such as by a GPU reset or a
+        /// driver update.
+        pub fn is_device_removed(&self) -> bool {
+            self.direct3d.is_removed()
+        }
+
+        /// Continue on a new device after the previous one has been removed,
+        /// the swap chain of the window is created again on the new device.
+        ///
+        /// A texture of the application cannot be moved to another device, so
+        /// the renderer of a texture cannot be recovered.
+        pub fn set_direct3d(&mut self, direct3d: Direct3DDevice) -> Result<(), Dx11GraphicsError> {
+            let Dx11Target::SwapChain(_, window) = self.target else {
+                return Err(Dx11GraphicsError::TextureDeviceRemoved);
+            };
+
+            // Everything that was created on the removed device is released first.
+            self.video_processor = None;
+            self.render_target_view = None;
+            self.input = None;
+
+            let swap_chain = create_swap_chain(&direct3d, window, self.size)?;
+            self.render_target_view = Some(create_render_target_view(
+                &direct3d,
+                &swap_chain,
+                self.size,
+            )?);
 
-                direct3d.context.RSSetViewports(Some(&[vp]));
+            self.target = Dx11Target::SwapChain(swap_chain, window);
+            self.direct3d = direct3d;
+            Ok(())
+        }
+
+        /// Set the rotation and mirroring of the rendered texture, the
+        /// transform is done by the video processor.
+        pub fn set_orientation(
+            &mut self,
+            rotation: VideoRotation,
+            mirror: bool,
+        ) -> Result<(), Dx11GraphicsError> {
+            if self.orientation != (rotation, mirror) {
+                if let Some(processor) = self.video_processor.as_mut() {
+                    processor.set_orientation(rotation, mirror)?;
+                }
+
+                self.orientation = (rotation, mirror);
             }
 
-            Ok(Self {
-                video_processor: None,
-                render_target_view,
-                swap_chain,
-                direct3d,
-            })
+            Ok(())
+        }
+
+        /// Set the size of the picture in the top left corner of the textures,
+        /// the padding of the textures is cropped by the video processor.
+        pub fn set_display_size(&mut self, size: Size) {
+            self.display_size = Some(size);
         }
 
         /// Draw this pixel buffer to the configured SurfaceTexture.
         pub fn submit(&mut self, texture: Texture) -> Result<(), Dx11GraphicsError> {
-            unsafe {
-                self.direct3d
-                    .context
-                    .ClearRenderTargetView(&self.render_target_view, &[0.0, 0.0, 0.0, 1.0]);
+            if let Some(render_target_view) = &self.render_target_view {
+                unsafe {
+                    self.direct3d
+                        .context
+                        .ClearRenderTargetView(render_target_view, &[0.0, 0.0, 0.0, 1.0]);
+                }
             }
 
             if self.video_processor.is_none() {
@@ -314,22 +1160,44 @@ pub mod dx11 {
                 let format = match texture {
                     Texture::Nv12(_) => DXGI_FORMAT_NV12,
                     Texture::Rgba(_) => DXGI_FORMAT_R8G8B8A8_UNORM,
+                    Texture::P010(_) => DXGI_FORMAT_P010,
                     _ => unimplemented!("not supports texture format"),
                 };
 
-                self.video_processor
-                    .replace(VideoResampler::new(VideoResamplerOptions {
-                        direct3d: self.direct3d.clone(),
-                        input: Resource::Default(format, size),
-                        output: Resource::Texture(unsafe {
-                            self.swap_chain.GetBuffer::<ID3D11Texture2D>(0)?
-                        }),
-                    })?);
+                let mut processor = VideoResampler::new(VideoResamplerOptions {
+                    direct3d: self.direct3d.clone(),
+                    input: Resource::Default(format, size),
+                    output: Resource::Texture(self.target.buffer()?),
+                })?;
+
+                // The swap chain is SDR, let the video processor convert the HDR10 input
+                // to the SDR output.
+                if format == DXGI_FORMAT_P010 {
+                    processor.set_color_space(
+                        DXGI_COLOR_SPACE_YCBCR_STUDIO_G2084_LEFT_P2020,
+                        DXGI_COLOR_SPACE_RGB_FULL_G22_NONE_P709,
+                    )?;
+                }
+
+                let (rotation, mirror) = self.orientation;
+                processor.set_orientation(rotation, mirror)?;
+
+                self.video_processor.replace(processor);
             }
 
             if let Some(processor) = self.video_processor.as_mut() {
-                let texture = match texture {
-                    Texture::Rgba(texture) | Texture::Nv12(texture) => texture,
+                let texture_size = texture.size();
+
+                // The samples of P010 are 16 bits, so the stride is twice the width.
+                let (texture, stride) = match texture {
+                    Texture::Rgba(texture) | Texture::Nv12(texture) => {
+                        let stride = texture.size().width;
+                        (texture, stride)
+                    }
+                    Texture::P010(texture) => {
+                        let stride = texture.size().width * 2;
+                        (texture, stride)
+                    }
                     _ => unimplemented!("not supports texture format"),
                 };
 
@@ -340,23 +1208,159 @@ pub mod dx11 {
                         }
                     },
                     Texture2DResource::Buffer(texture) => {
-                        processor.update_input_from_buffer(
-                            texture.buffers[0].as_ptr(),
-                            texture.size.width,
-                        )?;
+                        processor.update_input_from_buffer(texture.buffers[0].as_ptr(), stride)?;
 
                         None
                     }
                 };
 
-                processor.process(view)?;
+                // The area outside the output rect of the back buffer is filled with the
+                // background color.
+                let rotation = self.orientation.0;
+                let display_size = get_display_size(texture_size, self.display_size);
+                let (viewport, crop) = Viewport::fit(self.size, display_size, rotation, self.fit);
+                processor.set_output_rect(RECT {
+                    left: viewport.x as i32,
+                    top: viewport.y as i32,
+                    right: (viewport.x + viewport.width) as i32,
+                    bottom: (viewport.y + viewport.height) as i32,
+                });
+
+                // The input rect is in the texture space, which is before the rotation.
+                let [cx, cy] = match rotation {
+                    VideoRotation::Rotate90 | VideoRotation::Rotate270 => [crop[1], crop[0]],
+                    _ => crop,
+                };
+
+                let (width, height) = (
+                    (display_size.width as f32 * cx) as i32,
+                    (display_size.height as f32 * cy) as i32,
+                );
+
+                let (left, top) = (
+                    (display_size.width as i32 - width) / 2,
+                    (display_size.height as i32 - height) / 2,
+                );
+
+                processor.set_input_rect(RECT {
+                    right: left + width,
+                    bottom: top + height,
+                    left,
+                    top,
+                });
+
+                processor.process(view.clone())?;
+                self.input = Some(view);
             }
 
-            unsafe {
-                self.swap_chain.Present(0, DXGI_PRESENT(0)).ok()?;
+            if let Dx11Target::SwapChain(swap_chain, _) = &self.target {
+                unsafe {
+                    swap_chain.Present(0, DXGI_PRESENT(0)).ok()?;
+                }
             }
 
             Ok(())
         }
+
+        /// Read back the last rendered frame as it is displayed in the window,
+        /// the size of the image is the size of the swap chain.
+        ///
+        /// The content of the back buffer is discarded after presenting, so the
+        /// last frame is processed to the back buffer again without presenting,
+        /// and the back buffer is copied to the CPU.
+        pub fn snapshot(&mut self) -> Result<RgbaImage, Dx11GraphicsError> {
+            let (Some(processor), Some(input)) = (self.video_processor.as_mut(), &self.input)
+            else {
+                return Err(Dx11GraphicsError::NotFoundFrame);
+            };
+
+            if let Some(render_target_view) = &self.render_target_view {
+                unsafe {
+                    self.direct3d
+                        .context
+                        .ClearRenderTargetView(render_target_view, &[0.0, 0.0, 0.0, 1.0]);
+                }
+            }
+
+            processor.process(input.clone())?;
+
+            // The back buffer is RGBA, only the padding of the rows is dropped.
+            let buffer = processor.get_output_buffer()?;
+            let row = self.size.width as usize * 4;
+            let mut data = Vec::with_capacity(row * self.size.height as usize);
+            for i in 0..self.size.height as usize {
+                data.extend_from_slice(unsafe {
+                    std::slice::from_raw_parts(buffer.buffer().add(i * buffer.stride()), row)
+                });
+            }
+
+            Ok(RgbaImage {
+                size: self.size,
+                data,
+            })
+        }
+    }
+
+    fn create_swap_chain(
+        direct3d: &Direct3DDevice,
+        window: HWND,
+        size: Size,
+    ) -> Result<IDXGISwapChain, Dx11GraphicsError> {
+        unsafe {
+            let dxgi_factory = CreateDXGIFactory::<IDXGIFactory>()?;
+
+            let mut desc = DXGI_SWAP_CHAIN_DESC::default();
+            desc.BufferCount = 1;
+            desc.BufferDesc.Width = size.width;
+            desc.BufferDesc.Height = size.height;
+            desc.BufferDesc.Format = DXGI_FORMAT_R8G8B8A8_UNORM;
+            desc.BufferUsage = DXGI_USAGE_RENDER_TARGET_OUTPUT;
+            desc.OutputWindow = window;
+            desc.SampleDesc.Count = 1;
+            desc.Windowed = true.into();
+
+            let mut swap_chain = None;
+            dxgi_factory
+                .CreateSwapChain(&direct3d.device, &desc, &mut swap_chain)
+                .ok()?;
+
+            Ok(swap_chain.unwrap())
+        }
+    }
+
+    fn create_render_target_view(
+        direct3d: &Direct3DDevice,
+        swap_chain: &IDXGISwapChain,
+        size: Size,
+    ) -> Result<ID3D11RenderTargetView, Dx11GraphicsError> {
+        let back_buffer = unsafe { swap_chain.GetBuffer::<ID3D11Texture2D>(0)? };
+        let render_target_view = unsafe {
+            let mut render_target_view = None;
+            direct3d.device.CreateRenderTargetView(
+                &back_buffer,
+                None,
+                Some(&mut render_target_view),
+            )?;
+
+            render_target_view.unwrap()
+        };
+
+        unsafe {
+            direct3d
+                .context
+                .OMSetRenderTargets(Some(&[Some(render_target_view.clone())]), None);
+        }
+
+        unsafe {
+            let mut vp = D3D11_VIEWPORT::default();
+            vp.Width = size.width as f32;
+            vp.Height = size.height as f32;
+            vp.MinDepth = 0.0;
+            vp.MaxDepth = 1.0;
+
+            direct3d.context.RSSetViewports(Some(&[vp]));
+        }
+
+        Ok(render_target_view)
     }
 }
@@ -0,0 +1,316 @@
+use std::sync::Arc;
+
+use crate::{
+    texture::{Texture2DSource, Texture2DSourceOptions},
+    DeviceContext, FitMode, GraphicsError, ScaleFilter, SurfaceContext, Texture, Vertex, Viewport,
+};
+
+use hylarana_common::{frame::VideoRotation, AdapterPreference, Size};
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::Direct3DDevice;
+
+use wgpu::{
+    BindGroup, Buffer, Color, CommandEncoderDescriptor, Device, IndexFormat, LoadOp, Operations,
+    Queue, RenderPassColorAttachment, RenderPassDescriptor, StoreOp, Surface, SurfaceConfiguration,
+    SurfaceError, SurfaceTarget, TextureViewDescriptor,
+};
+
+/// The number of columns and rows of the mosaic, the tiles are placed from
+/// left to right and top to bottom.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MosaicGrid {
+    pub columns: u32,
+    pub rows: u32,
+}
+
+impl MosaicGrid {
+    /// The smallest square grid that holds the number of tiles.
+    pub fn with_count(count: usize) -> Self {
+        let columns = (count.max(1) as f64).sqrt().ceil() as u32;
+        Self {
+            rows: (count.max(1) as u32).div_ceil(columns),
+            columns,
+        }
+    }
+
+    fn capacity(&self) -> usize {
+        (self.columns * self.rows) as usize
+    }
+}
+
+#[derive(Debug)]
+pub struct MosaicRendererOptions<T> {
+    #[cfg(target_os = "windows")]
+    pub direct3d: Direct3DDevice,
+    pub window: T,
+    /// The size of the window in physical pixels.
+    pub size: Size,
+    pub grid: MosaicGrid,
+    pub filter: ScaleFilter,
+    pub adapter: AdapterPreference,
+}
+
+struct Tile {
+    source: Texture2DSource,
+    bind_group: Option<BindGroup>,
+    orientation: (VideoRotation, bool),
+    size: Size,
+}
+
+/// Mosaic Renderer.
+///
+/// Renders multiple video streams tiled in one window, each stream is a tile
+/// identified by its index in the grid. A tile can be focused to fill the
+/// whole window, such as when watching one of the streams closely.
+///
+/// The whole window is redrawn when any tile is submitted, the other tiles
+/// reuse their last texture.
+pub struct MosaicRenderer<'a> {
+    #[cfg(target_os = "windows")]
+    direct3d: Direct3DDevice,
+    surface: Surface<'a>,
+    config: SurfaceConfiguration,
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    vertex_buffer: Buffer,
+    index_buffer: Buffer,
+    filter: ScaleFilter,
+    grid: MosaicGrid,
+    focus: Option<usize>,
+    tiles: Vec<Option<Tile>>,
+}
+
+impl<'a> MosaicRenderer<'a> {
+    pub fn new<T: Into<SurfaceTarget<'a>>>(
+        options: MosaicRendererOptions<T>,
+    ) -> Result<Self, GraphicsError> {
+        let SurfaceContext {
+            surface,
+            config,
+            context:
+                DeviceContext {
+                    device,
+                    queue,
+                    vertex_buffer,
+                    index_buffer,
+                },
+        } = SurfaceContext::new(options.window, options.size, options.adapter)?;
+
+        Ok(Self {
+            #[cfg(target_os = "windows")]
+            direct3d: options.direct3d,
+            filter: options.filter,
+            grid: options.grid,
+            tiles: Vec::new(),
+            focus: None,
+            vertex_buffer,
+            index_buffer,
+            surface,
+            config,
+            device,
+            queue,
+        })
+    }
+
+    /// Reconfigure the surface when the size of the window changes, the size
+    /// is in physical pixels.
+    pub fn resize(&mut self, size: Size) -> Result<(), GraphicsError> {
+        if size.width == 0 || size.height == 0 {
+            return Ok(());
+        }
+
+        if self.config.width != size.width || self.config.height != size.height {
+            self.config.width = size.width;
+            self.config.height = size.height;
+            self.surface.configure(&self.device, &self.config);
+        }
+
+        self.draw()
+    }
+
+    /// Change the grid, the tiles that do not fit in the grid are not drawn.
+    pub fn set_grid(&mut self, grid: MosaicGrid) -> Result<(), GraphicsError> {
+        self.grid = grid;
+        self.draw()
+    }
+
+    /// Let the tile fill the whole window, or go back to the grid with `None`.
+    pub fn set_focus(&mut self, focus: Option<usize>) -> Result<(), GraphicsError> {
+        self.focus = focus;
+        self.draw()
+    }
+
+    pub fn set_orientation(&mut self, index: usize, rotation: VideoRotation, mirror: bool) {
+        if let Some(Some(tile)) = self.tiles.get_mut(index) {
+            tile.orientation = (rotation, mirror);
+        }
+    }
+
+    /// Submit the texture of the tile and redraw the window.
+    pub fn submit(&mut self, index: usize, texture: Texture) -> Result<(), GraphicsError> {
+        if self.tiles.len() <= index {
+            self.tiles.resize_with(index + 1, || None);
+        }
+
+        // The tile is created when its first texture arrives.
+        if self.tiles[index].is_none() {
+            self.tiles[index] = Some(Tile {
+                source: Texture2DSource::new(Texture2DSourceOptions {
+                    #[cfg(target_os = "windows")]
+                    direct3d: self.direct3d.clone(),
+                    device: self.device.clone(),
+                    queue: self.queue.clone(),
+                    filter: self.filter,
+                })?,
+                orientation: (VideoRotation::Rotate0, false),
+                bind_group: None,
+                size: texture.size(),
+            });
+        }
+
+        if let Some(tile) = self.tiles[index].as_mut() {
+            tile.size = texture.size();
+            tile.bind_group = tile
+                .source
+                .get_view(texture)?
+                .map(|(_, bind_group, _)| bind_group);
+        }
+
+        self.draw()
+    }
+
+    /// Remove the tile, such as when the stream is closed, the area of the
+    /// tile is left black.
+    pub fn clear(&mut self, index: usize) -> Result<(), GraphicsError> {
+        if let Some(tile) = self.tiles.get_mut(index) {
+            drop(tile.take());
+        }
+
+        self.draw()
+    }
+
+    fn draw(&mut self) -> Result<(), GraphicsError> {
+        let surface = Size {
+            width: self.config.width,
+            height: self.config.height,
+        };
+
+        // Calculate the area of every visible tile first, the transform of the tile
+        // needs to be updated before the render pass borrows the tiles.
+        let mut viewports = Vec::with_capacity(self.tiles.len());
+        for (index, tile) in self.tiles.iter_mut().enumerate() {
+            let Some(tile) = tile else {
+                continue;
+            };
+
+            let cell = match self.focus {
+                Some(focus) if focus == index => Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: surface.width as f32,
+                    height: surface.height as f32,
+                },
+                Some(_) => continue,
+                None => {
+                    if index >= self.grid.capacity() {
+                        continue;
+                    }
+
+                    let width = surface.width as f32 / self.grid.columns as f32;
+                    let height = surface.height as f32 / self.grid.rows as f32;
+                    Viewport {
+                        x: (index as u32 % self.grid.columns) as f32 * width,
+                        y: (index as u32 / self.grid.columns) as f32 * height,
+                        width,
+                        height,
+                    }
+                }
+            };
+
+            let (rotation, mirror) = tile.orientation;
+            let (viewport, crop) = Viewport::fit(
+                Size {
+                    width: cell.width as u32,
+                    height: cell.height as u32,
+                },
+                tile.size,
+                rotation,
+                FitMode::Contain,
+            );
+
+            tile.source
+                .set_transform(rotation, mirror, crop, [1.0, 1.0]);
+            viewports.push((
+                index,
+                Viewport {
+                    x: cell.x + viewport.x,
+                    y: cell.y + viewport.y,
+                    ..viewport
+                },
+            ));
+        }
+
+        let output = match self.surface.get_current_texture() {
+            Ok(output) => output,
+            Err(SurfaceError::Outdated | SurfaceError::Lost) => {
+                self.surface.configure(&self.device, &self.config);
+
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let view = output
+            .texture
+            .create_view(&TextureViewDescriptor::default());
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&CommandEncoderDescriptor { label: None });
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &view,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), IndexFormat::Uint16);
+
+            for (index, viewport) in viewports {
+                if let Some(tile) = &self.tiles[index] {
+                    if let (Some(pipeline), Some(bind_group)) =
+                        (tile.source.pipeline(), &tile.bind_group)
+                    {
+                        render_pass.set_viewport(
+                            viewport.x,
+                            viewport.y,
+                            viewport.width,
+                            viewport.height,
+                            0.0,
+                            1.0,
+                        );
+
+                        render_pass.set_pipeline(pipeline);
+                        render_pass.set_bind_group(0, Some(bind_group), &[]);
+                        render_pass.set_bind_group(1, Some(tile.source.transform()), &[]);
+                        render_pass.draw_indexed(0..Vertex::INDICES.len() as u32, 0, 0..1);
+                    }
+                }
+            }
+        }
+
+        self.queue.submit(Some(encoder.finish()));
+        output.present();
+
+        Ok(())
+    }
+}
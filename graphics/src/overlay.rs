@@ -0,0 +1,305 @@
+use std::sync::Arc;
+
+use crate::{interop::InteropError, FromNativeResourceError, Texture2DResource, Vertex};
+
+#[cfg(target_os = "windows")]
+use crate::interop::win32::Interop;
+
+use bytemuck::{Pod, Zeroable};
+use hylarana_common::Size;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::Direct3DDevice;
+
+use wgpu::{
+    include_wgsl,
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState,
+    Buffer, BufferBindingType, BufferUsages, ColorTargetState, ColorWrites, Device, Extent3d,
+    FilterMode, FragmentState, ImageCopyTexture, ImageDataLayout, IndexFormat, MultisampleState,
+    Origin3d, PipelineCompilationOptions, PipelineLayoutDescriptor, PrimitiveState,
+    PrimitiveTopology, Queue, RenderPass, RenderPipeline, RenderPipelineDescriptor, Sampler,
+    SamplerBindingType, SamplerDescriptor, ShaderStages, Texture, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureViewDescriptor,
+    TextureViewDimension, VertexState,
+};
+
+/// The area of the surface that the overlay is drawn to, in normalized
+/// coordinates of the surface, that is, `0.0` to `1.0` from the top left
+/// corner, so the overlay follows the surface when it is resized.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OverlayRect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Pod, Zeroable)]
+struct OverlayUniform {
+    rect: [f32; 4],
+    alpha: f32,
+    // Uniform buffers are aligned to 16 bytes.
+    padding: [f32; 3],
+}
+
+/// An RGBA layer composited over the video, such as a logo or connection
+/// stats, it is drawn in the same render pass as the video.
+pub(crate) struct Overlay {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    pipeline: RenderPipeline,
+    layout: BindGroupLayout,
+    sampler: Sampler,
+    buffer: Buffer,
+    uniform: OverlayUniform,
+    texture: Option<Texture>,
+    bind_group: Option<BindGroup>,
+    #[cfg(target_os = "windows")]
+    interop: Interop,
+}
+
+impl Overlay {
+    pub(crate) fn new(
+        device: Arc<Device>,
+        queue: Arc<Queue>,
+        #[cfg(target_os = "windows")] direct3d: Direct3DDevice,
+    ) -> Self {
+        let uniform = OverlayUniform {
+            rect: [0.0; 4],
+            alpha: 1.0,
+            padding: [0.0; 3],
+        };
+
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&uniform),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::VERTEX_FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mipmap_filter: FilterMode::Nearest,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let module = device.create_shader_module(include_wgsl!("./shaders/overlay.wgsl"));
+        let pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&device.create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: None,
+                bind_group_layouts: &[&layout],
+                push_constant_ranges: &[],
+            })),
+            vertex: VertexState {
+                entry_point: Some("vs_main"),
+                module: &module,
+                compilation_options: PipelineCompilationOptions::default(),
+                buffers: &[Vertex::desc()],
+            },
+            fragment: Some(FragmentState {
+                entry_point: Some("fs_main"),
+                module: &module,
+                compilation_options: PipelineCompilationOptions::default(),
+                targets: &[Some(ColorTargetState {
+                    blend: Some(BlendState::ALPHA_BLENDING),
+                    write_mask: ColorWrites::ALL,
+                    format: TextureFormat::Bgra8Unorm,
+                })],
+            }),
+            primitive: PrimitiveState {
+                topology: PrimitiveTopology::TriangleStrip,
+                strip_index_format: Some(IndexFormat::Uint16),
+                ..Default::default()
+            },
+            multisample: MultisampleState::default(),
+            depth_stencil: None,
+            multiview: None,
+            cache: None,
+        });
+
+        Self {
+            #[cfg(target_os = "windows")]
+            interop: Interop::new(device.clone(), direct3d),
+            bind_group: None,
+            texture: None,
+            uniform,
+            buffer,
+            sampler,
+            layout,
+            pipeline,
+            device,
+            queue,
+        }
+    }
+
+    #[cfg(target_os = "windows")]
+    pub(crate) fn set_direct3d(&mut self, direct3d: Direct3DDevice) {
+        self.interop = Interop::new(self.device.clone(), direct3d);
+    }
+
+    /// Update the overlay, the texture is RGBA, and a software texture is
+    /// copied to the internal texture.
+    pub(crate) fn update(
+        &mut self,
+        texture: &Texture2DResource,
+        rect: OverlayRect,
+        alpha: f32,
+    ) -> Result<(), FromNativeResourceError> {
+        let texture = match texture {
+            #[cfg(target_os = "windows")]
+            Texture2DResource::Texture(texture) => texture
+                .texture(&mut self.interop)?
+                .first()
+                .ok_or_else(|| InteropError::NotSupportTextureFormat)?,
+            // Only the Direct3D11 textures are imported for the overlay.
+            #[cfg(not(target_os = "windows"))]
+            Texture2DResource::Texture(_) => {
+                return Err(InteropError::NotSupportTextureFormat.into())
+            }
+            Texture2DResource::Buffer(buffer) => {
+                if buffer.buffers.is_empty() {
+                    return Err(InteropError::NotSupportTextureFormat.into());
+                }
+
+                let size = buffer.size;
+                if self.texture.as_ref().map(|it| it.size())
+                    != Some(Extent3d {
+                        width: size.width,
+                        height: size.height,
+                        depth_or_array_layers: 1,
+                    })
+                {
+                    self.texture = Some(self.create_texture(size));
+                }
+
+                let texture = self.texture.as_ref().unwrap();
+                self.queue.write_texture(
+                    ImageCopyTexture {
+                        aspect: TextureAspect::All,
+                        texture,
+                        mip_level: 0,
+                        origin: Origin3d::ZERO,
+                    },
+                    buffer.buffers[0],
+                    ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(size.width * 4),
+                        rows_per_image: Some(size.height),
+                    },
+                    texture.size(),
+                );
+
+                texture
+            }
+        };
+
+        let view = texture.create_view(&TextureViewDescriptor {
+            dimension: Some(TextureViewDimension::D2),
+            format: Some(TextureFormat::Rgba8Unorm),
+            ..Default::default()
+        });
+
+        self.bind_group = Some(self.device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &self.layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: BindingResource::TextureView(&view),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: BindingResource::Sampler(&self.sampler),
+                },
+                BindGroupEntry {
+                    binding: 2,
+                    resource: self.buffer.as_entire_binding(),
+                },
+            ],
+        }));
+
+        let uniform = OverlayUniform {
+            rect: [rect.x, rect.y, rect.width, rect.height],
+            alpha: alpha.clamp(0.0, 1.0),
+            padding: [0.0; 3],
+        };
+
+        if uniform != self.uniform {
+            self.queue
+                .write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+            self.uniform = uniform;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.bind_group = None;
+    }
+
+    /// Draw the overlay in the render pass, the vertex and index buffers of the
+    /// video are reused, so they need to be set before this call.
+    pub(crate) fn draw(&self, render_pass: &mut RenderPass) {
+        if let Some(bind_group) = &self.bind_group {
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, Some(bind_group), &[]);
+            render_pass.draw_indexed(0..Vertex::INDICES.len() as u32, 0, 0..1);
+        }
+    }
+
+    fn create_texture(&self, size: Size) -> Texture {
+        self.device.create_texture(&TextureDescriptor {
+            label: None,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: TextureDimension::D2,
+            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+            view_formats: &[],
+            size: Extent3d {
+                depth_or_array_layers: 1,
+                width: size.width,
+                height: size.height,
+            },
+            format: TextureFormat::Rgba8Unorm,
+        })
+    }
+}
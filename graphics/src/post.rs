@@ -0,0 +1,388 @@
+use std::{borrow::Cow, sync::Arc};
+
+use crate::{GraphicsError, Vertex};
+
+use bytemuck::{Pod, Zeroable};
+use hylarana_common::Size;
+use pollster::FutureExt;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
+    BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, Buffer,
+    BufferBindingType, BufferUsages, Color, ColorTargetState, ColorWrites, CommandEncoder, Device,
+    ErrorFilter, Extent3d, FilterMode, FragmentState, IndexFormat, LoadOp, MultisampleState,
+    Operations, PipelineCompilationOptions, PipelineLayout, PipelineLayoutDescriptor,
+    PrimitiveState, PrimitiveTopology, Queue, RenderPass, RenderPassColorAttachment,
+    RenderPassDescriptor, RenderPipeline, RenderPipelineDescriptor, Sampler, SamplerBindingType,
+    SamplerDescriptor, ShaderModuleDescriptor, ShaderSource, ShaderStages, StoreOp, Texture,
+    TextureDescriptor, TextureDimension, TextureFormat, TextureSampleType, TextureUsages,
+    TextureView, TextureViewDescriptor, TextureViewDimension, VertexState,
+};
+
+const COMMON_SHADER: &str = include_str!("./shaders/post/common.wgsl");
+
+/// A post-process pass that is applied to the video after it is converted to
+/// RGB and scaled to the surface, before the overlay is composited.
+///
+/// The source is a WGSL fragment shader with the entry point `fs_main`, which
+/// receives the texture coordinates at `@location(0)` and returns the color.
+/// The following are declared for the shader:
+///
+/// * `source` and `source_sampler`, the output of the previous pass.
+/// * `post.size` and `post.texel`, the size of the source in pixels and the
+///   size of a pixel in texture coordinates.
+/// * `post.params`, a `vec4<f32>` of parameters that can be updated without
+///   recompiling the shader.
+/// * `sample_offset(coords, x, y)`, samples the source at an offset in pixels.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PostProcessShader {
+    pub source: Cow<'static, str>,
+    pub params: [f32; 4],
+}
+
+impl PostProcessShader {
+    pub fn new<T: Into<Cow<'static, str>>>(source: T, params: [f32; 4]) -> Self {
+        Self {
+            source: source.into(),
+            params,
+        }
+    }
+
+    /// Unsharp mask, the strength is usually between `0.0` and `1.0`, such as
+    /// `0.3` for mild sharpening of upscaled streams.
+    pub fn sharpen(strength: f32) -> Self {
+        Self::new(
+            include_str!("./shaders/post/sharpen.wgsl"),
+            [strength, 0.0, 0.0, 0.0],
+        )
+    }
+
+    /// The brightness is an offset added to the color, `0.0` keeps it, the
+    /// contrast and saturation are factors, `1.0` keeps them.
+    pub fn color(brightness: f32, contrast: f32, saturation: f32) -> Self {
+        Self::new(
+            include_str!("./shaders/post/color.wgsl"),
+            [brightness, contrast, saturation, 0.0],
+        )
+    }
+
+    /// Blends the neighbouring lines to hide the combing of interlaced video,
+    /// this works best when the video is not scaled vertically.
+    pub fn deinterlace() -> Self {
+        Self::new(include_str!("./shaders/post/deinterlace.wgsl"), [0.0; 4])
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Pod, Zeroable)]
+struct PostProcessUniform {
+    size: [f32; 2],
+    texel: [f32; 2],
+    params: [f32; 4],
+}
+
+impl PostProcessUniform {
+    fn new(size: Size, params: [f32; 4]) -> Self {
+        let (width, height) = (size.width.max(1) as f32, size.height.max(1) as f32);
+
+        Self {
+            size: [width, height],
+            texel: [1.0 / width, 1.0 / height],
+            params,
+        }
+    }
+}
+
+struct PostProcessPass {
+    pipeline: RenderPipeline,
+    buffer: Buffer,
+    uniform: PostProcessUniform,
+    // One bind group for each of the intermediate textures.
+    bind_groups: Vec<BindGroup>,
+}
+
+/// The chain of post-process passes, the video is rendered to an intermediate
+/// texture, and each pass reads the output of the previous one, the last pass
+/// renders to the target.
+pub(crate) struct PostProcess {
+    device: Arc<Device>,
+    queue: Arc<Queue>,
+    layout: BindGroupLayout,
+    pipeline_layout: PipelineLayout,
+    sampler: Sampler,
+    size: Size,
+    passes: Vec<PostProcessPass>,
+    // Two textures are enough, the passes render to them in turn.
+    textures: Vec<(Texture, TextureView)>,
+}
+
+impl PostProcess {
+    pub(crate) fn new(device: Arc<Device>, queue: Arc<Queue>, size: Size) -> Self {
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&layout],
+            push_constant_ranges: &[],
+        });
+
+        let sampler = device.create_sampler(&SamplerDescriptor {
+            address_mode_u: AddressMode::ClampToEdge,
+            address_mode_v: AddressMode::ClampToEdge,
+            address_mode_w: AddressMode::ClampToEdge,
+            mipmap_filter: FilterMode::Nearest,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        Self {
+            textures: Vec::new(),
+            passes: Vec::new(),
+            pipeline_layout,
+            sampler,
+            layout,
+            device,
+            queue,
+            size,
+        }
+    }
+
+    /// Replace the passes, the shaders are compiled here, if any of them fails
+    /// to compile, the current passes are kept.
+    pub(crate) fn set(&mut self, shaders: &[PostProcessShader]) -> Result<(), GraphicsError> {
+        let mut pipelines = Vec::with_capacity(shaders.len());
+        for shader in shaders {
+            pipelines.push((self.create_pipeline(&shader.source)?, shader.params));
+        }
+
+        self.passes = pipelines
+            .into_iter()
+            .map(|(pipeline, params)| {
+                let uniform = PostProcessUniform::new(self.size, params);
+
+                PostProcessPass {
+                    buffer: self.device.create_buffer_init(&BufferInitDescriptor {
+                        label: None,
+                        contents: bytemuck::bytes_of(&uniform),
+                        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+                    }),
+                    bind_groups: Vec::new(),
+                    pipeline,
+                    uniform,
+                }
+            })
+            .collect();
+
+        self.textures.clear();
+        self.resize(self.size);
+        Ok(())
+    }
+
+    /// Update the parameters of a pass, an index out of range is ignored.
+    pub(crate) fn set_params(&mut self, index: usize, params: [f32; 4]) {
+        if let Some(pass) = self.passes.get_mut(index) {
+            let uniform = PostProcessUniform::new(self.size, params);
+            if uniform != pass.uniform {
+                self.queue
+                    .write_buffer(&pass.buffer, 0, bytemuck::bytes_of(&uniform));
+                pass.uniform = uniform;
+            }
+        }
+    }
+
+    /// Recreate the intermediate textures for the size of the target.
+    pub(crate) fn resize(&mut self, size: Size) {
+        if self.passes.is_empty() {
+            self.textures.clear();
+            self.size = size;
+
+            return;
+        }
+
+        if size == self.size && !self.textures.is_empty() {
+            return;
+        }
+
+        // A single pass renders to the target directly, so it only needs one
+        // texture for the video.
+        let count = self.passes.len().min(2);
+        self.textures = (0..count)
+            .map(|_| {
+                let texture = self.device.create_texture(&TextureDescriptor {
+                    label: None,
+                    mip_level_count: 1,
+                    sample_count: 1,
+                    dimension: TextureDimension::D2,
+                    format: TextureFormat::Bgra8Unorm,
+                    usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+                    view_formats: &[],
+                    size: Extent3d {
+                        width: size.width,
+                        height: size.height,
+                        depth_or_array_layers: 1,
+                    },
+                });
+
+                let view = texture.create_view(&TextureViewDescriptor::default());
+                (texture, view)
+            })
+            .collect();
+
+        for pass in &mut self.passes {
+            let uniform = PostProcessUniform::new(size, pass.uniform.params);
+            if uniform != pass.uniform {
+                self.queue
+                    .write_buffer(&pass.buffer, 0, bytemuck::bytes_of(&uniform));
+                pass.uniform = uniform;
+            }
+
+            pass.bind_groups = self
+                .textures
+                .iter()
+                .map(|(_, view)| {
+                    self.device.create_bind_group(&BindGroupDescriptor {
+                        label: None,
+                        layout: &self.layout,
+                        entries: &[
+                            BindGroupEntry {
+                                binding: 0,
+                                resource: BindingResource::TextureView(view),
+                            },
+                            BindGroupEntry {
+                                binding: 1,
+                                resource: BindingResource::Sampler(&self.sampler),
+                            },
+                            BindGroupEntry {
+                                binding: 2,
+                                resource: pass.buffer.as_entire_binding(),
+                            },
+                        ],
+                    })
+                })
+                .collect();
+        }
+
+        self.size = size;
+    }
+
+    /// The view that the video is rendered to when there are passes.
+    pub(crate) fn input(&self) -> Option<&TextureView> {
+        self.textures.first().map(|(_, view)| view)
+    }
+
+    /// Encode all passes except the last one, which is drawn into the render
+    /// pass of the target through `draw_last`, so that the overlay can be
+    /// drawn in the same render pass.
+    pub(crate) fn encode(&self, encoder: &mut CommandEncoder) {
+        let count = self.passes.len().saturating_sub(1);
+        for (index, pass) in self.passes[..count].iter().enumerate() {
+            let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                color_attachments: &[Some(RenderPassColorAttachment {
+                    view: &self.textures[(index + 1) % 2].1,
+                    resolve_target: None,
+                    ops: Operations {
+                        load: LoadOp::Clear(Color::BLACK),
+                        store: StoreOp::Store,
+                    },
+                })],
+                ..Default::default()
+            });
+
+            self.draw(&mut render_pass, pass, index);
+        }
+    }
+
+    /// Draw the last pass in the render pass of the target, the vertex and
+    /// index buffers of the video need to be set before this call.
+    pub(crate) fn draw_last(&self, render_pass: &mut RenderPass) {
+        if let Some(pass) = self.passes.last() {
+            self.draw(render_pass, pass, self.passes.len() - 1);
+        }
+    }
+
+    fn draw(&self, render_pass: &mut RenderPass, pass: &PostProcessPass, index: usize) {
+        render_pass.set_pipeline(&pass.pipeline);
+        render_pass.set_bind_group(0, Some(&pass.bind_groups[index % 2]), &[]);
+        render_pass.draw_indexed(0..Vertex::INDICES.len() as u32, 0, 0..1);
+    }
+
+    fn create_pipeline(&self, source: &str) -> Result<RenderPipeline, GraphicsError> {
+        // Shaders from the users may be invalid, catch the validation errors instead
+        // of letting the device panic.
+        self.device.push_error_scope(ErrorFilter::Validation);
+
+        let module = self.device.create_shader_module(ShaderModuleDescriptor {
+            label: None,
+            source: ShaderSource::Wgsl(format!("{}\n{}", COMMON_SHADER, source).into()),
+        });
+
+        let pipeline = self
+            .device
+            .create_render_pipeline(&RenderPipelineDescriptor {
+                label: None,
+                layout: Some(&self.pipeline_layout),
+                vertex: VertexState {
+                    entry_point: Some("vs_main"),
+                    module: &module,
+                    compilation_options: PipelineCompilationOptions::default(),
+                    buffers: &[Vertex::desc()],
+                },
+                fragment: Some(FragmentState {
+                    entry_point: Some("fs_main"),
+                    module: &module,
+                    compilation_options: PipelineCompilationOptions::default(),
+                    targets: &[Some(ColorTargetState {
+                        blend: None,
+                        write_mask: ColorWrites::ALL,
+                        format: TextureFormat::Bgra8Unorm,
+                    })],
+                }),
+                primitive: PrimitiveState {
+                    topology: PrimitiveTopology::TriangleStrip,
+                    strip_index_format: Some(IndexFormat::Uint16),
+                    ..Default::default()
+                },
+                multisample: MultisampleState::default(),
+                depth_stencil: None,
+                multiview: None,
+                cache: None,
+            });
+
+        if let Some(e) = self.device.pop_error_scope().block_on() {
+            return Err(GraphicsError::PostProcessError(e.to_string()));
+        }
+
+        Ok(pipeline)
+    }
+}
@@ -3,9 +3,9 @@
 @group(0) @binding(2) var sampler_: sampler;
 
 @fragment fn main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
-    let y = textureSample(y_texture, sampler_, coords).r;
-    let u = textureSample(uv_texture, sampler_, coords).r - 0.5;
-    let v = textureSample(uv_texture, sampler_, coords).g - 0.5;
+    let y = sample_texture(y_texture, sampler_, coords).r;
+    let u = sample_texture(uv_texture, sampler_, coords).r - 0.5;
+    let v = sample_texture(uv_texture, sampler_, coords).g - 0.5;
 
     let r = y + 1.5748 * v;
     let g = y - 0.187324 * u - 0.468124 * v;
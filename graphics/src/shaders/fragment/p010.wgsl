@@ -0,0 +1,51 @@
+@group(0) @binding(0) var y_texture: texture_2d<f32>;
+@group(0) @binding(1) var uv_texture: texture_2d<f32>;
+@group(0) @binding(2) var sampler_: sampler;
+
+// The brightness of the SDR reference white, in nits (ITU-R BT.2408).
+const REFERENCE_WHITE: f32 = 203.0;
+
+// The peak brightness of the content mapped to the SDR white.
+const PEAK_WHITE: f32 = 1000.0;
+
+// SMPTE ST 2084 (PQ) EOTF, returns the linear light normalized to 10000 nits.
+fn pq_eotf(value: vec3<f32>) -> vec3<f32> {
+    let m1 = 0.1593017578125;
+    let m2 = 78.84375;
+    let c1 = 0.8359375;
+    let c2 = 18.8515625;
+    let c3 = 18.6875;
+
+    let e = pow(max(value, vec3<f32>(0.0)), vec3<f32>(1.0 / m2));
+    return pow(max(e - c1, vec3<f32>(0.0)) / (c2 - c3 * e), vec3<f32>(1.0 / m1));
+}
+
+@fragment fn main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
+    // The samples are in the high 10 bits, convert to the 10-bit code values and
+    // expand the limited range.
+    let scale = 65535.0 / 64.0;
+    let y = (sample_texture(y_texture, sampler_, coords).r * scale - 64.0) / 876.0;
+    let u = (sample_texture(uv_texture, sampler_, coords).r * scale - 512.0) / 896.0;
+    let v = (sample_texture(uv_texture, sampler_, coords).g * scale - 512.0) / 896.0;
+
+    // BT.2020 non-constant luminance.
+    let pq = vec3<f32>(
+        y + 1.4746 * v,
+        y - 0.164553 * u - 0.571353 * v,
+        y + 1.8814 * u,
+    );
+
+    // Convert to linear light relative to the reference white, then convert the
+    // BT.2020 primaries to BT.709 primaries.
+    let linear = pq_eotf(pq) * (10000.0 / REFERENCE_WHITE);
+    let bt709 = max(vec3<f32>(
+        1.6605 * linear.r - 0.5876 * linear.g - 0.0728 * linear.b,
+        -0.1246 * linear.r + 1.1329 * linear.g - 0.0083 * linear.b,
+        -0.0182 * linear.r - 0.1006 * linear.g + 1.1187 * linear.b,
+    ), vec3<f32>(0.0));
+
+    // Extended reinhard tone mapping, and encode with the display gamma.
+    let white = PEAK_WHITE / REFERENCE_WHITE;
+    let mapped = bt709 * (1.0 + bt709 / (white * white)) / (1.0 + bt709);
+    return vec4<f32>(pow(mapped, vec3<f32>(1.0 / 2.2)), 1.0);
+}
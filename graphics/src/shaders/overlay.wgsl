@@ -0,0 +1,30 @@
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) coords: vec2<f32>,
+};
+
+struct Overlay {
+    // The x, y, width and height in the normalized coordinates of the surface,
+    // the origin is the top left corner.
+    rect: vec4<f32>,
+    alpha: f32,
+};
+
+@group(0) @binding(0) var texture_: texture_2d<f32>;
+@group(0) @binding(1) var sampler_: sampler;
+@group(0) @binding(2) var<uniform> overlay: Overlay;
+
+@vertex fn vs_main(@location(0) position: vec2<f32>, @location(1) coords: vec2<f32>) -> VertexOutput {
+    let uv = vec2<f32>(coords.x, 1.0 - coords.y);
+    let point = overlay.rect.xy + uv * overlay.rect.zw;
+
+    var output: VertexOutput;
+    output.position = vec4<f32>(point.x * 2.0 - 1.0, 1.0 - point.y * 2.0, 0.0, 1.0);
+    output.coords = uv;
+    return output;
+}
+
+@fragment fn fs_main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
+    let color = textureSample(texture_, sampler_, coords);
+    return vec4<f32>(color.rgb, color.a * overlay.alpha);
+}
@@ -0,0 +1,12 @@
+// params.x is the brightness offset, params.y is the contrast factor and
+// params.z is the saturation factor.
+
+@fragment fn fs_main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
+    let color = sample_offset(coords, 0.0, 0.0);
+
+    var rgb = (color.rgb - 0.5) * post.params.y + 0.5 + post.params.x;
+    let luma = dot(rgb, vec3<f32>(0.2126, 0.7152, 0.0722));
+    rgb = mix(vec3<f32>(luma), rgb, post.params.z);
+
+    return vec4<f32>(clamp(rgb, vec3<f32>(0.0), vec3<f32>(1.0)), color.a);
+}
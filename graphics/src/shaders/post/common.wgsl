@@ -0,0 +1,31 @@
+// Prepended to the source of every post-process shader, the shader only needs
+// to provide the fragment entry point `fs_main`.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) coords: vec2<f32>,
+};
+
+struct PostProcess {
+    // The size of the source texture in pixels.
+    size: vec2<f32>,
+    // The size of a pixel in texture coordinates.
+    texel: vec2<f32>,
+    // The parameters of the shader, their meaning is up to the shader.
+    params: vec4<f32>,
+};
+
+@group(0) @binding(0) var source: texture_2d<f32>;
+@group(0) @binding(1) var source_sampler: sampler;
+@group(0) @binding(2) var<uniform> post: PostProcess;
+
+@vertex fn vs_main(@location(0) position: vec2<f32>, @location(1) coords: vec2<f32>) -> VertexOutput {
+    var output: VertexOutput;
+    output.position = vec4<f32>(position, 0.0, 1.0);
+    output.coords = vec2<f32>(coords.x, 1.0 - coords.y);
+    return output;
+}
+
+fn sample_offset(coords: vec2<f32>, x: f32, y: f32) -> vec4<f32> {
+    return textureSample(source, source_sampler, coords + vec2<f32>(x, y) * post.texel);
+}
@@ -0,0 +1,10 @@
+// Blend deinterlacing, each line is averaged with its neighbours so that the
+// combing of the two fields is hidden, at the cost of some vertical detail.
+
+@fragment fn fs_main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
+    let color = sample_offset(coords, 0.0, 0.0) * 0.5
+        + sample_offset(coords, 0.0, -1.0) * 0.25
+        + sample_offset(coords, 0.0, 1.0) * 0.25;
+
+    return color;
+}
@@ -0,0 +1,12 @@
+// Unsharp mask over the cross shaped neighbourhood, params.x is the strength.
+
+@fragment fn fs_main(@location(0) coords: vec2<f32>) -> @location(0) vec4<f32> {
+    let center = sample_offset(coords, 0.0, 0.0);
+    let blur = (sample_offset(coords, -1.0, 0.0)
+        + sample_offset(coords, 1.0, 0.0)
+        + sample_offset(coords, 0.0, -1.0)
+        + sample_offset(coords, 0.0, 1.0)) * 0.25;
+
+    let color = center.rgb + (center.rgb - blur.rgb) * post.params.x;
+    return vec4<f32>(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)), center.a);
+}
@@ -0,0 +1,28 @@
+// Bicubic B-spline filtering, the 16 texels are folded into 4 bilinear
+// samples, so the sampler must be bilinear.
+fn sample_texture(texture: texture_2d<f32>, sampler_: sampler, coords: vec2<f32>) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(texture));
+    let texel = coords * size - 0.5;
+    let base = floor(texel);
+    let f = texel - base;
+
+    let f2 = f * f;
+    let f3 = f2 * f;
+    let w0 = (1.0 - 3.0 * f + 3.0 * f2 - f3) / 6.0;
+    let w1 = (4.0 - 6.0 * f2 + 3.0 * f3) / 6.0;
+    let w2 = (1.0 + 3.0 * f + 3.0 * f2 - 3.0 * f3) / 6.0;
+    let w3 = f3 / 6.0;
+
+    let s0 = w0 + w1;
+    let s1 = w2 + w3;
+    let c0 = (base - 0.5 + w1 / s0) / size;
+    let c1 = (base + 1.5 + w3 / s1) / size;
+
+    let a = textureSample(texture, sampler_, c0);
+    let b = textureSample(texture, sampler_, vec2<f32>(c1.x, c0.y));
+    let c = textureSample(texture, sampler_, vec2<f32>(c0.x, c1.y));
+    let d = textureSample(texture, sampler_, c1);
+
+    let s = s0 / (s0 + s1);
+    return mix(mix(d, c, s.x), mix(b, a, s.x), s.y);
+}
@@ -0,0 +1,5 @@
+// Sample the texture directly, the filtering is done by the sampler, which is
+// either nearest or bilinear.
+fn sample_texture(texture: texture_2d<f32>, sampler_: sampler, coords: vec2<f32>) -> vec4<f32> {
+    return textureSample(texture, sampler_, coords);
+}
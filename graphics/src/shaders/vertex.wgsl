@@ -3,9 +3,45 @@ struct VertexOutput {
     @location(0) coords: vec2<f32>,
 };
 
+struct Transform {
+    // Clockwise rotation in quarter turns.
+    rotation: u32,
+    // Whether to mirror the output horizontally.
+    mirror: u32,
+    // The visible fraction of the texture, centered.
+    crop: vec2<f32>,
+    // The fraction of the texture that has the picture, from the top left
+    // corner, the rest is padding.
+    display: vec2<f32>,
+};
+
+@group(1) @binding(0) var<uniform> transform: Transform;
+
 @vertex fn main(@location(0) position: vec2<f32>, @location(1) coords: vec2<f32>) -> VertexOutput {
     var output: VertexOutput;
     output.position = vec4<f32>(position, 0.0, 1.0);
-    output.coords = vec2<f32>(coords.x, 1.0 - coords.y);
+
+    // The quad fills the viewport whatever the rotation, the renderer fits the
+    // viewport to the rotated size of the picture.
+    var uv = vec2<f32>(coords.x, 1.0 - coords.y);
+    uv = 0.5 + (uv - 0.5) * transform.crop;
+    if (transform.mirror != 0u) {
+        uv.x = 1.0 - uv.x;
+    }
+
+    switch transform.rotation {
+        case 1u: {
+            uv = vec2<f32>(uv.y, 1.0 - uv.x);
+        }
+        case 2u: {
+            uv = vec2<f32>(1.0 - uv.x, 1.0 - uv.y);
+        }
+        case 3u: {
+            uv = vec2<f32>(1.0 - uv.y, uv.x);
+        }
+        default: {}
+    }
+
+    output.coords = uv * transform.display;
     return output;
 }
@@ -1,20 +1,24 @@
 mod bgra;
 mod i420;
 mod nv12;
+mod p010;
 mod rgba;
 
 use std::sync::Arc;
 
-use self::{bgra::Bgra, i420::I420, nv12::Nv12, rgba::Rgba};
-use crate::{interop::InteropError, Vertex};
+use self::{bgra::Bgra, i420::I420, nv12::Nv12, p010::P010, rgba::Rgba};
+use crate::{interop::InteropError, vertex::Transform, ScaleFilter, Vertex};
 
 #[cfg(target_os = "windows")]
 use crate::interop::win32::Interop;
 
-#[cfg(any(target_os = "linux", target_os = "macos"))]
-type Interop = ();
+#[cfg(target_os = "macos")]
+use crate::interop::macos::{pixel_buffer_size, Interop};
 
-use hylarana_common::Size;
+#[cfg(target_os = "linux")]
+use crate::interop::linux::Interop;
+
+use hylarana_common::{frame::VideoRotation, Size};
 use smallvec::SmallVec;
 use thiserror::Error;
 
@@ -23,6 +27,12 @@ use hylarana_common::win32::{
     windows::Win32::Graphics::Direct3D11::ID3D11Texture2D, Direct3DDevice, EasyTexture,
 };
 
+#[cfg(target_os = "macos")]
+use hylarana_common::macos::CVPixelBufferRef;
+
+#[cfg(target_os = "linux")]
+use hylarana_common::frame::{DmaBuf, VideoFormat};
+
 use wgpu::{
     include_wgsl, AddressMode, BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout,
     BindGroupLayoutDescriptor, BindGroupLayoutEntry, BindingResource, BindingType, BlendState,
@@ -30,9 +40,9 @@ use wgpu::{
     ImageDataLayout, IndexFormat, MultisampleState, Origin3d, PipelineCompilationOptions,
     PipelineLayoutDescriptor, PrimitiveState, PrimitiveTopology, Queue, RenderPipeline,
     RenderPipelineDescriptor, SamplerBindingType, SamplerDescriptor, ShaderModuleDescriptor,
-    ShaderStages, Texture as WGPUTexture, TextureAspect, TextureDescriptor, TextureDimension,
-    TextureFormat, TextureSampleType, TextureUsages, TextureView, TextureViewDescriptor,
-    TextureViewDimension, VertexState,
+    ShaderSource, ShaderStages, Texture as WGPUTexture, TextureAspect, TextureDescriptor,
+    TextureDimension, TextureFormat, TextureSampleType, TextureUsages, TextureView,
+    TextureViewDescriptor, TextureViewDimension, VertexState,
 };
 
 #[derive(Debug, Error)]
@@ -45,22 +55,54 @@ pub enum FromNativeResourceError {
 pub enum Texture2DRaw {
     #[cfg(target_os = "windows")]
     ID3D11Texture2D(ID3D11Texture2D, u32),
+    /// The NV12 or P010 pixel buffer of VideoToolbox, the planes of it are
+    /// imported into the Metal device of wgpu without being copied.
+    #[cfg(target_os = "macos")]
+    CVPixelBufferRef(CVPixelBufferRef),
+    /// The DMA-BUF planes of the VAAPI surfaces or of the PipeWire capture,
+    /// imported into the Vulkan device of wgpu without being copied.
+    #[cfg(target_os = "linux")]
+    DmaBuf(DmaBufTexture),
+}
+
+/// The planes of a frame that are exported as DMA-BUF file descriptors, the
+/// file descriptors are borrowed, they are not closed by the renderer.
+#[cfg(target_os = "linux")]
+#[derive(Debug)]
+pub struct DmaBufTexture {
+    pub format: VideoFormat,
+    pub size: Size,
+    pub linesize: [usize; 3],
+    pub planes: DmaBuf,
 }
 
 impl Texture2DRaw {
-    #[cfg(target_os = "windows")]
+    /// The textures of the planes are separate textures on Metal, on the
+    /// other backends the planes are the aspects of one texture.
     pub(crate) fn texture<'b>(
         &self,
         interop: &'b mut Interop,
-    ) -> Result<&'b WGPUTexture, FromNativeResourceError> {
+    ) -> Result<&'b [WGPUTexture], FromNativeResourceError> {
         Ok(match self {
-            Self::ID3D11Texture2D(dx11, index) => interop.from_hal(dx11, *index)?,
+            #[cfg(target_os = "windows")]
+            Self::ID3D11Texture2D(dx11, index) => {
+                std::slice::from_ref(interop.import(dx11, *index)?)
+            }
+            #[cfg(target_os = "macos")]
+            Self::CVPixelBufferRef(buffer) => interop.import(*buffer)?,
+            #[cfg(target_os = "linux")]
+            Self::DmaBuf(texture) => interop.import(
+                texture.format,
+                texture.size,
+                &texture.linesize,
+                &texture.planes,
+            )?,
         })
     }
 
-    #[cfg(target_os = "windows")]
     pub(crate) fn size(&self) -> Size {
         match self {
+            #[cfg(target_os = "windows")]
             Self::ID3D11Texture2D(dx11, _) => {
                 let desc = dx11.desc();
                 Size {
@@ -68,6 +110,10 @@ impl Texture2DRaw {
                     height: desc.Height,
                 }
             }
+            #[cfg(target_os = "macos")]
+            Self::CVPixelBufferRef(buffer) => pixel_buffer_size(*buffer),
+            #[cfg(target_os = "linux")]
+            Self::DmaBuf(texture) => texture.size,
         }
     }
 }
@@ -80,29 +126,25 @@ pub struct Texture2DBuffer<'a> {
 
 #[derive(Debug)]
 pub enum Texture2DResource<'a> {
-    #[cfg(target_os = "windows")]
     Texture(Texture2DRaw),
     Buffer(Texture2DBuffer<'a>),
 }
 
 impl<'a> Texture2DResource<'a> {
     /// Get the hardware texture, here does not deal with software texture, so
-    /// if it is software texture directly return None.
-    #[allow(unused_variables)]
+    /// if it is software texture directly return no textures.
     pub(crate) fn texture<'b>(
         &self,
         interop: &'b mut Interop,
-    ) -> Result<Option<&'b WGPUTexture>, FromNativeResourceError> {
+    ) -> Result<&'b [WGPUTexture], FromNativeResourceError> {
         Ok(match self {
-            #[cfg(target_os = "windows")]
-            Texture2DResource::Texture(texture) => Some(texture.texture(interop)?),
-            Texture2DResource::Buffer(_) => None,
+            Texture2DResource::Texture(texture) => texture.texture(interop)?,
+            Texture2DResource::Buffer(_) => &[],
         })
     }
 
     pub(crate) fn size(&self) -> Size {
         match self {
-            #[cfg(target_os = "windows")]
             Texture2DResource::Texture(texture) => texture.size(),
             Texture2DResource::Buffer(texture) => texture.size,
         }
@@ -115,26 +157,42 @@ pub enum Texture<'a> {
     Rgba(Texture2DResource<'a>),
     Nv12(Texture2DResource<'a>),
     I420(Texture2DBuffer<'a>),
+    /// 10-bit HDR10 texture, note that the hardware texture is only supported
+    /// for the pixel buffers of macOS.
+    P010(Texture2DResource<'a>),
 }
 
 impl<'a> Texture<'a> {
     pub(crate) fn texture<'b>(
         &self,
         interop: &'b mut Interop,
-    ) -> Result<Option<&'b WGPUTexture>, FromNativeResourceError> {
+    ) -> Result<&'b [WGPUTexture], FromNativeResourceError> {
         Ok(match self {
             Texture::Rgba(texture) | Texture::Bgra(texture) | Texture::Nv12(texture) => {
                 texture.texture(interop)?
             }
-            Texture::I420(_) => None,
+            Texture::I420(_) => &[],
+            // wgpu does not have a P010 texture format, so there is no way to create a
+            // view for the hardware texture. The planes of the pixel buffers of macOS are
+            // separate textures of 16 bits.
+            Texture::P010(texture) => match texture {
+                #[cfg(target_os = "macos")]
+                Texture2DResource::Texture(texture) => texture.texture(interop)?,
+                #[cfg(not(target_os = "macos"))]
+                Texture2DResource::Texture(_) => {
+                    return Err(InteropError::NotSupportTextureFormat.into())
+                }
+                Texture2DResource::Buffer(_) => &[],
+            },
         })
     }
 
     pub(crate) fn size(&self) -> Size {
         match self {
-            Texture::Rgba(texture) | Texture::Bgra(texture) | Texture::Nv12(texture) => {
-                texture.size()
-            }
+            Texture::Rgba(texture)
+            | Texture::Bgra(texture)
+            | Texture::Nv12(texture)
+            | Texture::P010(texture) => texture.size(),
             Texture::I420(texture) => texture.size,
         }
     }
@@ -143,9 +201,11 @@ impl<'a> Texture<'a> {
 trait Texture2DSample {
     fn create_texture_descriptor(size: Size) -> impl IntoIterator<Item = (Size, TextureFormat)>;
 
+    /// The textures are the hardware texture of the frame, there are none for
+    /// the software frames, whose data is written to the internal textures.
     fn views_descriptors<'a>(
         &'a self,
-        texture: Option<&'a WGPUTexture>,
+        textures: &'a [WGPUTexture],
     ) -> impl IntoIterator<Item = (&'a WGPUTexture, TextureFormat, TextureAspect)>;
 
     fn copy_buffer_descriptors<'a>(
@@ -185,7 +245,7 @@ trait Texture2DSample {
     /// PipelineLayoutOptions, which can be used to create a PipelineLayout.
     fn bind_group_layout(&self, device: &Device) -> BindGroupLayout {
         let mut entries: SmallVec<[BindGroupLayoutEntry; 5]> = SmallVec::with_capacity(5);
-        for (i, _) in self.views_descriptors(None).into_iter().enumerate() {
+        for (i, _) in self.views_descriptors(&[]).into_iter().enumerate() {
             entries.push(BindGroupLayoutEntry {
                 count: None,
                 binding: i as u32,
@@ -222,20 +282,27 @@ trait Texture2DSample {
         &self,
         device: &Device,
         layout: &BindGroupLayout,
-        texture: Option<&WGPUTexture>,
+        textures: &[WGPUTexture],
+        filter: ScaleFilter,
     ) -> BindGroup {
+        // Bicubic filtering is done in the shader on top of bilinear samples.
+        let filter = match filter {
+            ScaleFilter::Nearest => FilterMode::Nearest,
+            ScaleFilter::Bilinear | ScaleFilter::Bicubic => FilterMode::Linear,
+        };
+
         let sampler = device.create_sampler(&SamplerDescriptor {
             address_mode_u: AddressMode::ClampToEdge,
             address_mode_v: AddressMode::ClampToEdge,
             address_mode_w: AddressMode::ClampToEdge,
             mipmap_filter: FilterMode::Nearest,
-            mag_filter: FilterMode::Nearest,
-            min_filter: FilterMode::Nearest,
+            mag_filter: filter,
+            min_filter: filter,
             ..Default::default()
         });
 
         let mut views: SmallVec<[TextureView; 5]> = SmallVec::with_capacity(5);
-        for (texture, format, aspect) in self.views_descriptors(texture) {
+        for (texture, format, aspect) in self.views_descriptors(textures) {
             views.push(texture.create_view(&TextureViewDescriptor {
                 dimension: Some(TextureViewDimension::D2),
                 format: Some(format),
@@ -294,6 +361,7 @@ enum Texture2DSourceSample {
     Rgba(Rgba),
     Nv12(Nv12),
     I420(I420),
+    P010(P010),
 }
 
 impl Texture2DSourceSample {
@@ -303,23 +371,38 @@ impl Texture2DSourceSample {
             Texture::Rgba(_) => Self::Rgba(Rgba::new(device, size)),
             Texture::Nv12(_) => Self::Nv12(Nv12::new(device, size)),
             Texture::I420(_) => Self::I420(I420::new(device, size)),
+            Texture::P010(_) => Self::P010(P010::new(device, size)),
         }
     }
 
-    fn fragment(&self) -> ShaderModuleDescriptor {
-        match self {
-            Texture2DSourceSample::Rgba(_) => {
-                include_wgsl!("./shaders/fragment/any.wgsl")
+    /// The fragment shader is prefixed with the sampling function of the
+    /// filter, the shaders sample the textures through `sample_texture`.
+    fn fragment(&self, filter: ScaleFilter) -> ShaderModuleDescriptor<'static> {
+        let sample = match filter {
+            ScaleFilter::Nearest | ScaleFilter::Bilinear => {
+                include_str!("./shaders/sample/direct.wgsl")
             }
-            Texture2DSourceSample::Bgra(_) => {
-                include_wgsl!("./shaders/fragment/any.wgsl")
+            ScaleFilter::Bicubic => include_str!("./shaders/sample/bicubic.wgsl"),
+        };
+
+        let fragment = match self {
+            Texture2DSourceSample::Rgba(_) | Texture2DSourceSample::Bgra(_) => {
+                include_str!("./shaders/fragment/any.wgsl")
             }
             Texture2DSourceSample::Nv12(_) => {
-                include_wgsl!("./shaders/fragment/nv12.wgsl")
+                include_str!("./shaders/fragment/nv12.wgsl")
             }
             Texture2DSourceSample::I420(_) => {
-                include_wgsl!("./shaders/fragment/i420.wgsl")
+                include_str!("./shaders/fragment/i420.wgsl")
+            }
+            Texture2DSourceSample::P010(_) => {
+                include_str!("./shaders/fragment/p010.wgsl")
             }
+        };
+
+        ShaderModuleDescriptor {
+            label: None,
+            source: ShaderSource::Wgsl(format!("{}\n{}", sample, fragment).into()),
         }
     }
 
@@ -329,6 +412,7 @@ impl Texture2DSourceSample {
             Self::Rgba(texture) => texture.bind_group_layout(device),
             Self::Nv12(texture) => texture.bind_group_layout(device),
             Self::I420(texture) => texture.bind_group_layout(device),
+            Self::P010(texture) => texture.bind_group_layout(device),
         }
     }
 }
@@ -338,6 +422,7 @@ pub struct Texture2DSourceOptions {
     pub direct3d: Direct3DDevice,
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
+    pub filter: ScaleFilter,
 }
 
 pub struct Texture2DSource {
@@ -346,6 +431,8 @@ pub struct Texture2DSource {
     pipeline: Option<RenderPipeline>,
     sample: Option<Texture2DSourceSample>,
     bind_group_layout: Option<BindGroupLayout>,
+    transform: Transform,
+    filter: ScaleFilter,
     interop: Interop,
 }
 
@@ -354,10 +441,15 @@ impl Texture2DSource {
         #[cfg(target_os = "windows")]
         let interop = Interop::new(options.device.clone(), options.direct3d);
 
-        #[cfg(any(target_os = "linux", target_os = "macos"))]
-        let interop = ();
+        #[cfg(target_os = "macos")]
+        let interop = Interop::new(options.device.clone());
+
+        #[cfg(target_os = "linux")]
+        let interop = Interop::new(options.device.clone());
 
         Ok(Self {
+            transform: Transform::new(&options.device),
+            filter: options.filter,
             device: options.device,
             queue: options.queue,
             bind_group_layout: None,
@@ -367,6 +459,48 @@ impl Texture2DSource {
         })
     }
 
+    /// Copy the D3D11 textures with a new device, such as after the previous
+    /// device has been removed, the textures of the old device cannot be used
+    /// anymore.
+    #[cfg(target_os = "windows")]
+    pub fn set_direct3d(&mut self, direct3d: Direct3DDevice) {
+        self.interop = Interop::new(self.device.clone(), direct3d);
+    }
+
+    #[cfg(target_os = "windows")]
+    pub fn is_device_removed(&self) -> bool {
+        self.interop.direct3d().is_removed()
+    }
+
+    /// Set the rotation, mirroring and the visible fraction of the texture
+    /// when it is drawn, the transform is done in the vertex shader. The
+    /// display is the fraction of the texture from the top left corner that
+    /// has the picture, `[1.0, 1.0]` if the texture has no padding.
+    ///
+    /// The quad fills the viewport, the rotation does not change its size, so
+    /// for a quarter turn the viewport has to have the aspect ratio of the
+    /// rotated picture, see `Viewport::fit`.
+    pub fn set_transform(
+        &mut self,
+        rotation: VideoRotation,
+        mirror: bool,
+        crop: [f32; 2],
+        display: [f32; 2],
+    ) {
+        self.transform
+            .update(&self.queue, rotation, mirror, crop, display);
+    }
+
+    /// The pipeline of the texture, it is only available after the first
+    /// texture has been received.
+    pub fn pipeline(&self) -> Option<&RenderPipeline> {
+        self.pipeline.as_ref()
+    }
+
+    pub fn transform(&self) -> &BindGroup {
+        self.transform.bind_group()
+    }
+
     /// If it is a hardware texture, it will directly create view for the
     /// current texture, if it is a software texture, it will write the data to
     /// the internal texture first, and then create the view for the internal
@@ -375,7 +509,7 @@ impl Texture2DSource {
     pub fn get_view(
         &mut self,
         texture: Texture,
-    ) -> Result<Option<(&RenderPipeline, BindGroup)>, FromNativeResourceError> {
+    ) -> Result<Option<(&RenderPipeline, BindGroup, &BindGroup)>, FromNativeResourceError> {
         // Not yet initialized, initialize the environment first.
         if self.sample.is_none() {
             let size = texture.size();
@@ -389,7 +523,7 @@ impl Texture2DSource {
                         layout: Some(&self.device.create_pipeline_layout(
                             &PipelineLayoutDescriptor {
                                 label: None,
-                                bind_group_layouts: &[&bind_group_layout],
+                                bind_group_layouts: &[&bind_group_layout, self.transform.layout()],
                                 push_constant_ranges: &[],
                             },
                         )),
@@ -403,7 +537,9 @@ impl Texture2DSource {
                         },
                         fragment: Some(FragmentState {
                             entry_point: Some("main"),
-                            module: &self.device.create_shader_module(sample.fragment()),
+                            module: &self
+                                .device
+                                .create_shader_module(sample.fragment(self.filter)),
                             compilation_options: PipelineCompilationOptions::default(),
                             targets: &[Some(ColorTargetState {
                                 blend: Some(BlendState::REPLACE),
@@ -451,6 +587,11 @@ impl Texture2DSource {
                         i420.update(&self.queue, texture);
                     }
                 }
+                Texture::P010(Texture2DResource::Buffer(buffer)) => {
+                    if let Texture2DSourceSample::P010(p010) = sample {
+                        p010.update(&self.queue, buffer);
+                    }
+                }
                 _ => (),
             }
         }
@@ -459,23 +600,27 @@ impl Texture2DSource {
             if let (Some(layout), Some(sample), Some(pipeline)) =
                 (&self.bind_group_layout, &self.sample, &self.pipeline)
             {
-                let texture = texture.texture(&mut self.interop)?;
+                let textures = texture.texture(&mut self.interop)?;
                 Some((
                     pipeline,
                     match sample {
                         Texture2DSourceSample::Bgra(sample) => {
-                            sample.bind_group(&self.device, layout, texture)
+                            sample.bind_group(&self.device, layout, textures, self.filter)
                         }
                         Texture2DSourceSample::Rgba(sample) => {
-                            sample.bind_group(&self.device, layout, texture)
+                            sample.bind_group(&self.device, layout, textures, self.filter)
                         }
                         Texture2DSourceSample::Nv12(sample) => {
-                            sample.bind_group(&self.device, layout, texture)
+                            sample.bind_group(&self.device, layout, textures, self.filter)
                         }
                         Texture2DSourceSample::I420(sample) => {
-                            sample.bind_group(&self.device, layout, texture)
+                            sample.bind_group(&self.device, layout, textures, self.filter)
+                        }
+                        Texture2DSourceSample::P010(sample) => {
+                            sample.bind_group(&self.device, layout, textures, self.filter)
                         }
                     },
+                    self.transform.bind_group(),
                 ))
             } else {
                 None
@@ -18,10 +18,10 @@ impl Texture2DSample for Bgra {
 
     fn views_descriptors<'a>(
         &'a self,
-        texture: Option<&'a Texture>,
+        textures: &'a [Texture],
     ) -> impl IntoIterator<Item = (&'a Texture, TextureFormat, TextureAspect)> {
         [(
-            texture.unwrap_or_else(|| &self.0),
+            textures.first().unwrap_or(&self.0),
             TextureFormat::Bgra8Unorm,
             TextureAspect::All,
         )]
@@ -56,7 +56,7 @@ impl Texture2DSample for I420 {
 
     fn views_descriptors<'a>(
         &'a self,
-        _: Option<&'a Texture>,
+        _: &'a [Texture],
     ) -> impl IntoIterator<Item = (&'a Texture, TextureFormat, TextureAspect)> {
         [
             (&self.0, TextureFormat::R8Unorm, TextureAspect::All),
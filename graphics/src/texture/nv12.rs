@@ -49,21 +49,25 @@ impl Texture2DSample for Nv12 {
 
     fn views_descriptors<'a>(
         &'a self,
-        texture: Option<&'a Texture>,
+        textures: &'a [Texture],
     ) -> impl IntoIterator<Item = (&'a Texture, TextureFormat, TextureAspect)> {
-        // When you create a view directly for a texture, the external texture is a
-        // single texture, and you need to create different planes of views on top of
-        // the single texture.
-        if let Some(texture) = texture {
-            [
+        match textures {
+            // When you create a view directly for a texture, the external texture is a
+            // single texture, and you need to create different planes of views on top of
+            // the single texture.
+            [texture] => [
                 (texture, TextureFormat::R8Unorm, TextureAspect::Plane0),
                 (texture, TextureFormat::Rg8Unorm, TextureAspect::Plane1),
-            ]
-        } else {
-            [
+            ],
+            // The planes of the pixel buffers of macOS are imported as separate textures.
+            [y, uv, ..] => [
+                (y, TextureFormat::R8Unorm, TextureAspect::All),
+                (uv, TextureFormat::Rg8Unorm, TextureAspect::All),
+            ],
+            [] => [
                 (&self.0, TextureFormat::R8Unorm, TextureAspect::All),
                 (&self.1, TextureFormat::Rg8Unorm, TextureAspect::All),
-            ]
+            ],
         }
     }
 
@@ -0,0 +1,76 @@
+use super::Texture2DSample;
+
+use hylarana_common::Size;
+use wgpu::{Device, Texture, TextureAspect, TextureFormat};
+
+/// P010 has the same layout as NV12, the entire image in Y is written out,
+/// followed by interleaved lines that go U0, V0, U1, V1, etc. The difference
+/// is that each sample takes 16 bits, of which the high 10 bits are valid.
+///
+/// This is the usual layout for HDR10 content, where the colors use BT.2020
+/// primaries and the SMPTE ST 2084 (PQ) transfer function, so the fragment
+/// shader needs to convert the PQ signal to the SDR surface.
+///
+/// Note that wgpu does not have a P010 texture format, so only software
+/// textures and the pixel buffers of macOS, whose planes are separate
+/// textures, are supported here.
+pub struct P010(Texture, Texture);
+
+impl P010 {
+    pub(crate) fn new(device: &Device, size: Size) -> Self {
+        let mut textures = Self::create(device, size);
+        Self(textures.next().unwrap(), textures.next().unwrap())
+    }
+}
+
+impl Texture2DSample for P010 {
+    fn create_texture_descriptor(size: Size) -> impl IntoIterator<Item = (Size, TextureFormat)> {
+        [
+            (size, TextureFormat::R16Unorm),
+            (
+                Size {
+                    width: size.width / 2,
+                    height: size.height / 2,
+                },
+                TextureFormat::Rg16Unorm,
+            ),
+        ]
+    }
+
+    fn views_descriptors<'a>(
+        &'a self,
+        textures: &'a [Texture],
+    ) -> impl IntoIterator<Item = (&'a Texture, TextureFormat, TextureAspect)> {
+        match textures {
+            // The planes of the pixel buffers of macOS are imported as separate textures.
+            [y, uv, ..] => [
+                (y, TextureFormat::R16Unorm, TextureAspect::All),
+                (uv, TextureFormat::Rg16Unorm, TextureAspect::All),
+            ],
+            _ => [
+                (&self.0, TextureFormat::R16Unorm, TextureAspect::All),
+                (&self.1, TextureFormat::Rg16Unorm, TextureAspect::All),
+            ],
+        }
+    }
+
+    fn copy_buffer_descriptors<'a>(
+        &self,
+        buffers: &'a [&'a [u8]],
+    ) -> impl IntoIterator<Item = (&'a [u8], &Texture, TextureAspect, Size)> {
+        // Each sample is two bytes, and a row of the UV plane has half of the samples
+        // but two channels, so both planes have the same number of bytes per row.
+        let size = {
+            let size = self.0.size();
+            Size {
+                width: size.width * 2,
+                height: size.height,
+            }
+        };
+
+        [
+            (buffers[0], &self.0, TextureAspect::All, size),
+            (buffers[1], &self.1, TextureAspect::All, size),
+        ]
+    }
+}
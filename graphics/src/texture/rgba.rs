@@ -34,10 +34,10 @@ impl Texture2DSample for Rgba {
 
     fn views_descriptors<'a>(
         &'a self,
-        texture: Option<&'a Texture>,
+        textures: &'a [Texture],
     ) -> impl IntoIterator<Item = (&'a Texture, TextureFormat, TextureAspect)> {
         [(
-            texture.unwrap_or_else(|| &self.0),
+            textures.first().unwrap_or(&self.0),
             TextureFormat::Rgba8Unorm,
             TextureAspect::All,
         )]
@@ -1,5 +1,11 @@
 use bytemuck::{Pod, Zeroable};
-use wgpu::{BufferAddress, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode};
+use hylarana_common::frame::VideoRotation;
+use wgpu::{
+    util::{BufferInitDescriptor, DeviceExt},
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferAddress, BufferBindingType, BufferUsages,
+    Device, Queue, ShaderStages, VertexAttribute, VertexBufferLayout, VertexFormat, VertexStepMode,
+};
 
 #[repr(C)]
 #[derive(Copy, Clone, Pod, Zeroable)]
@@ -44,3 +50,109 @@ impl Vertex {
         }
     }
 }
+
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Pod, Zeroable)]
+struct TransformUniform {
+    rotation: u32,
+    mirror: u32,
+    crop: [f32; 2],
+    display: [f32; 2],
+}
+
+impl Default for TransformUniform {
+    fn default() -> Self {
+        Self {
+            rotation: 0,
+            mirror: 0,
+            crop: [1.0, 1.0],
+            display: [1.0, 1.0],
+        }
+    }
+}
+
+/// The orientation transform applied by the vertex shader.
+///
+/// The texture coordinates are rotated clockwise in quarter turns and
+/// optionally mirrored horizontally, so that the frame is displayed upright
+/// regardless of how the source was captured. The crop is the visible fraction
+/// of the texture in the display space, which is used to fill the surface, and
+/// the display is the fraction of the texture that has the picture, the
+/// padding of the frames is never drawn.
+pub struct Transform {
+    uniform: TransformUniform,
+    buffer: Buffer,
+    layout: BindGroupLayout,
+    bind_group: BindGroup,
+}
+
+impl Transform {
+    pub fn new(device: &Device) -> Self {
+        let uniform = TransformUniform::default();
+        let buffer = device.create_buffer_init(&BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::bytes_of(&uniform),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
+        let layout = device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[BindGroupLayoutEntry {
+                binding: 0,
+                visibility: ShaderStages::VERTEX,
+                ty: BindingType::Buffer {
+                    ty: BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            }],
+        });
+
+        let bind_group = device.create_bind_group(&BindGroupDescriptor {
+            label: None,
+            layout: &layout,
+            entries: &[BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            }],
+        });
+
+        Self {
+            uniform,
+            buffer,
+            layout,
+            bind_group,
+        }
+    }
+
+    pub fn layout(&self) -> &BindGroupLayout {
+        &self.layout
+    }
+
+    pub fn bind_group(&self) -> &BindGroup {
+        &self.bind_group
+    }
+
+    /// Only writes to the uniform buffer when the transform has changed.
+    pub fn update(
+        &mut self,
+        queue: &Queue,
+        rotation: VideoRotation,
+        mirror: bool,
+        crop: [f32; 2],
+        display: [f32; 2],
+    ) {
+        let uniform = TransformUniform {
+            rotation: rotation as u32,
+            mirror: mirror as u32,
+            crop,
+            display,
+        };
+
+        if uniform != self.uniform {
+            queue.write_buffer(&self.buffer, 0, bytemuck::bytes_of(&uniform));
+            self.uniform = uniform;
+        }
+    }
+}
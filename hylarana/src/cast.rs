@@ -0,0 +1,827 @@
+use crate::{
+    hls::{HlsOutput, HlsOutputOptions},
+    output::StreamOutputEvent,
+    packets::{EncodedCodecParameters, EncodedPacketSink},
+};
+
+use std::{
+    fs,
+    io::{Error, ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, UdpSocket},
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use hylarana_common::atomic::EasyAtomic;
+use native_tls::{TlsConnector, TlsStream};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+
+const NAMESPACE_CONNECTION: &str = "urn:x-cast:com.google.cast.tp.connection";
+const NAMESPACE_HEARTBEAT: &str = "urn:x-cast:com.google.cast.tp.heartbeat";
+const NAMESPACE_RECEIVER: &str = "urn:x-cast:com.google.cast.receiver";
+const NAMESPACE_MEDIA: &str = "urn:x-cast:com.google.cast.media";
+
+// The Default Media Receiver of the devices, it plays the HLS stream.
+const DEFAULT_MEDIA_RECEIVER: &str = "CC1AD845";
+
+const SENDER_ID: &str = "sender-0";
+const RECEIVER_ID: &str = "receiver-0";
+
+const PLAYLIST: &str = "index.m3u8";
+
+// The devices ping the senders every 5 seconds, the connection is lost when
+// nothing arrives for three times as long.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+
+// The device only loads the playlist and a few segments at the same time, the
+// connections beyond this are closed right away.
+const MAX_FILE_CONNECTIONS: usize = 8;
+
+/// Options of a Cast output.
+#[derive(Debug, Clone)]
+pub struct CastOutputOptions {
+    /// The address of the device, the addresses and the port of a
+    /// `CastDevice` that is found with `DiscoveryService::browse_cast`.
+    pub device: SocketAddr,
+    /// The title that is shown on the device.
+    pub title: String,
+    /// The directory of the HLS segments, a directory in the temporary
+    /// directory of the system by default.
+    pub directory: PathBuf,
+    /// The port of the HTTP server that serves the segments to the device, any
+    /// free port by default.
+    pub port: u16,
+    /// The target duration of the segments, 1 second by default.
+    pub segment_duration: Duration,
+    /// The bit rate of the AAC audio, 128 kbps by default.
+    pub audio_bit_rate: u64,
+    /// How many packets can wait for the disk, when the queue is full the
+    /// video is dropped up to the next key frame. 256 by default.
+    pub queue_size: usize,
+    /// How long the connection waits for the device, 10 seconds by default.
+    pub timeout: Duration,
+    /// The delay before the first reconnect, it is doubled after every failed
+    /// attempt up to `max_reconnect_delay`. 1 second by default.
+    pub reconnect_delay: Duration,
+    /// 30 seconds by default.
+    pub max_reconnect_delay: Duration,
+}
+
+impl Default for CastOutputOptions {
+    fn default() -> Self {
+        Self {
+            device: SocketAddr::from(([0, 0, 0, 0], 8009)),
+            title: "Hylarana".to_string(),
+            directory: std::env::temp_dir().join("hylarana-cast"),
+            port: 0,
+            segment_duration: Duration::from_secs(1),
+            audio_bit_rate: 128_000,
+            queue_size: 256,
+            timeout: Duration::from_secs(10),
+            reconnect_delay: Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+type EventHandler = Box<dyn Fn(StreamOutputEvent) + Send + Sync>;
+
+struct Shared {
+    events: Mutex<Option<EventHandler>>,
+    closed: AtomicBool,
+    // The application has been stopped on the device, such as with the remote
+    // of the TV.
+    stopped: AtomicBool,
+}
+
+impl Shared {
+    fn emit(&self, event: StreamOutputEvent) {
+        if let Some(handler) = self.events.lock().as_ref() {
+            handler(event);
+        }
+    }
+}
+
+/// Mirrors the stream of a sender to a Google Cast device, such as a
+/// Chromecast or a TV with Cast built in, so that no second computer is
+/// needed to show the screen on a TV, see `HylaranaSender::set_packet_sink`.
+///
+/// The stream is written as HLS, which is served by an HTTP server of the
+/// output and played by the Default Media Receiver of the device, so the
+/// device is a few seconds behind the sender. The video is passed as it is,
+/// H264 is played by all devices, HEVC only by some of them. The output
+/// connects again when the connection to the device is lost. When the
+/// application is stopped on the device, the output removes itself from the
+/// sender.
+pub struct CastOutput {
+    hls: HlsOutput,
+    shared: Arc<Shared>,
+    // Stopped when the output is dropped.
+    #[allow(dead_code)]
+    server: FileServer,
+}
+
+impl CastOutput {
+    pub fn new(options: CastOutputOptions) -> std::io::Result<Self> {
+        log::info!("create cast output: options={:?}", options);
+
+        let shared = Arc::new(Shared {
+            events: Mutex::new(None),
+            closed: AtomicBool::new(false),
+            stopped: AtomicBool::new(false),
+        });
+
+        // The device is told to load the playlist when it exists, a playlist of an
+        // earlier output would be loaded before the first segment is written.
+        let _ = fs::remove_file(options.directory.join(PLAYLIST));
+
+        let shared_ = shared.clone();
+        let hls = HlsOutput::new(HlsOutputOptions {
+            directory: options.directory.clone(),
+            playlist: PLAYLIST.to_string(),
+            segment_duration: options.segment_duration,
+            audio_bit_rate: options.audio_bit_rate,
+            queue_size: options.queue_size,
+            ..Default::default()
+        })?
+        .on_event(move |event| {
+            // The device is connected when it plays the stream, not when the
+            // segments are written.
+            if matches!(
+                event,
+                StreamOutputEvent::Congested | StreamOutputEvent::Disconnected { .. }
+            ) {
+                shared_.emit(event);
+            }
+        });
+
+        // The address on which the device reaches this host, nothing is sent by
+        // connecting a UDP socket.
+        let host = {
+            let socket = UdpSocket::bind(SocketAddr::from(([0, 0, 0, 0], 0)))?;
+            socket.connect(options.device)?;
+            socket.local_addr()?.ip()
+        };
+
+        let server = FileServer::bind(
+            SocketAddr::from(([0, 0, 0, 0], options.port)),
+            options.directory.clone(),
+        )?;
+
+        let url = format!(
+            "http://{}/{}",
+            SocketAddr::new(host, server.port()),
+            PLAYLIST
+        );
+
+        let shared_ = shared.clone();
+        thread::Builder::new()
+            .name("HylaranaCastOutputThread".to_string())
+            .spawn(move || {
+                Session::run(&options, &shared_, &url);
+
+                log::info!("cast output thread is closed");
+
+                shared_.emit(StreamOutputEvent::Closed);
+            })?;
+
+        Ok(Self {
+            hls,
+            shared,
+            server,
+        })
+    }
+
+    pub fn on_event<F>(self, handler: F) -> Self
+    where
+        F: Fn(StreamOutputEvent) + Send + Sync + 'static,
+    {
+        self.shared.events.lock().replace(Box::new(handler));
+        self
+    }
+}
+
+impl EncodedPacketSink for CastOutput {
+    fn parameters(&self, parameters: &EncodedCodecParameters) {
+        self.hls.parameters(parameters)
+    }
+
+    fn video(&self, packet: &[u8], key_frame: bool, timestamp: u64) -> bool {
+        !self.shared.stopped.get() && self.hls.video(packet, key_frame, timestamp)
+    }
+
+    fn audio(&self, packet: &[u8], timestamp: u64) -> bool {
+        !self.shared.stopped.get() && self.hls.audio(packet, timestamp)
+    }
+
+    fn close(&self) {
+        self.shared.closed.update(true);
+        self.hls.close()
+    }
+}
+
+impl Drop for CastOutput {
+    fn drop(&mut self) {
+        self.shared.closed.update(true);
+    }
+}
+
+// How a session with the device has ended.
+enum SessionEnd {
+    Closed,
+    Stopped,
+}
+
+// A session of the Cast protocol, the sender launches the Default Media
+// Receiver on the device and loads the playlist into it once the first
+// segment has been written.
+struct Session<'a> {
+    channel: CastChannel,
+    shared: &'a Shared,
+    title: &'a str,
+    url: &'a str,
+    playlist: PathBuf,
+    // The ID of the launched application, it is used to stop it.
+    session_id: Option<String>,
+    // The destination of the media messages.
+    transport_id: Option<String>,
+    loaded: bool,
+    playing: bool,
+}
+
+impl<'a> Session<'a> {
+    fn run(options: &CastOutputOptions, shared: &Shared, url: &str) {
+        let mut delay = options.reconnect_delay;
+
+        while !shared.closed.get() {
+            let result =
+                CastChannel::connect(options.device, options.timeout).and_then(|channel| {
+                    Session {
+                        playlist: options.directory.join(PLAYLIST),
+                        title: &options.title,
+                        session_id: None,
+                        transport_id: None,
+                        loaded: false,
+                        playing: false,
+                        channel,
+                        shared,
+                        url,
+                    }
+                    .play()
+                });
+
+            match result {
+                Ok(SessionEnd::Closed) => break,
+                Ok(SessionEnd::Stopped) => {
+                    log::info!("cast application is stopped on the device");
+
+                    shared.stopped.update(true);
+                    break;
+                }
+                Err(e) => {
+                    log::warn!("cast output disconnected, err={:?}, retry={:?}", e, delay);
+
+                    shared.emit(StreamOutputEvent::Disconnected {
+                        error: e.to_string(),
+                        retry: delay,
+                    });
+
+                    let retry_at = Instant::now() + delay;
+                    while !shared.closed.get() && Instant::now() < retry_at {
+                        thread::sleep(Duration::from_millis(100));
+                    }
+
+                    delay = (delay * 2).min(options.max_reconnect_delay);
+                }
+            }
+        }
+    }
+
+    fn play(mut self) -> Result<SessionEnd, Error> {
+        self.channel.send(
+            RECEIVER_ID,
+            NAMESPACE_CONNECTION,
+            json!({ "type": "CONNECT" }),
+        )?;
+
+        let request_id = self.channel.request_id();
+        self.channel.send(
+            RECEIVER_ID,
+            NAMESPACE_RECEIVER,
+            json!({
+                "type": "LAUNCH",
+                "appId": DEFAULT_MEDIA_RECEIVER,
+                "requestId": request_id,
+            }),
+        )?;
+
+        let mut received_at = Instant::now();
+        let mut ping_at = Instant::now() + HEARTBEAT_INTERVAL;
+        loop {
+            if self.shared.closed.get() {
+                self.stop();
+
+                return Ok(SessionEnd::Closed);
+            }
+
+            if let Some(message) = self.channel.recv()? {
+                received_at = Instant::now();
+
+                if let Some(end) = self.process(message)? {
+                    return Ok(end);
+                }
+            } else if received_at.elapsed() > HEARTBEAT_TIMEOUT {
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    "the device does not respond",
+                ));
+            }
+
+            if Instant::now() >= ping_at {
+                ping_at = Instant::now() + HEARTBEAT_INTERVAL;
+
+                self.channel
+                    .send(RECEIVER_ID, NAMESPACE_HEARTBEAT, json!({ "type": "PING" }))?;
+            }
+
+            // The device fails to play a playlist without segments.
+            if !self.loaded && self.playlist.exists() {
+                if let Some(transport_id) = self.transport_id.clone() {
+                    self.load(&transport_id)?;
+                }
+            }
+        }
+    }
+
+    fn process(&mut self, message: CastMessage) -> Result<Option<SessionEnd>, Error> {
+        let payload: Value = serde_json::from_str(&message.payload)?;
+        let kind = payload["type"].as_str().unwrap_or_default();
+
+        match (message.namespace.as_str(), kind) {
+            (NAMESPACE_HEARTBEAT, "PING") => {
+                self.channel.send(
+                    &message.source,
+                    NAMESPACE_HEARTBEAT,
+                    json!({ "type": "PONG" }),
+                )?;
+            }
+            (NAMESPACE_CONNECTION, "CLOSE") => {
+                return Ok(Some(SessionEnd::Stopped));
+            }
+            (NAMESPACE_RECEIVER, "RECEIVER_STATUS") => {
+                let application = payload["status"]["applications"]
+                    .as_array()
+                    .and_then(|it| {
+                        it.iter()
+                            .find(|it| it["appId"].as_str() == Some(DEFAULT_MEDIA_RECEIVER))
+                    })
+                    .cloned();
+
+                match (application, self.transport_id.is_some()) {
+                    (Some(application), false) => {
+                        let transport_id = application["transportId"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string();
+
+                        log::info!("cast application is launched, transport={}", transport_id);
+
+                        self.channel.send(
+                            &transport_id,
+                            NAMESPACE_CONNECTION,
+                            json!({ "type": "CONNECT" }),
+                        )?;
+
+                        self.session_id = application["sessionId"].as_str().map(String::from);
+                        self.transport_id = Some(transport_id);
+                    }
+                    // Another application has taken over the device.
+                    (None, true) => {
+                        return Ok(Some(SessionEnd::Stopped));
+                    }
+                    _ => (),
+                }
+            }
+            (NAMESPACE_RECEIVER, "LAUNCH_ERROR") | (NAMESPACE_MEDIA, "LOAD_FAILED") => {
+                return Err(Error::other(format!(
+                    "the device has failed to play the stream, message={}",
+                    message.payload
+                )));
+            }
+            (NAMESPACE_MEDIA, "MEDIA_STATUS") => {
+                let status = &payload["status"][0];
+                match status["playerState"].as_str() {
+                    Some("PLAYING") if !self.playing => {
+                        log::info!("cast device is playing the stream");
+
+                        self.playing = true;
+                        self.shared.emit(StreamOutputEvent::Connected);
+                    }
+                    Some("IDLE") if status["idleReason"].as_str() == Some("ERROR") => {
+                        return Err(Error::other("the device has failed to play the stream"));
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+
+        Ok(None)
+    }
+
+    fn load(&mut self, transport_id: &str) -> Result<(), Error> {
+        log::info!("cast device load the stream, url={}", self.url);
+
+        let request_id = self.channel.request_id();
+        self.channel.send(
+            transport_id,
+            NAMESPACE_MEDIA,
+            json!({
+                "type": "LOAD",
+                "requestId": request_id,
+                "autoplay": true,
+                "media": {
+                    "contentId": self.url,
+                    "contentUrl": self.url,
+                    "contentType": "application/x-mpegurl",
+                    "streamType": "LIVE",
+                    "hlsSegmentFormat": "fmp4",
+                    "hlsVideoSegmentFormat": "fmp4",
+                    "metadata": {
+                        "metadataType": 0,
+                        "title": self.title,
+                    },
+                },
+            }),
+        )?;
+
+        self.loaded = true;
+        Ok(())
+    }
+
+    // Stops the application on the device, so that the TV goes back to where
+    // it was.
+    fn stop(&mut self) {
+        if let Some(session_id) = self.session_id.take() {
+            let request_id = self.channel.request_id();
+            if let Err(e) = self.channel.send(
+                RECEIVER_ID,
+                NAMESPACE_RECEIVER,
+                json!({
+                    "type": "STOP",
+                    "sessionId": session_id,
+                    "requestId": request_id,
+                }),
+            ) {
+                log::warn!("failed to stop cast application, err={:?}", e);
+            }
+        }
+    }
+}
+
+// A message of the Cast protocol, only the string payloads are used, all of
+// them are JSON.
+struct CastMessage {
+    source: String,
+    destination: String,
+    namespace: String,
+    payload: String,
+}
+
+impl CastMessage {
+    // Encodes the CastMessage protobuf by hand, it is the only message of the
+    // protocol.
+    fn encode(&self) -> Vec<u8> {
+        fn varint(buf: &mut Vec<u8>, mut value: u64) {
+            while value >= 0x80 {
+                buf.push((value as u8 & 0x7f) | 0x80);
+                value >>= 7;
+            }
+
+            buf.push(value as u8);
+        }
+
+        fn string(buf: &mut Vec<u8>, field: u8, value: &str) {
+            buf.push(field << 3 | 2);
+            varint(buf, value.len() as u64);
+            buf.extend_from_slice(value.as_bytes());
+        }
+
+        let mut buf = Vec::with_capacity(self.payload.len() + 128);
+
+        // protocol_version = CASTV2_1_0
+        buf.extend_from_slice(&[1 << 3, 0]);
+        string(&mut buf, 2, &self.source);
+        string(&mut buf, 3, &self.destination);
+        string(&mut buf, 4, &self.namespace);
+
+        // payload_type = STRING
+        buf.extend_from_slice(&[5 << 3, 0]);
+        string(&mut buf, 6, &self.payload);
+        buf
+    }
+
+    fn decode(mut buf: &[u8]) -> Option<Self> {
+        fn varint(buf: &mut &[u8]) -> Option<u64> {
+            let mut value = 0;
+            for shift in (0..64).step_by(7) {
+                let (&byte, rest) = buf.split_first()?;
+                *buf = rest;
+
+                value |= ((byte & 0x7f) as u64) << shift;
+                if byte & 0x80 == 0 {
+                    return Some(value);
+                }
+            }
+
+            None
+        }
+
+        let mut message = Self {
+            source: String::new(),
+            destination: String::new(),
+            namespace: String::new(),
+            payload: String::new(),
+        };
+
+        while !buf.is_empty() {
+            let key = varint(&mut buf)?;
+            match key & 7 {
+                0 => {
+                    varint(&mut buf)?;
+                }
+                2 => {
+                    let size = varint(&mut buf)? as usize;
+                    if buf.len() < size {
+                        return None;
+                    }
+
+                    let (value, rest) = buf.split_at(size);
+                    buf = rest;
+
+                    let value = || String::from_utf8_lossy(value).to_string();
+                    match key >> 3 {
+                        2 => message.source = value(),
+                        3 => message.destination = value(),
+                        4 => message.namespace = value(),
+                        6 => message.payload = value(),
+                        _ => (),
+                    }
+                }
+                _ => return None,
+            }
+        }
+
+        Some(message)
+    }
+}
+
+// The TLS connection to the device, the messages are prefixed with their
+// size in big endian.
+struct CastChannel {
+    stream: TlsStream<TcpStream>,
+    buf: Vec<u8>,
+    request_id: u64,
+}
+
+impl CastChannel {
+    fn connect(addr: SocketAddr, timeout: Duration) -> Result<Self, Error> {
+        log::info!("cast output connect to device, addr={}", addr);
+
+        let stream = TcpStream::connect_timeout(&addr, timeout)?;
+        stream.set_read_timeout(Some(timeout))?;
+        stream.set_write_timeout(Some(timeout))?;
+
+        let stream = Self::connector()?
+            .connect(&addr.ip().to_string(), stream)
+            .map_err(Error::other)?;
+
+        // Wake up regularly to send the heartbeat and to check if the output is
+        // closed.
+        stream
+            .get_ref()
+            .set_read_timeout(Some(Duration::from_millis(500)))?;
+
+        Ok(Self {
+            buf: Vec::with_capacity(4096),
+            request_id: 0,
+            stream,
+        })
+    }
+
+    // The devices present a certificate that is signed by the device CA of
+    // Google, which is not trusted by the system, and they are reached by their
+    // address, which is not in the certificate. The verification is only
+    // relaxed for this channel, which carries the control messages of the
+    // session, the media is served by the file server anyway.
+    fn connector() -> Result<TlsConnector, Error> {
+        TlsConnector::builder()
+            .danger_accept_invalid_certs(true)
+            .danger_accept_invalid_hostnames(true)
+            .build()
+            .map_err(Error::other)
+    }
+
+    fn request_id(&mut self) -> u64 {
+        self.request_id += 1;
+        self.request_id
+    }
+
+    fn send(&mut self, destination: &str, namespace: &str, payload: Value) -> Result<(), Error> {
+        let message = CastMessage {
+            source: SENDER_ID.to_string(),
+            destination: destination.to_string(),
+            namespace: namespace.to_string(),
+            payload: payload.to_string(),
+        }
+        .encode();
+
+        self.stream
+            .write_all(&(message.len() as u32).to_be_bytes())?;
+        self.stream.write_all(&message)?;
+        self.stream.flush()
+    }
+
+    // Returns `None` when no message arrives before the timeout.
+    fn recv(&mut self) -> Result<Option<CastMessage>, Error> {
+        loop {
+            if self.buf.len() >= 4 {
+                let size = u32::from_be_bytes([self.buf[0], self.buf[1], self.buf[2], self.buf[3]])
+                    as usize;
+
+                if size > 64 * 1024 {
+                    return Err(Error::new(
+                        ErrorKind::InvalidData,
+                        "cast message is too big",
+                    ));
+                }
+
+                if self.buf.len() >= size + 4 {
+                    let message = CastMessage::decode(&self.buf[4..size + 4]);
+                    self.buf.drain(..size + 4);
+
+                    return message
+                        .map(Some)
+                        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "invalid cast message"));
+                }
+            }
+
+            let mut chunk = [0u8; 4096];
+            match self.stream.read(&mut chunk) {
+                Ok(0) => return Err(ErrorKind::UnexpectedEof.into()),
+                Ok(size) => self.buf.extend_from_slice(&chunk[..size]),
+                Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                    return Ok(None)
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// Serves the files of the HLS directory over http to the device, the server
+// is stopped when it is dropped.
+struct FileServer {
+    addr: SocketAddr,
+    closed: Arc<AtomicBool>,
+}
+
+impl FileServer {
+    fn bind(addr: SocketAddr, directory: PathBuf) -> Result<Self, Error> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let closed = Arc::new(AtomicBool::new(false));
+
+        log::info!("cast file server listening, addr={}", addr);
+
+        let closed_ = closed.clone();
+        let connections = Arc::new(AtomicUsize::new(0));
+        thread::Builder::new()
+            .name("HylaranaCastFileServerThread".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if closed_.get() {
+                        break;
+                    }
+
+                    // The device loads the playlist and the segments at the same time.
+                    if let Ok(stream) = stream {
+                        if connections.fetch_add(1, Ordering::AcqRel) >= MAX_FILE_CONNECTIONS {
+                            connections.fetch_sub(1, Ordering::AcqRel);
+
+                            log::warn!("cast file server is busy, drop the connection");
+
+                            continue;
+                        }
+
+                        let directory = directory.clone();
+                        let connections_ = connections.clone();
+                        if let Err(e) = thread::Builder::new()
+                            .name("HylaranaCastFileServerThread".to_string())
+                            .spawn(move || {
+                                if let Err(e) = serve(stream, &directory) {
+                                    log::warn!("cast file server failed to respond, err={:?}", e);
+                                }
+
+                                connections_.fetch_sub(1, Ordering::AcqRel);
+                            })
+                        {
+                            connections.fetch_sub(1, Ordering::AcqRel);
+
+                            log::warn!("cast file server failed to spawn, err={:?}", e);
+                        }
+                    }
+                }
+
+                log::info!("cast file server is closed, addr={}", addr);
+            })?;
+
+        Ok(Self { addr, closed })
+    }
+
+    fn port(&self) -> u16 {
+        self.addr.port()
+    }
+}
+
+impl Drop for FileServer {
+    fn drop(&mut self) {
+        self.closed.update(true);
+
+        // Wake up the listener that is blocked in accept.
+        let _ = TcpStream::connect_timeout(
+            &SocketAddr::from(([127, 0, 0, 1], self.addr.port())),
+            Duration::from_secs(1),
+        );
+    }
+}
+
+fn serve(mut stream: TcpStream, directory: &Path) -> Result<(), Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    // Only the request line matters, the rest of the request is ignored.
+    let mut buf = [0u8; 2048];
+    let size = stream.read(&mut buf)?;
+
+    let request = String::from_utf8_lossy(&buf[..size]);
+    let mut line = request.lines().next().unwrap_or_default().split(' ');
+    let (method, path) = (
+        line.next().unwrap_or_default(),
+        line.next().unwrap_or_default(),
+    );
+
+    // Only the files of the directory are served, not its sub directories, the
+    // name has to be a single plain component, which also rejects the drive
+    // prefixes and the roots of Windows.
+    let name = path
+        .trim_start_matches('/')
+        .split('?')
+        .next()
+        .unwrap_or_default();
+
+    let mut components = Path::new(name).components();
+    let is_file_name = matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    );
+
+    let file = if method == "GET"
+        && is_file_name
+        && !name.starts_with('.')
+        && !name.contains(['/', '\\', ':'])
+    {
+        fs::read(directory.join(name)).ok()
+    } else {
+        None
+    };
+
+    let Some(body) = file else {
+        return write!(
+            stream,
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+        );
+    };
+
+    let content_type = match Path::new(name).extension().and_then(|it| it.to_str()) {
+        Some("m3u8") => "application/vnd.apple.mpegurl",
+        Some("m4s") => "video/iso.segment",
+        _ => "video/mp4",
+    };
+
+    // The receiver is a web page on the device, it needs CORS for the files.
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        content_type,
+        body.len()
+    )?;
+
+    stream.write_all(&body)
+}
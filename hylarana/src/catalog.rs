@@ -0,0 +1,322 @@
+use crate::{
+    DisconnectReason, HylaranaError, HylaranaReceiverError, HylaranaSenderError, StreamErrorKind,
+};
+
+use std::io::{Error, ErrorKind};
+
+use hylarana_capture::{AudioCaptureError, Capture, CaptureError, PermissionState, SourceType};
+use hylarana_codec::{
+    AudioDecoderError, AudioEncoderError, CodecError, VideoDecoderError, VideoEncoderError,
+    WatermarkerError,
+};
+use serde::{Deserialize, Serialize};
+
+/// The stable identifiers of the failures and of the reasons of the events
+/// that are shown to the user, so that the applications can show them in
+/// their own language instead of the English text of the errors.
+///
+/// The numbers and the ids never change, new codes are only added. The codes
+/// are grouped by the hundreds, an application that does not know a new code
+/// can fall back to its group.
+#[repr(u32)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    #[default]
+    Unknown = 0,
+
+    /// The user has denied the permission of the capture.
+    CapturePermissionDenied = 100,
+    /// The capture source does not exist, such as an unplugged device.
+    CaptureSourceNotFound = 101,
+    /// The capture backend is not registered.
+    CaptureBackendNotFound = 102,
+    /// The source type cannot be captured in this environment.
+    CaptureNotSupported = 103,
+    CaptureFailed = 104,
+
+    /// The codec or the hardware encoder is not available on this machine.
+    EncoderNotSupported = 200,
+    EncoderFailed = 201,
+    /// The codec or the hardware decoder is not available on this machine.
+    DecoderNotSupported = 202,
+    DecoderFailed = 203,
+
+    /// The port is used by another application.
+    PortInUse = 300,
+    /// The address does not belong to this machine.
+    AddressNotAvailable = 301,
+    ConnectionRefused = 302,
+    NetworkUnreachable = 303,
+    TimedOut = 304,
+    /// The system does not allow the network operation, such as a firewall.
+    NetworkPermissionDenied = 305,
+    NetworkFailed = 306,
+    /// The sender requires a pairing code, and the receiver has given up
+    /// without the right code.
+    PairingFailed = 307,
+
+    /// The stream is closed locally.
+    StreamClosed = 400,
+    /// The sink has closed the stream.
+    StreamSinkClosed = 401,
+    /// The connection is lost without the remote side saying goodbye.
+    StreamConnectionLost = 402,
+    /// The remote side has closed the stream.
+    StreamRemoteClosed = 403,
+    /// The capture source has been removed, such as an unplugged display.
+    StreamSourceRemoved = 404,
+    StreamCaptureFailed = 405,
+    StreamEncodeFailed = 406,
+    StreamDecodeFailed = 407,
+    StreamTransportFailed = 408,
+    /// The sender has disconnected the receiver.
+    StreamKicked = 409,
+
+    /// The metadata of the stream cannot be serialized.
+    InvalidMetadata = 500,
+    /// The capture helper process of the sandbox mode has failed.
+    SandboxFailed = 501,
+    /// An error of the system api, see `ErrorParams::os_error`.
+    SystemError = 502,
+    /// The image of the watermark cannot be read or decoded.
+    InvalidWatermark = 503,
+}
+
+impl ErrorCode {
+    /// The id of the code, such as `capture.permission_denied`, which can be
+    /// used as the key of the translations.
+    pub fn id(self) -> &'static str {
+        match self {
+            Self::Unknown => "unknown",
+            Self::CapturePermissionDenied => "capture.permission_denied",
+            Self::CaptureSourceNotFound => "capture.source_not_found",
+            Self::CaptureBackendNotFound => "capture.backend_not_found",
+            Self::CaptureNotSupported => "capture.not_supported",
+            Self::CaptureFailed => "capture.failed",
+            Self::EncoderNotSupported => "codec.encoder_not_supported",
+            Self::EncoderFailed => "codec.encoder_failed",
+            Self::DecoderNotSupported => "codec.decoder_not_supported",
+            Self::DecoderFailed => "codec.decoder_failed",
+            Self::PortInUse => "network.port_in_use",
+            Self::AddressNotAvailable => "network.address_not_available",
+            Self::ConnectionRefused => "network.connection_refused",
+            Self::NetworkUnreachable => "network.unreachable",
+            Self::TimedOut => "network.timed_out",
+            Self::NetworkPermissionDenied => "network.permission_denied",
+            Self::NetworkFailed => "network.failed",
+            Self::PairingFailed => "network.pairing_failed",
+            Self::StreamClosed => "stream.closed",
+            Self::StreamSinkClosed => "stream.sink_closed",
+            Self::StreamConnectionLost => "stream.connection_lost",
+            Self::StreamRemoteClosed => "stream.remote_closed",
+            Self::StreamSourceRemoved => "stream.source_removed",
+            Self::StreamCaptureFailed => "stream.capture_failed",
+            Self::StreamEncodeFailed => "stream.encode_failed",
+            Self::StreamDecodeFailed => "stream.decode_failed",
+            Self::StreamTransportFailed => "stream.transport_failed",
+            Self::StreamKicked => "stream.kicked",
+            Self::InvalidMetadata => "system.invalid_metadata",
+            Self::SandboxFailed => "system.sandbox_failed",
+            Self::SystemError => "system.error",
+            Self::InvalidWatermark => "system.invalid_watermark",
+        }
+    }
+}
+
+/// The parameters of an error, they fill the placeholders of the translated
+/// text, such as the name of the backend. The parameters that do not apply
+/// to the code are `None`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorParams {
+    /// The type of the capture source.
+    pub source_type: Option<SourceType>,
+    /// The name of the capture backend.
+    pub name: Option<String>,
+    /// The error code of the operating system, such as `errno` or the
+    /// `GetLastError` of Windows.
+    pub os_error: Option<i32>,
+}
+
+/// The code and the parameters of an error, see `ErrorCatalog`.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorContext {
+    pub code: ErrorCode,
+    pub params: ErrorParams,
+    /// The English text of the error, for the logs and the reports, it is not
+    /// meant to be shown to the user.
+    pub message: String,
+}
+
+impl ErrorContext {
+    fn new(code: ErrorCode, message: String) -> Self {
+        Self {
+            params: ErrorParams::default(),
+            message,
+            code,
+        }
+    }
+
+    fn with_source_type(mut self, kind: SourceType) -> Self {
+        self.params.source_type = Some(kind);
+        self
+    }
+
+    fn with_name(mut self, name: &str) -> Self {
+        self.params.name = Some(name.to_string());
+        self
+    }
+}
+
+/// Describes an error or the reason of an event with an `ErrorCode`, so that
+/// it can be localized.
+pub trait ErrorCatalog {
+    fn error_context(&self) -> ErrorContext;
+}
+
+impl ErrorCatalog for Error {
+    fn error_context(&self) -> ErrorContext {
+        let code = match self.kind() {
+            ErrorKind::AddrInUse => ErrorCode::PortInUse,
+            ErrorKind::AddrNotAvailable => ErrorCode::AddressNotAvailable,
+            ErrorKind::ConnectionRefused => ErrorCode::ConnectionRefused,
+            ErrorKind::NetworkUnreachable | ErrorKind::HostUnreachable => {
+                ErrorCode::NetworkUnreachable
+            }
+            ErrorKind::TimedOut => ErrorCode::TimedOut,
+            ErrorKind::PermissionDenied => ErrorCode::NetworkPermissionDenied,
+            _ => ErrorCode::NetworkFailed,
+        };
+
+        let mut context = ErrorContext::new(code, self.to_string());
+        context.params.os_error = self.raw_os_error();
+        context
+    }
+}
+
+impl ErrorCatalog for CaptureError {
+    fn error_context(&self) -> ErrorContext {
+        let message = self.to_string();
+
+        // The errors of the platforms do not tell the permission apart, the
+        // failure is taken as denied when the permission is denied.
+        let failed = |kind: SourceType| {
+            let code = if Capture::check_permissions(kind) == PermissionState::Denied {
+                ErrorCode::CapturePermissionDenied
+            } else {
+                ErrorCode::CaptureFailed
+            };
+
+            ErrorContext::new(code, message.clone()).with_source_type(kind)
+        };
+
+        match self {
+            Self::AudioCaptureError(AudioCaptureError::NotFoundAudioSource) => {
+                ErrorContext::new(ErrorCode::CaptureSourceNotFound, message)
+                    .with_source_type(SourceType::Audio)
+            }
+            Self::AudioCaptureError(_) => failed(SourceType::Audio),
+            Self::ScreenCaptureError(_) => failed(SourceType::Screen),
+            Self::CameraCaptureError(_) => failed(SourceType::Camera),
+            Self::BackendNotFound(name) => {
+                ErrorContext::new(ErrorCode::CaptureBackendNotFound, message).with_name(name)
+            }
+            Self::NotSupported(name) => {
+                ErrorContext::new(ErrorCode::CaptureNotSupported, message).with_name(name)
+            }
+            Self::BackendError(_) => ErrorContext::new(ErrorCode::CaptureFailed, message),
+        }
+    }
+}
+
+impl ErrorCatalog for HylaranaSenderError {
+    fn error_context(&self) -> ErrorContext {
+        let message = self.to_string();
+
+        match self {
+            Self::TransportError(e) => e.error_context(),
+            Self::CaptureError(e) => e.error_context(),
+            Self::VideoEncoderError(
+                VideoEncoderError::CodecError(CodecError::NotSupportCodec)
+                | VideoEncoderError::OpenAVCodecError,
+            )
+            | Self::AudioEncoderError(
+                AudioEncoderError::NotFoundAVCodec | AudioEncoderError::OpenAVCodecError,
+            ) => ErrorContext::new(ErrorCode::EncoderNotSupported, message),
+            Self::VideoEncoderError(_) | Self::AudioEncoderError(_) => {
+                ErrorContext::new(ErrorCode::EncoderFailed, message)
+            }
+            Self::MetadataError(_) => ErrorContext::new(ErrorCode::InvalidMetadata, message),
+            Self::WatermarkError(WatermarkerError::ReadImageError(e)) => {
+                let mut context = ErrorContext::new(ErrorCode::InvalidWatermark, message);
+                context.params.os_error = e.raw_os_error();
+                context
+            }
+            Self::WatermarkError(_) => ErrorContext::new(ErrorCode::InvalidWatermark, message),
+            Self::SandboxError(e) => {
+                let mut context = ErrorContext::new(ErrorCode::SandboxFailed, message);
+                context.params.os_error = e.raw_os_error();
+                context
+            }
+        }
+    }
+}
+
+impl ErrorCatalog for HylaranaReceiverError {
+    fn error_context(&self) -> ErrorContext {
+        let message = self.to_string();
+
+        match self {
+            Self::CreateThreadError(e) => {
+                let mut context = ErrorContext::new(ErrorCode::SystemError, message);
+                context.params.os_error = e.raw_os_error();
+                context
+            }
+            Self::SendMessageError(e) => e.error_context(),
+            Self::PairingFailed => ErrorContext::new(ErrorCode::PairingFailed, message),
+            Self::VideoDecoderError(
+                VideoDecoderError::CodecError(CodecError::NotSupportCodec)
+                | VideoDecoderError::OpenAVCodecError,
+            )
+            | Self::AudioDecoderError(
+                AudioDecoderError::NotFoundAVCodec | AudioDecoderError::OpenAVCodecError,
+            ) => ErrorContext::new(ErrorCode::DecoderNotSupported, message),
+            Self::VideoDecoderError(_) | Self::AudioDecoderError(_) => {
+                ErrorContext::new(ErrorCode::DecoderFailed, message)
+            }
+        }
+    }
+}
+
+impl ErrorCatalog for HylaranaError {
+    fn error_context(&self) -> ErrorContext {
+        match self {
+            Self::TransportError(e) => e.error_context(),
+            #[cfg(target_os = "windows")]
+            Self::Win32Error(e) => {
+                let mut context = ErrorContext::new(ErrorCode::SystemError, e.to_string());
+                context.params.os_error = Some(e.code().0);
+                context
+            }
+        }
+    }
+}
+
+impl ErrorCatalog for DisconnectReason {
+    fn error_context(&self) -> ErrorContext {
+        let code = match self {
+            Self::Closed => ErrorCode::StreamClosed,
+            Self::SinkClosed => ErrorCode::StreamSinkClosed,
+            Self::TransportClosed => ErrorCode::StreamConnectionLost,
+            Self::RemoteClosed => ErrorCode::StreamRemoteClosed,
+            Self::Kicked => ErrorCode::StreamKicked,
+            Self::SourceRemoved => ErrorCode::StreamSourceRemoved,
+            Self::Error(StreamErrorKind::Capture) => ErrorCode::StreamCaptureFailed,
+            Self::Error(StreamErrorKind::Encode) => ErrorCode::StreamEncodeFailed,
+            Self::Error(StreamErrorKind::Decode) => ErrorCode::StreamDecodeFailed,
+            Self::Error(StreamErrorKind::Transport) => ErrorCode::StreamTransportFailed,
+        };
+
+        ErrorContext::new(code, format!("{:?}", self))
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    fmt,
+    sync::{Arc, LazyLock},
+};
+
+use hylarana_common::AdapterPreference;
+use parking_lot::RwLock;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::Direct3DDevice;
+
+static SHARED_CONTEXT: LazyLock<GraphicsContext> =
+    LazyLock::new(|| GraphicsContext::new(AdapterPreference::LowPower));
+
+struct ContextInner {
+    adapter: RwLock<AdapterPreference>,
+    #[cfg(target_os = "windows")]
+    direct3d: RwLock<Option<Direct3DDevice>>,
+}
+
+/// The graphics device that the capture, the codecs and the renderers run on.
+///
+/// The frames are passed between them as textures of the device, so a
+/// receiver and the player of its frames have to use the same context, the
+/// same goes for the views of a mosaic player. The clones of a context share
+/// the device.
+///
+/// Everything uses the shared context of the process by default. All the
+/// decoders and renderers of a device go through its immediate context, so a
+/// process with many streams, such as a video wall, can give each group of
+/// receivers and their players its own context, which has its own device.
+#[derive(Clone)]
+pub struct GraphicsContext(Arc<ContextInner>);
+
+impl GraphicsContext {
+    /// Create a context with its own device on the adapter chosen by the
+    /// preference, the device is created when it is first used.
+    pub fn new(adapter: AdapterPreference) -> Self {
+        log::info!("create graphics context, adapter={:?}", adapter);
+
+        Self(Arc::new(ContextInner {
+            adapter: RwLock::new(adapter),
+            #[cfg(target_os = "windows")]
+            direct3d: RwLock::new(None),
+        }))
+    }
+
+    /// The context that is shared by the whole process, its adapter is set by
+    /// `set_adapter_preference`.
+    pub fn shared() -> Self {
+        SHARED_CONTEXT.clone()
+    }
+
+    pub fn adapter(&self) -> AdapterPreference {
+        *self.0.adapter.read()
+    }
+
+    // The device is created again on the new adapter when it is next used, the
+    // objects that have been created keep the old device.
+    pub(crate) fn set_adapter(&self, adapter: AdapterPreference) {
+        *self.0.adapter.write() = adapter;
+
+        #[cfg(target_os = "windows")]
+        self.0.direct3d.write().take();
+    }
+
+    /// Get the D3D device of the context, the device is created if it has not
+    /// been created yet. The device is also created again when it has been
+    /// removed, such as by a driver update, the users of the removed device get
+    /// the new one when they recover.
+    #[cfg(target_os = "windows")]
+    pub fn direct3d(&self) -> Direct3DDevice {
+        if let Some(direct3d) = self.0.direct3d.read().as_ref() {
+            if !direct3d.is_removed() {
+                return direct3d.clone();
+            }
+        }
+
+        // Another thread may have replaced the device while waiting for the lock.
+        let mut direct3d = self.0.direct3d.write();
+        if let Some(it) = direct3d.as_ref().filter(|it| !it.is_removed()) {
+            return it.clone();
+        }
+
+        if direct3d.is_some() {
+            log::warn!("D3D device has been removed, create a new one");
+        }
+
+        direct3d
+            .insert(
+                Direct3DDevice::with_adapter(self.adapter())
+                    .expect("D3D device was not initialized successfully!"),
+            )
+            .clone()
+    }
+}
+
+impl Default for GraphicsContext {
+    fn default() -> Self {
+        Self::shared()
+    }
+}
+
+impl fmt::Debug for GraphicsContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GraphicsContext")
+            .field("adapter", &self.adapter())
+            .field("shared", &Arc::ptr_eq(&self.0, &SHARED_CONTEXT.0))
+            .finish()
+    }
+}
@@ -0,0 +1,383 @@
+//! Dumps of the raw frames of the sender, for finding out whether a problem
+//! of the picture or the sound comes from the capture, the encoder or the
+//! transport.
+//!
+//! When a dump directory is set, the senders write the captured frames to the
+//! directory before they are encoded, the video as a Y4M file and the audio as
+//! a WAV file, a new file is started when the size or the format of the frames
+//! changes. The directory can be changed while the senders are running, and
+//! the `HYLARANA_FRAME_DUMP` environment variable sets it at the start.
+//!
+//! Only the NV12, I420 and P010 frames in the memory are written, the frames
+//! of the textures and the RGB frames are skipped.
+
+use std::{
+    env,
+    fs::File,
+    io::{BufWriter, Error, Seek, SeekFrom, Write},
+    path::PathBuf,
+    slice::from_raw_parts,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        LazyLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use hylarana_common::frame::{get_planes, AudioFrame, VideoFormat, VideoFrame, VideoSubFormat};
+use parking_lot::RwLock;
+
+const ENV: &str = "HYLARANA_FRAME_DUMP";
+
+static DIRECTORY: LazyLock<RwLock<Option<PathBuf>>> =
+    LazyLock::new(|| RwLock::new(env::var_os(ENV).map(PathBuf::from)));
+
+// Counts the changes of the directory, so that the dumpers do not take the
+// lock for every frame.
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+/// Set the directory that the raw frames of the senders are dumped to, `None`
+/// stops the dumps. This also affects the senders that are running, see
+/// the `HYLARANA_FRAME_DUMP` environment variable.
+pub fn set_frame_dump_directory(directory: Option<PathBuf>) {
+    log::info!("set frame dump directory={:?}", directory);
+
+    *DIRECTORY.write() = directory;
+    GENERATION.fetch_add(1, Ordering::Relaxed);
+}
+
+fn create_file(kind: &str, extension: &str) -> Option<(BufWriter<File>, PathBuf)> {
+    let directory = DIRECTORY.read().clone()?;
+    let path = directory.join(format!(
+        "{}-{}.{}",
+        kind,
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|it| it.as_millis())
+            .unwrap_or(0),
+        extension
+    ));
+
+    match File::create(&path) {
+        Ok(file) => {
+            log::info!("dump {} frames to path={:?}", kind, path);
+
+            Some((BufWriter::new(file), path))
+        }
+        Err(e) => {
+            log::error!("failed to create frame dump, path={:?}, err={:?}", path, e);
+
+            None
+        }
+    }
+}
+
+// The file follows the directory, it is closed when the directory changes and
+// created again with the next frame.
+struct Dump<T> {
+    generation: u64,
+    enabled: bool,
+    file: Option<T>,
+    // The frames that cannot be written are only reported once.
+    skipped: bool,
+}
+
+impl<T> Default for Dump<T> {
+    fn default() -> Self {
+        Self {
+            generation: u64::MAX,
+            enabled: false,
+            file: None,
+            skipped: false,
+        }
+    }
+}
+
+impl<T> Dump<T> {
+    // Returns whether the frames are dumped, the file is closed if the directory
+    // has changed since the last frame.
+    fn is_enabled(&mut self) -> bool {
+        let generation = GENERATION.load(Ordering::Relaxed);
+        if self.generation != generation {
+            self.generation = generation;
+            self.enabled = DIRECTORY.read().is_some();
+            self.skipped = false;
+            self.file = None;
+        }
+
+        self.enabled
+    }
+}
+
+struct Y4mWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    format: VideoFormat,
+    width: u32,
+    height: u32,
+    // A row of the interleaved chroma plane, split into the two planes of Y4M.
+    row: Vec<u8>,
+}
+
+impl Y4mWriter {
+    fn new(
+        (mut file, path): (BufWriter<File>, PathBuf),
+        frame: &VideoFrame,
+        frame_rate: u8,
+    ) -> Result<Self, Error> {
+        writeln!(
+            file,
+            "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 {}",
+            frame.width,
+            frame.height,
+            frame_rate.max(1),
+            if frame.format == VideoFormat::P010 {
+                "C420p10 XYSCSS=420P10"
+            } else {
+                "C420jpeg XYSCSS=420JPEG"
+            }
+        )?;
+
+        Ok(Self {
+            format: frame.format,
+            width: frame.width,
+            height: frame.height,
+            row: Vec::new(),
+            file,
+            path,
+        })
+    }
+
+    fn is_compatible(&self, frame: &VideoFrame) -> bool {
+        self.format == frame.format && self.width == frame.width && self.height == frame.height
+    }
+
+    fn write(&mut self, frame: &VideoFrame) -> Result<(), Error> {
+        self.file.write_all(b"FRAME\n")?;
+
+        let planes = get_planes(frame.format, frame.width, frame.height);
+        for (i, (size, rows)) in planes.iter().enumerate() {
+            if *rows == 0 {
+                continue;
+            }
+
+            let rows = (0..*rows).map(|row| unsafe {
+                from_raw_parts(
+                    (frame.data[i] as *const u8).add(row * frame.linesize[i]),
+                    *size,
+                )
+            });
+
+            match frame.format {
+                // The samples of P010 are in the high bits, Y4M takes them in the low
+                // bits.
+                VideoFormat::P010 if i == 0 => {
+                    for row in rows {
+                        self.row.clear();
+                        push_p010(&mut self.row, row);
+
+                        self.file.write_all(&self.row)?;
+                    }
+                }
+                // The chroma of NV12 and P010 is interleaved, Y4M takes the U plane
+                // before the V plane.
+                VideoFormat::NV12 | VideoFormat::P010 => {
+                    let sample = if frame.format == VideoFormat::P010 {
+                        2
+                    } else {
+                        1
+                    };
+                    let rows = rows.collect::<Vec<_>>();
+                    for offset in [0, sample] {
+                        for row in &rows {
+                            self.row.clear();
+                            for pair in row.chunks_exact(sample * 2) {
+                                let value = &pair[offset..offset + sample];
+                                if sample == 2 {
+                                    push_p010(&mut self.row, value);
+                                } else {
+                                    self.row.extend_from_slice(value);
+                                }
+                            }
+
+                            self.file.write_all(&self.row)?;
+                        }
+                    }
+                }
+                _ => {
+                    for row in rows {
+                        self.file.write_all(row)?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn push_p010(output: &mut Vec<u8>, samples: &[u8]) {
+    for sample in samples.chunks_exact(2) {
+        let value = u16::from_le_bytes([sample[0], sample[1]]) >> 6;
+        output.extend_from_slice(&value.to_le_bytes());
+    }
+}
+
+/// Writes the captured video frames of a sender to the dump directory.
+#[derive(Default)]
+pub(crate) struct VideoFrameDumper(Dump<Y4mWriter>);
+
+impl VideoFrameDumper {
+    pub(crate) fn write(&mut self, frame: &VideoFrame, frame_rate: u8) {
+        let dump = &mut self.0;
+        if !dump.is_enabled() {
+            return;
+        }
+
+        let planes = match frame.format {
+            VideoFormat::NV12 | VideoFormat::P010 => 2,
+            VideoFormat::I420 => 3,
+            VideoFormat::BGRA | VideoFormat::RGBA => 0,
+        };
+
+        if frame.sub_format != VideoSubFormat::SW
+            || planes == 0
+            || frame.data[..planes].iter().any(|it| it.is_null())
+        {
+            if !dump.skipped {
+                log::warn!(
+                    "video frame cannot be dumped, format={:?}, sub_format={:?}",
+                    frame.format,
+                    frame.sub_format
+                );
+
+                dump.skipped = true;
+            }
+
+            return;
+        }
+
+        if !dump
+            .file
+            .as_ref()
+            .map(|it| it.is_compatible(frame))
+            .unwrap_or(false)
+        {
+            dump.file = create_file("video", "y4m").and_then(|file| {
+                Y4mWriter::new(file, frame, frame_rate)
+                    .map_err(|e| log::error!("failed to write y4m header, err={:?}", e))
+                    .ok()
+            });
+        }
+
+        if let Some(writer) = dump.file.as_mut() {
+            if let Err(e) = writer.write(frame) {
+                log::error!(
+                    "failed to dump video frame, path={:?}, err={:?}",
+                    writer.path,
+                    e
+                );
+
+                dump.file = None;
+            }
+        }
+    }
+}
+
+// The sizes in the header are written when the file is finished.
+struct WavWriter {
+    file: BufWriter<File>,
+    path: PathBuf,
+    sample_rate: u32,
+    size: u32,
+}
+
+impl WavWriter {
+    fn new((mut file, path): (BufWriter<File>, PathBuf), sample_rate: u32) -> Result<Self, Error> {
+        // The samples are mono 16-bit PCM.
+        file.write_all(b"RIFF")?;
+        file.write_all(&0u32.to_le_bytes())?;
+        file.write_all(b"WAVEfmt ")?;
+        file.write_all(&16u32.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&1u16.to_le_bytes())?;
+        file.write_all(&sample_rate.to_le_bytes())?;
+        file.write_all(&(sample_rate * 2).to_le_bytes())?;
+        file.write_all(&2u16.to_le_bytes())?;
+        file.write_all(&16u16.to_le_bytes())?;
+        file.write_all(b"data")?;
+        file.write_all(&0u32.to_le_bytes())?;
+
+        Ok(Self {
+            sample_rate,
+            size: 0,
+            file,
+            path,
+        })
+    }
+
+    fn write(&mut self, frame: &AudioFrame) -> Result<(), Error> {
+        let samples = unsafe { from_raw_parts(frame.data, frame.frames as usize) };
+        for sample in samples {
+            self.file.write_all(&sample.to_le_bytes())?;
+        }
+
+        self.size = self.size.saturating_add(frame.frames * 2);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<(), Error> {
+        self.file.seek(SeekFrom::Start(4))?;
+        self.file.write_all(&(36 + self.size).to_le_bytes())?;
+        self.file.seek(SeekFrom::Start(40))?;
+        self.file.write_all(&self.size.to_le_bytes())?;
+        self.file.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::warn!(
+                "failed to finish wav dump, path={:?}, err={:?}",
+                self.path,
+                e
+            );
+        }
+    }
+}
+
+/// Writes the captured audio frames of a sender to the dump directory.
+#[derive(Default)]
+pub(crate) struct AudioFrameDumper(Dump<WavWriter>);
+
+impl AudioFrameDumper {
+    pub(crate) fn write(&mut self, frame: &AudioFrame) {
+        let dump = &mut self.0;
+        if !dump.is_enabled() || frame.data.is_null() || frame.frames == 0 {
+            return;
+        }
+
+        if dump.file.as_ref().map(|it| it.sample_rate) != Some(frame.sample_rate) {
+            // The previous file is finished when it is dropped.
+            dump.file = None;
+            dump.file = create_file("audio", "wav").and_then(|file| {
+                WavWriter::new(file, frame.sample_rate)
+                    .map_err(|e| log::error!("failed to write wav header, err={:?}", e))
+                    .ok()
+            });
+        }
+
+        if let Some(writer) = dump.file.as_mut() {
+            if let Err(e) = writer.write(frame) {
+                log::error!(
+                    "failed to dump audio frame, path={:?}, err={:?}",
+                    writer.path,
+                    e
+                );
+
+                dump.file = None;
+            }
+        }
+    }
+}
@@ -0,0 +1,564 @@
+//! Interop with GStreamer pipelines, the streams of a receiver are pushed into
+//! `appsrc` elements, and the samples of `appsink` elements are captured by a
+//! sender, so the existing pipelines can feed or consume the streams without
+//! the C FFI.
+
+use crate::{receiver::config_codec, AVFrameObserver, AVFrameSink, AVFrameStream, StreamEvent};
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+};
+
+use bytes::{BufMut, BytesMut};
+use gstreamer::{
+    prelude::*, Buffer, BufferFlags, Caps, ClockTime, FlowError, Fraction, List, Sample,
+};
+use gstreamer_app::{AppSink, AppSrc};
+use hylarana_capture::{
+    AudioCaptureSourceDescription, CaptureBackend, CaptureError, CaptureStream, FrameArrived,
+    FramePacer, Source, SourceEvent, SourceType, VideoCaptureSourceDescription,
+};
+use hylarana_common::{
+    clock::MediaClock,
+    frame::{get_planes, AudioFrame, VideoFormat, VideoFrame, VideoSubFormat},
+};
+use hylarana_transport::BufferFlag;
+use parking_lot::Mutex;
+
+// The rows of the planes of the raw video are aligned to 4 bytes, which are
+// the default strides of GStreamer for the formats of the frames.
+fn stride(size: usize) -> usize {
+    size.next_multiple_of(4)
+}
+
+fn format_name(format: VideoFormat) -> &'static str {
+    match format {
+        VideoFormat::BGRA => "BGRA",
+        VideoFormat::RGBA => "RGBA",
+        VideoFormat::NV12 => "NV12",
+        VideoFormat::I420 => "I420",
+        VideoFormat::P010 => "P010_10LE",
+    }
+}
+
+/// What a `GstAppSrcSink` pushes into the `appsrc` elements.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GstAppSrcFormat {
+    /// The decoded frames, the video as `video/x-raw` and the audio as
+    /// mono `S16LE` `audio/x-raw`. Only the software frames can be pushed, so
+    /// the receiver should use a software decoder.
+    Raw,
+    /// The encoded packets, the video as `video/x-h264` or `video/x-h265` in
+    /// the byte stream format and the audio as `audio/x-opus`. The receiver
+    /// has to pass the packets through, see
+    /// `HylaranaReceiverCodecOptions::passthrough`.
+    Compressed,
+}
+
+// The time of the first frame is the start of the buffers, it is shared by the
+// tracks so that they stay in sync.
+#[derive(Default)]
+struct Timeline(Mutex<Option<u64>>);
+
+impl Timeline {
+    fn pts(&self, timestamp: u64) -> ClockTime {
+        let base = *self.0.lock().get_or_insert(timestamp);
+        ClockTime::from_useconds(timestamp.saturating_sub(base))
+    }
+}
+
+// The caps are only set on the element when they change.
+struct AppSrcTrack {
+    element: AppSrc,
+    caps: Option<Caps>,
+}
+
+impl AppSrcTrack {
+    fn new(element: AppSrc) -> Mutex<Self> {
+        element.set_format(gstreamer::Format::Time);
+        element.set_is_live(true);
+
+        Mutex::new(Self {
+            caps: None,
+            element,
+        })
+    }
+
+    fn push(&mut self, caps: Caps, buffer: Buffer) -> bool {
+        if self.caps.as_ref() != Some(&caps) {
+            self.element.set_caps(Some(&caps));
+            self.caps = Some(caps);
+        }
+
+        // The stream is closed when the pipeline has ended, the buffers are dropped
+        // while the pipeline is not playing.
+        match self.element.push_buffer(buffer) {
+            Ok(_) => true,
+            Err(FlowError::Eos) => false,
+            Err(e) => {
+                log::trace!("failed to push buffer to appsrc, err={:?}", e);
+
+                true
+            }
+        }
+    }
+}
+
+fn create_buffer(bytes: Vec<u8>, pts: ClockTime, delta: bool) -> Buffer {
+    let mut buffer = Buffer::from_mut_slice(bytes);
+    {
+        let buffer = buffer.get_mut().unwrap();
+        buffer.set_pts(pts);
+
+        if delta {
+            buffer.set_flags(BufferFlags::DELTA_UNIT);
+        }
+    }
+
+    buffer
+}
+
+/// A sink of a receiver that pushes the streams into the `appsrc` elements of
+/// GStreamer pipelines, a track without an element is dropped.
+///
+/// The buffers are timestamped from the first frame of the stream, the
+/// elements are set to the time format and live. The end of the stream is
+/// sent to the elements when the stream is closed.
+///
+/// ```ignore
+/// let appsrc = pipeline.by_name("src").unwrap().downcast::<AppSrc>().unwrap();
+/// let sink = GstAppSrcSink::new(Some(appsrc), None, GstAppSrcFormat::Compressed);
+/// let receiver = Hylarana::create_receiver(id, options, sink)?;
+/// ```
+pub struct GstAppSrcSink {
+    format: GstAppSrcFormat,
+    timeline: Timeline,
+    video: Option<Mutex<AppSrcTrack>>,
+    audio: Option<Mutex<AppSrcTrack>>,
+    // The configuration of the encoder is pushed in front of the next key frame,
+    // so that the parsers get a complete access unit.
+    config: Mutex<Option<(&'static str, Vec<u8>)>>,
+    events: Mutex<Option<Box<dyn Fn(StreamEvent) + Send + Sync>>>,
+}
+
+impl GstAppSrcSink {
+    pub fn new(video: Option<AppSrc>, audio: Option<AppSrc>, format: GstAppSrcFormat) -> Self {
+        Self {
+            video: video.map(AppSrcTrack::new),
+            audio: audio.map(AppSrcTrack::new),
+            timeline: Timeline::default(),
+            config: Mutex::new(None),
+            events: Mutex::new(None),
+            format,
+        }
+    }
+
+    /// Set the handler of the events of the stream.
+    pub fn on_event<F>(self, handler: F) -> Self
+    where
+        F: Fn(StreamEvent) + Send + Sync + 'static,
+    {
+        self.events.lock().replace(Box::new(handler));
+        self
+    }
+}
+
+impl AVFrameSink for GstAppSrcSink {
+    fn video(&self, frame: &VideoFrame) -> bool {
+        let Some(video) = self.video.as_ref() else {
+            return true;
+        };
+
+        if self.format != GstAppSrcFormat::Raw {
+            return true;
+        }
+
+        if frame.sub_format != VideoSubFormat::SW {
+            log::trace!("appsrc only takes software frames, the frame is dropped");
+
+            return true;
+        }
+
+        let planes = get_planes(frame.format, frame.width, frame.height);
+        let mut bytes =
+            Vec::with_capacity(planes.iter().map(|(size, rows)| stride(*size) * rows).sum());
+
+        for (i, (size, rows)) in planes.iter().enumerate() {
+            for row in 0..*rows {
+                bytes.extend_from_slice(unsafe {
+                    std::slice::from_raw_parts(
+                        (frame.data[i] as *const u8).add(row * frame.linesize[i]),
+                        *size,
+                    )
+                });
+
+                bytes.resize(bytes.len() + stride(*size) - size, 0);
+            }
+        }
+
+        video.lock().push(
+            Caps::builder("video/x-raw")
+                .field("format", format_name(frame.format))
+                .field("width", frame.width as i32)
+                .field("height", frame.height as i32)
+                .field("framerate", Fraction::new(0, 1))
+                .build(),
+            create_buffer(bytes, self.timeline.pts(frame.timestamp), false),
+        )
+    }
+
+    fn audio(&self, frame: &AudioFrame) -> bool {
+        let Some(audio) = self.audio.as_ref() else {
+            return true;
+        };
+
+        if self.format != GstAppSrcFormat::Raw || frame.data.is_null() {
+            return true;
+        }
+
+        let samples = unsafe { std::slice::from_raw_parts(frame.data, frame.frames as usize) };
+        let mut bytes = BytesMut::with_capacity(samples.len() * 2);
+        for sample in samples {
+            bytes.put_i16_le(*sample);
+        }
+
+        audio.lock().push(
+            Caps::builder("audio/x-raw")
+                .field("format", "S16LE")
+                .field("layout", "interleaved")
+                .field("rate", frame.sample_rate as i32)
+                .field("channels", 1)
+                .build(),
+            create_buffer(bytes.to_vec(), self.timeline.pts(frame.timestamp), false),
+        )
+    }
+
+    fn video_packet(&self, packet: &[u8], flags: i32, timestamp: u64) -> bool {
+        let Some(video) = self.video.as_ref() else {
+            return true;
+        };
+
+        if flags & BufferFlag::MASK == BufferFlag::Config as i32 {
+            if let Some(codec) = config_codec(packet) {
+                self.config.lock().replace((codec, packet.to_vec()));
+            }
+
+            return true;
+        }
+
+        let key_frame = flags & BufferFlag::MASK == BufferFlag::KeyFrame as i32;
+        let (codec, mut bytes) = match self.config.lock().as_ref() {
+            Some((codec, config)) if key_frame => (*codec, config.clone()),
+            Some((codec, _)) => (*codec, Vec::new()),
+            // The packets in front of the first configuration cannot be decoded.
+            None => return true,
+        };
+
+        bytes.extend_from_slice(packet);
+        video.lock().push(
+            Caps::builder(if codec == "hevc" {
+                "video/x-h265"
+            } else {
+                "video/x-h264"
+            })
+            .field("stream-format", "byte-stream")
+            .field("alignment", "au")
+            .build(),
+            create_buffer(bytes, self.timeline.pts(timestamp), !key_frame),
+        )
+    }
+
+    fn audio_packet(&self, packet: &[u8], flags: i32, timestamp: u64) -> bool {
+        let Some(audio) = self.audio.as_ref() else {
+            return true;
+        };
+
+        // The identification header only tells the number of channels, which is
+        // always one.
+        if flags & BufferFlag::MASK == BufferFlag::Config as i32 {
+            return true;
+        }
+
+        audio.lock().push(
+            Caps::builder("audio/x-opus")
+                .field("channel-mapping-family", 0)
+                .field("channels", 1)
+                .field("rate", 48000)
+                .build(),
+            create_buffer(packet.to_vec(), self.timeline.pts(timestamp), false),
+        )
+    }
+}
+
+impl AVFrameObserver for GstAppSrcSink {
+    fn event(&self, event: StreamEvent) {
+        if let Some(handler) = self.events.lock().as_ref() {
+            handler(event);
+        }
+    }
+
+    fn close(&self) {
+        for element in [
+            self.video.as_ref().map(|it| it.lock().element.clone()),
+            self.audio.as_ref().map(|it| it.lock().element.clone()),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if let Err(e) = element.end_of_stream() {
+                log::warn!("failed to end appsrc stream, err={:?}", e);
+            }
+        }
+    }
+}
+
+impl AVFrameStream for GstAppSrcSink {}
+
+/// A capture backend whose sources are the `appsink` elements of GStreamer
+/// pipelines, so that a sender captures the samples of a pipeline like a
+/// screen or a microphone.
+///
+/// The video sinks take `NV12` or `I420` frames in any size, the sender
+/// scales them to the size of the stream. The audio sinks are set to mono
+/// `S16LE` at the sample rate of the sender when the capture starts. The
+/// backend is cheap to clone, the sources can be added after it is
+/// registered.
+///
+/// ```ignore
+/// let backend = GstAppSinkBackend::default();
+/// Capture::register_backend(backend.clone());
+///
+/// let source = backend.add_source("decklink", SourceType::Camera, appsink);
+/// ```
+#[derive(Default, Clone)]
+pub struct GstAppSinkBackend(Arc<Mutex<Vec<(Source, AppSink)>>>);
+
+impl GstAppSinkBackend {
+    /// The name of the backend, see `Source::backend`.
+    pub const NAME: &'static str = "gstreamer";
+
+    /// Add an `appsink` as a source of a type, the screens and the cameras
+    /// are video sources. The returned source is also listed by
+    /// `Capture::get_sources`.
+    pub fn add_source(&self, name: &str, kind: SourceType, element: AppSink) -> Source {
+        if kind != SourceType::Audio {
+            element.set_caps(Some(
+                &Caps::builder("video/x-raw")
+                    .field("format", List::new(["NV12", "I420"]))
+                    .build(),
+            ));
+        }
+
+        let mut sources = self.0.lock();
+        let source = Source {
+            id: element.name().to_string(),
+            name: name.to_string(),
+            index: sources.len(),
+            is_default: false,
+            rotation: Default::default(),
+            backend: Some(Self::NAME.to_string()),
+            kind,
+        };
+
+        sources.retain(|(it, _)| it.id != source.id);
+        sources.push((source.clone(), element));
+        source
+    }
+
+    /// Remove the sources of an `appsink`, the captures that have been
+    /// started are not stopped.
+    pub fn remove_source(&self, id: &str) {
+        self.0.lock().retain(|(it, _)| it.id != id);
+    }
+
+    fn element(&self, source: &Source) -> Result<AppSink, CaptureError> {
+        self.0
+            .lock()
+            .iter()
+            .find(|(it, _)| it.id == source.id)
+            .map(|(_, element)| element.clone())
+            .ok_or_else(|| {
+                CaptureError::BackendError(format!("appsink not found, id={}", source.id).into())
+            })
+    }
+}
+
+// Pulls the samples of an `appsink` on its own thread until it is closed or the
+// pipeline ends, the handler gets `None` at the end of the stream.
+struct AppSinkStream(Arc<AtomicBool>);
+
+impl AppSinkStream {
+    fn spawn<F>(element: AppSink, mut handler: F) -> Result<Self, CaptureError>
+    where
+        F: FnMut(Option<&Sample>) -> bool + Send + 'static,
+    {
+        let running = Arc::new(AtomicBool::new(true));
+        let running_ = running.clone();
+
+        thread::Builder::new()
+            .name("HylaranaGstAppSinkThread".to_string())
+            .spawn(move || {
+                while running_.load(Ordering::Relaxed) {
+                    if let Some(sample) = element.try_pull_sample(ClockTime::from_mseconds(100)) {
+                        if !handler(Some(&sample)) {
+                            break;
+                        }
+                    } else if element.is_eos() {
+                        log::info!("appsink is end of stream, name={}", element.name());
+
+                        handler(None);
+                        break;
+                    }
+                }
+
+                log::info!("appsink capture thread is closed");
+            })
+            .map_err(|e| CaptureError::BackendError(Box::new(e)))?;
+
+        Ok(Self(running))
+    }
+}
+
+impl CaptureStream for AppSinkStream {
+    fn close(&self) -> Result<(), CaptureError> {
+        self.0.store(false, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl CaptureBackend for GstAppSinkBackend {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn sources(&self, kind: SourceType) -> Result<Vec<Source>, CaptureError> {
+        Ok(self
+            .0
+            .lock()
+            .iter()
+            .filter(|(it, _)| it.kind == kind)
+            .map(|(it, _)| it.clone())
+            .collect())
+    }
+
+    fn start_video(
+        &self,
+        description: VideoCaptureSourceDescription,
+        mut arrived: Box<dyn FrameArrived<Frame = VideoFrame>>,
+    ) -> Result<Box<dyn CaptureStream>, CaptureError> {
+        let element = self.element(&description.source)?;
+        let mut pacer = FramePacer::new(description.fps, description.adaptive_pacing);
+
+        let mut frame = VideoFrame::default();
+        frame.sub_format = VideoSubFormat::SW;
+        frame.rotation = description.source.rotation;
+
+        Ok(Box::new(AppSinkStream::spawn(element, move |sample| {
+            let Some(sample) = sample else {
+                arrived.event(SourceEvent::Removed);
+                return false;
+            };
+
+            let Some(structure) = sample.caps().and_then(|it| it.structure(0)) else {
+                return true;
+            };
+
+            let (Ok(format), Ok(width), Ok(height)) = (
+                structure.get::<&str>("format"),
+                structure.get::<i32>("width"),
+                structure.get::<i32>("height"),
+            ) else {
+                return true;
+            };
+
+            frame.format = match format {
+                "NV12" => VideoFormat::NV12,
+                "I420" => VideoFormat::I420,
+                _ => return true,
+            };
+
+            frame.width = width as u32;
+            frame.height = height as u32;
+            frame.timestamp = if let Some(timestamp) = pacer.accept() {
+                timestamp
+            } else {
+                return true;
+            };
+
+            let Some(map) = sample.buffer().and_then(|it| it.map_readable().ok()) else {
+                return true;
+            };
+
+            let planes = get_planes(frame.format, frame.width, frame.height);
+            let mut offset = 0;
+            for (i, (size, rows)) in planes.iter().enumerate() {
+                frame.linesize[i] = stride(*size);
+                frame.data[i] = unsafe { map.as_ptr().add(offset) as *const _ };
+                offset += stride(*size) * rows;
+            }
+
+            if map.len() < offset {
+                log::warn!("appsink sample is too small, size={}", map.len());
+
+                return true;
+            }
+
+            arrived.sink(&frame)
+        })?))
+    }
+
+    fn start_audio(
+        &self,
+        description: AudioCaptureSourceDescription,
+        mut arrived: Box<dyn FrameArrived<Frame = AudioFrame>>,
+    ) -> Result<Box<dyn CaptureStream>, CaptureError> {
+        let element = self.element(&description.source)?;
+        element.set_caps(Some(
+            &Caps::builder("audio/x-raw")
+                .field("format", "S16LE")
+                .field("layout", "interleaved")
+                .field("rate", description.sample_rate as i32)
+                .field("channels", 1)
+                .build(),
+        ));
+
+        let mut samples = Vec::new();
+        let mut frame = AudioFrame::default();
+        frame.sample_rate = description.sample_rate;
+
+        Ok(Box::new(AppSinkStream::spawn(element, move |sample| {
+            let Some(sample) = sample else {
+                arrived.event(SourceEvent::Removed);
+                return false;
+            };
+
+            let Some(map) = sample.buffer().and_then(|it| it.map_readable().ok()) else {
+                return true;
+            };
+
+            // The samples are copied, the memory of the buffer may not be aligned.
+            samples.clear();
+            samples.extend(
+                map.chunks_exact(2)
+                    .map(|it| i16::from_le_bytes([it[0], it[1]])),
+            );
+
+            if samples.is_empty() {
+                return true;
+            }
+
+            frame.frames = samples.len() as u32;
+            frame.data = samples.as_ptr();
+            // The sample is pulled when it is complete, its first sample was captured
+            // one sample duration ago.
+            frame.timestamp = MediaClock::now()
+                .saturating_sub(frame.frames as u64 * 1_000_000 / frame.sample_rate.max(1) as u64);
+
+            arrived.sink(&frame)
+        })?))
+    }
+}
@@ -0,0 +1,128 @@
+use crate::{
+    output::{MuxerOutput, MuxerOutputOptions, StreamOutputEvent},
+    packets::{EncodedCodecParameters, EncodedPacketSink},
+};
+
+use std::{fs, path::PathBuf, time::Duration};
+
+use hylarana_codec::StreamMuxerSettings;
+
+/// Options of an HLS output.
+#[derive(Debug, Clone)]
+pub struct HlsOutputOptions {
+    /// The directory of the playlist and of the segments, it is served as it
+    /// is by an HTTP server. The directory is created if it does not exist.
+    pub directory: PathBuf,
+    /// The file name of the playlist, `index.m3u8` by default.
+    pub playlist: String,
+    /// The target duration of the segments, 1 second by default. The segments
+    /// start with a key frame, so the key frame interval of the sender should
+    /// not be longer than this.
+    pub segment_duration: Duration,
+    /// How many segments are listed in the playlist, the older segments are
+    /// deleted. 6 by default.
+    pub playlist_size: usize,
+    /// The bit rate of the AAC audio, 128 kbps by default.
+    pub audio_bit_rate: u64,
+    /// How many packets can wait for the disk, when the queue is full the
+    /// video is dropped up to the next key frame. 256 by default.
+    pub queue_size: usize,
+}
+
+impl Default for HlsOutputOptions {
+    fn default() -> Self {
+        Self {
+            directory: PathBuf::new(),
+            playlist: "index.m3u8".to_string(),
+            segment_duration: Duration::from_secs(1),
+            playlist_size: 6,
+            audio_bit_rate: 128_000,
+            queue_size: 256,
+        }
+    }
+}
+
+/// Writes the stream of a sender as fMP4 (CMAF) segments and an HLS playlist,
+/// so that a plain HTTP server can serve the session to many viewers that
+/// only watch it, such as in a browser, see `HylaranaSender::set_packet_sink`.
+///
+/// The video is passed as it is, the audio is transcoded from Opus to AAC.
+/// With the default options the players are about three seconds behind the
+/// sender, the partial segments of the low latency HLS are not written. When
+/// the parameters of the stream change, such as the size of the video, the
+/// playlist is started again.
+pub struct HlsOutput(MuxerOutput);
+
+impl HlsOutput {
+    pub fn new(options: HlsOutputOptions) -> std::io::Result<Self> {
+        fs::create_dir_all(&options.directory)?;
+
+        let option = |key: &str, value: String| (key.to_string(), value);
+        Ok(Self(MuxerOutput::new(MuxerOutputOptions {
+            name: "hls",
+            thread: "HylaranaHlsOutputThread",
+            settings: StreamMuxerSettings {
+                url: options
+                    .directory
+                    .join(&options.playlist)
+                    .to_string_lossy()
+                    .to_string(),
+                format: Some("hls".to_string()),
+                options: vec![
+                    option("hls_segment_type", "fmp4".to_string()),
+                    option(
+                        "hls_time",
+                        options.segment_duration.as_secs_f64().to_string(),
+                    ),
+                    option("hls_list_size", options.playlist_size.to_string()),
+                    option(
+                        "hls_segment_filename",
+                        options
+                            .directory
+                            .join("segment-%d.m4s")
+                            .to_string_lossy()
+                            .to_string(),
+                    ),
+                    option(
+                        "hls_flags",
+                        "delete_segments+independent_segments+temp_file".to_string(),
+                    ),
+                    // The numbers of the segments go on when the playlist is started
+                    // again, so the players do not take the new segments as old ones.
+                    option("hls_start_number_source", "epoch".to_string()),
+                ],
+                interrupt: None,
+            },
+            audio_bit_rate: options.audio_bit_rate,
+            queue_size: options.queue_size,
+            reconnect_delay: Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(30),
+        })?))
+    }
+
+    pub fn on_event<F>(self, handler: F) -> Self
+    where
+        F: Fn(StreamOutputEvent) + Send + Sync + 'static,
+    {
+        self.0.set_event_handler(handler);
+        self
+    }
+}
+
+impl EncodedPacketSink for HlsOutput {
+    fn parameters(&self, parameters: &EncodedCodecParameters) {
+        self.0.parameters(parameters)
+    }
+
+    fn video(&self, packet: &[u8], key_frame: bool, timestamp: u64) -> bool {
+        self.0.video(packet, key_frame, timestamp)
+    }
+
+    fn audio(&self, packet: &[u8], timestamp: u64) -> bool {
+        self.0.audio(packet, timestamp)
+    }
+
+    fn close(&self) {
+        self.0.close()
+    }
+}
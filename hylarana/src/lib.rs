@@ -1,52 +1,154 @@
 #![doc = include_str!("../README.md")]
 
+mod cast;
+mod catalog;
+mod context;
+mod dump;
+mod hls;
+mod local;
+mod meter;
+mod metrics;
+mod mpegts;
+mod output;
+mod pacing;
+mod packets;
+mod preflight;
+mod profile;
+mod raw;
 mod receiver;
+mod rtmp;
+mod sandbox;
 mod sender;
+mod stream;
+mod watchdog;
+mod watcher;
 
-use std::slice::from_raw_parts;
+#[cfg(feature = "gstreamer")]
+mod gst;
+
+#[cfg(target_os = "windows")]
+mod miracast;
+
+use std::{
+    slice::from_raw_parts,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::sync::watch;
 
 pub use self::{
+    cast::{CastOutput, CastOutputOptions},
+    catalog::{ErrorCatalog, ErrorCode, ErrorContext, ErrorParams},
+    context::GraphicsContext,
+    dump::set_frame_dump_directory,
+    hls::{HlsOutput, HlsOutputOptions},
+    local::{FileRecorder, FileRecorderOptions, LocalSession, RecordingSink},
+    meter::AudioLevels,
+    metrics::{FrameRates, MetricsExporter, MetricsReporter, MetricsSnapshot},
+    mpegts::{MpegTsBackend, MpegTsOutput, MpegTsOutputOptions},
+    output::StreamOutputEvent,
+    pacing::{FramePacingOptions, LateFrame},
+    packets::{EncodedCodecParameters, EncodedPacketSink},
+    preflight::{PreflightCheck, PreflightReport, PreflightStage},
+    profile::{Profile, ProfileError, ProfileFormat, Profiles},
     receiver::{
-        HylaranaReceiver, HylaranaReceiverCodecOptions, HylaranaReceiverError,
-        HylaranaReceiverOptions,
+        DecodeStats, DroppedFrames, HylaranaReceiver, HylaranaReceiverCodecOptions,
+        HylaranaReceiverError, HylaranaReceiverOptions, HylaranaReceiverStats, LatencyStats,
+        LATENCY_HISTOGRAM_BOUNDS,
     },
+    rtmp::{RtmpOutput, RtmpOutputOptions},
+    sandbox::{run_capture_helper, SandboxOptions},
     sender::{
-        AudioOptions, HylaranaSender, HylaranaSenderError, HylaranaSenderMediaOptions,
-        HylaranaSenderOptions, HylaranaSenderTrackOptions, VideoOptions,
+        AudioOptions, ContentDetectionOptions, DirtyRegionOptions, DtxOptions, EnergySaverOptions,
+        HylaranaSender, HylaranaSenderError, HylaranaSenderMediaOptions, HylaranaSenderOptions,
+        HylaranaSenderTrackOptions, PrivacyMask, PrivacyMaskOptions, SceneCutOptions,
+        ThumbnailOptions, VideoOptions,
     },
+    stream::{AsyncFrameSink, AudioFrameStream, FrameQueue, VideoFrameStream},
+    watchdog::PipelineStage,
+    watcher::{ReceiverWatcher, SenderAnnouncement},
 };
 
-pub use hylarana_capture::{Capture, Source, SourceType};
-pub use hylarana_codec::{VideoDecoderType, VideoEncoderType};
+pub use hylarana_capture::{
+    AudioCaptureConfig, AudioMixerInput, CameraControl, CameraControlRange, CameraControlValue,
+    CameraControls, CameraFormat, CameraPixelFormat, Capture, PermissionState, Source, SourceEvent,
+    SourceType,
+};
+pub use hylarana_codec::{
+    CodecCapabilities, CodecCapability, CodecStatus, ContentHint, DeinterlaceMethod,
+    PrivacyMaskStyle, RateControl, VideoDecoderType, VideoEncoderTuning, VideoEncoderType,
+    VideoProfile, WatermarkContent, WatermarkOptions, WatermarkPosition,
+};
 pub use hylarana_common::{
-    frame::{AudioFrame, VideoFormat, VideoFrame, VideoSubFormat},
-    Size,
+    clock::MediaClock,
+    frame::{
+        AudioFrame, DmaBuf, FieldOrder, FramePool, OwnedAudioFrame, OwnedVideoFrame, VideoFormat,
+        VideoFrame, VideoRotation, VideoSubFormat,
+    },
+    input::{InputEvent, MouseButton},
+    power::PowerSource,
+    runtime::{
+        apply_thread_options, set_runtime_options, RuntimeOptions, ThreadKind, ThreadOptions,
+        ThreadPriority,
+    },
+    AdapterPreference, Size,
+};
+
+pub use hylarana_discovery::{CastDevice, DiscoveryError, DiscoveryEvent, DiscoveryService};
+pub use hylarana_graphics::{
+    enumerate_adapters, raw_window_handle, FitMode, GraphicsAdapter, MosaicGrid, RgbaImage,
+    ScaleFilter, SurfaceTarget,
 };
+pub use hylarana_transport::{
+    set_dump_directory as set_transport_dump_directory, BandwidthEstimate, DscpOptions,
+    KeepaliveOptions, MulticastOptions, PacketPacingOptions, PairingOptions, ReceiverInfo,
+    SharedMemoryOptions, StreamTracks, TransportOptions, TransportStrategy,
+};
+
+#[cfg(feature = "external-texture")]
+pub use hylarana_graphics::wgpu;
+
+#[cfg(feature = "gstreamer")]
+pub use self::gst::{GstAppSinkBackend, GstAppSrcFormat, GstAppSrcSink};
 
-pub use hylarana_discovery::{DiscoveryError, DiscoveryService};
-pub use hylarana_graphics::{raw_window_handle, SurfaceTarget};
-pub use hylarana_transport::{TransportOptions, TransportStrategy};
+#[cfg(feature = "gstreamer")]
+pub use {gstreamer, gstreamer_app};
+
+#[cfg(target_os = "windows")]
+pub use hylarana_capture::{VirtualDisplay, VirtualDisplayError};
+
+#[cfg(target_os = "windows")]
+pub use self::miracast::{
+    HylaranaMiracastError, HylaranaMiracastReceiver, MiracastAuthorization, MiracastEvent,
+    MiracastReceiverOptions,
+};
 
 #[cfg(target_os = "windows")]
 use hylarana_common::win32::{
-    d3d_texture_borrowed_raw, set_process_priority, shutdown as win32_shutdown,
-    startup as win32_startup, windows::Win32::Foundation::HWND, Direct3DDevice, ProcessPriority,
+    d3d_texture_borrowed_raw, shutdown as win32_shutdown, startup as win32_startup,
+    windows::Win32::{Foundation::HWND, Graphics::Direct3D11::ID3D11Texture2D},
+    Direct3DDevice,
 };
 
 #[cfg(target_os = "macos")]
-use hylarana_common::macos::{CVPixelBufferRef, PixelBufferRef};
+use hylarana_common::macos::CVPixelBufferRef;
 
+use hylarana_common::atomic::EasyAtomic;
 use parking_lot::Mutex;
-
-#[cfg(target_os = "windows")]
-use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
 
 #[cfg(target_os = "windows")]
 use hylarana_graphics::dx11::Dx11Renderer;
 
+use self::pacing::FramePacer;
+
 use hylarana_graphics::{
-    Renderer as WgpuRenderer, RendererOptions as WgpuRendererOptions, Texture, Texture2DBuffer,
-    Texture2DResource,
+    MosaicRenderer, MosaicRendererOptions, Renderer as WgpuRenderer,
+    RendererOptions as WgpuRendererOptions, Texture, Texture2DBuffer, Texture2DResource,
 };
 
 use rodio::{OutputStream, OutputStreamHandle, Sink};
@@ -70,16 +172,6 @@ pub fn startup() -> Result<(), HylaranaError> {
         log::warn!("{:?}", e);
     }
 
-    // In order to prevent other programs from affecting the delay performance of
-    // the current program, set the priority of the current process to high.
-    #[cfg(target_os = "windows")]
-    if set_process_priority(ProcessPriority::High).is_err() {
-        log::error!(
-            "failed to set current process priority, Maybe it's \
-            because you didn't run it with administrator privileges."
-        );
-    }
-
     #[cfg(target_os = "linux")]
     hylarana_capture::startup();
 
@@ -109,12 +201,360 @@ pub fn shutdown() -> Result<(), HylaranaError> {
     Ok(())
 }
 
+/// The metadata of the stream, which the receivers get before the decoding
+/// starts, so that they can show what the stream is.
+///
+/// The metadata is serialized as json, so it can also be published in the
+/// properties of the discovery service.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamMetadata {
+    pub title: String,
+    pub device_name: String,
+    /// The codecs of the stream, such as `h264` and `opus`, the sender fills
+    /// them in from the media options if they are empty.
+    pub codecs: Vec<String>,
+    /// The resolution of the video, the sender fills it in from the media
+    /// options if it is zero.
+    pub width: u32,
+    pub height: u32,
+    /// The capabilities of the sender, the sender fills them in if they are
+    /// empty. The metadata of older senders does not have them.
+    #[serde(default)]
+    pub capabilities: StreamCapabilities,
+}
+
+/// An optional feature of the stream.
+///
+/// The features that this version does not know are read as `Unknown`, so
+/// the peers of newer versions can advertise new features without breaking
+/// the older ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamFeature {
+    /// The receivers control the sender with the mouse and keyboard.
+    Input,
+    /// The data channel of `send_message`.
+    Message,
+    /// The receivers request a key frame when they join in the middle of the
+    /// stream.
+    KeyFrameRequest,
+    /// The sender pauses the stream without closing it.
+    Pause,
+    /// The clock of the sender, for measuring the latency.
+    Clock,
+    /// The receiver follows the sender when it switches the video codec.
+    CodecSwitch,
+    #[serde(other)]
+    Unknown,
+}
+
+impl StreamFeature {
+    /// The features of this version.
+    pub const ALL: [Self; 6] = [
+        Self::Input,
+        Self::Message,
+        Self::KeyFrameRequest,
+        Self::Pause,
+        Self::Clock,
+        Self::CodecSwitch,
+    ];
+}
+
+/// What a sender or a receiver supports. The sender advertises its
+/// capabilities in the metadata, and each receiver sends its capabilities back
+/// when it is created, the stream only uses what both sides support.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StreamCapabilities {
+    /// The codecs that can be encoded or decoded, such as `h264`, `hevc` and
+    /// `opus`.
+    #[serde(default)]
+    pub codecs: Vec<String>,
+    #[serde(default)]
+    pub features: Vec<StreamFeature>,
+}
+
+impl StreamCapabilities {
+    pub fn supports_codec(&self, codec: &str) -> bool {
+        self.codecs.iter().any(|it| it == codec)
+    }
+
+    pub fn supports(&self, feature: StreamFeature) -> bool {
+        feature != StreamFeature::Unknown && self.features.contains(&feature)
+    }
+
+    /// The capabilities that both sides support, the unknown features are
+    /// dropped.
+    pub fn intersect(&self, other: &Self) -> Self {
+        Self {
+            codecs: self
+                .codecs
+                .iter()
+                .filter(|it| other.supports_codec(it))
+                .cloned()
+                .collect(),
+            features: self
+                .features
+                .iter()
+                .copied()
+                .filter(|it| other.supports(*it))
+                .collect(),
+        }
+    }
+}
+
+// The name of the video codec in the metadata and the capabilities, HEVC is
+// always 10-bit.
+pub(crate) fn video_codec_name<T: Into<hylarana_codec::CodecType>>(codec: T) -> &'static str {
+    if codec.into().is_10bit() {
+        "hevc"
+    } else {
+        "h264"
+    }
+}
+
+// The first byte of the messages that the receiver sends back to the sender.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum MessageKind {
+    Input = 1,
+    Data = 2,
+    KeyFrame = 3,
+    Capabilities = 4,
+    Goodbye = 5,
+}
+
+// The minimum interval between the key frame requests, the receiver does not
+// send them more often, and the sender ignores the requests that arrive faster,
+// so that many receivers joining at once do not flood the stream with key
+// frames.
+pub(crate) const KEY_FRAME_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The kind of error that ended the stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamErrorKind {
+    Capture,
+    Encode,
+    Decode,
+    Transport,
+}
+
+/// Why the stream has ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DisconnectReason {
+    /// The sender or the receiver is closed locally, such as being dropped.
+    Closed,
+    /// The sink returned false for a frame.
+    SinkClosed,
+    /// The transport is closed without the remote side saying goodbye, such
+    /// as the network being disconnected or the remote side crashing.
+    TransportClosed,
+    /// The remote side has closed the stream on purpose. A multicast receiver
+    /// keeps waiting for the packets of a new sender with the same ID, it is
+    /// not closed by the sender.
+    RemoteClosed,
+    /// The sender has disconnected the receiver, see
+    /// `HylaranaSender::disconnect`.
+    Kicked,
+    /// The capture source has been removed, such as the display being
+    /// unplugged.
+    SourceRemoved,
+    /// An error occurred in the media pipeline.
+    Error(StreamErrorKind),
+}
+
+/// Why the sender has paused by itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    /// The session is locked, the lock screen cannot be captured.
+    SystemLock,
+    /// The system is going to sleep.
+    SystemSuspend,
+}
+
+/// What the video of the sender shows, see `ContentDetectionOptions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VideoContentState {
+    /// The video changes as usual.
+    Normal,
+    /// The video has been black for a while, such as a display that is turned
+    /// off or a protected window.
+    Black,
+    /// The video has not changed for a while, the capture delivers the same
+    /// frame again and again.
+    Frozen,
+}
+
+/// Events of the audio and video stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamEvent {
+    /// The receiver has received the first packet of the stream.
+    Connected,
+    /// The stream has ended, this is delivered before the close callback.
+    Disconnected { reason: DisconnectReason },
+    /// The remote side has requested a key frame.
+    KeyframeRequested,
+    /// A receiver has closed its stream on purpose, the receivers that are
+    /// cut off by the network are dropped by the transport silently.
+    ReceiverClosed,
+    /// The bit rate of the video encoder has changed, in bits per second.
+    BitrateChanged { bit_rate: u64 },
+    /// The video decoder could not be created, and the receiver fell back to
+    /// another decoder.
+    DecoderFallback {
+        from: VideoDecoderType,
+        to: VideoDecoderType,
+    },
+    /// The video encoder could not be created or failed while encoding, and
+    /// the sender fell back to another encoder of the same codec, the stream
+    /// continues with it.
+    EncoderFallback {
+        from: VideoEncoderType,
+        to: VideoEncoderType,
+    },
+    /// A receiver cannot decode the codec of the stream, and the sender
+    /// switched to a codec that all receivers support.
+    EncoderSwitched {
+        from: VideoEncoderType,
+        to: VideoEncoderType,
+    },
+    /// The sender switched the codec of the stream, and the receiver switched
+    /// to a decoder of the new codec.
+    DecoderSwitched {
+        from: VideoDecoderType,
+        to: VideoDecoderType,
+    },
+    /// An error occurred in the media pipeline, the stream is closed after
+    /// this.
+    Error(StreamErrorKind),
+    /// A stage of the media pipeline has not made any progress for several
+    /// seconds and could not be recovered, such as a hung capture or a removed
+    /// encoder device. The stream is closed after this.
+    Stalled { stage: PipelineStage },
+    /// The sender has paused the capture by itself, the receivers keep the
+    /// last frame.
+    Paused { reason: PauseReason },
+    /// The sender has resumed the capture after pausing by itself.
+    Resumed,
+    /// The sender has turned the energy saver on or off, as the system has
+    /// switched between the battery and the AC power, see
+    /// `EnergySaverOptions`.
+    EnergySaver { enabled: bool },
+    /// The sender of the stream has restarted with the same ID and the
+    /// receiver has reattached to it, see `TransportOptions::resume`. The
+    /// video continues with the first key frame of the new sender.
+    Reattached,
+    /// The video of the sender has become black or frozen, or has changed
+    /// again, see `ContentDetectionOptions`.
+    VideoContent { state: VideoContentState },
+}
+
 /// Audio and video streaming events observer.
 pub trait AVFrameObserver: Sync + Send {
     /// Callback when the sender is closed. This may be because the external
     /// side actively calls the close, or the audio and video packets cannot be
-    /// sent (the network is disconnected), etc.
+    /// sent (the network is disconnected), etc. The reason is delivered by the
+    /// `StreamEvent::Disconnected` event before this.
     fn close(&self) {}
+
+    /// Callback with the events of the stream.
+    #[allow(unused_variables)]
+    fn event(&self, event: StreamEvent) {}
+
+    /// Callback when the sender pauses the stream, no frames arrive until the
+    /// sender resumes, and the player keeps the last frame on the screen.
+    fn pause(&self) {}
+
+    /// Callback when the sender resumes the stream.
+    fn resume(&self) {}
+
+    /// Callback with the statistics of the receiver, this is called about once
+    /// per second while receiving.
+    #[allow(unused_variables)]
+    fn stats(&self, stats: &HylaranaReceiverStats) {}
+
+    /// Callback with the levels of the audio, about ten times per second while
+    /// the audio flows, so that the user interfaces can draw the meters. The
+    /// sender measures the mixed audio that it sends, the capture helper of
+    /// the sandbox mode does not report it, and the receiver measures the
+    /// audio that it plays.
+    #[allow(unused_variables)]
+    fn audio_levels(&self, levels: AudioLevels) {}
+
+    /// Callback when the receiver gets the metadata of the stream, this is
+    /// called before the first frame, and again if the metadata changes.
+    #[allow(unused_variables)]
+    fn metadata(&self, metadata: &StreamMetadata) {}
+
+    /// Callback with the messages of the data channel, which are sent by the
+    /// other side with `send_message`, the sender receives the messages of all
+    /// receivers.
+    #[allow(unused_variables)]
+    fn message(&self, message: &[u8]) {}
+
+    /// Callback with the thumbnails of the stream, which are JPEG images that
+    /// the sender publishes at a low rate, see `VideoOptions::thumbnail`.
+    #[allow(unused_variables)]
+    fn thumbnail(&self, image: &[u8]) {}
+
+    /// Callback with the pairing code of the sender, which the application
+    /// shows to the presenter. This is called when the pairing is turned on
+    /// and whenever a new code is generated, see `PairingOptions`.
+    #[allow(unused_variables)]
+    fn pairing_code(&self, code: &str) {}
+
+    /// Callback when the sender requires a pairing code, the application asks
+    /// the user for the code that the sender shows. The attempt is the number
+    /// of the codes that have been rejected. Returning `None` gives up, and
+    /// the receiver fails to be created.
+    #[allow(unused_variables)]
+    fn pairing_code_required(&self, attempt: u32) -> Option<String> {
+        None
+    }
+}
+
+// Whether the stream is closed, the change is also published to the tasks that
+// wait for the close.
+pub(crate) struct StreamStatus {
+    closed: AtomicBool,
+    notify: watch::Sender<bool>,
+}
+
+impl StreamStatus {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            closed: AtomicBool::new(false),
+            notify: watch::Sender::new(false),
+        })
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
+    pub(crate) async fn closed(&self) {
+        let _ = self.notify.subscribe().wait_for(|it| *it).await;
+    }
+}
+
+// Close the stream only once, the observer receives the reason before the close
+// callback.
+pub(crate) fn close_stream<T: AVFrameObserver>(
+    status: &StreamStatus,
+    observer: &T,
+    reason: DisconnectReason,
+) {
+    if !status.closed.update(true) {
+        log::info!("stream closed, reason={:?}", reason);
+
+        if let DisconnectReason::Error(kind) = reason {
+            observer.event(StreamEvent::Error(kind));
+        }
+
+        observer.event(StreamEvent::Disconnected { reason });
+        observer.close();
+
+        status.notify.send_replace(true);
+    }
 }
 
 /// Streaming sink for audio and video frames.
@@ -140,6 +580,46 @@ pub trait AVFrameSink: Sync + Send {
     fn audio(&self, frame: &AudioFrame) -> bool {
         true
     }
+
+    /// Callback for the encoded video packets of a receiver that passes the
+    /// packets through, see `HylaranaReceiverCodecOptions::passthrough`. The
+    /// packets are in the Annex B format, the first packet and the packets
+    /// after a codec switch are the configuration of the encoder, their flags
+    /// contain `BufferFlag::Config`.
+    ///
+    /// Returning `false` causes the stream to close.
+    #[allow(unused_variables)]
+    fn video_packet(&self, packet: &[u8], flags: i32, timestamp: u64) -> bool {
+        true
+    }
+
+    /// Callback for the encoded audio packets of a receiver that passes the
+    /// packets through, the packets are Opus, the first packet is the Opus
+    /// identification header.
+    ///
+    /// Returning `false` causes the stream to close.
+    #[allow(unused_variables)]
+    fn audio_packet(&self, packet: &[u8], flags: i32, timestamp: u64) -> bool {
+        true
+    }
+
+    /// The state of the buffers of the sink, the receiver adds it to its
+    /// statistics. The sinks that do not buffer the frames keep the default.
+    fn buffer_stats(&self) -> SinkBufferStats {
+        SinkBufferStats::default()
+    }
+}
+
+/// The buffers of a sink, see `AVFrameSink::buffer_stats`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SinkBufferStats {
+    /// The frames that are dropped because the queue of the sink is full.
+    pub overflow: u64,
+    /// The duration of the audio that is waiting to be played, the video is
+    /// shown as soon as it arrives in most sinks.
+    pub buffered: Duration,
+    /// The times the audio output ran out of samples.
+    pub audio_underruns: u64,
 }
 
 /// Abstraction of audio and video streams.
@@ -163,6 +643,36 @@ impl Hylarana {
         Ok(sender)
     }
 
+    /// Check the settings of a sender before it is needed, such as before a
+    /// meeting starts. The capture of each source, the encoders and the
+    /// transport are started and stopped again one after another, and the
+    /// result of each of them is reported, no stream is sent.
+    ///
+    /// The stages run in this process, the sandbox option is ignored. The
+    /// transport is released shortly after this returns, a sender that binds
+    /// the same address right away may have to retry.
+    pub fn preflight(options: &HylaranaSenderOptions) -> PreflightReport {
+        log::info!("preflight: options={:?}", options);
+
+        let report = preflight::preflight(options);
+        log::info!("preflight done: ok={}", report.is_ok());
+
+        report
+    }
+
+    /// Creates a local session, the media is captured and encoded like a
+    /// sender, but the packets go to the recording sink instead of a
+    /// transport, such as a `FileRecorder`.
+    pub fn create_local_session<T: AVFrameStream + 'static, R: RecordingSink + 'static>(
+        options: HylaranaSenderMediaOptions,
+        recorder: R,
+        sink: T,
+    ) -> Result<LocalSession<T>, HylaranaSenderError> {
+        log::info!("create local session: options={:?}", options);
+
+        LocalSession::new(options, recorder, sink)
+    }
+
     /// To create a receiver, you need to specify the sender's ID to associate
     /// with it.
     pub fn create_receiver<T: AVFrameStream + 'static>(
@@ -174,21 +684,88 @@ impl Hylarana {
 
         HylaranaReceiver::new(id, options.clone(), sink)
     }
-}
 
-#[cfg(target_os = "windows")]
-static DIRECT_3D_DEVICE: RwLock<Option<Direct3DDevice>> = RwLock::new(None);
+    /// Same as `create_sender`, but the capture, the encoders and the transport
+    /// are created on the blocking thread pool of tokio, so that the async
+    /// runtime is not blocked while they start.
+    pub async fn create_sender_async<T: AVFrameStream + 'static>(
+        options: HylaranaSenderOptions,
+        sink: T,
+    ) -> Result<HylaranaSender<T>, HylaranaSenderError>
+    where
+        HylaranaSender<T>: Send,
+    {
+        tokio::task::spawn_blocking(move || Self::create_sender(options, sink))
+            .await
+            .map_err(std::io::Error::other)?
+    }
 
-// Check if the D3D device has been created. If not, create a global one.
-#[cfg(target_os = "windows")]
-pub(crate) fn get_direct3d() -> Direct3DDevice {
-    if DIRECT_3D_DEVICE.read().is_none() {
-        DIRECT_3D_DEVICE
-            .write()
-            .replace(Direct3DDevice::new().expect("D3D device was not initialized successfully!"));
+    /// Same as `create_receiver`, but the receiver is created on the blocking
+    /// thread pool of tokio.
+    pub async fn create_receiver_async<T: AVFrameStream + 'static>(
+        id: String,
+        options: HylaranaReceiverOptions,
+        sink: T,
+    ) -> Result<HylaranaReceiver<T>, HylaranaReceiverError>
+    where
+        HylaranaReceiver<T>: Send,
+    {
+        tokio::task::spawn_blocking(move || Self::create_receiver(id, options, sink))
+            .await
+            .map_err(std::io::Error::other)?
+    }
+
+    /// Watch the senders on the LAN and connect to them automatically, the
+    /// senders publish a `SenderAnnouncement` with `DiscoveryService::register`.
+    ///
+    /// A receiver is created for each sender that passes the filter, the
+    /// callback creates its sink and codec options, or returns `None` to skip
+    /// the sender. The receiver is closed when the sender disappears from the
+    /// discovery, and is created again if the sender is announced again after
+    /// its stream has ended.
+    ///
+    /// ```ignore
+    /// let watcher = Hylarana::watch(
+    ///     |sender| sender.metadata.title == "meeting room",
+    ///     move |_| Some((codec.clone(), AVFrameStreamPlayer::new(options, observer).ok()?)),
+    /// )?;
+    /// ```
+    pub fn watch<T, F, C>(filter: F, callback: C) -> Result<ReceiverWatcher<T>, DiscoveryError>
+    where
+        T: AVFrameStream + 'static,
+        F: Fn(&SenderAnnouncement) -> bool + Send + 'static,
+        C: Fn(&SenderAnnouncement) -> Option<(HylaranaReceiverCodecOptions, T)> + Send + 'static,
+        HylaranaReceiver<T>: Send,
+    {
+        log::info!("watch senders");
+
+        ReceiverWatcher::new(filter, callback)
+    }
+
+    /// Check which video encoders and decoders can be used on this machine, so
+    /// that the settings only offer the codecs that work. Each codec is opened
+    /// once, so this is slow and the result should be cached.
+    pub fn probe_codecs() -> CodecCapabilities {
+        let capabilities = hylarana_codec::probe(
+            #[cfg(target_os = "windows")]
+            GraphicsContext::shared().direct3d(),
+        );
+
+        log::info!("probe codecs: {:?}", capabilities);
+
+        capabilities
     }
+}
 
-    DIRECT_3D_DEVICE.read().as_ref().unwrap().clone()
+/// Set the graphics adapter of the shared graphics context, which is used by
+/// the capture, the codecs and the renderers by default, so that they all run
+/// on the same GPU, see `enumerate_adapters` for the adapters of the system.
+///
+/// This only affects the senders, receivers and players created afterwards.
+pub fn set_adapter_preference(preference: AdapterPreference) {
+    log::info!("set adapter preference={:?}", preference);
+
+    GraphicsContext::shared().set_adapter(preference);
 }
 
 #[derive(Debug, Error)]
@@ -252,6 +829,31 @@ where
     }
 }
 
+impl<'a, O> AVFrameStreamPlayer<'a, O>
+where
+    O: AVFrameObserver,
+{
+    /// Update the size of the video render when the window is resized, this
+    /// does nothing if the player does not play video.
+    pub fn resize(&self, size: Size) -> Result<(), VideoRenderError> {
+        if let Some(player) = &self.video {
+            player.lock().resize(size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read back the last rendered frame, this returns `None` if the player
+    /// does not play video.
+    pub fn snapshot(&self) -> Result<Option<RgbaImage>, VideoRenderError> {
+        Ok(if let Some(player) = &self.video {
+            Some(player.lock().snapshot()?)
+        } else {
+            None
+        })
+    }
+}
+
 impl<'a, O> AVFrameStream for AVFrameStreamPlayer<'a, O> where O: AVFrameObserver {}
 
 impl<'a, O> AVFrameObserver for AVFrameStreamPlayer<'a, O>
@@ -261,6 +863,46 @@ where
     fn close(&self) {
         self.observer.close();
     }
+
+    fn pause(&self) {
+        self.observer.pause();
+    }
+
+    fn resume(&self) {
+        self.observer.resume();
+    }
+
+    fn stats(&self, stats: &HylaranaReceiverStats) {
+        self.observer.stats(stats);
+    }
+
+    fn audio_levels(&self, levels: AudioLevels) {
+        self.observer.audio_levels(levels);
+    }
+
+    fn event(&self, event: StreamEvent) {
+        self.observer.event(event);
+    }
+
+    fn message(&self, message: &[u8]) {
+        self.observer.message(message);
+    }
+
+    fn metadata(&self, metadata: &StreamMetadata) {
+        self.observer.metadata(metadata);
+    }
+
+    fn thumbnail(&self, image: &[u8]) {
+        self.observer.thumbnail(image);
+    }
+
+    fn pairing_code(&self, code: &str) {
+        self.observer.pairing_code(code);
+    }
+
+    fn pairing_code_required(&self, attempt: u32) -> Option<String> {
+        self.observer.pairing_code_required(attempt)
+    }
 }
 
 impl<'a, O> AVFrameSink for AVFrameStreamPlayer<'a, O>
@@ -294,6 +936,13 @@ where
             true
         }
     }
+
+    fn buffer_stats(&self) -> SinkBufferStats {
+        self.audio
+            .as_ref()
+            .map(|it| it.buffer_stats())
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Error)]
@@ -306,6 +955,9 @@ pub enum VideoRenderError {
     #[error("invalid d3d11texture2d texture")]
     #[cfg(target_os = "windows")]
     InvalidD3D11Texture,
+    #[error("unsupported dma-buf format")]
+    #[cfg(target_os = "linux")]
+    NotSupportDmaBufFormat,
 }
 
 #[derive(Debug, Error)]
@@ -321,15 +973,13 @@ pub enum AudioRenderError {
 }
 
 struct AudioSamples {
-    sample_rate: u32,
-    buffer: Vec<i16>,
+    frame: OwnedAudioFrame,
     index: usize,
-    frames: usize,
 }
 
 impl rodio::Source for AudioSamples {
     fn current_frame_len(&self) -> Option<usize> {
-        Some(self.frames)
+        Some(self.frame.frames as usize)
     }
 
     fn channels(&self) -> u16 {
@@ -337,7 +987,7 @@ impl rodio::Source for AudioSamples {
     }
 
     fn sample_rate(&self) -> u32 {
-        self.sample_rate
+        self.frame.sample_rate
     }
 
     fn total_duration(&self) -> Option<std::time::Duration> {
@@ -349,7 +999,7 @@ impl Iterator for AudioSamples {
     type Item = i16;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let item = self.buffer.get(self.index).map(|it| *it);
+        let item = self.frame.samples().get(self.index).copied();
         self.index += 1;
         item
     }
@@ -358,9 +1008,7 @@ impl Iterator for AudioSamples {
 impl From<&AudioFrame> for AudioSamples {
     fn from(frame: &AudioFrame) -> Self {
         Self {
-            buffer: unsafe { from_raw_parts(frame.data, frame.frames as usize) }.to_vec(),
-            sample_rate: frame.sample_rate,
-            frames: frame.frames as usize,
+            frame: frame.to_owned(),
             index: 0,
         }
     }
@@ -373,6 +1021,10 @@ pub struct AudioRender {
     #[allow(dead_code)]
     stream_handle: OutputStreamHandle,
     sink: Sink,
+    // The duration of the last frame in microseconds, the frames of a stream
+    // have the same duration.
+    frame_duration: AtomicU64,
+    underruns: AtomicU64,
 }
 
 unsafe impl Send for AudioRender {}
@@ -386,6 +1038,8 @@ impl AudioRender {
 
         sink.play();
         Ok(Self {
+            frame_duration: AtomicU64::new(0),
+            underruns: AtomicU64::new(0),
             stream_handle,
             stream,
             sink,
@@ -394,9 +1048,30 @@ impl AudioRender {
 
     /// Push an audio clip to the queue.
     pub fn send(&self, frame: &AudioFrame) -> Result<(), AudioRenderError> {
+        // The output has played everything before the clip arrived, there is a gap
+        // in the audio.
+        if self.sink.empty() && self.frame_duration.get() > 0 {
+            self.underruns.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if frame.sample_rate > 0 {
+            self.frame_duration
+                .update(frame.frames as u64 * 1_000_000 / frame.sample_rate as u64);
+        }
+
         self.sink.append(AudioSamples::from(frame));
         Ok(())
     }
+
+    /// The audio that is waiting to be played and the underruns of the
+    /// output.
+    pub fn buffer_stats(&self) -> SinkBufferStats {
+        SinkBufferStats {
+            buffered: Duration::from_micros(self.sink.len() as u64 * self.frame_duration.get()),
+            audio_underruns: self.underruns.get(),
+            ..Default::default()
+        }
+    }
 }
 
 impl Drop for AudioRender {
@@ -423,41 +1098,65 @@ pub struct VideoRenderOptions<T> {
     pub backend: VideoRenderBackend,
     /// The size of the target window.
     pub size: Size,
+    /// How the video is fitted into the window.
+    pub fit: FitMode,
+    /// The filter used when scaling the video, only the WebGPU backend
+    /// supports it.
+    pub filter: ScaleFilter,
     /// Renders the target's window.
     pub target: T,
+    /// The context of the receiver whose frames are rendered.
+    pub graphics: GraphicsContext,
+    /// Pace the frames to the refresh of the display, the frames are rendered
+    /// as soon as they are decoded if this is `None`.
+    pub pacing: Option<FramePacingOptions>,
 }
 
-/// Video player that can render video frames to window.
-pub enum VideoRender<'a> {
+enum VideoRenderer<'a> {
     WebGPU(WgpuRenderer<'a>),
     #[cfg(target_os = "windows")]
     Direct3D11(Dx11Renderer),
 }
 
+/// Video player that can render video frames to window.
+pub struct VideoRender<'a> {
+    renderer: VideoRenderer<'a>,
+    pacer: Option<FramePacer>,
+    #[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+    graphics: GraphicsContext,
+}
+
 impl<'a> VideoRender<'a> {
     /// Create a video player.
     pub fn new<T>(
         VideoRenderOptions {
             backend,
             size,
+            fit,
+            filter,
             target,
+            graphics,
+            pacing,
         }: VideoRenderOptions<T>,
     ) -> Result<Self, VideoRenderError>
     where
         T: Into<SurfaceTarget<'a>>,
     {
         log::info!(
-            "create video render, backend={:?}, size={:?}",
+            "create video render, backend={:?}, size={:?}, fit={:?}, filter={:?}, pacing={:?}",
             backend,
-            size
+            size,
+            fit,
+            filter,
+            pacing,
         );
 
         #[cfg(target_os = "windows")]
-        let direct3d = get_direct3d();
+        let direct3d = graphics.direct3d();
 
-        Ok(match backend {
+        let renderer = match backend {
             #[cfg(target_os = "windows")]
-            VideoRenderBackend::Direct3D11 => Self::Direct3D11(Dx11Renderer::new(
+            VideoRenderBackend::Direct3D11 => VideoRenderer::Direct3D11(Dx11Renderer::new(
                 match target.into() {
                     SurfaceTarget::Window(window) => match window.window_handle().unwrap().as_raw()
                     {
@@ -473,178 +1172,641 @@ impl<'a> VideoRender<'a> {
                     }
                 },
                 size,
+                fit,
                 direct3d,
             )?),
-            VideoRenderBackend::WebGPU => Self::WebGPU(WgpuRenderer::new(WgpuRendererOptions {
-                window: target,
-                #[cfg(target_os = "windows")]
-                direct3d,
-                size,
-            })?),
+            VideoRenderBackend::WebGPU => {
+                VideoRenderer::WebGPU(WgpuRenderer::new(WgpuRendererOptions {
+                    window: target,
+                    #[cfg(target_os = "windows")]
+                    direct3d,
+                    adapter: graphics.adapter(),
+                    size,
+                    fit,
+                    filter,
+                })?)
+            }
             #[allow(unreachable_patterns)]
             _ => unimplemented!("not supports the {:?} backend", backend),
+        };
+
+        Ok(Self {
+            pacer: pacing.map(FramePacer::new),
+            renderer,
+            graphics,
+        })
+    }
+
+    /// Create a video player that draws to a D3D11 texture of the application
+    /// instead of a window, such as a render texture of a game engine. The
+    /// texture has to be created on the device, which is usually the device
+    /// of the application, and the player has to be used on the thread that
+    /// owns the immediate context of the device.
+    #[cfg(target_os = "windows")]
+    pub fn with_d3d11_texture(
+        texture: ID3D11Texture2D,
+        fit: FitMode,
+        direct3d: Direct3DDevice,
+    ) -> Result<Self, VideoRenderError> {
+        log::info!("create video render with d3d11 texture, fit={:?}", fit);
+
+        Ok(Self {
+            renderer: VideoRenderer::Direct3D11(Dx11Renderer::with_texture(
+                texture, fit, direct3d,
+            )?),
+            // The application presents the texture by itself.
+            pacer: None,
+            // The device of the application is not created again when it has been
+            // removed, the renderer of a texture cannot be recovered.
+            graphics: GraphicsContext::shared(),
+        })
+    }
+
+    /// Update the size of the render target when the window is resized, the
+    /// size is in physical pixels, so the logical size of the window needs to
+    /// be multiplied by the scale factor of the display.
+    pub fn resize(&mut self, size: Size) -> Result<(), VideoRenderError> {
+        log::info!("video render resize, size={:?}", size);
+
+        match &mut self.renderer {
+            #[cfg(target_os = "windows")]
+            VideoRenderer::Direct3D11(render) => render.resize(size)?,
+            VideoRenderer::WebGPU(render) => render.resize(size),
+        }
+
+        Ok(())
+    }
+
+    /// Read back the last rendered frame as it is displayed in the window, such
+    /// as for thumbnails.
+    pub fn snapshot(&mut self) -> Result<RgbaImage, VideoRenderError> {
+        Ok(match &mut self.renderer {
+            #[cfg(target_os = "windows")]
+            VideoRenderer::Direct3D11(render) => render.snapshot()?,
+            VideoRenderer::WebGPU(render) => render.snapshot()?,
         })
     }
 
     /// Push video frames to the queue and the player will render them as
-    /// quickly as possible, basically in real time.
+    /// quickly as possible, basically in real time. With the pacing, this
+    /// blocks until the refresh that the frame is shown on, and the frames
+    /// that do not get a refresh are dropped.
+    ///
+    /// When the device has been removed, such as by a GPU reset, the frame is
+    /// dropped and the renderer continues on a new device. The renderer of a
+    /// texture of the application cannot be recovered, the application has to
+    /// create the texture and the player again.
     pub fn send(&mut self, frame: &VideoFrame) -> Result<(), VideoRenderError> {
-        match frame.sub_format {
+        if let Some(pacer) = self.pacer.as_mut() {
+            if !pacer.wait(frame.timestamp) {
+                return Ok(());
+            }
+        }
+
+        let result = self.render(frame);
+
+        #[cfg(target_os = "windows")]
+        if result.is_err() && self.is_device_removed() {
+            log::warn!("video render device has been removed, recreate the renderer");
+
+            let direct3d = self.graphics.direct3d();
+            match &mut self.renderer {
+                VideoRenderer::Direct3D11(render) => render.set_direct3d(direct3d)?,
+                VideoRenderer::WebGPU(render) => render.set_direct3d(direct3d),
+            }
+
+            return Ok(());
+        }
+
+        result
+    }
+
+    #[cfg(target_os = "windows")]
+    fn is_device_removed(&self) -> bool {
+        match &self.renderer {
+            VideoRenderer::Direct3D11(render) => render.is_device_removed(),
+            VideoRenderer::WebGPU(render) => render.is_device_removed(),
+        }
+    }
+
+    fn render(&mut self, frame: &VideoFrame) -> Result<(), VideoRenderError> {
+        match &mut self.renderer {
             #[cfg(target_os = "windows")]
-            VideoSubFormat::D3D11 => {
-                let texture =
-                    Texture2DResource::Texture(hylarana_graphics::Texture2DRaw::ID3D11Texture2D(
-                        d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
-                            .ok_or_else(|| VideoRenderError::InvalidD3D11Texture)?
-                            .clone(),
-                        frame.data[1] as u32,
-                    ));
-
-                let texture = match frame.format {
-                    VideoFormat::BGRA => Texture::Bgra(texture),
-                    VideoFormat::RGBA => Texture::Rgba(texture),
-                    VideoFormat::NV12 => Texture::Nv12(texture),
-                    VideoFormat::I420 => unimplemented!("no hardware texture for I420"),
-                };
-
-                match self {
-                    Self::Direct3D11(render) => render.submit(texture)?,
-                    Self::WebGPU(render) => render.submit(texture)?,
-                }
+            VideoRenderer::Direct3D11(render) => {
+                render.set_orientation(frame.rotation, frame.mirror)?;
+                render.set_display_size(frame.display_size());
+            }
+            VideoRenderer::WebGPU(render) => {
+                render.set_orientation(frame.rotation, frame.mirror);
+                render.set_display_size(frame.display_size());
             }
-            #[cfg(target_os = "macos")]
-            VideoSubFormat::CvPixelBufferRef => {
-                let pixel_buffer = PixelBufferRef::from(frame.data[0] as CVPixelBufferRef);
-                let linesize = pixel_buffer.linesize();
-                let data = pixel_buffer.data();
-                let size = pixel_buffer.size();
-
-                let buffers = [
+        }
+
+        frame_to_texture(frame, |texture| {
+            match &mut self.renderer {
+                #[cfg(target_os = "windows")]
+                VideoRenderer::Direct3D11(render) => render.submit(texture)?,
+                VideoRenderer::WebGPU(render) => render.submit(texture)?,
+            }
+
+            Ok(())
+        })
+    }
+}
+
+/// Mosaic player configuration.
+pub struct MosaicPlayerOptions<T> {
+    /// The size of the target window, in physical pixels.
+    pub size: Size,
+    /// The initial grid of the mosaic.
+    pub grid: MosaicGrid,
+    /// The filter used when scaling the videos.
+    pub filter: ScaleFilter,
+    /// Renders the target's window.
+    pub target: T,
+    /// The context of the receivers of the views.
+    pub graphics: GraphicsContext,
+}
+
+/// Video player that renders multiple video streams tiled in one window.
+///
+/// Each stream is played through its own view, which is an `AVFrameStream`
+/// that can be passed to a receiver, and the view only plays video. The
+/// mosaic is rendered using WebGPU.
+pub struct MosaicPlayer<'a>(Arc<Mutex<MosaicRenderer<'a>>>);
+
+impl<'a> MosaicPlayer<'a> {
+    pub fn new<T>(
+        MosaicPlayerOptions {
+            size,
+            grid,
+            filter,
+            target,
+            graphics,
+        }: MosaicPlayerOptions<T>,
+    ) -> Result<Self, VideoRenderError>
+    where
+        T: Into<SurfaceTarget<'a>>,
+    {
+        log::info!(
+            "create mosaic player, size={:?}, grid={:?}, filter={:?}",
+            size,
+            grid,
+            filter
+        );
+
+        Ok(Self(Arc::new(Mutex::new(MosaicRenderer::new(
+            MosaicRendererOptions {
+                #[cfg(target_os = "windows")]
+                direct3d: graphics.direct3d(),
+                adapter: graphics.adapter(),
+                window: target,
+                filter,
+                grid,
+                size,
+            },
+        )?))))
+    }
+
+    /// Create the view of the tile at the index, the tile is cleared when the
+    /// stream of the view is closed.
+    pub fn view<O: AVFrameObserver>(&self, index: usize, observer: O) -> MosaicView<'a, O> {
+        MosaicView {
+            render: self.0.clone(),
+            observer,
+            index,
+        }
+    }
+
+    pub fn set_grid(&self, grid: MosaicGrid) -> Result<(), VideoRenderError> {
+        Ok(self.0.lock().set_grid(grid)?)
+    }
+
+    /// Let the tile fill the whole window, or go back to the grid with `None`.
+    pub fn set_focus(&self, focus: Option<usize>) -> Result<(), VideoRenderError> {
+        Ok(self.0.lock().set_focus(focus)?)
+    }
+
+    /// Update the size of the render target when the window is resized, the
+    /// size is in physical pixels.
+    pub fn resize(&self, size: Size) -> Result<(), VideoRenderError> {
+        Ok(self.0.lock().resize(size)?)
+    }
+}
+
+/// A tile of the mosaic player, which plays one video stream.
+pub struct MosaicView<'a, O> {
+    render: Arc<Mutex<MosaicRenderer<'a>>>,
+    observer: O,
+    index: usize,
+}
+
+impl<'a, O> AVFrameStream for MosaicView<'a, O> where O: AVFrameObserver {}
+
+impl<'a, O> AVFrameObserver for MosaicView<'a, O>
+where
+    O: AVFrameObserver,
+{
+    fn close(&self) {
+        if let Err(e) = self.render.lock().clear(self.index) {
+            log::error!("MosaicView clear tile error={:?}", e);
+        }
+
+        self.observer.close();
+    }
+
+    fn pause(&self) {
+        self.observer.pause();
+    }
+
+    fn resume(&self) {
+        self.observer.resume();
+    }
+
+    fn stats(&self, stats: &HylaranaReceiverStats) {
+        self.observer.stats(stats);
+    }
+
+    fn audio_levels(&self, levels: AudioLevels) {
+        self.observer.audio_levels(levels);
+    }
+
+    fn event(&self, event: StreamEvent) {
+        self.observer.event(event);
+    }
+
+    fn message(&self, message: &[u8]) {
+        self.observer.message(message);
+    }
+
+    fn metadata(&self, metadata: &StreamMetadata) {
+        self.observer.metadata(metadata);
+    }
+
+    fn thumbnail(&self, image: &[u8]) {
+        self.observer.thumbnail(image);
+    }
+
+    fn pairing_code(&self, code: &str) {
+        self.observer.pairing_code(code);
+    }
+
+    fn pairing_code_required(&self, attempt: u32) -> Option<String> {
+        self.observer.pairing_code_required(attempt)
+    }
+}
+
+impl<'a, O> AVFrameSink for MosaicView<'a, O>
+where
+    O: AVFrameObserver,
+{
+    fn video(&self, frame: &VideoFrame) -> bool {
+        let mut render = self.render.lock();
+        if let Err(e) = frame_to_texture(frame, |texture| {
+            render.set_orientation(self.index, frame.rotation, frame.mirror);
+            render.submit(self.index, texture)?;
+
+            Ok(())
+        }) {
+            log::error!("MosaicView sink video error={:?}", e);
+
+            false
+        } else {
+            true
+        }
+    }
+}
+
+/// Options of a texture player, the device and the queue are the ones of the
+/// application, for bevy they are the inner devices of `RenderDevice` and
+/// `RenderQueue`, the version of wgpu has to be the same as the one that is
+/// exported by this crate.
+#[cfg(feature = "external-texture")]
+pub struct TexturePlayerOptions {
+    pub device: Arc<wgpu::Device>,
+    pub queue: Arc<wgpu::Queue>,
+    /// The size of the texture.
+    pub size: Size,
+    pub fit: FitMode,
+    pub filter: ScaleFilter,
+    /// The context of the receiver whose frames are rendered.
+    pub graphics: GraphicsContext,
+}
+
+/// A player that renders the video into a texture of the device of the
+/// application instead of a window, so that the stream can be drawn as a
+/// texture in a scene of a game engine without being read back to the memory.
+///
+/// The texture is BGRA and can be sampled and copied. The hardware frames of
+/// the decoder are imported into the device, which only works if the device
+/// uses the native backend of the platform, DX12 on windows, Vulkan on linux
+/// and Metal on macos, use a software decoder for the other backends. The
+/// audio is not played.
+#[cfg(feature = "external-texture")]
+pub struct TexturePlayer<O> {
+    render: Mutex<WgpuRenderer<'static>>,
+    observer: O,
+}
+
+#[cfg(feature = "external-texture")]
+impl<O> TexturePlayer<O>
+where
+    O: AVFrameObserver,
+{
+    pub fn new(options: TexturePlayerOptions, observer: O) -> Result<Self, VideoRenderError> {
+        log::info!(
+            "create texture player, size={:?}, fit={:?}, filter={:?}",
+            options.size,
+            options.fit,
+            options.filter,
+        );
+
+        Ok(Self {
+            render: Mutex::new(WgpuRenderer::external(
+                hylarana_graphics::ExternalRendererOptions {
+                    #[cfg(target_os = "windows")]
+                    direct3d: options.graphics.direct3d(),
+                    device: options.device,
+                    queue: options.queue,
+                    size: options.size,
+                    fit: options.fit,
+                    filter: options.filter,
+                },
+            )?),
+            observer,
+        })
+    }
+
+    /// Access the texture that the video is rendered to. The texture is locked
+    /// while the closure runs, a frame that arrives meanwhile waits, so only
+    /// record the commands that use it, such as a copy to a texture of the
+    /// engine.
+    pub fn with_texture<F, R>(&self, func: F) -> R
+    where
+        F: FnOnce(&wgpu::Texture) -> R,
+    {
+        func(
+            self.render
+                .lock()
+                .texture()
+                .expect("the texture player always has a texture"),
+        )
+    }
+
+    /// Recreate the texture with the new size, the textures previously
+    /// accessed through `with_texture` are no longer updated.
+    pub fn resize(&self, size: Size) {
+        self.render.lock().resize(size);
+    }
+}
+
+#[cfg(feature = "external-texture")]
+impl<O> AVFrameStream for TexturePlayer<O> where O: AVFrameObserver {}
+
+#[cfg(feature = "external-texture")]
+impl<O> AVFrameObserver for TexturePlayer<O>
+where
+    O: AVFrameObserver,
+{
+    fn close(&self) {
+        self.observer.close();
+    }
+
+    fn pause(&self) {
+        self.observer.pause();
+    }
+
+    fn resume(&self) {
+        self.observer.resume();
+    }
+
+    fn stats(&self, stats: &HylaranaReceiverStats) {
+        self.observer.stats(stats);
+    }
+
+    fn audio_levels(&self, levels: AudioLevels) {
+        self.observer.audio_levels(levels);
+    }
+
+    fn event(&self, event: StreamEvent) {
+        self.observer.event(event);
+    }
+
+    fn message(&self, message: &[u8]) {
+        self.observer.message(message);
+    }
+
+    fn metadata(&self, metadata: &StreamMetadata) {
+        self.observer.metadata(metadata);
+    }
+
+    fn thumbnail(&self, image: &[u8]) {
+        self.observer.thumbnail(image);
+    }
+
+    fn pairing_code(&self, code: &str) {
+        self.observer.pairing_code(code);
+    }
+
+    fn pairing_code_required(&self, attempt: u32) -> Option<String> {
+        self.observer.pairing_code_required(attempt)
+    }
+}
+
+#[cfg(feature = "external-texture")]
+impl<O> AVFrameSink for TexturePlayer<O>
+where
+    O: AVFrameObserver,
+{
+    fn video(&self, frame: &VideoFrame) -> bool {
+        let mut render = self.render.lock();
+        render.set_orientation(frame.rotation, frame.mirror);
+        render.set_display_size(frame.display_size());
+
+        if let Err(e) = frame_to_texture(frame, |texture| Ok(render.submit(texture)?)) {
+            log::error!("TexturePlayer sink video error={:?}", e);
+
+            false
+        } else {
+            true
+        }
+    }
+}
+
+/// Create a texture view of the video frame for the renderer, the buffers of a
+/// software frame are borrowed, so the texture is passed to the callback.
+fn frame_to_texture<F>(frame: &VideoFrame, submit: F) -> Result<(), VideoRenderError>
+where
+    F: FnOnce(Texture) -> Result<(), VideoRenderError>,
+{
+    match frame.sub_format {
+        #[cfg(target_os = "windows")]
+        VideoSubFormat::D3D11 => {
+            let texture =
+                Texture2DResource::Texture(hylarana_graphics::Texture2DRaw::ID3D11Texture2D(
+                    d3d_texture_borrowed_raw(&(frame.data[0] as *mut _))
+                        .ok_or_else(|| VideoRenderError::InvalidD3D11Texture)?
+                        .clone(),
+                    frame.data[1] as u32,
+                ));
+
+            let texture = match frame.format {
+                VideoFormat::BGRA => Texture::Bgra(texture),
+                VideoFormat::RGBA => Texture::Rgba(texture),
+                VideoFormat::NV12 => Texture::Nv12(texture),
+                VideoFormat::P010 => Texture::P010(texture),
+                VideoFormat::I420 => unimplemented!("no hardware texture for I420"),
+            };
+
+            submit(texture)?;
+        }
+        // The planes of the pixel buffer are imported into the renderer, they are not
+        // read by the CPU. The 10 bits pixel buffers of HEVC Main10 are P010.
+        #[cfg(target_os = "macos")]
+        VideoSubFormat::CvPixelBufferRef => {
+            let texture =
+                Texture2DResource::Texture(hylarana_graphics::Texture2DRaw::CVPixelBufferRef(
+                    frame.data[0] as CVPixelBufferRef,
+                ));
+
+            submit(match frame.format {
+                VideoFormat::P010 => Texture::P010(texture),
+                _ => Texture::Nv12(texture),
+            })?;
+        }
+        // The planes are imported into the Vulkan device of the renderer, the file
+        // descriptors stay owned by the frame.
+        #[cfg(target_os = "linux")]
+        VideoSubFormat::DmaBuf => {
+            let texture = Texture2DResource::Texture(hylarana_graphics::Texture2DRaw::DmaBuf(
+                hylarana_graphics::DmaBufTexture {
+                    format: frame.format,
+                    size: Size {
+                        width: frame.width,
+                        height: frame.height,
+                    },
+                    linesize: frame.linesize,
+                    planes: frame.dma_buf,
+                },
+            ));
+
+            // The renderer takes I420 only from the memory, its three planes are not
+            // imported, the frame is rejected instead.
+            let texture = match frame.format {
+                VideoFormat::BGRA => Texture::Bgra(texture),
+                VideoFormat::RGBA => Texture::Rgba(texture),
+                VideoFormat::NV12 => Texture::Nv12(texture),
+                VideoFormat::P010 => Texture::P010(texture),
+                VideoFormat::I420 => return Err(VideoRenderError::NotSupportDmaBufFormat),
+            };
+
+            submit(texture)?;
+        }
+        VideoSubFormat::SW => {
+            let buffers = match frame.format {
+                // RGBA stands for red green blue alpha. While it is sometimes described as a
+                // color space, it is actually a three-channel RGB color model supplemented
+                // with a fourth alpha channel. Alpha indicates how opaque each pixel is and
+                // allows an image to be combined over others using alpha compositing, with
+                // transparent areas and anti-aliasing of the edges of opaque regions. Each
+                // pixel is a 4D vector.
+                //
+                // The term does not define what RGB color space is being used. It also does
+                // not state whether or not the colors are premultiplied by the alpha value,
+                // and if they are it does not state what color space that premultiplication
+                // was done in. This means more information than just "RGBA" is needed to
+                // determine how to handle an image.
+                //
+                // In some contexts the abbreviation "RGBA" means a specific memory layout
+                // (called RGBA8888 below), with other terms such as "BGRA" used for
+                // alternatives. In other contexts "RGBA" means any layout.
+                VideoFormat::BGRA | VideoFormat::RGBA => [
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[0] as *const _,
+                            frame.linesize[0] * frame.height as usize,
+                        )
+                    },
+                    &[],
+                    &[],
+                ],
+                // YCbCr, Y′CbCr, or Y Pb/Cb Pr/Cr, also written as YCBCR or Y′CBCR, is a
+                // family of color spaces used as a part of the color image pipeline in video
+                // and digital photography systems. Y′ is the luma component and CB and CR are
+                // the blue-difference and red-difference chroma components. Y′ (with prime) is
+                // distinguished from Y, which is luminance, meaning that light intensity is
+                // nonlinearly encoded based on gamma corrected RGB primaries.
+                //
+                // Y′CbCr color spaces are defined by a mathematical coordinate transformation
+                // from an associated RGB primaries and white point. If the underlying RGB
+                // color space is absolute, the Y′CbCr color space is an absolute color space
+                // as well; conversely, if the RGB space is ill-defined, so is Y′CbCr. The
+                // transformation is defined in equations 32, 33 in ITU-T H.273. Nevertheless
+                // that rule does not apply to P3-D65 primaries used by Netflix with
+                // BT.2020-NCL matrix, so that means matrix was not derived from primaries, but
+                // now Netflix allows BT.2020 primaries (since 2021).[1] The same happens with
+                // JPEG: it has BT.601 matrix derived from System M primaries, yet the
+                // primaries of most images are BT.709.
+                // P010 has the same layout as NV12, only the samples are 16 bits, and the
+                // linesize is already in bytes.
+                VideoFormat::NV12 | VideoFormat::P010 => [
                     unsafe {
-                        from_raw_parts(data[0] as *const _, linesize[0] * size.height as usize)
+                        from_raw_parts(
+                            frame.data[0] as *const _,
+                            frame.linesize[0] * frame.height as usize,
+                        )
                     },
                     unsafe {
-                        from_raw_parts(data[1] as *const _, linesize[1] * size.height as usize)
+                        from_raw_parts(
+                            frame.data[1] as *const _,
+                            frame.linesize[1] * frame.height as usize,
+                        )
                     },
                     &[],
-                ];
-
-                match self {
-                    Self::WebGPU(render) => render.submit(Texture::Nv12(
-                        Texture2DResource::Buffer(Texture2DBuffer {
-                            buffers: &buffers,
-                            size,
-                        }),
-                    ))?,
-                }
-            }
-            VideoSubFormat::SW => {
-                let buffers = match frame.format {
-                    // RGBA stands for red green blue alpha. While it is sometimes described as a
-                    // color space, it is actually a three-channel RGB color model supplemented
-                    // with a fourth alpha channel. Alpha indicates how opaque each pixel is and
-                    // allows an image to be combined over others using alpha compositing, with
-                    // transparent areas and anti-aliasing of the edges of opaque regions. Each
-                    // pixel is a 4D vector.
-                    //
-                    // The term does not define what RGB color space is being used. It also does
-                    // not state whether or not the colors are premultiplied by the alpha value,
-                    // and if they are it does not state what color space that premultiplication
-                    // was done in. This means more information than just "RGBA" is needed to
-                    // determine how to handle an image.
-                    //
-                    // In some contexts the abbreviation "RGBA" means a specific memory layout
-                    // (called RGBA8888 below), with other terms such as "BGRA" used for
-                    // alternatives. In other contexts "RGBA" means any layout.
-                    VideoFormat::BGRA | VideoFormat::RGBA => [
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[0] as *const _,
-                                frame.linesize[0] * frame.height as usize,
-                            )
-                        },
-                        &[],
-                        &[],
-                    ],
-                    // YCbCr, Y′CbCr, or Y Pb/Cb Pr/Cr, also written as YCBCR or Y′CBCR, is a
-                    // family of color spaces used as a part of the color image pipeline in video
-                    // and digital photography systems. Y′ is the luma component and CB and CR are
-                    // the blue-difference and red-difference chroma components. Y′ (with prime) is
-                    // distinguished from Y, which is luminance, meaning that light intensity is
-                    // nonlinearly encoded based on gamma corrected RGB primaries.
-                    //
-                    // Y′CbCr color spaces are defined by a mathematical coordinate transformation
-                    // from an associated RGB primaries and white point. If the underlying RGB
-                    // color space is absolute, the Y′CbCr color space is an absolute color space
-                    // as well; conversely, if the RGB space is ill-defined, so is Y′CbCr. The
-                    // transformation is defined in equations 32, 33 in ITU-T H.273. Nevertheless
-                    // that rule does not apply to P3-D65 primaries used by Netflix with
-                    // BT.2020-NCL matrix, so that means matrix was not derived from primaries, but
-                    // now Netflix allows BT.2020 primaries (since 2021).[1] The same happens with
-                    // JPEG: it has BT.601 matrix derived from System M primaries, yet the
-                    // primaries of most images are BT.709.
-                    VideoFormat::NV12 => [
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[0] as *const _,
-                                frame.linesize[0] * frame.height as usize,
-                            )
-                        },
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[1] as *const _,
-                                frame.linesize[1] * frame.height as usize,
-                            )
-                        },
-                        &[],
-                    ],
-                    VideoFormat::I420 => [
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[0] as *const _,
-                                frame.linesize[0] * frame.height as usize,
-                            )
-                        },
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[1] as *const _,
-                                frame.linesize[1] * frame.height as usize,
-                            )
-                        },
-                        unsafe {
-                            from_raw_parts(
-                                frame.data[2] as *const _,
-                                frame.linesize[2] * frame.height as usize,
-                            )
-                        },
-                    ],
-                };
-
-                let texture = Texture2DBuffer {
-                    buffers: &buffers,
-                    size: Size {
-                        width: frame.width,
-                        height: frame.height,
+                ],
+                VideoFormat::I420 => [
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[0] as *const _,
+                            frame.linesize[0] * frame.height as usize,
+                        )
+                    },
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[1] as *const _,
+                            frame.linesize[1] * frame.height as usize,
+                        )
                     },
-                };
+                    unsafe {
+                        from_raw_parts(
+                            frame.data[2] as *const _,
+                            frame.linesize[2] * frame.height as usize,
+                        )
+                    },
+                ],
+            };
+
+            let texture = Texture2DBuffer {
+                buffers: &buffers,
+                size: Size {
+                    width: frame.width,
+                    height: frame.height,
+                },
+            };
 
-                let texture = match frame.format {
-                    VideoFormat::BGRA => Texture::Bgra(Texture2DResource::Buffer(texture)),
-                    VideoFormat::RGBA => Texture::Rgba(Texture2DResource::Buffer(texture)),
-                    VideoFormat::NV12 => Texture::Nv12(Texture2DResource::Buffer(texture)),
-                    VideoFormat::I420 => Texture::I420(texture),
-                };
+            let texture = match frame.format {
+                VideoFormat::BGRA => Texture::Bgra(Texture2DResource::Buffer(texture)),
+                VideoFormat::RGBA => Texture::Rgba(Texture2DResource::Buffer(texture)),
+                VideoFormat::NV12 => Texture::Nv12(Texture2DResource::Buffer(texture)),
+                VideoFormat::P010 => Texture::P010(Texture2DResource::Buffer(texture)),
+                VideoFormat::I420 => Texture::I420(texture),
+            };
 
-                match self {
-                    #[cfg(target_os = "windows")]
-                    Self::Direct3D11(render) => render.submit(texture)?,
-                    Self::WebGPU(render) => render.submit(texture)?,
-                }
-            }
-            #[allow(unreachable_patterns)]
-            _ => unimplemented!("not suppports the frame format = {:?}", frame.sub_format),
+            submit(texture)?;
         }
-
-        Ok(())
+        #[allow(unreachable_patterns)]
+        _ => unimplemented!("not suppports the frame format = {:?}", frame.sub_format),
     }
+
+    Ok(())
 }
@@ -0,0 +1,338 @@
+use crate::{
+    close_stream,
+    sender::{start_capture, AudioSender, PacketOutput, PreviewSink},
+    AVFrameStream, DisconnectReason, HylaranaSenderError, HylaranaSenderMediaOptions, StreamStatus,
+};
+
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use hylarana_capture::{AudioMixer, AudioMixerInput, Capture};
+use hylarana_transport::BufferFlag;
+use parking_lot::Mutex;
+
+/// Receives the encoded packets of a local session, such as to write them to
+/// a file.
+///
+/// The first packet of each track is the configuration of the encoder, its
+/// flags contain `BufferFlag::Config`. The video packets are in the Annex B
+/// format, the audio packets are Opus, the timestamps are on the media clock,
+/// in microseconds.
+pub trait RecordingSink: Sync + Send {
+    /// Returning `false` causes the session to close.
+    #[allow(unused_variables)]
+    fn video(&self, packet: &[u8], flags: i32, timestamp: u64) -> bool {
+        true
+    }
+
+    /// Returning `false` causes the session to close.
+    #[allow(unused_variables)]
+    fn audio(&self, packet: &[u8], flags: i32, timestamp: u64) -> bool {
+        true
+    }
+
+    /// Callback when the session is dropped, after the last packet.
+    fn close(&self) {}
+}
+
+/// Options of the file recorder, a track without a path is not written.
+#[derive(Debug, Clone, Default)]
+pub struct FileRecorderOptions {
+    /// The video is written as an elementary stream, such as `record.h264` or
+    /// `record.hevc`, which can be played or muxed with ffmpeg.
+    pub video: Option<PathBuf>,
+    /// The audio is written as an Ogg Opus file, such as `record.opus`.
+    pub audio: Option<PathBuf>,
+}
+
+/// A recording sink that writes the tracks to files.
+pub struct FileRecorder {
+    video: Option<Mutex<BufWriter<File>>>,
+    audio: Option<Mutex<OggOpusWriter>>,
+}
+
+impl FileRecorder {
+    pub fn new(options: FileRecorderOptions) -> std::io::Result<Self> {
+        Ok(Self {
+            video: match options.video {
+                Some(path) => Some(Mutex::new(BufWriter::new(File::create(path)?))),
+                None => None,
+            },
+            audio: match options.audio {
+                Some(path) => Some(Mutex::new(OggOpusWriter::new(File::create(path)?))),
+                None => None,
+            },
+        })
+    }
+}
+
+impl RecordingSink for FileRecorder {
+    fn video(&self, packet: &[u8], _flags: i32, _timestamp: u64) -> bool {
+        if let Some(video) = self.video.as_ref() {
+            if let Err(e) = video.lock().write_all(packet) {
+                log::error!("file recorder write video error={:?}", e);
+
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn audio(&self, packet: &[u8], flags: i32, _timestamp: u64) -> bool {
+        if let Some(audio) = self.audio.as_ref() {
+            if let Err(e) = audio.lock().write(packet, flags) {
+                log::error!("file recorder write audio error={:?}", e);
+
+                return false;
+            }
+        }
+
+        true
+    }
+
+    fn close(&self) {
+        if let Some(video) = self.video.as_ref() {
+            if let Err(e) = video.lock().flush() {
+                log::warn!("file recorder flush video error={:?}", e);
+            }
+        }
+
+        if let Some(audio) = self.audio.as_ref() {
+            if let Err(e) = audio.lock().finish() {
+                log::warn!("file recorder flush audio error={:?}", e);
+            }
+        }
+    }
+}
+
+// Each packet of the encoder is written to its own page, the granule position
+// counts the samples at 48 kHz, which Opus always uses in Ogg.
+struct OggOpusWriter {
+    file: BufWriter<File>,
+    granule: u64,
+    sequence: u32,
+    // The previous packet is held back, so that the last page can be marked as
+    // the end of the stream.
+    pending: Option<Vec<u8>>,
+}
+
+impl OggOpusWriter {
+    const SERIAL: u32 = 0x48594c41;
+
+    fn new(file: File) -> Self {
+        Self {
+            file: BufWriter::new(file),
+            granule: 0,
+            sequence: 0,
+            pending: None,
+        }
+    }
+
+    fn write(&mut self, packet: &[u8], flags: i32) -> std::io::Result<()> {
+        // The configuration of the encoder wraps the identification header in the
+        // `AOPUSHDR` block, the header itself starts at the `OpusHead` signature.
+        if flags & BufferFlag::Config as i32 != 0 {
+            if self.sequence > 0 {
+                return Ok(());
+            }
+
+            if let Some(offset) = packet.windows(8).position(|it| it == b"OpusHead") {
+                let head = &packet[offset..(offset + 19).min(packet.len())];
+
+                self.page(head, 0x02, 0)?;
+                self.page(&opus_tags(), 0, 0)?;
+            }
+
+            return Ok(());
+        }
+
+        if let Some(previous) = self.pending.replace(packet.to_vec()) {
+            self.granule += opus_samples(&previous);
+            self.page(&previous, 0, self.granule)?;
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        if let Some(previous) = self.pending.take() {
+            self.granule += opus_samples(&previous);
+            self.page(&previous, 0x04, self.granule)?;
+        }
+
+        self.file.flush()
+    }
+
+    fn page(&mut self, packet: &[u8], header_type: u8, granule: u64) -> std::io::Result<()> {
+        // The lacing values of a packet are 255 for each full segment and the
+        // remainder, a multiple of 255 ends with a zero.
+        let mut lacing = vec![255u8; packet.len() / 255];
+        lacing.push((packet.len() % 255) as u8);
+
+        let mut page = Vec::with_capacity(27 + lacing.len() + packet.len());
+        page.extend_from_slice(b"OggS");
+        page.push(0);
+        page.push(header_type);
+        page.extend_from_slice(&granule.to_le_bytes());
+        page.extend_from_slice(&Self::SERIAL.to_le_bytes());
+        page.extend_from_slice(&self.sequence.to_le_bytes());
+        page.extend_from_slice(&[0; 4]);
+        page.push(lacing.len() as u8);
+        page.extend_from_slice(&lacing);
+        page.extend_from_slice(packet);
+
+        let crc = ogg_crc(&page);
+        page[22..26].copy_from_slice(&crc.to_le_bytes());
+
+        self.sequence += 1;
+        self.file.write_all(&page)
+    }
+}
+
+fn opus_tags() -> Vec<u8> {
+    let vendor = b"hylarana";
+
+    let mut tags = Vec::with_capacity(8 + 4 + vendor.len() + 4);
+    tags.extend_from_slice(b"OpusTags");
+    tags.extend_from_slice(&(vendor.len() as u32).to_le_bytes());
+    tags.extend_from_slice(vendor);
+    tags.extend_from_slice(&0u32.to_le_bytes());
+    tags
+}
+
+// The number of samples of a packet at 48 kHz, see section 3.1 of RFC 6716.
+fn opus_samples(packet: &[u8]) -> u64 {
+    let Some(&toc) = packet.first() else {
+        return 0;
+    };
+
+    let config = toc >> 3;
+    let frame_size = if config < 12 {
+        // SILK, 10, 20, 40 or 60 ms.
+        [480, 960, 1920, 2880][config as usize % 4]
+    } else if config < 16 {
+        // Hybrid, 10 or 20 ms.
+        [480, 960][config as usize % 2]
+    } else {
+        // CELT, 2.5, 5, 10 or 20 ms.
+        [120, 240, 480, 960][config as usize % 4]
+    };
+
+    let frames = match toc & 0x03 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => packet.get(1).map(|it| it & 0x3F).unwrap_or(0) as u64,
+    };
+
+    frame_size * frames
+}
+
+fn ogg_crc(data: &[u8]) -> u32 {
+    let mut crc = 0u32;
+    for byte in data {
+        crc ^= (*byte as u32) << 24;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04C1_1DB7
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// A capture and encoding session without a transport, the encoded packets go
+/// to a recording sink, such as a file, so the crate can be used as a screen
+/// recorder without a loopback sender and receiver.
+///
+/// The sink gets the captured frames like the sink of a sender.
+pub struct LocalSession<T: AVFrameStream + 'static> {
+    status: Arc<StreamStatus>,
+    capture: Capture,
+    // The audio tracks are mixed on the thread of the mixer, it is stopped before
+    // the recording sink is closed.
+    mixer: Option<AudioMixer<AudioSender<T>>>,
+    recorder: Arc<dyn RecordingSink>,
+    sink: Arc<T>,
+}
+
+impl<T: AVFrameStream + 'static> LocalSession<T> {
+    pub(crate) fn new<R: RecordingSink + 'static>(
+        options: HylaranaSenderMediaOptions,
+        recorder: R,
+        sink: T,
+    ) -> Result<Self, HylaranaSenderError> {
+        tracing::info!("create local session");
+
+        let status = StreamStatus::new();
+        let recorder: Arc<dyn RecordingSink> = Arc::new(recorder);
+        let sink = Arc::new(sink);
+
+        let audio_inputs = options
+            .audio
+            .iter()
+            .map(|it| AudioMixerInput {
+                gain: it.options.gain,
+                muted: false,
+            })
+            .collect::<Vec<_>>();
+
+        let (capture, mixer) = start_capture(
+            &options,
+            &audio_inputs,
+            &PreviewSink::default(),
+            &Default::default(),
+            &PacketOutput::Recording(recorder.clone()),
+            &status,
+            &sink,
+        )?;
+
+        Ok(Self {
+            capture,
+            mixer,
+            recorder,
+            status,
+            sink,
+        })
+    }
+
+    /// Whether the session has been closed, such as because the capture source
+    /// has been removed or the recording sink returned false.
+    pub fn is_closed(&self) -> bool {
+        self.status.is_closed()
+    }
+
+    /// Wait until the session is closed.
+    pub async fn closed(&self) {
+        self.status.closed().await
+    }
+
+    /// Get the sink of the session.
+    pub fn get_sink(&self) -> &T {
+        &self.sink
+    }
+}
+
+impl<T: AVFrameStream + 'static> Drop for LocalSession<T> {
+    fn drop(&mut self) {
+        tracing::info!("local session drop");
+
+        if let Err(e) = self.capture.close() {
+            tracing::warn!(error = ?e, "local session capture close error");
+        }
+
+        self.mixer.take();
+
+        close_stream(&self.status, self.sink.as_ref(), DisconnectReason::Closed);
+
+        self.recorder.close();
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use hylarana_common::frame::AudioFrame;
+
+/// The levels of the audio over a short period, in dBFS, `0.0` is the full
+/// scale, see `AVFrameObserver::audio_levels`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioLevels {
+    /// The level of the loudest sample.
+    pub peak: f32,
+    /// The root mean square of the samples, which follows the loudness that
+    /// is heard more closely than the peak.
+    pub rms: f32,
+}
+
+impl AudioLevels {
+    /// The level that the silence is reported at, the digital silence has no
+    /// level in dBFS.
+    pub const SILENCE: f32 = -100.0;
+}
+
+// Measures the levels of the audio frames, the levels are taken over a period,
+// which is about the rate that the meters of the user interfaces are drawn at.
+pub(crate) struct AudioMeter {
+    peak: f64,
+    power: f64,
+    count: usize,
+}
+
+impl AudioMeter {
+    const PERIOD: Duration = Duration::from_millis(100);
+
+    // The sample rate of the frames that do not carry it.
+    const DEFAULT_SAMPLE_RATE: u32 = 48000;
+
+    pub(crate) fn new() -> Self {
+        Self {
+            peak: 0.0,
+            power: 0.0,
+            count: 0,
+        }
+    }
+
+    // Returns the levels once the samples of a period have been measured, the
+    // period ends with the frame that completes it.
+    pub(crate) fn push(&mut self, frame: &AudioFrame) -> Option<AudioLevels> {
+        if frame.data.is_null() || frame.frames == 0 {
+            return None;
+        }
+
+        let samples = unsafe { std::slice::from_raw_parts(frame.data, frame.frames as usize) };
+        for sample in samples {
+            let sample = *sample as f64 / i16::MAX as f64;
+
+            self.peak = self.peak.max(sample.abs());
+            self.power += sample * sample;
+            self.count += 1;
+        }
+
+        let sample_rate = if frame.sample_rate > 0 {
+            frame.sample_rate
+        } else {
+            Self::DEFAULT_SAMPLE_RATE
+        };
+
+        if (self.count as u128) * 1000 < sample_rate as u128 * Self::PERIOD.as_millis() {
+            return None;
+        }
+
+        let decibels = |value: f64| {
+            if value > 0.0 {
+                (20.0 * value.log10()).max(AudioLevels::SILENCE as f64) as f32
+            } else {
+                AudioLevels::SILENCE
+            }
+        };
+
+        let levels = AudioLevels {
+            peak: decibels(self.peak),
+            rms: decibels((self.power / self.count as f64).sqrt()),
+        };
+
+        self.peak = 0.0;
+        self.power = 0.0;
+        self.count = 0;
+
+        Some(levels)
+    }
+}
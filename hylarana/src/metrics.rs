@@ -0,0 +1,365 @@
+use std::{
+    fmt::Write as _,
+    io::{Error, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use hylarana_common::atomic::EasyAtomic;
+
+// The counters of the whole process, all senders and receivers add to them.
+pub(crate) static METRICS: Metrics = Metrics::new();
+
+pub(crate) struct Metrics {
+    pub video_frames_captured: AtomicU64,
+    pub video_frames_encoded: AtomicU64,
+    pub audio_frames_encoded: AtomicU64,
+    pub packets_sent: AtomicU64,
+    pub bytes_sent: AtomicU64,
+    pub video_frames_decoded: AtomicU64,
+    pub video_frames_rendered: AtomicU64,
+    pub audio_frames_decoded: AtomicU64,
+    pub encode_errors: AtomicU64,
+    pub decode_errors: AtomicU64,
+    pub send_queue: AtomicU64,
+    pub video_receive_queue: AtomicU64,
+    pub audio_receive_queue: AtomicU64,
+}
+
+impl Metrics {
+    const fn new() -> Self {
+        Self {
+            video_frames_captured: AtomicU64::new(0),
+            video_frames_encoded: AtomicU64::new(0),
+            audio_frames_encoded: AtomicU64::new(0),
+            packets_sent: AtomicU64::new(0),
+            bytes_sent: AtomicU64::new(0),
+            video_frames_decoded: AtomicU64::new(0),
+            video_frames_rendered: AtomicU64::new(0),
+            audio_frames_decoded: AtomicU64::new(0),
+            encode_errors: AtomicU64::new(0),
+            decode_errors: AtomicU64::new(0),
+            send_queue: AtomicU64::new(0),
+            video_receive_queue: AtomicU64::new(0),
+            audio_receive_queue: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) fn increment(counter: &AtomicU64) {
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+}
+
+/// The counters and the queue depths of the pipelines of the process, the
+/// counters only increase, the queue depths are the values at the time the
+/// snapshot is taken.
+#[derive(Debug, Clone)]
+pub struct MetricsSnapshot {
+    pub video_frames_captured: u64,
+    pub video_frames_encoded: u64,
+    pub audio_frames_encoded: u64,
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub video_frames_decoded: u64,
+    pub video_frames_rendered: u64,
+    pub audio_frames_decoded: u64,
+    pub encode_errors: u64,
+    pub decode_errors: u64,
+    /// The packets that are waiting to be sent by the transport.
+    pub send_queue: u64,
+    /// The packets that are waiting to be decoded.
+    pub video_receive_queue: u64,
+    pub audio_receive_queue: u64,
+    /// The Direct3D11 textures that the capture and the video processors of
+    /// the senders have created and reused, and the times they waited for the
+    /// GPU to finish with a texture, the textures are only pooled on Windows.
+    pub textures_created: u64,
+    pub textures_reused: u64,
+    pub texture_waits: u64,
+    pub taken: Instant,
+}
+
+/// The frame rates between two snapshots.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameRates {
+    pub captured: f64,
+    pub encoded: f64,
+    pub decoded: f64,
+    pub rendered: f64,
+}
+
+impl MetricsSnapshot {
+    pub fn take() -> Self {
+        #[cfg(target_os = "windows")]
+        let textures = {
+            let stats = hylarana_common::win32::TexturePoolStats::take();
+            (stats.created, stats.reused, stats.waits)
+        };
+
+        #[cfg(not(target_os = "windows"))]
+        let textures = (0, 0, 0);
+
+        Self {
+            video_frames_captured: METRICS.video_frames_captured.get(),
+            video_frames_encoded: METRICS.video_frames_encoded.get(),
+            audio_frames_encoded: METRICS.audio_frames_encoded.get(),
+            packets_sent: METRICS.packets_sent.get(),
+            bytes_sent: METRICS.bytes_sent.get(),
+            video_frames_decoded: METRICS.video_frames_decoded.get(),
+            video_frames_rendered: METRICS.video_frames_rendered.get(),
+            audio_frames_decoded: METRICS.audio_frames_decoded.get(),
+            encode_errors: METRICS.encode_errors.get(),
+            decode_errors: METRICS.decode_errors.get(),
+            send_queue: METRICS.send_queue.get(),
+            video_receive_queue: METRICS.video_receive_queue.get(),
+            audio_receive_queue: METRICS.audio_receive_queue.get(),
+            textures_created: textures.0,
+            textures_reused: textures.1,
+            texture_waits: textures.2,
+            taken: Instant::now(),
+        }
+    }
+
+    /// The video frame rates since the previous snapshot.
+    pub fn frame_rates(&self, previous: &Self) -> FrameRates {
+        let seconds = self.taken.duration_since(previous.taken).as_secs_f64();
+        if seconds <= 0.0 {
+            return FrameRates::default();
+        }
+
+        let rate = |current: u64, previous: u64| current.saturating_sub(previous) as f64 / seconds;
+        FrameRates {
+            captured: rate(self.video_frames_captured, previous.video_frames_captured),
+            encoded: rate(self.video_frames_encoded, previous.video_frames_encoded),
+            decoded: rate(self.video_frames_decoded, previous.video_frames_decoded),
+            rendered: rate(self.video_frames_rendered, previous.video_frames_rendered),
+        }
+    }
+
+    /// Format the snapshot in the text format of Prometheus.
+    pub fn to_prometheus(&self) -> String {
+        let mut text = String::with_capacity(2048);
+        for (name, kind, help, value) in [
+            (
+                "video_frames_captured_total",
+                "counter",
+                "Video frames captured.",
+                self.video_frames_captured,
+            ),
+            (
+                "video_frames_encoded_total",
+                "counter",
+                "Video frames encoded.",
+                self.video_frames_encoded,
+            ),
+            (
+                "audio_frames_encoded_total",
+                "counter",
+                "Audio frames encoded.",
+                self.audio_frames_encoded,
+            ),
+            (
+                "packets_sent_total",
+                "counter",
+                "Packets passed to the transport.",
+                self.packets_sent,
+            ),
+            (
+                "bytes_sent_total",
+                "counter",
+                "Bytes passed to the transport.",
+                self.bytes_sent,
+            ),
+            (
+                "video_frames_decoded_total",
+                "counter",
+                "Video frames decoded.",
+                self.video_frames_decoded,
+            ),
+            (
+                "video_frames_rendered_total",
+                "counter",
+                "Video frames rendered.",
+                self.video_frames_rendered,
+            ),
+            (
+                "audio_frames_decoded_total",
+                "counter",
+                "Audio frames decoded.",
+                self.audio_frames_decoded,
+            ),
+            (
+                "encode_errors_total",
+                "counter",
+                "Encoding errors.",
+                self.encode_errors,
+            ),
+            (
+                "decode_errors_total",
+                "counter",
+                "Decoding errors.",
+                self.decode_errors,
+            ),
+            (
+                "send_queue",
+                "gauge",
+                "Packets waiting to be sent.",
+                self.send_queue,
+            ),
+            (
+                "video_receive_queue",
+                "gauge",
+                "Video packets waiting to be decoded.",
+                self.video_receive_queue,
+            ),
+            (
+                "audio_receive_queue",
+                "gauge",
+                "Audio packets waiting to be decoded.",
+                self.audio_receive_queue,
+            ),
+            (
+                "textures_created_total",
+                "counter",
+                "Pooled textures created.",
+                self.textures_created,
+            ),
+            (
+                "textures_reused_total",
+                "counter",
+                "Pooled textures reused.",
+                self.textures_reused,
+            ),
+            (
+                "texture_waits_total",
+                "counter",
+                "Pooled textures waited for the GPU.",
+                self.texture_waits,
+            ),
+        ] {
+            let _ = write!(
+                text,
+                "# HELP hylarana_{name} {help}\n# TYPE hylarana_{name} {kind}\nhylarana_{name} {value}\n"
+            );
+        }
+
+        text
+    }
+}
+
+/// Serve the metrics in the text format of Prometheus over http, every
+/// request gets the current snapshot regardless of the path. The server is
+/// stopped when the exporter is dropped.
+pub struct MetricsExporter {
+    addr: SocketAddr,
+    closed: Arc<AtomicBool>,
+}
+
+impl MetricsExporter {
+    pub fn bind(addr: SocketAddr) -> Result<Self, Error> {
+        let listener = TcpListener::bind(addr)?;
+        let addr = listener.local_addr()?;
+        let closed = Arc::new(AtomicBool::new(false));
+
+        log::info!("metrics exporter listening, addr={}", addr);
+
+        let closed_ = closed.clone();
+        thread::Builder::new()
+            .name("HylaranaMetricsExporterThread".to_string())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    if closed_.get() {
+                        break;
+                    }
+
+                    if let Ok(stream) = stream {
+                        if let Err(e) = respond(stream) {
+                            log::warn!("metrics exporter failed to respond, err={:?}", e);
+                        }
+                    }
+                }
+
+                log::info!("metrics exporter is closed, addr={}", addr);
+            })?;
+
+        Ok(Self { addr, closed })
+    }
+
+    /// The address the exporter listens on, which is useful when binding to
+    /// port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for MetricsExporter {
+    fn drop(&mut self) {
+        self.closed.update(true);
+
+        // Wake up the listener that is blocked in accept.
+        let _ = TcpStream::connect_timeout(&self.addr, Duration::from_secs(1));
+    }
+}
+
+fn respond(mut stream: TcpStream) -> Result<(), Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    // Only the request line matters, the rest of the request is ignored.
+    let mut buf = [0u8; 1024];
+    let _ = stream.read(&mut buf)?;
+
+    let body = MetricsSnapshot::take().to_prometheus();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+/// Call the callback with the snapshot and the frame rates at the interval,
+/// the reporter is stopped when it is dropped.
+pub struct MetricsReporter {
+    closed: Arc<AtomicBool>,
+}
+
+impl MetricsReporter {
+    pub fn new<F>(interval: Duration, callback: F) -> Result<Self, Error>
+    where
+        F: Fn(&MetricsSnapshot, &FrameRates) + Send + 'static,
+    {
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let closed_ = closed.clone();
+        thread::Builder::new()
+            .name("HylaranaMetricsReporterThread".to_string())
+            .spawn(move || {
+                let mut previous = MetricsSnapshot::take();
+
+                while !closed_.get() {
+                    thread::sleep(interval);
+
+                    let current = MetricsSnapshot::take();
+                    callback(&current, &current.frame_rates(&previous));
+                    previous = current;
+                }
+            })?;
+
+        Ok(Self { closed })
+    }
+}
+
+impl Drop for MetricsReporter {
+    fn drop(&mut self) {
+        self.closed.update(true);
+    }
+}
@@ -0,0 +1,391 @@
+//! A Miracast sink on Windows, the screens of Android and Windows devices are
+//! received with the Miracast receiver of the system and passed to the same
+//! sinks as the streams of the senders, such as a video player.
+
+use crate::{AVFrameStream, DisconnectReason, GraphicsContext, StreamEvent};
+
+use std::sync::Arc;
+
+use hylarana_common::{
+    frame::{VideoFormat, VideoFrame, VideoSubFormat},
+    win32::Direct3DDevice,
+};
+
+use parking_lot::Mutex;
+use thiserror::Error;
+use windows::{
+    core::{Error as WindowsError, IInspectable, Interface, HSTRING},
+    ApplicationModel::Core::CoreApplicationView,
+    Foundation::TypedEventHandler,
+    Graphics::DirectX::Direct3D11::IDirect3DSurface,
+    Media::{
+        Core::MediaSource,
+        Miracast::{
+            MiracastReceiver, MiracastReceiverApplySettingsStatus,
+            MiracastReceiverAuthorizationMethod, MiracastReceiverConnectionCreatedEventArgs,
+            MiracastReceiverDisconnectedEventArgs, MiracastReceiverMediaSourceCreatedEventArgs,
+            MiracastReceiverSession, MiracastReceiverSessionStartStatus,
+        },
+        Playback::{IMediaPlaybackSource, MediaPlayer},
+    },
+    Win32::{
+        Graphics::{
+            Direct3D11::{
+                ID3D11Texture2D, D3D11_BIND_RENDER_TARGET, D3D11_BIND_SHADER_RESOURCE,
+                D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
+            },
+            Dxgi::{
+                Common::{DXGI_FORMAT_B8G8R8A8_UNORM, DXGI_SAMPLE_DESC},
+                IDXGISurface,
+            },
+        },
+        System::WinRT::Direct3D11::CreateDirect3D11SurfaceFromDXGISurface,
+    },
+};
+
+#[derive(Debug, Error)]
+pub enum HylaranaMiracastError {
+    #[error("miracast receiver is not supported on this system")]
+    NotSupported,
+    #[error("failed to apply miracast receiver settings, status={0}")]
+    ApplySettingsError(i32),
+    #[error("failed to start miracast receiver session, status={0}")]
+    StartSessionError(i32),
+    #[error(transparent)]
+    WindowsError(#[from] WindowsError),
+}
+
+/// How a device is allowed to connect to the Miracast receiver.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MiracastAuthorization {
+    /// Any device connects without asking.
+    #[default]
+    None,
+    /// The user confirms the connection in a prompt of the system.
+    ConfirmConnection,
+    /// The receiver shows a PIN that is entered on the device when the
+    /// device asks for it, see `MiracastEvent::Pin`.
+    Pin,
+}
+
+#[derive(Debug, Clone)]
+pub struct MiracastReceiverOptions {
+    /// The name of the receiver in the lists of the devices.
+    pub name: String,
+    pub model_name: String,
+    pub authorization: MiracastAuthorization,
+}
+
+impl Default for MiracastReceiverOptions {
+    fn default() -> Self {
+        Self {
+            name: "Hylarana".to_string(),
+            model_name: "Hylarana".to_string(),
+            authorization: MiracastAuthorization::None,
+        }
+    }
+}
+
+/// The events of the Miracast sessions, the sink gets the events of the
+/// stream as well.
+#[derive(Debug, Clone)]
+pub enum MiracastEvent {
+    /// A device has connected.
+    Connected { name: String },
+    /// The PIN of the connection, it is shown to the user so that it can be
+    /// entered on the device.
+    Pin { pin: String },
+    /// The device has disconnected, the receiver waits for the next device.
+    Disconnected,
+}
+
+// The pictures of the media player are copied into a texture of the shared
+// graphics device, which is passed to the sink like a decoded frame.
+struct FrameServer {
+    direct3d: Direct3DDevice,
+    texture: Option<(ID3D11Texture2D, IDirect3DSurface)>,
+    frame: VideoFrame,
+}
+
+// The texture is only used under the lock of the context.
+unsafe impl Send for FrameServer {}
+
+impl FrameServer {
+    fn new() -> Self {
+        let mut frame = VideoFrame::default();
+        frame.format = VideoFormat::BGRA;
+        frame.sub_format = VideoSubFormat::D3D11;
+
+        Self {
+            direct3d: GraphicsContext::shared().direct3d(),
+            texture: None,
+            frame,
+        }
+    }
+
+    fn copy(&mut self, player: &MediaPlayer) -> Result<Option<&VideoFrame>, WindowsError> {
+        let session = player.PlaybackSession()?;
+        let (width, height) = (session.NaturalVideoWidth()?, session.NaturalVideoHeight()?);
+        if width == 0 || height == 0 {
+            return Ok(None);
+        }
+
+        if self.texture.is_none() || self.frame.width != width || self.frame.height != height {
+            let desc = D3D11_TEXTURE2D_DESC {
+                Width: width,
+                Height: height,
+                MipLevels: 1,
+                ArraySize: 1,
+                Format: DXGI_FORMAT_B8G8R8A8_UNORM,
+                SampleDesc: DXGI_SAMPLE_DESC {
+                    Count: 1,
+                    Quality: 0,
+                },
+                Usage: D3D11_USAGE_DEFAULT,
+                BindFlags: (D3D11_BIND_RENDER_TARGET.0 | D3D11_BIND_SHADER_RESOURCE.0) as u32,
+                CPUAccessFlags: 0,
+                MiscFlags: 0,
+            };
+
+            let mut texture = None;
+            unsafe {
+                self.direct3d
+                    .device
+                    .CreateTexture2D(&desc, None, Some(&mut texture))?;
+            }
+
+            let texture = texture.ok_or_else(WindowsError::empty)?;
+            let surface = unsafe {
+                CreateDirect3D11SurfaceFromDXGISurface(&texture.cast::<IDXGISurface>()?)?
+            }
+            .cast::<IDirect3DSurface>()?;
+
+            self.frame.width = width;
+            self.frame.height = height;
+            self.frame.data[0] = texture.as_raw();
+            self.frame.data[1] = std::ptr::null();
+            self.texture = Some((texture, surface));
+        }
+
+        if let Some((_, surface)) = self.texture.as_ref() {
+            player.CopyFrameToVideoSurface(surface)?;
+        }
+
+        Ok(Some(&self.frame))
+    }
+}
+
+type EventHandler = Box<dyn Fn(MiracastEvent) + Send + Sync>;
+
+struct Context<T> {
+    sink: T,
+    events: Mutex<Option<EventHandler>>,
+    player: Mutex<Option<MediaPlayer>>,
+    server: Mutex<FrameServer>,
+}
+
+impl<T: AVFrameStream + 'static> Context<T> {
+    fn emit(&self, event: MiracastEvent) {
+        if let Some(handler) = self.events.lock().as_ref() {
+            handler(event);
+        }
+    }
+
+    fn play(self: &Arc<Self>, source: MediaSource) -> Result<(), WindowsError> {
+        let player = MediaPlayer::new()?;
+        player.SetRealTimePlayback(true)?;
+        player.SetIsVideoFrameServerEnabled(true)?;
+
+        let this = Arc::downgrade(self);
+        player.VideoFrameAvailable(&TypedEventHandler::<MediaPlayer, IInspectable>::new(
+            move |player, _| {
+                let (Some(this), Some(player)) = (this.upgrade(), player.as_ref()) else {
+                    return Ok(());
+                };
+
+                let mut server = this.server.lock();
+                if let Some(frame) = server.copy(player)? {
+                    if !this.sink.video(frame) {
+                        log::warn!("miracast sink video return false, stop the player");
+
+                        player.Pause()?;
+                    }
+                }
+
+                Ok(())
+            },
+        ))?;
+
+        player.SetSource(&source.cast::<IMediaPlaybackSource>()?)?;
+        player.Play()?;
+
+        // The audio is played by the media player.
+        if let Some(previous) = self.player.lock().replace(player) {
+            previous.Close()?;
+        }
+
+        Ok(())
+    }
+
+    fn stop(&self) {
+        if let Some(player) = self.player.lock().take() {
+            if let Err(e) = player.Close() {
+                log::warn!("failed to close miracast media player, err={:?}", e);
+            }
+        }
+    }
+}
+
+/// Receives the Miracast sessions of the devices, such as the screen casting
+/// of Android and Windows, with the Miracast receiver of Windows, which
+/// requires the "Wireless Display" optional feature of the system.
+///
+/// One device is received at a time, a new device takes over the session.
+/// The video is passed to the sink as BGRA textures of the shared graphics
+/// device, the audio is played by the system. The receiver listens until it
+/// is dropped.
+pub struct HylaranaMiracastReceiver<T: AVFrameStream + 'static> {
+    receiver: MiracastReceiver,
+    session: MiracastReceiverSession,
+    context: Arc<Context<T>>,
+}
+
+impl<T: AVFrameStream + 'static> HylaranaMiracastReceiver<T> {
+    pub fn new(options: MiracastReceiverOptions, sink: T) -> Result<Self, HylaranaMiracastError> {
+        log::info!("create miracast receiver: options={:?}", options);
+
+        let receiver = MiracastReceiver::new()?;
+        if !receiver.GetStatus()?.IsMiracastSupported()? {
+            return Err(HylaranaMiracastError::NotSupported);
+        }
+
+        let settings = receiver.GetDefaultSettings()?;
+        settings.SetFriendlyName(&HSTRING::from(options.name.as_str()))?;
+        settings.SetModelName(&HSTRING::from(options.model_name.as_str()))?;
+        settings.SetAuthorizationMethod(match options.authorization {
+            MiracastAuthorization::None => MiracastReceiverAuthorizationMethod::None,
+            MiracastAuthorization::ConfirmConnection => {
+                MiracastReceiverAuthorizationMethod::ConfirmConnection
+            }
+            MiracastAuthorization::Pin => {
+                MiracastReceiverAuthorizationMethod::PinDisplayIfRequested
+            }
+        })?;
+
+        let status = receiver
+            .DisconnectAllAndApplySettings(&settings)?
+            .Status()?;
+        if status != MiracastReceiverApplySettingsStatus::Success {
+            return Err(HylaranaMiracastError::ApplySettingsError(status.0));
+        }
+
+        let context = Arc::new(Context {
+            events: Mutex::new(None),
+            player: Mutex::new(None),
+            server: Mutex::new(FrameServer::new()),
+            sink,
+        });
+
+        // A desktop application has no view, the session is created without it.
+        let session = receiver.CreateSession(None::<&CoreApplicationView>)?;
+        session.SetAllowConnectionTakeover(true)?;
+        session.SetMaxSimultaneousConnections(1)?;
+
+        let this = Arc::downgrade(&context);
+        session.ConnectionCreated(&TypedEventHandler::<
+            MiracastReceiverSession,
+            MiracastReceiverConnectionCreatedEventArgs,
+        >::new(move |_, args| {
+            let (Some(this), Some(args)) = (this.upgrade(), args.as_ref()) else {
+                return Ok(());
+            };
+
+            let name = args.Connection()?.Transmitter()?.Name()?.to_string();
+            log::info!("miracast device connected, name={}", name);
+
+            let pin = args.Pin()?.to_string();
+            if !pin.is_empty() {
+                this.emit(MiracastEvent::Pin { pin });
+            }
+
+            this.emit(MiracastEvent::Connected { name });
+            this.sink.event(StreamEvent::Connected);
+            Ok(())
+        }))?;
+
+        let this = Arc::downgrade(&context);
+        session.MediaSourceCreated(&TypedEventHandler::<
+            MiracastReceiverSession,
+            MiracastReceiverMediaSourceCreatedEventArgs,
+        >::new(move |_, args| {
+            let (Some(this), Some(args)) = (this.upgrade(), args.as_ref()) else {
+                return Ok(());
+            };
+
+            if let Err(e) = this.play(args.MediaSource()?) {
+                log::error!("failed to play miracast media source, err={:?}", e);
+            }
+
+            Ok(())
+        }))?;
+
+        let this = Arc::downgrade(&context);
+        session.Disconnected(&TypedEventHandler::<
+            MiracastReceiverSession,
+            MiracastReceiverDisconnectedEventArgs,
+        >::new(move |_, _| {
+            if let Some(this) = this.upgrade() {
+                log::info!("miracast device disconnected");
+
+                this.stop();
+                this.emit(MiracastEvent::Disconnected);
+                this.sink.event(StreamEvent::Disconnected {
+                    reason: DisconnectReason::RemoteClosed,
+                });
+            }
+
+            Ok(())
+        }))?;
+
+        let status = session.StartAsync()?.get()?.Status()?;
+        if status != MiracastReceiverSessionStartStatus::Success {
+            return Err(HylaranaMiracastError::StartSessionError(status.0));
+        }
+
+        Ok(Self {
+            receiver,
+            session,
+            context,
+        })
+    }
+
+    pub fn on_event<F>(self, handler: F) -> Self
+    where
+        F: Fn(MiracastEvent) + Send + Sync + 'static,
+    {
+        self.context.events.lock().replace(Box::new(handler));
+        self
+    }
+}
+
+impl<T: AVFrameStream + 'static> Drop for HylaranaMiracastReceiver<T> {
+    fn drop(&mut self) {
+        log::info!("miracast receiver drop");
+
+        self.context.stop();
+
+        if let Err(e) = self.session.Close() {
+            log::warn!("failed to close miracast receiver session, err={:?}", e);
+        }
+
+        if let Err(e) = self.receiver.ClearKnownTransmitters() {
+            log::warn!("failed to clear miracast transmitters, err={:?}", e);
+        }
+
+        self.context.sink.event(StreamEvent::Disconnected {
+            reason: DisconnectReason::Closed,
+        });
+
+        self.context.sink.close();
+    }
+}
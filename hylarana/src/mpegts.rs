@@ -0,0 +1,410 @@
+//! MPEG-TS over UDP or RTP, the stream of a sender is sent to a hardware
+//! decoder, a gateway or the broadcast equipment, and a stream of them is
+//! captured by a sender like a camera and a microphone.
+
+use crate::{
+    output::{MuxerOutput, MuxerOutputOptions, StreamOutputEvent},
+    packets::{EncodedCodecParameters, EncodedPacketSink},
+};
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Weak,
+    },
+    thread,
+    time::Duration,
+};
+
+use hylarana_capture::{
+    AudioCaptureSourceDescription, CaptureBackend, CaptureError, CaptureStream, FrameArrived,
+    FramePacer, Source, SourceEvent, SourceType, VideoCaptureSourceDescription,
+};
+
+use hylarana_codec::{MediaFrame, MediaReader, MediaReaderSettings, StreamMuxerSettings};
+
+use hylarana_common::{
+    clock::MediaClock,
+    frame::{AudioFrame, VideoFrame},
+};
+
+use parking_lot::Mutex;
+
+// The size of the datagrams, seven packets of the transport stream fit in the
+// MTU of an ethernet.
+const PACKET_SIZE: &str = "1316";
+
+/// Options of an MPEG-TS output.
+#[derive(Debug, Clone)]
+pub struct MpegTsOutputOptions {
+    /// The destination, such as `udp://239.0.0.1:1234` for multicast, or
+    /// `rtp://192.168.1.10:5004` for the transport stream in RTP. The other
+    /// protocols of ffmpeg, such as `srt://`, work as well.
+    pub url: String,
+    /// The bit rate of the AAC audio, 128 kbps by default.
+    pub audio_bit_rate: u64,
+    /// How many packets can wait for the network, when the queue is full the
+    /// video is dropped up to the next key frame. 256 by default.
+    pub queue_size: usize,
+}
+
+impl Default for MpegTsOutputOptions {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            audio_bit_rate: 128_000,
+            queue_size: 256,
+        }
+    }
+}
+
+/// Sends the stream of a sender as a standard MPEG-TS, see
+/// `HylaranaSender::set_packet_sink`.
+///
+/// The video is passed as it is, the audio is transcoded from Opus to AAC,
+/// which is taken by all the decoders of the transport streams.
+pub struct MpegTsOutput(MuxerOutput);
+
+impl MpegTsOutput {
+    pub fn new(options: MpegTsOutputOptions) -> std::io::Result<Self> {
+        Ok(Self(MuxerOutput::new(MuxerOutputOptions {
+            name: "mpegts",
+            thread: "HylaranaMpegTsOutputThread",
+            settings: StreamMuxerSettings {
+                format: Some(
+                    if options.url.starts_with("rtp://") {
+                        "rtp_mpegts"
+                    } else {
+                        "mpegts"
+                    }
+                    .to_string(),
+                ),
+                options: vec![("pkt_size".to_string(), PACKET_SIZE.to_string())],
+                url: options.url,
+                interrupt: None,
+            },
+            audio_bit_rate: options.audio_bit_rate,
+            queue_size: options.queue_size,
+            reconnect_delay: Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(30),
+        })?))
+    }
+
+    pub fn on_event<F>(self, handler: F) -> Self
+    where
+        F: Fn(StreamOutputEvent) + Send + Sync + 'static,
+    {
+        self.0.set_event_handler(handler);
+        self
+    }
+}
+
+impl EncodedPacketSink for MpegTsOutput {
+    fn parameters(&self, parameters: &EncodedCodecParameters) {
+        self.0.parameters(parameters)
+    }
+
+    fn video(&self, packet: &[u8], key_frame: bool, timestamp: u64) -> bool {
+        self.0.video(packet, key_frame, timestamp)
+    }
+
+    fn audio(&self, packet: &[u8], timestamp: u64) -> bool {
+        self.0.audio(packet, timestamp)
+    }
+
+    fn close(&self) {
+        self.0.close()
+    }
+}
+
+// An input that is read once for the video and the audio captures of it, a
+// UDP port can only be read by one of them.
+struct Input {
+    url: String,
+    // Aborts the reading when the captures are closed.
+    closed: Arc<AtomicBool>,
+    video: Mutex<Option<(Box<dyn FrameArrived<Frame = VideoFrame>>, FramePacer)>>,
+    audio: Mutex<Option<Box<dyn FrameArrived<Frame = AudioFrame>>>>,
+    sample_rate: AtomicU32,
+}
+
+impl Input {
+    fn is_empty(&self) -> bool {
+        self.video.lock().is_none() && self.audio.lock().is_none()
+    }
+
+    fn detach(&self, kind: SourceType) {
+        if kind == SourceType::Audio {
+            drop(self.audio.lock().take());
+        } else {
+            drop(self.video.lock().take());
+        }
+
+        if self.is_empty() {
+            self.closed.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn removed(&self) {
+        if let Some((arrived, _)) = self.video.lock().as_mut() {
+            arrived.event(SourceEvent::Removed);
+        }
+
+        if let Some(arrived) = self.audio.lock().as_mut() {
+            arrived.event(SourceEvent::Removed);
+        }
+    }
+
+    // A capture whose sink returns false is detached.
+    fn sink(&self, frame: MediaFrame) {
+        let (accepted, kind) = match frame {
+            MediaFrame::Video(frame) => {
+                let mut video = self.video.lock();
+                let accepted = match video.as_mut() {
+                    // The frames are paced like the frames of the other sources, the
+                    // timestamps are taken from the media clock.
+                    Some((arrived, pacer)) => match pacer.accept() {
+                        Some(timestamp) => {
+                            frame.timestamp = timestamp;
+                            arrived.sink(frame)
+                        }
+                        None => true,
+                    },
+                    None => true,
+                };
+
+                (accepted, SourceType::Camera)
+            }
+            MediaFrame::Audio(frame) => {
+                let mut audio = self.audio.lock();
+                let accepted = match audio.as_mut() {
+                    Some(arrived) => {
+                        frame.timestamp = MediaClock::now().saturating_sub(
+                            frame.frames as u64 * 1_000_000 / frame.sample_rate.max(1) as u64,
+                        );
+
+                        arrived.sink(frame)
+                    }
+                    None => true,
+                };
+
+                (accepted, SourceType::Audio)
+            }
+        };
+
+        if !accepted {
+            self.detach(kind);
+        }
+    }
+
+    fn spawn(self: &Arc<Self>) -> Result<(), CaptureError> {
+        let input = Arc::downgrade(self);
+        let url = self.url.clone();
+        let closed = self.closed.clone();
+
+        thread::Builder::new()
+            .name("HylaranaMpegTsInputThread".to_string())
+            .spawn(move || {
+                let is_running = |input: &Weak<Input>| {
+                    !closed.load(Ordering::Relaxed) && input.strong_count() > 0
+                };
+
+                // A live input that stops sending is opened again, so the capture goes
+                // on when the stream comes back.
+                'a: while is_running(&input) {
+                    let mut reader = match MediaReader::new(MediaReaderSettings {
+                        url: url.clone(),
+                        format: Some("mpegts".to_string()),
+                        options: vec![("timeout".to_string(), "5000000".to_string())],
+                        interrupt: Some(closed.clone()),
+                    }) {
+                        Ok(reader) => reader,
+                        Err(e) => {
+                            log::warn!("mpegts input failed to open, url={}, err={:?}", url, e);
+
+                            thread::sleep(Duration::from_secs(1));
+                            continue;
+                        }
+                    };
+
+                    log::info!("mpegts input is opened, url={}", url);
+
+                    while let Some(it) = input.upgrade().filter(|_| is_running(&input)) {
+                        let sample_rate = it.sample_rate.load(Ordering::Relaxed);
+                        reader.set_audio_sample_rate(Some(sample_rate).filter(|it| *it > 0));
+
+                        match reader.read() {
+                            Ok(Some(frame)) => it.sink(frame),
+                            // The end of a file input, it is not opened again.
+                            Ok(None) => {
+                                it.removed();
+                                break 'a;
+                            }
+                            Err(e) => {
+                                log::warn!("mpegts input read error, url={}, err={:?}", url, e);
+
+                                break;
+                            }
+                        }
+                    }
+                }
+
+                log::info!("mpegts input thread is closed, url={}", url);
+            })
+            .map_err(|e| CaptureError::BackendError(Box::new(e)))?;
+
+        Ok(())
+    }
+}
+
+// Closes its half of an input.
+struct InputStream(Arc<Input>, SourceType);
+
+impl CaptureStream for InputStream {
+    fn close(&self) -> Result<(), CaptureError> {
+        self.0.detach(self.1);
+        Ok(())
+    }
+}
+
+impl Drop for InputStream {
+    fn drop(&mut self) {
+        self.0.detach(self.1);
+    }
+}
+
+#[derive(Default)]
+struct MpegTsBackendState {
+    sources: Vec<Source>,
+    inputs: Vec<Weak<Input>>,
+}
+
+/// A capture backend whose sources are MPEG-TS streams, such as the stream of
+/// an encoder or a gateway on a UDP port, so that a sender captures them like
+/// a camera and a microphone.
+///
+/// The video and the audio of a stream are separate sources, they share the
+/// reading of the stream when both are captured. The backend is cheap to
+/// clone, the sources can be added after it is registered.
+///
+/// ```ignore
+/// let backend = MpegTsBackend::default();
+/// Capture::register_backend(backend.clone());
+///
+/// let (video, audio) = backend.add_source("encoder", "udp://0.0.0.0:1234");
+/// ```
+#[derive(Default, Clone)]
+pub struct MpegTsBackend(Arc<Mutex<MpegTsBackendState>>);
+
+impl MpegTsBackend {
+    /// The name of the backend, see `Source::backend`.
+    pub const NAME: &'static str = "mpegts";
+
+    /// Add a stream, the url is an input of ffmpeg, such as
+    /// `udp://0.0.0.0:1234` or `rtp://0.0.0.0:5004`. Returns the video source,
+    /// which is a camera, and the audio source of the stream, they are also
+    /// listed by `Capture::get_sources`.
+    pub fn add_source(&self, name: &str, url: &str) -> (Source, Source) {
+        let mut state = self.0.lock();
+        state.sources.retain(|it| it.id != url);
+
+        let source = |kind| Source {
+            id: url.to_string(),
+            name: name.to_string(),
+            index: state.sources.len(),
+            is_default: false,
+            rotation: Default::default(),
+            backend: Some(Self::NAME.to_string()),
+            kind,
+        };
+
+        let sources = (source(SourceType::Camera), source(SourceType::Audio));
+        state.sources.push(sources.0.clone());
+        state.sources.push(sources.1.clone());
+        sources
+    }
+
+    /// Remove the sources of a stream, the captures that have been started are
+    /// not stopped.
+    pub fn remove_source(&self, url: &str) {
+        self.0.lock().sources.retain(|it| it.id != url);
+    }
+
+    // The input of the url that is already being read, or a new one.
+    fn input(&self, url: &str) -> Result<Arc<Input>, CaptureError> {
+        let mut state = self.0.lock();
+        if !state.sources.iter().any(|it| it.id == url) {
+            return Err(CaptureError::BackendError(
+                format!("mpegts source not found, url={}", url).into(),
+            ));
+        }
+
+        state.inputs.retain(|it| it.strong_count() > 0);
+        if let Some(input) = state
+            .inputs
+            .iter()
+            .filter_map(|it| it.upgrade())
+            .find(|it| it.url == url && !it.closed.load(Ordering::Relaxed))
+        {
+            return Ok(input);
+        }
+
+        let input = Arc::new(Input {
+            url: url.to_string(),
+            closed: Arc::new(AtomicBool::new(false)),
+            video: Mutex::new(None),
+            audio: Mutex::new(None),
+            sample_rate: AtomicU32::new(0),
+        });
+
+        input.spawn()?;
+        state.inputs.push(Arc::downgrade(&input));
+        Ok(input)
+    }
+}
+
+impl CaptureBackend for MpegTsBackend {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn sources(&self, kind: SourceType) -> Result<Vec<Source>, CaptureError> {
+        Ok(self
+            .0
+            .lock()
+            .sources
+            .iter()
+            .filter(|it| it.kind == kind)
+            .cloned()
+            .collect())
+    }
+
+    fn start_video(
+        &self,
+        description: VideoCaptureSourceDescription,
+        arrived: Box<dyn FrameArrived<Frame = VideoFrame>>,
+    ) -> Result<Box<dyn CaptureStream>, CaptureError> {
+        let input = self.input(&description.source.id)?;
+        input.video.lock().replace((
+            arrived,
+            FramePacer::new(description.fps, description.adaptive_pacing),
+        ));
+
+        Ok(Box::new(InputStream(input, SourceType::Camera)))
+    }
+
+    fn start_audio(
+        &self,
+        description: AudioCaptureSourceDescription,
+        arrived: Box<dyn FrameArrived<Frame = AudioFrame>>,
+    ) -> Result<Box<dyn CaptureStream>, CaptureError> {
+        let input = self.input(&description.source.id)?;
+        input
+            .sample_rate
+            .store(description.sample_rate, Ordering::Relaxed);
+
+        input.audio.lock().replace(arrived);
+
+        Ok(Box::new(InputStream(input, SourceType::Audio)))
+    }
+}
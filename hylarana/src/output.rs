@@ -0,0 +1,436 @@
+use crate::packets::{EncodedCodecParameters, EncodedPacketSink};
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{sync_channel, Receiver, SyncSender, TrySendError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use hylarana_codec::{
+    AacEncoder, AudioDecoder, AudioEncoderSettings, MuxerStream, StreamMuxer, StreamMuxerError,
+    StreamMuxerSettings,
+};
+
+use parking_lot::Mutex;
+
+// The libopus decoder always outputs 48 kHz.
+const SAMPLE_RATE: u32 = 48000;
+
+pub(crate) struct MuxerOutputOptions {
+    // The name of the output in the logs and of the thread.
+    pub(crate) name: &'static str,
+    pub(crate) thread: &'static str,
+    pub(crate) settings: StreamMuxerSettings,
+    pub(crate) audio_bit_rate: u64,
+    pub(crate) queue_size: usize,
+    pub(crate) reconnect_delay: Duration,
+    pub(crate) max_reconnect_delay: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub enum StreamOutputEvent {
+    /// The output is opened, for a network output this is when it has
+    /// connected to the server and is publishing.
+    Connected,
+    /// The output failed to open or to write, it is opened again after the
+    /// delay.
+    Disconnected { error: String, retry: Duration },
+    /// The output is slower than the stream, the packets are being dropped.
+    Congested,
+    /// The output is closed, no events come after this.
+    Closed,
+}
+
+enum Message {
+    Parameters(EncodedCodecParameters),
+    Video(Vec<u8>, bool, u64),
+    Audio(Vec<u8>, u64),
+}
+
+type EventHandler = Box<dyn Fn(StreamOutputEvent) + Send + Sync>;
+
+struct Shared {
+    events: Mutex<Option<EventHandler>>,
+    // Aborts the blocking operations of the connection when the output is
+    // closed.
+    closed: Arc<AtomicBool>,
+    // The video is dropped up to the next key frame after a packet has been
+    // dropped, or while there is no connection.
+    waiting_key_frame: AtomicBool,
+}
+
+impl Shared {
+    fn emit(&self, event: StreamOutputEvent) {
+        if let Some(handler) = self.events.lock().as_ref() {
+            handler(event);
+        }
+    }
+}
+
+// Writes the encoded packets of a sender to a muxer on its own thread, so a
+// slow output does not hold the sender back, the packets that it cannot keep
+// up with are dropped. The muxer is opened again when it fails, and when the
+// parameters of the stream change. The video is passed as it is, the audio is
+// transcoded from Opus to AAC.
+pub(crate) struct MuxerOutput {
+    name: &'static str,
+    tx: Mutex<Option<SyncSender<Message>>>,
+    shared: Arc<Shared>,
+}
+
+impl MuxerOutput {
+    pub(crate) fn new(options: MuxerOutputOptions) -> std::io::Result<Self> {
+        let name = options.name;
+        let (tx, rx) = sync_channel(options.queue_size.max(1));
+        let shared = Arc::new(Shared {
+            events: Mutex::new(None),
+            closed: Arc::new(AtomicBool::new(false)),
+            waiting_key_frame: AtomicBool::new(true),
+        });
+
+        let shared_ = shared.clone();
+        thread::Builder::new()
+            .name(options.thread.to_string())
+            .spawn(move || {
+                Worker::new(options, shared_.clone()).run(rx);
+
+                log::info!("{} output thread is closed", name);
+
+                shared_.emit(StreamOutputEvent::Closed);
+            })?;
+
+        Ok(Self {
+            tx: Mutex::new(Some(tx)),
+            name,
+            shared,
+        })
+    }
+
+    pub(crate) fn set_event_handler<F>(&self, handler: F)
+    where
+        F: Fn(StreamOutputEvent) + Send + Sync + 'static,
+    {
+        self.shared.events.lock().replace(Box::new(handler));
+    }
+
+    fn send(&self, message: Message) -> bool {
+        let Some(tx) = self.tx.lock().clone() else {
+            return false;
+        };
+
+        match tx.try_send(message) {
+            Ok(_) => true,
+            Err(TrySendError::Full(_)) => {
+                if !self.shared.waiting_key_frame.swap(true, Ordering::Relaxed) {
+                    log::warn!(
+                        "{} output queue is full, drop packets until next key frame",
+                        self.name
+                    );
+
+                    self.shared.emit(StreamOutputEvent::Congested);
+                }
+
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+
+    fn stop(&self) {
+        self.shared.closed.store(true, Ordering::Relaxed);
+        drop(self.tx.lock().take());
+    }
+}
+
+impl EncodedPacketSink for MuxerOutput {
+    fn parameters(&self, parameters: &EncodedCodecParameters) {
+        // The parameters are not dropped, so this waits for the queue, the
+        // parameters only change when the stream is reconfigured.
+        if let Some(tx) = self.tx.lock().clone() {
+            let _ = tx.send(Message::Parameters(parameters.clone()));
+        }
+    }
+
+    fn video(&self, packet: &[u8], key_frame: bool, timestamp: u64) -> bool {
+        if key_frame {
+            self.shared
+                .waiting_key_frame
+                .store(false, Ordering::Relaxed);
+        } else if self.shared.waiting_key_frame.load(Ordering::Relaxed) {
+            return true;
+        }
+
+        self.send(Message::Video(packet.to_vec(), key_frame, timestamp))
+    }
+
+    fn audio(&self, packet: &[u8], timestamp: u64) -> bool {
+        self.send(Message::Audio(packet.to_vec(), timestamp))
+    }
+
+    fn close(&self) {
+        self.stop();
+    }
+}
+
+impl Drop for MuxerOutput {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+struct Connection {
+    muxer: StreamMuxer,
+    video: usize,
+    audio: Option<usize>,
+    // The timestamps start from the first key frame of the connection.
+    base: u64,
+}
+
+// Decodes the Opus packets of the sender and encodes them again as AAC.
+struct Transcoder {
+    decoder: AudioDecoder,
+    encoder: AacEncoder,
+}
+
+impl Transcoder {
+    fn new(bit_rate: u64) -> Result<Self, String> {
+        Ok(Self {
+            decoder: AudioDecoder::new().map_err(|e| e.to_string())?,
+            encoder: AacEncoder::new(AudioEncoderSettings {
+                sample_rate: SAMPLE_RATE as u64,
+                bit_rate,
+            })
+            .map_err(|e| e.to_string())?,
+        })
+    }
+
+    // The packets that cannot be transcoded are skipped.
+    fn process(&mut self, packet: &[u8], timestamp: u64) {
+        if let Err(e) = self.decoder.decode(packet, timestamp) {
+            log::warn!("failed to decode audio for the output, err={:?}", e);
+
+            return;
+        }
+
+        while let Some(frame) = self.decoder.read() {
+            if let Err(e) = self.encoder.encode(frame) {
+                log::warn!("failed to encode audio for the output, err={:?}", e);
+            }
+        }
+    }
+}
+
+struct Worker {
+    options: MuxerOutputOptions,
+    shared: Arc<Shared>,
+    video: Option<EncodedCodecParameters>,
+    audio: Option<EncodedCodecParameters>,
+    transcoder: Option<Transcoder>,
+    connection: Option<Connection>,
+    delay: Duration,
+    retry_at: Instant,
+}
+
+impl Worker {
+    fn new(options: MuxerOutputOptions, shared: Arc<Shared>) -> Self {
+        Self {
+            delay: options.reconnect_delay,
+            retry_at: Instant::now(),
+            transcoder: None,
+            connection: None,
+            video: None,
+            audio: None,
+            options,
+            shared,
+        }
+    }
+
+    fn run(&mut self, rx: Receiver<Message>) {
+        while let Ok(message) = rx.recv() {
+            match message {
+                Message::Parameters(parameters) => self.set_parameters(parameters),
+                Message::Video(packet, key_frame, timestamp) => {
+                    if key_frame && self.connection.is_none() && Instant::now() >= self.retry_at {
+                        self.connect(timestamp);
+                    }
+
+                    if let Some(connection) = self.connection.as_mut() {
+                        let video = connection.video;
+                        let timestamp = timestamp.saturating_sub(connection.base);
+                        if let Err(e) = connection.muxer.write(video, &packet, key_frame, timestamp)
+                        {
+                            self.disconnect(e.to_string());
+                        }
+                    } else {
+                        self.shared.waiting_key_frame.store(true, Ordering::Relaxed);
+                    }
+                }
+                Message::Audio(packet, timestamp) => {
+                    if let Err(e) = self.write_audio(&packet, timestamp) {
+                        self.disconnect(e.to_string());
+                    }
+                }
+            }
+        }
+
+        self.connection = None;
+    }
+
+    // The streams of a connection are fixed when it is made, so it is made
+    // again when the parameters change.
+    fn set_parameters(&mut self, parameters: EncodedCodecParameters) {
+        match parameters {
+            EncodedCodecParameters::Video { .. } => {
+                if self.video.as_ref() != Some(&parameters) {
+                    self.video = Some(parameters);
+                    self.reset();
+                }
+            }
+            EncodedCodecParameters::Audio { .. } => {
+                if self.audio.as_ref() == Some(&parameters) {
+                    return;
+                }
+
+                self.transcoder = Transcoder::new(self.options.audio_bit_rate)
+                    .map_err(|e| {
+                        log::error!(
+                            "{} output failed to create audio transcoder, err={}",
+                            self.options.name,
+                            e
+                        )
+                    })
+                    .ok();
+
+                self.audio = Some(parameters);
+                self.reset();
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        if self.connection.take().is_some() {
+            log::info!("{} output parameters changed, reopen", self.options.name);
+
+            self.retry_at = Instant::now();
+            self.shared.waiting_key_frame.store(true, Ordering::Relaxed);
+        }
+    }
+
+    fn open(&self, base: u64) -> Result<Option<Connection>, StreamMuxerError> {
+        let Some(EncodedCodecParameters::Video {
+            codec,
+            width,
+            height,
+            extradata,
+        }) = self.video.as_ref()
+        else {
+            return Ok(None);
+        };
+
+        let mut muxer = StreamMuxer::new(StreamMuxerSettings {
+            interrupt: Some(self.shared.closed.clone()),
+            ..self.options.settings.clone()
+        })?;
+
+        let video = muxer.add_stream(MuxerStream::Video {
+            codec,
+            width: *width,
+            height: *height,
+            extradata,
+        })?;
+
+        let audio = match self.transcoder.as_ref() {
+            Some(transcoder) => Some(muxer.add_stream(MuxerStream::Audio {
+                codec: "aac",
+                sample_rate: SAMPLE_RATE,
+                channels: 1,
+                extradata: transcoder.encoder.extradata(),
+            })?),
+            None => None,
+        };
+
+        muxer.write_header()?;
+        Ok(Some(Connection {
+            muxer,
+            video,
+            audio,
+            base,
+        }))
+    }
+
+    fn connect(&mut self, base: u64) {
+        log::info!(
+            "{} output open url={}",
+            self.options.name,
+            self.options.settings.url
+        );
+
+        match self.open(base) {
+            Ok(Some(connection)) => {
+                log::info!("{} output is opened", self.options.name);
+
+                self.connection = Some(connection);
+                self.delay = self.options.reconnect_delay;
+                self.shared.emit(StreamOutputEvent::Connected);
+            }
+            Ok(None) => (),
+            Err(e) => self.disconnect(e.to_string()),
+        }
+    }
+
+    fn disconnect(&mut self, error: String) {
+        if self.shared.closed.load(Ordering::Relaxed) {
+            return;
+        }
+
+        log::warn!(
+            "{} output is closed, retry={:?}, err={}",
+            self.options.name,
+            self.delay,
+            error
+        );
+
+        self.connection = None;
+        self.retry_at = Instant::now() + self.delay;
+        self.shared.waiting_key_frame.store(true, Ordering::Relaxed);
+        self.shared.emit(StreamOutputEvent::Disconnected {
+            retry: self.delay,
+            error,
+        });
+
+        self.delay = (self.delay * 2).min(self.options.max_reconnect_delay);
+    }
+
+    fn write_audio(&mut self, packet: &[u8], timestamp: u64) -> Result<(), StreamMuxerError> {
+        let (Some(connection), Some(transcoder)) =
+            (self.connection.as_mut(), self.transcoder.as_mut())
+        else {
+            return Ok(());
+        };
+
+        let Some(audio) = connection.audio else {
+            return Ok(());
+        };
+
+        // The audio from before the first key frame is not sent.
+        if timestamp < connection.base {
+            return Ok(());
+        }
+
+        transcoder.process(packet, timestamp);
+        while let Some((packet, _, timestamp)) = transcoder.encoder.read() {
+            connection.muxer.write(
+                audio,
+                packet,
+                true,
+                timestamp.saturating_sub(connection.base),
+            )?;
+        }
+
+        Ok(())
+    }
+}
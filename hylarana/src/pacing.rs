@@ -0,0 +1,149 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+// A frame that is further than this from the time it is expected at, such as
+// after the sender has restarted or paused, starts over the schedule.
+const RESYNC_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// What the video render does with a frame that arrives after the refresh
+/// that it should have been shown on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LateFrame {
+    /// Show the frame on the next refresh, the following frames are shown
+    /// that much later.
+    #[default]
+    Present,
+    /// Skip the frame and keep the previous frame on the screen, the
+    /// following frames are shown that much later.
+    Drop,
+}
+
+/// Pace the frames of the video render to the refresh of the display.
+///
+/// Each frame is shown on the refresh that its timestamp falls on, after a
+/// fixed latency that absorbs the jitter of the network and the decoder, so
+/// the frames of 24, 25 or 30 fps content are held for a steady number of
+/// refreshes, such as the 3:2 pulldown of 24 fps on a 60 Hz display, instead
+/// of for as long as it took the next frame to decode.
+///
+/// The render cannot see the vertical blank of the display, the phase of the
+/// refreshes is taken from the first frame, which matches the display when
+/// the surface presents with vsync.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FramePacingOptions {
+    /// The refresh rate of the display in Hz.
+    pub refresh_rate: f64,
+    /// How long a frame is held back after its timestamp, a larger latency
+    /// keeps the cadence through a larger jitter.
+    pub latency: Duration,
+    pub late: LateFrame,
+}
+
+impl Default for FramePacingOptions {
+    fn default() -> Self {
+        Self {
+            refresh_rate: 60.0,
+            latency: Duration::from_millis(50),
+            late: LateFrame::Present,
+        }
+    }
+}
+
+pub(crate) struct FramePacer {
+    options: FramePacingOptions,
+    period: Duration,
+    // The time that the frame of the timestamp is shown at, without the latency.
+    anchor: Option<(Instant, u64)>,
+    // The refreshes are counted from the first frame.
+    origin: Instant,
+    // The refresh that the previous frame was shown on.
+    last: Option<u64>,
+}
+
+impl FramePacer {
+    pub(crate) fn new(options: FramePacingOptions) -> Self {
+        Self {
+            period: Duration::from_secs_f64(1.0 / options.refresh_rate.max(1.0)),
+            origin: Instant::now(),
+            anchor: None,
+            last: None,
+            options,
+        }
+    }
+
+    // The refresh that an instant falls on, rounded to the nearest refresh.
+    fn refresh(&self, at: Instant) -> u64 {
+        (at.saturating_duration_since(self.origin).as_secs_f64() / self.period.as_secs_f64())
+            .round() as u64
+    }
+
+    fn reset(&mut self, now: Instant, timestamp: u64) {
+        self.anchor = Some((now, timestamp));
+        if self.last.is_none() {
+            self.origin = now + self.options.latency;
+        }
+    }
+
+    // Wait for the refresh that the frame is shown on, returns false if the frame
+    // is dropped. The frames without a timestamp are shown right away.
+    pub(crate) fn wait(&mut self, timestamp: u64) -> bool {
+        if timestamp == 0 {
+            return true;
+        }
+
+        let now = Instant::now();
+        if self.anchor.is_none() {
+            self.reset(now, timestamp);
+        }
+
+        let (at, start) = self.anchor.unwrap();
+        let mut due = match timestamp.checked_sub(start) {
+            Some(delta) => at + Duration::from_micros(delta) + self.options.latency,
+            None => now + RESYNC_THRESHOLD * 2,
+        };
+
+        if due > now + RESYNC_THRESHOLD || due + RESYNC_THRESHOLD < now {
+            log::info!("video render pacing starts over, timestamp={}", timestamp);
+
+            self.reset(now, timestamp);
+            due = now + self.options.latency;
+        }
+
+        let mut refresh = self.refresh(due);
+
+        // The frame has missed its refresh, the schedule moves back by the time
+        // that it is behind.
+        let next = self.refresh(now + self.period / 2);
+        if refresh < next {
+            if let Some((at, _)) = self.anchor.as_mut() {
+                *at += self.period * (next - refresh) as u32;
+            }
+
+            if self.options.late == LateFrame::Drop {
+                return false;
+            }
+
+            refresh = next;
+        }
+
+        // Two frames never share a refresh, the first of them would not be seen,
+        // such as the content of a higher frame rate than the display.
+        if self.last.map(|it| refresh <= it).unwrap_or(false) {
+            return false;
+        }
+
+        // The frame is submitted half a refresh early, the surface presents it on
+        // the refresh.
+        let present = self.origin + self.period * refresh as u32;
+        thread::sleep(
+            present
+                .saturating_duration_since(Instant::now())
+                .saturating_sub(self.period / 2),
+        );
+
+        self.last = Some(refresh);
+        true
+    }
+}
@@ -0,0 +1,249 @@
+use crate::receiver::config_codec;
+
+use std::sync::Arc;
+
+use hylarana_transport::BufferFlag;
+use parking_lot::RwLock;
+
+/// The parameters of the codec of an encoded track, which are needed to mux
+/// the packets into a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EncodedCodecParameters {
+    Video {
+        /// `h264` or `hevc`.
+        codec: String,
+        /// The size of the video, zero on a receiver that has not got the
+        /// metadata of the stream yet, the parameters are passed again when
+        /// it arrives.
+        width: u32,
+        height: u32,
+        /// The configuration of the encoder in the Annex B format, the SPS and
+        /// the PPS, and the VPS for HEVC.
+        extradata: Vec<u8>,
+    },
+    Audio {
+        /// Always `opus`.
+        codec: String,
+        sample_rate: u32,
+        channels: u16,
+        /// The Opus identification header.
+        extradata: Vec<u8>,
+    },
+}
+
+/// Receives the encoded packets of a sender or a receiver, so that the
+/// application can mux them into its own container, or push them to a server
+/// with ffmpeg, without decoding and encoding the stream again.
+///
+/// The parameters of a track are passed before its first packet, and again
+/// when they change, such as when the sender switches the codec. The video
+/// starts with a key frame, the packets are in the Annex B format, the audio
+/// packets are Opus, the timestamps are on the media clock, in microseconds.
+pub trait EncodedPacketSink: Sync + Send {
+    #[allow(unused_variables)]
+    fn parameters(&self, parameters: &EncodedCodecParameters) {}
+
+    /// Returning `false` removes the sink, the stream goes on.
+    #[allow(unused_variables)]
+    fn video(&self, packet: &[u8], key_frame: bool, timestamp: u64) -> bool {
+        true
+    }
+
+    /// Returning `false` removes the sink, the stream goes on.
+    #[allow(unused_variables)]
+    fn audio(&self, packet: &[u8], timestamp: u64) -> bool {
+        true
+    }
+
+    /// Callback when the sink is removed or the stream is closed.
+    fn close(&self) {}
+}
+
+// Reads the sample rate and the channels of the Opus identification header.
+fn opus_parameters(header: &[u8]) -> Option<(u32, u16)> {
+    if header.len() < 19 || &header[..8] != b"OpusHead" {
+        return None;
+    }
+
+    Some((
+        u32::from_le_bytes([header[12], header[13], header[14], header[15]]),
+        header[9] as u16,
+    ))
+}
+
+#[derive(Default)]
+struct PacketTapState {
+    sink: Option<Arc<dyn EncodedPacketSink>>,
+    size: (u32, u32),
+    video: Option<EncodedCodecParameters>,
+    audio: Option<EncodedCodecParameters>,
+    // A new sink starts with the next key frame.
+    waiting_key_frame: bool,
+}
+
+impl PacketTapState {
+    fn remove(&mut self) {
+        if let Some(sink) = self.sink.take() {
+            sink.close();
+        }
+    }
+}
+
+impl Drop for PacketTapState {
+    fn drop(&mut self) {
+        self.remove();
+    }
+}
+
+// Passes the encoded packets of a stream to the packet sink of the application.
+// The parameters are kept without a sink, so that a sink that is set in the
+// middle of the stream gets them as well.
+#[derive(Default, Clone)]
+pub(crate) struct PacketTap(Arc<RwLock<PacketTapState>>);
+
+impl PacketTap {
+    pub(crate) fn new(width: u32, height: u32) -> Self {
+        Self(Arc::new(RwLock::new(PacketTapState {
+            size: (width, height),
+            ..Default::default()
+        })))
+    }
+
+    pub(crate) fn set_sink(&self, sink: Option<Arc<dyn EncodedPacketSink>>) {
+        let mut state = self.0.write();
+        state.remove();
+
+        if let Some(sink) = sink.as_ref() {
+            for parameters in [state.video.as_ref(), state.audio.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                sink.parameters(parameters);
+            }
+        }
+
+        state.waiting_key_frame = true;
+        state.sink = sink;
+    }
+
+    // The receiver gets the size of the video from the metadata of the stream.
+    pub(crate) fn set_size(&self, width: u32, height: u32) {
+        let mut state = self.0.write();
+        if state.size == (width, height) {
+            return;
+        }
+
+        state.size = (width, height);
+        if let Some(EncodedCodecParameters::Video {
+            codec, extradata, ..
+        }) = state.video.take()
+        {
+            let parameters = EncodedCodecParameters::Video {
+                codec,
+                width,
+                height,
+                extradata,
+            };
+
+            if let Some(sink) = state.sink.as_ref() {
+                sink.parameters(&parameters);
+            }
+
+            state.video = Some(parameters);
+        }
+    }
+
+    pub(crate) fn video(&self, packet: &[u8], flags: i32, timestamp: u64) {
+        let flags = flags & BufferFlag::MASK;
+        if flags == BufferFlag::Config as i32 {
+            let Some(codec) = config_codec(packet) else {
+                return;
+            };
+
+            let mut state = self.0.write();
+            let parameters = EncodedCodecParameters::Video {
+                codec: codec.to_string(),
+                width: state.size.0,
+                height: state.size.1,
+                extradata: packet.to_vec(),
+            };
+
+            if state.video.as_ref() != Some(&parameters) {
+                if let Some(sink) = state.sink.as_ref() {
+                    sink.parameters(&parameters);
+                }
+
+                state.video = Some(parameters);
+            }
+
+            return;
+        }
+
+        let key_frame = flags == BufferFlag::KeyFrame as i32;
+        let (sink, waiting_key_frame) = {
+            let state = self.0.read();
+            match state.sink.clone() {
+                Some(sink) if key_frame || !state.waiting_key_frame => {
+                    (sink, state.waiting_key_frame)
+                }
+                _ => return,
+            }
+        };
+
+        if waiting_key_frame {
+            self.0.write().waiting_key_frame = false;
+        }
+
+        if !sink.video(packet, key_frame, timestamp) {
+            self.reject(&sink);
+        }
+    }
+
+    pub(crate) fn audio(&self, packet: &[u8], flags: i32, timestamp: u64) {
+        if flags & BufferFlag::MASK == BufferFlag::Config as i32 {
+            let Some((sample_rate, channels)) = opus_parameters(packet) else {
+                return;
+            };
+
+            let mut state = self.0.write();
+            let parameters = EncodedCodecParameters::Audio {
+                codec: "opus".to_string(),
+                extradata: packet.to_vec(),
+                sample_rate,
+                channels,
+            };
+
+            if state.audio.as_ref() != Some(&parameters) {
+                if let Some(sink) = state.sink.as_ref() {
+                    sink.parameters(&parameters);
+                }
+
+                state.audio = Some(parameters);
+            }
+
+            return;
+        }
+
+        let sink = self.0.read().sink.clone();
+        if let Some(sink) = sink {
+            if !sink.audio(packet, timestamp) {
+                self.reject(&sink);
+            }
+        }
+    }
+
+    // The sink is only removed if it has not been replaced in the meantime.
+    fn reject(&self, sink: &Arc<dyn EncodedPacketSink>) {
+        log::warn!("encoded packet sink return false, the sink is removed");
+
+        let mut state = self.0.write();
+        if state
+            .sink
+            .as_ref()
+            .map(|it| Arc::ptr_eq(it, sink))
+            .unwrap_or(false)
+        {
+            state.remove();
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{HylaranaSenderError, HylaranaSenderOptions};
+
+use std::{
+    marker::PhantomData,
+    sync::mpsc::{sync_channel, Receiver, SyncSender},
+    time::{Duration, Instant},
+};
+
+use hylarana_capture::{
+    AudioCaptureSourceDescription, Capture, CaptureOptions, FrameArrived, SourceCaptureOptions,
+    VideoCaptureSourceDescription,
+};
+
+use hylarana_codec::{
+    AudioEncoder, AudioEncoderSettings, CodecType, VideoEncoder, VideoEncoderSettings,
+    VideoEncoderType,
+};
+
+use hylarana_common::{
+    frame::{AudioFrame, VideoFrame},
+    Size,
+};
+
+// How long a capture is given to deliver its first frame.
+const FIRST_FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A stage of the sender that is checked by `Hylarana::preflight`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreflightStage {
+    VideoCapture,
+    /// The index of the audio track in the options.
+    AudioCapture(usize),
+    VideoEncoder,
+    AudioEncoder,
+    Transport,
+}
+
+/// The result of a stage of the preflight.
+#[derive(Debug)]
+pub struct PreflightCheck {
+    pub stage: PreflightStage,
+    /// The time it took to start and stop the stage, without the time waited
+    /// for the first frame.
+    pub elapsed: Duration,
+    /// The time from the start of a capture to its first frame. This is `None`
+    /// for the other stages, and if no frame arrived in time, which is not an
+    /// error, such as the system audio while nothing is playing.
+    pub first_frame: Option<Duration>,
+    /// The video encoder that would be used, which is different from the
+    /// configured encoder if it has fallen back.
+    pub video_encoder: Option<VideoEncoderType>,
+    /// The error that the stage failed with, `None` if the stage works.
+    pub error: Option<HylaranaSenderError>,
+}
+
+impl PreflightCheck {
+    fn new(stage: PreflightStage) -> Self {
+        Self {
+            elapsed: Duration::ZERO,
+            first_frame: None,
+            video_encoder: None,
+            error: None,
+            stage,
+        }
+    }
+}
+
+/// The result of `Hylarana::preflight`, a check for each configured stage.
+#[derive(Debug, Default)]
+pub struct PreflightReport {
+    pub checks: Vec<PreflightCheck>,
+}
+
+impl PreflightReport {
+    /// Whether all the stages work.
+    pub fn is_ok(&self) -> bool {
+        self.checks.iter().all(|it| it.error.is_none())
+    }
+
+    /// The stages that failed.
+    pub fn failures(&self) -> impl Iterator<Item = &PreflightCheck> {
+        self.checks.iter().filter(|it| it.error.is_some())
+    }
+}
+
+// Takes the frames of the capture, only the arrival of the first frame is
+// reported.
+struct FrameProbe<T> {
+    first: Option<SyncSender<()>>,
+    _frame: PhantomData<fn(&T)>,
+}
+
+impl<T> FrameProbe<T> {
+    fn new(first: SyncSender<()>) -> Self {
+        Self {
+            first: Some(first),
+            _frame: PhantomData,
+        }
+    }
+}
+
+impl<T> FrameArrived for FrameProbe<T> {
+    type Frame = T;
+
+    fn sink(&mut self, _: &Self::Frame) -> bool {
+        if let Some(first) = self.first.take() {
+            let _ = first.try_send(());
+        }
+
+        true
+    }
+}
+
+// The stage returns the time it has waited for the frames, which is not counted.
+fn check<F>(stage: PreflightStage, func: F) -> PreflightCheck
+where
+    F: FnOnce(&mut PreflightCheck) -> Result<Duration, HylaranaSenderError>,
+{
+    let mut check = PreflightCheck::new(stage);
+
+    let now = Instant::now();
+    let waited = match func(&mut check) {
+        Ok(waited) => waited,
+        Err(e) => {
+            check.error = Some(e);
+            Duration::ZERO
+        }
+    };
+
+    check.elapsed = now.elapsed().saturating_sub(waited);
+
+    if let Some(e) = check.error.as_ref() {
+        tracing::warn!(stage = ?stage, error = ?e, "preflight stage failed");
+    } else {
+        tracing::info!(stage = ?stage, elapsed = ?check.elapsed, first_frame = ?check.first_frame, "preflight stage passed");
+    }
+
+    check
+}
+
+// Start the capture and wait for its first frame, returns the time waited.
+fn start_capture<V, A>(
+    check: &mut PreflightCheck,
+    options: CaptureOptions<V, A>,
+    first: Receiver<()>,
+) -> Result<Duration, HylaranaSenderError>
+where
+    V: FrameArrived<Frame = VideoFrame> + 'static,
+    A: FrameArrived<Frame = AudioFrame> + 'static,
+{
+    let now = Instant::now();
+    let capture = Capture::start(options)?;
+
+    let started = now.elapsed();
+    if first.recv_timeout(FIRST_FRAME_TIMEOUT).is_ok() {
+        check.first_frame = Some(now.elapsed());
+    }
+
+    let waited = now.elapsed() - started;
+
+    capture.close()?;
+    Ok(waited)
+}
+
+pub(crate) fn preflight(options: &HylaranaSenderOptions) -> PreflightReport {
+    let mut report = PreflightReport::default();
+    let media = &options.media;
+
+    if let Some(video) = media.video.as_ref() {
+        #[cfg(target_os = "windows")]
+        let direct3d = media.graphics.direct3d();
+
+        let mut codec = video.options.codec;
+        report
+            .checks
+            .push(check(PreflightStage::VideoEncoder, |check| {
+                let (_, used) = VideoEncoder::with_fallback(VideoEncoderSettings {
+                    codec: video.options.codec,
+                    key_frame_interval: video.options.key_frame_interval,
+                    frame_rate: video.options.frame_rate,
+                    width: video.options.width,
+                    height: video.options.height,
+                    bit_rate: video.options.bit_rate,
+                    tuning: video.options.tuning,
+                    scene_cut: video.options.scene_cut.is_some(),
+                    #[cfg(target_os = "windows")]
+                    direct3d: Some(direct3d.clone()),
+                })?;
+
+                check.video_encoder = Some(used);
+                codec = used;
+                Ok(Duration::ZERO)
+            }));
+
+        // The capture outputs the frames for the encoder, as the sender does.
+        report
+            .checks
+            .push(check(PreflightStage::VideoCapture, |check| {
+                let (tx, rx) = sync_channel(1);
+                start_capture(
+                    check,
+                    CaptureOptions::<_, FrameProbe<AudioFrame>> {
+                        video: Some(SourceCaptureOptions {
+                            description: VideoCaptureSourceDescription {
+                                hardware: CodecType::from(codec).is_hardware(),
+                                hdr: CodecType::from(codec).is_10bit(),
+                                fps: video.options.frame_rate,
+                                adaptive_pacing: video.options.adaptive_pacing,
+                                size: video.options.capture_size.unwrap_or(Size {
+                                    width: video.options.width,
+                                    height: video.options.height,
+                                }),
+                                source: video.source.clone(),
+                                #[cfg(target_os = "windows")]
+                                direct3d: direct3d.clone(),
+                            },
+                            arrived: FrameProbe::<VideoFrame>::new(tx),
+                        }),
+                        audio: Vec::new(),
+                    },
+                    rx,
+                )
+            }));
+    }
+
+    if let Some(first) = media.audio.first() {
+        report.checks.push(check(PreflightStage::AudioEncoder, |_| {
+            AudioEncoder::new(AudioEncoderSettings {
+                bit_rate: first.options.bit_rate,
+                sample_rate: first.options.sample_rate,
+            })?;
+
+            Ok(Duration::ZERO)
+        }));
+
+        for (index, track) in media.audio.iter().enumerate() {
+            report
+                .checks
+                .push(check(PreflightStage::AudioCapture(index), |check| {
+                    let (tx, rx) = sync_channel(1);
+                    start_capture(
+                        check,
+                        CaptureOptions::<FrameProbe<VideoFrame>, _> {
+                            video: None,
+                            audio: vec![SourceCaptureOptions {
+                                description: AudioCaptureSourceDescription {
+                                    sample_rate: first.options.sample_rate as u32,
+                                    source: track.source.clone(),
+                                    config: track.options.capture,
+                                },
+                                arrived: FrameProbe::<AudioFrame>::new(tx),
+                            }],
+                        },
+                        rx,
+                    )
+                }));
+        }
+    }
+
+    // The sender binds or connects the sockets of the transport, it is closed
+    // when it is dropped.
+    report.checks.push(check(PreflightStage::Transport, |_| {
+        hylarana_transport::create_sender(options.transport)?;
+
+        Ok(Duration::ZERO)
+    }));
+
+    report
+}
@@ -0,0 +1,220 @@
+use crate::{AudioOptions, DtxOptions, VideoOptions};
+
+use std::{fs, path::Path};
+
+use hylarana_codec::{RateControl, VideoEncoderTuning, VideoEncoderType, VideoProfile};
+use hylarana_transport::TransportOptions;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ProfileError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    TomlDeserializeError(#[from] toml::de::Error),
+    #[error(transparent)]
+    TomlSerializeError(#[from] toml::ser::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+    #[error("unknown profile format, the extension of the file must be toml or json")]
+    UnknownFormat,
+}
+
+/// The format of the profile files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProfileFormat {
+    Toml,
+    Json,
+}
+
+impl ProfileFormat {
+    /// Get the format from the extension of the file.
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        match path.as_ref().extension()?.to_str()? {
+            "toml" => Some(Self::Toml),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+/// A named preset of the options of the sender, the options that are `None`
+/// are left to the application.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Profile {
+    pub name: String,
+    #[serde(default)]
+    pub video: Option<VideoOptions>,
+    #[serde(default)]
+    pub audio: Option<AudioOptions>,
+    /// The address of the transport depends on the network, so the built-in
+    /// profiles do not have it.
+    #[serde(default)]
+    pub transport: Option<TransportOptions>,
+}
+
+/// A list of profiles, which is saved as a list of tables in toml:
+///
+/// ```toml
+/// [[profile]]
+/// name = "Low latency LAN"
+///
+/// [profile.video]
+/// codec = "X264"
+/// frame_rate = 60
+/// ...
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Profiles {
+    #[serde(default, rename = "profile")]
+    profiles: Vec<Profile>,
+}
+
+impl Profiles {
+    pub const LOW_LATENCY_LAN: &'static str = "Low latency LAN";
+    pub const QUALITY_WAN: &'static str = "Quality WAN";
+
+    /// The profiles that are built into the sdk, x264 is used because it is
+    /// available everywhere, the sender falls back to it anyway.
+    pub fn builtin() -> Self {
+        Self {
+            profiles: vec![
+                // Bandwidth is plentiful in the local network, the bit rate is kept
+                // constant and the picture is never buffered.
+                Profile {
+                    name: Self::LOW_LATENCY_LAN.to_string(),
+                    video: Some(VideoOptions {
+                        codec: VideoEncoderType::X264,
+                        frame_rate: 60,
+                        width: 1920,
+                        height: 1080,
+                        bit_rate: 10 * 1024 * 1024,
+                        key_frame_interval: 120,
+                        adaptive_pacing: true,
+                        capture_size: None,
+                        thumbnail: None,
+                        deinterlace: None,
+                        watermark: None,
+                        privacy_masks: None,
+                        content_detection: None,
+                        scene_cut: None,
+                        dirty_regions: None,
+                        tuning: VideoEncoderTuning {
+                            rate_control: Some(RateControl::Cbr),
+                            ..Default::default()
+                        },
+                    }),
+                    audio: Some(AudioOptions {
+                        sample_rate: 48000,
+                        bit_rate: 64000,
+                        gain: 1.0,
+                        capture: Default::default(),
+                        dtx: None,
+                    }),
+                    transport: None,
+                },
+                // The links over the internet are narrower, the key frames are replaced
+                // with intra refresh to avoid the spikes, and the encoder is allowed to
+                // look ahead for better quality at the lower bit rate. The audio is not
+                // sent during silence.
+                Profile {
+                    name: Self::QUALITY_WAN.to_string(),
+                    video: Some(VideoOptions {
+                        codec: VideoEncoderType::X264,
+                        frame_rate: 30,
+                        width: 1920,
+                        height: 1080,
+                        bit_rate: 4 * 1024 * 1024,
+                        key_frame_interval: 60,
+                        adaptive_pacing: true,
+                        capture_size: None,
+                        thumbnail: None,
+                        deinterlace: None,
+                        watermark: None,
+                        privacy_masks: None,
+                        content_detection: None,
+                        scene_cut: None,
+                        dirty_regions: None,
+                        tuning: VideoEncoderTuning {
+                            profile: Some(VideoProfile::High),
+                            rate_control: Some(RateControl::Vbr {
+                                max_bit_rate: 6 * 1024 * 1024,
+                            }),
+                            low_latency: false,
+                            intra_refresh: true,
+                            ..Default::default()
+                        },
+                    }),
+                    audio: Some(AudioOptions {
+                        sample_rate: 48000,
+                        bit_rate: 128000,
+                        gain: 1.0,
+                        capture: Default::default(),
+                        dtx: Some(DtxOptions::default()),
+                    }),
+                    transport: None,
+                },
+            ],
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|it| it.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Profile> {
+        self.profiles.iter()
+    }
+
+    /// Add a profile, the profile with the same name is replaced.
+    pub fn insert(&mut self, profile: Profile) {
+        if let Some(it) = self.profiles.iter_mut().find(|it| it.name == profile.name) {
+            *it = profile;
+        } else {
+            self.profiles.push(profile);
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Profile> {
+        let index = self.profiles.iter().position(|it| it.name == name)?;
+        Some(self.profiles.remove(index))
+    }
+
+    /// Add all profiles of the other list, such as the profiles of the user
+    /// on top of the built-in profiles.
+    pub fn extend(&mut self, other: Profiles) {
+        for profile in other.profiles {
+            self.insert(profile);
+        }
+    }
+
+    pub fn parse(text: &str, format: ProfileFormat) -> Result<Self, ProfileError> {
+        Ok(match format {
+            ProfileFormat::Toml => toml::from_str(text)?,
+            ProfileFormat::Json => serde_json::from_str(text)?,
+        })
+    }
+
+    pub fn to_string(&self, format: ProfileFormat) -> Result<String, ProfileError> {
+        Ok(match format {
+            ProfileFormat::Toml => toml::to_string_pretty(self)?,
+            ProfileFormat::Json => serde_json::to_string_pretty(self)?,
+        })
+    }
+
+    /// Load the profiles from a file, the format is chosen by the extension
+    /// of the file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ProfileError> {
+        let format = ProfileFormat::from_path(&path).ok_or(ProfileError::UnknownFormat)?;
+        Self::parse(&fs::read_to_string(path)?, format)
+    }
+
+    /// Save the profiles to a file, the format is chosen by the extension of
+    /// the file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), ProfileError> {
+        let format = ProfileFormat::from_path(&path).ok_or(ProfileError::UnknownFormat)?;
+        fs::write(path, self.to_string(format)?)?;
+        Ok(())
+    }
+}
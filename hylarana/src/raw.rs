@@ -0,0 +1,323 @@
+use crate::{
+    close_stream,
+    metrics::{Metrics, METRICS},
+    sender::{send_clock, EncoderControl, PacketOutput},
+    AVFrameStream, DisconnectReason, StreamStatus,
+};
+
+use std::{
+    mem::size_of,
+    ptr::null,
+    sync::{Arc, Weak},
+};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use hylarana_capture::{FrameArrived, SourceEvent};
+use hylarana_common::frame::{
+    get_planes, AudioFrame, VideoFormat, VideoFrame, VideoRotation, VideoSubFormat,
+};
+use hylarana_transport::{BufferFlag, PacketPool, StreamBufferInfo};
+
+// The shared memory transport carries the raw frames, the configuration of a
+// raw track only tells the receivers that the track has started.
+pub(crate) const RAW_VIDEO_CONFIG: &[u8] = b"HYLARANA_RAW_VIDEO";
+pub(crate) const RAW_AUDIO_CONFIG: &[u8] = b"HYLARANA_RAW_AUDIO";
+
+// | format (u8) | padding | width (u32) | height (u32) | planes |
+const VIDEO_HEADER: usize = 12;
+
+// | sample rate (u32) | samples |
+const AUDIO_HEADER: usize = 4;
+
+fn video_format(value: u8) -> Option<VideoFormat> {
+    Some(match value {
+        0 => VideoFormat::BGRA,
+        1 => VideoFormat::RGBA,
+        2 => VideoFormat::NV12,
+        3 => VideoFormat::I420,
+        4 => VideoFormat::P010,
+        _ => return None,
+    })
+}
+
+// The planes are packed without the padding of the rows, returns false for the
+// frames that are not in the memory.
+fn pack_video_frame(frame: &VideoFrame, buf: &mut BytesMut) -> bool {
+    if frame.sub_format != VideoSubFormat::SW {
+        return false;
+    }
+
+    let planes = get_planes(frame.format, frame.width, frame.height);
+    let size = planes.iter().map(|(it, rows)| it * rows).sum::<usize>();
+
+    buf.clear();
+    buf.reserve(VIDEO_HEADER + size);
+    buf.put_u8(frame.format as u8);
+    buf.put_bytes(0, 3);
+    buf.put_u32_le(frame.width);
+    buf.put_u32_le(frame.height);
+
+    for (i, (linesize, rows)) in planes.iter().enumerate() {
+        if *rows == 0 {
+            continue;
+        }
+
+        if frame.data[i].is_null() || frame.linesize[i] < *linesize {
+            return false;
+        }
+
+        for row in 0..*rows {
+            buf.put_slice(unsafe {
+                std::slice::from_raw_parts(
+                    (frame.data[i] as *const u8).add(row * frame.linesize[i]),
+                    *linesize,
+                )
+            });
+        }
+    }
+
+    true
+}
+
+pub(crate) fn pack_audio_frame(frame: &AudioFrame, buf: &mut BytesMut) {
+    buf.clear();
+    buf.put_u32_le(frame.sample_rate);
+    buf.put_slice(unsafe {
+        std::slice::from_raw_parts(
+            frame.data as *const u8,
+            frame.frames as usize * size_of::<i16>(),
+        )
+    });
+}
+
+/// Sends the captured video frames to the shared memory transport as they
+/// are, instead of the encoder.
+pub(crate) struct RawVideoSender<T: AVFrameStream + 'static> {
+    output: PacketOutput,
+    packets: PacketPool,
+    status: Arc<StreamStatus>,
+    control: Arc<EncoderControl>,
+    sink: Weak<T>,
+    buffer: BytesMut,
+    // The time the clock of the sender was last sent.
+    clock: u64,
+    // The frames that are not in the memory are only reported once.
+    dropped: bool,
+}
+
+impl<T: AVFrameStream + 'static> RawVideoSender<T> {
+    pub(crate) fn new(
+        status: Arc<StreamStatus>,
+        output: &PacketOutput,
+        control: Arc<EncoderControl>,
+        sink: &Arc<T>,
+    ) -> Self {
+        let packets = PacketPool::default();
+        output.send(
+            &packets,
+            RAW_VIDEO_CONFIG,
+            StreamBufferInfo::Video(BufferFlag::Config as i32, 0),
+        );
+
+        Self {
+            output: output.clone(),
+            buffer: BytesMut::new(),
+            sink: Arc::downgrade(sink),
+            clock: 0,
+            dropped: false,
+            packets,
+            control,
+            status,
+        }
+    }
+
+    fn process(&mut self, frame: &VideoFrame) -> Result<(), DisconnectReason> {
+        Metrics::increment(&METRICS.video_frames_captured);
+        self.control.capture.beat();
+
+        send_clock(&self.output, &self.packets, &mut self.clock);
+
+        // Every frame stands on its own, so every frame is a key frame, the
+        // receivers that join in the middle of the stream start with any frame.
+        if pack_video_frame(frame, &mut self.buffer) {
+            self.control.encode.beat();
+
+            Metrics::increment(&METRICS.packets_sent);
+            Metrics::add(&METRICS.bytes_sent, self.buffer.len() as u64);
+
+            if !self.output.send(
+                &self.packets,
+                &self.buffer,
+                StreamBufferInfo::Video(
+                    BufferFlag::with_orientation(
+                        BufferFlag::KeyFrame as i32,
+                        frame.rotation,
+                        frame.mirror,
+                    ),
+                    frame.timestamp,
+                ),
+            ) {
+                tracing::warn!("raw video send frame to output failed");
+
+                return Err(self.output.closed_reason());
+            }
+        } else if !std::mem::replace(&mut self.dropped, true) {
+            tracing::warn!(sub_format = ?frame.sub_format, "raw video frame is not in the memory, drop it");
+        }
+
+        if let Some(sink) = self.sink.upgrade() {
+            if sink.video(frame) {
+                Ok(())
+            } else {
+                tracing::warn!("video sink on frame return false");
+
+                Err(DisconnectReason::SinkClosed)
+            }
+        } else {
+            tracing::warn!("video sink weak upgrade failed, maybe is drop");
+
+            Err(DisconnectReason::Closed)
+        }
+    }
+}
+
+impl<T: AVFrameStream + 'static> FrameArrived for RawVideoSender<T> {
+    type Frame = VideoFrame;
+
+    fn sink(&mut self, frame: &Self::Frame) -> bool {
+        if let Err(reason) = self.process(frame) {
+            if let Some(sink) = self.sink.upgrade() {
+                close_stream(&self.status, sink.as_ref(), reason);
+            }
+
+            false
+        } else {
+            true
+        }
+    }
+
+    fn event(&mut self, event: SourceEvent) {
+        tracing::info!(event = ?event, "video capture source event");
+
+        if event == SourceEvent::Removed {
+            if let Some(sink) = self.sink.upgrade() {
+                close_stream(&self.status, sink.as_ref(), DisconnectReason::SourceRemoved);
+            }
+        }
+    }
+}
+
+/// Takes the raw video frames of the shared memory transport in place of the
+/// decoder, the frame points to the packet.
+#[derive(Default)]
+pub(crate) struct RawVideoDecoder {
+    packet: Bytes,
+    frame: VideoFrame,
+    ready: bool,
+}
+
+impl RawVideoDecoder {
+    pub(crate) fn decode(&mut self, packet: &Bytes, timestamp: u64) {
+        if packet.as_ref() == RAW_VIDEO_CONFIG {
+            return;
+        }
+
+        let format = if packet.len() >= VIDEO_HEADER {
+            video_format(packet[0])
+        } else {
+            None
+        };
+
+        let Some(format) = format else {
+            tracing::warn!(size = packet.len(), "invalid raw video frame");
+            return;
+        };
+
+        let width = u32::from_le_bytes(packet[4..8].try_into().unwrap());
+        let height = u32::from_le_bytes(packet[8..12].try_into().unwrap());
+        let planes = get_planes(format, width, height);
+        if planes.iter().map(|(it, rows)| it * rows).sum::<usize>() != packet.len() - VIDEO_HEADER {
+            tracing::warn!(
+                size = packet.len(),
+                width,
+                height,
+                "invalid raw video frame"
+            );
+            return;
+        }
+
+        self.packet = packet.clone();
+
+        let mut data = [null(); 3];
+        let mut linesize = [0; 3];
+        let mut offset = VIDEO_HEADER;
+        for (i, (size, rows)) in planes.iter().enumerate() {
+            if *rows > 0 {
+                data[i] = unsafe { self.packet.as_ptr().add(offset) } as *const _;
+                linesize[i] = *size;
+                offset += size * rows;
+            }
+        }
+
+        self.frame.format = format;
+        self.frame.sub_format = VideoSubFormat::SW;
+        self.frame.width = width;
+        self.frame.height = height;
+        self.frame.data = data;
+        self.frame.linesize = linesize;
+        self.frame.timestamp = timestamp;
+        self.ready = true;
+    }
+
+    pub(crate) fn set_orientation(&mut self, rotation: VideoRotation, mirror: bool) {
+        self.frame.rotation = rotation;
+        self.frame.mirror = mirror;
+    }
+
+    // The frame is taken with the packet that it points to, so that it can be
+    // kept while the next frames are taken.
+    pub(crate) fn take(&mut self) -> Option<(Bytes, VideoFrame)> {
+        if std::mem::take(&mut self.ready) {
+            Some((self.packet.clone(), VideoFrame { ..self.frame }))
+        } else {
+            None
+        }
+    }
+}
+
+/// Takes the raw audio frames of the shared memory transport in place of the
+/// decoder.
+#[derive(Default)]
+pub(crate) struct RawAudioDecoder {
+    packet: Bytes,
+    frame: AudioFrame,
+    ready: bool,
+}
+
+impl RawAudioDecoder {
+    pub(crate) fn decode(&mut self, packet: &Bytes, timestamp: u64) {
+        if packet.as_ref() == RAW_AUDIO_CONFIG {
+            return;
+        }
+
+        if packet.len() < AUDIO_HEADER || (packet.len() - AUDIO_HEADER) % size_of::<i16>() != 0 {
+            tracing::warn!(size = packet.len(), "invalid raw audio frame");
+            return;
+        }
+
+        self.packet = packet.clone();
+        self.frame.sample_rate = u32::from_le_bytes(packet[0..4].try_into().unwrap());
+        self.frame.frames = ((packet.len() - AUDIO_HEADER) / size_of::<i16>()) as u32;
+        self.frame.data = unsafe { self.packet.as_ptr().add(AUDIO_HEADER) } as *const i16;
+        self.frame.timestamp = timestamp;
+        self.ready = true;
+    }
+
+    pub(crate) fn read(&mut self) -> Option<&AudioFrame> {
+        if std::mem::take(&mut self.ready) {
+            Some(&self.frame)
+        } else {
+            None
+        }
+    }
+}
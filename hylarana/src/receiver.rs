@@ -1,15 +1,48 @@
-use crate::AVFrameStream;
+use crate::{
+    close_stream,
+    meter::AudioMeter,
+    metrics::{Metrics, METRICS},
+    packets::{EncodedPacketSink, PacketTap},
+    raw::{RawAudioDecoder, RawVideoDecoder},
+    stream::FrameQueueInner,
+    video_codec_name,
+    watchdog::{Heartbeat, PipelineStage, StallState, Verdict, Watchdog},
+    AVFrameObserver, AVFrameSink, AVFrameStream, AVFrameStreamPlayer, DisconnectReason, FrameQueue,
+    GraphicsContext, MessageKind, RgbaImage, SinkBufferStats, StreamCapabilities, StreamErrorKind,
+    StreamEvent, StreamFeature, StreamMetadata, StreamStatus, VideoRenderError,
+    KEY_FRAME_REQUEST_INTERVAL,
+};
 
 use std::{
-    sync::{atomic::AtomicBool, Arc},
-    thread,
+    collections::VecDeque,
+    io::ErrorKind,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
 };
 
-use hylarana_codec::{AudioDecoder, VideoDecoder, VideoDecoderSettings, VideoDecoderType};
-use hylarana_common::atomic::EasyAtomic;
+use bytes::Bytes;
+use hylarana_codec::{
+    AudioDecoder, CodecType, DecodedVideoFrame, VideoDecoder, VideoDecoderSettings,
+    VideoDecoderType, MAX_TAKEN_FRAMES,
+};
+use hylarana_common::{
+    atomic::EasyAtomic,
+    clock::MediaClock,
+    frame::{AudioFrame, OwnedAudioFrame, OwnedVideoFrame, VideoFrame, VideoRotation},
+    input::InputEvent,
+    runtime::{self, ThreadKind},
+};
 use hylarana_transport::{
-    StreamKind, StreamMultiReceiverAdapter, TransportOptions, TransportReceiver,
+    BandwidthEstimate, BufferFlag, StreamControl, StreamKind, StreamMultiReceiverAdapter,
+    StreamReceiverAdapterAbstract, StreamTracks, TransportOptions, TransportReceiver,
+    TransportStrategy,
 };
+use parking_lot::Mutex;
 
 use thiserror::Error;
 
@@ -24,12 +57,28 @@ pub enum HylaranaReceiverError {
     VideoDecoderError(#[from] hylarana_codec::VideoDecoderError),
     #[error(transparent)]
     AudioDecoderError(#[from] hylarana_codec::AudioDecoderError),
+    #[error("failed to send message: {0}")]
+    SendMessageError(std::io::Error),
+    #[error("the sender requires a pairing code")]
+    PairingFailed,
 }
 
 /// Receiver media codec configuration.
 #[derive(Debug, Clone)]
 pub struct HylaranaReceiverCodecOptions {
     pub video: VideoDecoderType,
+    /// The context that the decoder runs on, the player of the frames has to
+    /// use the same context.
+    pub graphics: GraphicsContext,
+    /// Only take the thumbnails and the control messages of the stream, the
+    /// media is received but neither decoded nor passed to the sink, such as
+    /// for a list of the streams with previews.
+    pub thumbnail_only: bool,
+    /// Pass the encoded packets to `AVFrameSink::video_packet` and
+    /// `AVFrameSink::audio_packet` instead of decoding them, such as for
+    /// remuxing the stream without decoding it. The frames of the shared
+    /// memory transport are not encoded, they are still passed as frames.
+    pub passthrough: bool,
 }
 
 /// Receiver configuration.
@@ -37,123 +86,986 @@ pub struct HylaranaReceiverCodecOptions {
 pub struct HylaranaReceiverOptions {
     pub transport: TransportOptions,
     pub codec: HylaranaReceiverCodecOptions,
+    /// Only take the audio or the video of the stream, the decoder of the
+    /// other track is not created. The multicast and shared memory senders
+    /// still send all the tracks, which the receiver drops.
+    pub tracks: StreamTracks,
+    /// The code that is submitted to a sender that requires pairing, see
+    /// `PairingOptions`. The receiver asks
+    /// `AVFrameObserver::pairing_code_required` for the code if this is
+    /// `None` or the code is wrong.
+    pub pairing_code: Option<String>,
 }
 
-fn create_video_decoder<T: AVFrameStream + 'static>(
-    transport: &TransportReceiver<StreamMultiReceiverAdapter>,
-    status: Arc<AtomicBool>,
+/// The upper bounds of the buckets of the latency histogram in milliseconds,
+/// the frames above the last bound are counted in the last bucket.
+pub const LATENCY_HISTOGRAM_BOUNDS: [u64; 7] = [16, 33, 50, 100, 200, 500, 1000];
+
+/// Capture to display latency of the recently rendered video frames.
+///
+/// The latency is calculated with the system clocks of the sender and the
+/// receiver, so it is only accurate when the clocks of the devices are
+/// synchronized, such as with NTP.
+#[derive(Debug, Clone, Default)]
+pub struct LatencyStats {
+    /// The number of frames that are measured.
+    pub samples: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    /// The number of frames in each bucket of `LATENCY_HISTOGRAM_BOUNDS`.
+    pub histogram: [usize; LATENCY_HISTOGRAM_BOUNDS.len() + 1],
+}
+
+/// Time it takes to decode the recent video packets.
+#[derive(Debug, Clone, Default)]
+pub struct DecodeStats {
+    /// The number of packets that are measured.
+    pub samples: usize,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+/// The video frames that are not shown, counted since the receiver was
+/// created.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DroppedFrames {
+    /// The frames that are decoded but not passed to the sink, because the
+    /// sink is too slow and the receiver catches up with the stream.
+    pub late: u64,
+    /// The packets that are dropped while the decoder waits for a key frame,
+    /// such as after packet loss.
+    pub corrupt: u64,
+    /// The frames that the sink dropped because its queue was full.
+    pub overflow: u64,
+}
+
+/// Statistics of the receiver.
+#[derive(Debug, Clone, Default)]
+pub struct HylaranaReceiverStats {
+    /// This is `None` until the clock of the sender is received and a frame is
+    /// rendered.
+    pub latency: Option<LatencyStats>,
+    /// This is `None` until a video packet is decoded.
+    pub decode: Option<DecodeStats>,
+    pub dropped: DroppedFrames,
+    /// The media that is waiting in the sink to be played, see
+    /// `SinkBufferStats`.
+    pub buffered: Duration,
+    /// The times the audio output ran out of samples.
+    pub audio_underruns: u64,
+    /// The estimate of the link to the sender, see
+    /// `HylaranaSender::estimated_bandwidth`.
+    pub bandwidth: Option<BandwidthEstimate>,
+}
+
+// The number of recent frames that the latency and the decode time are
+// measured over.
+const LATENCY_WINDOW: usize = 600;
+
+// The decoded frames are not rendered while more video packets than this are
+// waiting, the sink is behind the stream and shows the newer frames instead.
+const LATE_FRAME_BACKLOG: usize = 10;
+
+// The decoded frames that wait for the render thread, the oldest frame is
+// dropped when the sink is behind instead of holding up the decoder. One more
+// frame is being rendered and one is taken from the decoder while the queue is
+// full, which are all held out of the surfaces of the decoder.
+const RENDER_QUEUE_SIZE: usize = MAX_TAKEN_FRAMES - 2;
+
+// The render thread waits for the frames in turns of this, the queue is closed
+// when the decoder thread exits.
+const RENDER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Default)]
+struct StatsProbe {
+    // The last clock of the sender, the media clock and the system time.
+    clock: Option<(u64, u64)>,
+    // Updated with the clock of the sender and when the stats are read.
+    bandwidth: Option<BandwidthEstimate>,
+    // Latency of the recent frames in microseconds.
+    samples: VecDeque<u64>,
+    // Decode time of the recent packets in microseconds.
+    decode: VecDeque<u64>,
+    dropped: DroppedFrames,
+}
+
+fn push_sample(samples: &mut VecDeque<u64>, value: u64) {
+    if samples.len() >= LATENCY_WINDOW {
+        samples.pop_front();
+    }
+
+    samples.push_back(value);
+}
+
+fn sorted_samples(samples: &VecDeque<u64>) -> Vec<u64> {
+    let mut samples = samples.iter().copied().collect::<Vec<_>>();
+    samples.sort_unstable();
+    samples
+}
+
+fn percentile(samples: &[u64], p: usize) -> Duration {
+    Duration::from_micros(samples[(samples.len() - 1) * p / 100])
+}
+
+fn mean(samples: &[u64]) -> Duration {
+    Duration::from_micros(samples.iter().sum::<u64>() / samples.len() as u64)
+}
+
+impl StatsProbe {
+    // Called when the frame is rendered, the timestamp is the capture time of the
+    // frame on the media clock of the sender.
+    fn record(&mut self, timestamp: u64) {
+        let Some((media, system)) = self.clock else {
+            return;
+        };
+
+        if timestamp == 0 {
+            return;
+        }
+
+        // The capture time of the frame on the system clock of the sender, the
+        // difference of the clocks may make the latency negative.
+        let capture = system as i64 + (timestamp as i64 - media as i64);
+        let latency = (MediaClock::system() as i64 - capture).max(0) as u64;
+
+        push_sample(&mut self.samples, latency);
+    }
+
+    fn record_decode(&mut self, elapsed: Duration) {
+        push_sample(&mut self.decode, elapsed.as_micros() as u64);
+    }
+
+    // The buffers and the overflow of the sink are added to the statistics of the
+    // decoder, they are taken from the sink before the probe is locked, so that
+    // the threads that record into the probe do not wait for the sink.
+    fn stats(&self, buffers: SinkBufferStats) -> HylaranaReceiverStats {
+        HylaranaReceiverStats {
+            latency: self.latency_stats(),
+            decode: if self.decode.is_empty() {
+                None
+            } else {
+                let samples = sorted_samples(&self.decode);
+
+                Some(DecodeStats {
+                    samples: samples.len(),
+                    mean: mean(&samples),
+                    p50: percentile(&samples, 50),
+                    p95: percentile(&samples, 95),
+                    p99: percentile(&samples, 99),
+                    max: percentile(&samples, 100),
+                })
+            },
+            dropped: DroppedFrames {
+                overflow: buffers.overflow,
+                ..self.dropped
+            },
+            buffered: buffers.buffered,
+            audio_underruns: buffers.audio_underruns,
+            bandwidth: self.bandwidth,
+        }
+    }
+
+    fn latency_stats(&self) -> Option<LatencyStats> {
+        if self.samples.is_empty() {
+            return None;
+        }
+
+        let samples = sorted_samples(&self.samples);
+
+        let mut histogram = [0; LATENCY_HISTOGRAM_BOUNDS.len() + 1];
+        for it in &samples {
+            histogram[LATENCY_HISTOGRAM_BOUNDS
+                .iter()
+                .position(|bound| *it < bound * 1000)
+                .unwrap_or(LATENCY_HISTOGRAM_BOUNDS.len())] += 1;
+        }
+
+        Some(LatencyStats {
+            samples: samples.len(),
+            min: percentile(&samples, 0),
+            max: percentile(&samples, 100),
+            mean: mean(&samples),
+            p50: percentile(&samples, 50),
+            p95: percentile(&samples, 95),
+            p99: percentile(&samples, 99),
+            histogram,
+        })
+    }
+}
+
+// The frames of the shared memory transport are not encoded, they are taken as
+// they are instead of decoded.
+enum VideoCodec {
+    Decoder(VideoDecoder),
+    Raw(RawVideoDecoder),
+}
+
+impl VideoCodec {
+    fn decode(
+        &mut self,
+        packet: &Bytes,
+        timestamp: u64,
+    ) -> Result<(), hylarana_codec::VideoDecoderError> {
+        match self {
+            Self::Decoder(it) => it.decode(packet, timestamp),
+            Self::Raw(it) => {
+                it.decode(packet, timestamp);
+                Ok(())
+            }
+        }
+    }
+
+    fn set_orientation(&mut self, rotation: VideoRotation, mirror: bool) {
+        match self {
+            Self::Decoder(it) => it.set_orientation(rotation, mirror),
+            Self::Raw(it) => it.set_orientation(rotation, mirror),
+        }
+    }
+
+    fn take(&mut self) -> Option<RenderFrame> {
+        match self {
+            Self::Decoder(it) => it.take().map(RenderFrame::Decoded),
+            Self::Raw(it) => it
+                .take()
+                .map(|(packet, frame)| RenderFrame::Raw(packet, frame)),
+        }
+    }
+}
+
+// The frames that the decoder thread passes to the render thread, they are
+// kept alive until they are rendered.
+enum RenderFrame {
+    Decoded(DecodedVideoFrame),
+    // The raw frame points to the packet.
+    Raw(Bytes, VideoFrame),
+}
+
+impl Deref for RenderFrame {
+    type Target = VideoFrame;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Decoded(it) => it,
+            Self::Raw(_, it) => it,
+        }
+    }
+}
+
+enum AudioCodec {
+    Decoder(AudioDecoder),
+    Raw(RawAudioDecoder),
+}
+
+impl AudioCodec {
+    fn decode(
+        &mut self,
+        packet: &Bytes,
+        timestamp: u64,
+    ) -> Result<(), hylarana_codec::AudioDecoderError> {
+        match self {
+            Self::Decoder(it) => it.decode(packet, timestamp),
+            Self::Raw(it) => {
+                it.decode(packet, timestamp);
+                Ok(())
+            }
+        }
+    }
+
+    fn read(&mut self) -> Option<&AudioFrame> {
+        match self {
+            Self::Decoder(it) => it.read(),
+            Self::Raw(it) => it.read(),
+        }
+    }
+}
+
+// Plays silence while the sender has stopped sending the audio during silence,
+// see `DtxOptions`, the frames of silence follow the last decoded frame at its
+// length and sample rate.
+struct SilenceFiller {
+    samples: Vec<i16>,
+    sample_rate: u32,
+    timestamp: u64,
+    duration: Duration,
+    // The time that the next frame of silence is due at.
+    due: Instant,
+}
+
+impl SilenceFiller {
+    // Returns `None` if the length of the frame is not known.
+    fn new(last: &AudioFrame) -> Option<Self> {
+        if last.frames == 0 || last.sample_rate == 0 {
+            return None;
+        }
+
+        let duration =
+            Duration::from_micros(last.frames as u64 * 1_000_000 / last.sample_rate as u64);
+
+        Some(Self {
+            samples: vec![0; last.frames as usize],
+            sample_rate: last.sample_rate,
+            timestamp: if last.timestamp > 0 {
+                last.timestamp + duration.as_micros() as u64
+            } else {
+                0
+            },
+            due: Instant::now() + duration,
+            duration,
+        })
+    }
+
+    fn timeout(&self) -> Duration {
+        self.due.saturating_duration_since(Instant::now())
+    }
+
+    fn next(&mut self) -> AudioFrame {
+        let frame = AudioFrame {
+            sample_rate: self.sample_rate,
+            frames: self.samples.len() as u32,
+            data: self.samples.as_ptr(),
+            timestamp: self.timestamp,
+        };
+
+        if self.timestamp > 0 {
+            self.timestamp += self.duration.as_micros() as u64;
+        }
+
+        self.due += self.duration;
+        frame
+    }
+}
+
+// Create the video decoder, if the hardware decoder is not available, fall back
+// to the software decoder, there is no software decoder for HEVC. Returns the
+// decoder that is actually used.
+fn create_video_codec<T: AVFrameStream + 'static>(
     sink: &Arc<T>,
     settings: VideoDecoderSettings,
+    raw: bool,
+) -> Result<(VideoCodec, VideoDecoderType), HylaranaReceiverError> {
+    if raw {
+        return Ok((VideoCodec::Raw(Default::default()), settings.codec));
+    }
+
+    let kind = CodecType::from(settings.codec);
+    match VideoDecoder::new(settings.clone()) {
+        Err(e) if kind.is_hardware() && !kind.is_10bit() => {
+            tracing::warn!(
+                codec = ?settings.codec,
+                error = ?e,
+                "failed to create video decoder, fall back to software"
+            );
+
+            let codec = VideoDecoder::new(VideoDecoderSettings {
+                codec: VideoDecoderType::H264,
+                ..settings.clone()
+            })?;
+
+            sink.event(StreamEvent::DecoderFallback {
+                from: settings.codec,
+                to: VideoDecoderType::H264,
+            });
+
+            Ok((VideoCodec::Decoder(codec), VideoDecoderType::H264))
+        }
+        it => Ok((VideoCodec::Decoder(it?), settings.codec)),
+    }
+}
+
+// The decoder of the same kind for another codec, the sender switches from HEVC
+// to H264 when a receiver cannot decode HEVC.
+fn decoder_for_codec(codec: VideoDecoderType, name: &str) -> Option<VideoDecoderType> {
+    Some(match (codec, name) {
+        (VideoDecoderType::HevcD3D11, "h264") => VideoDecoderType::D3D11,
+        (VideoDecoderType::HevcQsv, "h264") => VideoDecoderType::Qsv,
+        (VideoDecoderType::D3D11, "hevc") => VideoDecoderType::HevcD3D11,
+        (VideoDecoderType::Qsv, "hevc") => VideoDecoderType::HevcQsv,
+        (codec, name) if video_codec_name(codec) == name => codec,
+        _ => return None,
+    })
+}
+
+// The codec of a configuration of the encoder, by the type of its first NAL
+// unit, which is the VPS for HEVC and the SPS for H264.
+pub(crate) fn config_codec(packet: &[u8]) -> Option<&'static str> {
+    let offset = packet.windows(3).position(|it| it == [0, 0, 1])? + 3;
+    let header = *packet.get(offset)?;
+
+    if (header >> 1) & 0x3F == 32 {
+        Some("hevc")
+    } else if header & 0x1F == 7 {
+        Some("h264")
+    } else {
+        None
+    }
+}
+
+// A receiver with an HEVC decoder can also switch to the H264 decoder of the
+// same kind.
+fn receiver_capabilities(codec: VideoDecoderType) -> StreamCapabilities {
+    let mut codecs = vec!["h264".to_string()];
+    if CodecType::from(codec).is_10bit() {
+        codecs.push("hevc".to_string());
+    }
+
+    codecs.push("opus".to_string());
+
+    StreamCapabilities {
+        features: StreamFeature::ALL.to_vec(),
+        codecs,
+    }
+}
+
+// Shared by the video decoder thread and the watchdog of the receiver.
+#[derive(Default)]
+struct DecoderHealth {
+    // The last time the decoder output a frame.
+    heartbeat: Heartbeat,
+    // The number of video packets taken by the decoder thread.
+    packets: AtomicU64,
+    // The decoder has stalled, the thread creates it again.
+    reset: AtomicBool,
+}
+
+fn create_video_decoder<T: AVFrameStream + 'static>(
+    transport: &Arc<TransportReceiver<StreamMultiReceiverAdapter>>,
+    status: Arc<StreamStatus>,
+    connected: Arc<AtomicBool>,
+    sink: &Arc<T>,
+    probe: Arc<Mutex<StatsProbe>>,
+    metadata: Arc<Mutex<Option<StreamMetadata>>>,
+    health: Arc<DecoderHealth>,
+    options: &HylaranaReceiverCodecOptions,
+    tracks: StreamTracks,
+    raw: bool,
+    tap: PacketTap,
 ) -> Result<(), HylaranaReceiverError> {
     let sink_ = Arc::downgrade(sink);
+    let transport_ = Arc::downgrade(transport);
     let adapter = transport.get_adapter();
-    let mut codec = VideoDecoder::new(settings)?;
+    #[cfg(target_os = "windows")]
+    let graphics = options.graphics.clone();
+    #[allow(unused_mut)]
+    let mut settings = VideoDecoderSettings {
+        codec: options.video,
+        #[cfg(target_os = "windows")]
+        direct3d: Some(graphics.direct3d()),
+    };
+    // The receivers of the thumbnails or of the audio only do not create a
+    // decoder, the raw decoder takes its place but is never used, the control
+    // messages are still taken from the video track.
+    let thumbnail_only = options.thumbnail_only || tracks == StreamTracks::Audio;
+    let passthrough = options.passthrough && !raw;
+    let (mut codec, mut current) =
+        create_video_codec(sink, settings.clone(), raw || thumbnail_only || passthrough)?;
+
+    let queue = Arc::new(FrameQueueInner::new(RENDER_QUEUE_SIZE));
+    let renderer = create_video_renderer(status.clone(), sink, probe.clone(), queue.clone())?;
+
+    runtime::spawn(ThreadKind::Decode, "VideoDecoder", move || {
+        #[cfg(target_os = "windows")]
+        let thread_class_guard = MediaThreadClass::Playback.join().ok();
+
+        // The last configuration of the encoder, a recreated decoder starts with it.
+        let mut config: Option<Bytes> = None;
+        let mut waiting_key_frame = false;
+
+        let mut reason = DisconnectReason::Closed;
+        while let Some(sink) = sink_.upgrade() {
+            // The render thread closes the queue when the sink returns false.
+            if queue.is_closed() {
+                reason = DisconnectReason::SinkClosed;
+                break;
+            }
+
+            if let Some((packet, flags, timestamp)) = adapter.next(StreamKind::Video) {
+                METRICS
+                    .video_receive_queue
+                    .update(adapter.pending(StreamKind::Video) as u64);
+
+                if !connected.update(true) {
+                    sink.event(StreamEvent::Connected);
+                }
 
-    thread::Builder::new()
-        .name("VideoDecoderThread".to_string())
-        .spawn(move || {
-            #[cfg(target_os = "windows")]
-            let thread_class_guard = MediaThreadClass::Playback.join().ok();
+                // The control messages of the stream are carried in the video stream.
+                if flags & BufferFlag::CONTROL != 0 {
+                    match StreamControl::from_payload(&packet) {
+                        Some(StreamControl::Pause) => sink.pause(),
+                        Some(StreamControl::Resume) => sink.resume(),
+                        Some(StreamControl::Clock { media, system }) => {
+                            let bandwidth =
+                                transport_.upgrade().and_then(|it| it.estimated_bandwidth());
+                            let buffers = sink.buffer_stats();
 
-            'a: while let Some(sink) = sink_.upgrade() {
-                if let Some((packet, _, timestamp)) = adapter.next(StreamKind::Video) {
-                    if let Err(e) = codec.decode(&packet, timestamp) {
-                        log::error!("video decode error={:?}", e);
+                            let stats = {
+                                let mut probe = probe.lock();
+                                probe.clock = Some((media, system));
+                                probe.bandwidth = bandwidth;
+                                probe.stats(buffers)
+                            };
 
+                            sink.stats(&stats);
+                        }
+                        Some(StreamControl::Message(message)) => sink.message(&message),
+                        Some(StreamControl::Thumbnail(image)) => sink.thumbnail(&image),
+                        // The transport ends the stream after the goodbye, or reattaches
+                        // to a new sender, see `TransportOptions::resume`.
+                        Some(StreamControl::Goodbye) => {
+                            tracing::info!("sender closed the stream");
+                        }
+                        Some(StreamControl::Kick) => {
+                            tracing::info!("sender disconnected the receiver");
+                        }
+                        // The keepalives are answered by the transport.
+                        Some(StreamControl::Ping { .. }) => (),
+                        // The metadata is repeated in front of the key frames, only the
+                        // changes are passed to the sink.
+                        Some(StreamControl::Metadata(bytes)) => {
+                            match serde_json::from_slice::<StreamMetadata>(&bytes) {
+                                Ok(value) => {
+                                    let mut metadata = metadata.lock();
+                                    if metadata.as_ref() != Some(&value) {
+                                        tracing::info!(metadata = ?value, "receiver got stream metadata");
+
+                                        tap.set_size(value.width, value.height);
+
+                                        sink.metadata(&value);
+                                        metadata.replace(value);
+                                    }
+                                }
+                                Err(e) => tracing::warn!(error = ?e, "invalid stream metadata"),
+                            }
+                        }
+                        None => tracing::warn!("unknown stream control message"),
+                    }
+
+                    continue;
+                }
+
+                // The frames of the shared memory transport are not encoded.
+                if !raw {
+                    tap.video(&packet, flags, timestamp);
+                }
+
+                if thumbnail_only {
+                    health.heartbeat.beat();
+                    continue;
+                }
+
+                if passthrough {
+                    health.heartbeat.beat();
+
+                    if !sink.video_packet(&packet, flags, timestamp) {
+                        tracing::warn!("video packet sink return false!");
+
+                        reason = DisconnectReason::SinkClosed;
                         break;
-                    } else {
-                        while let Some(frame) = codec.read() {
-                            if !sink.video(frame) {
-                                log::warn!("video sink return false!");
+                    }
+
+                    continue;
+                }
+
+                let _span =
+                    tracing::trace_span!("video_packet", size = packet.len(), flags, timestamp)
+                        .entered();
+
+                health.packets.fetch_add(1, Ordering::Relaxed);
 
-                                break 'a;
+                // The watchdog found the decoder stalled, such as a hardware decoder that
+                // lost its device. The new decoder has no reference frames, it waits for
+                // the next key frame.
+                if health.reset.update(false) {
+                    tracing::warn!(codec = ?current, "recreate the stalled video decoder");
+
+                    let recreated = create_video_codec(
+                        &sink,
+                        VideoDecoderSettings {
+                            codec: current,
+                            ..settings.clone()
+                        },
+                        raw,
+                    )
+                    .map_err(|e| e.to_string())
+                    .and_then(|(mut it, _)| {
+                        if let Some(config) = config.as_ref() {
+                            it.decode(config, timestamp).map_err(|e| e.to_string())?;
+                        }
+
+                        Ok(it)
+                    });
+
+                    match recreated {
+                        Ok(it) => {
+                            codec = it;
+                            waiting_key_frame = true;
+                            adapter.lose();
+                        }
+                        Err(e) => {
+                            tracing::error!(error = %e, "failed to recreate video decoder");
+
+                            reason = DisconnectReason::Error(StreamErrorKind::Decode);
+                            break;
+                        }
+                    }
+                }
+
+                if waiting_key_frame {
+                    if flags & BufferFlag::MASK == BufferFlag::KeyFrame as i32 {
+                        waiting_key_frame = false;
+                    } else if flags & BufferFlag::MASK != BufferFlag::Config as i32 {
+                        health.heartbeat.beat();
+                        continue;
+                    }
+                }
+
+                // The sender has switched the codec, the new configuration is followed by a
+                // key frame of the new codec.
+                if flags & BufferFlag::MASK == BufferFlag::Config as i32 {
+                    config.replace(packet.clone());
+
+                    if let Some(to) = config_codec(&packet)
+                        .filter(|it| *it != video_codec_name(current))
+                        .and_then(|it| decoder_for_codec(current, it))
+                    {
+                        match create_video_codec(
+                            &sink,
+                            VideoDecoderSettings {
+                                codec: to,
+                                ..settings.clone()
+                            },
+                            raw,
+                        ) {
+                            Ok((it, to)) => {
+                                tracing::info!(from = ?current, to = ?to, "switch video decoder");
+
+                                sink.event(StreamEvent::DecoderSwitched { from: current, to });
+
+                                codec = it;
+                                current = to;
+                            }
+                            Err(e) => {
+                                tracing::error!(error = ?e, "failed to switch video decoder");
+
+                                reason = DisconnectReason::Error(StreamErrorKind::Decode);
+                                break;
                             }
                         }
                     }
-                } else {
-                    log::warn!("video adapter next is none!");
+                }
+
+                let (rotation, mirror) = BufferFlag::get_orientation(flags);
+                codec.set_orientation(rotation, mirror);
+
+                let started = Instant::now();
+                let decoded =
+                    tracing::trace_span!("decode").in_scope(|| codec.decode(&packet, timestamp));
 
+                probe.lock().record_decode(started.elapsed());
+
+                if let Err(e) = decoded {
+                    tracing::error!(error = ?e, "video decode error");
+                    Metrics::increment(&METRICS.decode_errors);
+
+                    // The decoder is created again on a new device with the next packet,
+                    // like a stalled decoder.
+                    #[cfg(target_os = "windows")]
+                    if settings
+                        .direct3d
+                        .as_ref()
+                        .map(|it| it.is_removed())
+                        .unwrap_or(false)
+                    {
+                        tracing::warn!("d3d device of the video decoder has been removed");
+
+                        settings.direct3d = Some(graphics.direct3d());
+                        health.reset.update(true);
+                        continue;
+                    }
+
+                    reason = DisconnectReason::Error(StreamErrorKind::Decode);
                     break;
+                } else {
+                    while let Some(frame) = codec.take() {
+                        Metrics::increment(&METRICS.video_frames_decoded);
+                        health.heartbeat.beat();
+
+                        // The frame is still decoded, the following frames refer to it.
+                        if adapter.pending(StreamKind::Video) > LATE_FRAME_BACKLOG {
+                            probe.lock().dropped.late += 1;
+                            continue;
+                        }
+
+                        // The render thread is behind, the oldest waiting frame is dropped.
+                        if !queue.push(frame) {
+                            probe.lock().dropped.late += 1;
+                        }
+                    }
                 }
+            } else {
+                tracing::warn!("video adapter next is none!");
+
+                reason = closed_reason(&adapter);
+                break;
             }
+        }
+
+        tracing::warn!("video decoder thread is closed!");
+
+        // The sink is closed after the render thread has exited, so that no frame is
+        // passed to it afterwards.
+        queue.close();
+        let _ = renderer.join();
+
+        if let Some(sink) = sink_.upgrade() {
+            close_receiver(&status, &probe, sink.as_ref(), reason);
+        }
+
+        #[cfg(target_os = "windows")]
+        if let Some(guard) = thread_class_guard {
+            drop(guard)
+        }
+    })?;
+
+    Ok(())
+}
 
-            log::warn!("video decoder thread is closed!");
-            if let Some(sink) = sink_.upgrade() {
-                if !status.get() {
-                    status.update(true);
-                    sink.close();
+// The decoded frames are passed to the sink on their own thread, so that a slow
+// sink does not hold up the decoder, and the decoder does not hold up the
+// receiving of the packets.
+fn create_video_renderer<T: AVFrameStream + 'static>(
+    status: Arc<StreamStatus>,
+    sink: &Arc<T>,
+    probe: Arc<Mutex<StatsProbe>>,
+    queue: Arc<FrameQueueInner<RenderFrame>>,
+) -> Result<JoinHandle<()>, HylaranaReceiverError> {
+    let sink_ = Arc::downgrade(sink);
+
+    let renderer = runtime::spawn(ThreadKind::Render, "VideoRender", move || {
+        #[cfg(target_os = "windows")]
+        let thread_class_guard = MediaThreadClass::Playback.join().ok();
+
+        loop {
+            let Some(frame) = queue.pop(RENDER_POLL_INTERVAL) else {
+                if queue.is_closed() {
+                    break;
                 }
-            }
 
-            #[cfg(target_os = "windows")]
-            if let Some(guard) = thread_class_guard {
-                drop(guard)
+                continue;
+            };
+
+            let Some(sink) = sink_.upgrade() else {
+                break;
+            };
+
+            let _span = tracing::trace_span!("render").entered();
+
+            if !sink.video(&frame) {
+                tracing::warn!("video sink return false!");
+
+                // The decoder thread exits when it finds the queue closed.
+                close_receiver(&status, &probe, sink.as_ref(), DisconnectReason::SinkClosed);
+                queue.close();
+                break;
             }
-        })?;
 
-    Ok(())
+            // The frame has been rendered by the sink.
+            Metrics::increment(&METRICS.video_frames_rendered);
+            probe.lock().record(frame.timestamp);
+        }
+
+        tracing::warn!("video render thread is closed!");
+
+        #[cfg(target_os = "windows")]
+        if let Some(guard) = thread_class_guard {
+            drop(guard)
+        }
+    })?;
+
+    Ok(renderer)
 }
 
 fn create_audio_decoder<T: AVFrameStream + 'static>(
     transport: &TransportReceiver<StreamMultiReceiverAdapter>,
-    status: Arc<AtomicBool>,
+    status: Arc<StreamStatus>,
+    connected: Arc<AtomicBool>,
     sink: &Arc<T>,
+    probe: Arc<Mutex<StatsProbe>>,
+    raw: bool,
+    thumbnail_only: bool,
+    passthrough: bool,
+    tap: PacketTap,
 ) -> Result<(), HylaranaReceiverError> {
     let sink_ = Arc::downgrade(sink);
     let adapter = transport.get_adapter();
-    let mut codec = AudioDecoder::new()?;
+    let passthrough = passthrough && !raw;
+    let mut codec = if raw || thumbnail_only || passthrough {
+        AudioCodec::Raw(Default::default())
+    } else {
+        AudioCodec::Decoder(AudioDecoder::new()?)
+    };
 
-    thread::Builder::new()
-        .name("AudioDecoderThread".to_string())
-        .spawn(move || {
-            #[cfg(target_os = "windows")]
-            let thread_class_guard = MediaThreadClass::ProAudio.join().ok();
+    runtime::spawn(ThreadKind::Decode, "AudioDecoder", move || {
+        #[cfg(target_os = "windows")]
+        let thread_class_guard = MediaThreadClass::ProAudio.join().ok();
 
-            'a: while let Some(sink) = sink_.upgrade() {
-                if let Some((packet, _, timestamp)) = adapter.next(StreamKind::Audio) {
-                    if let Err(e) = codec.decode(&packet, timestamp) {
-                        log::error!("audio decode error={:?}", e);
+        let mut reason = DisconnectReason::Closed;
+        let mut filler: Option<SilenceFiller> = None;
+        let mut meter = AudioMeter::new();
+        'a: while let Some(sink) = sink_.upgrade() {
+            let item = if let Some(it) = filler.as_mut() {
+                match adapter.next_timeout(StreamKind::Audio, it.timeout()) {
+                    Some(item) => item,
+                    None => {
+                        let frame = it.next();
+                        if let Some(levels) = meter.push(&frame) {
+                            sink.audio_levels(levels);
+                        }
 
-                        break;
-                    } else {
-                        while let Some(frame) = codec.read() {
-                            if !sink.audio(frame) {
-                                log::warn!("audio sink return false!");
+                        if !sink.audio(&frame) {
+                            tracing::warn!("audio sink return false!");
 
-                                break 'a;
-                            }
+                            reason = DisconnectReason::SinkClosed;
+                            break;
                         }
+
+                        continue;
                     }
-                } else {
-                    log::warn!("audio adapter next is none!");
+                }
+            } else {
+                adapter.next(StreamKind::Audio)
+            };
 
-                    break;
+            if let Some((packet, flags, timestamp)) = item {
+                METRICS
+                    .audio_receive_queue
+                    .update(adapter.pending(StreamKind::Audio) as u64);
+
+                if !connected.update(true) {
+                    sink.event(StreamEvent::Connected);
                 }
-            }
 
-            log::warn!("audio decoder thread is closed!");
-            if let Some(sink) = sink_.upgrade() {
-                if !status.get() {
-                    status.update(true);
-                    sink.close();
+                if !raw {
+                    tap.audio(&packet, flags, timestamp);
                 }
-            }
 
-            #[cfg(target_os = "windows")]
-            if let Some(guard) = thread_class_guard {
-                drop(guard)
+                // The packets are only taken out of the queue.
+                if thumbnail_only {
+                    continue;
+                }
+
+                if passthrough {
+                    if !sink.audio_packet(&packet, flags, timestamp) {
+                        tracing::warn!("audio packet sink return false!");
+
+                        reason = DisconnectReason::SinkClosed;
+                        break;
+                    }
+
+                    continue;
+                }
+
+                let _span =
+                    tracing::trace_span!("audio_packet", size = packet.len(), timestamp).entered();
+
+                if let Err(e) = codec.decode(&packet, timestamp) {
+                    tracing::error!(error = ?e, "audio decode error");
+                    Metrics::increment(&METRICS.decode_errors);
+
+                    reason = DisconnectReason::Error(StreamErrorKind::Decode);
+                    break;
+                } else {
+                    filler = None;
+
+                    while let Some(frame) = codec.read() {
+                        Metrics::increment(&METRICS.audio_frames_decoded);
+
+                        if let Some(levels) = meter.push(frame) {
+                            sink.audio_levels(levels);
+                        }
+
+                        if !sink.audio(frame) {
+                            tracing::warn!("audio sink return false!");
+
+                            reason = DisconnectReason::SinkClosed;
+                            break 'a;
+                        }
+
+                        // The sender stops after this packet, the gap is filled
+                        // until the next packet arrives.
+                        if flags & BufferFlag::SILENCE != 0 {
+                            filler = SilenceFiller::new(frame);
+                        }
+                    }
+                }
+            } else {
+                tracing::warn!("audio adapter next is none!");
+
+                reason = closed_reason(&adapter);
+                break;
             }
-        })?;
+        }
+
+        tracing::warn!("audio decoder thread is closed!");
+        if let Some(sink) = sink_.upgrade() {
+            close_receiver(&status, &probe, sink.as_ref(), reason);
+        }
+
+        #[cfg(target_os = "windows")]
+        if let Some(guard) = thread_class_guard {
+            drop(guard)
+        }
+    })?;
 
     Ok(())
 }
 
+// The sender says goodbye before it closes the stream, the stream is cut off
+// otherwise.
+fn closed_reason(adapter: &StreamMultiReceiverAdapter) -> DisconnectReason {
+    if adapter.is_kicked() {
+        DisconnectReason::Kicked
+    } else if adapter.is_remote_closed() {
+        DisconnectReason::RemoteClosed
+    } else {
+        DisconnectReason::TransportClosed
+    }
+}
+
+// Log the statistics when the stream is closed, so that the reports of choppy
+// playback can be triaged from the logs.
+fn close_receiver<T: AVFrameStream>(
+    status: &StreamStatus,
+    probe: &Mutex<StatsProbe>,
+    sink: &T,
+    reason: DisconnectReason,
+) {
+    if !status.is_closed() {
+        let buffers = sink.buffer_stats();
+
+        tracing::info!(reason = ?reason, stats = ?probe.lock().stats(buffers), "receiver stats");
+    }
+
+    close_stream(status, sink, reason);
+}
+
 /// Screen casting receiver.
 pub struct HylaranaReceiver<T: AVFrameStream + 'static> {
-    #[allow(unused)]
-    transport: TransportReceiver<StreamMultiReceiverAdapter>,
-    status: Arc<AtomicBool>,
+    transport: Arc<TransportReceiver<StreamMultiReceiverAdapter>>,
+    status: Arc<StreamStatus>,
+    probe: Arc<Mutex<StatsProbe>>,
+    metadata: Arc<Mutex<Option<StreamMetadata>>>,
+    capabilities: StreamCapabilities,
+    packets: PacketTap,
     sink: Arc<T>,
+    #[allow(dead_code)]
+    watchdog: Watchdog,
 }
 
 impl<T: AVFrameStream + 'static> HylaranaReceiver<T> {
@@ -165,39 +1077,366 @@ impl<T: AVFrameStream + 'static> HylaranaReceiver<T> {
         options: HylaranaReceiverOptions,
         sink: T,
     ) -> Result<Self, HylaranaReceiverError> {
-        log::info!("create receiver");
+        tracing::info!("create receiver");
+
+        let raw = matches!(
+            options.transport.strategy,
+            TransportStrategy::SharedMemory(_)
+        );
+        let tracks = options.tracks;
+
+        // The sender rejects the receivers without the right code in the handshake,
+        // the code is asked for until the sender takes it or the user gives up.
+        let mut code = options.pairing_code;
+        let mut attempt = 0;
+        let transport = Arc::new(loop {
+            let submitted = code.is_some();
+            match hylarana_transport::create_split_receiver(
+                id.clone(),
+                options.transport,
+                tracks,
+                code.take(),
+            ) {
+                Ok(it) => break it,
+                Err(e) if e.kind() == ErrorKind::PermissionDenied => {
+                    if submitted {
+                        attempt += 1;
+                    }
+
+                    tracing::info!(attempt, "sender requires a pairing code");
 
-        let transport = hylarana_transport::create_split_receiver(id, options.transport)?;
-        let status = Arc::new(AtomicBool::new(false));
+                    code = sink.pairing_code_required(attempt);
+                    if code.is_none() {
+                        return Err(HylaranaReceiverError::PairingFailed);
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        });
+
+        let probe: Arc<Mutex<StatsProbe>> = Default::default();
+
+        // The packets are dropped while the video is waiting for a key frame, such as
+        // when joining in the middle of the stream, ask the sender for one instead of
+        // waiting for the next key frame of the interval. A receiver of the thumbnails
+        // or of the audio only does not need the key frames.
+        if !options.codec.thumbnail_only && tracks != StreamTracks::Audio {
+            let probe = probe.clone();
+            let transport_ = Arc::downgrade(&transport);
+            let requested: Mutex<Option<Instant>> = Mutex::new(None);
+            transport.get_adapter().on_key_frame_required(move || {
+                probe.lock().dropped.corrupt += 1;
+
+                let mut requested = requested.lock();
+                if requested
+                    .map(|it| it.elapsed() < KEY_FRAME_REQUEST_INTERVAL)
+                    .unwrap_or(false)
+                {
+                    return;
+                }
+
+                requested.replace(Instant::now());
+                if let Some(transport) = transport_.upgrade() {
+                    tracing::info!("receiver is waiting for a key frame, request it from sender");
+
+                    if let Err(e) = send_back(&transport, MessageKind::KeyFrame, &[]) {
+                        tracing::warn!(error = ?e, "failed to request key frame");
+                    }
+                }
+            });
+        }
+
+        // The capabilities are sent once, the senders of older versions ignore them.
+        let capabilities = receiver_capabilities(options.codec.video);
+        advertise_capabilities(&transport, &capabilities);
+
+        let status = StreamStatus::new();
+        let connected = Arc::new(AtomicBool::new(false));
+        let metadata: Arc<Mutex<Option<StreamMetadata>>> = Default::default();
+        let packets = PacketTap::default();
         let sink = Arc::new(sink);
 
-        create_audio_decoder(&transport, status.clone(), &sink)?;
+        // A restarted sender knows nothing about the receiver, the capabilities are
+        // sent to it again.
+        {
+            let capabilities = capabilities.clone();
+            let transport_ = Arc::downgrade(&transport);
+            let sink_ = Arc::downgrade(&sink);
+            transport.on_reattached(move || {
+                tracing::info!("receiver reattached to restarted sender");
+
+                if let Some(transport) = transport_.upgrade() {
+                    advertise_capabilities(&transport, &capabilities);
+                }
+
+                if let Some(sink) = sink_.upgrade() {
+                    sink.event(StreamEvent::Reattached);
+                }
+            });
+        }
+        let health: Arc<DecoderHealth> = Default::default();
+
+        if tracks != StreamTracks::Video {
+            create_audio_decoder(
+                &transport,
+                status.clone(),
+                connected.clone(),
+                &sink,
+                probe.clone(),
+                raw,
+                options.codec.thumbnail_only,
+                options.codec.passthrough,
+                packets.clone(),
+            )?;
+        }
+
         create_video_decoder(
             &transport,
             status.clone(),
+            connected,
             &sink,
-            VideoDecoderSettings {
-                codec: options.codec.video,
-                #[cfg(target_os = "windows")]
-                direct3d: Some(crate::get_direct3d()),
-            },
+            probe.clone(),
+            metadata.clone(),
+            health.clone(),
+            &options.codec,
+            tracks,
+            raw,
+            packets.clone(),
         )?;
 
+        let watchdog = {
+            let adapter = transport.get_adapter();
+            let status = status.clone();
+            let probe = probe.clone();
+            let sink_ = Arc::downgrade(&sink);
+
+            let mut state = StallState::default();
+            let mut packets = 0;
+            Watchdog::new("HylaranaReceiverWatchdogThread", move || {
+                let Some(sink) = sink_.upgrade() else {
+                    return false;
+                };
+
+                if status.is_closed() {
+                    return false;
+                }
+
+                // A decoder that has nothing to decode is not stalled, such as while the
+                // sender is paused.
+                let taken = health.packets.load(Ordering::Relaxed);
+                if taken == packets && adapter.pending(StreamKind::Video) == 0 {
+                    health.heartbeat.beat();
+                }
+
+                packets = taken;
+
+                match state.check(&health.heartbeat) {
+                    Verdict::Healthy => true,
+                    Verdict::Recover => {
+                        tracing::warn!("video decoder is stalled, recreate it");
+
+                        health.reset.update(true);
+                        state.recovered(&health.heartbeat);
+                        true
+                    }
+                    Verdict::Failed => {
+                        tracing::error!("video decoder cannot be recovered");
+
+                        sink.event(StreamEvent::Stalled {
+                            stage: PipelineStage::Decode,
+                        });
+
+                        close_receiver(
+                            &status,
+                            &probe,
+                            sink.as_ref(),
+                            DisconnectReason::Error(StreamErrorKind::Decode),
+                        );
+
+                        false
+                    }
+                }
+            })?
+        };
+
         Ok(Self {
             transport,
             status,
+            probe,
+            metadata,
+            capabilities,
+            packets,
             sink,
+            watchdog,
         })
     }
+
+    /// Get the statistics of the receiver, the observer of the sink also
+    /// receives them periodically.
+    pub fn stats(&self) -> HylaranaReceiverStats {
+        let bandwidth = self.transport.estimated_bandwidth();
+        let buffers = self.sink.buffer_stats();
+
+        let mut probe = self.probe.lock();
+        probe.bandwidth = bandwidth;
+        probe.stats(buffers)
+    }
+
+    /// Get the metadata of the stream, this is `None` until the metadata has
+    /// arrived, which happens before the first frame if the sender has set it.
+    pub fn metadata(&self) -> Option<StreamMetadata> {
+        self.metadata.lock().clone()
+    }
+
+    /// The capabilities that the receiver and the sender support, this is
+    /// `None` until the metadata has arrived. The senders of older versions do
+    /// not advertise their capabilities, in which case nothing is supported.
+    pub fn capabilities(&self) -> Option<StreamCapabilities> {
+        self.metadata
+            .lock()
+            .as_ref()
+            .map(|it| self.capabilities.intersect(&it.capabilities))
+    }
+
+    /// Send a mouse or keyboard event to the sender, the event is only
+    /// injected if the sender has enabled the input, otherwise it is ignored.
+    /// A multicast sender ignores all the events.
+    pub fn send_input(&self, event: &InputEvent) -> Result<(), HylaranaReceiverError> {
+        send_back(&self.transport, MessageKind::Input, &event.encode())
+    }
+
+    /// Send a message to the sender through the data channel, the message is
+    /// sent as a single packet, so it must fit in the mtu of the transport.
+    /// A multicast sender ignores the messages, its back channel only serves
+    /// the key frame requests.
+    pub fn send_message(&self, message: &[u8]) -> Result<(), HylaranaReceiverError> {
+        send_back(&self.transport, MessageKind::Data, message)
+    }
+
+    /// Ask the sender to encode the next frame as a key frame. This is done
+    /// automatically when the video is waiting for a key frame, the sender
+    /// limits how often the requests are served.
+    pub fn request_key_frame(&self) -> Result<(), HylaranaReceiverError> {
+        send_back(&self.transport, MessageKind::KeyFrame, &[])
+    }
+
+    /// Pass the encoded packets of the stream to a sink as they arrive, such
+    /// as to record the stream without encoding it again, see
+    /// `EncodedPacketSink`. Pass `None` to remove the sink.
+    ///
+    /// The packets are passed whether or not they are decoded, the video of
+    /// the sink starts with the next key frame, which is requested from the
+    /// sender. The receivers of the shared memory transport have no packets.
+    pub fn set_packet_sink<S: EncodedPacketSink + 'static>(&self, sink: Option<S>) {
+        tracing::info!(enabled = sink.is_some(), "receiver set packet sink");
+
+        let enabled = sink.is_some();
+        self.packets
+            .set_sink(sink.map(|it| Arc::new(it) as Arc<dyn EncodedPacketSink>));
+
+        if enabled {
+            if let Err(e) = self.request_key_frame() {
+                tracing::warn!(error = ?e, "failed to request key frame");
+            }
+        }
+    }
+
+    /// Whether the stream has been closed, the sender or receiver can be
+    /// dropped after this.
+    pub fn is_closed(&self) -> bool {
+        self.status.is_closed()
+    }
+
+    /// Wait until the stream is closed, either by the other side, by an
+    /// error, or because the sink returned false.
+    pub async fn closed(&self) {
+        self.status.closed().await
+    }
+
+    /// Get the sink of the receiver, such as the player passed in when
+    /// creating the receiver.
+    pub fn get_sink(&self) -> &T {
+        &self.sink
+    }
+}
+
+// The pull mode, the frames are taken from the queues of the sink on the thread
+// of the application.
+impl HylaranaReceiver<FrameQueue> {
+    /// See `FrameQueue::try_next_video_frame`.
+    pub fn try_next_video_frame(&self) -> Option<OwnedVideoFrame> {
+        self.sink.try_next_video_frame()
+    }
+
+    /// See `FrameQueue::next_video_frame`.
+    pub fn next_video_frame(&self, timeout: Duration) -> Option<OwnedVideoFrame> {
+        self.sink.next_video_frame(timeout)
+    }
+
+    /// See `FrameQueue::try_next_audio_frame`.
+    pub fn try_next_audio_frame(&self) -> Option<OwnedAudioFrame> {
+        self.sink.try_next_audio_frame()
+    }
+
+    /// See `FrameQueue::next_audio_frame`.
+    pub fn next_audio_frame(&self, timeout: Duration) -> Option<OwnedAudioFrame> {
+        self.sink.next_audio_frame(timeout)
+    }
+}
+
+fn advertise_capabilities(
+    transport: &TransportReceiver<StreamMultiReceiverAdapter>,
+    capabilities: &StreamCapabilities,
+) {
+    match serde_json::to_vec(capabilities) {
+        Ok(payload) => {
+            if let Err(e) = send_back(transport, MessageKind::Capabilities, &payload) {
+                tracing::info!(error = ?e, "failed to advertise receiver capabilities");
+            }
+        }
+        Err(e) => tracing::warn!(error = ?e, "failed to serialize receiver capabilities"),
+    }
+}
+
+fn send_back(
+    transport: &TransportReceiver<StreamMultiReceiverAdapter>,
+    kind: MessageKind,
+    payload: &[u8],
+) -> Result<(), HylaranaReceiverError> {
+    let mut message = Vec::with_capacity(payload.len() + 1);
+    message.push(kind as u8);
+    message.extend_from_slice(payload);
+
+    transport
+        .send_message(&message)
+        .map_err(HylaranaReceiverError::SendMessageError)
+}
+
+impl<O: AVFrameObserver + 'static> HylaranaReceiver<AVFrameStreamPlayer<'static, O>> {
+    /// Read back the last frame rendered by the player, this returns `None` if
+    /// the player does not play video.
+    pub fn snapshot(&self) -> Result<Option<RgbaImage>, VideoRenderError> {
+        self.sink.snapshot()
+    }
 }
 
 impl<T: AVFrameStream + 'static> Drop for HylaranaReceiver<T> {
     fn drop(&mut self) {
-        log::info!("receiver drop");
+        tracing::info!("receiver drop");
 
-        if !self.status.get() {
-            self.status.update(true);
-            self.sink.close();
+        // The sender is told that the receiver has left on purpose, a multicast
+        // sender ignores the message.
+        if !self.status.is_closed() {
+            if let Err(e) = send_back(&self.transport, MessageKind::Goodbye, &[]) {
+                tracing::info!(error = ?e, "failed to say goodbye to sender");
+            }
         }
+
+        close_receiver(
+            &self.status,
+            &self.probe,
+            self.sink.as_ref(),
+            DisconnectReason::Closed,
+        );
     }
 }
@@ -0,0 +1,102 @@
+use crate::{
+    output::{MuxerOutput, MuxerOutputOptions, StreamOutputEvent},
+    packets::{EncodedCodecParameters, EncodedPacketSink},
+};
+
+use std::time::Duration;
+
+use hylarana_codec::StreamMuxerSettings;
+
+/// Options of an RTMP output.
+#[derive(Debug, Clone)]
+pub struct RtmpOutputOptions {
+    /// The url of the ingest with the stream key, such as
+    /// `rtmp://live.twitch.tv/app/{key}`, `rtmps://` needs an ffmpeg that is
+    /// built with TLS.
+    pub url: String,
+    /// The bit rate of the AAC audio, 128 kbps by default.
+    pub audio_bit_rate: u64,
+    /// How many packets can wait for the server, when the queue is full the
+    /// video is dropped up to the next key frame. 256 by default.
+    pub queue_size: usize,
+    /// How long the connection waits for the server before it is taken as
+    /// lost, 10 seconds by default.
+    pub timeout: Duration,
+    /// The delay before the first reconnect, it is doubled after every failed
+    /// attempt up to `max_reconnect_delay`. 1 second by default.
+    pub reconnect_delay: Duration,
+    /// 30 seconds by default.
+    pub max_reconnect_delay: Duration,
+}
+
+impl Default for RtmpOutputOptions {
+    fn default() -> Self {
+        Self {
+            url: String::new(),
+            audio_bit_rate: 128_000,
+            queue_size: 256,
+            timeout: Duration::from_secs(10),
+            reconnect_delay: Duration::from_secs(1),
+            max_reconnect_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Pushes the stream of a sender to an RTMP server, such as the ingest of a
+/// live streaming platform, next to the normal transport, see
+/// `HylaranaSender::set_packet_sink`.
+///
+/// The video is passed as it is, H264 is taken by all servers, HEVC needs a
+/// server that supports the enhanced RTMP. The audio is transcoded from Opus
+/// to AAC. The output connects again when the connection is lost, and the
+/// connection is written on its own thread, so a slow server does not hold
+/// the sender back, the packets that it cannot keep up with are dropped.
+pub struct RtmpOutput(MuxerOutput);
+
+impl RtmpOutput {
+    pub fn new(options: RtmpOutputOptions) -> std::io::Result<Self> {
+        Ok(Self(MuxerOutput::new(MuxerOutputOptions {
+            name: "rtmp",
+            thread: "HylaranaRtmpOutputThread",
+            settings: StreamMuxerSettings {
+                format: Some("flv".to_string()),
+                options: vec![(
+                    "rw_timeout".to_string(),
+                    options.timeout.as_micros().to_string(),
+                )],
+                url: options.url,
+                interrupt: None,
+            },
+            audio_bit_rate: options.audio_bit_rate,
+            queue_size: options.queue_size,
+            reconnect_delay: options.reconnect_delay,
+            max_reconnect_delay: options.max_reconnect_delay,
+        })?))
+    }
+
+    pub fn on_event<F>(self, handler: F) -> Self
+    where
+        F: Fn(StreamOutputEvent) + Send + Sync + 'static,
+    {
+        self.0.set_event_handler(handler);
+        self
+    }
+}
+
+impl EncodedPacketSink for RtmpOutput {
+    fn parameters(&self, parameters: &EncodedCodecParameters) {
+        self.0.parameters(parameters)
+    }
+
+    fn video(&self, packet: &[u8], key_frame: bool, timestamp: u64) -> bool {
+        self.0.video(packet, key_frame, timestamp)
+    }
+
+    fn audio(&self, packet: &[u8], timestamp: u64) -> bool {
+        self.0.audio(packet, timestamp)
+    }
+
+    fn close(&self) {
+        self.0.close()
+    }
+}
@@ -0,0 +1,690 @@
+use crate::{
+    sender::{start_capture, EncoderControl, PacketOutput, PreviewSink},
+    AVFrameObserver, AVFrameSink, AVFrameStream, AudioOptions, DisconnectReason, GraphicsContext,
+    HylaranaSenderError, HylaranaSenderMediaOptions, HylaranaSenderTrackOptions,
+    PrivacyMaskOptions, RecordingSink, StreamErrorKind, StreamEvent, StreamStatus, VideoOptions,
+};
+
+use std::{
+    fs::{self, OpenOptions},
+    hash::{BuildHasher, RandomState},
+    io::{BufRead, BufReader, Error, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, Command, ExitStatus, Stdio},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use hylarana_capture::AudioMixerInput;
+use hylarana_common::{atomic::EasyAtomic, AdapterPreference};
+use hylarana_transport::{PacketPool, StreamBufferInfo};
+use memmap2::MmapMut;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+// The helper process is the executable started with this variable, which is
+// the path of the shared memory.
+const HELPER_ENV: &str = "HYLARANA_CAPTURE_HELPER";
+
+// The helper writes this line to the stdout once the capture has started, the
+// output before it is not from the helper.
+const HELPER_READY: &str = "HYLARANA_CAPTURE_HELPER_READY";
+
+const EXIT_CLOSED: i32 = 0;
+const EXIT_SOURCE_REMOVED: i32 = 3;
+const EXIT_CAPTURE_ERROR: i32 = 4;
+const EXIT_ENCODE_ERROR: i32 = 5;
+
+// The helper is killed if it has not exited this long after it was asked to.
+const CLOSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+const RING_HEADER: usize = 64;
+const RING_CAPACITY: usize = 8 * 1024 * 1024;
+
+// | size (u32) | kind (u8) | padding | flags (i32) | timestamp (u64) | packet |
+const RECORD_HEADER: usize = 20;
+
+const KIND_VIDEO: u8 = 0;
+const KIND_AUDIO: u8 = 1;
+
+static RING_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Run the capture and the encoders of a sender in a helper process, so that
+/// a crash of the capture or of a driver does not take down the application.
+///
+/// The helper is the executable started again, which has to call
+/// `run_capture_helper` at the very start of `main`, before anything else is
+/// initialized. The encoded packets are passed back in shared memory, and the
+/// stdout of the helper is used by the crate. A helper that crashes is started
+/// again, unless it crashes again before it has encoded anything.
+///
+/// The sink of the sender does not get the captured frames, and the preview
+/// is not available.
+#[derive(Debug, Clone, Default)]
+pub struct SandboxOptions {
+    /// The executable of the helper, the current executable if this is `None`.
+    pub executable: Option<PathBuf>,
+}
+
+/// Run the capture helper if this process has been started as one by a
+/// sender, the process exits when the capture ends. Returns immediately
+/// otherwise.
+pub fn run_capture_helper() {
+    let Ok(path) = std::env::var(HELPER_ENV) else {
+        return;
+    };
+
+    let code = match helper_main(Path::new(&path)) {
+        Ok(code) => code,
+        Err(e) => {
+            log::error!("capture helper error={:?}", e);
+
+            EXIT_CAPTURE_ERROR
+        }
+    };
+
+    log::info!("capture helper exit, code={}", code);
+
+    std::process::exit(code);
+}
+
+#[derive(Serialize, Deserialize)]
+struct HelperRequest {
+    video: Option<HylaranaSenderTrackOptions<VideoOptions>>,
+    audio: Vec<HylaranaSenderTrackOptions<AudioOptions>>,
+    audio_inputs: Vec<AudioMixerInput>,
+    adapter: AdapterPreference,
+    h264_only: bool,
+    // The masks may have changed since the video options were set.
+    privacy_masks: Option<PrivacyMaskOptions>,
+}
+
+// The commands that the sender sends to the helper, one json per line.
+#[derive(Debug, Serialize, Deserialize)]
+enum HelperCommand {
+    KeyFrame,
+    H264Only,
+    AudioInput {
+        index: usize,
+        input: AudioMixerInput,
+    },
+    PrivacyMasks(Option<PrivacyMaskOptions>),
+    Close,
+}
+
+/// How the helper process has ended.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum HelperExit {
+    SourceRemoved,
+    Error(StreamErrorKind),
+    /// The packets cannot be sent anymore.
+    OutputClosed,
+    Crashed,
+}
+
+impl HelperExit {
+    fn from_status(status: ExitStatus) -> Self {
+        match status.code() {
+            Some(EXIT_SOURCE_REMOVED) => Self::SourceRemoved,
+            Some(EXIT_CAPTURE_ERROR) => Self::Error(StreamErrorKind::Capture),
+            Some(EXIT_ENCODE_ERROR) => Self::Error(StreamErrorKind::Encode),
+            // The helper does not end by itself without a reason.
+            _ => Self::Crashed,
+        }
+    }
+}
+
+// A ring buffer of the encoded packets in the shared memory, the helper writes
+// the packets and the sender reads them. The positions in the header count all
+// the bytes that have been written and read.
+struct PacketRing {
+    // The memory is accessed through the pointer, the map only keeps it alive.
+    #[allow(dead_code)]
+    map: MmapMut,
+    ptr: *mut u8,
+}
+
+unsafe impl Send for PacketRing {}
+unsafe impl Sync for PacketRing {}
+
+impl PacketRing {
+    // The ring holds the encoded screen, so only the user can open it, and the
+    // file is created anew, an existing file or a link at the path is an error.
+    fn create(path: &Path) -> Result<Self, Error> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            options.mode(0o600).custom_flags(libc::O_NOFOLLOW);
+        }
+
+        let file = options.open(path)?;
+        file.set_len((RING_HEADER + RING_CAPACITY) as u64)?;
+        Self::map(&file)
+    }
+
+    fn open(path: &Path) -> Result<Self, Error> {
+        Self::map(&OpenOptions::new().read(true).write(true).open(path)?)
+    }
+
+    fn map(file: &fs::File) -> Result<Self, Error> {
+        let mut map = unsafe { MmapMut::map_mut(file)? };
+        if map.len() < RING_HEADER + RING_CAPACITY {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "shared memory is too small",
+            ));
+        }
+
+        Ok(Self {
+            ptr: map.as_mut_ptr(),
+            map,
+        })
+    }
+
+    fn written(&self) -> &AtomicU64 {
+        unsafe { &*(self.ptr as *const AtomicU64) }
+    }
+
+    fn read(&self) -> &AtomicU64 {
+        unsafe { &*(self.ptr.add(8) as *const AtomicU64) }
+    }
+
+    fn copy_in(&self, position: u64, data: &[u8]) {
+        let offset = (position % RING_CAPACITY as u64) as usize;
+        let first = data.len().min(RING_CAPACITY - offset);
+
+        unsafe {
+            let base = self.ptr.add(RING_HEADER);
+            ptr::copy_nonoverlapping(data.as_ptr(), base.add(offset), first);
+            ptr::copy_nonoverlapping(data.as_ptr().add(first), base, data.len() - first);
+        }
+    }
+
+    fn copy_out(&self, position: u64, data: &mut [u8]) {
+        let offset = (position % RING_CAPACITY as u64) as usize;
+        let first = data.len().min(RING_CAPACITY - offset);
+
+        unsafe {
+            let base = self.ptr.add(RING_HEADER);
+            ptr::copy_nonoverlapping(base.add(offset), data.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(base, data.as_mut_ptr().add(first), data.len() - first);
+        }
+    }
+
+    // Only the helper writes, returns false if there is no room for the packet.
+    fn push(&self, kind: u8, flags: i32, timestamp: u64, packet: &[u8]) -> bool {
+        let written = self.written().load(Ordering::Acquire);
+        let read = self.read().load(Ordering::Acquire);
+
+        // The positions are in the shared memory, they are not trusted to be in
+        // order.
+        let size = RECORD_HEADER + packet.len();
+        match written.checked_sub(read) {
+            Some(used) if used as usize + size <= RING_CAPACITY => (),
+            _ => return false,
+        }
+
+        let mut header = [0u8; RECORD_HEADER];
+        header[0..4].copy_from_slice(&(packet.len() as u32).to_le_bytes());
+        header[4] = kind;
+        header[8..12].copy_from_slice(&flags.to_le_bytes());
+        header[12..20].copy_from_slice(&timestamp.to_le_bytes());
+
+        self.copy_in(written, &header);
+        self.copy_in(written + RECORD_HEADER as u64, packet);
+        self.written()
+            .store(written + size as u64, Ordering::Release);
+
+        true
+    }
+
+    // Only the sender reads. The helper may have crashed in the middle of a
+    // write or be corrupted, so the positions and the sizes are checked before
+    // anything is copied, an error means that the helper cannot be trusted
+    // anymore.
+    fn pop(&self, packet: &mut Vec<u8>) -> Result<Option<(u8, i32, u64)>, Error> {
+        let read = self.read().load(Ordering::Acquire);
+        let written = self.written().load(Ordering::Acquire);
+        if read == written {
+            return Ok(None);
+        }
+
+        let available = match written.checked_sub(read) {
+            Some(it) if it as usize >= RECORD_HEADER && it as usize <= RING_CAPACITY => it as usize,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    format!("invalid ring positions, written={written}, read={read}"),
+                ))
+            }
+        };
+
+        let mut header = [0u8; RECORD_HEADER];
+        self.copy_out(read, &mut header);
+
+        let size = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        if size > available - RECORD_HEADER || !matches!(header[4], KIND_VIDEO | KIND_AUDIO) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("invalid ring record, size={size}, kind={}", header[4]),
+            ));
+        }
+
+        packet.resize(size, 0);
+        self.copy_out(read + RECORD_HEADER as u64, packet);
+        self.read()
+            .store(read + (RECORD_HEADER + size) as u64, Ordering::Release);
+
+        Ok(Some((
+            header[4],
+            i32::from_le_bytes(header[8..12].try_into().unwrap()),
+            u64::from_le_bytes(header[12..20].try_into().unwrap()),
+        )))
+    }
+}
+
+/// The capture and the encoders of a sender that run in a helper process.
+pub(crate) struct CaptureProcess {
+    child: Arc<Mutex<Child>>,
+    stdin: Arc<Mutex<ChildStdin>>,
+    exit: Arc<Mutex<Option<HelperExit>>>,
+    closing: Arc<AtomicBool>,
+}
+
+impl CaptureProcess {
+    pub(crate) fn spawn(
+        options: &SandboxOptions,
+        media: &HylaranaSenderMediaOptions,
+        audio_inputs: &[AudioMixerInput],
+        control: &Arc<EncoderControl>,
+        output: &PacketOutput,
+    ) -> Result<Self, HylaranaSenderError> {
+        // The name is not predictable, so that other users cannot take the path
+        // before the sender.
+        let path = std::env::temp_dir().join(format!(
+            "hylarana-capture-{}-{:016x}",
+            std::process::id(),
+            RandomState::new().hash_one(RING_ID.fetch_add(1, Ordering::Relaxed))
+        ));
+
+        let executable = match options.executable.clone() {
+            Some(it) => it,
+            None => std::env::current_exe().map_err(HylaranaSenderError::SandboxError)?,
+        };
+
+        let ring = PacketRing::create(&path).map_err(HylaranaSenderError::SandboxError)?;
+
+        tracing::info!(executable = ?executable, path = ?path, "spawn capture helper");
+
+        let mut child = match Command::new(&executable)
+            .env(HELPER_ENV, &path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+        {
+            Ok(it) => it,
+            Err(e) => {
+                let _ = fs::remove_file(&path);
+
+                return Err(HylaranaSenderError::SandboxError(e));
+            }
+        };
+
+        let mut stdin = child.stdin.take().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+
+        let request = HelperRequest {
+            video: media.video.clone(),
+            audio: media.audio.clone(),
+            audio_inputs: audio_inputs.to_vec(),
+            adapter: media.graphics.adapter(),
+            h264_only: control.h264_only.get(),
+            privacy_masks: control.privacy_masks.lock().clone(),
+        };
+
+        let started = (|| -> Result<(), Error> {
+            writeln!(stdin, "{}", serde_json::to_string(&request)?)?;
+
+            let mut line = String::new();
+            loop {
+                line.clear();
+                if stdout.read_line(&mut line)? == 0 {
+                    return Err(Error::new(
+                        ErrorKind::UnexpectedEof,
+                        "capture helper has exited while starting",
+                    ));
+                }
+
+                if line.trim_end() == HELPER_READY {
+                    return Ok(());
+                }
+            }
+        })();
+
+        if let Err(e) = started {
+            let _ = child.kill();
+            let _ = child.wait();
+            let _ = fs::remove_file(&path);
+
+            return Err(HylaranaSenderError::SandboxError(e));
+        }
+
+        // Like a capture in this process, the watchdog counts from the start.
+        if media.video.is_some() {
+            control.capture.beat();
+            control.encode.beat();
+        }
+
+        let process = Self {
+            child: Arc::new(Mutex::new(child)),
+            stdin: Arc::new(Mutex::new(stdin)),
+            exit: Default::default(),
+            closing: Default::default(),
+        };
+
+        let child = process.child.clone();
+        let stdin = process.stdin.clone();
+        let exit = process.exit.clone();
+        let closing = process.closing.clone();
+        let control = control.clone();
+        let output = output.clone();
+        thread::Builder::new()
+            .name("HylaranaCaptureHelperThread".to_string())
+            .spawn(move || {
+                let packets = PacketPool::default();
+                let mut h264_only = request.h264_only;
+                let mut packet = Vec::with_capacity(64 * 1024);
+                let mut doorbell = [0u8; 256];
+
+                // The helper writes to the stdout after each packet, the packets
+                // themselves are in the ring.
+                'a: while matches!(stdout.read(&mut doorbell), Ok(size) if size > 0) {
+                    loop {
+                        let (kind, flags, timestamp) = match ring.pop(&mut packet) {
+                            Ok(Some(it)) => it,
+                            Ok(None) => break,
+                            Err(e) => {
+                                tracing::error!(error = ?e, "capture helper has corrupted the ring");
+
+                                // The helper is handled like a crash, a new helper gets a new
+                                // ring.
+                                let mut child = child.lock();
+                                let _ = child.kill();
+                                let _ = child.wait();
+
+                                exit.lock().replace(HelperExit::Crashed);
+                                break 'a;
+                            }
+                        };
+
+                        let info = if kind == KIND_VIDEO {
+                            control.capture.beat();
+                            control.encode.beat();
+
+                            StreamBufferInfo::Video(flags, timestamp)
+                        } else {
+                            StreamBufferInfo::Audio(flags, timestamp)
+                        };
+
+                        if !output.send(&packets, &packet, info) {
+                            exit.lock().replace(HelperExit::OutputClosed);
+                            break 'a;
+                        }
+                    }
+
+                    // The back channel of the receivers is handled by the sender.
+                    if control.key_frame.update(false) {
+                        send_command(&stdin, &HelperCommand::KeyFrame);
+                    }
+
+                    if !h264_only && control.h264_only.get() {
+                        h264_only = true;
+                        send_command(&stdin, &HelperCommand::H264Only);
+                    }
+                }
+
+                // The lock of the child is not held while waiting, the sender may be
+                // closing it at the same time.
+                while !closing.get() && exit.lock().is_none() {
+                    match child.lock().try_wait() {
+                        Ok(None) => thread::sleep(Duration::from_millis(10)),
+                        status => {
+                            tracing::warn!(status = ?status, "capture helper has exited");
+
+                            exit.lock().replace(match status {
+                                Ok(Some(status)) => HelperExit::from_status(status),
+                                _ => HelperExit::Crashed,
+                            });
+                        }
+                    }
+                }
+
+                drop(ring);
+                let _ = fs::remove_file(&path);
+            })
+            .map_err(HylaranaSenderError::SandboxError)?;
+
+        Ok(process)
+    }
+
+    /// How the helper has ended, `None` while it is running.
+    pub(crate) fn exited(&self) -> Option<HelperExit> {
+        *self.exit.lock()
+    }
+
+    pub(crate) fn set_audio_input(&self, index: usize, input: AudioMixerInput) {
+        send_command(&self.stdin, &HelperCommand::AudioInput { index, input });
+    }
+
+    pub(crate) fn set_privacy_masks(&self, options: Option<PrivacyMaskOptions>) {
+        send_command(&self.stdin, &HelperCommand::PrivacyMasks(options));
+    }
+
+    pub(crate) fn close(&self) -> Result<(), Error> {
+        if self.closing.update(true) {
+            return Ok(());
+        }
+
+        send_command(&self.stdin, &HelperCommand::Close);
+
+        let mut child = self.child.lock();
+        let deadline = Instant::now() + CLOSE_TIMEOUT;
+        while Instant::now() < deadline {
+            if child.try_wait()?.is_some() {
+                return Ok(());
+            }
+
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        tracing::warn!("capture helper does not exit, kill it");
+
+        child.kill()?;
+        child.wait()?;
+        Ok(())
+    }
+}
+
+fn send_command(stdin: &Mutex<ChildStdin>, command: &HelperCommand) {
+    let result = serde_json::to_string(command)
+        .map_err(Error::from)
+        .and_then(|it| writeln!(stdin.lock(), "{}", it));
+
+    // The helper may have exited, which is found out by the reading thread.
+    if let Err(e) = result {
+        tracing::warn!(command = ?command, error = ?e, "failed to send command to capture helper");
+    }
+}
+
+// The packets of the encoders of the helper go to the ring.
+struct RingWriter {
+    ring: PacketRing,
+    control: Arc<EncoderControl>,
+    full: AtomicBool,
+}
+
+impl RingWriter {
+    fn write(&self, kind: u8, packet: &[u8], flags: i32, timestamp: u64) -> bool {
+        if !self.ring.push(kind, flags, timestamp, packet) {
+            // The decoders of the receivers cannot continue without the dropped
+            // packet, the video starts again from a key frame.
+            if !self.full.update(true) {
+                log::warn!("capture helper ring is full, drop packets");
+            }
+
+            self.control.key_frame.update(true);
+            return true;
+        }
+
+        self.full.update(false);
+
+        // The sender is gone if the doorbell cannot be written.
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(b"\n").is_ok() && stdout.flush().is_ok()
+    }
+}
+
+impl RecordingSink for RingWriter {
+    fn video(&self, packet: &[u8], flags: i32, timestamp: u64) -> bool {
+        self.write(KIND_VIDEO, packet, flags, timestamp)
+    }
+
+    fn audio(&self, packet: &[u8], flags: i32, timestamp: u64) -> bool {
+        self.write(KIND_AUDIO, packet, flags, timestamp)
+    }
+}
+
+// The helper has no use for the frames, it only keeps why the stream ended.
+#[derive(Default)]
+struct HelperSink {
+    reason: Mutex<Option<DisconnectReason>>,
+}
+
+impl AVFrameStream for HelperSink {}
+
+impl AVFrameSink for HelperSink {}
+
+impl AVFrameObserver for HelperSink {
+    fn event(&self, event: StreamEvent) {
+        if let StreamEvent::Disconnected { reason } = event {
+            self.reason.lock().replace(reason);
+        } else {
+            log::info!("capture helper event={:?}", event);
+        }
+    }
+}
+
+fn helper_main(path: &Path) -> Result<i32, HylaranaSenderError> {
+    if let Err(e) = crate::startup() {
+        log::warn!("capture helper startup error={:?}", e);
+    }
+
+    let mut stdin = BufReader::new(std::io::stdin());
+    let mut line = String::new();
+    stdin.read_line(&mut line)?;
+
+    let request: HelperRequest = serde_json::from_str(&line)?;
+    log::info!("capture helper start, path={:?}", path);
+
+    let control: Arc<EncoderControl> = Default::default();
+    control.h264_only.update(request.h264_only);
+    control.set_privacy_masks(request.privacy_masks);
+
+    let status = StreamStatus::new();
+    let sink = Arc::new(HelperSink::default());
+    let (capture, mixer) = start_capture(
+        &HylaranaSenderMediaOptions {
+            video: request.video,
+            audio: request.audio,
+            graphics: GraphicsContext::new(request.adapter),
+            sandbox: None,
+        },
+        &request.audio_inputs,
+        &PreviewSink::default(),
+        &control,
+        &PacketOutput::Recording(Arc::new(RingWriter {
+            ring: PacketRing::open(path)?,
+            control: control.clone(),
+            full: AtomicBool::new(false),
+        })),
+        &status,
+        &sink,
+    )?;
+
+    {
+        let mut stdout = std::io::stdout().lock();
+        writeln!(stdout, "{}", HELPER_READY)?;
+        stdout.flush()?;
+    }
+
+    // The commands are read on their own thread, the capture may also end by
+    // itself, such as when the source is removed.
+    let closing = Arc::new(AtomicBool::new(false));
+    let mixer = Arc::new(Mutex::new(mixer));
+    {
+        let closing = closing.clone();
+        let control = control.clone();
+        let mixer = mixer.clone();
+        thread::Builder::new()
+            .name("HylaranaCaptureHelperCommandThread".to_string())
+            .spawn(move || {
+                for line in stdin.lines() {
+                    let command = match line.map(|it| serde_json::from_str(&it)) {
+                        Ok(Ok(it)) => it,
+                        Ok(Err(e)) => {
+                            log::warn!("capture helper invalid command, error={:?}", e);
+
+                            continue;
+                        }
+                        Err(_) => break,
+                    };
+
+                    match command {
+                        HelperCommand::KeyFrame => {
+                            control.key_frame.update(true);
+                        }
+                        HelperCommand::H264Only => {
+                            control.h264_only.update(true);
+                        }
+                        HelperCommand::AudioInput { index, input } => {
+                            if let Some(mixer) = mixer.lock().as_ref() {
+                                mixer.set_input(index, input);
+                            }
+                        }
+                        HelperCommand::PrivacyMasks(options) => {
+                            control.set_privacy_masks(options);
+                        }
+                        HelperCommand::Close => break,
+                    }
+                }
+
+                // The sender has asked the helper to close, or has gone away.
+                closing.update(true);
+            })?;
+    }
+
+    while !closing.get() && !status.is_closed() {
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    capture.close()?;
+    mixer.lock().take();
+
+    let reason = *sink.reason.lock();
+    Ok(match reason {
+        Some(DisconnectReason::SourceRemoved) => EXIT_SOURCE_REMOVED,
+        Some(DisconnectReason::Error(StreamErrorKind::Encode)) => EXIT_ENCODE_ERROR,
+        Some(DisconnectReason::Error(_)) => EXIT_CAPTURE_ERROR,
+        _ => EXIT_CLOSED,
+    })
+}
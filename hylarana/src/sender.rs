@@ -1,34 +1,71 @@
-use crate::AVFrameStream;
+use crate::{
+    close_stream,
+    dump::{AudioFrameDumper, VideoFrameDumper},
+    meter::AudioMeter,
+    metrics::{Metrics, METRICS},
+    packets::{EncodedPacketSink, PacketTap},
+    raw::{pack_audio_frame, RawVideoSender, RAW_AUDIO_CONFIG},
+    sandbox::{CaptureProcess, HelperExit, SandboxOptions},
+    video_codec_name,
+    watchdog::{Heartbeat, PipelineStage, StallState, Verdict, Watchdog},
+    AVFrameSink, AVFrameStream, DisconnectReason, GraphicsContext, MessageKind, RecordingSink,
+    StreamCapabilities, StreamErrorKind, StreamEvent, StreamFeature, StreamMetadata, StreamStatus,
+    VideoContentState, KEY_FRAME_REQUEST_INTERVAL,
+};
 
 use std::{
+    io::{Error, ErrorKind},
     mem::size_of,
     sync::{atomic::AtomicBool, Arc, Weak},
+    thread::{self, ThreadId},
+    time::{Duration, Instant},
 };
 
-use bytes::BytesMut;
+use bytes::{Bytes, BytesMut};
 use hylarana_capture::{
-    AudioCaptureSourceDescription, Capture, CaptureOptions, FrameArrived, Source,
-    SourceCaptureOptions, VideoCaptureSourceDescription,
+    AudioCaptureConfig, AudioCaptureSourceDescription, AudioMixer, AudioMixerInput, Capture,
+    CaptureOptions, FrameArrived, Source, SourceCaptureOptions, SourceEvent, SourceType,
+    VideoCaptureSourceDescription,
 };
+use parking_lot::{Mutex, RwLock};
 
 use hylarana_common::{
     atomic::EasyAtomic,
-    frame::{AudioFrame, VideoFrame},
+    clock::MediaClock,
+    frame::{AudioFrame, VideoFormat, VideoFrame},
+    input::InputEvent,
+    power::{self, PowerMonitor, PowerSource},
+    runtime::{self, ThreadKind},
     Size,
 };
 
 use hylarana_codec::{
-    create_opus_identification_header, AudioEncoder, AudioEncoderSettings, CodecType, VideoEncoder,
-    VideoEncoderSettings, VideoEncoderType,
+    create_opus_identification_header, AudioEncoder, AudioEncoderSettings, CodecType,
+    ContentDetector, ContentDetectorSettings, DeinterlaceMethod, Deinterlacer,
+    DeinterlacerSettings, MaskArea, PrivacyMaskStyle, PrivacyMasker, PrivacyMaskerSettings,
+    RateControl, ThumbnailEncoder, ThumbnailEncoderSettings, VideoDecoder, VideoDecoderSettings,
+    VideoDecoderType, VideoEncoder, VideoEncoderSettings, VideoEncoderTuning, VideoEncoderType,
+    VideoScaler, VideoScalerSettings, WatermarkOptions, Watermarker, WatermarkerSettings,
 };
 
 use hylarana_transport::{
-    copy_from_slice as package_copy_from_slice, BufferFlag, StreamBufferInfo, StreamSenderAdapter,
-    TransportOptions, TransportSender,
+    copy_from_slice as package_copy_from_slice, BandwidthEstimate, BufferFlag, PacketPacingOptions,
+    PacketPool, PairingOptions, ReceiverInfo, StreamBufferInfo, StreamControl, StreamSenderAdapter,
+    TransportOptions, TransportSender, TransportStrategy,
 };
 
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+#[cfg(target_os = "windows")]
+use crate::PauseReason;
+
+#[cfg(target_os = "windows")]
+use hylarana_common::win32::{
+    get_window_area, windows::Win32::Foundation::HWND, Direct3DDevice, SystemEvent,
+    SystemEventListener,
+};
+
 #[derive(Debug, Error)]
 pub enum HylaranaSenderError {
     #[error(transparent)]
@@ -39,10 +76,16 @@ pub enum HylaranaSenderError {
     VideoEncoderError(#[from] hylarana_codec::VideoEncoderError),
     #[error(transparent)]
     AudioEncoderError(#[from] hylarana_codec::AudioEncoderError),
+    #[error(transparent)]
+    MetadataError(#[from] serde_json::Error),
+    #[error(transparent)]
+    WatermarkError(#[from] hylarana_codec::WatermarkerError),
+    #[error("capture helper error: {0}")]
+    SandboxError(std::io::Error),
 }
 
 /// Description of video coding.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VideoOptions {
     pub codec: VideoEncoderType,
     pub frame_rate: u8,
@@ -50,17 +93,278 @@ pub struct VideoOptions {
     pub height: u32,
     pub bit_rate: u64,
     pub key_frame_interval: u32,
+    /// Reduce the capture frame rate when the encoder cannot keep up, instead
+    /// of accumulating latency.
+    #[serde(default)]
+    pub adaptive_pacing: bool,
+    /// The size the source is captured at, if it is different from the width
+    /// and height, the frames are scaled before they are encoded, such as
+    /// capturing 4K and encoding 1080p. `None` captures at the encoded size.
+    #[serde(default)]
+    pub capture_size: Option<Size>,
+    /// The profile, rate control and other parameters of the encoder, the
+    /// default is tuned for low latency.
+    #[serde(default)]
+    pub tuning: VideoEncoderTuning,
+    /// Publish small JPEG images of the video in the control messages of the
+    /// stream, so that the lists of the streams can show live previews, see
+    /// `AVFrameObserver::thumbnail`. The capture helper of the sandbox mode
+    /// does not publish them.
+    #[serde(default)]
+    pub thumbnail: Option<ThumbnailOptions>,
+    /// Deinterlace the interlaced frames of the source, such as a capture
+    /// card, before they are encoded, the progressive frames are passed on as
+    /// they are. `None` encodes the frames as they are.
+    #[serde(default)]
+    pub deinterlace: Option<DeinterlaceMethod>,
+    /// Draw a watermark into the frames before they are encoded, such as the
+    /// id of the user, so that the content that is relayed or recorded can be
+    /// traced. The stream is closed if the frames cannot be watermarked, they
+    /// are never sent without it. The local preview and the raw frames of the
+    /// shared memory transport are not watermarked.
+    #[serde(default)]
+    pub watermark: Option<WatermarkOptions>,
+    /// Hide areas of the frames before they are encoded, such as the
+    /// notifications or a panel with sensitive content, they can be changed
+    /// while streaming, see `HylaranaSender::set_privacy_masks`. The raw
+    /// frames of the shared memory transport are not masked.
+    #[serde(default)]
+    pub privacy_masks: Option<PrivacyMaskOptions>,
+    /// Report the video that has been black or frozen for a while, see
+    /// `ContentDetectionOptions`. `None` does not detect it.
+    #[serde(default)]
+    pub content_detection: Option<ContentDetectionOptions>,
+    /// Encode a key frame at the hard cuts of the video, such as a slide
+    /// change, see `SceneCutOptions`. The encoders do not insert key frames
+    /// for the scene changes by themselves then. `None` leaves it to the
+    /// encoders.
+    #[serde(default)]
+    pub scene_cut: Option<SceneCutOptions>,
+    /// Spend the bits on the areas of the frames that have changed, for the
+    /// desktops that are mostly static, see `DirtyRegionOptions`. `None`
+    /// encodes the whole frames alike.
+    #[serde(default)]
+    pub dirty_regions: Option<DirtyRegionOptions>,
+}
+
+/// An area of the video that is hidden before the frames are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PrivacyMask {
+    /// A rectangle in fractions of the width and height of the frames, from
+    /// `0.0` to `1.0` from the top left corner, so that it covers the same
+    /// content at any size of the frames.
+    Rect {
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+    },
+    /// The area of a window by its handle, the mask follows the window when it
+    /// moves, and nothing is masked while the window is hidden or minimized.
+    /// Only the screens of Windows support it, it is ignored otherwise.
+    Window(u64),
+}
+
+/// Options of the privacy masks of the video.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PrivacyMaskOptions {
+    pub masks: Vec<PrivacyMask>,
+    #[serde(default)]
+    pub style: PrivacyMaskStyle,
+}
+
+/// Options of the thumbnails of the video.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ThumbnailOptions {
+    /// The time between the thumbnails.
+    pub interval: Duration,
+    /// The width of the thumbnails, the height follows the aspect ratio of the
+    /// video.
+    pub width: u32,
+    /// The quality of the JPEG images, from 1 to 100.
+    pub quality: u8,
+}
+
+impl Default for ThumbnailOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            width: 320,
+            quality: 70,
+        }
+    }
+}
+
+/// Options of the detection of the black and frozen video.
+///
+/// The captured frames are checked before they are encoded, and
+/// `StreamEvent::VideoContent` is raised once the video has been black or has
+/// not changed for the duration, and again as soon as it changes, so that the
+/// video that shows nothing can be told apart from a stream that does not
+/// arrive. The capture helper of the sandbox mode does not report it.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContentDetectionOptions {
+    /// How long the video has to be black or frozen before it is reported.
+    pub duration: Duration,
+    /// The 8 bits luma that the black frames do not exceed, the black of the
+    /// limited range is 16.
+    pub black_threshold: u8,
+    /// Stop encoding the frames that have not changed once the video is
+    /// reported as frozen, to save the bandwidth. One frame per second is
+    /// still encoded, as well as the key frames that the receivers request.
+    pub skip_frozen: bool,
+}
+
+impl Default for ContentDetectionOptions {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(3),
+            black_threshold: 32,
+            skip_frozen: false,
+        }
+    }
+}
+
+/// Options of the key frames at the scene changes.
+///
+/// The frames are compared with the previous frame before they are encoded,
+/// and a key frame is encoded when enough of the picture has changed, so that
+/// the new scene is sharp at once instead of being built up over the
+/// following frames. The key frames are not encoded with intra refresh.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SceneCutOptions {
+    /// The fraction of the picture that has to change, from `0.0` to `1.0`.
+    pub threshold: f32,
+    /// The shortest time between the key frames of the scene changes, so that
+    /// the fast motion, such as scrolling, does not encode a key frame for
+    /// every frame.
+    pub min_interval: Duration,
+}
+
+impl Default for SceneCutOptions {
+    fn default() -> Self {
+        Self {
+            threshold: 0.25,
+            min_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+/// Options of the encoding of the changed areas of the video.
+///
+/// The frames are compared with the previous frame in tiles before they are
+/// encoded, the tiles that have not changed are encoded with a coarser
+/// quantizer, which the static content does not need, so that the bit rate
+/// of a mostly static desktop drops. The capture does not report the changed
+/// areas, they are found by the comparison. The whole frame is encoded alike
+/// when too much of it has changed, as well as the key frames, which code the
+/// whole picture again.
+///
+/// Only the software frames are compared at the full size. The Direct3D11
+/// textures are compared scaled down, which may miss a small change, so their
+/// frames are always encoded alike.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DirtyRegionOptions {
+    /// The width and height of the tiles in pixels, the multiples of 16 match
+    /// the macroblocks of the encoders.
+    pub tile_size: u32,
+    /// The fraction of the picture that can change before the whole frame is
+    /// encoded alike, from `0.0` to `1.0`.
+    pub max_damage: f32,
+}
+
+impl Default for DirtyRegionOptions {
+    fn default() -> Self {
+        Self {
+            tile_size: 64,
+            max_damage: 0.5,
+        }
+    }
 }
 
 /// Description of the audio encoding.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct AudioOptions {
     pub sample_rate: u64,
     pub bit_rate: u64,
+    /// The gain applied to the track when it is mixed with other tracks, `1.0`
+    /// keeps the original volume.
+    #[serde(default = "default_gain")]
+    pub gain: f32,
+    /// The sample rate and channels that the device of the track is opened
+    /// with, the default opens the device in its own configuration.
+    #[serde(default)]
+    pub capture: AudioCaptureConfig,
+    /// Stop sending the audio during silence, see `DtxOptions`, the encoder
+    /// uses the option of the first track. `None` sends the audio all the
+    /// time.
+    #[serde(default)]
+    pub dtx: Option<DtxOptions>,
+}
+
+/// Options of the discontinuous transmission of the audio. The sender stops
+/// sending packets while the level of the audio stays below the threshold,
+/// and the receiver plays silence until the packets come back, which saves
+/// most of the bandwidth of the audio in a screen share with occasional
+/// talking. The shared memory transport sends the audio all the time.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DtxOptions {
+    /// The level in dBFS that the audio is taken as silence below.
+    pub threshold: f32,
+    /// How long the audio has to stay below the threshold before the sender
+    /// stops, so the short pauses of speech are still sent.
+    pub hangover: Duration,
+}
+
+impl Default for DtxOptions {
+    fn default() -> Self {
+        Self {
+            threshold: -50.0,
+            hangover: Duration::from_millis(500),
+        }
+    }
+}
+
+fn default_gain() -> f32 {
+    1.0
+}
+
+/// Options of the energy saver of the sender. While the system runs on
+/// battery, the sender captures at a lower frame rate, prefers the hardware
+/// encoder and paces the packets, and it goes back to its own options when
+/// the system is plugged in. The capture and the encoders are created again
+/// on each switch, see `StreamEvent::EnergySaver`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EnergySaverOptions {
+    /// The frame rate of the capture on battery, the video keeps its frame
+    /// rate if it is lower.
+    pub frame_rate: u8,
+    /// Use the hardware encoder of the codec on battery, which takes much
+    /// less power than the software encoders.
+    pub hardware_encoder: bool,
+    /// The pacing of the packets on battery, see `TransportOptions::pacing`.
+    /// `None` keeps the pacing of the transport.
+    pub pacing: Option<PacketPacingOptions>,
+}
+
+impl Default for EnergySaverOptions {
+    fn default() -> Self {
+        Self {
+            frame_rate: 15,
+            hardware_encoder: true,
+            pacing: Some(PacketPacingOptions::default()),
+        }
+    }
 }
 
 /// Options of the media track.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HylaranaSenderTrackOptions<T> {
     pub source: Source,
     pub options: T,
@@ -70,7 +374,16 @@ pub struct HylaranaSenderTrackOptions<T> {
 #[derive(Debug, Clone)]
 pub struct HylaranaSenderMediaOptions {
     pub video: Option<HylaranaSenderTrackOptions<VideoOptions>>,
-    pub audio: Option<HylaranaSenderTrackOptions<AudioOptions>>,
+    /// The audio tracks are mixed into one stream, such as the system audio
+    /// and a microphone, the encoder uses the sample rate and the bit rate of
+    /// the first track.
+    pub audio: Vec<HylaranaSenderTrackOptions<AudioOptions>>,
+    /// The context that the capture and the encoders run on.
+    pub graphics: GraphicsContext,
+    /// Run the capture and the encoders of a sender in a helper process, see
+    /// `SandboxOptions`, a local session and the shared memory transport
+    /// ignore it.
+    pub sandbox: Option<SandboxOptions>,
 }
 
 /// Sender configuration.
@@ -78,328 +391,2484 @@ pub struct HylaranaSenderMediaOptions {
 pub struct HylaranaSenderOptions {
     pub media: HylaranaSenderMediaOptions,
     pub transport: TransportOptions,
+    pub metadata: StreamMetadata,
+    /// A stable ID of the stream that the receivers and the announcements use
+    /// instead of a random one, so that the receivers that wait for the
+    /// sender with `TransportOptions::resume` reattach to a restarted sender.
+    /// It cannot be empty, longer than 256 bytes or contain commas.
+    pub id: Option<String>,
+    /// Save energy while the system runs on battery, see
+    /// `EnergySaverOptions`. `None` uses the same options all the time.
+    pub energy_saver: Option<EnergySaverOptions>,
+    /// Require the receivers to submit the code that the sender shows, see
+    /// `PairingOptions` and `AVFrameObserver::pairing_code`. Only the direct
+    /// mode supports it. `None` lets all the receivers connect.
+    pub pairing: Option<PairingOptions>,
 }
 
-struct VideoSender<T: AVFrameStream + 'static> {
-    adapter: Arc<StreamSenderAdapter>,
-    status: Arc<AtomicBool>,
-    encoder: VideoEncoder,
-    sink: Weak<T>,
-}
+// The interval at which the clock of the sender is sent, in microseconds.
+const CLOCK_INTERVAL: u64 = 1_000_000;
 
-// Encoding is a relatively complex task. If you add encoding tasks to the
-// pipeline that pushes frames, it will slow down the entire pipeline.
-//
-// Here, the tasks are separated, and the encoding tasks are separated into
-// independent threads. The encoding thread is notified of task updates through
-// the optional lock.
-impl<T: AVFrameStream + 'static> VideoSender<T> {
-    fn new(
-        status: Arc<AtomicBool>,
-        transport: &TransportSender,
-        settings: VideoEncoderSettings,
-        sink: &Arc<T>,
-    ) -> Result<Self, HylaranaSenderError> {
-        Ok(Self {
-            encoder: VideoEncoder::new(settings)?,
-            adapter: transport.get_adapter(),
-            sink: Arc::downgrade(sink),
-            status,
-        })
-    }
+// The frames of the lossless video are many times larger than the compressed
+// frames, a key frame of 1080p can take several megabytes.
+const LOSSLESS_PACKET_POOL_SIZE: usize = 32 * 1024 * 1024;
 
-    fn process(&mut self, frame: &VideoFrame) -> bool {
-        // Push the audio and video frames into the encoder.
-        if self.encoder.update(frame) {
-            // Try to get the encoded data packets. The audio and video frames do not
-            // correspond to the data packets one by one, so you need to try to get
-            // multiple packets until they are empty.
-            if let Err(e) = self.encoder.encode() {
-                log::error!("video encode error={:?}", e);
+// The latency that the lossless video is sent with if the transport does not
+// specify one, the frames of a few megabytes cannot be sent within the default
+// latency.
+const LOSSLESS_LATENCY: Duration = Duration::from_millis(200);
 
-                return false;
-            } else {
-                while let Some((buffer, flags, timestamp)) = self.encoder.read() {
-                    if !self.adapter.send(
-                        package_copy_from_slice(buffer),
-                        StreamBufferInfo::Video(flags, timestamp),
-                    ) {
-                        log::warn!("video send packet to adapter failed");
-
-                        return false;
-                    }
-                }
-            }
-        } else {
-            log::warn!("video encoder update frame failed");
+fn is_lossless(options: &HylaranaSenderOptions) -> bool {
+    options
+        .media
+        .video
+        .as_ref()
+        .map(|it| it.options.tuning.rate_control == Some(RateControl::Lossless))
+        .unwrap_or(false)
+}
 
-            return false;
-        }
+pub(crate) type PreviewSink = Arc<RwLock<Option<Box<dyn AVFrameSink>>>>;
 
-        if let Some(sink) = self.sink.upgrade() {
-            if sink.video(frame) {
-                true
-            } else {
-                log::warn!("video sink on frame return false");
+// The state that the back channel of the receivers shares with the video
+// encoder, it outlives the capture, so a restarted capture keeps it.
+#[derive(Default)]
+pub(crate) struct EncoderControl {
+    // A receiver has requested a key frame.
+    pub(crate) key_frame: AtomicBool,
+    // A receiver cannot decode HEVC, the video is encoded as H264 from now on.
+    pub(crate) h264_only: AtomicBool,
+    // The capabilities that the sender and all receivers that have joined
+    // support.
+    capabilities: Mutex<StreamCapabilities>,
+    metadata: Mutex<StreamMetadata>,
+    // The last time a frame arrived from the capture and the encoder output a
+    // packet, for the watchdog.
+    pub(crate) capture: Heartbeat,
+    pub(crate) encode: Heartbeat,
+    // The D3D device of the encoder has been removed, the watchdog restarts the
+    // capture and the encoders on a new device.
+    device_removed: AtomicBool,
+    // The privacy masks of the video, the encoder takes them again when they have
+    // changed.
+    pub(crate) privacy_masks: Mutex<Option<PrivacyMaskOptions>>,
+    privacy_changed: AtomicBool,
+}
 
-                false
-            }
-        } else {
-            log::warn!("video sink weak upgrade failed, maybe is drop");
+impl EncoderControl {
+    pub(crate) fn set_privacy_masks(&self, options: Option<PrivacyMaskOptions>) {
+        *self.privacy_masks.lock() = options;
+        self.privacy_changed.update(true);
+    }
 
-            false
+    fn negotiate(&self, remote: &StreamCapabilities) {
+        let mut capabilities = self.capabilities.lock();
+        *capabilities = capabilities.intersect(remote);
+
+        tracing::info!(
+            remote = ?remote,
+            negotiated = ?*capabilities,
+            "receiver advertised capabilities"
+        );
+
+        // All receivers decode H264. There is no way back to HEVC, the receiver
+        // that cannot decode it may still be watching.
+        if !capabilities.supports_codec("hevc") {
+            self.h264_only.update(true);
         }
     }
-}
-
-impl<T: AVFrameStream + 'static> FrameArrived for VideoSender<T> {
-    type Frame = VideoFrame;
 
-    fn sink(&mut self, frame: &Self::Frame) -> bool {
-        if self.process(frame) {
-            true
-        } else {
-            if let Some(sink) = self.sink.upgrade() {
-                if !self.status.get() {
-                    self.status.update(true);
-                    sink.close();
+    fn set_metadata(
+        &self,
+        adapter: &StreamSenderAdapter,
+        mut metadata: StreamMetadata,
+    ) -> Result<(), serde_json::Error> {
+        if self.h264_only.get() {
+            for codec in metadata.codecs.iter_mut() {
+                if codec == "hevc" {
+                    *codec = "h264".to_string();
                 }
             }
-
-            false
         }
+
+        adapter.set_metadata(serde_json::to_vec(&metadata)?.into());
+
+        *self.metadata.lock() = metadata;
+        Ok(())
     }
 }
 
-struct AudioSender<T: AVFrameStream + 'static> {
-    adapter: Arc<StreamSenderAdapter>,
-    status: Arc<AtomicBool>,
-    encoder: AudioEncoder,
-    chunk_count: usize,
-    buffer: BytesMut,
-    sink: Weak<T>,
+// What the monitor has found in a frame.
+#[derive(Default)]
+struct ContentUpdate {
+    // The state of the video if it has to be reported.
+    event: Option<VideoContentState>,
+    // The frame is frozen and can be skipped.
+    skip: bool,
+    // The frame is a scene change.
+    key_frame: bool,
+    // The areas of the frame that have changed, `None` if the whole frame is
+    // encoded alike.
+    damage: Option<Vec<MaskArea>>,
 }
 
-// Encoding is a relatively complex task. If you add encoding tasks to the
-// pipeline that pushes frames, it will slow down the entire pipeline.
-//
-// Here, the tasks are separated, and the encoding tasks are separated into
-// independent threads. The encoding thread is notified of task updates through
-// the optional lock.
-impl<T: AVFrameStream + 'static> AudioSender<T> {
-    fn new(
-        status: Arc<AtomicBool>,
-        transport: &TransportSender,
-        settings: AudioEncoderSettings,
-        sink: &Arc<T>,
-    ) -> Result<Self, HylaranaSenderError> {
-        let adapter = transport.get_adapter();
+// Follows what the video shows from the frames that the detector checks, for the
+// detection of the black and frozen video, of the scene changes and of the
+// changed areas.
+struct ContentMonitor {
+    detector: ContentDetector,
+    options: Option<ContentDetectionOptions>,
+    scene_cut: Option<SceneCutOptions>,
+    dirty_regions: Option<DirtyRegionOptions>,
+    // The state of the frames and since when they have been in it.
+    current: (VideoContentState, Instant),
+    reported: VideoContentState,
+    // The last time a frame was encoded while frozen.
+    encoded: Instant,
+    // The last time a key frame was encoded for a scene change.
+    cut: Option<Instant>,
+}
 
-        // Create an opus header data. The opus decoder needs this data to obtain audio
-        // information. Here, actively add an opus header information to the queue, and
-        // the adapter layer will automatically cache it.
-        adapter.send(
-            package_copy_from_slice(&create_opus_identification_header(
-                1,
-                settings.sample_rate as u32,
-            )),
-            StreamBufferInfo::Audio(BufferFlag::Config as i32, 0),
-        );
+impl ContentMonitor {
+    // The frozen frames are encoded at this interval when they are skipped.
+    const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
 
-        Ok(AudioSender {
-            chunk_count: settings.sample_rate as usize / 1000 * 100,
-            encoder: AudioEncoder::new(settings)?,
-            buffer: BytesMut::with_capacity(48000),
-            sink: Arc::downgrade(sink),
-            adapter,
-            status,
-        })
+    fn new(
+        options: Option<ContentDetectionOptions>,
+        scene_cut: Option<SceneCutOptions>,
+        dirty_regions: Option<DirtyRegionOptions>,
+        detector: ContentDetector,
+    ) -> Self {
+        Self {
+            current: (VideoContentState::Normal, Instant::now()),
+            reported: VideoContentState::Normal,
+            encoded: Instant::now(),
+            cut: None,
+            dirty_regions,
+            detector,
+            scene_cut,
+            options,
+        }
     }
 
-    fn process(&mut self, frame: &AudioFrame) -> bool {
-        self.buffer.extend_from_slice(unsafe {
-            std::slice::from_raw_parts(
-                frame.data as *const _,
-                frame.frames as usize * size_of::<i16>(),
-            )
-        });
+    fn update(
+        &mut self,
+        frame: &VideoFrame,
+    ) -> Result<ContentUpdate, hylarana_codec::ContentDetectorError> {
+        let content = self.detector.process(frame)?;
+        let now = Instant::now();
 
-        if self.buffer.len() >= self.chunk_count * 2 {
-            let payload = self.buffer.split_to(self.chunk_count * size_of::<i16>());
-            let frame = AudioFrame {
-                data: payload.as_ptr() as *const _,
-                frames: self.chunk_count as u32,
-                sample_rate: 0,
-            };
+        let mut update = ContentUpdate::default();
+        if let Some(options) = self.scene_cut {
+            if content.changed >= options.threshold
+                && self
+                    .cut
+                    .map(|it| now - it >= options.min_interval)
+                    .unwrap_or(true)
+            {
+                self.cut = Some(now);
+                update.key_frame = true;
+            }
+        }
 
-            if self.encoder.update(&frame) {
-                // Push the audio and video frames into the encoder.
-                if let Err(e) = self.encoder.encode() {
-                    log::error!("audio encode error={:?}", e);
+        if let Some(options) = self.dirty_regions {
+            update.damage = self.detector.damage(options.tile_size).filter(|areas| {
+                let area = areas
+                    .iter()
+                    .map(|it| it.width as u64 * it.height as u64)
+                    .sum::<u64>();
 
-                    return false;
-                } else {
-                    // Try to get the encoded data packets. The audio and video frames
-                    // do not correspond to the data
-                    // packets one by one, so you need to try to get
-                    // multiple packets until they are empty.
-                    while let Some((buffer, flags, timestamp)) = self.encoder.read() {
-                        if !self.adapter.send(
-                            package_copy_from_slice(buffer),
-                            StreamBufferInfo::Audio(flags, timestamp),
-                        ) {
-                            log::warn!("audio send packet to adapter failed");
+                area as f64 <= options.max_damage as f64 * frame.width as f64 * frame.height as f64
+            });
+        }
 
-                            return false;
-                        }
-                    }
-                }
-            } else {
-                log::warn!("audio encoder update frame failed");
+        let Some(options) = self.options else {
+            return Ok(update);
+        };
 
-                return false;
-            }
+        let state = if content.black {
+            VideoContentState::Black
+        } else if content.unchanged {
+            VideoContentState::Frozen
+        } else {
+            VideoContentState::Normal
+        };
+
+        if state != self.current.0 {
+            self.current = (state, now);
         }
 
-        if let Some(sink) = self.sink.upgrade() {
-            if sink.audio(frame) {
-                true
-            } else {
-                log::warn!("audio sink on frame return false");
+        // The video that changes again is reported at once.
+        if self.current.0 != self.reported
+            && (self.current.0 == VideoContentState::Normal
+                || now - self.current.1 >= options.duration)
+        {
+            self.reported = self.current.0;
+            update.event = Some(self.reported);
+        }
 
-                false
-            }
-        } else {
-            log::warn!("audio sink weak upgrade failed, maybe is drop");
+        update.skip = options.skip_frozen
+            && self.reported != VideoContentState::Normal
+            && content.unchanged
+            && now - self.encoded < Self::KEEPALIVE_INTERVAL;
 
-            false
+        if !update.skip {
+            self.encoded = now;
         }
+
+        Ok(update)
     }
 }
 
-impl<T: AVFrameStream + 'static> FrameArrived for AudioSender<T> {
-    type Frame = AudioFrame;
+// The areas of the privacy masks in the pixels of a frame. The windows are looked
+// up for every frame, they may have moved, the frame may be scaled from the
+// display.
+#[allow(unused_variables)]
+fn privacy_mask_areas(
+    masks: &[PrivacyMask],
+    display: Option<&str>,
+    width: u32,
+    height: u32,
+) -> Vec<MaskArea> {
+    let area = |left: f64, top: f64, right: f64, bottom: f64| {
+        let (left, top) = (left.max(0.0) as u32, top.max(0.0) as u32);
+        let (right, bottom) = (right.max(0.0).ceil() as u32, bottom.max(0.0).ceil() as u32);
 
-    fn sink(&mut self, frame: &Self::Frame) -> bool {
-        if self.process(frame) {
-            true
-        } else {
-            if let Some(sink) = self.sink.upgrade() {
-                if !self.status.get() {
-                    self.status.update(true);
-                    sink.close();
-                }
+        MaskArea {
+            x: left,
+            y: top,
+            width: right.saturating_sub(left),
+            height: bottom.saturating_sub(top),
+        }
+    };
+
+    let (w, h) = (width as f64, height as f64);
+    masks
+        .iter()
+        .filter_map(|mask| match *mask {
+            PrivacyMask::Rect {
+                x,
+                y,
+                width,
+                height,
+            } => {
+                let (x, y) = (x.clamp(0.0, 1.0) as f64, y.clamp(0.0, 1.0) as f64);
+                Some(area(
+                    x * w,
+                    y * h,
+                    (x + width.max(0.0) as f64).min(1.0) * w,
+                    (y + height.max(0.0) as f64).min(1.0) * h,
+                ))
             }
+            #[cfg(target_os = "windows")]
+            PrivacyMask::Window(id) => {
+                let (rect, size) = get_window_area(HWND(id as _), display?)?;
+                let (sx, sy) = (w / size.width as f64, h / size.height as f64);
 
-            false
-        }
-    }
+                Some(area(
+                    rect.left as f64 * sx,
+                    rect.top as f64 * sy,
+                    rect.right as f64 * sx,
+                    rect.bottom as f64 * sy,
+                ))
+            }
+            #[cfg(not(target_os = "windows"))]
+            PrivacyMask::Window(_) => None,
+        })
+        .collect()
 }
 
-/// Screen casting sender.
-pub struct HylaranaSender<T: AVFrameStream + 'static> {
-    transport: TransportSender,
-    status: Arc<AtomicBool>,
-    capture: Capture,
-    sink: Arc<T>,
+// Where the encoded packets go, the transport of a sender, or the recording sink
+// of a local session, which has no transport at all.
+#[derive(Clone)]
+pub(crate) enum PacketOutput {
+    /// The packets are also passed to the packet sink of the application.
+    Transport(Arc<StreamSenderAdapter>, PacketTap),
+    /// The shared memory transport, which takes the raw frames instead of the
+    /// packets of the encoders.
+    Raw(Arc<StreamSenderAdapter>),
+    Recording(Arc<dyn RecordingSink>),
 }
 
-impl<T: AVFrameStream + 'static> HylaranaSender<T> {
-    // Create a sender. The capture of the sender is started following the sender,
-    // but both video capture and audio capture can be empty, which means you can
-    // create a sender that captures nothing.
-    pub(crate) fn new(
-        options: HylaranaSenderOptions,
-        sink: T,
-    ) -> Result<Self, HylaranaSenderError> {
-        log::info!("create sender");
+impl PacketOutput {
+    fn adapter(&self) -> Option<&Arc<StreamSenderAdapter>> {
+        match self {
+            Self::Transport(adapter, _) | Self::Raw(adapter) => Some(adapter),
+            Self::Recording(_) => None,
+        }
+    }
 
-        let mut capture_options = CaptureOptions::default();
-        let transport = hylarana_transport::create_sender(options.transport)?;
-        let status = Arc::new(AtomicBool::new(false));
-        let sink = Arc::new(sink);
+    fn tap(&self) -> Option<&PacketTap> {
+        match self {
+            Self::Transport(_, tap) => Some(tap),
+            _ => None,
+        }
+    }
 
-        if let Some(HylaranaSenderTrackOptions { source, options }) = options.media.audio {
-            capture_options.audio = Some(SourceCaptureOptions {
-                arrived: AudioSender::new(
-                    status.clone(),
-                    &transport,
-                    AudioEncoderSettings {
-                        sample_rate: options.sample_rate,
-                        bit_rate: options.bit_rate,
-                    },
-                    &sink,
-                )?,
-                description: AudioCaptureSourceDescription {
-                    sample_rate: options.sample_rate as u32,
-                    source,
-                },
-            });
+    pub(crate) fn is_raw(&self) -> bool {
+        matches!(self, Self::Raw(_))
+    }
+
+    pub(crate) fn send(&self, packets: &PacketPool, buffer: &[u8], info: StreamBufferInfo) -> bool {
+        match self {
+            Self::Transport(adapter, tap) => {
+                match info {
+                    StreamBufferInfo::Video(flags, timestamp) => {
+                        tap.video(buffer, flags, timestamp)
+                    }
+                    StreamBufferInfo::Audio(flags, timestamp) => {
+                        tap.audio(buffer, flags, timestamp)
+                    }
+                }
+
+                adapter.send(packets.copy_from_slice(buffer), info)
+            }
+            Self::Raw(adapter) => adapter.send(packets.copy_from_slice(buffer), info),
+            Self::Recording(sink) => match info {
+                StreamBufferInfo::Video(flags, timestamp) => sink.video(buffer, flags, timestamp),
+                StreamBufferInfo::Audio(flags, timestamp) => sink.audio(buffer, flags, timestamp),
+            },
         }
+    }
 
-        if let Some(HylaranaSenderTrackOptions { source, options }) = options.media.video {
-            capture_options.video = Some(SourceCaptureOptions {
-                description: VideoCaptureSourceDescription {
-                    hardware: CodecType::from(options.codec).is_hardware(),
-                    fps: options.frame_rate,
-                    size: Size {
-                        width: options.width,
-                        height: options.height,
-                    },
-                    source,
-                    #[cfg(target_os = "windows")]
-                    direct3d: crate::get_direct3d(),
-                },
-                arrived: VideoSender::new(
-                    status.clone(),
-                    &transport,
-                    VideoEncoderSettings {
-                        codec: options.codec,
-                        key_frame_interval: options.key_frame_interval,
-                        frame_rate: options.frame_rate,
-                        width: options.width,
-                        height: options.height,
-                        bit_rate: options.bit_rate,
-                        #[cfg(target_os = "windows")]
-                        direct3d: Some(crate::get_direct3d()),
-                    },
-                    &sink,
-                )?,
-            });
+    // Why the stream ends when a packet cannot be written.
+    pub(crate) fn closed_reason(&self) -> DisconnectReason {
+        match self {
+            Self::Transport(..) | Self::Raw(_) => DisconnectReason::TransportClosed,
+            Self::Recording(_) => DisconnectReason::SinkClosed,
         }
+    }
+}
 
-        Ok(Self {
-            capture: Capture::start(capture_options)?,
-            transport,
-            status,
+// Decodes the packets of the encoder again for the local preview. The decoder is
+// created when the preview is enabled, it starts with the cached configuration
+// and the next key frame.
+struct VideoPreview {
+    sink: PreviewSink,
+    // `None` if the stream cannot be decoded on this device.
+    codec: Option<VideoDecoderType>,
+    decoder: Option<VideoDecoder>,
+    config: Option<Vec<u8>>,
+    // The preview is decoded on the device of the encoder.
+    #[cfg(target_os = "windows")]
+    direct3d: Option<Direct3DDevice>,
+}
+
+impl VideoPreview {
+    fn new(sink: PreviewSink, settings: &VideoEncoderSettings) -> Self {
+        Self {
+            codec: Self::decoder_type(settings.codec),
+            #[cfg(target_os = "windows")]
+            direct3d: settings.direct3d.clone(),
+            decoder: None,
+            config: None,
             sink,
-        })
+        }
     }
 
-    /// Get the ID of the sender, each sender has an individual ID identifier,
-    /// you need to specify the ID of the sender when creating the receiver.
-    pub fn get_id(&self) -> &str {
-        self.transport.get_id()
+    fn decoder_type(codec: VideoEncoderType) -> Option<VideoDecoderType> {
+        match codec {
+            VideoEncoderType::X264 | VideoEncoderType::Qsv | VideoEncoderType::VideoToolBox => {
+                Some(VideoDecoderType::H264)
+            }
+            // There is no software decoder for HEVC.
+            #[cfg(target_os = "windows")]
+            VideoEncoderType::X265 | VideoEncoderType::HevcQsv => Some(VideoDecoderType::HevcD3D11),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
     }
-}
 
-impl<T: AVFrameStream + 'static> Drop for HylaranaSender<T> {
-    fn drop(&mut self) {
-        log::info!("sender drop");
+    fn push(&mut self, packet: &[u8], flags: i32, timestamp: u64, frame: &VideoFrame) {
+        let flag = flags & BufferFlag::MASK;
+        if flag == BufferFlag::Config as i32 {
+            self.config = Some(packet.to_vec());
+        }
 
-        if !self.status.get() {
-            self.status.update(true);
+        let sink = self.sink.read();
+        let sink = if let Some(sink) = sink.as_ref() {
+            sink
+        } else {
+            self.decoder = None;
+            return;
+        };
 
-            // When the sender releases, the cleanup work should be done, but there is a
-            // more troublesome point here. If it is actively released by the outside, it
-            // will also call back to the external closing event. It stands to reason that
-            // it should be distinguished whether it is an active closure, but in order to
-            // make it simpler to implement, let's do it this way first.
-            if let Err(e) = self.capture.close() {
-                log::warn!("hylarana sender capture close error={:?}", e);
+        if self.decoder.is_none() {
+            if flag != BufferFlag::KeyFrame as i32 {
+                return;
             }
 
-            self.sink.close();
+            if let Some(codec) = self.codec {
+                match VideoDecoder::new(VideoDecoderSettings {
+                    codec,
+                    #[cfg(target_os = "windows")]
+                    direct3d: self.direct3d.clone(),
+                }) {
+                    Ok(mut decoder) => {
+                        if let Some(config) = self.config.as_ref() {
+                            if let Err(e) = decoder.decode(config, 0) {
+                                tracing::warn!(error = ?e, "preview decode config error");
+                            }
+                        }
+
+                        self.decoder = Some(decoder);
+                    }
+                    Err(e) => {
+                        tracing::error!(error = ?e, "failed to create preview decoder");
+
+                        self.codec = None;
+                    }
+                }
+            }
         }
+
+        if let Some(decoder) = self.decoder.as_mut() {
+            decoder.set_orientation(frame.rotation, frame.mirror);
+
+            if let Err(e) = decoder.decode(packet, timestamp) {
+                tracing::warn!(error = ?e, "preview decode error");
+
+                self.decoder = None;
+                return;
+            }
+
+            while let Some(frame) = decoder.read() {
+                sink.video(frame);
+            }
+        }
+    }
+
+    // The encoder has been replaced, the packets of the new encoder start with a
+    // new configuration.
+    fn reset(&mut self, codec: VideoEncoderType) {
+        self.codec = Self::decoder_type(codec);
+        self.decoder = None;
+        self.config = None;
+    }
+}
+
+struct VideoSender<T: AVFrameStream + 'static> {
+    output: PacketOutput,
+    packets: PacketPool,
+    status: Arc<StreamStatus>,
+    // The settings of the encoder that is used, the codec may differ from the
+    // configured one after falling back.
+    settings: VideoEncoderSettings,
+    encoder: VideoEncoder,
+    scaler: VideoScaler,
+    preview: VideoPreview,
+    sink: Weak<T>,
+    control: Arc<EncoderControl>,
+    thumbnail: Option<VideoThumbnail>,
+    deinterlacer: Option<Deinterlacer>,
+    watermarker: Option<Watermarker>,
+    content: Option<ContentMonitor>,
+    masker: PrivacyMasker,
+    masks: Option<PrivacyMaskOptions>,
+    // The id of the screen that is captured, the windows are masked on it.
+    display: Option<String>,
+    dump: VideoFrameDumper,
+    // The time the clock of the sender was last sent.
+    clock: u64,
+    thread: EncodeThread,
+}
+
+// The frames are encoded on the thread of the capture source that delivers
+// them, which takes the options of the encode threads when it first encodes.
+// The capture may deliver the frames on a new thread after restarting.
+#[derive(Default)]
+struct EncodeThread(Option<ThreadId>);
+
+impl EncodeThread {
+    fn enter(&mut self) {
+        let id = thread::current().id();
+        if self.0 != Some(id) {
+            self.0 = Some(id);
+
+            runtime::apply_thread_options(ThreadKind::Encode);
+        }
+    }
+}
+
+// Encoding is a relatively complex task. If you add encoding tasks to the
+// pipeline that pushes frames, it will slow down the entire pipeline.
+//
+// Here, the tasks are separated, and the encoding tasks are separated into
+// independent threads. The encoding thread is notified of task updates through
+// the optional lock.
+impl<T: AVFrameStream + 'static> VideoSender<T> {
+    fn new(
+        status: Arc<StreamStatus>,
+        output: &PacketOutput,
+        mut settings: VideoEncoderSettings,
+        preview: PreviewSink,
+        control: Arc<EncoderControl>,
+        thumbnail: Option<ThumbnailOptions>,
+        deinterlace: Option<DeinterlaceMethod>,
+        watermark: Option<WatermarkOptions>,
+        display: Option<String>,
+        content_detection: Option<ContentDetectionOptions>,
+        scene_cut: Option<SceneCutOptions>,
+        dirty_regions: Option<DirtyRegionOptions>,
+        sink: &Arc<T>,
+    ) -> Result<Self, HylaranaSenderError> {
+        if control.h264_only.get() && CodecType::from(settings.codec).is_10bit() {
+            settings.codec = VideoEncoderType::X264;
+        }
+
+        let (encoder, codec) = VideoEncoder::with_fallback(settings.clone())?;
+        if codec != settings.codec {
+            sink.event(StreamEvent::EncoderFallback {
+                from: settings.codec,
+                to: codec,
+            });
+
+            settings.codec = codec;
+        }
+
+        // The stream goes on without the thumbnails if they cannot be encoded.
+        let thumbnail = thumbnail
+            .filter(|_| output.adapter().is_some())
+            .and_then(|options| match VideoThumbnail::new(options, &settings) {
+                Ok(it) => Some(it),
+                Err(e) => {
+                    tracing::warn!(error = ?e, "failed to create thumbnail encoder");
+
+                    None
+                }
+            });
+
+        let watermarker = watermark
+            .map(|options| {
+                Watermarker::new(WatermarkerSettings {
+                    options,
+                    #[cfg(target_os = "windows")]
+                    direct3d: settings
+                        .direct3d
+                        .clone()
+                        .expect("the video encoder settings always have a d3d device"),
+                })
+            })
+            .transpose()?;
+
+        Ok(Self {
+            content: (content_detection.is_some()
+                || scene_cut.is_some()
+                || dirty_regions.is_some())
+            .then(|| {
+                ContentMonitor::new(
+                    content_detection,
+                    scene_cut,
+                    dirty_regions,
+                    ContentDetector::new(ContentDetectorSettings {
+                        threshold: content_detection.unwrap_or_default().black_threshold,
+                        #[cfg(target_os = "windows")]
+                        direct3d: settings
+                            .direct3d
+                            .clone()
+                            .expect("the video encoder settings always have a d3d device"),
+                    }),
+                )
+            }),
+            masker: PrivacyMasker::new(PrivacyMaskerSettings {
+                #[cfg(target_os = "windows")]
+                direct3d: settings
+                    .direct3d
+                    .clone()
+                    .expect("the video encoder settings always have a d3d device"),
+            }),
+            masks: control.privacy_masks.lock().clone(),
+            display,
+            preview: VideoPreview::new(preview, &settings),
+            scaler: create_video_scaler(&settings),
+            deinterlacer: deinterlace.map(|method| {
+                Deinterlacer::new(DeinterlacerSettings {
+                    method,
+                    #[cfg(target_os = "windows")]
+                    direct3d: settings
+                        .direct3d
+                        .clone()
+                        .expect("the video encoder settings always have a d3d device"),
+                })
+            }),
+            thumbnail,
+            watermarker,
+            output: output.clone(),
+            packets: if settings.tuning.rate_control == Some(RateControl::Lossless) {
+                PacketPool::new(LOSSLESS_PACKET_POOL_SIZE)
+            } else {
+                PacketPool::default()
+            },
+            sink: Arc::downgrade(sink),
+            dump: VideoFrameDumper::default(),
+            clock: 0,
+            thread: EncodeThread::default(),
+            control,
+            settings,
+            encoder,
+            status,
+        })
+    }
+
+    /// The encoder that is actually used, which is different from the
+    /// configured encoder if it has fallen back.
+    fn codec(&self) -> VideoEncoderType {
+        self.settings.codec
+    }
+
+    // A hardware encoder may also fail while encoding, such as when the driver is
+    // reset, the stream continues with the next encoder of the fallback chain.
+    fn fallback(&mut self) -> bool {
+        let codec = if let Some(codec) = self.settings.codec.fallback() {
+            codec
+        } else {
+            return false;
+        };
+
+        let from = self.settings.codec;
+        match self.replace_encoder(codec) {
+            Ok(codec) => {
+                tracing::warn!(from = ?from, to = ?codec, "video encoder failed, fall back");
+
+                if let Some(sink) = self.sink.upgrade() {
+                    sink.event(StreamEvent::EncoderFallback { from, to: codec });
+                }
+
+                true
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to create fallback video encoder");
+
+                false
+            }
+        }
+    }
+
+    // A receiver cannot decode HEVC, the stream continues with H264. The new
+    // encoder starts with its own configuration, the receivers switch their
+    // decoders on it.
+    fn switch_to_h264(&mut self) -> bool {
+        let from = self.settings.codec;
+        match self.replace_encoder(VideoEncoderType::X264) {
+            Ok(codec) => {
+                tracing::info!(from = ?from, to = ?codec, "switch video encoder for receivers");
+
+                if let Some(sink) = self.sink.upgrade() {
+                    sink.event(StreamEvent::EncoderSwitched { from, to: codec });
+                }
+
+                if let Some(adapter) = self.output.adapter() {
+                    let metadata = self.control.metadata.lock().clone();
+                    if let Err(e) = self.control.set_metadata(adapter, metadata) {
+                        tracing::warn!(error = ?e, "failed to update stream metadata");
+                    }
+                }
+
+                true
+            }
+            Err(e) => {
+                tracing::error!(error = ?e, "failed to create h264 video encoder");
+
+                false
+            }
+        }
+    }
+
+    fn replace_encoder(
+        &mut self,
+        codec: VideoEncoderType,
+    ) -> Result<VideoEncoderType, hylarana_codec::VideoEncoderError> {
+        let (encoder, codec) = VideoEncoder::with_fallback(VideoEncoderSettings {
+            codec,
+            ..self.settings.clone()
+        })?;
+
+        // The new encoder sends its own configuration before the first key
+        // frame, the preview restarts with it.
+        self.settings.codec = codec;
+        self.scaler = create_video_scaler(&self.settings);
+        self.preview.reset(codec);
+        self.encoder = encoder;
+        Ok(codec)
+    }
+
+    fn process(&mut self, frame: &VideoFrame) -> Result<(), DisconnectReason> {
+        let _span = tracing::trace_span!(
+            "video_frame",
+            codec = ?self.settings.codec,
+            timestamp = frame.timestamp
+        )
+        .entered();
+
+        Metrics::increment(&METRICS.video_frames_captured);
+        self.control.capture.beat();
+        self.thread.enter();
+        self.dump.write(frame, self.settings.frame_rate);
+
+        send_clock(&self.output, &self.packets, &mut self.clock);
+
+        // The deinterlacer is taken out while the frame it outputs is encoded, the
+        // encoder may be replaced in the meantime.
+        if let Some(mut deinterlacer) = self.deinterlacer.take() {
+            let result = if deinterlacer.is_required(frame) {
+                let result = {
+                    let _span = tracing::trace_span!("deinterlace").entered();
+
+                    deinterlacer.process(frame)
+                };
+
+                match result {
+                    Ok(Some(frame)) => self.encode(frame),
+                    // The filter holds the first frame back.
+                    Ok(None) => Ok(()),
+                    Err(e) => {
+                        tracing::error!(error = ?e, "video deinterlace error");
+                        Metrics::increment(&METRICS.encode_errors);
+
+                        Err(DisconnectReason::Error(StreamErrorKind::Encode))
+                    }
+                }
+            } else {
+                self.encode(frame)
+            };
+
+            self.deinterlacer = Some(deinterlacer);
+            return result;
+        }
+
+        self.encode(frame)
+    }
+
+    fn encode(&mut self, frame: &VideoFrame) -> Result<(), DisconnectReason> {
+        // The encoder is switched before the frame is scaled, the scaler of the new
+        // encoder outputs its format.
+        if self.control.h264_only.get()
+            && CodecType::from(self.settings.codec).is_10bit()
+            && !self.switch_to_h264()
+        {
+            return Err(DisconnectReason::Error(StreamErrorKind::Encode));
+        }
+
+        let input = if self.scaler.is_required(frame) {
+            let _span = tracing::trace_span!("scale").entered();
+
+            match self.scaler.process(frame) {
+                Ok(it) => it,
+                Err(e) => {
+                    tracing::error!(error = ?e, "video scale error");
+                    Metrics::increment(&METRICS.encode_errors);
+
+                    return Err(DisconnectReason::Error(StreamErrorKind::Encode));
+                }
+            }
+        } else {
+            frame
+        };
+
+        // The detection only reports what the video shows, the stream goes on without
+        // it if it fails.
+        let mut damage = None;
+        if let Some(content) = self.content.as_mut() {
+            let _span = tracing::trace_span!("content_detection").entered();
+
+            match content.update(input) {
+                Ok(ContentUpdate {
+                    event,
+                    skip,
+                    key_frame,
+                    damage: areas,
+                }) => {
+                    damage = areas;
+
+                    if key_frame {
+                        tracing::debug!("video scene cut");
+
+                        self.encoder.request_key_frame();
+                    }
+
+                    if let Some(state) = event {
+                        tracing::info!(state = ?state, "video content changed");
+
+                        if let Some(sink) = self.sink.upgrade() {
+                            sink.event(StreamEvent::VideoContent { state });
+                        }
+                    }
+
+                    // The frozen frames are not encoded, the watchdog takes the skipped frames
+                    // as progress.
+                    if skip && !self.control.key_frame.get() {
+                        self.control.encode.beat();
+
+                        return Ok(());
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!(error = ?e, "video content detection error");
+
+                    self.content = None;
+                }
+            }
+        }
+
+        if self.control.privacy_changed.update(false) {
+            self.masks = self.control.privacy_masks.lock().clone();
+        }
+
+        // The frames that cannot be masked are not sent.
+        let input = if let Some(options) = self.masks.as_ref().filter(|it| !it.masks.is_empty()) {
+            let _span = tracing::trace_span!("privacy_mask").entered();
+
+            let areas = privacy_mask_areas(
+                &options.masks,
+                self.display.as_deref(),
+                input.width,
+                input.height,
+            );
+
+            match self.masker.process(input, &areas, options.style) {
+                Ok(it) => it,
+                Err(e) => {
+                    tracing::error!(error = ?e, "video privacy mask error");
+                    Metrics::increment(&METRICS.encode_errors);
+
+                    return Err(DisconnectReason::Error(StreamErrorKind::Encode));
+                }
+            }
+        } else {
+            input
+        };
+
+        // The frames that cannot be watermarked are not sent.
+        let input = if let Some(watermarker) = self.watermarker.as_mut() {
+            let _span = tracing::trace_span!("watermark").entered();
+
+            match watermarker.process(input) {
+                Ok(it) => it,
+                Err(e) => {
+                    tracing::error!(error = ?e, "video watermark error");
+                    Metrics::increment(&METRICS.encode_errors);
+
+                    return Err(DisconnectReason::Error(StreamErrorKind::Encode));
+                }
+            }
+        } else {
+            input
+        };
+
+        // The thumbnails are taken from the frames that are encoded, so they are
+        // watermarked too.
+        if let (Some(thumbnail), Some(adapter)) = (self.thumbnail.as_mut(), self.output.adapter()) {
+            thumbnail.publish(adapter, &self.packets, input);
+        }
+
+        if self.control.key_frame.update(false) {
+            self.encoder.request_key_frame();
+        }
+
+        self.encoder.set_damage(damage.as_deref());
+
+        // Push the audio and video frames into the encoder.
+        let encoded = {
+            let _span = tracing::trace_span!("encode").entered();
+
+            if !self.encoder.update(input) {
+                tracing::warn!("video encoder update frame failed");
+
+                false
+            } else if let Err(e) = self.encoder.encode() {
+                tracing::error!(error = ?e, "video encode error");
+
+                false
+            } else {
+                true
+            }
+        };
+
+        if encoded {
+            Metrics::increment(&METRICS.video_frames_encoded);
+
+            let _span = tracing::trace_span!("send").entered();
+
+            // Try to get the encoded data packets. The audio and video frames do not
+            // correspond to the data packets one by one, so you need to try to get
+            // multiple packets until they are empty.
+            while let Some((buffer, flags, timestamp)) = self.encoder.read() {
+                self.preview.push(buffer, flags, timestamp, frame);
+                self.control.encode.beat();
+
+                Metrics::increment(&METRICS.packets_sent);
+                Metrics::add(&METRICS.bytes_sent, buffer.len() as u64);
+                tracing::trace!(size = buffer.len(), flags, timestamp, "video packet");
+
+                // The orientation of the picture is not encoded, it is carried in the
+                // high bits of the flags.
+                if !self.output.send(
+                    &self.packets,
+                    buffer,
+                    StreamBufferInfo::Video(
+                        BufferFlag::with_orientation(flags, frame.rotation, frame.mirror),
+                        timestamp,
+                    ),
+                ) {
+                    tracing::warn!("video send packet to output failed");
+
+                    return Err(self.output.closed_reason());
+                }
+            }
+
+            if let Some(adapter) = self.output.adapter() {
+                METRICS.send_queue.update(adapter.pending() as u64);
+            }
+        } else {
+            Metrics::increment(&METRICS.encode_errors);
+
+            // The frames of the capture are on the removed device too, so only
+            // restarting the whole pipeline helps, the frames are dropped until then.
+            #[cfg(target_os = "windows")]
+            if self
+                .settings
+                .direct3d
+                .as_ref()
+                .map(|it| it.is_removed())
+                .unwrap_or(false)
+            {
+                if !self.control.device_removed.update(true) {
+                    tracing::warn!("d3d device of the video encoder has been removed");
+                }
+
+                return Ok(());
+            }
+
+            if !self.fallback() {
+                return Err(DisconnectReason::Error(StreamErrorKind::Encode));
+            }
+        }
+
+        if let Some(sink) = self.sink.upgrade() {
+            if sink.video(frame) {
+                Ok(())
+            } else {
+                tracing::warn!("video sink on frame return false");
+
+                Err(DisconnectReason::SinkClosed)
+            }
+        } else {
+            tracing::warn!("video sink weak upgrade failed, maybe is drop");
+
+            Err(DisconnectReason::Closed)
+        }
+    }
+}
+
+// The receiver measures the latency of the frames with the clock of the sender,
+// which is sent periodically in the video stream.
+pub(crate) fn send_clock(output: &PacketOutput, packets: &PacketPool, clock: &mut u64) {
+    let Some(adapter) = output.adapter() else {
+        return;
+    };
+
+    if MediaClock::elapsed(*clock) >= CLOCK_INTERVAL {
+        *clock = MediaClock::now();
+
+        let control = StreamControl::Clock {
+            media: *clock,
+            system: MediaClock::system(),
+        };
+
+        if !adapter.send(
+            packets.copy_from_slice(&control.as_payload()),
+            StreamBufferInfo::Video(BufferFlag::CONTROL, 0),
+        ) {
+            tracing::warn!("send stream clock to adapter failed");
+        }
+    }
+}
+
+// Encodes a frame of the source into a thumbnail once per interval, the
+// thumbnails go to the receivers as control messages.
+struct VideoThumbnail {
+    encoder: ThumbnailEncoder,
+    interval: Duration,
+    sent: Option<Instant>,
+}
+
+impl VideoThumbnail {
+    fn new(
+        options: ThumbnailOptions,
+        #[allow(unused_variables)] settings: &VideoEncoderSettings,
+    ) -> Result<Self, hylarana_codec::ThumbnailEncoderError> {
+        Ok(Self {
+            encoder: ThumbnailEncoder::new(ThumbnailEncoderSettings {
+                width: options.width,
+                quality: options.quality,
+                #[cfg(target_os = "windows")]
+                direct3d: settings
+                    .direct3d
+                    .clone()
+                    .expect("the video encoder settings always have a d3d device"),
+            })?,
+            interval: options.interval,
+            sent: None,
+        })
+    }
+
+    fn publish(&mut self, adapter: &StreamSenderAdapter, packets: &PacketPool, frame: &VideoFrame) {
+        if self
+            .sent
+            .map(|it| it.elapsed() < self.interval)
+            .unwrap_or(false)
+        {
+            return;
+        }
+
+        self.sent = Some(Instant::now());
+
+        let _span = tracing::trace_span!("thumbnail").entered();
+
+        match self.encoder.encode(frame) {
+            Ok(image) => {
+                let control = StreamControl::Thumbnail(Bytes::copy_from_slice(image));
+                if !adapter.send(
+                    packets.copy_from_slice(&control.as_payload()),
+                    StreamBufferInfo::Video(BufferFlag::CONTROL, 0),
+                ) {
+                    tracing::warn!("send thumbnail to adapter failed");
+                }
+            }
+            Err(e) => tracing::warn!(error = ?e, "thumbnail encode error"),
+        }
+    }
+}
+
+// The source may deliver frames of a different size or format than the encoder,
+// these frames go through the scaler first.
+fn create_video_scaler(settings: &VideoEncoderSettings) -> VideoScaler {
+    let kind = CodecType::from(settings.codec);
+
+    VideoScaler::new(VideoScalerSettings {
+        size: Size {
+            width: settings.width,
+            height: settings.height,
+        },
+        format: if kind.is_10bit() {
+            VideoFormat::P010
+        } else {
+            VideoFormat::NV12
+        },
+        hardware: kind.is_hardware(),
+        content_hint: settings.tuning.content_hint,
+        #[cfg(target_os = "windows")]
+        direct3d: settings
+            .direct3d
+            .clone()
+            .expect("the video encoder settings always have a d3d device"),
+    })
+}
+
+impl<T: AVFrameStream + 'static> FrameArrived for VideoSender<T> {
+    type Frame = VideoFrame;
+
+    fn sink(&mut self, frame: &Self::Frame) -> bool {
+        if let Err(reason) = self.process(frame) {
+            if let Some(sink) = self.sink.upgrade() {
+                close_stream(&self.status, sink.as_ref(), reason);
+            }
+
+            false
+        } else {
+            true
+        }
+    }
+
+    fn event(&mut self, event: SourceEvent) {
+        tracing::info!(event = ?event, "video capture source event");
+
+        // The resolution change is handled inside the capture, and the frames keep the
+        // configured size, only the removal of the source ends the stream.
+        if event == SourceEvent::Removed {
+            if let Some(sink) = self.sink.upgrade() {
+                close_stream(&self.status, sink.as_ref(), DisconnectReason::SourceRemoved);
+            }
+        }
+    }
+}
+
+pub(crate) struct AudioSender<T: AVFrameStream + 'static> {
+    output: PacketOutput,
+    packets: PacketPool,
+    status: Arc<StreamStatus>,
+    // `None` for the shared memory transport, the samples are sent as they are.
+    encoder: Option<AudioEncoder>,
+    chunk_count: usize,
+    buffer: BytesMut,
+    sink: Weak<T>,
+    dtx: Option<SilenceDetector>,
+    meter: AudioMeter,
+    dump: AudioFrameDumper,
+    // Whether the sender has stopped sending packets during silence.
+    muted: bool,
+    thread: EncodeThread,
+}
+
+// Tells whether the audio has stayed below the threshold for longer than the
+// hangover.
+struct SilenceDetector {
+    options: DtxOptions,
+    silence: Duration,
+}
+
+impl SilenceDetector {
+    // The samples are the bytes of a chunk of the encoder.
+    fn is_silent(&mut self, samples: &[u8], duration: Duration) -> bool {
+        let count = samples.len() / size_of::<i16>();
+        if count == 0 {
+            return false;
+        }
+
+        let power = samples
+            .chunks_exact(size_of::<i16>())
+            .map(|it| {
+                let sample = i16::from_ne_bytes([it[0], it[1]]) as f64 / i16::MAX as f64;
+                sample * sample
+            })
+            .sum::<f64>()
+            / count as f64;
+
+        if 10.0 * power.max(1e-10).log10() >= self.options.threshold as f64 {
+            self.silence = Duration::ZERO;
+            return false;
+        }
+
+        self.silence += duration;
+        self.silence > self.options.hangover
+    }
+}
+
+// Encoding is a relatively complex task. If you add encoding tasks to the
+// pipeline that pushes frames, it will slow down the entire pipeline.
+//
+// Here, the tasks are separated, and the encoding tasks are separated into
+// independent threads. The encoding thread is notified of task updates through
+// the optional lock.
+impl<T: AVFrameStream + 'static> AudioSender<T> {
+    fn new(
+        status: Arc<StreamStatus>,
+        output: &PacketOutput,
+        settings: AudioEncoderSettings,
+        dtx: Option<DtxOptions>,
+        sink: &Arc<T>,
+    ) -> Result<Self, HylaranaSenderError> {
+        let packets = PacketPool::new(256 * 1024);
+
+        // Create an opus header data. The opus decoder needs this data to obtain audio
+        // information. Here, actively add an opus header information to the queue, and
+        // the adapter layer will automatically cache it.
+        output.send(
+            &packets,
+            &if output.is_raw() {
+                RAW_AUDIO_CONFIG.to_vec()
+            } else {
+                create_opus_identification_header(1, settings.sample_rate as u32)
+            },
+            StreamBufferInfo::Audio(BufferFlag::Config as i32, 0),
+        );
+
+        Ok(AudioSender {
+            chunk_count: settings.sample_rate as usize / 1000 * 100,
+            encoder: if output.is_raw() {
+                None
+            } else {
+                Some(AudioEncoder::new(settings)?)
+            },
+            buffer: BytesMut::with_capacity(48000),
+            sink: Arc::downgrade(sink),
+            dtx: dtx.map(|options| SilenceDetector {
+                silence: Duration::ZERO,
+                options,
+            }),
+            output: output.clone(),
+            dump: AudioFrameDumper::default(),
+            meter: AudioMeter::new(),
+            muted: false,
+            thread: EncodeThread::default(),
+            packets,
+            status,
+        })
+    }
+
+    fn process(&mut self, frame: &AudioFrame) -> Result<(), DisconnectReason> {
+        self.dump.write(frame);
+
+        if self.encoder.is_some() {
+            self.thread.enter();
+            self.encode(frame)?;
+        } else {
+            pack_audio_frame(frame, &mut self.buffer);
+
+            if !self.output.send(
+                &self.packets,
+                &self.buffer,
+                StreamBufferInfo::Audio(0, frame.timestamp),
+            ) {
+                tracing::warn!("audio send frame to output failed");
+
+                return Err(self.output.closed_reason());
+            }
+        }
+
+        if let Some(sink) = self.sink.upgrade() {
+            if let Some(levels) = self.meter.push(frame) {
+                sink.audio_levels(levels);
+            }
+
+            if sink.audio(frame) {
+                Ok(())
+            } else {
+                tracing::warn!("audio sink on frame return false");
+
+                Err(DisconnectReason::SinkClosed)
+            }
+        } else {
+            tracing::warn!("audio sink weak upgrade failed, maybe is drop");
+
+            Err(DisconnectReason::Closed)
+        }
+    }
+
+    fn encode(&mut self, frame: &AudioFrame) -> Result<(), DisconnectReason> {
+        let Some(encoder) = self.encoder.as_mut() else {
+            return Ok(());
+        };
+
+        self.buffer.extend_from_slice(unsafe {
+            std::slice::from_raw_parts(
+                frame.data as *const _,
+                frame.frames as usize * size_of::<i16>(),
+            )
+        });
+
+        if self.buffer.len() >= self.chunk_count * 2 {
+            // The chunk starts with the oldest sample in the buffer, its capture time is
+            // the end time of the current frame minus the duration of the buffered
+            // samples.
+            let timestamp = if frame.timestamp > 0 && frame.sample_rate > 0 {
+                let duration =
+                    |samples: usize| samples as u64 * 1_000_000 / frame.sample_rate as u64;
+
+                (frame.timestamp + duration(frame.frames as usize))
+                    .saturating_sub(duration(self.buffer.len() / size_of::<i16>()))
+            } else {
+                0
+            };
+
+            let payload = self.buffer.split_to(self.chunk_count * size_of::<i16>());
+            let silent = self
+                .dtx
+                .as_mut()
+                .map(|it| {
+                    it.is_silent(
+                        &payload,
+                        Duration::from_micros(
+                            self.chunk_count as u64 * 1_000_000 / frame.sample_rate.max(1) as u64,
+                        ),
+                    )
+                })
+                .unwrap_or(false);
+
+            if silent != self.muted {
+                tracing::debug!(silent, "audio sender silence changed");
+            }
+            let frame = AudioFrame {
+                data: payload.as_ptr() as *const _,
+                frames: self.chunk_count as u32,
+                sample_rate: 0,
+                timestamp,
+            };
+
+            let _span = tracing::trace_span!("audio_frame", timestamp).entered();
+
+            if encoder.update(&frame) {
+                // Push the audio and video frames into the encoder.
+                if let Err(e) = encoder.encode() {
+                    tracing::error!(error = ?e, "audio encode error");
+                    Metrics::increment(&METRICS.encode_errors);
+
+                    return Err(DisconnectReason::Error(StreamErrorKind::Encode));
+                } else {
+                    // Try to get the encoded data packets. The audio and video frames
+                    // do not correspond to the data
+                    // packets one by one, so you need to try to get
+                    // multiple packets until they are empty.
+                    Metrics::increment(&METRICS.audio_frames_encoded);
+
+                    while let Some((buffer, flags, timestamp)) = encoder.read() {
+                        // The encoder keeps running during silence, so that it
+                        // picks up where the audio resumes, only the packets are
+                        // dropped. The last packet before the gap tells the
+                        // receiver to fill it.
+                        if self.muted {
+                            continue;
+                        }
+
+                        let flags = if silent {
+                            flags | BufferFlag::SILENCE
+                        } else {
+                            flags
+                        };
+
+                        Metrics::increment(&METRICS.packets_sent);
+                        Metrics::add(&METRICS.bytes_sent, buffer.len() as u64);
+
+                        if !self.output.send(
+                            &self.packets,
+                            buffer,
+                            StreamBufferInfo::Audio(flags, timestamp),
+                        ) {
+                            tracing::warn!("audio send packet to output failed");
+
+                            return Err(self.output.closed_reason());
+                        }
+                    }
+
+                    self.muted = silent;
+                }
+            } else {
+                tracing::warn!("audio encoder update frame failed");
+                Metrics::increment(&METRICS.encode_errors);
+
+                return Err(DisconnectReason::Error(StreamErrorKind::Encode));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: AVFrameStream + 'static> FrameArrived for AudioSender<T> {
+    type Frame = AudioFrame;
+
+    fn sink(&mut self, frame: &Self::Frame) -> bool {
+        if let Err(reason) = self.process(frame) {
+            if let Some(sink) = self.sink.upgrade() {
+                close_stream(&self.status, sink.as_ref(), reason);
+            }
+
+            false
+        } else {
+            true
+        }
+    }
+}
+
+// The video of the shared memory transport does not go through the encoder.
+enum VideoArrived<T: AVFrameStream + 'static> {
+    Encoder(VideoSender<T>),
+    Raw(RawVideoSender<T>),
+}
+
+impl<T: AVFrameStream + 'static> FrameArrived for VideoArrived<T> {
+    type Frame = VideoFrame;
+
+    fn sink(&mut self, frame: &Self::Frame) -> bool {
+        match self {
+            Self::Encoder(it) => it.sink(frame),
+            Self::Raw(it) => it.sink(frame),
+        }
+    }
+
+    fn event(&mut self, event: SourceEvent) {
+        match self {
+            Self::Encoder(it) => it.event(event),
+            Self::Raw(it) => it.event(event),
+        }
+    }
+}
+
+pub(crate) fn start_capture<T: AVFrameStream + 'static>(
+    options: &HylaranaSenderMediaOptions,
+    audio_inputs: &[AudioMixerInput],
+    preview: &PreviewSink,
+    control: &Arc<EncoderControl>,
+    output: &PacketOutput,
+    status: &Arc<StreamStatus>,
+    sink: &Arc<T>,
+) -> Result<(Capture, Option<AudioMixer<AudioSender<T>>>), HylaranaSenderError> {
+    let mut capture_options = CaptureOptions::default();
+    let mut mixer = None;
+
+    if let Some(first) = options.audio.first() {
+        let sample_rate = first.options.sample_rate;
+        let audio_mixer = AudioMixer::new(
+            AudioSender::new(
+                status.clone(),
+                output,
+                AudioEncoderSettings {
+                    bit_rate: first.options.bit_rate,
+                    sample_rate,
+                },
+                first.options.dtx,
+                sink,
+            )?,
+            audio_inputs,
+        );
+
+        // All tracks are resampled to the sample rate of the encoder.
+        for (index, track) in options.audio.iter().enumerate() {
+            capture_options.audio.push(SourceCaptureOptions {
+                arrived: audio_mixer.track(index),
+                description: AudioCaptureSourceDescription {
+                    sample_rate: sample_rate as u32,
+                    source: track.source.clone(),
+                    config: track.options.capture,
+                },
+            });
+        }
+
+        mixer = Some(audio_mixer);
+    }
+
+    if let Some(HylaranaSenderTrackOptions {
+        source,
+        options: video,
+    }) = options.video.clone()
+    {
+        // The frames of the capture go to the encoder as textures of the device.
+        #[cfg(target_os = "windows")]
+        let direct3d = options.graphics.direct3d();
+
+        // The raw frames are copied from the memory, there is no encoder.
+        let (arrived, hardware, hdr) = if output.is_raw() {
+            (
+                VideoArrived::Raw(RawVideoSender::new(
+                    status.clone(),
+                    output,
+                    control.clone(),
+                    sink,
+                )),
+                false,
+                false,
+            )
+        } else {
+            let display = (source.kind == SourceType::Screen).then(|| source.id.clone());
+            let arrived = VideoSender::new(
+                status.clone(),
+                output,
+                VideoEncoderSettings {
+                    codec: video.codec,
+                    key_frame_interval: video.key_frame_interval,
+                    frame_rate: video.frame_rate,
+                    width: video.width,
+                    height: video.height,
+                    bit_rate: video.bit_rate,
+                    tuning: video.tuning,
+                    scene_cut: video.scene_cut.is_some(),
+                    #[cfg(target_os = "windows")]
+                    direct3d: Some(direct3d.clone()),
+                },
+                preview.clone(),
+                control.clone(),
+                video.thumbnail,
+                video.deinterlace,
+                video.watermark,
+                display,
+                video.content_detection,
+                video.scene_cut,
+                video.dirty_regions,
+                sink,
+            )?;
+
+            // The capture outputs the frames for the encoder that is actually used,
+            // which may be a software encoder after falling back.
+            let codec = CodecType::from(arrived.codec());
+            (
+                VideoArrived::Encoder(arrived),
+                codec.is_hardware(),
+                codec.is_10bit(),
+            )
+        };
+
+        capture_options.video = Some(SourceCaptureOptions {
+            description: VideoCaptureSourceDescription {
+                hardware,
+                hdr,
+                fps: video.frame_rate,
+                adaptive_pacing: video.adaptive_pacing,
+                size: video.capture_size.unwrap_or(Size {
+                    width: video.width,
+                    height: video.height,
+                }),
+                source,
+                #[cfg(target_os = "windows")]
+                direct3d,
+            },
+            arrived,
+        });
+    }
+
+    let capture = Capture::start(capture_options)?;
+
+    // The watchdog counts the time from the start, the stages have not made any
+    // progress yet.
+    if options.video.is_some() {
+        control.capture.beat();
+        control.encode.beat();
+    }
+
+    Ok((capture, mixer))
+}
+
+fn complete_metadata(
+    mut metadata: StreamMetadata,
+    options: &HylaranaSenderMediaOptions,
+) -> StreamMetadata {
+    if let Some(video) = options.video.as_ref() {
+        if metadata.width == 0 || metadata.height == 0 {
+            metadata.width = video.options.width;
+            metadata.height = video.options.height;
+        }
+    }
+
+    if metadata.codecs.is_empty() {
+        if let Some(video) = options.video.as_ref() {
+            metadata
+                .codecs
+                .push(video_codec_name(video.options.codec).to_string());
+        }
+
+        if !options.audio.is_empty() {
+            metadata.codecs.push("opus".to_string());
+        }
+    }
+
+    // The sender can always fall back to the software H264 encoder.
+    if metadata.capabilities == StreamCapabilities::default() {
+        let mut codecs = Vec::with_capacity(3);
+        if let Some(video) = options.video.as_ref() {
+            codecs.push("h264".to_string());
+
+            if CodecType::from(video.options.codec).is_10bit() {
+                codecs.push("hevc".to_string());
+            }
+        }
+
+        if !options.audio.is_empty() {
+            codecs.push("opus".to_string());
+        }
+
+        metadata.capabilities = StreamCapabilities {
+            features: StreamFeature::ALL.to_vec(),
+            codecs,
+        };
+    }
+
+    metadata
+}
+
+// The capture runs in this process, or in a helper process in the sandbox mode.
+enum PipelineCapture {
+    Local(Capture),
+    Sandboxed(CaptureProcess),
+}
+
+impl PipelineCapture {
+    fn close(&self) -> Result<(), HylaranaSenderError> {
+        match self {
+            Self::Local(capture) => capture.close()?,
+            Self::Sandboxed(process) => {
+                process.close().map_err(HylaranaSenderError::SandboxError)?
+            }
+        }
+
+        Ok(())
+    }
+
+    // How the helper process has ended, the local capture ends with the stream.
+    fn exited(&self) -> Option<HelperExit> {
+        match self {
+            Self::Local(_) => None,
+            Self::Sandboxed(process) => process.exited(),
+        }
+    }
+}
+
+// The capture and the encoders of a sender, they are created again when the
+// sender resumes, or when the watchdog finds them stalled.
+struct SenderPipeline<T: AVFrameStream + 'static> {
+    media: HylaranaSenderMediaOptions,
+    // The capture is `None` while the sender is paused.
+    capture: Mutex<Option<PipelineCapture>>,
+    // The settings of the audio tracks are kept when the capture is restarted.
+    audio_inputs: Mutex<Vec<AudioMixerInput>>,
+    mixer: Mutex<Option<AudioMixer<AudioSender<T>>>>,
+    preview: PreviewSink,
+    control: Arc<EncoderControl>,
+    output: PacketOutput,
+    status: Arc<StreamStatus>,
+    sink: Arc<T>,
+    #[cfg(target_os = "windows")]
+    system: Mutex<SystemPause>,
+    // The encode heartbeat when the helper process last crashed.
+    helper_crash: Mutex<Option<u64>>,
+    energy_saver: Option<EnergySaverOptions>,
+    // The pacing of the transport, which is restored when the energy saver is
+    // turned off.
+    pacing: Option<PacketPacingOptions>,
+    // The system runs on battery and the energy saver is on.
+    battery: AtomicBool,
+}
+
+// The states of the system that the capture cannot continue through.
+#[cfg(target_os = "windows")]
+#[derive(Default)]
+struct SystemPause {
+    locked: bool,
+    suspended: bool,
+    // The sender resumes when the states are over.
+    resume: bool,
+}
+
+#[cfg(target_os = "windows")]
+impl SystemPause {
+    fn is_active(&self) -> bool {
+        self.locked || self.suspended
+    }
+}
+
+impl<T: AVFrameStream + 'static> SenderPipeline<T> {
+    // The options of the media, with the options of the energy saver applied
+    // while it is on.
+    fn media(&self) -> HylaranaSenderMediaOptions {
+        let mut media = self.media.clone();
+        if let Some(options) = self.energy_saver.filter(|_| self.battery.get()) {
+            if let Some(video) = media.video.as_mut() {
+                video.options.frame_rate = video.options.frame_rate.min(options.frame_rate);
+
+                if options.hardware_encoder {
+                    if let Some(codec) = video.options.codec.hardware() {
+                        video.options.codec = codec;
+                    }
+                }
+            }
+        }
+
+        media
+    }
+
+    fn start(&self, capture: &mut Option<PipelineCapture>) -> Result<(), HylaranaSenderError> {
+        let media = self.media();
+
+        // The raw frames of the shared memory transport cannot be passed out of the
+        // helper, there is nothing to encode anyway.
+        if let Some(sandbox) = media.sandbox.as_ref().filter(|_| !self.output.is_raw()) {
+            capture.replace(PipelineCapture::Sandboxed(CaptureProcess::spawn(
+                sandbox,
+                &media,
+                &self.audio_inputs.lock(),
+                &self.control,
+                &self.output,
+            )?));
+
+            return Ok(());
+        }
+
+        let (it, mixer) = start_capture(
+            &media,
+            &self.audio_inputs.lock(),
+            &self.preview,
+            &self.control,
+            &self.output,
+            &self.status,
+            &self.sink,
+        )?;
+
+        capture.replace(PipelineCapture::Local(it));
+        *self.mixer.lock() = mixer;
+        Ok(())
+    }
+
+    // The receivers keep the last frame while the sender is paused.
+    fn pause(&self) -> Result<bool, HylaranaSenderError> {
+        let Some(capture) = self.capture.lock().take() else {
+            return Ok(false);
+        };
+
+        self.stop(capture)?;
+        self.send_control(StreamControl::Pause);
+        Ok(true)
+    }
+
+    // The capture and the encoders are created again, so the stream starts with a
+    // key frame.
+    fn resume(&self) -> Result<bool, HylaranaSenderError> {
+        let mut capture = self.capture.lock();
+        if capture.is_some() {
+            return Ok(false);
+        }
+
+        self.send_control(StreamControl::Resume);
+        self.start(&mut capture)?;
+        Ok(true)
+    }
+
+    fn send_control(&self, control: StreamControl) -> bool {
+        let Some(adapter) = self.output.adapter() else {
+            return true;
+        };
+
+        if !adapter.send(
+            package_copy_from_slice(&control.as_payload()),
+            StreamBufferInfo::Video(BufferFlag::CONTROL, 0),
+        ) {
+            tracing::warn!(control = ?control, "send stream control to adapter failed");
+
+            return false;
+        }
+
+        true
+    }
+
+    // Pause while the session is locked or the system is suspended, and resume
+    // when both are over, unless the user has paused the sender in the meantime.
+    #[cfg(target_os = "windows")]
+    fn on_system_event(&self, event: SystemEvent) {
+        tracing::info!(event = ?event, "sender system event");
+
+        let mut system = self.system.lock();
+        let active = system.is_active();
+        match event {
+            SystemEvent::Locked => system.locked = true,
+            SystemEvent::Unlocked => system.locked = false,
+            SystemEvent::Suspended => system.suspended = true,
+            SystemEvent::Resumed => system.suspended = false,
+        }
+
+        let result = if !active && system.is_active() {
+            self.pause().map(|paused| {
+                if paused {
+                    system.resume = true;
+
+                    self.sink.event(StreamEvent::Paused {
+                        reason: if system.locked {
+                            PauseReason::SystemLock
+                        } else {
+                            PauseReason::SystemSuspend
+                        },
+                    });
+                }
+            })
+        } else if active && !system.is_active() && std::mem::take(&mut system.resume) {
+            self.resume().map(|resumed| {
+                if resumed {
+                    self.sink.event(StreamEvent::Resumed);
+                }
+            })
+        } else {
+            Ok(())
+        };
+
+        if let Err(e) = result {
+            tracing::error!(error = ?e, "failed to pause or resume the sender for the system");
+
+            close_stream(
+                &self.status,
+                self.sink.as_ref(),
+                DisconnectReason::Error(StreamErrorKind::Capture),
+            );
+        }
+    }
+
+    // Turn the energy saver on while the system runs on battery, and off when it
+    // is plugged in.
+    fn on_power_source(&self, source: PowerSource) {
+        let Some(options) = self.energy_saver else {
+            return;
+        };
+
+        let enabled = source == PowerSource::Battery;
+        if self.battery.update(enabled) == enabled {
+            return;
+        }
+
+        tracing::info!(enabled, "sender energy saver");
+
+        if options.pacing.is_some() {
+            if let Some(adapter) = self.output.adapter() {
+                adapter.set_pacing(if enabled { options.pacing } else { self.pacing });
+            }
+        }
+
+        if let Err(e) = self.restart() {
+            tracing::error!(error = ?e, "failed to restart the sender for the energy saver");
+
+            close_stream(
+                &self.status,
+                self.sink.as_ref(),
+                DisconnectReason::Error(StreamErrorKind::Capture),
+            );
+
+            return;
+        }
+
+        self.sink.event(StreamEvent::EnergySaver { enabled });
+    }
+
+    fn stop(&self, capture: PipelineCapture) -> Result<(), HylaranaSenderError> {
+        capture.close()?;
+        self.mixer.lock().take();
+
+        self.control.capture.stop();
+        self.control.encode.stop();
+        Ok(())
+    }
+
+    // Create the capture and the encoders again, such as the duplication of the
+    // display and the session of the hardware encoder.
+    fn restart(&self) -> Result<(), HylaranaSenderError> {
+        let mut capture = self.capture.lock();
+        if let Some(it) = capture.take() {
+            self.stop(it)?;
+            self.start(&mut capture)?;
+        }
+
+        Ok(())
+    }
+
+    // Called by the watchdog. The encoder runs on the thread of the capture, a
+    // hung encoder stops the capture too, but after the capture has delivered a
+    // frame, which tells the stages apart.
+    fn watch(&self, state: &mut StallState) -> bool {
+        if self.status.is_closed() {
+            return false;
+        }
+
+        let exited = match self.capture.lock().as_ref() {
+            Some(capture) => capture.exited(),
+            None => {
+                *state = StallState::default();
+                return true;
+            }
+        };
+
+        if let Some(exit) = exited {
+            return self.helper_exited(exit, state);
+        }
+
+        if self.control.device_removed.update(false) {
+            tracing::warn!("restart the sender pipeline on a new d3d device");
+
+            if let Err(e) = self.restart() {
+                tracing::error!(error = ?e, "failed to restart the sender pipeline");
+
+                close_stream(
+                    &self.status,
+                    self.sink.as_ref(),
+                    DisconnectReason::Error(StreamErrorKind::Encode),
+                );
+
+                return false;
+            }
+
+            *state = StallState::default();
+            return true;
+        }
+
+        let stage = if self.control.capture.last() > self.control.encode.last() {
+            PipelineStage::Encode
+        } else {
+            PipelineStage::Capture
+        };
+
+        match state.check(&self.control.encode) {
+            Verdict::Healthy => true,
+            Verdict::Recover => {
+                tracing::warn!(stage = ?stage, "sender pipeline is stalled, restart the capture");
+
+                if let Err(e) = self.restart() {
+                    tracing::error!(error = ?e, "failed to restart the sender pipeline");
+
+                    return self.stalled(stage);
+                }
+
+                state.recovered(&self.control.encode);
+                true
+            }
+            Verdict::Failed => self.stalled(stage),
+        }
+    }
+
+    // A crashed helper is started again, unless it has not encoded anything since
+    // it last crashed, the other exits end the stream.
+    fn helper_exited(&self, exit: HelperExit, state: &mut StallState) -> bool {
+        let reason = match exit {
+            HelperExit::Crashed => {
+                let encoded = self.control.encode.last();
+                if self.helper_crash.lock().replace(encoded) == Some(encoded) {
+                    tracing::error!("capture helper has crashed again without progress");
+
+                    DisconnectReason::Error(StreamErrorKind::Capture)
+                } else {
+                    tracing::warn!("capture helper has crashed, restart it");
+
+                    if let Err(e) = self.restart() {
+                        tracing::error!(error = ?e, "failed to restart the capture helper");
+
+                        DisconnectReason::Error(StreamErrorKind::Capture)
+                    } else {
+                        *state = StallState::default();
+                        return true;
+                    }
+                }
+            }
+            HelperExit::SourceRemoved => DisconnectReason::SourceRemoved,
+            HelperExit::Error(kind) => DisconnectReason::Error(kind),
+            HelperExit::OutputClosed => self.output.closed_reason(),
+        };
+
+        close_stream(&self.status, self.sink.as_ref(), reason);
+        false
+    }
+
+    fn stalled(&self, stage: PipelineStage) -> bool {
+        tracing::error!(stage = ?stage, "sender pipeline cannot be recovered");
+
+        self.sink.event(StreamEvent::Stalled { stage });
+        close_stream(
+            &self.status,
+            self.sink.as_ref(),
+            DisconnectReason::Error(if stage == PipelineStage::Capture {
+                StreamErrorKind::Capture
+            } else {
+                StreamErrorKind::Encode
+            }),
+        );
+
+        false
+    }
+}
+
+/// Screen casting sender.
+pub struct HylaranaSender<T: AVFrameStream + 'static> {
+    transport: TransportSender,
+    pipeline: Arc<SenderPipeline<T>>,
+    input: Arc<AtomicBool>,
+    #[allow(dead_code)]
+    watchdog: Watchdog,
+    #[cfg(target_os = "windows")]
+    #[allow(dead_code)]
+    system_events: Option<SystemEventListener>,
+    #[allow(dead_code)]
+    power_monitor: Option<PowerMonitor>,
+}
+
+impl<T: AVFrameStream + 'static> HylaranaSender<T> {
+    // Create a sender. The capture of the sender is started following the sender,
+    // but both video capture and audio capture can be empty, which means you can
+    // create a sender that captures nothing.
+    pub(crate) fn new(
+        mut options: HylaranaSenderOptions,
+        sink: T,
+    ) -> Result<Self, HylaranaSenderError> {
+        tracing::info!("create sender");
+
+        if is_lossless(&options) && options.transport.latency.is_none() {
+            tracing::info!("lossless video, transport latency={:?}", LOSSLESS_LATENCY);
+
+            options.transport.latency = Some(LOSSLESS_LATENCY);
+        }
+
+        let transport = match options.id.clone() {
+            Some(id) => hylarana_transport::create_sender_with_id(id, options.transport)?,
+            None => hylarana_transport::create_sender(options.transport)?,
+        };
+
+        let output = if matches!(
+            options.transport.strategy,
+            TransportStrategy::SharedMemory(_)
+        ) {
+            PacketOutput::Raw(transport.get_adapter())
+        } else {
+            let (width, height) = options
+                .media
+                .video
+                .as_ref()
+                .map(|it| (it.options.width, it.options.height))
+                .unwrap_or_default();
+
+            PacketOutput::Transport(transport.get_adapter(), PacketTap::new(width, height))
+        };
+
+        let status = StreamStatus::new();
+        let input = Arc::new(AtomicBool::new(false));
+        let control: Arc<EncoderControl> = Default::default();
+        let sink = Arc::new(sink);
+
+        if let Some(video) = options.media.video.as_ref() {
+            control.set_privacy_masks(video.options.privacy_masks.clone());
+        }
+
+        // The metadata is set before the back channel is opened, the receivers
+        // are negotiated against the capabilities in it.
+        let metadata = complete_metadata(options.metadata, &options.media);
+        *control.capabilities.lock() = metadata.capabilities.clone();
+        control.set_metadata(&transport.get_adapter(), metadata)?;
+
+        {
+            // The back channel of multicast is a plain UDP socket that any host on the
+            // network can send to, only the key frame requests are accepted from it,
+            // which do no more than a receiver joining the stream.
+            let multicast = matches!(options.transport.strategy, TransportStrategy::Multicast(_));
+
+            let input = input.clone();
+            let control = control.clone();
+            let requested: Mutex<Option<Instant>> = Mutex::new(None);
+            let sink = Arc::downgrade(&sink);
+            if let Err(e) = transport.on_message(move |message| {
+                if let Some((&kind, payload)) = message.split_first() {
+                    if multicast && kind != MessageKind::KeyFrame as u8 {
+                        return;
+                    }
+
+                    if kind == MessageKind::Data as u8 {
+                        if let Some(sink) = sink.upgrade() {
+                            sink.message(payload);
+                        }
+                    } else if kind == MessageKind::Input as u8 && input.get() {
+                        if let Some(event) = InputEvent::decode(payload) {
+                            if let Err(e) = Capture::inject_input(&event) {
+                                tracing::warn!(event = ?event, error = ?e, "failed to inject input");
+                            }
+                        } else {
+                            tracing::warn!("received an invalid input event");
+                        }
+                    } else if kind == MessageKind::KeyFrame as u8 {
+                        // All receivers share the stream, the requests that arrive within
+                        // the interval are already served by the previous key frame.
+                        let mut requested = requested.lock();
+                        if requested
+                            .map(|it| it.elapsed() >= KEY_FRAME_REQUEST_INTERVAL)
+                            .unwrap_or(true)
+                        {
+                            tracing::info!("receiver requested a key frame");
+
+                            requested.replace(Instant::now());
+                            control.key_frame.update(true);
+                        }
+                    } else if kind == MessageKind::Capabilities as u8 {
+                        match serde_json::from_slice::<StreamCapabilities>(payload) {
+                            Ok(capabilities) => control.negotiate(&capabilities),
+                            Err(e) => tracing::warn!(error = ?e, "received invalid capabilities"),
+                        }
+                    } else if kind == MessageKind::Goodbye as u8 {
+                        tracing::info!("receiver closed the stream");
+
+                        if let Some(sink) = sink.upgrade() {
+                            sink.event(StreamEvent::ReceiverClosed);
+                        }
+                    }
+                }
+            }) {
+                tracing::info!(error = ?e, "sender back channel is not available");
+            }
+        }
+
+        {
+            let sink = Arc::downgrade(&sink);
+            transport.on_pairing_code(move |code| {
+                if let Some(sink) = sink.upgrade() {
+                    sink.pairing_code(code);
+                }
+            });
+        }
+
+        // The receivers are checked before the capture starts, so that no receiver
+        // gets the stream without the code.
+        if options.pairing.is_some() {
+            transport.set_pairing(options.pairing)?;
+        }
+
+        let audio_inputs = options
+            .media
+            .audio
+            .iter()
+            .map(|it| AudioMixerInput {
+                gain: it.options.gain,
+                muted: false,
+            })
+            .collect::<Vec<_>>();
+
+        // The sender starts in the energy saver if the system is on battery already.
+        let battery =
+            options.energy_saver.is_some() && power::power_source() == Some(PowerSource::Battery);
+
+        if let Some(pacing) = options
+            .energy_saver
+            .and_then(|it| it.pacing)
+            .filter(|_| battery)
+        {
+            if let Some(adapter) = output.adapter() {
+                adapter.set_pacing(Some(pacing));
+            }
+        }
+
+        let pipeline = Arc::new(SenderPipeline {
+            energy_saver: options.energy_saver,
+            pacing: options.transport.pacing,
+            battery: AtomicBool::new(battery),
+            output,
+            audio_inputs: Mutex::new(audio_inputs),
+            capture: Mutex::new(None),
+            mixer: Mutex::new(None),
+            preview: Default::default(),
+            media: options.media,
+            #[cfg(target_os = "windows")]
+            system: Default::default(),
+            helper_crash: Default::default(),
+            control,
+            status,
+            sink,
+        });
+
+        pipeline.start(&mut pipeline.capture.lock())?;
+
+        if battery {
+            pipeline
+                .sink
+                .event(StreamEvent::EnergySaver { enabled: true });
+        }
+
+        let watchdog = {
+            let pipeline = Arc::downgrade(&pipeline);
+            let mut state = StallState::default();
+            Watchdog::new("HylaranaSenderWatchdogThread", move || {
+                pipeline
+                    .upgrade()
+                    .map(|it| it.watch(&mut state))
+                    .unwrap_or(false)
+            })?
+        };
+
+        // The sender still works without the notifications, it fails to capture the
+        // lock screen as before.
+        #[cfg(target_os = "windows")]
+        let system_events = {
+            let pipeline = Arc::downgrade(&pipeline);
+            match SystemEventListener::new(move |event| {
+                if let Some(pipeline) = pipeline.upgrade() {
+                    pipeline.on_system_event(event);
+                }
+            }) {
+                Ok(it) => Some(it),
+                Err(e) => {
+                    tracing::warn!(error = ?e, "failed to listen to system events");
+
+                    None
+                }
+            }
+        };
+
+        // The sender keeps its own options if the power source cannot be watched.
+        let power_monitor = if options.energy_saver.is_some() {
+            let pipeline = Arc::downgrade(&pipeline);
+            match PowerMonitor::new(move |source| {
+                if let Some(pipeline) = pipeline.upgrade() {
+                    pipeline.on_power_source(source);
+                }
+            }) {
+                Ok(it) => Some(it),
+                Err(e) => {
+                    tracing::warn!(error = ?e, "failed to watch the power source");
+
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        Ok(Self {
+            transport,
+            pipeline,
+            input,
+            watchdog,
+            #[cfg(target_os = "windows")]
+            system_events,
+            power_monitor,
+        })
+    }
+
+    /// Pause the sender, the capture is stopped and the receivers are notified,
+    /// so they keep the last frame instead of closing the stream.
+    pub fn pause(&self) -> Result<(), HylaranaSenderError> {
+        // The sender does not resume by itself after the user has paused it.
+        #[cfg(target_os = "windows")]
+        {
+            self.pipeline.system.lock().resume = false;
+        }
+
+        if self.pipeline.pause()? {
+            tracing::info!("sender pause");
+        }
+
+        Ok(())
+    }
+
+    /// Resume the paused sender, the capture and the encoders are created
+    /// again, so the stream starts with a key frame.
+    ///
+    /// While the session is locked or the system is suspended, the sender
+    /// resumes when it is over instead.
+    pub fn resume(&self) -> Result<(), HylaranaSenderError> {
+        #[cfg(target_os = "windows")]
+        {
+            let mut system = self.pipeline.system.lock();
+            if system.is_active() {
+                system.resume = true;
+
+                return Ok(());
+            }
+        }
+
+        if self.pipeline.resume()? {
+            tracing::info!("sender resume");
+        }
+
+        Ok(())
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.pipeline.capture.lock().is_none()
+    }
+
+    /// Preview the video as the receivers see it, the packets of the encoder
+    /// are decoded again and passed to the sink, so the artifacts of the
+    /// encoding are visible. Pass `None` to stop the preview.
+    ///
+    /// The preview starts with the next key frame, and the return value of the
+    /// sink is ignored.
+    pub fn set_preview<S: AVFrameSink + 'static>(&self, sink: Option<S>) {
+        tracing::info!(enabled = sink.is_some(), "sender set preview");
+
+        *self.pipeline.preview.write() = sink.map(|it| Box::new(it) as Box<dyn AVFrameSink>);
+    }
+
+    /// Pass the encoded packets of the stream to a sink, such as to mux them
+    /// into a file while streaming, see `EncodedPacketSink`. Pass `None` to
+    /// remove the sink.
+    ///
+    /// A key frame is requested, the video of the sink starts with it. The
+    /// senders of the shared memory transport do not encode the frames, and
+    /// have no packets.
+    pub fn set_packet_sink<S: EncodedPacketSink + 'static>(&self, sink: Option<S>) {
+        tracing::info!(enabled = sink.is_some(), "sender set packet sink");
+
+        if let Some(tap) = self.pipeline.output.tap() {
+            let enabled = sink.is_some();
+            tap.set_sink(sink.map(|it| Arc::new(it) as Arc<dyn EncodedPacketSink>));
+
+            if enabled {
+                self.pipeline.control.key_frame.update(true);
+            }
+        }
+    }
+
+    /// Get the settings of the audio tracks, in the order of the tracks in the
+    /// media options.
+    pub fn audio_inputs(&self) -> Vec<AudioMixerInput> {
+        self.pipeline.audio_inputs.lock().clone()
+    }
+
+    /// Change the gain of an audio track while streaming, `1.0` keeps the
+    /// original volume, an index out of range is ignored.
+    pub fn set_audio_gain(&self, index: usize, gain: f32) {
+        self.update_audio_input(index, |it| it.gain = gain);
+    }
+
+    /// Mute or unmute an audio track while streaming, an index out of range is
+    /// ignored.
+    pub fn set_audio_muted(&self, index: usize, muted: bool) {
+        self.update_audio_input(index, |it| it.muted = muted);
+    }
+
+    fn update_audio_input<F: FnOnce(&mut AudioMixerInput)>(&self, index: usize, func: F) {
+        if let Some(input) = self.pipeline.audio_inputs.lock().get_mut(index) {
+            func(input);
+
+            tracing::info!(index, input = ?input, "sender update audio input");
+
+            if let Some(mixer) = self.pipeline.mixer.lock().as_ref() {
+                mixer.set_input(index, *input);
+            }
+
+            if let Some(PipelineCapture::Sandboxed(process)) = self.pipeline.capture.lock().as_ref()
+            {
+                process.set_audio_input(index, *input);
+            }
+        }
+    }
+
+    /// Get the privacy masks of the video.
+    pub fn privacy_masks(&self) -> Option<PrivacyMaskOptions> {
+        self.pipeline.control.privacy_masks.lock().clone()
+    }
+
+    /// Replace the privacy masks of the video while streaming, the next frame
+    /// that is encoded is masked with them, `None` removes them.
+    pub fn set_privacy_masks(&self, options: Option<PrivacyMaskOptions>) {
+        tracing::info!(options = ?options, "sender update privacy masks");
+
+        #[cfg(not(target_os = "windows"))]
+        if options
+            .as_ref()
+            .map(|it| {
+                it.masks
+                    .iter()
+                    .any(|it| matches!(it, PrivacyMask::Window(_)))
+            })
+            .unwrap_or(false)
+        {
+            tracing::warn!("window privacy masks are only supported on windows");
+        }
+
+        self.pipeline.control.set_privacy_masks(options.clone());
+
+        if let Some(PipelineCapture::Sandboxed(process)) = self.pipeline.capture.lock().as_ref() {
+            process.set_privacy_masks(options);
+        }
+    }
+
+    /// Get the metadata of the stream, the codecs and the resolution are filled
+    /// in from the media options, it can be published with the discovery
+    /// service.
+    pub fn metadata(&self) -> StreamMetadata {
+        self.pipeline.control.metadata.lock().clone()
+    }
+
+    /// Update the metadata of the stream, the receivers get the new metadata
+    /// with the next key frame.
+    pub fn set_metadata(&self, metadata: StreamMetadata) -> Result<(), HylaranaSenderError> {
+        self.pipeline.control.set_metadata(
+            &self.transport.get_adapter(),
+            complete_metadata(metadata, &self.pipeline.media),
+        )?;
+
+        Ok(())
+    }
+
+    /// The capabilities that the sender and all receivers that have joined
+    /// support. The receivers of older versions do not advertise their
+    /// capabilities and are not taken into account, neither are the receivers
+    /// of a multicast stream.
+    ///
+    /// The video is switched from HEVC to H264 as soon as a receiver cannot
+    /// decode HEVC, and stays H264 for the rest of the stream.
+    pub fn capabilities(&self) -> StreamCapabilities {
+        self.pipeline.control.capabilities.lock().clone()
+    }
+
+    /// Allow the receivers to control the sender with the mouse and keyboard,
+    /// the events are injected into the system of the sender. This is disabled
+    /// by default, and has no effect in multicast mode, whose back channel is
+    /// not authenticated, so the events are never accepted from it.
+    pub fn set_input_enabled(&self, enabled: bool) {
+        tracing::info!(enabled, "sender set input enabled");
+
+        self.input.update(enabled);
+    }
+
+    /// Get the ID of the sender, each sender has an individual ID identifier,
+    /// you need to specify the ID of the sender when creating the receiver.
+    pub fn get_id(&self) -> &str {
+        self.transport.get_id()
+    }
+
+    /// Whether the stream has been closed, the sender or receiver can be
+    /// dropped after this.
+    pub fn is_closed(&self) -> bool {
+        self.pipeline.status.is_closed()
+    }
+
+    /// Wait until the stream is closed, either by the other side, by an
+    /// error, or because the sink returned false.
+    pub async fn closed(&self) {
+        self.pipeline.status.closed().await
+    }
+
+    /// Get the sink of the sender, such as the player passed in when creating
+    /// the sender.
+    pub fn get_sink(&self) -> &T {
+        &self.pipeline.sink
+    }
+
+    /// The receivers that are attached to the sender, so that the presenter
+    /// can see the viewers. Only the direct mode knows its receivers, the list
+    /// is empty in the other modes.
+    pub fn receivers(&self) -> Vec<ReceiverInfo> {
+        self.transport.receivers()
+    }
+
+    /// Disconnect a receiver of `receivers`, the receiver closes its stream
+    /// with `DisconnectReason::Kicked`. Returns false if the receiver is not
+    /// attached.
+    pub fn disconnect(&self, id: u64) -> bool {
+        tracing::info!(id, "disconnect receiver");
+
+        self.transport.disconnect(id)
+    }
+
+    /// Turn the pairing on or off, see `HylaranaSenderOptions::pairing`, the
+    /// receivers that have connected stay connected. A new code is passed to
+    /// `AVFrameObserver::pairing_code`.
+    pub fn set_pairing(&self, options: Option<PairingOptions>) -> Result<(), HylaranaSenderError> {
+        Ok(self.transport.set_pairing(options)?)
+    }
+
+    /// The pairing code that the receivers have to submit, `None` if the
+    /// pairing is off.
+    pub fn pairing_code(&self) -> Option<String> {
+        self.transport.pairing_code()
+    }
+
+    /// The estimate of the link to the receivers, updated on every call, so
+    /// that the application can warn the user or lower the quality before the
+    /// stream degrades. In direct mode this is the slowest receiver, and it is
+    /// `None` in the multicast and loopback modes, or before a receiver has
+    /// connected.
+    pub fn estimated_bandwidth(&self) -> Option<BandwidthEstimate> {
+        self.transport.estimated_bandwidth()
+    }
+
+    /// Send a message to all receivers through the data channel, the message
+    /// is carried in the stream, so it arrives in order with the media and
+    /// reaches the receivers in all transport modes.
+    pub fn send_message(&self, message: &[u8]) -> Result<(), HylaranaSenderError> {
+        if !self
+            .pipeline
+            .send_control(StreamControl::Message(Bytes::copy_from_slice(message)))
+        {
+            return Err(Error::new(ErrorKind::BrokenPipe, "stream is closed").into());
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: AVFrameStream + 'static> Drop for HylaranaSender<T> {
+    fn drop(&mut self) {
+        tracing::info!("sender drop");
+
+        // When the sender releases, the cleanup work should be done, but there is a
+        // more troublesome point here. If it is actively released by the outside, it
+        // will also call back to the external closing event, the observer can tell it
+        // apart by the reason of the disconnect event.
+        if let Some(capture) = self.pipeline.capture.lock().as_ref() {
+            if let Err(e) = capture.close() {
+                tracing::warn!(error = ?e, "hylarana sender capture close error");
+            }
+        }
+
+        close_stream(
+            &self.pipeline.status,
+            self.pipeline.sink.as_ref(),
+            DisconnectReason::Closed,
+        );
     }
 }
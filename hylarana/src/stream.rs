@@ -0,0 +1,328 @@
+use crate::{AVFrameObserver, AVFrameSink, AVFrameStream, SinkBufferStats, StreamEvent};
+
+use std::{
+    collections::VecDeque,
+    pin::Pin,
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    task::{Context, Poll},
+    time::{Duration, Instant},
+};
+
+use futures_core::Stream;
+use hylarana_common::{
+    atomic::EasyAtomic,
+    frame::{AudioFrame, OwnedAudioFrame, OwnedVideoFrame, VideoFrame},
+};
+use parking_lot::{Condvar, Mutex};
+use tokio::sync::mpsc::{channel, error::TrySendError, Receiver, Sender};
+
+/// A sink that delivers the frames as async streams, for the applications that
+/// run on tokio instead of handling the frames in the callbacks.
+///
+/// The frames are copied into owned frames, only the software frames can be
+/// copied, so the receiver should use a software decoder. The queues are
+/// bounded, the frames are dropped when a stream is not polled in time, so a
+/// slow consumer does not stall the pipeline. The streams end when the stream
+/// is closed.
+///
+/// ```ignore
+/// let (sink, mut video, _audio) = AsyncFrameSink::new(4);
+/// let receiver = Hylarana::create_receiver_async(id, options, sink).await?;
+///
+/// while let Some(frame) = video.recv().await {
+///     // ...
+/// }
+/// ```
+pub struct AsyncFrameSink {
+    video: Mutex<Option<Sender<OwnedVideoFrame>>>,
+    audio: Mutex<Option<Sender<OwnedAudioFrame>>>,
+    events: Mutex<Option<Box<dyn Fn(StreamEvent) + Send + Sync>>>,
+    overflow: AtomicU64,
+}
+
+impl AsyncFrameSink {
+    /// The capacity is the number of the frames of each stream that are kept
+    /// while the consumer is busy.
+    pub fn new(capacity: usize) -> (Self, VideoFrameStream, AudioFrameStream) {
+        let (video_tx, video_rx) = channel(capacity.max(1));
+        let (audio_tx, audio_rx) = channel(capacity.max(1));
+
+        (
+            Self {
+                video: Mutex::new(Some(video_tx)),
+                audio: Mutex::new(Some(audio_tx)),
+                events: Mutex::new(None),
+                overflow: AtomicU64::new(0),
+            },
+            VideoFrameStream(video_rx),
+            AudioFrameStream(audio_rx),
+        )
+    }
+
+    /// Set the handler of the events of the stream.
+    pub fn on_event<F>(self, handler: F) -> Self
+    where
+        F: Fn(StreamEvent) + Send + Sync + 'static,
+    {
+        self.events.lock().replace(Box::new(handler));
+        self
+    }
+}
+
+// The frame is dropped if the queue is full, the consumer catches up with the
+// newer frames.
+fn push<T>(sender: &Mutex<Option<Sender<T>>>, item: Option<T>, overflow: &AtomicU64) {
+    if let (Some(sender), Some(item)) = (sender.lock().as_ref(), item) {
+        if let Err(TrySendError::Full(_)) = sender.try_send(item) {
+            log::trace!("async frame queue is full, the frame is dropped");
+
+            overflow.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl AVFrameSink for AsyncFrameSink {
+    fn video(&self, frame: &VideoFrame) -> bool {
+        push(&self.video, frame.to_owned(), &self.overflow);
+        true
+    }
+
+    fn audio(&self, frame: &AudioFrame) -> bool {
+        push(&self.audio, Some(frame.to_owned()), &self.overflow);
+        true
+    }
+
+    fn buffer_stats(&self) -> SinkBufferStats {
+        SinkBufferStats {
+            overflow: self.overflow.get(),
+            ..Default::default()
+        }
+    }
+}
+
+impl AVFrameObserver for AsyncFrameSink {
+    fn event(&self, event: StreamEvent) {
+        if let Some(handler) = self.events.lock().as_ref() {
+            handler(event);
+        }
+    }
+
+    fn close(&self) {
+        // Dropping the senders ends the streams.
+        drop(self.video.lock().take());
+        drop(self.audio.lock().take());
+    }
+}
+
+impl AVFrameStream for AsyncFrameSink {}
+
+/// The video frames of an `AsyncFrameSink`.
+pub struct VideoFrameStream(Receiver<OwnedVideoFrame>);
+
+impl VideoFrameStream {
+    /// Wait for the next frame, `None` is returned after the stream is
+    /// closed.
+    pub async fn recv(&mut self) -> Option<OwnedVideoFrame> {
+        self.0.recv().await
+    }
+}
+
+impl Stream for VideoFrameStream {
+    type Item = OwnedVideoFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+/// The audio frames of an `AsyncFrameSink`.
+pub struct AudioFrameStream(Receiver<OwnedAudioFrame>);
+
+impl AudioFrameStream {
+    /// Wait for the next frame, `None` is returned after the stream is
+    /// closed.
+    pub async fn recv(&mut self) -> Option<OwnedAudioFrame> {
+        self.0.recv().await
+    }
+}
+
+impl Stream for AudioFrameStream {
+    type Item = OwnedAudioFrame;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.poll_recv(cx)
+    }
+}
+
+// A bounded queue that drops the oldest item when it is full, the consumer
+// always gets the newest frames.
+pub(crate) struct FrameQueueInner<T> {
+    items: Mutex<VecDeque<T>>,
+    condvar: Condvar,
+    closed: AtomicBool,
+    capacity: usize,
+    overflow: AtomicU64,
+}
+
+impl<T> FrameQueueInner<T> {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self {
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            condvar: Condvar::new(),
+            closed: AtomicBool::new(false),
+            capacity: capacity.max(1),
+            overflow: AtomicU64::new(0),
+        }
+    }
+
+    // Returns false if the oldest item has been dropped to make room for it.
+    pub(crate) fn push(&self, item: T) -> bool {
+        let mut items = self.items.lock();
+        let overflow = items.len() >= self.capacity;
+        if overflow {
+            items.pop_front();
+
+            self.overflow.fetch_add(1, Ordering::Relaxed);
+        }
+
+        items.push_back(item);
+        self.condvar.notify_one();
+
+        !overflow
+    }
+
+    fn try_pop(&self) -> Option<T> {
+        self.items.lock().pop_front()
+    }
+
+    pub(crate) fn pop(&self, timeout: Duration) -> Option<T> {
+        let deadline = Instant::now() + timeout;
+
+        let mut items = self.items.lock();
+        while items.is_empty() && !self.closed.get() {
+            if self.condvar.wait_until(&mut items, deadline).timed_out() {
+                break;
+            }
+        }
+
+        items.pop_front()
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
+    pub(crate) fn close(&self) {
+        // Hold the lock so that a consumer that has just checked the flag is
+        // already waiting when it is notified.
+        let _items = self.items.lock();
+
+        self.closed.update(true);
+        self.condvar.notify_all();
+    }
+}
+
+/// A sink that keeps the frames in bounded queues, so that they are pulled by
+/// the application, such as on the render thread of a game engine, instead of
+/// being pushed to it on the codec threads.
+///
+/// The frames are copied into owned frames, only the software frames can be
+/// copied, so the receiver should use a software decoder. When a queue is
+/// full, the oldest frame is dropped.
+pub struct FrameQueue {
+    video: FrameQueueInner<OwnedVideoFrame>,
+    audio: FrameQueueInner<OwnedAudioFrame>,
+    events: Mutex<Option<Box<dyn Fn(StreamEvent) + Send + Sync>>>,
+}
+
+impl FrameQueue {
+    /// The capacity is the number of the frames of each queue, a render
+    /// thread that only shows the newest frame can use 1.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            video: FrameQueueInner::new(capacity),
+            audio: FrameQueueInner::new(capacity),
+            events: Mutex::new(None),
+        }
+    }
+
+    /// Set the handler of the events of the stream.
+    pub fn on_event<F>(self, handler: F) -> Self
+    where
+        F: Fn(StreamEvent) + Send + Sync + 'static,
+    {
+        self.events.lock().replace(Box::new(handler));
+        self
+    }
+
+    /// Take the oldest video frame in the queue without waiting.
+    pub fn try_next_video_frame(&self) -> Option<OwnedVideoFrame> {
+        self.video.try_pop()
+    }
+
+    /// Take the oldest video frame in the queue, waits up to the timeout if
+    /// the queue is empty, `None` is returned on timeout or after the stream
+    /// is closed.
+    pub fn next_video_frame(&self, timeout: Duration) -> Option<OwnedVideoFrame> {
+        self.video.pop(timeout)
+    }
+
+    /// Take the oldest audio frame in the queue without waiting.
+    pub fn try_next_audio_frame(&self) -> Option<OwnedAudioFrame> {
+        self.audio.try_pop()
+    }
+
+    /// Take the oldest audio frame in the queue, waits up to the timeout if
+    /// the queue is empty, `None` is returned on timeout or after the stream
+    /// is closed.
+    pub fn next_audio_frame(&self, timeout: Duration) -> Option<OwnedAudioFrame> {
+        self.audio.pop(timeout)
+    }
+}
+
+impl AVFrameSink for FrameQueue {
+    fn video(&self, frame: &VideoFrame) -> bool {
+        if let Some(frame) = frame.to_owned() {
+            self.video.push(frame);
+        }
+
+        true
+    }
+
+    fn audio(&self, frame: &AudioFrame) -> bool {
+        self.audio.push(frame.to_owned());
+        true
+    }
+
+    fn buffer_stats(&self) -> SinkBufferStats {
+        let buffered = self
+            .audio
+            .items
+            .lock()
+            .iter()
+            .filter(|it| it.sample_rate > 0)
+            .map(|it| it.frames as u64 * 1_000_000 / it.sample_rate as u64)
+            .sum();
+
+        SinkBufferStats {
+            overflow: self.video.overflow.get() + self.audio.overflow.get(),
+            buffered: Duration::from_micros(buffered),
+            ..Default::default()
+        }
+    }
+}
+
+impl AVFrameObserver for FrameQueue {
+    fn event(&self, event: StreamEvent) {
+        if let Some(handler) = self.events.lock().as_ref() {
+            handler(event);
+        }
+    }
+
+    fn close(&self) {
+        self.video.close();
+        self.audio.close();
+    }
+}
+
+impl AVFrameStream for FrameQueue {}
@@ -0,0 +1,124 @@
+use std::{
+    io::Error,
+    sync::{
+        atomic::{AtomicBool, AtomicU64},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use hylarana_common::{atomic::EasyAtomic, clock::MediaClock};
+
+/// A stage of the media pipeline that is watched for stalls.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PipelineStage {
+    Capture,
+    Encode,
+    Decode,
+}
+
+// A stage that makes no progress for this long is stalled, the capture keeps
+// delivering frames of a static screen, so this is never reached by a healthy
+// stage.
+const STALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+// How often the stages are checked.
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+// The time a stage last made progress on the media clock, zero while the stage
+// is not running, such as while the sender is paused.
+#[derive(Default)]
+pub(crate) struct Heartbeat(AtomicU64);
+
+impl Heartbeat {
+    pub(crate) fn beat(&self) {
+        self.0.update(MediaClock::now());
+    }
+
+    pub(crate) fn stop(&self) {
+        self.0.update(0);
+    }
+
+    pub(crate) fn last(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+pub(crate) enum Verdict {
+    Healthy,
+    /// The stage has stalled, the watchdog tries to recover it.
+    Recover,
+    /// The stage has not made any progress since it was recovered.
+    Failed,
+}
+
+// Each stall is recovered once, a stage that stalls again without making any
+// progress after the recovery cannot be recovered.
+#[derive(Default)]
+pub(crate) struct StallState {
+    // The heartbeat at the time of the recovery.
+    recovered: Option<u64>,
+}
+
+impl StallState {
+    pub(crate) fn check(&mut self, heartbeat: &Heartbeat) -> Verdict {
+        let last = heartbeat.last();
+        if self.recovered.is_some() && self.recovered != Some(last) {
+            self.recovered = None;
+        }
+
+        if last == 0 || MediaClock::elapsed(last) < STALL_TIMEOUT.as_micros() as u64 {
+            Verdict::Healthy
+        } else if self.recovered.is_some() {
+            Verdict::Failed
+        } else {
+            Verdict::Recover
+        }
+    }
+
+    // The stage is given another full timeout to make progress after the
+    // recovery.
+    pub(crate) fn recovered(&mut self, heartbeat: &Heartbeat) {
+        heartbeat.beat();
+
+        self.recovered = Some(heartbeat.last());
+    }
+}
+
+/// Checks the stages of a sender or a receiver periodically, the check
+/// returns `false` to stop the watchdog. The watchdog is stopped when it is
+/// dropped.
+pub(crate) struct Watchdog {
+    closed: Arc<AtomicBool>,
+}
+
+impl Watchdog {
+    pub(crate) fn new<F>(name: &str, mut check: F) -> Result<Self, Error>
+    where
+        F: FnMut() -> bool + Send + 'static,
+    {
+        let closed = Arc::new(AtomicBool::new(false));
+
+        let closed_ = closed.clone();
+        thread::Builder::new()
+            .name(name.to_string())
+            .spawn(move || {
+                while !closed_.get() {
+                    thread::sleep(CHECK_INTERVAL);
+
+                    if closed_.get() || !check() {
+                        break;
+                    }
+                }
+            })?;
+
+        Ok(Self { closed })
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        self.closed.update(true);
+    }
+}
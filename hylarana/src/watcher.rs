@@ -0,0 +1,162 @@
+use crate::{
+    AVFrameStream, Hylarana, HylaranaReceiver, HylaranaReceiverCodecOptions,
+    HylaranaReceiverOptions, StreamMetadata,
+};
+
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr},
+    sync::Arc,
+};
+
+use hylarana_discovery::{DiscoveryError, DiscoveryEvent, DiscoveryService};
+use hylarana_transport::{TransportOptions, TransportStrategy};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+/// The properties of a sender that are published in the discovery service,
+/// so that the receivers can connect to it, see `Hylarana::watch`.
+///
+/// ```ignore
+/// let discovery = DiscoveryService::register(
+///     port,
+///     &SenderAnnouncement {
+///         id: sender.get_id().to_string(),
+///         transport,
+///         metadata: sender.metadata(),
+///     },
+/// )?;
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SenderAnnouncement {
+    pub id: String,
+    /// The address of a direct sender is replaced with the address that the
+    /// announcement came from, so the sender can publish an unspecified
+    /// address.
+    pub transport: TransportOptions,
+    #[serde(default)]
+    pub metadata: StreamMetadata,
+}
+
+impl SenderAnnouncement {
+    fn resolve(&mut self, addrs: &[Ipv4Addr]) {
+        if let TransportStrategy::Direct(addr) = &mut self.transport.strategy {
+            if let Some(ip) = addrs.first() {
+                addr.set_ip(IpAddr::V4(*ip));
+            }
+        }
+    }
+}
+
+type Receivers<T> = Arc<Mutex<HashMap<String, (SenderAnnouncement, HylaranaReceiver<T>)>>>;
+
+/// The receivers that are connected automatically by `Hylarana::watch`, the
+/// discovery is stopped and all the receivers are closed when it is dropped.
+pub struct ReceiverWatcher<T: AVFrameStream + 'static> {
+    // The discovery is dropped first, so no receiver is created while the
+    // receivers are dropped.
+    #[allow(dead_code)]
+    discovery: DiscoveryService,
+    receivers: Receivers<T>,
+}
+
+impl<T: AVFrameStream + 'static> ReceiverWatcher<T> {
+    pub(crate) fn new<F, C>(filter: F, callback: C) -> Result<Self, DiscoveryError>
+    where
+        F: Fn(&SenderAnnouncement) -> bool + Send + 'static,
+        C: Fn(&SenderAnnouncement) -> Option<(HylaranaReceiverCodecOptions, T)> + Send + 'static,
+        HylaranaReceiver<T>: Send,
+    {
+        let receivers: Receivers<T> = Default::default();
+
+        let receivers_ = Arc::downgrade(&receivers);
+        let discovery = DiscoveryService::browse(move |event| {
+            let Some(receivers) = receivers_.upgrade() else {
+                return;
+            };
+
+            match event {
+                DiscoveryEvent::Resolved {
+                    name,
+                    addrs,
+                    properties,
+                } => {
+                    let mut announcement: SenderAnnouncement = properties;
+                    announcement.resolve(&addrs);
+
+                    // A sender is resolved again when its addresses change, the receiver
+                    // is only created again if the stream of the sender has ended.
+                    let mut receivers = receivers.lock();
+                    if let Some((_, receiver)) = receivers.get(&name) {
+                        if !receiver.is_closed() {
+                            return;
+                        }
+                    }
+
+                    receivers.remove(&name);
+
+                    if !filter(&announcement) {
+                        return;
+                    }
+
+                    let Some((codec, sink)) = callback(&announcement) else {
+                        return;
+                    };
+
+                    match Hylarana::create_receiver(
+                        announcement.id.clone(),
+                        HylaranaReceiverOptions {
+                            transport: announcement.transport,
+                            codec,
+                            tracks: Default::default(),
+                            pairing_code: None,
+                        },
+                        sink,
+                    ) {
+                        Ok(receiver) => {
+                            tracing::info!(name = %name, id = %announcement.id, "watcher connect sender");
+
+                            receivers.insert(name, (announcement, receiver));
+                        }
+                        Err(e) => {
+                            tracing::warn!(name = %name, error = ?e, "watcher failed to connect sender");
+                        }
+                    }
+                }
+                DiscoveryEvent::Removed { name } => {
+                    if receivers.lock().remove(&name).is_some() {
+                        tracing::info!(name = %name, "watcher disconnect sender");
+                    }
+                }
+            }
+        })?;
+
+        Ok(Self {
+            discovery,
+            receivers,
+        })
+    }
+
+    /// The senders that are currently connected.
+    pub fn senders(&self) -> Vec<SenderAnnouncement> {
+        self.receivers
+            .lock()
+            .values()
+            .filter(|(_, receiver)| !receiver.is_closed())
+            .map(|(announcement, _)| announcement.clone())
+            .collect()
+    }
+
+    /// Call the function with the receiver of a connected sender, by the id
+    /// of the sender.
+    pub fn with_receiver<F, R>(&self, id: &str, func: F) -> Option<R>
+    where
+        F: FnOnce(&HylaranaReceiver<T>) -> R,
+    {
+        self.receivers
+            .lock()
+            .values()
+            .find(|(announcement, _)| announcement.id == id)
+            .map(|(_, receiver)| func(receiver))
+    }
+}
@@ -2,6 +2,43 @@ use rubato::{FastFixedIn, PolynomialDegree, Resampler};
 
 pub use rubato::{ResampleError, ResampleResult, ResamplerConstructionError};
 
+/// The sample formats of the input of the audio resampler, the samples are
+/// converted to 16-bit in the same pass as the channels are taken apart.
+pub trait AudioSample: Copy {
+    /// The sample in the range of a 16-bit sample.
+    fn to_f32(self) -> f32;
+
+    /// The samples of the input as they are if they are already 16-bit.
+    #[allow(unused_variables)]
+    fn as_i16(buffer: &[Self]) -> Option<&[i16]> {
+        None
+    }
+}
+
+impl AudioSample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn as_i16(buffer: &[Self]) -> Option<&[i16]> {
+        Some(buffer)
+    }
+}
+
+// The 24-bit samples of the devices are carried in the high bits of 32-bit
+// samples.
+impl AudioSample for i32 {
+    fn to_f32(self) -> f32 {
+        (self >> 16) as f32
+    }
+}
+
+impl AudioSample for f32 {
+    fn to_f32(self) -> f32 {
+        self.clamp(-1.0, 1.0) * i16::MAX as f32
+    }
+}
+
 /// Audio resampler, quickly resample input to a single channel count and
 /// different sampling rates.
 ///
@@ -33,12 +70,12 @@ impl AudioResampler {
         })
     }
 
-    pub fn resample<'a>(
+    pub fn resample<'a, T: AudioSample>(
         &'a mut self,
-        buffer: &'a [i16],
+        buffer: &'a [T],
         channels: usize,
     ) -> ResampleResult<&'a [i16]> {
-        if channels == 1 && self.sampler.is_none() {
+        if let (Some(buffer), 1, None) = (T::as_i16(buffer), channels, self.sampler.as_ref()) {
             Ok(buffer)
         } else {
             self.samples.clear();
@@ -46,10 +83,10 @@ impl AudioResampler {
 
             for item in buffer.iter().step_by(channels) {
                 if self.sampler.is_none() {
-                    self.samples.push(*item);
+                    self.samples.push(item.to_f32() as i16);
                 } else {
                     // need resample
-                    self.input_buffer.push(*item as f32);
+                    self.input_buffer.push(item.to_f32());
                 }
             }
 
@@ -75,6 +112,7 @@ pub mod win32 {
     use std::mem::ManuallyDrop;
 
     use hylarana_common::{
+        frame::{FieldOrder, VideoRotation},
         win32::{
             windows::{
                 core::{Error, Interface},
@@ -83,23 +121,29 @@ pub mod win32 {
                     Graphics::{
                         Direct3D11::{
                             ID3D11Device, ID3D11DeviceContext, ID3D11Texture2D, ID3D11VideoContext,
-                            ID3D11VideoDevice, ID3D11VideoProcessor,
+                            ID3D11VideoContext1, ID3D11VideoDevice, ID3D11VideoProcessor,
                             ID3D11VideoProcessorEnumerator, ID3D11VideoProcessorInputView,
                             ID3D11VideoProcessorOutputView, D3D11_BIND_RENDER_TARGET,
                             D3D11_CPU_ACCESS_READ, D3D11_MAPPED_SUBRESOURCE, D3D11_MAP_READ,
                             D3D11_RESOURCE_MISC_SHARED, D3D11_TEXTURE2D_DESC, D3D11_USAGE_DEFAULT,
-                            D3D11_USAGE_STAGING, D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+                            D3D11_USAGE_STAGING,
+                            D3D11_VIDEO_FRAME_FORMAT_INTERLACED_BOTTOM_FIELD_FIRST,
+                            D3D11_VIDEO_FRAME_FORMAT_INTERLACED_TOP_FIELD_FIRST,
+                            D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
                             D3D11_VIDEO_PROCESSOR_COLOR_SPACE, D3D11_VIDEO_PROCESSOR_CONTENT_DESC,
                             D3D11_VIDEO_PROCESSOR_INPUT_VIEW_DESC,
-                            D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC, D3D11_VIDEO_PROCESSOR_STREAM,
+                            D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC,
+                            D3D11_VIDEO_PROCESSOR_ROTATION_180, D3D11_VIDEO_PROCESSOR_ROTATION_270,
+                            D3D11_VIDEO_PROCESSOR_ROTATION_90,
+                            D3D11_VIDEO_PROCESSOR_ROTATION_IDENTITY, D3D11_VIDEO_PROCESSOR_STREAM,
                             D3D11_VIDEO_USAGE_PLAYBACK_NORMAL, D3D11_VPIV_DIMENSION_TEXTURE2D,
                             D3D11_VPOV_DIMENSION_TEXTURE2D,
                         },
-                        Dxgi::Common::DXGI_FORMAT,
+                        Dxgi::Common::{DXGI_COLOR_SPACE_TYPE, DXGI_FORMAT},
                     },
                 },
             },
-            Direct3DDevice,
+            Direct3DDevice, PooledTexture, TexturePool,
         },
         Size,
     };
@@ -133,12 +177,23 @@ pub mod win32 {
         video_processor: ID3D11VideoProcessor,
         input_view: ID3D11VideoProcessorInputView,
         output_view: ID3D11VideoProcessorOutputView,
+        overlay: Option<ID3D11VideoProcessorInputView>,
+        // The output views of the pooled textures that have been processed into, the
+        // textures of a pool are reused, so are their views.
+        pooled_views: Vec<(ID3D11Texture2D, ID3D11VideoProcessorOutputView)>,
+        // The output is copied to the staging texture to be read by the CPU, creating
+        // a texture of the size of the frame for each frame stalls the GPU.
+        staging: Option<ID3D11Texture2D>,
     }
 
     unsafe impl Send for VideoResampler {}
     unsafe impl Sync for VideoResampler {}
 
     impl VideoResampler {
+        // A few more than the textures that a pool keeps, the views of the textures
+        // that have left the pool are dropped.
+        const MAX_POOLED_VIEWS: usize = 8;
+
         /// Create `VideoResampler`, the default_device parameter is used to
         /// directly use the device when it has been created externally, so
         /// there is no need to copy across devices, which improves
@@ -292,6 +347,9 @@ pub mod win32 {
                 output_texture,
                 input_view,
                 output_view,
+                overlay: None,
+                pooled_views: Vec::new(),
+                staging: None,
             })
         }
 
@@ -355,14 +413,185 @@ pub mod win32 {
             Ok(input_view)
         }
 
+        /// Set the color space of the input and the output, this is required
+        /// when converting between HDR and SDR, for example, converting
+        /// scRGB to HDR10 requires the input to be
+        /// `DXGI_COLOR_SPACE_RGB_FULL_G10_NONE_P709` and the output to be
+        /// `DXGI_COLOR_SPACE_YCBCR_STUDIO_G2084_LEFT_P2020`.
+        pub fn set_color_space(
+            &mut self,
+            input: DXGI_COLOR_SPACE_TYPE,
+            output: DXGI_COLOR_SPACE_TYPE,
+        ) -> Result<(), Error> {
+            let video_context = self.video_context.cast::<ID3D11VideoContext1>()?;
+            unsafe {
+                video_context.VideoProcessorSetStreamColorSpace1(&self.video_processor, 0, input);
+                video_context.VideoProcessorSetOutputColorSpace1(&self.video_processor, output);
+            }
+
+            Ok(())
+        }
+
+        /// Set the area of the input texture that is drawn to the output, the
+        /// rest of the input is cropped.
+        pub fn set_input_rect(&mut self, rect: RECT) {
+            unsafe {
+                self.video_context.VideoProcessorSetStreamSourceRect(
+                    &self.video_processor,
+                    0,
+                    true,
+                    Some(&rect),
+                );
+            }
+        }
+
+        /// Set the area of the output texture that the input is drawn to, the
+        /// rest of the output is filled with the background color.
+        pub fn set_output_rect(&mut self, rect: RECT) {
+            unsafe {
+                self.video_context.VideoProcessorSetStreamDestRect(
+                    &self.video_processor,
+                    0,
+                    true,
+                    Some(&rect),
+                );
+            }
+        }
+
+        /// Rotate the input stream clockwise and optionally mirror it
+        /// horizontally, the mirroring is applied after the rotation.
+        pub fn set_orientation(
+            &mut self,
+            rotation: VideoRotation,
+            mirror: bool,
+        ) -> Result<(), Error> {
+            let rotation = match rotation {
+                VideoRotation::Rotate0 => D3D11_VIDEO_PROCESSOR_ROTATION_IDENTITY,
+                VideoRotation::Rotate90 => D3D11_VIDEO_PROCESSOR_ROTATION_90,
+                VideoRotation::Rotate180 => D3D11_VIDEO_PROCESSOR_ROTATION_180,
+                VideoRotation::Rotate270 => D3D11_VIDEO_PROCESSOR_ROTATION_270,
+            };
+
+            let video_context = self.video_context.cast::<ID3D11VideoContext1>()?;
+            unsafe {
+                video_context.VideoProcessorSetStreamRotation(
+                    &self.video_processor,
+                    0,
+                    true,
+                    rotation,
+                );
+
+                video_context.VideoProcessorSetStreamMirror(
+                    &self.video_processor,
+                    0,
+                    mirror,
+                    mirror,
+                    false,
+                );
+            }
+
+            Ok(())
+        }
+
+        /// Set the field order of the input stream, the video processor
+        /// deinterlaces the interlaced input into a progressive output frame,
+        /// only the current frame is used as the reference, so the quality
+        /// depends on the deinterlacing of the driver.
+        pub fn set_field_order(&mut self, order: FieldOrder) {
+            let format = match order {
+                FieldOrder::Progressive => D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+                FieldOrder::TopFieldFirst => D3D11_VIDEO_FRAME_FORMAT_INTERLACED_TOP_FIELD_FIRST,
+                FieldOrder::BottomFieldFirst => {
+                    D3D11_VIDEO_FRAME_FORMAT_INTERLACED_BOTTOM_FIELD_FIRST
+                }
+            };
+
+            unsafe {
+                self.video_context.VideoProcessorSetStreamFrameFormat(
+                    &self.video_processor,
+                    0,
+                    format,
+                );
+            }
+        }
+
+        /// Draw a texture over the input stream as a second stream, such as a
+        /// watermark. The texture is usually BGRA, its alpha is multiplied by
+        /// the given alpha, and it is drawn to the given area of the output.
+        /// `None` removes the overlay.
+        pub fn set_overlay(
+            &mut self,
+            overlay: Option<(&ID3D11Texture2D, RECT, f32)>,
+        ) -> Result<(), Error> {
+            let Some((texture, rect, alpha)) = overlay else {
+                self.overlay = None;
+                return Ok(());
+            };
+
+            let view = self.create_input_view(texture, 0)?;
+
+            let mut desc = D3D11_TEXTURE2D_DESC::default();
+            unsafe {
+                texture.GetDesc(&mut desc);
+            }
+
+            unsafe {
+                self.video_context.VideoProcessorSetStreamSourceRect(
+                    &self.video_processor,
+                    1,
+                    true,
+                    Some(&RECT {
+                        left: 0,
+                        top: 0,
+                        right: desc.Width as i32,
+                        bottom: desc.Height as i32,
+                    }),
+                );
+
+                self.video_context.VideoProcessorSetStreamDestRect(
+                    &self.video_processor,
+                    1,
+                    true,
+                    Some(&rect),
+                );
+
+                self.video_context.VideoProcessorSetStreamAlpha(
+                    &self.video_processor,
+                    1,
+                    true,
+                    alpha.clamp(0.0, 1.0),
+                );
+
+                self.video_context.VideoProcessorSetStreamFrameFormat(
+                    &self.video_processor,
+                    1,
+                    D3D11_VIDEO_FRAME_FORMAT_PROGRESSIVE,
+                );
+            }
+
+            self.overlay = Some(view);
+            Ok(())
+        }
+
         pub fn get_output(&self) -> &ID3D11Texture2D {
             &self.output_texture
         }
 
         pub fn get_output_buffer(&mut self) -> Result<TextureBuffer, Error> {
-            Ok(TextureBuffer::new(
-                &self.d3d_device,
+            let staging = match self.staging.as_ref() {
+                Some(it) => it.clone(),
+                None => self
+                    .staging
+                    .insert(TextureBuffer::create_staging(
+                        &self.d3d_device,
+                        &self.output_texture,
+                    )?)
+                    .clone(),
+            };
+
+            Ok(TextureBuffer::with_staging(
                 &self.d3d_context,
+                staging,
                 &self.output_texture,
             )?)
         }
@@ -371,25 +600,94 @@ pub mod win32 {
             &mut self,
             input_view: Option<ID3D11VideoProcessorInputView>,
         ) -> Result<(), Error> {
-            unsafe {
-                let mut streams = [D3D11_VIDEO_PROCESSOR_STREAM::default()];
-                streams[0].Enable = true.into();
-                streams[0].OutputIndex = 0;
-                streams[0].InputFrameOrField = 0;
-                streams[0].pInputSurface =
-                    ManuallyDrop::new(Some(input_view.unwrap_or_else(|| self.input_view.clone())));
+            self.blt(input_view, &self.output_view)
+        }
+
+        /// Perform the conversion into a texture taken from the pool instead
+        /// of the internal output texture, so that the output of the previous
+        /// frames can still be read, such as by the encoder, while the next
+        /// frame is processed. The textures of the pool must have the
+        /// description of the output texture.
+        pub fn process_into(
+            &mut self,
+            input_view: Option<ID3D11VideoProcessorInputView>,
+            pool: &TexturePool,
+        ) -> Result<PooledTexture, Error> {
+            let texture = pool.get()?;
+
+            let index = if let Some(index) = self
+                .pooled_views
+                .iter()
+                .position(|(it, _)| it == texture.texture())
+            {
+                index
+            } else {
+                if self.pooled_views.len() >= Self::MAX_POOLED_VIEWS {
+                    self.pooled_views.remove(0);
+                }
+
+                let view = unsafe {
+                    let mut desc = D3D11_VIDEO_PROCESSOR_OUTPUT_VIEW_DESC::default();
+                    desc.ViewDimension = D3D11_VPOV_DIMENSION_TEXTURE2D;
+
+                    let mut view = None;
+                    self.video_device.CreateVideoProcessorOutputView(
+                        texture.texture(),
+                        &self.video_enumerator,
+                        &desc,
+                        Some(&mut view),
+                    )?;
+
+                    view.unwrap()
+                };
+
+                self.pooled_views.push((texture.texture().clone(), view));
+                self.pooled_views.len() - 1
+            };
+
+            self.blt(input_view, &self.pooled_views[index].1)?;
+            Ok(texture)
+        }
+
+        fn blt(
+            &self,
+            input_view: Option<ID3D11VideoProcessorInputView>,
+            output_view: &ID3D11VideoProcessorOutputView,
+        ) -> Result<(), Error> {
+            let stream = |view: ID3D11VideoProcessorInputView| {
+                let mut stream = D3D11_VIDEO_PROCESSOR_STREAM::default();
+                stream.Enable = true.into();
+                stream.OutputIndex = 0;
+                stream.InputFrameOrField = 0;
+                stream.pInputSurface = ManuallyDrop::new(Some(view));
+                stream
+            };
 
+            // The overlay is the second stream, it is blended over the first one.
+            let mut streams = vec![stream(
+                input_view.unwrap_or_else(|| self.input_view.clone()),
+            )];
+
+            if let Some(view) = self.overlay.clone() {
+                streams.push(stream(view));
+            }
+
+            let result = unsafe {
                 self.video_context.VideoProcessorBlt(
                     &self.video_processor,
-                    &self.output_view,
+                    output_view,
                     0,
                     &streams,
-                )?;
+                )
+            };
 
-                ManuallyDrop::drop(&mut streams[0].pInputSurface);
+            for stream in streams.iter_mut() {
+                unsafe {
+                    ManuallyDrop::drop(&mut stream.pInputSurface);
+                }
             }
 
-            Ok(())
+            result
         }
     }
 
@@ -408,20 +706,21 @@ pub mod win32 {
             d3d_context: &'a ID3D11DeviceContext,
             source_texture: &ID3D11Texture2D,
         ) -> Result<Self, Error> {
-            let texture = unsafe {
-                let mut desc = D3D11_TEXTURE2D_DESC::default();
-                source_texture.GetDesc(&mut desc);
-
-                desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
-                desc.Usage = D3D11_USAGE_STAGING;
-                desc.BindFlags = 0;
-                desc.MiscFlags = 0;
-
-                let mut texture = None;
-                d3d_device.CreateTexture2D(&desc, None, Some(&mut texture))?;
-                texture.unwrap()
-            };
+            Self::with_staging(
+                d3d_context,
+                Self::create_staging(d3d_device, source_texture)?,
+                source_texture,
+            )
+        }
 
+        /// Copy the texture to a staging texture that has been created with
+        /// `create_staging` for the same texture description, so that the
+        /// staging texture is reused for each frame.
+        pub fn with_staging(
+            d3d_context: &'a ID3D11DeviceContext,
+            texture: ID3D11Texture2D,
+            source_texture: &ID3D11Texture2D,
+        ) -> Result<Self, Error> {
             unsafe {
                 d3d_context.CopyResource(&texture, source_texture);
             }
@@ -438,6 +737,26 @@ pub mod win32 {
             })
         }
 
+        /// Create the staging texture that the CPU reads the texture from.
+        pub fn create_staging(
+            d3d_device: &ID3D11Device,
+            source_texture: &ID3D11Texture2D,
+        ) -> Result<ID3D11Texture2D, Error> {
+            Ok(unsafe {
+                let mut desc = D3D11_TEXTURE2D_DESC::default();
+                source_texture.GetDesc(&mut desc);
+
+                desc.CPUAccessFlags = D3D11_CPU_ACCESS_READ.0 as u32;
+                desc.Usage = D3D11_USAGE_STAGING;
+                desc.BindFlags = 0;
+                desc.MiscFlags = 0;
+
+                let mut texture = None;
+                d3d_device.CreateTexture2D(&desc, None, Some(&mut texture))?;
+                texture.unwrap()
+            })
+        }
+
         /// Represents a pointer to texture data. Internally, the texture is
         /// copied to the CPU first, and then the internal data is
         /// mapped.
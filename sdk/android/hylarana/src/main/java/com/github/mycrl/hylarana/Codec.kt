@@ -29,6 +29,12 @@ class Video {
         private var surface: Surface? = null
         private var worker: Thread
 
+        /**
+         * The orientation bits carried in the high bits of the buffer flags, see [setOrientation].
+         */
+        @Volatile
+        private var orientation: Int = 0
+
         init {
             val format = MediaFormat.createVideoFormat(MediaFormat.MIMETYPE_VIDEO_AVC, configure.width, configure.height)
             format.setInteger(MediaFormat.KEY_BITRATE_MODE, MediaCodecInfo.EncoderCapabilities.BITRATE_MODE_VBR)
@@ -81,7 +87,7 @@ class Video {
                         if (index >= 0) {
                             val outputBuffer = codec.getOutputBuffer(index)
                             if (outputBuffer != null && bufferInfo.size > 0) {
-                                streamBufferInfo.flags = bufferInfo.flags
+                                streamBufferInfo.flags = (bufferInfo.flags and 0x0F) or orientation
                                 streamBufferInfo.timestamp = bufferInfo.presentationTimeUs
                                 outputBuffer.get(buffer, 0, bufferInfo.size)
 
@@ -115,6 +121,15 @@ class Video {
             return surface
         }
 
+        /**
+         * Set the clockwise rotation (0/90/180/270) and horizontal mirroring of the frames, the
+         * receiver rotates the frames back when rendering.
+         */
+        fun setOrientation(rotation: Int, mirror: Boolean) {
+            val turns = (Math.floorMod(rotation + 45, 360) / 90) and 0x3
+            orientation = (turns shl 4) or (if (mirror) 1 shl 6 else 0)
+        }
+
         fun start() {
             if (!isRunning) {
                 isRunning = true
@@ -197,7 +212,7 @@ class Video {
                 if (index >= 0) {
                     codec.getInputBuffer(index)?.clear()
                     codec.getInputBuffer(index)?.put(buf)
-                    codec.queueInputBuffer(index, 0, buf.size, timestamp, flags)
+                    codec.queueInputBuffer(index, 0, buf.size, timestamp, flags and 0x0F)
                 }
             } catch (e: Exception) {
                 Log.w("com.github.mycrl.hylarana", "VideoDecoder sink exception", e)
@@ -307,7 +322,7 @@ class Audio {
             if (index >= 0) {
                 codec.getInputBuffer(index)?.clear()
                 codec.getInputBuffer(index)?.put(buf)
-                codec.queueInputBuffer(index, 0, buf.size, timestamp, flags)
+                codec.queueInputBuffer(index, 0, buf.size, timestamp, flags and 0x0F)
             }
         }
 
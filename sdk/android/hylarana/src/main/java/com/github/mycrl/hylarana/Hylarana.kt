@@ -45,6 +45,8 @@ data class TransportOptions(
     val strategy: TransportStrategy,
     /**
      * see: [Maximum_transmission_unit](https://en.wikipedia.org/wiki/Maximum_transmission_unit)
+     *
+     * Zero discovers the MTU of the path to the peer.
      */
     val mtu: Int
 )
@@ -129,10 +131,17 @@ internal class Hylarana {
         )
     }
 
+    /**
+     * `code` is the pairing code of the sender, null if the sender does not
+     * require one. A wrong code fails the creation.
+     */
     fun createReceiver(
-        id: String, options: TransportOptions, observer: HylaranaReceiverAdapterObserver
+        id: String,
+        options: TransportOptions,
+        observer: HylaranaReceiverAdapterObserver,
+        code: String? = null,
     ): HylaranaReceiverAdapter {
-        var receiver = createTransportReceiver(id, options, observer)
+        var receiver = createTransportReceiver(id, options, observer, code)
         if (receiver == 0L) {
             throw Exception("failed to create transport receiver")
         }
@@ -186,6 +195,7 @@ internal class Hylarana {
         id: String,
         options: TransportOptions,
         observer: HylaranaReceiverAdapterObserver,
+        code: String?,
     ): Long
 
     /**
@@ -101,11 +101,14 @@ class HylaranaService {
          * than auto-discovery, and the handshake will take less time.
          *
          * `port` The port number from the created sender.
+         *
+         * `code` The pairing code of the sender, null if the sender does not require one.
          */
         fun createReceiver(
             id: String,
             options: HylaranaOptions,
-            observer: HylaranaReceiverObserver
+            observer: HylaranaReceiverObserver,
+            code: String? = null,
         ): HylaranaReceiver {
             return HylaranaReceiver(
                 hylarana.createReceiver(
@@ -179,7 +182,8 @@ class HylaranaService {
                                 )
                             }
                         }
-                    }
+                    },
+                    code,
                 )
             )
         }
@@ -242,6 +246,14 @@ class HylaranaSender(
         videoEncoder.sink(frame)
     }
 
+    /**
+     * Set the clockwise rotation (0/90/180/270) of the frames and whether they are mirrored, for
+     * example for the front camera, this is sent to the receivers with the video stream.
+     */
+    fun setOrientation(rotation: Int, mirror: Boolean) {
+        videoEncoder.setOrientation(rotation, mirror)
+    }
+
     fun pushAudioFrame(chunk: ByteArray) {
         audioEncoder.sink(chunk)
     }
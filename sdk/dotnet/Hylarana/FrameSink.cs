@@ -0,0 +1,171 @@
+using System;
+using System.Runtime.InteropServices;
+
+namespace Hylarana
+{
+    /// <summary>
+    /// Receives the frames of a sender or a receiver through delegates. The
+    /// delegates are called on the threads of the codecs, do not block in them,
+    /// and dispatch to the UI thread if the UI is updated, such as with
+    /// <c>Dispatcher.BeginInvoke</c> in WPF.
+    /// </summary>
+    public class FrameSink
+    {
+        /// <summary>
+        /// Called for each video frame, returning false closes the stream.
+        /// </summary>
+        public Func<VideoFrame, bool>? OnVideo { get; set; }
+
+        /// <summary>
+        /// Called for each audio frame, returning false closes the stream.
+        /// </summary>
+        public Func<AudioFrame, bool>? OnAudio { get; set; }
+
+        /// <summary>
+        /// Called when the stream is closed, such as when the sender stops.
+        /// </summary>
+        public Action? OnClose { get; set; }
+
+        // The delegates are kept in static fields, so that they are not
+        // collected while the library holds the function pointers.
+        private static readonly Native.VideoCallback VideoCallback = OnVideoFrame;
+        private static readonly Native.AudioCallback AudioCallback = OnAudioFrame;
+        private static readonly Native.CloseCallback CloseCallback = OnClosed;
+
+        internal static readonly IntPtr ClosePointer = Marshal.GetFunctionPointerForDelegate(CloseCallback);
+
+        internal Native.FrameSink ToNative(GCHandle handle) => new Native.FrameSink
+        {
+            Video = Marshal.GetFunctionPointerForDelegate(VideoCallback),
+            Audio = Marshal.GetFunctionPointerForDelegate(AudioCallback),
+            Close = ClosePointer,
+            Ctx = GCHandle.ToIntPtr(handle),
+        };
+
+        private static FrameSink FromContext(IntPtr ctx) => (FrameSink)GCHandle.FromIntPtr(ctx).Target!;
+
+        // The exceptions must not unwind into the library.
+        private static byte OnVideoFrame(IntPtr ctx, ref VideoFrame frame)
+        {
+            try
+            {
+                return (byte)(FromContext(ctx).OnVideo?.Invoke(frame) ?? true ? 1 : 0);
+            }
+            catch (Exception)
+            {
+                return 0;
+            }
+        }
+
+        private static byte OnAudioFrame(IntPtr ctx, ref AudioFrame frame)
+        {
+            try
+            {
+                return (byte)(FromContext(ctx).OnAudio?.Invoke(frame) ?? true ? 1 : 0);
+            }
+            catch (Exception)
+            {
+                return 0;
+            }
+        }
+
+        private static void OnClosed(IntPtr ctx)
+        {
+            try
+            {
+                FromContext(ctx).OnClose?.Invoke();
+            }
+            catch (Exception)
+            {
+            }
+        }
+    }
+
+    /// <summary>
+    /// The options of the renderer that plays the stream in a window, the
+    /// window is a HWND on Windows, such as the handle of a <c>HwndHost</c> in
+    /// WPF, or a NSView on macOS.
+    /// </summary>
+    public class RendererOptions
+    {
+        public IntPtr Window { get; set; }
+        /// <summary>The size of the window in physical pixels.</summary>
+        public uint Width { get; set; }
+        public uint Height { get; set; }
+        public VideoRenderBackend Backend { get; set; } = VideoRenderBackend.WebGPU;
+        public FitMode Fit { get; set; } = FitMode.Contain;
+        public ScaleFilter Filter { get; set; } = ScaleFilter.Bilinear;
+        /// <summary>Whether the audio of the stream is played.</summary>
+        public bool Audio { get; set; } = true;
+
+        /// <summary>Called when the stream is closed.</summary>
+        public Action? OnClose { get; set; }
+
+        internal Native.PlayerOptions ToNative(GCHandle handle) => new Native.PlayerOptions
+        {
+            Options = new Native.AVFrameStreamPlayerOptions
+            {
+                Type = Audio ? Native.PlayerType.All : Native.PlayerType.OnlyVideo,
+                Video = new Native.VideoRenderOptions
+                {
+                    Window = new Native.WindowOptions
+                    {
+                        Type = RuntimeInformation.IsOSPlatform(OSPlatform.OSX)
+                            ? Native.WindowType.Appkit
+                            : Native.WindowType.Win32,
+                        Handle = Window,
+                        Width = Width,
+                        Height = Height,
+                    },
+                    Backend = Backend,
+                    Fit = Fit,
+                    Filter = Filter,
+                },
+            },
+            Close = FrameSink.ClosePointer,
+            Ctx = GCHandle.ToIntPtr(handle),
+        };
+
+        // The close callback of the renderer shares the callback of the sinks.
+        internal FrameSink ToSink() => new FrameSink { OnClose = OnClose };
+    }
+
+    /// <summary>
+    /// An RGBA image read back from a renderer.
+    /// </summary>
+    public class Snapshot
+    {
+        public uint Width { get; }
+        public uint Height { get; }
+        public byte[] Data { get; }
+
+        internal Snapshot(uint width, uint height, byte[] data)
+        {
+            Width = width;
+            Height = height;
+            Data = data;
+        }
+
+        internal delegate byte Reader(byte[] buf, UIntPtr len, out uint width, out uint height);
+
+        // The first call only gets the size of the image.
+        internal static Snapshot? Read(Reader reader)
+        {
+            var probe = new byte[1];
+            if (reader(probe, (UIntPtr)probe.Length, out var width, out var height) != 0)
+            {
+                return null;
+            }
+
+            if (width == 0 || height == 0)
+            {
+                return null;
+            }
+
+            var data = new byte[width * height * 4];
+            return reader(data, (UIntPtr)data.Length, out width, out height) != 0
+                ? new Snapshot(width, height, data)
+                : null;
+        }
+    }
+}
@@ -0,0 +1,238 @@
+using System;
+using System.Runtime.InteropServices;
+
+namespace Hylarana
+{
+    // The layouts of the structures in `ffi/include/hylarana.h`, the booleans
+    // are one byte in C, so they are declared as bytes to keep the structures
+    // blittable.
+    internal static class Native
+    {
+        private const string Library = "hylarana";
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public delegate byte VideoCallback(IntPtr ctx, ref VideoFrame frame);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public delegate byte AudioCallback(IntPtr ctx, ref AudioFrame frame);
+
+        [UnmanagedFunctionPointer(CallingConvention.Cdecl)]
+        public delegate void CloseCallback(IntPtr ctx);
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct Source
+        {
+            public UIntPtr Index;
+            public SourceType Type;
+            public IntPtr Id;
+            public IntPtr Name;
+            public byte IsDefault;
+            public VideoRotation Rotation;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct Sources
+        {
+            public IntPtr Items;
+            public UIntPtr Capacity;
+            public UIntPtr Size;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct TransportOptions
+        {
+            public TransportStrategy Strategy;
+            public IntPtr Address;
+            public UIntPtr Mtu;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct VideoEncoderOptions
+        {
+            public VideoEncoderType Codec;
+            public byte FrameRate;
+            public uint Width;
+            public uint Height;
+            public ulong BitRate;
+            public uint KeyFrameInterval;
+            public byte AdaptivePacing;
+            public uint CaptureWidth;
+            public uint CaptureHeight;
+            public VideoProfile Profile;
+            public byte Level;
+            public RateControl RateControl;
+            public ulong MaxBitRate;
+            public byte Qp;
+            public byte BFrames;
+            public byte Slices;
+            public byte LowLatency;
+            public byte IntraRefresh;
+            public ContentHint ContentHint;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct AudioEncoderOptions
+        {
+            public ulong SampleRate;
+            public ulong BitRate;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct VideoTrackOptions
+        {
+            public IntPtr Source;
+            public VideoEncoderOptions Options;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct AudioTrackOptions
+        {
+            public IntPtr Source;
+            public AudioEncoderOptions Options;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct SenderOptions
+        {
+            public IntPtr Video;
+            public IntPtr Audio;
+            public TransportOptions Transport;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct ReceiverOptions
+        {
+            public VideoDecoderType Video;
+            public TransportOptions Transport;
+        }
+
+        // Only the win32 and the appkit windows are supported, they have the
+        // same layout, the union is as large as the xlib window.
+        [StructLayout(LayoutKind.Explicit, Size = 40)]
+        public struct WindowOptions
+        {
+            [FieldOffset(0)]
+            public WindowType Type;
+            [FieldOffset(8)]
+            public IntPtr Handle;
+            [FieldOffset(16)]
+            public uint Width;
+            [FieldOffset(20)]
+            public uint Height;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct VideoRenderOptions
+        {
+            public WindowOptions Window;
+            public VideoRenderBackend Backend;
+            public FitMode Fit;
+            public ScaleFilter Filter;
+        }
+
+        [StructLayout(LayoutKind.Explicit, Size = 64)]
+        public struct AVFrameStreamPlayerOptions
+        {
+            [FieldOffset(0)]
+            public PlayerType Type;
+            [FieldOffset(8)]
+            public VideoRenderOptions Video;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct PlayerOptions
+        {
+            public AVFrameStreamPlayerOptions Options;
+            public IntPtr Close;
+            public IntPtr Ctx;
+        }
+
+        [StructLayout(LayoutKind.Sequential)]
+        public struct FrameSink
+        {
+            public IntPtr Video;
+            public IntPtr Audio;
+            public IntPtr Close;
+            public IntPtr Ctx;
+        }
+
+        public enum WindowType
+        {
+            Win32,
+            Xlib,
+            Wayland,
+            Appkit,
+        }
+
+        public enum PlayerType
+        {
+            All,
+            OnlyVideo,
+            OnlyAudio,
+            Quiet,
+        }
+
+        [DllImport(Library)]
+        public static extern byte hylarana_startup();
+
+        [DllImport(Library)]
+        public static extern void hylarana_shutdown();
+
+        [DllImport(Library)]
+        public static extern void hylarana_set_adapter_preference(AdapterPreference preference, uint vendor, uint device);
+
+        [DllImport(Library)]
+        public static extern Sources hylarana_get_sources(SourceType kind);
+
+        [DllImport(Library)]
+        public static extern void hylarana_sources_destroy(ref Sources sources);
+
+        [DllImport(Library)]
+        public static extern IntPtr hylarana_create_sender(SenderOptions options, FrameSink sink, byte[] id);
+
+        [DllImport(Library)]
+        public static extern void hylarana_sender_destroy(IntPtr sender);
+
+        [DllImport(Library)]
+        public static extern byte hylarana_sender_pause(IntPtr sender);
+
+        [DllImport(Library)]
+        public static extern byte hylarana_sender_resume(IntPtr sender);
+
+        [DllImport(Library)]
+        public static extern IntPtr hylarana_create_sender_with_player(SenderOptions options, PlayerOptions player, byte[] id);
+
+        [DllImport(Library)]
+        public static extern void hylarana_sender_with_player_destroy(IntPtr sender);
+
+        [DllImport(Library)]
+        public static extern byte hylarana_sender_with_player_pause(IntPtr sender);
+
+        [DllImport(Library)]
+        public static extern byte hylarana_sender_with_player_resume(IntPtr sender);
+
+        [DllImport(Library)]
+        public static extern byte hylarana_sender_renderer_resize(IntPtr sender, uint width, uint height);
+
+        [DllImport(Library)]
+        public static extern byte hylarana_sender_renderer_snapshot(IntPtr sender, byte[] buf, UIntPtr len, out uint width, out uint height);
+
+        [DllImport(Library)]
+        public static extern IntPtr hylarana_create_receiver([MarshalAs(UnmanagedType.LPStr)] string id, ReceiverOptions options, FrameSink sink);
+
+        [DllImport(Library)]
+        public static extern void hylarana_receiver_destroy(IntPtr receiver);
+
+        [DllImport(Library)]
+        public static extern IntPtr hylarana_create_receiver_with_player([MarshalAs(UnmanagedType.LPStr)] string id, ReceiverOptions options, PlayerOptions player);
+
+        [DllImport(Library)]
+        public static extern void hylarana_receiver_with_player_destroy(IntPtr receiver);
+
+        [DllImport(Library)]
+        public static extern byte hylarana_receiver_renderer_resize(IntPtr receiver, uint width, uint height);
+
+        [DllImport(Library)]
+        public static extern byte hylarana_receiver_renderer_snapshot(IntPtr receiver, byte[] buf, UIntPtr len, out uint width, out uint height);
+    }
+}
@@ -0,0 +1,122 @@
+using System;
+using System.Runtime.InteropServices;
+
+namespace Hylarana
+{
+    /// <summary>
+    /// Receives a stream from a sender, dispose the receiver to stop it.
+    /// </summary>
+    public sealed class Receiver : IDisposable
+    {
+        private IntPtr receiver;
+        private GCHandle handle;
+        private readonly bool player;
+
+        private Receiver(IntPtr receiver, GCHandle handle, bool player)
+        {
+            this.receiver = receiver;
+            this.handle = handle;
+            this.player = player;
+        }
+
+        /// <summary>
+        /// Create a receiver of the stream with the id, the decoded frames are
+        /// passed to the sink.
+        /// </summary>
+        public static Receiver Create(string id, ReceiverOptions options, FrameSink sink)
+        {
+            var handle = GCHandle.Alloc(sink);
+
+            using var allocations = new Allocations();
+            var pointer = Native.hylarana_create_receiver(id, ToNative(options, allocations), sink.ToNative(handle));
+            return Created(pointer, handle, false);
+        }
+
+        /// <summary>
+        /// Create a receiver of the stream with the id that plays the stream in
+        /// a window.
+        /// </summary>
+        public static Receiver Create(string id, ReceiverOptions options, RendererOptions renderer)
+        {
+            var handle = GCHandle.Alloc(renderer.ToSink());
+
+            using var allocations = new Allocations();
+            var pointer = Native.hylarana_create_receiver_with_player(id, ToNative(options, allocations), renderer.ToNative(handle));
+            return Created(pointer, handle, true);
+        }
+
+        private static Receiver Created(IntPtr pointer, GCHandle handle, bool player)
+        {
+            if (pointer == IntPtr.Zero)
+            {
+                handle.Free();
+                throw new HylaranaException("failed to create the receiver");
+            }
+
+            return new Receiver(pointer, handle, player);
+        }
+
+        private static Native.ReceiverOptions ToNative(ReceiverOptions options, Allocations allocations) => new Native.ReceiverOptions
+        {
+            Video = options.Video,
+            Transport = allocations.Transport(options.Transport),
+        };
+
+        /// <summary>
+        /// Resize the renderer when the window is resized, the size is in
+        /// physical pixels.
+        /// </summary>
+        public void Resize(uint width, uint height)
+        {
+            EnsurePlayer();
+
+            if (Native.hylarana_receiver_renderer_resize(Pointer, width, height) == 0)
+            {
+                throw new HylaranaException("failed to resize the renderer");
+            }
+        }
+
+        /// <summary>
+        /// Read back the last rendered frame, null if nothing is rendered yet.
+        /// </summary>
+        public Snapshot? Snapshot()
+        {
+            EnsurePlayer();
+
+            var pointer = Pointer;
+            return Hylarana.Snapshot.Read((byte[] buf, UIntPtr len, out uint width, out uint height) =>
+                Native.hylarana_receiver_renderer_snapshot(pointer, buf, len, out width, out height));
+        }
+
+        public void Dispose()
+        {
+            if (receiver == IntPtr.Zero)
+            {
+                return;
+            }
+
+            if (player)
+            {
+                Native.hylarana_receiver_with_player_destroy(receiver);
+            }
+            else
+            {
+                Native.hylarana_receiver_destroy(receiver);
+            }
+
+            // The callbacks are no longer called after the receiver is destroyed.
+            receiver = IntPtr.Zero;
+            handle.Free();
+        }
+
+        private IntPtr Pointer => receiver != IntPtr.Zero ? receiver : throw new ObjectDisposedException(nameof(Receiver));
+
+        private void EnsurePlayer()
+        {
+            if (!player)
+            {
+                throw new InvalidOperationException("the receiver does not have a renderer");
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+using System;
+using System.Collections.Generic;
+using System.Runtime.InteropServices;
+
+namespace Hylarana
+{
+    /// <summary>
+    /// A capture source, such as a screen, a camera or an audio device.
+    /// </summary>
+    public class Source
+    {
+        public SourceType Type { get; }
+        public string Id { get; }
+        public string Name { get; }
+        public bool IsDefault { get; }
+        public VideoRotation Rotation { get; }
+
+        private readonly UIntPtr index;
+
+        internal Source(Native.Source raw)
+        {
+            Type = raw.Type;
+            Id = Marshal.PtrToStringAnsi(raw.Id) ?? "";
+            Name = Marshal.PtrToStringAnsi(raw.Name) ?? "";
+            IsDefault = raw.IsDefault != 0;
+            Rotation = raw.Rotation;
+            index = raw.Index;
+        }
+
+        internal IntPtr ToNative(Allocations allocations) => allocations.Struct(new Native.Source
+        {
+            Index = index,
+            Type = Type,
+            Id = allocations.String(Id),
+            Name = allocations.String(Name),
+            IsDefault = (byte)(IsDefault ? 1 : 0),
+            Rotation = Rotation,
+        });
+
+        public override string ToString() => $"{Type}: {Name} ({Id})";
+    }
+
+    // The native memory of the options that is released after the options are
+    // passed to the library.
+    internal sealed class Allocations : IDisposable
+    {
+        private readonly List<IntPtr> pointers = new List<IntPtr>();
+
+        public IntPtr String(string value)
+        {
+            var pointer = Marshal.StringToHGlobalAnsi(value);
+            pointers.Add(pointer);
+            return pointer;
+        }
+
+        public IntPtr Struct<T>(T value) where T : struct
+        {
+            var pointer = Marshal.AllocHGlobal(Marshal.SizeOf<T>());
+            Marshal.StructureToPtr(value, pointer, false);
+            pointers.Add(pointer);
+            return pointer;
+        }
+
+        public Native.TransportOptions Transport(TransportOptions options) => new Native.TransportOptions
+        {
+            Strategy = options.Strategy,
+            Address = String(options.Address),
+            Mtu = (UIntPtr)options.Mtu,
+        };
+
+        public void Dispose()
+        {
+            foreach (var pointer in pointers)
+            {
+                Marshal.FreeHGlobal(pointer);
+            }
+
+            pointers.Clear();
+        }
+    }
+
+    public static class Sdk
+    {
+        /// <summary>
+        /// Initialize the environment, this must be called before using the
+        /// SDK. On Windows this is done when the library is loaded.
+        /// </summary>
+        public static void Startup()
+        {
+            if (!RuntimeInformation.IsOSPlatform(OSPlatform.Windows) && Native.hylarana_startup() == 0)
+            {
+                throw new HylaranaException("failed to initialize the environment");
+            }
+        }
+
+        /// <summary>
+        /// Clean up the environment, it is recommended to call this when the
+        /// application exits.
+        /// </summary>
+        public static void Shutdown()
+        {
+            if (!RuntimeInformation.IsOSPlatform(OSPlatform.Windows))
+            {
+                Native.hylarana_shutdown();
+            }
+        }
+
+        /// <summary>
+        /// Set the graphics adapter used by the capture, the codecs and the
+        /// renderers, the vendor id and the device id are only used by the
+        /// device preference.
+        /// </summary>
+        public static void SetAdapterPreference(AdapterPreference preference, uint vendor = 0, uint device = 0)
+        {
+            Native.hylarana_set_adapter_preference(preference, vendor, device);
+        }
+
+        /// <summary>
+        /// Get the capture sources of the type.
+        /// </summary>
+        public static IReadOnlyList<Source> GetSources(SourceType type)
+        {
+            var sources = Native.hylarana_get_sources(type);
+            try
+            {
+                var items = new List<Source>((int)sources.Size);
+                var size = Marshal.SizeOf<Native.Source>();
+                for (var i = 0; i < (int)sources.Size; i++)
+                {
+                    items.Add(new Source(Marshal.PtrToStructure<Native.Source>(sources.Items + i * size)));
+                }
+
+                return items;
+            }
+            finally
+            {
+                Native.hylarana_sources_destroy(ref sources);
+            }
+        }
+    }
+}
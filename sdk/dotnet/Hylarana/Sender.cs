@@ -0,0 +1,169 @@
+using System;
+using System.Runtime.InteropServices;
+using System.Text;
+
+namespace Hylarana
+{
+    /// <summary>
+    /// Captures the sources and sends them to the receivers, dispose the
+    /// sender to stop it.
+    /// </summary>
+    public sealed class Sender : IDisposable
+    {
+        /// <summary>
+        /// The id of the stream, which is passed to the receivers.
+        /// </summary>
+        public string Id { get; }
+
+        private IntPtr sender;
+        private GCHandle handle;
+        private readonly bool player;
+
+        private Sender(IntPtr sender, GCHandle handle, bool player, byte[] id)
+        {
+            var length = Array.IndexOf(id, (byte)0);
+            Id = Encoding.ASCII.GetString(id, 0, length < 0 ? id.Length : length);
+            this.sender = sender;
+            this.handle = handle;
+            this.player = player;
+        }
+
+        /// <summary>
+        /// Create a sender, the captured frames are passed to the sink, such
+        /// as for a local preview.
+        /// </summary>
+        public static Sender Create(SenderOptions options, FrameSink sink)
+        {
+            var handle = GCHandle.Alloc(sink);
+            var id = new byte[256];
+
+            using var allocations = new Allocations();
+            var pointer = Native.hylarana_create_sender(ToNative(options, allocations), sink.ToNative(handle), id);
+            return Created(pointer, handle, false, id);
+        }
+
+        /// <summary>
+        /// Create a sender that previews the captured sources in a window.
+        /// </summary>
+        public static Sender Create(SenderOptions options, RendererOptions renderer)
+        {
+            var handle = GCHandle.Alloc(renderer.ToSink());
+            var id = new byte[256];
+
+            using var allocations = new Allocations();
+            var pointer = Native.hylarana_create_sender_with_player(ToNative(options, allocations), renderer.ToNative(handle), id);
+            return Created(pointer, handle, true, id);
+        }
+
+        private static Sender Created(IntPtr pointer, GCHandle handle, bool player, byte[] id)
+        {
+            if (pointer == IntPtr.Zero)
+            {
+                handle.Free();
+                throw new HylaranaException("failed to create the sender");
+            }
+
+            return new Sender(pointer, handle, player, id);
+        }
+
+        private static Native.SenderOptions ToNative(SenderOptions options, Allocations allocations) => new Native.SenderOptions
+        {
+            Video = options.Video is { } video
+                ? allocations.Struct(new Native.VideoTrackOptions
+                {
+                    Source = video.Source.ToNative(allocations),
+                    Options = video.Options.ToNative(),
+                })
+                : IntPtr.Zero,
+            Audio = options.Audio is { } audio
+                ? allocations.Struct(new Native.AudioTrackOptions
+                {
+                    Source = audio.Source.ToNative(allocations),
+                    Options = new Native.AudioEncoderOptions
+                    {
+                        SampleRate = audio.Options.SampleRate,
+                        BitRate = audio.Options.BitRate,
+                    },
+                })
+                : IntPtr.Zero,
+            Transport = allocations.Transport(options.Transport),
+        };
+
+        /// <summary>
+        /// Pause the capture, the receivers keep the last frame until the
+        /// sender resumes.
+        /// </summary>
+        public void Pause()
+        {
+            if ((player ? Native.hylarana_sender_with_player_pause(Pointer) : Native.hylarana_sender_pause(Pointer)) == 0)
+            {
+                throw new HylaranaException("failed to pause the sender");
+            }
+        }
+
+        public void Resume()
+        {
+            if ((player ? Native.hylarana_sender_with_player_resume(Pointer) : Native.hylarana_sender_resume(Pointer)) == 0)
+            {
+                throw new HylaranaException("failed to resume the sender");
+            }
+        }
+
+        /// <summary>
+        /// Resize the renderer when the window is resized, the size is in
+        /// physical pixels.
+        /// </summary>
+        public void Resize(uint width, uint height)
+        {
+            EnsurePlayer();
+
+            if (Native.hylarana_sender_renderer_resize(Pointer, width, height) == 0)
+            {
+                throw new HylaranaException("failed to resize the renderer");
+            }
+        }
+
+        /// <summary>
+        /// Read back the last rendered frame, null if nothing is rendered yet.
+        /// </summary>
+        public Snapshot? Snapshot()
+        {
+            EnsurePlayer();
+
+            var pointer = Pointer;
+            return Hylarana.Snapshot.Read((byte[] buf, UIntPtr len, out uint width, out uint height) =>
+                Native.hylarana_sender_renderer_snapshot(pointer, buf, len, out width, out height));
+        }
+
+        public void Dispose()
+        {
+            if (sender == IntPtr.Zero)
+            {
+                return;
+            }
+
+            if (player)
+            {
+                Native.hylarana_sender_with_player_destroy(sender);
+            }
+            else
+            {
+                Native.hylarana_sender_destroy(sender);
+            }
+
+            // The callbacks are no longer called after the sender is destroyed.
+            sender = IntPtr.Zero;
+            handle.Free();
+        }
+
+        private IntPtr Pointer => sender != IntPtr.Zero ? sender : throw new ObjectDisposedException(nameof(Sender));
+
+        private void EnsurePlayer()
+        {
+            if (!player)
+            {
+                throw new InvalidOperationException("the sender does not have a renderer");
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+using System;
+using System.Runtime.InteropServices;
+
+namespace Hylarana
+{
+    public enum VideoFormat
+    {
+        Bgra,
+        Rgba,
+        Nv12,
+        I420,
+        P010,
+    }
+
+    public enum VideoSubFormat
+    {
+        /// <summary>The frame is a CVPixelBufferRef, only on macOS.</summary>
+        CvPixelBuffer,
+        /// <summary>The frame is an ID3D11Texture2D, only on Windows.</summary>
+        D3D11,
+        /// <summary>The planes of the frame are in the memory.</summary>
+        Software,
+        /// <summary>The planes are DMA-BUF file descriptors, only on Linux.</summary>
+        DmaBuf,
+    }
+
+    public enum FieldOrder
+    {
+        Progressive,
+        TopFieldFirst,
+        BottomFieldFirst,
+    }
+
+    public enum VideoRotation
+    {
+        Rotate0,
+        Rotate90,
+        Rotate180,
+        Rotate270,
+    }
+
+    public enum SourceType
+    {
+        Camera,
+        Screen,
+        Audio,
+    }
+
+    public enum VideoEncoderType
+    {
+        X264,
+        Qsv,
+        VideoToolbox,
+        X265,
+        HevcQsv,
+    }
+
+    public enum VideoDecoderType
+    {
+        H264,
+        D3D11,
+        Qsv,
+        VideoToolbox,
+        HevcD3D11,
+        HevcQsv,
+    }
+
+    public enum VideoProfile
+    {
+        Default,
+        Baseline,
+        Main,
+        High,
+    }
+
+    public enum RateControl
+    {
+        Default,
+        Cbr,
+        Vbr,
+        Cqp,
+        Lossless,
+    }
+
+    public enum ContentHint
+    {
+        Default,
+        Motion,
+        Detail,
+    }
+
+    public enum TransportStrategy
+    {
+        Direct,
+        Relay,
+        Multicast,
+    }
+
+    public enum VideoRenderBackend
+    {
+        Direct3D11,
+        WebGPU,
+    }
+
+    public enum FitMode
+    {
+        Stretch,
+        Contain,
+        Cover,
+    }
+
+    public enum ScaleFilter
+    {
+        Nearest,
+        Bilinear,
+        Bicubic,
+    }
+
+    public enum AdapterPreference
+    {
+        LowPower,
+        HighPerformance,
+        Device,
+    }
+
+    /// <summary>
+    /// A video frame, the planes are owned by the pipeline and are only valid
+    /// inside the callback, copy them if they are used later.
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct VideoFrame
+    {
+        public VideoFormat Format;
+        public VideoSubFormat SubFormat;
+        public uint Width;
+        public uint Height;
+        public IntPtr Data0;
+        public IntPtr Data1;
+        public IntPtr Data2;
+        public UIntPtr Linesize0;
+        public UIntPtr Linesize1;
+        public UIntPtr Linesize2;
+        public VideoRotation Rotation;
+        private byte mirror;
+        /// <summary>The capture time in microseconds, zero if unknown.</summary>
+        public ulong Timestamp;
+        public FieldOrder FieldOrder;
+        /// <summary>
+        /// The file descriptors of the planes if the subformat is DmaBuf, the
+        /// strides of the planes are the linesizes.
+        /// </summary>
+        public int DmaBufFd0;
+        public int DmaBufFd1;
+        public int DmaBufFd2;
+        public uint DmaBufOffset0;
+        public uint DmaBufOffset1;
+        public uint DmaBufOffset2;
+        /// <summary>The DRM format modifier of the buffers, zero is linear.</summary>
+        public ulong DmaBufModifier;
+        /// <summary>
+        /// The visible area of the frame from the top left corner, the rest
+        /// is padding, zero means the whole frame is visible.
+        /// </summary>
+        public uint DisplayWidth;
+        public uint DisplayHeight;
+
+        public bool Mirror => mirror != 0;
+    }
+
+    /// <summary>
+    /// An audio frame of 16 bits PCM, the samples are only valid inside the
+    /// callback.
+    /// </summary>
+    [StructLayout(LayoutKind.Sequential)]
+    public struct AudioFrame
+    {
+        public int SampleRate;
+        public uint Frames;
+        public IntPtr Data;
+        /// <summary>The capture time in microseconds, zero if unknown.</summary>
+        public ulong Timestamp;
+
+        public unsafe ReadOnlySpan<short> Samples => new ReadOnlySpan<short>((void*)Data, (int)Frames);
+    }
+
+    public class TransportOptions
+    {
+        public TransportStrategy Strategy { get; set; } = TransportStrategy.Direct;
+        /// <summary>
+        /// The address that the sender listens on, or that the receiver
+        /// connects to, such as 192.168.1.100:8080.
+        /// </summary>
+        public string Address { get; set; } = "0.0.0.0:8080";
+        /// <summary>
+        /// The MTU of the transport, zero discovers the MTU of the path to
+        /// the peer.
+        /// </summary>
+        public int Mtu { get; set; } = 1400;
+    }
+
+    public class VideoEncoderOptions
+    {
+        public VideoEncoderType Codec { get; set; } = VideoEncoderType.X264;
+        public byte FrameRate { get; set; } = 30;
+        public uint Width { get; set; } = 1280;
+        public uint Height { get; set; } = 720;
+        public ulong BitRate { get; set; } = 10_000_000;
+        public uint KeyFrameInterval { get; set; } = 30;
+        public bool AdaptivePacing { get; set; }
+        /// <summary>The size of the capture, zero uses the encoder size.</summary>
+        public uint CaptureWidth { get; set; }
+        public uint CaptureHeight { get; set; }
+        public VideoProfile Profile { get; set; } = VideoProfile.Default;
+        /// <summary>Zero lets the encoder choose the level.</summary>
+        public byte Level { get; set; }
+        public RateControl RateControl { get; set; } = RateControl.Default;
+        public ulong MaxBitRate { get; set; }
+        public byte Qp { get; set; }
+        public byte BFrames { get; set; }
+        public byte Slices { get; set; }
+        public bool LowLatency { get; set; } = true;
+        public bool IntraRefresh { get; set; }
+        public ContentHint ContentHint { get; set; } = ContentHint.Default;
+
+        internal Native.VideoEncoderOptions ToNative() => new Native.VideoEncoderOptions
+        {
+            Codec = Codec,
+            FrameRate = FrameRate,
+            Width = Width,
+            Height = Height,
+            BitRate = BitRate,
+            KeyFrameInterval = KeyFrameInterval,
+            AdaptivePacing = (byte)(AdaptivePacing ? 1 : 0),
+            CaptureWidth = CaptureWidth,
+            CaptureHeight = CaptureHeight,
+            Profile = Profile,
+            Level = Level,
+            RateControl = RateControl,
+            MaxBitRate = MaxBitRate,
+            Qp = Qp,
+            BFrames = BFrames,
+            Slices = Slices,
+            LowLatency = (byte)(LowLatency ? 1 : 0),
+            IntraRefresh = (byte)(IntraRefresh ? 1 : 0),
+            ContentHint = ContentHint,
+        };
+    }
+
+    public class AudioEncoderOptions
+    {
+        public ulong SampleRate { get; set; } = 48000;
+        public ulong BitRate { get; set; } = 64000;
+    }
+
+    public class VideoTrackOptions
+    {
+        public Source Source { get; set; }
+        public VideoEncoderOptions Options { get; set; } = new VideoEncoderOptions();
+
+        public VideoTrackOptions(Source source)
+        {
+            Source = source;
+        }
+    }
+
+    public class AudioTrackOptions
+    {
+        public Source Source { get; set; }
+        public AudioEncoderOptions Options { get; set; } = new AudioEncoderOptions();
+
+        public AudioTrackOptions(Source source)
+        {
+            Source = source;
+        }
+    }
+
+    public class SenderOptions
+    {
+        public VideoTrackOptions? Video { get; set; }
+        public AudioTrackOptions? Audio { get; set; }
+        public TransportOptions Transport { get; set; } = new TransportOptions();
+    }
+
+    public class ReceiverOptions
+    {
+        public VideoDecoderType Video { get; set; } = VideoDecoderType.H264;
+        public TransportOptions Transport { get; set; } = new TransportOptions();
+    }
+
+    public class HylaranaException : Exception
+    {
+        public HylaranaException(string message) : base(message)
+        {
+        }
+    }
+}
@@ -0,0 +1,136 @@
+package com.github.mycrl.hylarana_flutter
+
+import android.media.AudioAttributes
+import android.media.AudioFormat
+import android.media.AudioTrack
+import android.os.Handler
+import android.os.Looper
+import android.view.Surface
+import com.github.mycrl.hylarana.HylaranaReceiver
+import com.github.mycrl.hylarana.HylaranaReceiverObserver
+import com.github.mycrl.hylarana.HylaranaService
+import com.github.mycrl.hylarana.TransportOptions
+import com.github.mycrl.hylarana.TransportStrategy
+import io.flutter.embedding.engine.plugins.FlutterPlugin
+import io.flutter.plugin.common.MethodCall
+import io.flutter.plugin.common.MethodChannel
+import io.flutter.view.TextureRegistry
+
+/**
+ * The decoder of the android sdk renders to a surface directly, so the surface of a surface
+ * texture of the engine is given to the receiver.
+ */
+class HylaranaFlutterPlugin : FlutterPlugin, MethodChannel.MethodCallHandler {
+    private class Receiver(
+        val texture: TextureRegistry.SurfaceTextureEntry,
+        val surface: Surface,
+        val track: AudioTrack,
+    ) {
+        var receiver: HylaranaReceiver? = null
+
+        fun release() {
+            receiver?.release()
+            receiver = null
+
+            track.release()
+            surface.release()
+            texture.release()
+        }
+    }
+
+    private lateinit var channel: MethodChannel
+    private lateinit var textures: TextureRegistry
+    private val handler = Handler(Looper.getMainLooper())
+    private val receivers = HashMap<Long, Receiver>()
+
+    override fun onAttachedToEngine(binding: FlutterPlugin.FlutterPluginBinding) {
+        textures = binding.textureRegistry
+        channel = MethodChannel(binding.binaryMessenger, "hylarana_flutter")
+        channel.setMethodCallHandler(this)
+    }
+
+    override fun onDetachedFromEngine(binding: FlutterPlugin.FlutterPluginBinding) {
+        channel.setMethodCallHandler(null)
+
+        for (receiver in receivers.values) {
+            receiver.release()
+        }
+
+        receivers.clear()
+    }
+
+    override fun onMethodCall(call: MethodCall, result: MethodChannel.Result) {
+        when (call.method) {
+            "createReceiver" -> {
+                try {
+                    result.success(createReceiver(call))
+                } catch (e: Exception) {
+                    result.error("CREATE_RECEIVER", e.message, null)
+                }
+            }
+            "disposeReceiver" -> {
+                receivers.remove(call.argument<Number>("textureId")!!.toLong())?.release()
+                result.success(null)
+            }
+            else -> result.notImplemented()
+        }
+    }
+
+    private fun createReceiver(call: MethodCall): Long {
+        val texture = textures.createSurfaceTexture()
+        texture.surfaceTexture().setDefaultBufferSize(1920, 1080)
+
+        val receiver = Receiver(
+            texture,
+            Surface(texture.surfaceTexture()),
+            AudioTrack.Builder()
+                .setAudioAttributes(
+                    AudioAttributes.Builder()
+                        .setUsage(AudioAttributes.USAGE_MEDIA)
+                        .setContentType(AudioAttributes.CONTENT_TYPE_MUSIC)
+                        .build()
+                )
+                .setAudioFormat(
+                    AudioFormat.Builder()
+                        .setEncoding(AudioFormat.ENCODING_PCM_16BIT)
+                        .setSampleRate(48000)
+                        .setChannelMask(AudioFormat.CHANNEL_OUT_MONO)
+                        .build()
+                )
+                .setPerformanceMode(AudioTrack.PERFORMANCE_MODE_LOW_LATENCY)
+                .setTransferMode(AudioTrack.MODE_STREAM)
+                .setBufferSizeInBytes(48000 / 10 * 2)
+                .build(),
+        )
+
+        try {
+            receiver.receiver = HylaranaService.createReceiver(
+                call.argument<String>("id")!!,
+                TransportOptions(
+                    TransportStrategy(
+                        call.argument<Int>("strategy")!!,
+                        call.argument<String>("address")!!,
+                    ),
+                    call.argument<Int>("mtu")!!,
+                ),
+                object : HylaranaReceiverObserver() {
+                    override val surface = receiver.surface
+                    override val track = receiver.track
+
+                    override fun close() {
+                        handler.post {
+                            channel.invokeMethod("onClose", texture.id())
+                        }
+                    }
+                },
+            )
+        } catch (e: Exception) {
+            receiver.release()
+            throw e
+        }
+
+        receiver.track.play()
+        receivers[texture.id()] = receiver
+        return texture.id()
+    }
+}
@@ -0,0 +1,124 @@
+import 'dart:async';
+
+import 'package:flutter/services.dart';
+import 'package:flutter/widgets.dart';
+
+const MethodChannel _channel = MethodChannel('hylarana_flutter');
+
+/// Transport layer strategies.
+enum HylaranaTransportStrategy {
+  /// The receiver connects directly to the SRT server of the sender.
+  direct,
+
+  /// The sender and the receiver pass the data through a relay server.
+  relay,
+
+  /// The receiver processes the multicast packets of the sender.
+  multicast,
+}
+
+class HylaranaTransportOptions {
+  const HylaranaTransportOptions({
+    required this.address,
+    this.strategy = HylaranaTransportStrategy.direct,
+    this.mtu = 1400,
+  });
+
+  final HylaranaTransportStrategy strategy;
+
+  /// The address of the sender, such as 192.168.1.100:8080.
+  final String address;
+
+  /// The MTU of the transport, zero discovers the MTU of the path to the
+  /// sender.
+  final int mtu;
+}
+
+/// A receiver whose video is drawn to an external texture, show it with
+/// [HylaranaView] and dispose it when it is no longer used. The frames are
+/// copied into the textures from the memory on the desktop, so the software
+/// decoder is used there, Android decodes to the surface of the texture.
+class HylaranaReceiverController {
+  HylaranaReceiverController._(this.textureId);
+
+  static final Map<int, HylaranaReceiverController> _controllers = {};
+
+  static bool _initialized = false;
+
+  /// The id of the texture of the engine.
+  final int textureId;
+
+  final Completer<void> _closed = Completer();
+
+  /// Completes when the stream is closed, such as when the sender stops.
+  Future<void> get closed => _closed.future;
+
+  static Future<HylaranaReceiverController> create({
+    required String id,
+    required HylaranaTransportOptions transport,
+  }) async {
+    if (!_initialized) {
+      _initialized = true;
+      _channel.setMethodCallHandler(_handle);
+    }
+
+    final textureId = await _channel.invokeMethod<int>('createReceiver', {
+      'id': id,
+      'strategy': transport.strategy.index,
+      'address': transport.address,
+      'mtu': transport.mtu,
+    });
+
+    return _controllers[textureId!] = HylaranaReceiverController._(textureId);
+  }
+
+  static Future<void> _handle(MethodCall call) async {
+    if (call.method == 'onClose') {
+      final controller = _controllers[call.arguments as int];
+      if (controller != null && !controller._closed.isCompleted) {
+        controller._closed.complete();
+      }
+    }
+  }
+
+  /// Close the receiver and release the texture.
+  Future<void> dispose() async {
+    _controllers.remove(textureId);
+    await _channel.invokeMethod('disposeReceiver', {'textureId': textureId});
+
+    if (!_closed.isCompleted) {
+      _closed.complete();
+    }
+  }
+}
+
+/// Shows the video of a receiver, the texture has the size of the video, so
+/// it is fitted into the widget by [fit].
+class HylaranaView extends StatelessWidget {
+  const HylaranaView({
+    super.key,
+    required this.controller,
+    this.fit = BoxFit.contain,
+    this.aspectRatio = 16 / 9,
+  });
+
+  final HylaranaReceiverController controller;
+
+  final BoxFit fit;
+
+  /// The aspect ratio of the video, the size of the video is not reported
+  /// to Dart.
+  final double aspectRatio;
+
+  @override
+  Widget build(BuildContext context) {
+    return FittedBox(
+      fit: fit,
+      child: SizedBox(
+        width: 1920,
+        height: 1920 / aspectRatio,
+        child: Texture(textureId: controller.textureId),
+      ),
+    );
+  }
+}
@@ -0,0 +1,4 @@
+#import <FlutterMacOS/FlutterMacOS.h>
+
+@interface HylaranaFlutterPlugin : NSObject <FlutterPlugin>
+@end
@@ -0,0 +1,168 @@
+#import "HylaranaFlutterPlugin.h"
+
+#import <CoreVideo/CoreVideo.h>
+
+#include <hylarana.h>
+
+// The frames are copied into pixel buffers of core video, the engine asks for
+// a new pixel buffer after the texture is marked as available.
+@interface HylaranaTexture : NSObject <FlutterTexture>
+
+@property(nonatomic, weak) NSObject<FlutterTextureRegistry>* textures;
+@property(nonatomic, weak) FlutterMethodChannel* channel;
+@property(nonatomic) int64_t textureId;
+@property(nonatomic) HylaranaTextureReceiver receiver;
+
+@end
+
+static void HylaranaTextureOnFrame(void* ctx)
+{
+    HylaranaTexture* texture = (__bridge HylaranaTexture*)ctx;
+    NSObject<FlutterTextureRegistry>* textures = texture.textures;
+    int64_t textureId = texture.textureId;
+
+    dispatch_async(dispatch_get_main_queue(), ^{
+        [textures textureFrameAvailable:textureId];
+    });
+}
+
+static void HylaranaTextureOnClose(void* ctx)
+{
+    HylaranaTexture* texture = (__bridge HylaranaTexture*)ctx;
+    FlutterMethodChannel* channel = texture.channel;
+    int64_t textureId = texture.textureId;
+
+    dispatch_async(dispatch_get_main_queue(), ^{
+        [channel invokeMethod:@"onClose" arguments:@(textureId)];
+    });
+}
+
+@implementation HylaranaTexture
+
+- (CVPixelBufferRef _Nullable)copyPixelBuffer
+{
+    const HylaranaPixelBuffer* pixels = hylarana_texture_receiver_copy_pixel_buffer(0, 0, self.receiver);
+    if (pixels == NULL)
+    {
+        return NULL;
+    }
+
+    NSDictionary* attributes = @{
+        (NSString*)kCVPixelBufferMetalCompatibilityKey : @YES,
+        (NSString*)kCVPixelBufferIOSurfacePropertiesKey : @{},
+    };
+
+    CVPixelBufferRef buffer = NULL;
+    CVReturn ret = CVPixelBufferCreate(kCFAllocatorDefault,
+                                       pixels->width,
+                                       pixels->height,
+                                       kCVPixelFormatType_32BGRA,
+                                       (__bridge CFDictionaryRef)attributes,
+                                       &buffer);
+
+    if (ret == kCVReturnSuccess)
+    {
+        CVPixelBufferLockBaseAddress(buffer, 0);
+
+        uint8_t* dst = CVPixelBufferGetBaseAddress(buffer);
+        size_t stride = CVPixelBufferGetBytesPerRow(buffer);
+        for (size_t i = 0; i < pixels->height; i++)
+        {
+            memcpy(dst + i * stride, pixels->buffer + i * pixels->width * 4, pixels->width * 4);
+        }
+
+        CVPixelBufferUnlockBaseAddress(buffer, 0);
+    }
+
+    pixels->release_callback(pixels->release_context);
+    return buffer;
+}
+
+- (void)dealloc
+{
+    if (self.receiver != NULL)
+    {
+        hylarana_texture_receiver_destroy(self.receiver);
+    }
+}
+
+@end
+
+@interface HylaranaFlutterPlugin ()
+
+@property(nonatomic, strong) NSObject<FlutterTextureRegistry>* textures;
+@property(nonatomic, strong) FlutterMethodChannel* channel;
+@property(nonatomic, strong) NSMutableDictionary<NSNumber*, HylaranaTexture*>* receivers;
+
+@end
+
+@implementation HylaranaFlutterPlugin
+
++ (void)registerWithRegistrar:(NSObject<FlutterPluginRegistrar>*)registrar
+{
+    hylarana_startup();
+
+    HylaranaFlutterPlugin* plugin = [[HylaranaFlutterPlugin alloc] init];
+    plugin.textures = registrar.textures;
+    plugin.receivers = [NSMutableDictionary dictionary];
+    plugin.channel = [FlutterMethodChannel methodChannelWithName:@"hylarana_flutter"
+                                                 binaryMessenger:registrar.messenger];
+
+    [registrar addMethodCallDelegate:plugin channel:plugin.channel];
+}
+
+- (void)handleMethodCall:(FlutterMethodCall*)call result:(FlutterResult)result
+{
+    if ([call.method isEqualToString:@"createReceiver"])
+    {
+        HylaranaTexture* texture = [[HylaranaTexture alloc] init];
+        texture.textures = self.textures;
+        texture.channel = self.channel;
+        texture.textureId = [self.textures registerTexture:texture];
+
+        HylaranaReceiverOptions options;
+        options.codec.video = VIDEO_DECODER_H264;
+        options.transport.strategy = (HylaranaTransportStrategy)[call.arguments[@"strategy"] intValue];
+        options.transport.address = [call.arguments[@"address"] UTF8String];
+        options.transport.mtu = [call.arguments[@"mtu"] unsignedLongValue];
+
+        // The texture is not retained by the receiver, it is kept alive by
+        // the plugin until the receiver is disposed.
+        texture.receiver = hylarana_create_texture_receiver([call.arguments[@"id"] UTF8String],
+                                                            options,
+                                                            PIXEL_ORDER_BGRA,
+                                                            HylaranaTextureOnFrame,
+                                                            HylaranaTextureOnClose,
+                                                            (__bridge void*)texture);
+
+        if (texture.receiver == NULL)
+        {
+            [self.textures unregisterTexture:texture.textureId];
+            result([FlutterError errorWithCode:@"CREATE_RECEIVER"
+                                       message:@"failed to create the receiver"
+                                       details:nil]);
+        }
+        else
+        {
+            self.receivers[@(texture.textureId)] = texture;
+            result(@(texture.textureId));
+        }
+    }
+    else if ([call.method isEqualToString:@"disposeReceiver"])
+    {
+        NSNumber* textureId = call.arguments[@"textureId"];
+        if (self.receivers[textureId] != nil)
+        {
+            [self.textures unregisterTexture:textureId.longLongValue];
+            [self.receivers removeObjectForKey:textureId];
+        }
+
+        result(nil);
+    }
+    else
+    {
+        result(FlutterMethodNotImplemented);
+    }
+}
+
+@end
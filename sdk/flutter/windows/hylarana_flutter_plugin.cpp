@@ -0,0 +1,197 @@
+#include "include/hylarana_flutter/hylarana_flutter_plugin_c_api.h"
+
+#include <windows.h>
+
+#include <flutter/method_channel.h>
+#include <flutter/plugin_registrar_windows.h>
+#include <flutter/standard_method_codec.h>
+#include <flutter/texture_registrar.h>
+
+#include <map>
+#include <memory>
+#include <optional>
+#include <string>
+
+extern "C"
+{
+#include <hylarana.h>
+}
+
+namespace
+{
+    // Posted to the top level window when a stream is closed, the channel can
+    // only be used on the platform thread.
+    const UINT WM_HYLARANA_CLOSE = WM_APP + 0x4859;
+
+    using flutter::EncodableMap;
+    using flutter::EncodableValue;
+
+    struct Receiver
+    {
+        HWND window = nullptr;
+        int64_t texture_id = -1;
+        flutter::TextureRegistrar* textures = nullptr;
+        std::unique_ptr<flutter::TextureVariant> texture;
+        HylaranaTextureReceiver receiver = nullptr;
+    };
+
+    template <typename T> T GetArgument(const EncodableMap& args, const char* key)
+    {
+        return std::get<T>(args.at(EncodableValue(key)));
+    }
+
+    class HylaranaFlutterPlugin : public flutter::Plugin
+    {
+    public:
+        explicit HylaranaFlutterPlugin(flutter::PluginRegistrarWindows* registrar)
+            : _registrar(registrar), _textures(registrar->texture_registrar())
+        {
+            _channel = std::make_unique<flutter::MethodChannel<EncodableValue>>(
+                registrar->messenger(), "hylarana_flutter", &flutter::StandardMethodCodec::GetInstance());
+
+            _channel->SetMethodCallHandler([this](const auto& call, auto result) {
+                HandleMethodCall(call, std::move(result));
+            });
+
+            _delegate = registrar->RegisterTopLevelWindowProcDelegate(
+                [this](HWND hwnd, UINT message, WPARAM wparam, LPARAM lparam) -> std::optional<LRESULT> {
+                    if (message != WM_HYLARANA_CLOSE)
+                    {
+                        return std::nullopt;
+                    }
+
+                    int64_t texture_id = static_cast<int64_t>(wparam);
+                    if (_receivers.find(texture_id) != _receivers.end())
+                    {
+                        _channel->InvokeMethod("onClose", std::make_unique<EncodableValue>(texture_id));
+                    }
+
+                    return 0;
+                });
+        }
+
+        ~HylaranaFlutterPlugin() override
+        {
+            _registrar->UnregisterTopLevelWindowProcDelegate(_delegate);
+
+            for (auto& [texture_id, receiver] : _receivers)
+            {
+                Dispose(std::move(receiver));
+            }
+        }
+
+    private:
+        void HandleMethodCall(const flutter::MethodCall<EncodableValue>& call,
+                              std::unique_ptr<flutter::MethodResult<EncodableValue>> result)
+        {
+            const auto& args = std::get<EncodableMap>(*call.arguments());
+
+            if (call.method_name() == "createReceiver")
+            {
+                int64_t texture_id = CreateReceiver(args);
+                if (texture_id < 0)
+                {
+                    result->Error("CREATE_RECEIVER", "failed to create the receiver");
+                }
+                else
+                {
+                    result->Success(EncodableValue(texture_id));
+                }
+            }
+            else if (call.method_name() == "disposeReceiver")
+            {
+                int64_t texture_id = args.at(EncodableValue("textureId")).LongValue();
+
+                auto iter = _receivers.find(texture_id);
+                if (iter != _receivers.end())
+                {
+                    Dispose(std::move(iter->second));
+                    _receivers.erase(iter);
+                }
+
+                result->Success();
+            }
+            else
+            {
+                result->NotImplemented();
+            }
+        }
+
+        int64_t CreateReceiver(const EncodableMap& args)
+        {
+            auto receiver = std::make_unique<Receiver>();
+            receiver->textures = _textures;
+            receiver->window = GetAncestor(_registrar->GetView()->GetNativeWindow(), GA_ROOT);
+
+            // The receiver is created after the texture is registered, the
+            // engine does not copy the pixel buffer before the first frame is
+            // marked as available.
+            Receiver* ctx = receiver.get();
+            receiver->texture = std::make_unique<flutter::TextureVariant>(
+                flutter::PixelBufferTexture([ctx](size_t width, size_t height) {
+                    return reinterpret_cast<const FlutterDesktopPixelBuffer*>(
+                        hylarana_texture_receiver_copy_pixel_buffer(width, height, ctx->receiver));
+                }));
+
+            receiver->texture_id = _textures->RegisterTexture(receiver->texture.get());
+
+            std::string id = GetArgument<std::string>(args, "id");
+            std::string address = GetArgument<std::string>(args, "address");
+
+            HylaranaReceiverOptions options;
+            options.codec.video = VIDEO_DECODER_H264;
+            options.transport.strategy = static_cast<HylaranaTransportStrategy>(GetArgument<int32_t>(args, "strategy"));
+            options.transport.address = address.c_str();
+            options.transport.mtu = static_cast<size_t>(GetArgument<int32_t>(args, "mtu"));
+
+            receiver->receiver = hylarana_create_texture_receiver(
+                id.c_str(), options, PIXEL_ORDER_RGBA, OnFrame, OnClose, ctx);
+            if (receiver->receiver == nullptr)
+            {
+                _textures->UnregisterTexture(receiver->texture_id);
+                return -1;
+            }
+
+            int64_t texture_id = receiver->texture_id;
+            _receivers[texture_id] = std::move(receiver);
+            return texture_id;
+        }
+
+        // The texture is released asynchronously, the receiver is destroyed
+        // after the engine stops copying the pixel buffers.
+        void Dispose(std::unique_ptr<Receiver> receiver)
+        {
+            Receiver* ctx = receiver.release();
+            ctx->textures->UnregisterTexture(ctx->texture_id, [ctx]() {
+                hylarana_texture_receiver_destroy(ctx->receiver);
+                delete ctx;
+            });
+        }
+
+        static void OnFrame(void* ctx)
+        {
+            auto receiver = static_cast<Receiver*>(ctx);
+            receiver->textures->MarkTextureFrameAvailable(receiver->texture_id);
+        }
+
+        static void OnClose(void* ctx)
+        {
+            auto receiver = static_cast<Receiver*>(ctx);
+            PostMessage(receiver->window, WM_HYLARANA_CLOSE, static_cast<WPARAM>(receiver->texture_id), 0);
+        }
+
+        flutter::PluginRegistrarWindows* _registrar;
+        flutter::TextureRegistrar* _textures;
+        std::unique_ptr<flutter::MethodChannel<EncodableValue>> _channel;
+        std::map<int64_t, std::unique_ptr<Receiver>> _receivers;
+        int _delegate = 0;
+    };
+} // namespace
+
+void HylaranaFlutterPluginCApiRegisterWithRegistrar(FlutterDesktopPluginRegistrarRef registrar)
+{
+    auto windows_registrar =
+        flutter::PluginRegistrarManager::GetInstance()->GetRegistrar<flutter::PluginRegistrarWindows>(registrar);
+
+    windows_registrar->AddPlugin(std::make_unique<HylaranaFlutterPlugin>(windows_registrar));
+}
@@ -0,0 +1,221 @@
+using System;
+using System.Runtime.InteropServices;
+using UnityEngine;
+
+namespace Hylarana
+{
+    public enum TransportStrategy
+    {
+        Direct,
+        Relay,
+        Multicast,
+    }
+
+    public enum VideoDecoderType
+    {
+        H264,
+        D3D11,
+        Qsv,
+        VideoToolbox,
+        HevcD3D11,
+        HevcQsv,
+    }
+
+    public enum FitMode
+    {
+        Stretch,
+        Contain,
+        Cover,
+    }
+
+    [StructLayout(LayoutKind.Sequential)]
+    internal struct RawReceiverOptions
+    {
+        public VideoDecoderType Video;
+        public TransportStrategy Strategy;
+        public IntPtr Address;
+        public UIntPtr Mtu;
+    }
+
+    internal static class Native
+    {
+#if UNITY_IOS && !UNITY_EDITOR
+        private const string Library = "__Internal";
+#else
+        private const string Library = "hylarana";
+#endif
+
+        [DllImport(Library)]
+        public static extern IntPtr hylarana_unity_get_render_event_func();
+
+        [DllImport(Library)]
+        public static extern IntPtr hylarana_unity_create_receiver(
+            [MarshalAs(UnmanagedType.LPStr)] string id,
+            RawReceiverOptions options
+        );
+
+        [DllImport(Library)]
+        public static extern int hylarana_unity_receiver_get_event_id(IntPtr receiver);
+
+        [DllImport(Library)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern bool hylarana_unity_receiver_bind_texture(
+            IntPtr receiver,
+            IntPtr texture,
+            FitMode fit
+        );
+
+        [DllImport(Library)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern bool hylarana_unity_receiver_get_video_size(
+            IntPtr receiver,
+            out uint width,
+            out uint height
+        );
+
+        [DllImport(Library)]
+        [return: MarshalAs(UnmanagedType.U1)]
+        public static extern bool hylarana_unity_receiver_is_closed(IntPtr receiver);
+
+        [DllImport(Library)]
+        public static extern void hylarana_unity_receiver_destroy(IntPtr receiver);
+    }
+
+    /// <summary>
+    /// Receives a stream and draws its video to a texture, the frames are
+    /// drawn by the native plugin on the render thread, they are never copied
+    /// through C#.
+    /// </summary>
+    public class HylaranaReceiver : MonoBehaviour
+    {
+        public string Id;
+        public string Address;
+        public TransportStrategy Strategy = TransportStrategy.Direct;
+        public VideoDecoderType Decoder = VideoDecoderType.H264;
+        public FitMode Fit = FitMode.Contain;
+        /// <summary>
+        /// The MTU of the transport, zero discovers the MTU of the path to
+        /// the sender.
+        /// </summary>
+        public int Mtu = 1400;
+
+        /// <summary>
+        /// The texture that the video is drawn to, it is created when the size
+        /// of the video is known.
+        /// </summary>
+        public Texture Texture { get; private set; }
+
+        public event Action<Texture> OnTextureChanged;
+
+        private IntPtr receiver = IntPtr.Zero;
+        private IntPtr renderEventFunc;
+        private int eventId;
+
+        void OnEnable()
+        {
+            var address = Marshal.StringToHGlobalAnsi(Address);
+            try
+            {
+                receiver = Native.hylarana_unity_create_receiver(
+                    Id,
+                    new RawReceiverOptions
+                    {
+                        Video = Decoder,
+                        Strategy = Strategy,
+                        Address = address,
+                        Mtu = (UIntPtr)Mtu,
+                    }
+                );
+            }
+            finally
+            {
+                Marshal.FreeHGlobal(address);
+            }
+
+            if (receiver == IntPtr.Zero)
+            {
+                Debug.LogError("hylarana: failed to create the receiver");
+                enabled = false;
+                return;
+            }
+
+            renderEventFunc = Native.hylarana_unity_get_render_event_func();
+            eventId = Native.hylarana_unity_receiver_get_event_id(receiver);
+        }
+
+        void Update()
+        {
+            if (receiver == IntPtr.Zero)
+            {
+                return;
+            }
+
+            if (Native.hylarana_unity_receiver_is_closed(receiver))
+            {
+                enabled = false;
+                return;
+            }
+
+            // The size of the video is known after the first render event.
+            if (Native.hylarana_unity_receiver_get_video_size(receiver, out var width, out var height))
+            {
+                if (Texture == null || Texture.width != width || Texture.height != height)
+                {
+                    CreateTexture((int)width, (int)height);
+                }
+            }
+
+            GL.IssuePluginEvent(renderEventFunc, eventId);
+        }
+
+        void OnDisable()
+        {
+            if (receiver != IntPtr.Zero)
+            {
+                Native.hylarana_unity_receiver_destroy(receiver);
+                receiver = IntPtr.Zero;
+            }
+
+            DestroyTexture();
+        }
+
+        private void CreateTexture(int width, int height)
+        {
+            DestroyTexture();
+
+            // D3D11 draws the video with the video processor, which needs a
+            // render target, Metal copies the pixels into a BGRA texture.
+            if (SystemInfo.graphicsDeviceType == UnityEngine.Rendering.GraphicsDeviceType.Direct3D11)
+            {
+                var texture = new RenderTexture(width, height, 0, RenderTextureFormat.ARGB32);
+                texture.Create();
+                Texture = texture;
+            }
+            else
+            {
+                Texture = new Texture2D(width, height, TextureFormat.BGRA32, false);
+            }
+
+            if (!Native.hylarana_unity_receiver_bind_texture(receiver, Texture.GetNativeTexturePtr(), Fit))
+            {
+                Debug.LogError("hylarana: failed to bind the texture");
+            }
+
+            OnTextureChanged?.Invoke(Texture);
+        }
+
+        private void DestroyTexture()
+        {
+            if (Texture != null)
+            {
+                if (receiver != IntPtr.Zero)
+                {
+                    Native.hylarana_unity_receiver_bind_texture(receiver, IntPtr.Zero, Fit);
+                }
+
+                Destroy(Texture);
+                Texture = null;
+            }
+        }
+    }
+}
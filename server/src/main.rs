@@ -1,15 +1,11 @@
-use std::{
-    collections::{HashMap, HashSet},
-    net::SocketAddr,
-    str::FromStr,
-    sync::Arc,
-    thread,
-};
+use std::{collections::HashMap, net::SocketAddr, str::FromStr, sync::Arc, thread};
 
 use anyhow::Result;
 use clap::Parser;
 use hylarana_transport::{
-    shutdown, startup, StreamInfo, StreamInfoKind, TransmissionOptions, TransmissionServer,
+    shutdown, startup, StreamInfo, StreamInfoKind, StreamTracks, TransmissionFragmentDecoder,
+    TransmissionFragmentEncoder, TransmissionOptions, TransmissionServer, TransmissionSocket,
+    UnPackage,
 };
 use parking_lot::RwLock;
 
@@ -27,6 +23,70 @@ pub struct Configure {
     pub bind: SocketAddr,
     #[arg(long)]
     pub mtu: usize,
+    /// The DSCP value that the forwarded packets are marked with, such as 34
+    /// for AF41.
+    #[arg(long)]
+    pub dscp: Option<u8>,
+}
+
+fn forward(
+    sockets: &HashMap<SocketAddr, Arc<TransmissionSocket>>,
+    addr: &SocketAddr,
+    buf: &[u8],
+    closed: &mut Vec<SocketAddr>,
+) {
+    if let Some(socket) = sockets.get(addr) {
+        if let Err(e) = socket.send(buf) {
+            if !closed.contains(addr) {
+                closed.push(*addr);
+            }
+
+            log::warn!("not send a buf to srt socket, addr={:?}, err={:?}", addr, e);
+        }
+    }
+}
+
+// The subscribers of a single track only get the packages of the track, the
+// packages are fragmented again with a sequence of their own for each track, so
+// the subscribers do not take the packages of the other track as lost.
+fn forward_tracks(
+    sockets: &HashMap<SocketAddr, Arc<TransmissionSocket>>,
+    subscribers: &HashMap<SocketAddr, StreamTracks>,
+    decoder: &mut TransmissionFragmentDecoder,
+    encoders: &mut HashMap<StreamTracks, TransmissionFragmentEncoder>,
+    max_pkt_size: usize,
+    buf: &[u8],
+    closed: &mut Vec<SocketAddr>,
+) {
+    let Some((_, bytes)) = decoder.decode(buf) else {
+        return;
+    };
+
+    let info = match UnPackage::unpack(bytes.clone()) {
+        Ok((info, _)) => info,
+        Err(e) => {
+            log::warn!("not forward an invalid package, err={}", e);
+
+            return;
+        }
+    };
+
+    for tracks in [StreamTracks::Video, StreamTracks::Audio] {
+        if !tracks.contains(info.kind, info.flags) || !subscribers.values().any(|it| *it == tracks)
+        {
+            continue;
+        }
+
+        let encoder = encoders
+            .entry(tracks)
+            .or_insert_with(|| TransmissionFragmentEncoder::new(max_pkt_size));
+
+        for chunk in encoder.encode(&bytes) {
+            for (addr, _) in subscribers.iter().filter(|(_, it)| **it == tracks) {
+                forward(sockets, addr, chunk, closed);
+            }
+        }
+    }
 }
 
 fn main() -> Result<()> {
@@ -45,13 +105,16 @@ fn main() -> Result<()> {
     opt.mtu = config.mtu as u32;
     opt.latency = 40;
     opt.fc = 32;
+    opt.dscp = config.dscp;
 
     // Start the srt server
+    let max_pkt_size = opt.max_pkt_size();
     let server = TransmissionServer::bind(config.bind, opt, 100)?;
     log::info!("starting srt server...");
 
     let sockets = Arc::new(RwLock::new(HashMap::with_capacity(200)));
     let subscribers = Arc::new(RwLock::new(HashMap::with_capacity(200)));
+    let publishers = Arc::new(RwLock::new(HashMap::with_capacity(200)));
 
     loop {
         match server.accept() {
@@ -75,6 +138,19 @@ fn main() -> Result<()> {
                     continue;
                 };
 
+                // The publishers and subscribers of other versions cannot exchange the
+                // packages, so they are not forwarded to each other.
+                if !stream_info.is_compatible() {
+                    log::error!(
+                        "incompatible stream version, addr={:?}, info={:?}",
+                        addr,
+                        stream_info
+                    );
+
+                    socket.close();
+                    continue;
+                }
+
                 log::info!(
                     "accept a srt socket, addr={:?}, info={:?}",
                     addr,
@@ -89,18 +165,28 @@ fn main() -> Result<()> {
                         subscribers
                             .write()
                             .entry(stream_info.id.clone())
-                            .or_insert_with(|| HashSet::with_capacity(200))
-                            .insert(addr);
+                            .or_insert_with(|| HashMap::with_capacity(200))
+                            .insert(addr, stream_info.tracks);
+                    } else {
+                        publishers
+                            .write()
+                            .insert(stream_info.id.clone(), socket.clone());
                     }
                 }
 
                 let socket = socket.clone();
                 let sockets = sockets.clone();
                 let subscribers = subscribers.clone();
+                let publishers = publishers.clone();
                 thread::spawn(move || {
                     let mut buf = [0u8; 2000];
                     let mut closed = Vec::with_capacity(100);
 
+                    // The packages of the publisher are put together again for the
+                    // subscribers of a single track, see `forward_tracks`.
+                    let mut decoder = TransmissionFragmentDecoder::new();
+                    let mut encoders = HashMap::with_capacity(2);
+
                     loop {
                         match socket.read(&mut buf) {
                             Ok(size) => {
@@ -108,10 +194,21 @@ fn main() -> Result<()> {
                                     break;
                                 }
 
-                                // Subscribers are not allowed to write any information to the
-                                // server!
+                                // The subscribers can only send the messages of the back
+                                // channel, which are forwarded to the publisher of the channel.
                                 if stream_info.kind == StreamInfoKind::Subscriber {
-                                    break;
+                                    if let Some(publisher) = publishers.read().get(&stream_info.id)
+                                    {
+                                        if let Err(e) = publisher.send(&buf[..size]) {
+                                            log::warn!(
+                                                "not send a message to publisher, addr={:?}, err={:?}",
+                                                addr,
+                                                e
+                                            );
+                                        }
+                                    }
+
+                                    continue;
                                 }
 
                                 closed.clear();
@@ -123,18 +220,22 @@ fn main() -> Result<()> {
                                     // Forwards all packets sent by the publisher to all subscribers
                                     // of the same channel
                                     if let Some(items) = subscribers.get(&stream_info.id) {
-                                        for addr in items.iter() {
-                                            if let Some(socket) = sockets.get(addr) {
-                                                if let Err(e) = socket.send(&buf[..size]) {
-                                                    closed.push(*addr);
-
-                                                    log::warn!(
-                                                        "not send a buf to srt socket, addr={:?}, err={:?}",
-                                                        addr,
-                                                        e
-                                                    );
-                                                }
-                                            }
+                                        for (addr, _) in
+                                            items.iter().filter(|(_, it)| **it == StreamTracks::All)
+                                        {
+                                            forward(&sockets, addr, &buf[..size], &mut closed);
+                                        }
+
+                                        if items.values().any(|it| *it != StreamTracks::All) {
+                                            forward_tracks(
+                                                &sockets,
+                                                items,
+                                                &mut decoder,
+                                                &mut encoders,
+                                                max_pkt_size,
+                                                &buf[..size],
+                                                &mut closed,
+                                            );
                                         }
                                     }
                                 }
@@ -169,8 +270,10 @@ fn main() -> Result<()> {
                     // If the publisher has exited, it is necessary to close all subscribers of the
                     // current channel and inform the client that the publisher has exited.
                     if stream_info.kind == StreamInfoKind::Publisher {
+                        publishers.write().remove(&stream_info.id);
+
                         if let Some(items) = subscribers.remove(&stream_info.id) {
-                            for addr in items.iter() {
+                            for addr in items.keys() {
                                 if let Some(socket) = sockets.remove(addr) {
                                     socket.close()
                                 }
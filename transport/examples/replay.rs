@@ -0,0 +1,66 @@
+//! Replay a packet dump of the transport and print the packets that the
+//! receiver adapter gets, in the order it gets them.
+//!
+//! ```text
+//! cargo run -p hylarana-transport --example replay -- <dump> [--realtime]
+//! ```
+
+use std::env;
+
+use bytes::Bytes;
+use hylarana_transport::{
+    replay, BufferFlag, ReplayOptions, StreamKind, StreamReceiverAdapterAbstract,
+};
+
+struct PrintAdapter;
+
+impl StreamReceiverAdapterAbstract for PrintAdapter {
+    fn send(&self, buf: Bytes, kind: StreamKind, flags: i32, timestamp: u64) -> bool {
+        let flag = flags & BufferFlag::MASK;
+
+        println!(
+            "{:?} size={} flags={:#04x}{}{} timestamp={}",
+            kind,
+            buf.len(),
+            flags,
+            if flag & BufferFlag::KeyFrame as i32 != 0 {
+                " key"
+            } else {
+                ""
+            },
+            if flag & BufferFlag::Config as i32 != 0 {
+                " config"
+            } else {
+                ""
+            },
+            timestamp
+        );
+
+        true
+    }
+
+    fn close(&self) {}
+
+    fn lose(&self) {
+        println!("lost");
+    }
+}
+
+fn main() -> Result<(), std::io::Error> {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let Some(path) = args.iter().find(|it| !it.starts_with("--")) else {
+        eprintln!("usage: replay <dump> [--realtime]");
+        return Ok(());
+    };
+
+    let stats = replay(
+        path,
+        &PrintAdapter,
+        ReplayOptions {
+            realtime: args.iter().any(|it| it == "--realtime"),
+        },
+    )?;
+
+    println!("packets={} lost={}", stats.packets, stats.lost);
+    Ok(())
+}
@@ -0,0 +1,20 @@
+#![no_main]
+
+use std::str::FromStr;
+
+use hylarana_transport::StreamInfo;
+use libfuzzer_sys::fuzz_target;
+
+// The stream id of the handshake comes from the network, parsing it never
+// panics, and the parsed information survives a round trip.
+fuzz_target!(|data: &str| {
+    if let Ok(info) = StreamInfo::from_str(data) {
+        if !info.id.contains(',') && !info.id.contains('=') {
+            let parsed = StreamInfo::from_str(&info.to_string()).unwrap();
+
+            assert_eq!(parsed.id, info.id);
+            assert_eq!(parsed.kind, info.kind);
+            assert_eq!(parsed.version, info.version);
+        }
+    }
+});
@@ -0,0 +1,16 @@
+#![no_main]
+
+use bytes::{Bytes, BytesMut};
+use hylarana_transport::{Package, UnPackage};
+use libfuzzer_sys::fuzz_target;
+
+// Any input is either rejected or unpacks to a package that packs back to the
+// same bytes.
+fuzz_target!(|data: &[u8]| {
+    if let Ok((info, body)) = UnPackage::unpack(Bytes::copy_from_slice(data)) {
+        let mut bytes = BytesMut::zeroed(Package::HEAD_SIZE);
+        bytes.extend_from_slice(&body);
+
+        assert_eq!(&Package::pack(info, bytes)[..], data);
+    }
+});
@@ -1,31 +1,81 @@
 use std::{
+    collections::VecDeque,
     fmt,
     sync::{
-        atomic::{AtomicBool, AtomicU8},
-        mpsc::{channel, Receiver, Sender},
+        atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError, Sender, TryRecvError},
     },
+    time::Duration,
 };
 
-use bytes::{Bytes, BytesMut};
-use hylarana_common::atomic::{AtomicOption, EasyAtomic};
-use parking_lot::Mutex;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use hylarana_common::{
+    atomic::{AtomicOption, EasyAtomic},
+    frame::VideoRotation,
+};
+use parking_lot::{Mutex, RwLock};
+use xxhash_rust::xxh3::xxh3_64;
+
+use crate::{package::copy_from_slice, PacketPacingOptions};
 
-struct Channel<T>(Sender<Option<T>>, Mutex<Receiver<Option<T>>>);
+// The number of the items in the channel is counted, the channels are unbounded,
+// so the count shows when a stage of the pipeline falls behind.
+struct Channel<T>(Sender<Option<T>>, Mutex<Receiver<Option<T>>>, AtomicUsize);
 
 impl<T> Default for Channel<T> {
     fn default() -> Self {
         let (tx, rx) = channel();
-        Self(tx, Mutex::new(rx))
+        Self(tx, Mutex::new(rx), AtomicUsize::new(0))
     }
 }
 
 impl<T> Channel<T> {
     fn send(&self, item: Option<T>) -> bool {
+        self.2.fetch_add(1, Ordering::Relaxed);
         self.0.send(item).is_ok()
     }
 
     fn recv(&self) -> Option<T> {
-        self.1.lock().recv().ok().flatten()
+        let item = self.1.lock().recv().ok().flatten();
+        self.taken();
+
+        item
+    }
+
+    // Returns `None` if the channel is empty, the item is `None` at the end of
+    // the channel.
+    fn try_recv(&self) -> Option<Option<T>> {
+        let item = match self.1.lock().try_recv() {
+            Ok(item) => item,
+            Err(TryRecvError::Empty) => return None,
+            Err(TryRecvError::Disconnected) => None,
+        };
+
+        self.taken();
+        Some(item)
+    }
+
+    // Returns `None` if nothing arrived in time, the item is `None` at the end of
+    // the channel.
+    fn recv_timeout(&self, timeout: Duration) -> Option<Option<T>> {
+        let item = match self.1.lock().recv_timeout(timeout) {
+            Ok(item) => item,
+            Err(RecvTimeoutError::Timeout) => return None,
+            Err(RecvTimeoutError::Disconnected) => None,
+        };
+
+        self.taken();
+        Some(item)
+    }
+
+    fn taken(&self) {
+        let _ = self
+            .2
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |it| it.checked_sub(1));
+    }
+
+    fn len(&self) -> usize {
+        self.2.load(Ordering::Relaxed)
     }
 }
 
@@ -33,10 +83,20 @@ impl<T> Channel<T> {
 struct PacketFilter {
     initialized: AtomicBool,
     readable: AtomicBool,
+    // The hash of the last configuration that was passed to the decoder.
+    config: AtomicU64,
 }
 
 impl PacketFilter {
-    fn filter(&self, flag: i32, keyframe: bool) -> bool {
+    fn filter(&self, buf: &[u8], flag: i32, keyframe: bool) -> bool {
+        // Control messages are not media, they are not related to the state of the
+        // decoder.
+        if flag & BufferFlag::CONTROL != 0 {
+            return true;
+        }
+
+        let flag = flag & BufferFlag::MASK;
+
         // First check whether the decoder has been initialized. Here, it is judged
         // whether the configuration information has arrived. If the configuration
         // information has arrived, the decoder initialization is marked as completed.
@@ -45,6 +105,7 @@ impl PacketFilter {
                 return false;
             }
 
+            self.config.update(xxh3_64(buf));
             self.initialized.update(true);
             return true;
         }
@@ -53,15 +114,28 @@ impl PacketFilter {
         // If it has been initialized, it means that the configuration information has
         // been received. It is meaningless to receive it again later. Here, duplicate
         // configuration information is filtered out.
+        //
+        // A different configuration comes from a new encoder, such as after the
+        // sender switched the codec, the stream continues with the next key frame.
         if flag == BufferFlag::Config as i32 {
-            return false;
+            let hash = xxh3_64(buf);
+            if self.config.update(hash) == hash {
+                return false;
+            }
+
+            if keyframe {
+                self.readable.update(false);
+            }
+
+            return true;
         }
 
         // The audio does not have keyframes
         if keyframe {
             // Check whether the current stream is in a readable state. When packet loss
             // occurs, the entire stream should be paused and wait for the next key frame to
-            // arrive.
+            // arrive. With intra refresh, the recovery points of the refresh cycles are
+            // sent as key frames.
             if !self.readable.get() {
                 if flag == BufferFlag::KeyFrame as i32 {
                     self.readable.update(true);
@@ -77,6 +151,12 @@ impl PacketFilter {
     fn loss(&self) {
         self.readable.update(false);
     }
+
+    // The packets are dropped until the next key frame, the decoder cannot make
+    // progress before it arrives.
+    fn is_waiting_key_frame(&self) -> bool {
+        !self.initialized.get() || !self.readable.get()
+    }
 }
 
 #[repr(i32)]
@@ -88,6 +168,151 @@ pub enum BufferFlag {
     Partial = 8,
 }
 
+impl BufferFlag {
+    /// Only the low four bits of the flags are buffer flags, the high bits of
+    /// the video stream flags carry the orientation of the picture.
+    pub const MASK: i32 = 0x0F;
+
+    /// The packet is a control message of the stream instead of media, see
+    /// `StreamControl`.
+    pub const CONTROL: i32 = 0x80;
+
+    /// The audio packet is the last one before the sender stops sending during
+    /// silence, the receiver fills the gap until the next packet. This shares
+    /// the bits of the orientation, which the audio stream does not have.
+    pub const SILENCE: i32 = 0x10;
+
+    /// Encode the orientation of the picture into the high bits of the flags.
+    pub fn with_orientation(flags: i32, rotation: VideoRotation, mirror: bool) -> i32 {
+        (flags & Self::MASK) | ((rotation as i32) << 4) | ((mirror as i32) << 6)
+    }
+
+    /// Decode the orientation of the picture from the high bits of the flags.
+    pub fn get_orientation(flags: i32) -> (VideoRotation, bool) {
+        (
+            VideoRotation::from_degrees((flags >> 4 & 0x03) * 90),
+            flags >> 6 & 0x01 == 1,
+        )
+    }
+}
+
+/// Control messages of the stream, a message is sent as a packet of the video
+/// stream with the control flag, the first byte of the packet is the type of
+/// the message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamControl {
+    /// The sender has paused, no media is sent until it resumes.
+    Pause,
+    Resume,
+    /// The clock of the sender, `media` is the media clock and `system` is the
+    /// system time since the unix epoch, both are in microseconds and taken at
+    /// the same moment. The receiver uses it to relate the timestamps of the
+    /// frames to its own clock.
+    Clock {
+        media: u64,
+        system: u64,
+    },
+    /// The data of the application, the transport does not care about the
+    /// content.
+    Message(Bytes),
+    /// The metadata of the stream, such as the title and the device name, the
+    /// transport does not care about the content.
+    Metadata(Bytes),
+    /// A small image of the video, such as a JPEG, so that the receivers can
+    /// show a preview of the stream without decoding it.
+    Thumbnail(Bytes),
+    /// The sender is closed on purpose, this is the last packet of the
+    /// stream, so that the receivers do not take the end of the stream as a
+    /// network failure.
+    Goodbye,
+    /// A keepalive of the sender, `sent` is the time of the sender that the
+    /// receiver answers with, see `KeepaliveOptions`. The transport answers
+    /// the keepalives, they are not passed to the receiver adapters.
+    Ping {
+        sent: u64,
+    },
+    /// The sender has disconnected this receiver on purpose, this is the last
+    /// packet of the receiver, which closes the stream instead of waiting to
+    /// reattach, see `Sender::disconnect`.
+    Kick,
+}
+
+impl StreamControl {
+    pub fn from_payload(mut buf: &[u8]) -> Option<Self> {
+        if buf.is_empty() {
+            return None;
+        }
+
+        Some(match buf.get_u8() {
+            1 => Self::Pause,
+            2 => Self::Resume,
+            3 if buf.len() >= 16 => Self::Clock {
+                media: buf.get_u64(),
+                system: buf.get_u64(),
+            },
+            4 => Self::Message(Bytes::copy_from_slice(buf)),
+            5 => Self::Metadata(Bytes::copy_from_slice(buf)),
+            6 => Self::Thumbnail(Bytes::copy_from_slice(buf)),
+            7 => Self::Goodbye,
+            8 if buf.len() >= 8 => Self::Ping {
+                sent: buf.get_u64(),
+            },
+            9 => Self::Kick,
+            _ => return None,
+        })
+    }
+
+    /// Whether the payload is a goodbye, without copying the content of the
+    /// other messages.
+    pub fn is_goodbye(buf: &[u8]) -> bool {
+        buf.first() == Some(&7)
+    }
+
+    /// Whether the payload is a keepalive, see `is_goodbye`.
+    pub fn is_ping(buf: &[u8]) -> bool {
+        buf.first() == Some(&8)
+    }
+
+    /// Whether the payload is a kick, see `is_goodbye`.
+    pub fn is_kick(buf: &[u8]) -> bool {
+        buf.first() == Some(&9)
+    }
+
+    pub fn as_payload(&self) -> Bytes {
+        let mut bytes = BytesMut::with_capacity(17);
+
+        match self {
+            Self::Pause => bytes.put_u8(1),
+            Self::Resume => bytes.put_u8(2),
+            Self::Clock { media, system } => {
+                bytes.put_u8(3);
+                bytes.put_u64(*media);
+                bytes.put_u64(*system);
+            }
+            Self::Message(message) => {
+                bytes.put_u8(4);
+                bytes.put(message.as_ref());
+            }
+            Self::Metadata(metadata) => {
+                bytes.put_u8(5);
+                bytes.put(metadata.as_ref());
+            }
+            Self::Thumbnail(image) => {
+                bytes.put_u8(6);
+                bytes.put(image.as_ref());
+            }
+            Self::Goodbye => bytes.put_u8(7),
+            Self::Ping { sent } => {
+                bytes.put_u8(8);
+                bytes.put_u64(*sent);
+            }
+            Self::Kick => bytes.put_u8(9),
+        }
+
+        bytes.freeze()
+    }
+}
+
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StreamKind {
@@ -128,6 +353,7 @@ pub enum StreamBufferInfo {
 struct ConfigCache {
     video: AtomicOption<BytesMut>,
     audio: AtomicOption<BytesMut>,
+    metadata: AtomicOption<BytesMut>,
 }
 
 #[derive(Default)]
@@ -139,6 +365,34 @@ impl AutoInsertOfConfigInfo {
     const AUDIO_INTERVAL: u8 = 30;
 }
 
+type SendItem = (BytesMut, StreamKind, i32, u64);
+
+// The packets that have been taken out of the channel but not sent yet, they
+// are kept by the track, see `StreamSenderAdapter::next`.
+#[derive(Default)]
+struct SendQueue {
+    audio: VecDeque<SendItem>,
+    video: VecDeque<SendItem>,
+    closed: bool,
+}
+
+impl SendQueue {
+    fn push(&mut self, item: SendItem) {
+        match item.1 {
+            StreamKind::Audio => self.audio.push_back(item),
+            StreamKind::Video => self.video.push_back(item),
+        }
+    }
+
+    fn pop(&mut self) -> Option<SendItem> {
+        self.audio.pop_front().or_else(|| self.video.pop_front())
+    }
+
+    fn len(&self) -> usize {
+        self.audio.len() + self.video.len()
+    }
+}
+
 /// Video Audio Streaming Send Processing
 ///
 /// Because the receiver will normally join the stream in the middle of the
@@ -146,14 +400,71 @@ impl AutoInsertOfConfigInfo {
 /// sps and pps as well as the key frame information.
 #[derive(Default)]
 pub struct StreamSenderAdapter {
-    channel: Channel<(BytesMut, StreamKind, i32, u64)>,
+    channel: Channel<SendItem>,
+    queue: Mutex<SendQueue>,
     aioci: AutoInsertOfConfigInfo,
     config: ConfigCache,
+    closed: AtomicBool,
+    // A change of the pacing that the sender thread has not taken yet.
+    pacing: Mutex<Option<Option<PacketPacingOptions>>>,
 }
 
 impl StreamSenderAdapter {
+    // The goodbye is sent in front of the end of the channel, so the sender
+    // threads send it before they close the sockets.
     pub(crate) fn close(&self) {
-        self.channel.send(None);
+        if !self.closed.update(true) {
+            self.send_control(&StreamControl::Goodbye);
+            self.channel.send(None);
+        }
+    }
+
+    // Queue a control message of the transport, the messages are not sent
+    // once the adapter has been closed.
+    pub(crate) fn send_control(&self, control: &StreamControl) -> bool {
+        if self.closed.get() && *control != StreamControl::Goodbye {
+            return false;
+        }
+
+        self.channel.send(Some((
+            copy_from_slice(&control.as_payload()),
+            StreamKind::Video,
+            BufferFlag::CONTROL,
+            0,
+        )))
+    }
+
+    /// Set the metadata of the stream, the metadata is inserted in front of the
+    /// configuration of the streams, so that the receivers that join in the
+    /// middle of the stream receive it before they start decoding.
+    pub fn set_metadata(&self, metadata: Bytes) {
+        self.config.metadata.swap(Some(copy_from_slice(
+            &StreamControl::Metadata(metadata).as_payload(),
+        )));
+    }
+
+    /// Change the pacing of the packets, see `TransportOptions::pacing`, the
+    /// sender thread takes it before the next packet. The shared memory
+    /// transport does not pace the packets.
+    pub fn set_pacing(&self, pacing: Option<PacketPacingOptions>) {
+        self.pacing.lock().replace(pacing);
+    }
+
+    pub(crate) fn take_pacing(&self) -> Option<Option<PacketPacingOptions>> {
+        self.pacing.lock().take()
+    }
+
+    fn send_metadata(&self) -> bool {
+        if let Some(metadata) = self.config.metadata.get() {
+            self.channel.send(Some((
+                metadata.clone(),
+                StreamKind::Video,
+                BufferFlag::CONTROL,
+                0,
+            )))
+        } else {
+            true
+        }
     }
 
     // h264 decoding any p-frames and i-frames requires sps and pps
@@ -167,12 +478,17 @@ impl StreamSenderAdapter {
 
         match info {
             StreamBufferInfo::Video(flags, timestamp) => {
-                if flags == BufferFlag::Config as i32 {
+                let flag = flags & BufferFlag::MASK;
+                if flag == BufferFlag::Config as i32 {
                     self.config.video.swap(Some(buf.clone()));
                 }
 
                 // Add SPS and PPS units in front of each keyframe (only use android)
-                if flags == BufferFlag::KeyFrame as i32 {
+                if flag == BufferFlag::KeyFrame as i32 {
+                    if !self.send_metadata() {
+                        return false;
+                    }
+
                     if let Some(config) = self.config.video.get() {
                         if !self.channel.send(Some((
                             config.clone(),
@@ -198,6 +514,10 @@ impl StreamSenderAdapter {
                 self.aioci
                     .audio
                     .update(if count == AutoInsertOfConfigInfo::AUDIO_INTERVAL {
+                        if !self.send_metadata() {
+                            return false;
+                        }
+
                         if let Some(config) = self.config.audio.get() {
                             if !self.channel.send(Some((
                                 config.clone(),
@@ -220,8 +540,45 @@ impl StreamSenderAdapter {
         }
     }
 
-    pub fn next(&self) -> Option<(BytesMut, StreamKind, i32, u64)> {
-        self.channel.recv()
+    /// Take the next packet to send. The audio packets that are waiting are
+    /// taken before the video packets, so that the audio keeps playing when
+    /// the link falls behind, the packets of each track stay in order.
+    pub fn next(&self) -> Option<SendItem> {
+        loop {
+            {
+                let mut queue = self.queue.lock();
+                while !queue.closed {
+                    match self.channel.try_recv() {
+                        Some(Some(item)) => queue.push(item),
+                        Some(None) => queue.closed = true,
+                        None => break,
+                    }
+                }
+
+                if let Some(item) = queue.pop() {
+                    return Some(item);
+                }
+
+                if queue.closed {
+                    return None;
+                }
+            }
+
+            // The queue is not locked while waiting, so that the pending packets can
+            // be counted in the meantime.
+            let item = self.channel.recv();
+
+            let mut queue = self.queue.lock();
+            match item {
+                Some(item) => queue.push(item),
+                None => queue.closed = true,
+            }
+        }
+    }
+
+    /// The number of the packets that are waiting to be sent.
+    pub fn pending(&self) -> usize {
+        self.channel.len() + self.queue.lock().len()
     }
 }
 
@@ -229,12 +586,60 @@ pub trait StreamReceiverAdapterAbstract: Sync + Send {
     fn send(&self, buf: Bytes, kind: StreamKind, flags: i32, timestamp: u64) -> bool;
     fn close(&self);
     fn lose(&self);
+    /// Whether the last packet of the sender was a goodbye, that is the
+    /// sender has closed the stream on purpose instead of being cut off.
+    fn is_remote_closed(&self) -> bool {
+        false
+    }
+
+    /// Whether the sender has disconnected this receiver, the receiver does
+    /// not reattach to the sender then, see `StreamControl::Kick`.
+    fn is_kicked(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Default)]
 struct Filter {
     video: PacketFilter,
     audio: PacketFilter,
+    goodbye: AtomicBool,
+    kicked: AtomicBool,
+    key_frame_handler: RwLock<Option<Box<dyn Fn() + Send + Sync>>>,
+}
+
+impl Filter {
+    // Tracks the goodbye of the sender, any packet after the goodbye comes from a
+    // new sender with the same id, such as after the receiver has reattached.
+    // Returns whether the packet is passed on, the keepalives are answered by the
+    // transport.
+    fn control(&self, buf: &[u8], flags: i32) -> bool {
+        let control = flags & BufferFlag::CONTROL != 0;
+        let kick = control && StreamControl::is_kick(buf);
+        if kick {
+            self.kicked.update(true);
+        }
+
+        // The receiver that is kicked ends its stream as after a goodbye.
+        self.goodbye
+            .update(kick || (control && StreamControl::is_goodbye(buf)));
+
+        !(control && StreamControl::is_ping(buf))
+    }
+
+    fn video(&self, buf: &[u8], flags: i32) -> bool {
+        if self.video.filter(buf, flags, true) {
+            return true;
+        }
+
+        if self.video.is_waiting_key_frame() {
+            if let Some(handler) = self.key_frame_handler.read().as_ref() {
+                handler();
+            }
+        }
+
+        false
+    }
 }
 
 /// Video Audio Streaming Receiver Processing
@@ -252,6 +657,20 @@ impl StreamReceiverAdapter {
     pub fn next(&self) -> Option<(Bytes, StreamKind, i32, u64)> {
         self.channel.recv()
     }
+
+    /// Sets the handler that is called when a video packet is dropped because
+    /// the stream is waiting for a key frame, such as after joining in the
+    /// middle of the stream or after packet loss. The handler is called for
+    /// every dropped packet.
+    pub fn on_key_frame_required<F>(&self, handler: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.filter
+            .key_frame_handler
+            .write()
+            .replace(Box::new(handler));
+    }
 }
 
 impl StreamReceiverAdapterAbstract for StreamReceiverAdapter {
@@ -268,6 +687,14 @@ impl StreamReceiverAdapterAbstract for StreamReceiverAdapter {
         );
     }
 
+    fn is_remote_closed(&self) -> bool {
+        self.filter.goodbye.get()
+    }
+
+    fn is_kicked(&self) -> bool {
+        self.filter.kicked.get()
+    }
+
     /// As soon as a keyframe is received, the keyframe is cached, and when a
     /// packet loss occurs, the previous keyframe is retransmitted directly into
     /// the decoder.
@@ -276,9 +703,13 @@ impl StreamReceiverAdapterAbstract for StreamReceiverAdapter {
             return true;
         }
 
+        if !self.filter.control(&buf, flags) {
+            return true;
+        }
+
         if match kind {
-            StreamKind::Video => self.filter.video.filter(flags, true),
-            StreamKind::Audio => self.filter.audio.filter(flags, false),
+            StreamKind::Video => self.filter.video(&buf, flags),
+            StreamKind::Audio => self.filter.audio.filter(&buf, flags, false),
         } {
             return self.channel.send(Some((buf, kind, flags, timestamp)));
         }
@@ -311,6 +742,41 @@ impl StreamMultiReceiverAdapter {
             StreamKind::Audio => self.channel.audio.recv(),
         }
     }
+
+    /// Same as `next`, but gives up after the timeout, the outer `None` means
+    /// that no packet arrived in time.
+    pub fn next_timeout(
+        &self,
+        kind: StreamKind,
+        timeout: Duration,
+    ) -> Option<Option<(Bytes, i32, u64)>> {
+        match kind {
+            StreamKind::Video => self.channel.video.recv_timeout(timeout),
+            StreamKind::Audio => self.channel.audio.recv_timeout(timeout),
+        }
+    }
+
+    /// The number of the packets that are waiting to be decoded.
+    pub fn pending(&self, kind: StreamKind) -> usize {
+        match kind {
+            StreamKind::Video => self.channel.video.len(),
+            StreamKind::Audio => self.channel.audio.len(),
+        }
+    }
+
+    /// Sets the handler that is called when a video packet is dropped because
+    /// the stream is waiting for a key frame, such as after joining in the
+    /// middle of the stream or after packet loss. The handler is called for
+    /// every dropped packet.
+    pub fn on_key_frame_required<F>(&self, handler: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.filter
+            .key_frame_handler
+            .write()
+            .replace(Box::new(handler));
+    }
 }
 
 impl StreamReceiverAdapterAbstract for StreamMultiReceiverAdapter {
@@ -328,6 +794,14 @@ impl StreamReceiverAdapterAbstract for StreamMultiReceiverAdapter {
         );
     }
 
+    fn is_remote_closed(&self) -> bool {
+        self.filter.goodbye.get()
+    }
+
+    fn is_kicked(&self) -> bool {
+        self.filter.kicked.get()
+    }
+
     /// As soon as a keyframe is received, the keyframe is cached, and when a
     /// packet loss occurs, the previous keyframe is retransmitted directly into
     /// the decoder.
@@ -336,14 +810,18 @@ impl StreamReceiverAdapterAbstract for StreamMultiReceiverAdapter {
             return true;
         }
 
+        if !self.filter.control(&buf, flags) {
+            return true;
+        }
+
         match kind {
             StreamKind::Video => {
-                if self.filter.video.filter(flags, true) {
+                if self.filter.video(&buf, flags) {
                     return self.channel.video.send(Some((buf, flags, timestamp)));
                 }
             }
             StreamKind::Audio => {
-                if self.filter.audio.filter(flags, false) {
+                if self.filter.audio.filter(&buf, flags, false) {
                     return self.channel.audio.send(Some((buf, flags, timestamp)));
                 }
             }
@@ -0,0 +1,279 @@
+//! Packet dumps, for reproducing the problems of a stream.
+//!
+//! When a dump directory is set, each sender and receiver created afterwards
+//! writes all packets that it sends or receives to a file in the directory,
+//! and a dump can be replayed into a receiver adapter, which processes the
+//! packets in the same order with the same loss detection as the receiver.
+//!
+//! The file starts with the magic `HYLDUMP` and a version byte, followed by
+//! the records:
+//!
+//! ```text
+//! | elapsed (u64, microseconds) | sequence (u64) | size (u32) | packet |
+//! ```
+//!
+//! All integers are big endian. The packets are the packages of the transport
+//! before they are unpacked, see `Package`.
+
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Error, ErrorKind, Read, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::Bytes;
+use parking_lot::{Mutex, RwLock};
+
+use crate::{receiver::process_packet, StreamReceiverAdapterAbstract, StreamTracks};
+
+const MAGIC: &[u8; 7] = b"HYLDUMP";
+const VERSION: u8 = 1;
+
+static DUMP_DIRECTORY: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Set the directory that the packets are dumped to, `None` disables the
+/// dumps. This only affects the senders and receivers created afterwards.
+pub fn set_dump_directory(directory: Option<PathBuf>) {
+    log::info!("set transport dump directory={:?}", directory);
+
+    *DUMP_DIRECTORY.write() = directory;
+}
+
+/// Which side of the stream a dump was written by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DumpSide {
+    Sender,
+    Receiver,
+}
+
+struct DumpWriter {
+    file: BufWriter<File>,
+    sequence: u64,
+}
+
+/// Writes the packets of a sender or a receiver to a dump file.
+pub struct PacketDumper {
+    writer: Mutex<Option<DumpWriter>>,
+    start: Instant,
+}
+
+impl PacketDumper {
+    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(MAGIC)?;
+        file.write_all(&[VERSION])?;
+
+        Ok(Self {
+            writer: Mutex::new(Some(DumpWriter { file, sequence: 0 })),
+            start: Instant::now(),
+        })
+    }
+
+    // Create a dumper in the dump directory if it is set, the errors are only
+    // logged, so that the stream works without the dump.
+    pub(crate) fn from_directory(id: &str, side: DumpSide) -> Option<Self> {
+        let directory = DUMP_DIRECTORY.read().clone()?;
+        let path = directory.join(format!(
+            "{}-{}-{}.dump",
+            id,
+            match side {
+                DumpSide::Sender => "sender",
+                DumpSide::Receiver => "receiver",
+            },
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|it| it.as_millis())
+                .unwrap_or(0)
+        ));
+
+        match Self::new(&path) {
+            Ok(it) => {
+                log::info!("transport dump packets to path={:?}", path);
+
+                Some(it)
+            }
+            Err(e) => {
+                log::error!(
+                    "failed to create transport dump, path={:?}, err={:?}",
+                    path,
+                    e
+                );
+
+                None
+            }
+        }
+    }
+
+    /// Write a packet that is sent, the packets of the sender are numbered in
+    /// the order they are written.
+    pub fn sent(&self, packet: &[u8]) {
+        self.write(None, packet);
+    }
+
+    /// Write a packet that is received with the sequence number of the
+    /// transport, the packets that are lost are missing from the sequence.
+    pub fn received(&self, sequence: u64, packet: &[u8]) {
+        self.write(Some(sequence), packet);
+    }
+
+    fn write(&self, sequence: Option<u64>, packet: &[u8]) {
+        let elapsed = self.start.elapsed().as_micros() as u64;
+
+        let mut writer = self.writer.lock();
+        let result = if let Some(writer) = writer.as_mut() {
+            let sequence = sequence.unwrap_or_else(|| {
+                writer.sequence += 1;
+                writer.sequence - 1
+            });
+
+            (|| {
+                writer.file.write_all(&elapsed.to_be_bytes())?;
+                writer.file.write_all(&sequence.to_be_bytes())?;
+                writer
+                    .file
+                    .write_all(&(packet.len() as u32).to_be_bytes())?;
+                writer.file.write_all(packet)
+            })()
+        } else {
+            return;
+        };
+
+        // The dump stops at the first error, the stream is not affected.
+        if let Err(e) = result {
+            log::error!("failed to write transport dump, err={:?}", e);
+
+            writer.take();
+        }
+    }
+}
+
+impl Drop for PacketDumper {
+    fn drop(&mut self) {
+        if let Some(mut writer) = self.writer.lock().take() {
+            if let Err(e) = writer.file.flush() {
+                log::warn!("failed to flush transport dump, err={:?}", e);
+            }
+        }
+    }
+}
+
+/// A packet of a dump.
+#[derive(Debug, Clone)]
+pub struct DumpRecord {
+    /// The time since the dump was created.
+    pub elapsed: Duration,
+    pub sequence: u64,
+    pub packet: Bytes,
+}
+
+/// Reads the packets of a dump file in the order they were written.
+pub struct PacketDumpReader(BufReader<File>);
+
+impl PacketDumpReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let mut file = BufReader::new(File::open(path)?);
+
+        let mut header = [0u8; 8];
+        file.read_exact(&mut header)?;
+        if &header[..7] != MAGIC || header[7] != VERSION {
+            return Err(Error::new(ErrorKind::InvalidData, "not a transport dump"));
+        }
+
+        Ok(Self(file))
+    }
+}
+
+impl Iterator for PacketDumpReader {
+    type Item = Result<DumpRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut head = [0u8; 20];
+        match self.0.read_exact(&mut head) {
+            Ok(_) => (),
+            // The dump of a stream that was not closed may end in the middle of a
+            // record.
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return None,
+            Err(e) => return Some(Err(e)),
+        }
+
+        let mut packet = vec![0u8; u32::from_be_bytes(head[16..20].try_into().unwrap()) as usize];
+        if let Err(e) = self.0.read_exact(&mut packet) {
+            return if e.kind() == ErrorKind::UnexpectedEof {
+                None
+            } else {
+                Some(Err(e))
+            };
+        }
+
+        Some(Ok(DumpRecord {
+            elapsed: Duration::from_micros(u64::from_be_bytes(head[0..8].try_into().unwrap())),
+            sequence: u64::from_be_bytes(head[8..16].try_into().unwrap()),
+            packet: Bytes::from(packet),
+        }))
+    }
+}
+
+/// Options of replaying a dump.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayOptions {
+    /// Feed the packets at the times they were recorded, otherwise they are
+    /// fed as fast as the adapter takes them. The order of the packets is the
+    /// same in both cases.
+    pub realtime: bool,
+}
+
+/// The counts of a replay.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ReplayStats {
+    pub packets: u64,
+    /// The packets that are missing from the sequence or cannot be unpacked,
+    /// the adapter is told about each of them.
+    pub lost: u64,
+}
+
+/// Feed the packets of a dump into a receiver adapter, the adapter is not
+/// closed at the end, so that the caller can drain it first. Returns early if
+/// the adapter refuses a packet.
+pub fn replay<T, P>(path: P, adapter: &T, options: ReplayOptions) -> Result<ReplayStats, Error>
+where
+    T: StreamReceiverAdapterAbstract,
+    P: AsRef<Path>,
+{
+    let reader = PacketDumpReader::open(path)?;
+    let start = Instant::now();
+
+    let mut stats = ReplayStats::default();
+    let mut sequence = 0;
+
+    for record in reader {
+        let record = record?;
+
+        if options.realtime {
+            if let Some(delay) = record.elapsed.checked_sub(start.elapsed()) {
+                thread::sleep(delay);
+            }
+        }
+
+        stats.packets += 1;
+
+        let (accepted, lost) = process_packet(
+            adapter,
+            None,
+            StreamTracks::All,
+            &mut sequence,
+            record.sequence,
+            record.packet,
+        );
+        if lost {
+            stats.lost += 1;
+        }
+
+        if !accepted {
+            break;
+        }
+    }
+
+    Ok(stats)
+}
@@ -0,0 +1,152 @@
+use std::{
+    io::Error,
+    sync::{atomic::AtomicU64, Arc, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::{Buf, BufMut};
+use hylarana_common::{
+    atomic::EasyAtomic,
+    runtime::{self, ThreadKind},
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+
+use crate::{BufferFlag, Package, StreamControl, StreamSenderAdapter};
+
+// The clock of the keepalives, the times in the pings are only read by the
+// sender that sent them.
+static EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+
+// The pongs are sent on the back channel of the receiver, they start with a
+// magic that the messages of the application do not start with.
+const PONG_MAGIC: [u8; 4] = [0xFF, b'H', b'Y', b'K'];
+
+/// Options of the keepalives of the SRT connections, the multicast, loopback
+/// and shared memory strategies do not use them.
+///
+/// The sender sends a ping to the receivers in every interval, and the
+/// receivers answer it on the back channel, so a connection that is only
+/// open on one side is found out within the timeout instead of the timeout
+/// of the system. The answers also measure the round trip time, see
+/// `BandwidthEstimate::rtt`.
+///
+/// The sender closes the connection of a direct receiver that has not
+/// answered in time, a relay sender does not know about the receivers, it
+/// only measures the round trip time. The receiver closes the connection if
+/// nothing has arrived from the sender in time, so the sender has to send
+/// the pings as well, otherwise a paused stream is taken as lost.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KeepaliveOptions {
+    /// How often the sender sends a ping, 1 second by default.
+    pub interval: Duration,
+    /// How long a side waits for the other side before it closes the
+    /// connection, 5 seconds by default.
+    pub timeout: Duration,
+}
+
+impl Default for KeepaliveOptions {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(5),
+        }
+    }
+}
+
+fn now() -> u64 {
+    EPOCH.elapsed().as_micros() as u64
+}
+
+// Sends a ping through the adapter in every interval until the adapter is
+// closed, the pings are queued behind the packets that are waiting to be sent.
+pub(crate) fn spawn_pinger(
+    adapter: Weak<StreamSenderAdapter>,
+    options: KeepaliveOptions,
+) -> Result<(), Error> {
+    runtime::spawn(ThreadKind::Transport, "StreamKeepalive", move || {
+        loop {
+            thread::sleep(options.interval);
+
+            let Some(adapter) = adapter.upgrade() else {
+                break;
+            };
+
+            if !adapter.send_control(&StreamControl::Ping { sent: now() }) {
+                break;
+            }
+        }
+
+        log::info!("keepalive pinger is closed");
+    })?;
+
+    Ok(())
+}
+
+// The time of the ping in a package of the stream, the header is peeked
+// without checking it, the package is checked when it is passed on.
+pub(crate) fn ping(bytes: &[u8]) -> Option<u64> {
+    if bytes.len() <= Package::HEAD_SIZE || bytes[4] as i32 & BufferFlag::CONTROL == 0 {
+        return None;
+    }
+
+    match StreamControl::from_payload(&bytes[Package::HEAD_SIZE..]) {
+        Some(StreamControl::Ping { sent }) => Some(sent),
+        _ => None,
+    }
+}
+
+// The answer of the receiver to a ping, it carries the time of the ping back.
+pub(crate) fn pong(sent: u64) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(12);
+    bytes.put_slice(&PONG_MAGIC);
+    bytes.put_u64(sent);
+    bytes
+}
+
+// When the sender has last heard from a receiver, and the round trip time of
+// the last pong, the times are in microseconds of the clock of the keepalives.
+pub(crate) struct Heartbeat {
+    last: AtomicU64,
+    rtt: AtomicU64,
+}
+
+impl Heartbeat {
+    pub(crate) fn new() -> Arc<Self> {
+        Arc::new(Self {
+            last: AtomicU64::new(now()),
+            rtt: AtomicU64::new(0),
+        })
+    }
+
+    // Any message of the receiver shows that it is alive, returns whether the
+    // message is a pong, which is not passed to the application.
+    pub(crate) fn received(&self, mut message: &[u8]) -> bool {
+        let now = now();
+        self.last.update(now);
+
+        if message.len() != 12 || message[..4] != PONG_MAGIC {
+            return false;
+        }
+
+        message.advance(4);
+        self.rtt
+            .update(now.saturating_sub(message.get_u64()).max(1));
+
+        true
+    }
+
+    pub(crate) fn is_expired(&self, timeout: Duration) -> bool {
+        now().saturating_sub(self.last.get()) > timeout.as_micros() as u64
+    }
+
+    // `None` until the first pong has arrived.
+    pub(crate) fn rtt(&self) -> Option<Duration> {
+        match self.rtt.get() {
+            0 => None,
+            rtt => Some(Duration::from_micros(rtt)),
+        }
+    }
+}
@@ -1,20 +1,39 @@
 mod adapter;
+mod dump;
+mod keepalive;
+mod loopback;
+mod mtu;
 mod multicast;
+mod pacer;
 mod package;
+mod pairing;
 mod receiver;
 mod sender;
+mod shm;
 mod transmission;
 
 pub use self::{
     adapter::{
-        BufferFlag, StreamBufferInfo, StreamKind, StreamMultiReceiverAdapter,
+        BufferFlag, StreamBufferInfo, StreamControl, StreamKind, StreamMultiReceiverAdapter,
         StreamReceiverAdapter, StreamReceiverAdapterAbstract, StreamSenderAdapter,
     },
+    dump::{
+        replay, set_dump_directory, DumpRecord, DumpSide, PacketDumpReader, PacketDumper,
+        ReplayOptions, ReplayStats,
+    },
+    keepalive::KeepaliveOptions,
+    loopback::LoopbackOptions,
     multicast::{Server as MulticastServer, Socket as MulticastSocket},
-    package::{copy_from_slice, with_capacity, Package, PacketInfo, UnPackage},
+    pacer::PacketPacingOptions,
+    package::{
+        copy_from_slice, with_capacity, Package, PacketInfo, PacketPool, UnPackage, UnPackageError,
+    },
+    pairing::PairingOptions,
     receiver::{create_mix_receiver, create_split_receiver, Receiver as TransportReceiver},
-    sender::{create_sender, Sender as TransportSender},
+    sender::{create_sender, create_sender_with_id, ReceiverInfo, Sender as TransportSender},
+    shm::SharedMemoryOptions,
     transmission::{
+        ConnectHandler as TransmissionConnectHandler,
         FragmentDecoder as TransmissionFragmentDecoder,
         FragmentEncoder as TransmissionFragmentEncoder, Options as TransmissionOptions,
         Server as TransmissionServer, Socket as TransmissionSocket,
@@ -23,8 +42,9 @@ pub use self::{
 
 use std::{
     io::{Error, ErrorKind},
-    net::SocketAddr,
+    net::{Ipv4Addr, SocketAddr},
     str::FromStr,
+    time::Duration,
 };
 
 use serde::{Deserialize, Serialize};
@@ -75,6 +95,136 @@ pub enum TransportStrategy {
     /// example: 239.0.0.1:8080
     /// ```
     Multicast(SocketAddr),
+    /// In-process mode, the receiver gets the packets of the sender in the same
+    /// process through a simulated network, which can lose, delay, reorder and
+    /// throttle the packets, such as for the tests on a single machine.
+    ///
+    /// The receiver finds the sender by the ID of the sender, the options of
+    /// the receiver describe its network, the options of the sender are
+    /// ignored.
+    Loopback(LoopbackOptions),
+    /// Same machine mode, the sender writes the raw frames to a ring buffer in
+    /// the shared memory and the receivers in other processes read them, the
+    /// frames are neither encoded nor sent over the network, such as for the
+    /// local monitoring of a stream.
+    ///
+    /// The receiver finds the sender by the ID of the sender, the options of
+    /// the receiver are ignored. A receiver that falls behind skips to the
+    /// latest frame, and there is no back channel from the receivers to the
+    /// sender.
+    SharedMemory(SharedMemoryOptions),
+}
+
+/// The tracks of a stream that a receiver takes.
+///
+/// The direct, relay and loopback senders do not send the other track to the
+/// receiver at all. The multicast and shared memory senders have a single
+/// stream for all the receivers, the receiver drops the other track.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StreamTracks {
+    #[default]
+    All,
+    Video,
+    Audio,
+}
+
+impl StreamTracks {
+    /// Whether a package is sent to the receivers of the tracks, the control
+    /// messages are carried in the video track, they are sent to the
+    /// receivers of the audio as well.
+    pub fn contains(&self, kind: StreamKind, flags: i32) -> bool {
+        match self {
+            Self::All => true,
+            Self::Video => kind == StreamKind::Video,
+            Self::Audio => kind == StreamKind::Audio || flags & BufferFlag::CONTROL != 0,
+        }
+    }
+}
+
+/// The estimate of the network link from the congestion control of SRT, the
+/// multicast, loopback and shared memory modes have no estimate.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct BandwidthEstimate {
+    /// The estimated capacity of the link, in bits per second.
+    pub bandwidth: u64,
+    /// The rate that the stream is sent or received at since the previous
+    /// estimate, in bits per second.
+    pub rate: u64,
+    /// The round trip time, the sender measures it by the keepalives if they
+    /// are enabled, see `KeepaliveOptions`.
+    pub rtt: Duration,
+    /// The MTU that the two sides of the connection agreed on, the packets
+    /// of the stream are fragmented to fit in it.
+    pub mtu: usize,
+}
+
+/// Options of the multicast strategy, the defaults work within a single
+/// subnet.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MulticastOptions {
+    /// The number of routers that the packets of the sender can cross, 1
+    /// keeps the packets in the local network, a routed multicast network
+    /// needs a larger value.
+    pub ttl: u32,
+    /// The local interface that the packets are sent and received on, see
+    /// `IP_MULTICAST_IF`. The system chooses the interface by the routing
+    /// table if this is `None`.
+    pub interface: Option<Ipv4Addr>,
+    /// Only receive the packets of this source, which is the address of the
+    /// sender, by joining the group as a source-specific multicast (SSM)
+    /// group, the group has to be in the SSM range `232.0.0.0/8`. This only
+    /// affects the receivers.
+    pub source: Option<Ipv4Addr>,
+}
+
+impl Default for MulticastOptions {
+    fn default() -> Self {
+        Self {
+            ttl: 1,
+            interface: None,
+            source: None,
+        }
+    }
+}
+
+/// The DSCP values that the packets of the sender are marked with, so that
+/// the routers and switches of a managed network can prioritize the stream,
+/// see [RFC 4594](https://www.rfc-editor.org/rfc/rfc4594).
+///
+/// The multicast sender marks the packets of each track with its own value.
+/// An SRT connection carries all the tracks, it is marked with the value of
+/// the video. On Windows the multicast packets are marked through qWave,
+/// which only sets the value for the administrators and otherwise falls back
+/// to the marking of the audio and video traffic type, the SRT connections
+/// are not marked.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DscpOptions {
+    /// Expedited forwarding (EF, 46) by default.
+    pub audio: u8,
+    /// Assured forwarding 41 (AF41, 34) by default.
+    pub video: u8,
+}
+
+impl Default for DscpOptions {
+    fn default() -> Self {
+        Self {
+            audio: 46,
+            video: 34,
+        }
+    }
+}
+
+impl DscpOptions {
+    /// The value of the packets of a track, the control messages are carried
+    /// in the video track.
+    pub fn value(&self, kind: StreamKind) -> u8 {
+        match kind {
+            StreamKind::Audio => self.audio,
+            StreamKind::Video => self.video,
+        }
+    }
 }
 
 /// Transport configuration.
@@ -82,7 +232,51 @@ pub enum TransportStrategy {
 pub struct TransportOptions {
     pub strategy: TransportStrategy,
     /// see: [Maximum_transmission_unit](https://en.wikipedia.org/wiki/Maximum_transmission_unit)
+    ///
+    /// The MTU of the path to the peer is discovered if this is 0, 1400 is
+    /// taken where it cannot be discovered, such as on macOS. The MTU is
+    /// clamped between 576 and 1500, and an SRT connection takes the smaller
+    /// MTU of its two sides, see `BandwidthEstimate::mtu`.
+    #[serde(default)]
     pub mtu: usize,
+    /// Only used by the multicast strategy.
+    #[serde(default)]
+    pub multicast: MulticastOptions,
+    /// Mark the packets of the sender for the quality of service, the packets
+    /// are not marked if this is `None`. The loopback and shared memory
+    /// strategies do not use the network.
+    #[serde(default)]
+    pub dscp: Option<DscpOptions>,
+    /// How long a receiver waits for a sender with the same ID when the
+    /// stream of the sender has ended, such as when the sender restarts, the
+    /// receiver reattaches to the new sender instead of being closed. The
+    /// receiver is closed right away if this is `None`. A multicast receiver
+    /// is never closed by the sender, it takes the packets of a new sender
+    /// with the same ID anyway.
+    #[serde(default)]
+    pub resume: Option<Duration>,
+    /// Spread the packets of the video frames over the frame interval, see
+    /// `PacketPacingOptions`, the frames are sent as bursts if this is `None`. The
+    /// loopback and shared memory strategies do not use the network.
+    #[serde(default)]
+    pub pacing: Option<PacketPacingOptions>,
+    /// Send keepalives on the SRT connections, see `KeepaliveOptions`, the
+    /// connections only rely on the timeout of SRT if this is `None`.
+    #[serde(default)]
+    pub keepalive: Option<KeepaliveOptions>,
+    /// The latency of the SRT connections, the packets that have not arrived
+    /// within it are dropped, 20 milliseconds if this is `None`. The large
+    /// frames, such as the frames of the lossless video, take longer than
+    /// that to send. The latency of a sender also applies to its receivers,
+    /// the relay server keeps its own latency.
+    #[serde(default)]
+    pub latency: Option<Duration>,
+}
+
+// The latency of the SRT connections in milliseconds, see
+// `TransportOptions::latency`.
+pub(crate) fn srt_latency(latency: Option<Duration>) -> u32 {
+    latency.map(|it| it.as_millis() as u32).unwrap_or(20)
 }
 
 #[repr(u8)]
@@ -93,10 +287,38 @@ pub enum StreamInfoKind {
     Publisher = 1,
 }
 
+/// The stream information carried in the srt handshake.
 #[derive(Default, Debug, Clone)]
 pub struct StreamInfo {
     pub id: String,
     pub kind: StreamInfoKind,
+    /// The version of the package format of the peer, zero for the peers
+    /// that do not carry a version, see `Package::VERSION`.
+    pub version: u8,
+    /// The tracks that a subscriber takes, the peers that do not carry it
+    /// take all the tracks.
+    pub tracks: StreamTracks,
+    /// The pairing code that a subscriber submits to the sender, see
+    /// `PairingOptions`.
+    pub pairing_code: Option<String>,
+}
+
+impl StreamInfo {
+    pub fn new(id: String, kind: StreamInfoKind) -> Self {
+        Self {
+            version: Package::VERSION,
+            tracks: StreamTracks::All,
+            pairing_code: None,
+            kind,
+            id,
+        }
+    }
+
+    /// Whether the peer uses the same package format, the peers of other
+    /// versions are rejected during the handshake.
+    pub fn is_compatible(&self) -> bool {
+        self.version == Package::VERSION
+    }
 }
 
 impl FromStr for StreamInfo {
@@ -124,6 +346,21 @@ impl FromStr for StreamInfo {
                                 }
                             }
                         }
+                        "v" => {
+                            if let Ok(version) = v.parse::<u8>() {
+                                info.version = version;
+                            }
+                        }
+                        "t" => {
+                            info.tracks = match v {
+                                "1" => StreamTracks::Video,
+                                "2" => StreamTracks::Audio,
+                                _ => StreamTracks::All,
+                            };
+                        }
+                        "p" => {
+                            info.pairing_code = Some(v.to_string());
+                        }
                         _ => (),
                     }
                 }
@@ -138,6 +375,24 @@ impl FromStr for StreamInfo {
 
 impl ToString for StreamInfo {
     fn to_string(&self) -> String {
-        format!("#!::i={},k={}", self.id, self.kind as u8)
+        let mut value = format!(
+            "#!::i={},k={},v={},t={}",
+            self.id,
+            self.kind as u8,
+            self.version,
+            match self.tracks {
+                StreamTracks::All => 0,
+                StreamTracks::Video => 1,
+                StreamTracks::Audio => 2,
+            }
+        );
+
+        // The peers of older versions ignore the fields they do not know.
+        if let Some(code) = &self.pairing_code {
+            value.push_str(",p=");
+            value.push_str(code);
+        }
+
+        value
     }
 }
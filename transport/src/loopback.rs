@@ -0,0 +1,338 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    io::{Error, ErrorKind},
+    sync::{Arc, Weak},
+    thread,
+    time::{Duration, Instant},
+};
+
+use bytes::Bytes;
+use crossbeam::channel::{unbounded, RecvTimeoutError, Sender as ChannelSender};
+use hylarana_common::runtime::{self, ThreadKind};
+use once_cell::sync::Lazy;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adapter::StreamReceiverAdapterAbstract,
+    dump::{DumpSide, PacketDumper},
+    receiver::{process_packet, Resume},
+    sender::MessageHandler,
+    Package, PacketInfo, StreamSenderAdapter, StreamTracks,
+};
+
+/// The network conditions that the loopback transport simulates between the
+/// sender and a receiver, the default is a perfect network.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LoopbackOptions {
+    /// The probability that a fragment of the MTU size is lost, from 0.0 to
+    /// 1.0, a packet is lost if any of its fragments is lost.
+    #[serde(default)]
+    pub loss: f32,
+    /// The delay of every packet, in milliseconds.
+    #[serde(default)]
+    pub latency: u32,
+    /// A random delay of up to this many milliseconds that is added to every
+    /// packet.
+    #[serde(default)]
+    pub jitter: u32,
+    /// The probability that a packet is held back behind the following
+    /// packets, from 0.0 to 1.0.
+    #[serde(default)]
+    pub reorder: f32,
+    /// The bandwidth of the link in bits per second, the packets queue behind
+    /// each other when it is exceeded. Zero is unlimited.
+    #[serde(default)]
+    pub bandwidth: u64,
+    /// The seed of the random decisions, the same seed loses and delays the
+    /// same packets of the same stream.
+    #[serde(default)]
+    pub seed: u64,
+}
+
+// A sender of the loopback transport, the receivers find it by the id of the
+// sender.
+struct Hub {
+    // The links of the receivers and the tracks that they take.
+    links: Mutex<Vec<(ChannelSender<Bytes>, StreamTracks)>>,
+    handler: MessageHandler,
+}
+
+static HUBS: Lazy<RwLock<HashMap<String, Weak<Hub>>>> = Lazy::new(Default::default);
+
+fn find_hub(id: &str) -> Option<Arc<Hub>> {
+    HUBS.read().get(id).and_then(|it| it.upgrade())
+}
+
+/// The loopback side of a receiver, the messages go to the handler of the
+/// sender directly. The sender is replaced when the receiver reattaches.
+pub struct Socket(Arc<RwLock<Weak<Hub>>>);
+
+impl Socket {
+    pub fn send(&self, message: &[u8]) -> Result<(), Error> {
+        let hub = self
+            .0
+            .read()
+            .upgrade()
+            .ok_or_else(|| Error::new(ErrorKind::NotConnected, "sender is closed"))?;
+
+        if let Some(handler) = hub.handler.read().as_ref() {
+            handler(message);
+        }
+
+        Ok(())
+    }
+}
+
+// xorshift64*, the simulation has to be reproducible, so it does not use the
+// random generator of the system.
+struct Random(u64);
+
+impl Random {
+    fn new(seed: u64) -> Self {
+        Self(if seed == 0 {
+            0x9E37_79B9_7F4A_7C15
+        } else {
+            seed
+        })
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    // A number in 0.0..1.0.
+    fn float(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+pub(crate) fn create_sender(
+    id: &str,
+    adapter: &Arc<StreamSenderAdapter>,
+    handler: MessageHandler,
+) -> Result<(), Error> {
+    let hub = Arc::new(Hub {
+        links: Mutex::new(Vec::with_capacity(5)),
+        handler,
+    });
+
+    HUBS.write().insert(id.to_string(), Arc::downgrade(&hub));
+
+    log::info!("create loopback sender, id={}", id);
+
+    let id = id.to_string();
+    let stream = Package::stream_id(&id);
+    let dumper = PacketDumper::from_directory(&id, DumpSide::Sender);
+    let adapter_ = Arc::downgrade(adapter);
+    runtime::spawn(ThreadKind::Transport, "StreamLoopbackSender", move || {
+        // If the adapter has been released, close the current thread
+        while let Some(adapter) = adapter_.upgrade() {
+            if let Some((buf, kind, flags, timestamp)) = adapter.next() {
+                if buf.is_empty() {
+                    continue;
+                }
+
+                let payload = Package::pack(
+                    PacketInfo {
+                        kind,
+                        flags,
+                        timestamp,
+                        stream,
+                        fec_group: 0,
+                    },
+                    buf,
+                );
+
+                if let Some(dumper) = dumper.as_ref() {
+                    dumper.sent(&payload);
+                }
+
+                // The link of a receiver that has been closed is removed.
+                hub.links.lock().retain(|(link, tracks)| {
+                    !tracks.contains(kind, flags) || link.send(payload.clone()).is_ok()
+                });
+            } else {
+                break;
+            }
+        }
+
+        log::info!("loopback sender is closed, id={}", id);
+
+        {
+            let mut hubs = HUBS.write();
+            if hubs.get(&id).map(|it| it.as_ptr()) == Some(Arc::as_ptr(&hub)) {
+                hubs.remove(&id);
+            }
+        }
+
+        if let Some(adapter) = adapter_.upgrade() {
+            adapter.close();
+        }
+    })?;
+
+    Ok(())
+}
+
+// A packet waiting to be delivered, ordered by the time it is delivered and
+// then by the order it was sent.
+type Delayed = Reverse<(Instant, u64, Bytes)>;
+
+pub(crate) fn create_receiver<T: StreamReceiverAdapterAbstract + 'static>(
+    id: &str,
+    options: LoopbackOptions,
+    mtu: usize,
+    tracks: StreamTracks,
+    resume: Resume,
+    adapter: &Arc<T>,
+) -> Result<Socket, Error> {
+    let hub = find_hub(id)
+        .ok_or_else(|| Error::new(ErrorKind::NotFound, "loopback sender is not found"))?;
+
+    let (tx, mut rx) = unbounded::<Bytes>();
+    hub.links.lock().push((tx, tracks));
+
+    let shared = Arc::new(RwLock::new(Arc::downgrade(&hub)));
+    let shared_ = shared.clone();
+
+    log::info!("create loopback receiver, id={}, options={:?}", id, options);
+
+    let id = id.to_string();
+    let stream = Package::stream_id(&id);
+    let dumper = PacketDumper::from_directory(&id, DumpSide::Receiver);
+    let adapter_ = Arc::downgrade(adapter);
+    runtime::spawn(ThreadKind::Transport, "StreamLoopbackReceiver", move || {
+        let mut random = Random::new(options.seed);
+        let mut queue: BinaryHeap<Delayed> = BinaryHeap::new();
+        let mut transmitted = Instant::now();
+        let mut sent = 0;
+        let mut sequence = 0;
+        let mut closed = false;
+
+        'a: while !closed || !queue.is_empty() {
+            let now = Instant::now();
+
+            // Deliver the packets that are due.
+            while let Some(Reverse((at, _, _))) = queue.peek() {
+                if *at > now {
+                    break;
+                }
+
+                let Reverse((_, seq, bytes)) = queue.pop().unwrap();
+                if let Some(dumper) = dumper.as_ref() {
+                    dumper.received(seq, &bytes);
+                }
+
+                if let Some(adapter) = adapter_.upgrade() {
+                    if !process_packet(
+                        adapter.as_ref(),
+                        Some(stream),
+                        tracks,
+                        &mut sequence,
+                        seq,
+                        bytes,
+                    )
+                    .0
+                    {
+                        break 'a;
+                    }
+                } else {
+                    break 'a;
+                }
+            }
+
+            if closed {
+                if let Some(Reverse((at, _, _))) = queue.peek() {
+                    thread::sleep(at.saturating_duration_since(Instant::now()));
+                }
+
+                continue;
+            }
+
+            // Wake up for the next packet that is due, or regularly to find out
+            // whether the receiver has been released.
+            let timeout = queue
+                .peek()
+                .map(|Reverse((at, _, _))| at.saturating_duration_since(now))
+                .unwrap_or(Duration::from_millis(100))
+                .min(Duration::from_millis(100));
+
+            let bytes = match rx.recv_timeout(timeout) {
+                Ok(it) => it,
+                Err(RecvTimeoutError::Timeout) => {
+                    if adapter_.strong_count() == 0 {
+                        break;
+                    }
+
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    // The packets of the old sender that are still on the way are
+                    // delivered once the new sender is found.
+                    if let Some(hub) = resume.wait(|| find_hub(&id)) {
+                        let (tx, it) = unbounded::<Bytes>();
+                        hub.links.lock().push((tx, tracks));
+                        *shared_.write() = Arc::downgrade(&hub);
+                        rx = it;
+
+                        // The decoders wait for the first key frame of the new sender.
+                        if let Some(adapter) = adapter_.upgrade() {
+                            adapter.lose();
+                        }
+
+                        log::info!("loopback receiver reattached to sender, id={}", id);
+
+                        resume.reattached();
+                    } else {
+                        closed = true;
+                    }
+
+                    continue;
+                }
+            };
+
+            let seq = sent;
+            sent += 1;
+
+            // The packet is fragmented by the MTU like on the network, it is
+            // lost if any of the fragments is lost.
+            let fragments = bytes.len().div_ceil(mtu.max(1)) as i32;
+            if options.loss > 0.0 && random.float() < 1.0 - (1.0 - options.loss).powi(fragments) {
+                continue;
+            }
+
+            let mut at = Instant::now();
+            if let Some(micros) =
+                (bytes.len() as u64 * 8 * 1_000_000).checked_div(options.bandwidth)
+            {
+                transmitted = transmitted.max(at) + Duration::from_micros(micros);
+                at = transmitted;
+            }
+
+            let mut delay = options.latency as u64 * 1000;
+            if options.jitter > 0 {
+                delay += random.next() % (options.jitter as u64 * 1000);
+            }
+
+            // A reordered packet arrives after the packets that follow it
+            // within the jitter, or one millisecond later.
+            if options.reorder > 0.0 && random.float() < options.reorder {
+                delay += (options.jitter as u64).max(1) * 1000;
+            }
+
+            queue.push(Reverse((at + Duration::from_micros(delay), seq, bytes)));
+        }
+
+        log::warn!("loopback receiver is closed, id={}", id);
+
+        if let Some(adapter) = adapter_.upgrade() {
+            adapter.close();
+        }
+    })?;
+
+    Ok(Socket(shared))
+}
@@ -0,0 +1,233 @@
+use std::{
+    io::Error,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
+    thread,
+    time::Duration,
+};
+
+// The range that the MTU of the transport is clamped to, the MTU of the options
+// as well as the discovered MTU.
+pub(crate) const MIN_MTU: usize = 576;
+pub(crate) const MAX_MTU: usize = 1500;
+
+// The MTU that is taken when the path cannot be discovered, which also fits
+// through the common tunnels and VPNs.
+pub(crate) const DEFAULT_MTU: usize = 1400;
+
+// The probes are sent to the discard port of the peer, so they do not reach the
+// sockets of the stream.
+const DISCARD_PORT: u16 = 9;
+
+// How long the routers on the path are given to report that the probe does not
+// fit.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(100);
+
+// The MTU of the options clamped, the default is taken if it is zero, such as
+// for a listener that does not know its peers yet.
+pub(crate) fn clamp(mtu: usize, default: usize) -> usize {
+    if mtu == 0 {
+        default
+    } else {
+        mtu.clamp(MIN_MTU, MAX_MTU)
+    }
+}
+
+// The MTU of the options is only clamped, the MTU of the path to the address is
+// discovered if it is zero.
+pub(crate) fn resolve(mtu: usize, addr: SocketAddr) -> usize {
+    if mtu != 0 {
+        return mtu.clamp(MIN_MTU, MAX_MTU);
+    }
+
+    match discover(addr) {
+        Ok(mtu) => {
+            let mtu = mtu.clamp(MIN_MTU, MAX_MTU);
+            log::info!("discovered path mtu, addr={}, mtu={}", addr, mtu);
+
+            mtu
+        }
+        Err(e) => {
+            log::warn!(
+                "failed to discover path mtu, use the default, addr={}, err={:?}",
+                addr,
+                e
+            );
+
+            DEFAULT_MTU
+        }
+    }
+}
+
+// The kernel knows the MTU of the route to the address, a probe of that size is
+// sent with the don't fragment bit, and the routers that the probe does not fit
+// through report their MTU back to the kernel, which is read again after the
+// probe.
+fn discover(addr: SocketAddr) -> Result<usize, Error> {
+    let socket = UdpSocket::bind(SocketAddr::new(
+        match addr.ip() {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        },
+        0,
+    ))?;
+
+    socket.connect(SocketAddr::new(addr.ip(), DISCARD_PORT))?;
+    sys::set_dont_fragment(&socket, addr.is_ipv4())?;
+
+    // The IP and UDP headers are part of the MTU.
+    let mtu = sys::path_mtu(&socket, addr.is_ipv4())?;
+    let header = if addr.is_ipv4() { 28 } else { 48 };
+
+    // The send fails right away if the probe is larger than the MTU that the
+    // kernel already knows, which is not an error of the discovery.
+    let _ = socket.send(&vec![0u8; mtu.saturating_sub(header)]);
+    thread::sleep(PROBE_TIMEOUT);
+
+    sys::path_mtu(&socket, addr.is_ipv4())
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod sys {
+    use std::{io::Error, mem::size_of, net::UdpSocket, os::fd::AsRawFd};
+
+    use libc::{
+        c_int, getsockopt, setsockopt, socklen_t, IPPROTO_IP, IPPROTO_IPV6, IPV6_MTU,
+        IPV6_MTU_DISCOVER, IPV6_PMTUDISC_DO, IP_MTU, IP_MTU_DISCOVER, IP_PMTUDISC_DO,
+    };
+
+    pub fn set_dont_fragment(socket: &UdpSocket, v4: bool) -> Result<(), Error> {
+        let (level, name, value) = if v4 {
+            (IPPROTO_IP, IP_MTU_DISCOVER, IP_PMTUDISC_DO)
+        } else {
+            (IPPROTO_IPV6, IPV6_MTU_DISCOVER, IPV6_PMTUDISC_DO)
+        };
+
+        if unsafe {
+            setsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                &value as *const c_int as *const _,
+                size_of::<c_int>() as socklen_t,
+            )
+        } != 0
+        {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub fn path_mtu(socket: &UdpSocket, v4: bool) -> Result<usize, Error> {
+        let (level, name) = if v4 {
+            (IPPROTO_IP, IP_MTU)
+        } else {
+            (IPPROTO_IPV6, IPV6_MTU)
+        };
+
+        let mut value: c_int = 0;
+        let mut size = size_of::<c_int>() as socklen_t;
+        if unsafe {
+            getsockopt(
+                socket.as_raw_fd(),
+                level,
+                name,
+                &mut value as *mut c_int as *mut _,
+                &mut size,
+            )
+        } != 0
+        {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(value as usize)
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod sys {
+    use std::{io::Error, mem::size_of, net::UdpSocket, os::windows::io::AsRawSocket};
+
+    use windows::{
+        core::PSTR,
+        Win32::Networking::WinSock::{getsockopt, setsockopt, IPPROTO_IP, IPPROTO_IPV6, SOCKET},
+    };
+
+    // The options of ws2ipdef.h, the MTU can be read since Windows 10 1703.
+    const IP_MTU_DISCOVER: i32 = 71;
+    const IP_MTU: i32 = 73;
+    const IPV6_MTU_DISCOVER: i32 = 71;
+    const IPV6_MTU: i32 = 72;
+    const IP_PMTUDISC_DO: i32 = 1;
+
+    pub fn set_dont_fragment(socket: &UdpSocket, v4: bool) -> Result<(), Error> {
+        let (level, name) = if v4 {
+            (IPPROTO_IP.0, IP_MTU_DISCOVER)
+        } else {
+            (IPPROTO_IPV6.0, IPV6_MTU_DISCOVER)
+        };
+
+        if unsafe {
+            setsockopt(
+                SOCKET(socket.as_raw_socket() as usize),
+                level,
+                name,
+                Some(&IP_PMTUDISC_DO.to_ne_bytes()),
+            )
+        } != 0
+        {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(())
+    }
+
+    pub fn path_mtu(socket: &UdpSocket, v4: bool) -> Result<usize, Error> {
+        let (level, name) = if v4 {
+            (IPPROTO_IP.0, IP_MTU)
+        } else {
+            (IPPROTO_IPV6.0, IPV6_MTU)
+        };
+
+        let mut value: i32 = 0;
+        let mut size = size_of::<i32>() as i32;
+        if unsafe {
+            getsockopt(
+                SOCKET(socket.as_raw_socket() as usize),
+                level,
+                name,
+                PSTR(&mut value as *mut i32 as *mut u8),
+                &mut size,
+            )
+        } != 0
+        {
+            return Err(Error::last_os_error());
+        }
+
+        Ok(value as usize)
+    }
+}
+
+// The MTU of the path cannot be read on the other platforms, the default is
+// taken.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "windows")))]
+mod sys {
+    use std::{
+        io::{Error, ErrorKind},
+        net::UdpSocket,
+    };
+
+    pub fn set_dont_fragment(_: &UdpSocket, _: bool) -> Result<(), Error> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "path mtu discovery is not supported",
+        ))
+    }
+
+    pub fn path_mtu(_: &UdpSocket, _: bool) -> Result<usize, Error> {
+        Err(Error::new(
+            ErrorKind::Unsupported,
+            "path mtu discovery is not supported",
+        ))
+    }
+}
@@ -0,0 +1,150 @@
+use std::net::{SocketAddr, UdpSocket};
+
+// Marks the packets of a socket with the DSCP values, the value is only
+// applied to the socket when it changes. The marking is given up after the
+// first failure, the packets are still sent.
+pub struct Marker {
+    current: Option<u8>,
+    failed: bool,
+    #[cfg(target_os = "windows")]
+    flow: Option<qos::Flow>,
+}
+
+impl Marker {
+    #[allow(unused_variables)]
+    pub fn new(socket: &UdpSocket, target: SocketAddr) -> Self {
+        Self {
+            current: None,
+            failed: false,
+            #[cfg(target_os = "windows")]
+            flow: match qos::Flow::new(socket, target) {
+                Ok(flow) => Some(flow),
+                Err(e) => {
+                    log::warn!("failed to add multicast socket to qos flow, err={:?}", e);
+
+                    None
+                }
+            },
+        }
+    }
+
+    pub fn mark(&mut self, socket: &UdpSocket, value: u8) {
+        if self.failed || self.current == Some(value) {
+            return;
+        }
+
+        self.current = Some(value);
+
+        // Windows ignores the TOS of the sockets, the value is set on the qos flow.
+        #[cfg(target_os = "windows")]
+        let result = {
+            let _ = socket;
+
+            match self.flow.as_ref() {
+                Some(flow) => flow.set(value),
+                None => return,
+            }
+        };
+
+        #[cfg(not(target_os = "windows"))]
+        let result = socket2::SockRef::from(socket).set_tos((value as u32) << 2);
+
+        if let Err(e) = result {
+            log::warn!(
+                "failed to mark multicast packets, dscp={}, err={:?}",
+                value,
+                e
+            );
+
+            self.failed = true;
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+mod qos {
+    use std::{
+        io::Error,
+        mem::size_of,
+        net::{SocketAddr, UdpSocket},
+        os::windows::io::AsRawSocket,
+    };
+
+    use os_socketaddr::OsSocketAddr;
+    use windows::Win32::{
+        Foundation::HANDLE,
+        NetworkManagement::QoS::{
+            QOSAddSocketToFlow, QOSCloseHandle, QOSCreateHandle, QOSSetFlow,
+            QOSSetOutgoingDSCPValue, QOSTrafficTypeAudioVideo, QOS_NON_ADAPTIVE_FLOW, QOS_VERSION,
+        },
+        Networking::WinSock::{SOCKADDR, SOCKET},
+    };
+
+    // The socket is marked as audio and video traffic by qWave, which only
+    // allows the administrators to set the DSCP value of the flow.
+    pub struct Flow {
+        handle: HANDLE,
+        id: u32,
+    }
+
+    unsafe impl Send for Flow {}
+    unsafe impl Sync for Flow {}
+
+    impl Flow {
+        pub fn new(socket: &UdpSocket, target: SocketAddr) -> Result<Self, Error> {
+            let version = QOS_VERSION {
+                MajorVersion: 1,
+                MinorVersion: 0,
+            };
+
+            let mut this = Self {
+                handle: HANDLE::default(),
+                id: 0,
+            };
+
+            unsafe { QOSCreateHandle(&version, &mut this.handle).ok()? };
+
+            let target = OsSocketAddr::from(target);
+            unsafe {
+                QOSAddSocketToFlow(
+                    this.handle,
+                    SOCKET(socket.as_raw_socket() as usize),
+                    Some(target.as_ptr() as *const SOCKADDR),
+                    QOSTrafficTypeAudioVideo,
+                    QOS_NON_ADAPTIVE_FLOW,
+                    &mut this.id,
+                )
+                .ok()?
+            };
+
+            Ok(this)
+        }
+
+        pub fn set(&self, value: u8) -> Result<(), Error> {
+            let value = value as u32;
+            unsafe {
+                QOSSetFlow(
+                    self.handle,
+                    self.id,
+                    QOSSetOutgoingDSCPValue,
+                    size_of::<u32>() as u32,
+                    &value as *const u32 as *const _,
+                    0,
+                    None,
+                )
+                .ok()?
+            };
+
+            Ok(())
+        }
+    }
+
+    impl Drop for Flow {
+        fn drop(&mut self) {
+            // The flows of the handle are removed with it.
+            if !self.handle.is_invalid() {
+                let _ = unsafe { QOSCloseHandle(self.handle) };
+            }
+        }
+    }
+}
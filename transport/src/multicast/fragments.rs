@@ -101,10 +101,12 @@ impl FragmentDecoder {
     pub fn decode(&mut self, chunk: Fragment) -> Option<(u64, Bytes)> {
         let mut result = None;
         if chunk.sequence as i128 != self.sequence {
+            // The packet is split off instead of copied, the buffer is reclaimed by the
+            // next write once the packet has been dropped.
             if !self.bytes.is_empty() && self.bytes.len() >= self.size {
                 result = Some((
                     self.sequence as u64,
-                    Bytes::copy_from_slice(&self.bytes[..self.size]),
+                    self.bytes.split_to(self.size).freeze(),
                 ));
             }
 
@@ -1,15 +1,18 @@
 mod dequeue;
+mod dscp;
 mod fragments;
 
 use std::{
-    io::Error,
+    io::{Error, ErrorKind},
     net::{IpAddr, Ipv4Addr, SocketAddr, UdpSocket},
+    sync::Arc,
 };
 
 use bytes::Bytes;
 use crossbeam::channel::{bounded, Receiver};
 use fragments::FragmentEncoder;
 use once_cell::sync::Lazy;
+use parking_lot::Mutex;
 use tokio::{runtime::Runtime, sync::mpsc::unbounded_channel};
 
 use self::{
@@ -17,6 +20,8 @@ use self::{
     fragments::{Fragment, FragmentDecoder},
 };
 
+use crate::{pacer::Pacer, MulticastOptions};
+
 static RUNTIME: Lazy<Runtime> =
     Lazy::new(|| Runtime::new().expect("failed to create tokio runtime, this is a bug"));
 
@@ -34,10 +39,14 @@ static RUNTIME: Lazy<Runtime> =
 /// is an unordered, unreliable protocol;
 ///
 /// This client is only used to receive multicast packets and does not send
-/// multicast packets.
+/// multicast packets, the messages sent back to the server are unicast.
 pub struct Socket {
     rx: Receiver<(u64, Bytes)>,
     close_signal: tokio::sync::mpsc::UnboundedSender<()>,
+    socket: Arc<tokio::net::UdpSocket>,
+    // The unicast address of the server, which is only known after the first
+    // packet has been received.
+    server: Arc<Mutex<Option<SocketAddr>>>,
 }
 
 unsafe impl Send for Socket {}
@@ -50,10 +59,14 @@ impl Socket {
     /// the specified multicast group.
     ///
     /// Note that only IPV4 is supported.
-    pub fn new(multicast: Ipv4Addr, bind: SocketAddr) -> Result<Self, Error> {
+    pub fn new(
+        multicast: Ipv4Addr,
+        bind: SocketAddr,
+        options: MulticastOptions,
+    ) -> Result<Self, Error> {
         assert!(bind.is_ipv4());
 
-        RUNTIME.block_on(Self::create(multicast, bind))
+        RUNTIME.block_on(Self::create(multicast, bind, options))
     }
 
     /// Reads packets sent from the multicast server.
@@ -66,24 +79,52 @@ impl Socket {
         self.rx.recv().ok()
     }
 
+    /// Sends a message back to the server, the message is a single unicast
+    /// datagram, so it is not reordered or retransmitted.
+    pub fn send(&self, bytes: &[u8]) -> Result<(), Error> {
+        let server = self.server.lock().ok_or_else(|| {
+            Error::new(
+                ErrorKind::NotConnected,
+                "no packet has been received from the server",
+            )
+        })?;
+
+        self.socket.try_send_to(bytes, server)?;
+        Ok(())
+    }
+
     pub fn close(&self) {
         let _ = self.close_signal.send(());
     }
 
-    async fn create(multicast: Ipv4Addr, bind: SocketAddr) -> Result<Self, Error> {
+    async fn create(
+        multicast: Ipv4Addr,
+        bind: SocketAddr,
+        options: MulticastOptions,
+    ) -> Result<Self, Error> {
         let socket = socket2::Socket::from(UdpSocket::bind(bind)?);
         socket.set_recv_buffer_size(4 * 1024 * 1024)?;
         socket.set_nonblocking(true)?;
 
-        let socket = tokio::net::UdpSocket::from_std(socket.into())?;
         if let IpAddr::V4(bind) = bind.ip() {
-            socket.join_multicast_v4(multicast, bind)?;
+            let interface = options.interface.unwrap_or(bind);
+            if let Some(source) = options.source {
+                socket.join_ssm_v4(&source, &multicast, &interface)?;
+            } else {
+                socket.join_multicast_v4(&multicast, &interface)?;
+            }
+
             socket.set_broadcast(true)?;
         }
 
+        let socket = Arc::new(tokio::net::UdpSocket::from_std(socket.into())?);
+
         let (close_signal, mut closed) = unbounded_channel();
         let (tx, rx) = bounded(5);
 
+        let server: Arc<Mutex<Option<SocketAddr>>> = Default::default();
+        let server_ = server.clone();
+        let socket_ = socket.clone();
         tokio::spawn(async move {
             let mut buf = vec![0u8; 2048];
             let mut queue = Dequeue::new(50);
@@ -91,12 +132,13 @@ impl Socket {
 
             'a: loop {
                 tokio::select! {
-                    Ok(size) = socket.recv(&mut buf[..]) => {
+                    Ok((size, addr)) = socket_.recv_from(&mut buf[..]) => {
                         if size == 0 {
                             break;
                         }
 
                         if let Ok(packet) = Fragment::try_from(&buf[..size]) {
+                            server_.lock().replace(addr);
                             queue.push(packet);
 
                             while let Some(chunk) = queue.pop() {
@@ -116,7 +158,12 @@ impl Socket {
             }
         });
 
-        Ok(Self { close_signal, rx })
+        Ok(Self {
+            close_signal,
+            socket,
+            server,
+            rx,
+        })
     }
 }
 
@@ -139,6 +186,7 @@ pub struct Server {
     target: SocketAddr,
     socket: UdpSocket,
     encoder: FragmentEncoder,
+    marker: Option<dscp::Marker>,
 }
 
 impl Server {
@@ -151,33 +199,72 @@ impl Server {
     ///
     /// MTU is used to specify the network unit size, this is used to limit the
     /// maximum size of packets sent.
-    pub fn new(multicast: Ipv4Addr, bind: SocketAddr, mtu: usize) -> Result<Self, Error> {
+    pub fn new(
+        multicast: Ipv4Addr,
+        bind: SocketAddr,
+        mtu: usize,
+        options: MulticastOptions,
+    ) -> Result<Self, Error> {
         assert!(bind.is_ipv4());
 
-        let socket = UdpSocket::bind(SocketAddr::new(bind.ip(), 0))?;
+        let socket = socket2::Socket::from(UdpSocket::bind(SocketAddr::new(bind.ip(), 0))?);
         if let IpAddr::V4(bind) = bind.ip() {
-            socket.join_multicast_v4(&multicast, &bind)?;
+            socket.join_multicast_v4(&multicast, &options.interface.unwrap_or(bind))?;
             socket.set_multicast_loop_v4(false)?;
+            socket.set_multicast_ttl_v4(options.ttl)?;
+
+            if let Some(interface) = options.interface {
+                socket.set_multicast_if_v4(&interface)?;
+            }
         }
 
         Ok(Self {
             target: SocketAddr::new(IpAddr::V4(multicast), bind.port()),
             encoder: FragmentEncoder::new(mtu),
-            socket,
+            socket: socket.into(),
+            marker: None,
         })
     }
 
+    /// Creates a new handle to the socket of the server, the receivers send
+    /// their messages to the unicast address of this socket, so the messages
+    /// can be read from the handle.
+    pub fn try_clone_socket(&self) -> Result<UdpSocket, Error> {
+        self.socket.try_clone()
+    }
+
+    /// Marks the packets that are sent after this with the DSCP value, see
+    /// `DscpOptions`.
+    pub fn set_dscp(&mut self, value: u8) {
+        self.marker
+            .get_or_insert_with(|| dscp::Marker::new(&self.socket, self.target))
+            .mark(&self.socket, value);
+    }
+
     /// Sends data on the socket to the remote address to which it is connected.
     ///
     /// Sends the packet to all members of the multicast group.
     ///
     /// Note that there may be packet loss.
     pub fn send(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        self.send_paced(bytes, None)
+    }
+
+    // Same as `send`, but each datagram waits for the pacer.
+    pub(crate) fn send_paced(
+        &mut self,
+        bytes: &[u8],
+        mut pacer: Option<&mut Pacer>,
+    ) -> Result<(), Error> {
         if bytes.is_empty() {
             return Ok(());
         }
 
         for chunk in self.encoder.encode(bytes) {
+            if let Some(pacer) = pacer.as_mut() {
+                pacer.wait(chunk.len());
+            }
+
             self.socket.send_to(chunk, self.target)?;
         }
 
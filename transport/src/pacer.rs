@@ -0,0 +1,122 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{BufferFlag, StreamKind};
+
+// The frame interval that is taken before the timestamps of two frames have
+// been seen, and the range of the intervals that are taken from the timestamps,
+// the timestamps of the frames around a pause or a restart are further apart.
+const DEFAULT_INTERVAL: Duration = Duration::from_micros(33_333);
+const MIN_INTERVAL: Duration = Duration::from_millis(1);
+const MAX_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Options of the packet pacing of the sender.
+///
+/// The packets of a large video frame, such as a key frame, are otherwise
+/// sent as one burst, which overflows the queues of the Wi-Fi links and the
+/// switches and is lost as a whole. With the pacing, a share of each frame
+/// is sent at once and the rest of the frame is spread over the frame
+/// interval, which is taken from the timestamps of the frames. The audio and
+/// the control messages are not paced, and a frame is sent at once when the
+/// packets behind it are piling up.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PacketPacingOptions {
+    /// The share of a frame that may be sent at once, from 0 to 1, `1.0`
+    /// sends the frames as bursts.
+    pub burst_ratio: f64,
+}
+
+impl Default for PacketPacingOptions {
+    fn default() -> Self {
+        Self { burst_ratio: 0.1 }
+    }
+}
+
+// A token bucket that is refilled at the rate that sends a frame in the frame
+// interval, the size of the bucket is the burst of the frame.
+pub(crate) struct Pacer {
+    options: PacketPacingOptions,
+    interval: Duration,
+    timestamp: Option<u64>,
+    paced: bool,
+    rate: f64,
+    tokens: f64,
+    capacity: f64,
+    updated: Instant,
+}
+
+impl Pacer {
+    pub(crate) fn new(options: PacketPacingOptions) -> Self {
+        Self {
+            interval: DEFAULT_INTERVAL,
+            updated: Instant::now(),
+            timestamp: None,
+            paced: false,
+            capacity: 0.0,
+            tokens: 0.0,
+            rate: 0.0,
+            options,
+        }
+    }
+
+    // Starts a payload of the stream, the chunks of the payload are then passed
+    // to `wait`. The payload is not paced if the packets behind it are waiting.
+    pub(crate) fn begin(
+        &mut self,
+        kind: StreamKind,
+        flags: i32,
+        timestamp: u64,
+        size: usize,
+        backlog: bool,
+    ) {
+        self.paced = kind == StreamKind::Video
+            && flags & BufferFlag::CONTROL == 0
+            && flags & BufferFlag::MASK != BufferFlag::Config as i32;
+
+        if !self.paced {
+            return;
+        }
+
+        if let Some(previous) = self.timestamp.replace(timestamp) {
+            let delta = Duration::from_micros(timestamp.saturating_sub(previous));
+            if delta >= MIN_INTERVAL && delta <= MAX_INTERVAL {
+                self.interval = self.interval.mul_f64(0.9) + delta.mul_f64(0.1);
+            }
+        }
+
+        self.paced = !backlog && self.options.burst_ratio < 1.0;
+        self.rate = size as f64 / self.interval.as_secs_f64();
+        self.capacity = size as f64 * self.options.burst_ratio.max(0.0);
+        self.tokens = self.capacity;
+        self.updated = Instant::now();
+    }
+
+    // Waits until the chunk of the payload can be sent.
+    pub(crate) fn wait(&mut self, size: usize) {
+        if !self.paced || self.rate <= 0.0 {
+            return;
+        }
+
+        let size = size as f64;
+        let now = Instant::now();
+        self.tokens = (self.tokens + now.duration_since(self.updated).as_secs_f64() * self.rate)
+            .min(self.capacity.max(size));
+        self.updated = now;
+
+        // The time overslept is credited to the next chunks.
+        if self.tokens < size {
+            let delay = Duration::from_secs_f64((size - self.tokens) / self.rate);
+            thread::sleep(delay);
+
+            self.tokens = size;
+            self.updated = now + delay;
+        }
+
+        self.tokens -= size;
+    }
+}
@@ -1,13 +1,21 @@
 use crate::adapter::StreamKind;
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use xxhash_rust::xxh3::xxh3_64;
+use parking_lot::Mutex;
+use thiserror::Error;
+use xxhash_rust::xxh3::{xxh3_64, Xxh3};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct PacketInfo {
     pub kind: StreamKind,
     pub flags: i32,
     pub timestamp: u64,
+    /// The id of the stream that the package belongs to, see
+    /// `Package::stream_id`.
+    pub stream: u32,
+    /// The group of the forward error correction, zero if the package is not
+    /// protected.
+    pub fec_group: u16,
 }
 
 /// Creates a BytesMut and copies from src to a buffer. The created buffer
@@ -27,13 +35,95 @@ pub fn with_capacity(size: usize) -> BytesMut {
     BytesMut::zeroed(size + Package::HEAD_SIZE)
 }
 
+/// Allocates the packets from a shared arena.
+///
+/// The packets are split off from the front of the arena, when all packets
+/// split off earlier have been sent and dropped, the memory of the arena is
+/// reclaimed instead of allocated again, so a stream whose packets are sent in
+/// time does not allocate for each packet.
+pub struct PacketPool {
+    arena: Mutex<BytesMut>,
+    size: usize,
+}
+
+impl Default for PacketPool {
+    fn default() -> Self {
+        Self::new(4 * 1024 * 1024)
+    }
+}
+
+impl PacketPool {
+    /// The size is the capacity that the arena grows by, it should be a few
+    /// times larger than the packets.
+    pub fn new(size: usize) -> Self {
+        Self {
+            arena: Mutex::new(BytesMut::with_capacity(size)),
+            size,
+        }
+    }
+
+    /// Same as `copy_from_slice`, but the buffer is taken from the arena.
+    pub fn copy_from_slice(&self, src: &[u8]) -> BytesMut {
+        let mut arena = self.arena.lock();
+        self.reserve(&mut arena, src.len() + Package::HEAD_SIZE);
+
+        arena.put_bytes(0, Package::HEAD_SIZE);
+        arena.put(src);
+        arena.split()
+    }
+
+    /// Same as `with_capacity`, but the buffer is taken from the arena.
+    pub fn with_capacity(&self, size: usize) -> BytesMut {
+        let mut arena = self.arena.lock();
+        self.reserve(&mut arena, size + Package::HEAD_SIZE);
+
+        arena.put_bytes(0, size + Package::HEAD_SIZE);
+        arena.split()
+    }
+
+    fn reserve(&self, arena: &mut BytesMut, size: usize) {
+        if arena.capacity() < size {
+            arena.reserve(size.max(self.size));
+        }
+    }
+}
+
 /// Because of the need to transmit both audio and video data in srt, it is
 /// necessary to identify the type of packet, this encoder is used to packetize
 /// specific types of data for transmission over the network.
+///
+/// The header of a package, all integers are big endian:
+///
+/// ```text
+/// | offset | size | field                                              |
+/// |--------|------|----------------------------------------------------|
+/// | 0      | 2    | magic, `HY`                                        |
+/// | 2      | 1    | version of the format, see `Package::VERSION`      |
+/// | 3      | 1    | track, 0 is video and 1 is audio                   |
+/// | 4      | 1    | flags of the buffer                                |
+/// | 5      | 1    | reserved, must be zero                             |
+/// | 6      | 2    | FEC group, zero if the package is not protected    |
+/// | 8      | 4    | stream id, see `Package::stream_id`                |
+/// | 12     | 4    | size of the package, including the header          |
+/// | 16     | 8    | presentation timestamp, in microseconds            |
+/// | 24     | 8    | xxh3 checksum of the header before it and the body |
+/// ```
+///
+/// A package whose version is different is rejected, the version is also
+/// carried in the stream id of the handshake, so the peers of different
+/// versions do not connect at all.
 pub struct Package;
 
 impl Package {
-    const HEAD_SIZE: usize = 26;
+    pub const HEAD_SIZE: usize = 32;
+    pub const MAGIC: u16 = 0x4859;
+    pub const VERSION: u8 = 1;
+
+    /// The id of a stream in the header of the packages, it is derived from the
+    /// id of the sender, so the receivers drop the packages of other streams.
+    pub fn stream_id(id: &str) -> u32 {
+        xxh3_64(id.as_bytes()) as u32
+    }
 
     /// The result of the encoding may be null, this is because an empty packet
     /// may be passed in from outside.
@@ -44,44 +134,190 @@ impl Package {
             bytes.set_len(0);
         }
 
-        bytes.put_u64(0);
-        bytes.put_u64(size as u64);
+        bytes.put_u16(Self::MAGIC);
+        bytes.put_u8(Self::VERSION);
         bytes.put_u8(info.kind as u8);
         bytes.put_u8(info.flags as u8);
+        bytes.put_u8(0);
+        bytes.put_u16(info.fec_group);
+        bytes.put_u32(info.stream);
+        bytes.put_u32(size as u32);
         bytes.put_u64(info.timestamp);
 
         unsafe {
             bytes.set_len(size);
         }
 
-        let hash = xxh3_64(&bytes[8..]);
-        bytes[0..8].copy_from_slice(&hash.to_be_bytes());
+        let hash = checksum(&bytes);
+        bytes[24..32].copy_from_slice(&hash.to_be_bytes());
         bytes.freeze()
     }
 }
 
+fn checksum(bytes: &[u8]) -> u64 {
+    let mut hasher = Xxh3::new();
+    hasher.update(&bytes[..24]);
+    hasher.update(&bytes[Package::HEAD_SIZE..]);
+    hasher.digest()
+}
+
+/// Why a package is rejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum UnPackageError {
+    #[error("the package is shorter than the header")]
+    TooShort,
+    #[error("the magic of the package is invalid")]
+    InvalidMagic,
+    #[error("the version of the package is not supported, version={0}")]
+    UnsupportedVersion(u8),
+    #[error("the reserved field of the package is not zero")]
+    InvalidReserved,
+    #[error("the track of the package is unknown, track={0}")]
+    UnknownTrack(u8),
+    #[error("the size of the package does not match the header")]
+    SizeMismatch,
+    #[error("the checksum of the package does not match")]
+    ChecksumMismatch,
+}
+
 /// Decode the packets received from the network and separate out the different
 /// types of data.
 pub struct UnPackage;
 
 impl UnPackage {
-    pub fn unpack(mut bytes: Bytes) -> Option<(PacketInfo, Bytes)> {
-        let count = bytes.len();
-        if bytes.get_u64() == xxh3_64(&bytes) {
-            if bytes.get_u64() as usize == count {
-                Some((
-                    PacketInfo {
-                        kind: StreamKind::try_from(bytes.get_u8()).ok()?,
-                        flags: bytes.get_u8() as i32,
-                        timestamp: bytes.get_u64(),
-                    },
-                    bytes,
-                ))
-            } else {
-                None
-            }
-        } else {
-            None
+    /// Validate the header of a package and split it into the information and
+    /// the body, every field of the header is checked.
+    pub fn unpack(mut bytes: Bytes) -> Result<(PacketInfo, Bytes), UnPackageError> {
+        if bytes.len() < Package::HEAD_SIZE {
+            return Err(UnPackageError::TooShort);
+        }
+
+        if u16::from_be_bytes([bytes[0], bytes[1]]) != Package::MAGIC {
+            return Err(UnPackageError::InvalidMagic);
+        }
+
+        if bytes[2] != Package::VERSION {
+            return Err(UnPackageError::UnsupportedVersion(bytes[2]));
+        }
+
+        if bytes[5] != 0 {
+            return Err(UnPackageError::InvalidReserved);
         }
+
+        let kind =
+            StreamKind::try_from(bytes[3]).map_err(|_| UnPackageError::UnknownTrack(bytes[3]))?;
+        if u32::from_be_bytes(bytes[12..16].try_into().unwrap()) as usize != bytes.len() {
+            return Err(UnPackageError::SizeMismatch);
+        }
+
+        if u64::from_be_bytes(bytes[24..32].try_into().unwrap()) != checksum(&bytes) {
+            return Err(UnPackageError::ChecksumMismatch);
+        }
+
+        bytes.advance(4);
+
+        let flags = bytes.get_u8() as i32;
+        bytes.advance(1);
+
+        let fec_group = bytes.get_u16();
+        let stream = bytes.get_u32();
+        bytes.advance(4);
+
+        let timestamp = bytes.get_u64();
+        bytes.advance(8);
+
+        Ok((
+            PacketInfo {
+                kind,
+                flags,
+                timestamp,
+                stream,
+                fec_group,
+            },
+            bytes,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package() -> Bytes {
+        Package::pack(
+            PacketInfo {
+                kind: StreamKind::Video,
+                flags: 1,
+                timestamp: 1000,
+                stream: Package::stream_id("test"),
+                fec_group: 2,
+            },
+            copy_from_slice(b"hello"),
+        )
+    }
+
+    // Changes a byte of the header and signs the package again, so only the
+    // changed field is wrong.
+    fn modify(index: usize, value: u8) -> Bytes {
+        let mut bytes = BytesMut::from(&package()[..]);
+        bytes[index] = value;
+
+        let hash = checksum(&bytes);
+        bytes[24..32].copy_from_slice(&hash.to_be_bytes());
+        bytes.freeze()
+    }
+
+    #[test]
+    fn round_trip() {
+        let (info, body) = UnPackage::unpack(package()).unwrap();
+
+        assert_eq!(
+            info,
+            PacketInfo {
+                kind: StreamKind::Video,
+                flags: 1,
+                timestamp: 1000,
+                stream: Package::stream_id("test"),
+                fec_group: 2,
+            }
+        );
+
+        assert_eq!(&body[..], b"hello");
+    }
+
+    #[test]
+    fn bad_magic() {
+        assert_eq!(
+            UnPackage::unpack(modify(0, 0)),
+            Err(UnPackageError::InvalidMagic)
+        );
+    }
+
+    #[test]
+    fn unknown_version() {
+        assert_eq!(
+            UnPackage::unpack(modify(2, Package::VERSION + 1)),
+            Err(UnPackageError::UnsupportedVersion(Package::VERSION + 1))
+        );
+    }
+
+    #[test]
+    fn short_buffer() {
+        assert_eq!(
+            UnPackage::unpack(package().slice(..Package::HEAD_SIZE - 1)),
+            Err(UnPackageError::TooShort)
+        );
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let mut bytes = BytesMut::from(&package()[..]);
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        assert_eq!(
+            UnPackage::unpack(bytes.freeze()),
+            Err(UnPackageError::ChecksumMismatch)
+        );
     }
 }
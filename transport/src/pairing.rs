@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Options of the pairing of the direct sender, only the direct mode supports
+/// it.
+///
+/// The sender generates a short code of digits that the application shows to
+/// the presenter, and a receiver has to submit the code when it connects,
+/// the receivers without the code are rejected in the handshake. The code is
+/// carried in the handshake as it is, it keeps the receivers that are not
+/// invited out, it does not encrypt the stream.
+///
+/// A receiver that submits a wrong code is locked out for a while, and the
+/// time doubles with every wrong code of the same address, the code of the
+/// locked out receivers is not checked at all. A new code is also generated
+/// after a number of wrong codes from all the receivers. This slows down the
+/// guessing from one address, it does not hold against an attacker with many
+/// addresses. The receivers that have connected stay connected.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PairingOptions {
+    /// The number of digits of the code, between 4 and 12, 6 by default.
+    pub length: usize,
+    /// The number of wrong codes that the sender takes before it generates a
+    /// new code, 5 by default.
+    pub attempts: u32,
+}
+
+impl Default for PairingOptions {
+    fn default() -> Self {
+        Self {
+            length: 6,
+            attempts: 5,
+        }
+    }
+}
+
+impl PairingOptions {
+    fn generate(&self) -> String {
+        let length = self.length.clamp(4, 12);
+
+        // The uuid is taken from the random source of the system, 12 digits are
+        // well within its 122 random bits.
+        let value = Uuid::new_v4().as_u128() % 10u128.pow(length as u32);
+        format!("{:0width$}", value, width = length)
+    }
+}
+
+// The lockout of the first wrong code of an address, and the longest lockout.
+const LOCKOUT: Duration = Duration::from_secs(1);
+const LOCKOUT_MAX: Duration = Duration::from_secs(60 * 60);
+
+// The wrong codes of an address are forgotten when the address has not
+// submitted a wrong code for this long after its lockout.
+const LOCKOUT_FORGET: Duration = Duration::from_secs(60 * 60);
+
+// The wrong codes of an address and the time until which it is locked out.
+struct Lockout {
+    failures: u32,
+    until: Instant,
+}
+
+// Compares the codes without returning at the first different digit, so that
+// the time of the comparison does not tell how much of the code is right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+pub(crate) type PairingHandler = Arc<RwLock<Option<Box<dyn Fn(&str) + Send + Sync>>>>;
+
+// The code of the sender, the number of wrong codes since it was generated and
+// the lockouts of the receiver addresses.
+pub(crate) struct Pairing {
+    options: PairingOptions,
+    code: String,
+    failures: u32,
+    lockouts: HashMap<Option<IpAddr>, Lockout>,
+}
+
+impl Pairing {
+    pub(crate) fn new(options: PairingOptions) -> Self {
+        Self {
+            code: options.generate(),
+            lockouts: HashMap::new(),
+            failures: 0,
+            options,
+        }
+    }
+
+    pub(crate) fn code(&self) -> &str {
+        &self.code
+    }
+
+    // Checks the code that a receiver has submitted, returns the new code if the
+    // code has been generated again. The address is `None` if the listener did
+    // not know it, these receivers share one lockout.
+    pub(crate) fn verify(
+        &mut self,
+        peer: Option<IpAddr>,
+        code: &str,
+    ) -> Result<(), Option<String>> {
+        let now = Instant::now();
+        if let Some(lockout) = self.lockouts.get(&peer) {
+            if now < lockout.until {
+                log::warn!("pairing reject a locked out receiver, peer={:?}", peer);
+
+                return Err(None);
+            }
+        }
+
+        if constant_time_eq(code.as_bytes(), self.code.as_bytes()) {
+            self.lockouts.remove(&peer);
+
+            return Ok(());
+        }
+
+        self.lockouts
+            .retain(|_, it| now.saturating_duration_since(it.until) < LOCKOUT_FORGET);
+
+        let lockout = self.lockouts.entry(peer).or_insert(Lockout {
+            failures: 0,
+            until: now,
+        });
+
+        lockout.until = now
+            + LOCKOUT
+                .saturating_mul(1 << lockout.failures.min(16))
+                .min(LOCKOUT_MAX);
+        lockout.failures += 1;
+
+        self.failures += 1;
+        if self.failures < self.options.attempts {
+            return Err(None);
+        }
+
+        self.code = self.options.generate();
+        self.failures = 0;
+
+        Err(Some(self.code.clone()))
+    }
+}
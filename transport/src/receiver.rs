@@ -1,30 +1,98 @@
 use std::{
-    io::Error,
+    io::{Error, ErrorKind},
     net::{IpAddr, SocketAddr},
-    sync::Arc,
+    sync::{atomic::AtomicBool, Arc},
     thread,
+    time::{Duration, Instant},
 };
 
+use bytes::Bytes;
+use hylarana_common::{
+    atomic::EasyAtomic,
+    runtime::{self, ThreadKind},
+};
+use parking_lot::RwLock;
+
 use crate::{
-    adapter::StreamReceiverAdapterAbstract, MulticastSocket, StreamInfo, StreamInfoKind,
-    StreamMultiReceiverAdapter, StreamReceiverAdapter, TransmissionFragmentDecoder,
-    TransmissionOptions, TransmissionSocket, TransportOptions, TransportStrategy, UnPackage,
+    adapter::StreamReceiverAdapterAbstract,
+    dump::{DumpSide, PacketDumper},
+    keepalive, loopback, mtu, shm, srt_latency, BandwidthEstimate, MulticastOptions,
+    MulticastSocket, Package, StreamInfo, StreamInfoKind, StreamMultiReceiverAdapter,
+    StreamReceiverAdapter, StreamTracks, TransmissionFragmentDecoder, TransmissionOptions,
+    TransmissionSocket, TransportOptions, TransportStrategy, UnPackage,
 };
 
+// How often a receiver looks for the sender again while it is waiting for the
+// sender to come back.
+const RESUME_INTERVAL: Duration = Duration::from_millis(500);
+
+type ReattachHandler = Arc<RwLock<Option<Box<dyn Fn() + Send + Sync>>>>;
+
+// The state of a receiver that waits for a sender with the same id after the
+// stream of the sender has ended, see `TransportOptions::resume`.
+#[derive(Clone)]
+pub(crate) struct Resume {
+    timeout: Option<Duration>,
+    closed: Arc<AtomicBool>,
+    handler: ReattachHandler,
+}
+
+impl Resume {
+    // Looks for the sender until it is found or the time is up, returns `None`
+    // right away if the receiver does not wait or has been closed.
+    pub(crate) fn wait<F, R>(&self, mut find: F) -> Option<R>
+    where
+        F: FnMut() -> Option<R>,
+    {
+        let deadline = Instant::now() + self.timeout?;
+        while !self.closed.get() && Instant::now() < deadline {
+            if let Some(it) = find() {
+                return Some(it);
+            }
+
+            thread::sleep(RESUME_INTERVAL);
+        }
+
+        None
+    }
+
+    pub(crate) fn is_closed(&self) -> bool {
+        self.closed.get()
+    }
+
+    pub(crate) fn reattached(&self) {
+        if let Some(handler) = self.handler.read().as_ref() {
+            handler();
+        }
+    }
+}
+
 enum Socket {
-    MulticastSocket(Arc<MulticastSocket>),
-    TransmissionSocket(Arc<TransmissionSocket>),
+    Multicast(Arc<MulticastSocket>),
+    // The socket is replaced when the receiver reattaches to the sender.
+    Transmission(Arc<RwLock<Arc<TransmissionSocket>>>),
+    Loopback(loopback::Socket),
+    // The shared memory transport has no back channel.
+    SharedMemory,
 }
 
 pub struct Receiver<T: StreamReceiverAdapterAbstract> {
     socket: Option<Socket>,
     adapter: Arc<T>,
+    resume: Resume,
+    max_message_size: usize,
 }
 
-impl<T: Default + StreamReceiverAdapterAbstract> Default for Receiver<T> {
-    fn default() -> Self {
+impl<T: Default + StreamReceiverAdapterAbstract> Receiver<T> {
+    fn new(resume: Option<Duration>) -> Self {
         Self {
             adapter: Arc::new(T::default()),
+            resume: Resume {
+                timeout: resume,
+                closed: Default::default(),
+                handler: Default::default(),
+            },
+            max_message_size: 0,
             socket: None,
         }
     }
@@ -35,7 +103,53 @@ impl<T: StreamReceiverAdapterAbstract> Receiver<T> {
         self.adapter.clone()
     }
 
+    /// Sends a message back to the sender, each message is sent as a single
+    /// packet, so the message cannot be larger than the packet size.
+    ///
+    /// In multicast mode the message is a unicast datagram to the sender,
+    /// which may be lost, and it can only be sent after the first packet of
+    /// the sender has been received.
+    ///
+    /// The messages that start with `0xFF` are reserved for the transport.
+    pub fn send_message(&self, message: &[u8]) -> Result<(), Error> {
+        if message.len() > self.max_message_size {
+            return Err(Error::new(ErrorKind::InvalidInput, "message is too large"));
+        }
+
+        match self.socket.as_ref() {
+            Some(Socket::Transmission(socket)) => socket.read().send(message),
+            Some(Socket::Multicast(socket)) => socket.send(message),
+            Some(Socket::Loopback(socket)) => socket.send(message),
+            Some(Socket::SharedMemory) => Err(Error::new(
+                ErrorKind::Unsupported,
+                "shared memory transport has no back channel",
+            )),
+            None => Err(Error::new(ErrorKind::NotConnected, "receiver is closed")),
+        }
+    }
+
+    /// The estimate of the link to the sender, `None` in the multicast and
+    /// loopback modes.
+    pub fn estimated_bandwidth(&self) -> Option<BandwidthEstimate> {
+        match self.socket.as_ref() {
+            Some(Socket::Transmission(socket)) => socket.read().estimate_bandwidth().ok(),
+            _ => None,
+        }
+    }
+
+    /// Sets the handler that is called when the receiver has reattached to a
+    /// sender with the same ID, see `TransportOptions::resume`. The sender
+    /// does not know about the messages that were sent to the previous
+    /// sender. The handler is called on the transport threads.
+    pub fn on_reattached<F>(&self, handler: F)
+    where
+        F: Fn() + Send + Sync + 'static,
+    {
+        self.resume.handler.write().replace(Box::new(handler));
+    }
+
     pub fn close(&self) {
+        self.resume.closed.update(true);
         self.adapter.close();
     }
 }
@@ -46,18 +160,85 @@ impl<T: StreamReceiverAdapterAbstract> Drop for Receiver<T> {
 
         if let Some(socket) = self.socket.as_ref() {
             match socket {
-                Socket::MulticastSocket(socket) => socket.close(),
-                Socket::TransmissionSocket(socket) => socket.close(),
+                Socket::Multicast(socket) => socket.close(),
+                Socket::Transmission(socket) => socket.read().close(),
+                // The loopback and shared memory receivers exit when the adapter is
+                // released.
+                Socket::Loopback(_) | Socket::SharedMemory => (),
             }
         }
     }
 }
 
-fn create_multicast_receiver<T>(id: String, addr: SocketAddr) -> Result<Receiver<T>, Error>
+// Pass a packet that is received to the adapter, the packets are checked for
+// loss by the sequence number, and the packets of other streams are dropped if
+// the stream is given, as are the tracks that the receiver does not take.
+// Returns whether the adapter accepted the packet, and whether a loss has been
+// reported to the adapter.
+pub(crate) fn process_packet<T: StreamReceiverAdapterAbstract>(
+    adapter: &T,
+    stream: Option<u32>,
+    tracks: StreamTracks,
+    sequence: &mut u64,
+    seq: u64,
+    bytes: Bytes,
+) -> (bool, bool) {
+    // Check whether the sequence number is continuous, in order to check whether
+    // packet loss has occurred
+    let result = if seq == 0 || seq - 1 == *sequence {
+        match UnPackage::unpack(bytes) {
+            Ok((info, _)) if !tracks.contains(info.kind, info.flags) => (true, false),
+            Ok((info, package)) if stream.map(|it| it == info.stream).unwrap_or(true) => {
+                if adapter.send(package, info.kind, info.flags, info.timestamp) {
+                    (true, false)
+                } else {
+                    log::error!("adapter on buf failed.");
+
+                    (false, false)
+                }
+            }
+            Ok((info, _)) => {
+                log::warn!(
+                    "received a package of another stream, stream={}",
+                    info.stream
+                );
+
+                adapter.lose();
+
+                (true, true)
+            }
+            Err(e) => {
+                log::warn!("received an invalid package, err={}", e);
+
+                adapter.lose();
+
+                (true, true)
+            }
+        }
+    } else {
+        adapter.lose();
+
+        (true, true)
+    };
+
+    *sequence = seq;
+    result
+}
+
+fn create_multicast_receiver<T>(
+    id: String,
+    addr: SocketAddr,
+    mtu: usize,
+    options: MulticastOptions,
+    tracks: StreamTracks,
+) -> Result<Receiver<T>, Error>
 where
     T: Default + StreamReceiverAdapterAbstract + 'static,
 {
-    let mut receiver = Receiver::<T>::default();
+    // The packets of a sender with the same id are taken anyway, the receiver
+    // does not find out that the sender has ended.
+    let mut receiver = Receiver::<T>::new(None);
+    receiver.max_message_size = mtu;
 
     // Creating a multicast receiver
     let socket = Arc::new(MulticastSocket::new(
@@ -66,39 +247,47 @@ where
             IpAddr::V6(_) => unimplemented!("not supports ipv6 multicast"),
         },
         SocketAddr::new("0.0.0.0".parse().unwrap(), addr.port()),
+        options,
     )?);
 
-    log::info!("create multicast receiver, id={}, addr={}", id, addr);
-    receiver.socket = Some(Socket::MulticastSocket(socket.clone()));
+    log::info!(
+        "create multicast receiver, id={}, addr={}, options={:?}",
+        id,
+        addr,
+        options
+    );
+    receiver.socket = Some(Socket::Multicast(socket.clone()));
 
     let mut sequence = 0;
+    let stream = Package::stream_id(&id);
+    let dumper = PacketDumper::from_directory(&id, DumpSide::Receiver);
     let adapter_ = Arc::downgrade(&receiver.adapter);
-    thread::Builder::new()
-        .name("HylaranaStreamMulticastReceiverThread".to_string())
-        .spawn(move || {
+    runtime::spawn(
+        ThreadKind::Transport,
+        "StreamMulticastReceiver",
+        move || {
             while let Some((seq, bytes)) = socket.read() {
                 if bytes.is_empty() {
                     break;
                 }
 
+                if let Some(dumper) = dumper.as_ref() {
+                    dumper.received(seq, &bytes);
+                }
+
                 if let Some(adapter) = adapter_.upgrade() {
-                    // Check whether the sequence number is continuous, in
-                    // order to check whether packet loss has occurred
-                    if seq == 0 || seq - 1 == sequence {
-                        if let Some((info, package)) = UnPackage::unpack(bytes) {
-                            if !adapter.send(package, info.kind, info.flags, info.timestamp) {
-                                log::error!("adapter on buf failed.");
-
-                                break;
-                            }
-                        } else {
-                            adapter.lose();
-                        }
-                    } else {
-                        adapter.lose()
+                    if !process_packet(
+                        adapter.as_ref(),
+                        Some(stream),
+                        tracks,
+                        &mut sequence,
+                        seq,
+                        bytes,
+                    )
+                    .0
+                    {
+                        break;
                     }
-
-                    sequence = seq;
                 } else {
                     break;
                 }
@@ -109,42 +298,62 @@ where
             if let Some(adapter) = adapter_.upgrade() {
                 adapter.close();
             }
-        })?;
+        },
+    )?;
 
     Ok(receiver)
 }
 
-fn create_srt_receiver<T>(id: String, addr: SocketAddr, mtu: usize) -> Result<Receiver<T>, Error>
+fn create_srt_receiver<T>(
+    id: String,
+    addr: SocketAddr,
+    options: TransportOptions,
+    tracks: StreamTracks,
+    pairing_code: Option<String>,
+) -> Result<Receiver<T>, Error>
 where
     T: Default + StreamReceiverAdapterAbstract + 'static,
 {
-    let mut receiver = Receiver::<T>::default();
+    let mut receiver = Receiver::<T>::new(options.resume);
 
     // Create an srt configuration and carry stream information
     let mut opt = TransmissionOptions::default();
     opt.fc = 32;
-    opt.latency = 20;
-    opt.mtu = mtu as u32;
-    opt.stream_id = Some(
-        StreamInfo {
-            kind: StreamInfoKind::Subscriber,
-            id: id.clone(),
-        }
-        .to_string(),
-    );
+    opt.latency = srt_latency(options.latency);
+    opt.mtu = mtu::resolve(options.mtu, addr) as u32;
+    // The sender pings in every interval, a read that times out means that the
+    // sender is gone.
+    opt.read_timeout = options.keepalive.map(|it| it.timeout.as_millis() as u32);
+    // The sender or the relay server only sends the tracks of the receiver.
+    let mut info = StreamInfo::new(id.clone(), StreamInfoKind::Subscriber);
+    info.tracks = tracks;
+    info.pairing_code = pairing_code;
+    opt.stream_id = Some(info.to_string());
 
     // Create an srt connection to the server
-    let socket = Arc::new(TransmissionSocket::connect(addr, opt)?);
+    let mut socket = Arc::new(TransmissionSocket::connect(addr, opt.clone())?);
+    receiver.max_message_size = socket.max_pkt_size().unwrap_or(opt.max_pkt_size());
+
+    log::info!(
+        "receiver connect to srt server, id={}, addr={}, max_pkt_size={}",
+        id,
+        addr,
+        receiver.max_message_size
+    );
 
-    log::info!("receiver connect to srt server, id={}, addr={}", id, addr);
-    receiver.socket = Some(Socket::TransmissionSocket(socket.clone()));
+    let shared = Arc::new(RwLock::new(socket.clone()));
+    receiver.socket = Some(Socket::Transmission(shared.clone()));
 
-    let mut sequence = 0;
+    let stream = Package::stream_id(&id);
+    let dumper = PacketDumper::from_directory(&id, DumpSide::Receiver);
     let adapter_ = Arc::downgrade(&receiver.adapter);
-    thread::Builder::new()
-        .name("HylaranaStreamReceiverThread".to_string())
-        .spawn(move || {
-            let mut buf = [0u8; 2000];
+    let resume = receiver.resume.clone();
+    runtime::spawn(ThreadKind::Transport, "StreamReceiver", move || {
+        let mut buf = [0u8; 2000];
+
+        'a: loop {
+            // The packets of a new sender start over.
+            let mut sequence = 0;
             let mut decoder = TransmissionFragmentDecoder::new();
 
             loop {
@@ -157,33 +366,48 @@ where
                         // All the fragments received from SRT are split and need to be
                         // reassembled here
                         if let Some((seq, bytes)) = decoder.decode(&buf[..size]) {
+                            if let Some(dumper) = dumper.as_ref() {
+                                dumper.received(seq, &bytes);
+                            }
+
+                            // The pings are answered whether the receiver uses the
+                            // keepalives or not, the sender measures the round trip
+                            // time by them.
+                            if let Some(sent) = keepalive::ping(&bytes) {
+                                if let Err(e) = socket.send(&keepalive::pong(sent)) {
+                                    log::warn!("failed to answer keepalive, err={:?}", e);
+                                }
+                            }
+
                             if let Some(adapter) = adapter_.upgrade() {
-                                // Check whether the sequence number is continuous, in
-                                // order to
-                                // check whether packet loss has
-                                // occurred
-                                if seq == 0 || seq - 1 == sequence {
-                                    if let Some((info, package)) = UnPackage::unpack(bytes) {
-                                        if !adapter.send(
-                                            package,
-                                            info.kind,
-                                            info.flags,
-                                            info.timestamp,
-                                        ) {
-                                            log::error!("adapter on buf failed.");
-
-                                            break;
-                                        }
-                                    } else {
-                                        adapter.lose();
-                                    }
-                                } else {
-                                    adapter.lose()
+                                if !process_packet(
+                                    adapter.as_ref(),
+                                    Some(stream),
+                                    tracks,
+                                    &mut sequence,
+                                    seq,
+                                    bytes,
+                                )
+                                .0
+                                {
+                                    break 'a;
                                 }
 
-                                sequence = seq;
+                                // The sender has closed the stream, the connection is
+                                // ended right away instead of waiting for the peer idle
+                                // timeout of SRT.
+                                if adapter.is_remote_closed() {
+                                    log::info!(
+                                        "srt receiver got goodbye from sender, id={}, addr={}",
+                                        id,
+                                        addr
+                                    );
+
+                                    socket.close();
+                                    break;
+                                }
                             } else {
-                                break;
+                                break 'a;
                             }
                         }
                     }
@@ -195,12 +419,53 @@ where
                 }
             }
 
-            log::warn!("srt receiver is closed, id={}, addr={}", id, addr);
-
+            // The packets that are cut off are lost, the decoders wait for the next
+            // key frame of the new sender. The receiver that the sender has kicked
+            // does not come back.
             if let Some(adapter) = adapter_.upgrade() {
-                adapter.close();
+                if adapter.is_kicked() {
+                    log::info!("srt receiver is kicked by sender, id={}, addr={}", id, addr);
+
+                    break;
+                }
+
+                adapter.lose();
+            } else {
+                break;
             }
-        })?;
+
+            let Some(it) = resume.wait(|| {
+                log::info!("srt receiver wait for sender, id={}, addr={}", id, addr);
+
+                TransmissionSocket::connect(addr, opt.clone()).ok()
+            }) else {
+                break;
+            };
+
+            socket = Arc::new(it);
+            *shared.write() = socket.clone();
+
+            // The receiver may have been closed while connecting, the socket is
+            // closed when it is dropped.
+            if resume.is_closed() {
+                break;
+            }
+
+            log::info!(
+                "srt receiver reattached to sender, id={}, addr={}",
+                id,
+                addr
+            );
+
+            resume.reattached();
+        }
+
+        log::warn!("srt receiver is closed, id={}, addr={}", id, addr);
+
+        if let Some(adapter) = adapter_.upgrade() {
+            adapter.close();
+        }
+    })?;
 
     Ok(receiver)
 }
@@ -208,11 +473,53 @@ where
 fn create_receiver<T: Default + StreamReceiverAdapterAbstract + 'static>(
     id: String,
     options: TransportOptions,
+    tracks: StreamTracks,
+    pairing_code: Option<String>,
 ) -> Result<Receiver<T>, Error> {
+    // The fields of the stream info in the srt handshake are separated by
+    // commas.
+    if pairing_code
+        .as_ref()
+        .map(|it| it.is_empty() || it.contains(','))
+        .unwrap_or(false)
+    {
+        return Err(Error::new(ErrorKind::InvalidInput, "invalid pairing code"));
+    }
+
     match options.strategy {
-        TransportStrategy::Multicast(addr) => create_multicast_receiver(id, addr),
+        // The messages of a multicast receiver go to the sender, whose address is
+        // not known yet.
+        TransportStrategy::Multicast(addr) => create_multicast_receiver(
+            id,
+            addr,
+            mtu::clamp(options.mtu, mtu::DEFAULT_MTU),
+            options.multicast,
+            tracks,
+        ),
         TransportStrategy::Direct(addr) | TransportStrategy::Relay(addr) => {
-            create_srt_receiver(id, addr, options.mtu)
+            create_srt_receiver(id, addr, options, tracks, pairing_code)
+        }
+        TransportStrategy::Loopback(loopback) => {
+            let mut receiver = Receiver::<T>::new(options.resume);
+            receiver.max_message_size = mtu::clamp(options.mtu, mtu::MAX_MTU);
+            receiver.socket = Some(Socket::Loopback(loopback::create_receiver(
+                &id,
+                loopback,
+                receiver.max_message_size,
+                tracks,
+                receiver.resume.clone(),
+                &receiver.adapter,
+            )?));
+
+            Ok(receiver)
+        }
+        TransportStrategy::SharedMemory(_) => {
+            let mut receiver = Receiver::<T>::new(options.resume);
+            receiver.max_message_size = mtu::clamp(options.mtu, mtu::MAX_MTU);
+            receiver.socket = Some(Socket::SharedMemory);
+
+            shm::create_receiver(&id, tracks, receiver.resume.clone(), &receiver.adapter)?;
+            Ok(receiver)
         }
     }
 }
@@ -220,11 +527,18 @@ fn create_receiver<T: Default + StreamReceiverAdapterAbstract + 'static>(
 /// Create channel-separated receivers where audio and video channels are
 /// received independently, so that a channel can be easily processed separately
 /// from different threads.
+///
+/// The receiver only takes the given tracks, see `StreamTracks`. The pairing
+/// code is submitted to a direct sender that requires it, see
+/// `PairingOptions`, the connection fails with `ErrorKind::PermissionDenied`
+/// if the code is missing or wrong.
 pub fn create_split_receiver(
     id: String,
     options: TransportOptions,
+    tracks: StreamTracks,
+    pairing_code: Option<String>,
 ) -> Result<Receiver<StreamMultiReceiverAdapter>, Error> {
-    create_receiver::<StreamMultiReceiverAdapter>(id, options)
+    create_receiver::<StreamMultiReceiverAdapter>(id, options, tracks, pairing_code)
 }
 
 /// Creating a mixed channel is the opposite of separating channels, where the
@@ -233,6 +547,8 @@ pub fn create_split_receiver(
 pub fn create_mix_receiver(
     id: String,
     options: TransportOptions,
+    tracks: StreamTracks,
+    pairing_code: Option<String>,
 ) -> Result<Receiver<StreamReceiverAdapter>, Error> {
-    create_receiver::<StreamReceiverAdapter>(id, options)
+    create_receiver::<StreamReceiverAdapter>(id, options, tracks, pairing_code)
 }
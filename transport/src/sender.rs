@@ -1,30 +1,128 @@
 use std::{
     collections::HashMap,
-    io::Error,
-    net::{IpAddr, SocketAddr},
-    sync::Arc,
-    thread,
+    io::{Error, ErrorKind},
+    net::{IpAddr, SocketAddr, UdpSocket},
+    str::FromStr,
+    sync::{Arc, Weak},
+    time::{Duration, SystemTime},
 };
 
+use hylarana_common::runtime::{self, ThreadKind};
 use parking_lot::RwLock;
 use uuid::Uuid;
 
 use crate::{
-    adapter::StreamSenderAdapter, MulticastServer, Package, PacketInfo, StreamInfo, StreamInfoKind,
-    TransmissionFragmentEncoder, TransmissionOptions, TransmissionServer, TransmissionSocket,
-    TransportOptions, TransportStrategy,
+    adapter::{StreamControl, StreamKind, StreamSenderAdapter},
+    dump::{DumpSide, PacketDumper},
+    keepalive::{self, Heartbeat},
+    loopback, mtu,
+    pacer::Pacer,
+    package::copy_from_slice,
+    pairing::{Pairing, PairingHandler},
+    shm, srt_latency, BandwidthEstimate, BufferFlag, DscpOptions, KeepaliveOptions,
+    MulticastOptions, MulticastServer, Package, PacketInfo, PacketPacingOptions, PairingOptions,
+    StreamInfo, StreamInfoKind, StreamTracks, TransmissionFragmentEncoder, TransmissionOptions,
+    TransmissionServer, TransmissionSocket, TransportOptions, TransportStrategy,
 };
 
+// The sockets are closed right after the goodbye is sent, they keep sending it
+// in the background for this long, in seconds.
+const GOODBYE_LINGER: u32 = 1;
+
+pub(crate) type MessageHandler = Arc<RwLock<Option<Box<dyn Fn(&[u8]) + Send + Sync>>>>;
+
+/// A receiver that is attached to the sender, see `Sender::receivers`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReceiverInfo {
+    /// The id of the connection, it is unique within the sender, a receiver
+    /// that connects again has a new id.
+    pub id: u64,
+    pub addr: SocketAddr,
+    pub tracks: StreamTracks,
+    /// The time the receiver has connected.
+    pub joined: SystemTime,
+    /// The round trip time, measured by the keepalives if they are enabled,
+    /// see `KeepaliveOptions`.
+    pub rtt: Option<Duration>,
+}
+
+// A receiver of the direct sender, the tracks that it takes and the largest
+// payload of its connection.
+struct DirectSocket {
+    id: u64,
+    joined: SystemTime,
+    socket: Arc<TransmissionSocket>,
+    tracks: StreamTracks,
+    max_pkt_size: usize,
+    heartbeat: Arc<Heartbeat>,
+}
+
+impl DirectSocket {
+    fn estimate_bandwidth(&self) -> Option<BandwidthEstimate> {
+        estimate_bandwidth(&self.socket, &self.heartbeat)
+    }
+
+    fn info(&self, addr: SocketAddr) -> ReceiverInfo {
+        ReceiverInfo {
+            id: self.id,
+            tracks: self.tracks,
+            joined: self.joined,
+            rtt: self.heartbeat.rtt().or_else(|| self.socket.rtt().ok()),
+            addr,
+        }
+    }
+}
+
+type DirectSockets = RwLock<HashMap<SocketAddr, DirectSocket>>;
+
+// The SRT sockets that the stream is sent on, the estimate of the bandwidth
+// comes from them.
+#[derive(Default)]
+enum Links {
+    #[default]
+    None,
+    Relay(Weak<TransmissionSocket>, Arc<Heartbeat>),
+    Direct(Weak<DirectSockets>),
+}
+
+// The round trip time of the keepalives takes the place of the estimate of SRT
+// once it has been measured.
+fn estimate_bandwidth(
+    socket: &TransmissionSocket,
+    heartbeat: &Heartbeat,
+) -> Option<BandwidthEstimate> {
+    let mut estimate = socket.estimate_bandwidth().ok()?;
+    if let Some(rtt) = heartbeat.rtt() {
+        estimate.rtt = rtt;
+    }
+
+    Some(estimate)
+}
+
 pub struct Sender {
     id: String,
     adapter: Arc<StreamSenderAdapter>,
+    handler: MessageHandler,
+    links: Links,
+    pairing: Arc<RwLock<Option<Pairing>>>,
+    pairing_handler: PairingHandler,
 }
 
 impl Default for Sender {
     fn default() -> Self {
+        Self::new(Uuid::new_v4().to_string())
+    }
+}
+
+impl Sender {
+    fn new(id: String) -> Self {
         Self {
-            id: Uuid::new_v4().to_string(),
+            id,
             adapter: Arc::new(StreamSenderAdapter::default()),
+            handler: Default::default(),
+            links: Default::default(),
+            pairing: Default::default(),
+            pairing_handler: Default::default(),
         }
     }
 }
@@ -38,6 +136,149 @@ impl Sender {
         self.adapter.clone()
     }
 
+    /// Sets the handler for the messages that the receivers send back to the
+    /// sender, the handler is called on the transport threads.
+    ///
+    /// In multicast mode the messages are unicast datagrams, so they may be
+    /// lost.
+    pub fn on_message<F>(&self, handler: F) -> Result<(), Error>
+    where
+        F: Fn(&[u8]) + Send + Sync + 'static,
+    {
+        self.handler.write().replace(Box::new(handler));
+        Ok(())
+    }
+
+    /// The estimate of the link to the receivers, which is the slowest of the
+    /// receivers in direct mode. This is `None` in the multicast, loopback and
+    /// shared memory modes, and before a receiver has connected.
+    pub fn estimated_bandwidth(&self) -> Option<BandwidthEstimate> {
+        match &self.links {
+            Links::Relay(socket, heartbeat) => {
+                estimate_bandwidth(socket.upgrade()?.as_ref(), heartbeat)
+            }
+            Links::Direct(sockets) => sockets
+                .upgrade()?
+                .read()
+                .values()
+                .filter_map(DirectSocket::estimate_bandwidth)
+                .min_by_key(|it| it.bandwidth),
+            Links::None => None,
+        }
+    }
+
+    /// The receivers that are attached to the sender. Only the direct mode
+    /// knows its receivers, the list is empty in the other modes.
+    pub fn receivers(&self) -> Vec<ReceiverInfo> {
+        match &self.links {
+            Links::Direct(sockets) => sockets
+                .upgrade()
+                .map(|sockets| {
+                    sockets
+                        .read()
+                        .iter()
+                        .map(|(addr, it)| it.info(*addr))
+                        .collect()
+                })
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Disconnect a receiver, see `receivers`. The receiver is told that it
+    /// has been kicked, so it closes its stream instead of waiting to
+    /// reattach. Returns false if the receiver is not attached.
+    pub fn disconnect(&self, id: u64) -> bool {
+        let Links::Direct(sockets) = &self.links else {
+            return false;
+        };
+
+        let Some(sockets) = sockets.upgrade() else {
+            return false;
+        };
+
+        let Some((addr, it)) = ({
+            let mut sockets = sockets.write();
+            let addr = sockets
+                .iter()
+                .find(|(_, it)| it.id == id)
+                .map(|(addr, _)| *addr);
+
+            addr.and_then(|addr| sockets.remove(&addr).map(|it| (addr, it)))
+        }) else {
+            return false;
+        };
+
+        log::info!(
+            "srt direct server disconnect a socket, addr={}, id={}",
+            addr,
+            id
+        );
+
+        // The receivers take any packet with the first sequence, the kick does not
+        // need the sequence of the stream. The socket keeps sending it while it
+        // lingers.
+        let payload = Package::pack(
+            PacketInfo {
+                kind: StreamKind::Video,
+                flags: BufferFlag::CONTROL,
+                timestamp: 0,
+                stream: Package::stream_id(&self.id),
+                fec_group: 0,
+            },
+            copy_from_slice(&StreamControl::Kick.as_payload()),
+        );
+
+        for chunk in TransmissionFragmentEncoder::new(it.max_pkt_size).encode(&payload) {
+            if it.socket.send(chunk).is_err() {
+                break;
+            }
+        }
+
+        it.socket.close();
+        true
+    }
+
+    /// Require the receivers to submit a pairing code when they connect, see
+    /// `PairingOptions`, `None` lets all the receivers connect again. A new
+    /// code is passed to the handler of `on_pairing_code`. Only the direct
+    /// mode supports it.
+    pub fn set_pairing(&self, options: Option<PairingOptions>) -> Result<(), Error> {
+        if !matches!(self.links, Links::Direct(_)) {
+            return Err(Error::new(
+                ErrorKind::Unsupported,
+                "pairing is only supported in direct mode",
+            ));
+        }
+
+        log::info!("sender set pairing, options={:?}", options);
+
+        let pairing = options.map(Pairing::new);
+        let code = pairing.as_ref().map(|it| it.code().to_string());
+        *self.pairing.write() = pairing;
+
+        if let (Some(code), Some(handler)) = (code, self.pairing_handler.read().as_ref()) {
+            handler(&code);
+        }
+
+        Ok(())
+    }
+
+    /// The current pairing code, `None` if the pairing is not required.
+    pub fn pairing_code(&self) -> Option<String> {
+        self.pairing.read().as_ref().map(|it| it.code().to_string())
+    }
+
+    /// Sets the handler that is called with the pairing code whenever a new
+    /// code is generated, so that the application can show it. The handler is
+    /// called on the transport threads.
+    pub fn on_pairing_code<F>(&self, handler: F)
+    where
+        F: Fn(&str) + Send + Sync + 'static,
+    {
+        self.pairing_handler.write().replace(Box::new(handler));
+    }
+
     pub fn close(&self) {
         self.adapter.close();
     }
@@ -49,8 +290,92 @@ impl Drop for Sender {
     }
 }
 
-fn create_multicast_sender(addr: SocketAddr, mtu: usize) -> Result<Sender, Error> {
-    let sender = Sender::default();
+// Each message sent by the receiver is a single srt packet, the messages are
+// read on a separate thread and passed to the handler of the sender, except for
+// the answers to the keepalives.
+fn spawn_message_reader(
+    socket: Arc<TransmissionSocket>,
+    handler: MessageHandler,
+    heartbeat: Arc<Heartbeat>,
+    addr: SocketAddr,
+) -> Result<(), Error> {
+    runtime::spawn(ThreadKind::Transport, "StreamMessageReader", move || {
+        let mut buf = [0u8; 2000];
+
+        while let Ok(size) = socket.read(&mut buf) {
+            if size == 0 {
+                break;
+            }
+
+            if heartbeat.received(&buf[..size]) {
+                continue;
+            }
+
+            if let Some(handler) = handler.read().as_ref() {
+                handler(&buf[..size]);
+            }
+        }
+
+        log::info!("srt message reader is closed, addr={}", addr);
+    })?;
+
+    Ok(())
+}
+
+// The receivers of multicast send their messages to the unicast address of the
+// server socket. The socket is read with a timeout, so that the thread can exit
+// once the sender is released.
+fn spawn_multicast_message_reader(
+    socket: UdpSocket,
+    handler: MessageHandler,
+    adapter: Weak<StreamSenderAdapter>,
+) -> Result<(), Error> {
+    socket.set_read_timeout(Some(Duration::from_secs(1)))?;
+
+    runtime::spawn(
+        ThreadKind::Transport,
+        "StreamMulticastMessageReader",
+        move || {
+            let mut buf = [0u8; 2000];
+
+            while adapter.strong_count() > 0 {
+                match socket.recv_from(&mut buf) {
+                    Ok((size, _)) => {
+                        if size == 0 {
+                            continue;
+                        }
+
+                        if let Some(handler) = handler.read().as_ref() {
+                            handler(&buf[..size]);
+                        }
+                    }
+                    Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => {
+                        continue
+                    }
+                    Err(e) => {
+                        log::error!("failed to read multicast message, err={:?}", e);
+
+                        break;
+                    }
+                }
+            }
+
+            log::info!("multicast message reader is closed");
+        },
+    )?;
+
+    Ok(())
+}
+
+fn create_multicast_sender(
+    id: String,
+    addr: SocketAddr,
+    mtu: usize,
+    options: MulticastOptions,
+    dscp: Option<DscpOptions>,
+    pacing: Option<PacketPacingOptions>,
+) -> Result<Sender, Error> {
+    let sender = Sender::new(id);
 
     // Create a multicast sender, the port is automatically assigned an idle port by
     // the system
@@ -61,194 +386,423 @@ fn create_multicast_sender(addr: SocketAddr, mtu: usize) -> Result<Sender, Error
         },
         format!("0.0.0.0:{}", addr.port()).parse().unwrap(),
         mtu,
+        options,
     )?;
 
-    log::info!("create multicast sender, id={}, addr={}", sender.id, addr);
+    log::info!(
+        "create multicast sender, id={}, addr={}, options={:?}",
+        sender.id,
+        addr,
+        options
+    );
+
+    spawn_multicast_message_reader(
+        server.try_clone_socket()?,
+        sender.handler.clone(),
+        Arc::downgrade(&sender.adapter),
+    )?;
 
     let id = sender.id.to_string();
+    let stream = Package::stream_id(&id);
+    let dumper = PacketDumper::from_directory(&id, DumpSide::Sender);
     let adapter_ = Arc::downgrade(&sender.adapter);
-    thread::Builder::new()
-        .name("HylaranaStreamMulticastSenderThread".to_string())
-        .spawn(move || {
-            // If the adapter has been released, close the current thread
-            'a: while let Some(adapter) = adapter_.upgrade() {
-                if let Some((buf, kind, flags, timestamp)) = adapter.next() {
-                    if buf.is_empty() {
-                        continue;
-                    }
+    runtime::spawn(ThreadKind::Transport, "StreamMulticastSender", move || {
+        let mut pacer = pacing.map(Pacer::new);
+
+        // If the adapter has been released, close the current thread
+        'a: while let Some(adapter) = adapter_.upgrade() {
+            if let Some((buf, kind, flags, timestamp)) = adapter.next() {
+                if buf.is_empty() {
+                    continue;
+                }
 
-                    // Packaging audio and video information
-                    let payload = Package::pack(
-                        PacketInfo {
-                            kind,
-                            flags,
-                            timestamp,
-                        },
-                        buf,
-                    );
+                // Packaging audio and video information
+                let payload = Package::pack(
+                    PacketInfo {
+                        kind,
+                        flags,
+                        timestamp,
+                        stream,
+                        fec_group: 0,
+                    },
+                    buf,
+                );
+
+                if let Some(dumper) = dumper.as_ref() {
+                    dumper.sent(&payload);
+                }
 
-                    // Here we check whether the audio and video data are being
-                    // multicasted, so as to dynamically
-                    // switch the protocol stack.
-                    if let Err(e) = server.send(&payload) {
-                        log::error!("failed to send buf in multicast, err={:?}", e);
+                if let Some(dscp) = dscp {
+                    server.set_dscp(dscp.value(kind));
+                }
 
-                        break 'a;
-                    }
-                } else {
-                    break;
+                if let Some(pacing) = adapter.take_pacing() {
+                    pacer = pacing.map(Pacer::new);
+                }
+
+                if let Some(pacer) = pacer.as_mut() {
+                    pacer.begin(kind, flags, timestamp, payload.len(), adapter.pending() > 0);
                 }
-            }
 
-            log::info!("multicast sender is closed, id={}, addr={}", id, addr);
+                // Here we check whether the audio and video data are being
+                // multicasted, so as to dynamically
+                // switch the protocol stack.
+                if let Err(e) = server.send_paced(&payload, pacer.as_mut()) {
+                    log::error!("failed to send buf in multicast, err={:?}", e);
 
-            if let Some(adapter) = adapter_.upgrade() {
-                adapter.close();
+                    break 'a;
+                }
+            } else {
+                break;
             }
-        })?;
+        }
+
+        log::info!("multicast sender is closed, id={}, addr={}", id, addr);
+
+        if let Some(adapter) = adapter_.upgrade() {
+            adapter.close();
+        }
+    })?;
 
     Ok(sender)
 }
 
-fn create_relay_sender(addr: SocketAddr, mtu: usize) -> Result<Sender, Error> {
-    let sender = Sender::default();
+fn create_relay_sender(
+    id: String,
+    addr: SocketAddr,
+    mtu: usize,
+    dscp: Option<DscpOptions>,
+    pacing: Option<PacketPacingOptions>,
+    keepalive: Option<KeepaliveOptions>,
+    latency: Option<Duration>,
+) -> Result<Sender, Error> {
+    let mut sender = Sender::new(id);
 
     // Create an srt configuration and carry stream information
     let mut opt = TransmissionOptions::default();
     opt.fc = 32;
-    opt.latency = 20;
+    opt.latency = srt_latency(latency);
+    opt.peer_latency = opt.latency;
     opt.mtu = mtu as u32;
-    opt.stream_id = Some(
-        StreamInfo {
-            kind: StreamInfoKind::Publisher,
-            id: sender.id.clone(),
-        }
-        .to_string(),
-    );
+    opt.dscp = dscp.map(|it| it.video);
+    opt.linger = GOODBYE_LINGER;
+    opt.stream_id = Some(StreamInfo::new(sender.id.clone(), StreamInfoKind::Publisher).to_string());
 
     // Create an srt connection to the server
-    let server = TransmissionSocket::connect(addr, opt.clone())?;
+    let server = Arc::new(TransmissionSocket::connect(addr, opt.clone())?);
+
+    // The relay server may have a smaller MTU than the sender.
+    let max_pkt_size = server.max_pkt_size().unwrap_or(opt.max_pkt_size());
 
-    log::info!("sender connect to relay server, addr={}", addr);
+    log::info!(
+        "sender connect to relay server, addr={}, max_pkt_size={}",
+        addr,
+        max_pkt_size
+    );
+
+    // The relay server forwards the messages of the subscribers to the publisher,
+    // the answers to the keepalives come from all the subscribers.
+    let heartbeat = Heartbeat::new();
+    sender.links = Links::Relay(Arc::downgrade(&server), heartbeat.clone());
+    spawn_message_reader(server.clone(), sender.handler.clone(), heartbeat, addr)?;
+
+    if let Some(keepalive) = keepalive {
+        keepalive::spawn_pinger(Arc::downgrade(&sender.adapter), keepalive)?;
+    }
 
     let id = sender.id.clone();
+    let stream = Package::stream_id(&id);
+    let dumper = PacketDumper::from_directory(&id, DumpSide::Sender);
     let adapter_ = Arc::downgrade(&sender.adapter);
-    thread::Builder::new()
-        .name("HylaranaStreamRelaySenderThread".to_string())
-        .spawn(move || {
-            let mut encoder = TransmissionFragmentEncoder::new(opt.max_pkt_size());
-
-            // If the adapter has been released, close the current thread
-            'a: while let Some(adapter) = adapter_.upgrade() {
-                if let Some((buf, kind, flags, timestamp)) = adapter.next() {
-                    if buf.is_empty() {
-                        continue;
-                    }
+    runtime::spawn(ThreadKind::Transport, "StreamRelaySender", move || {
+        let mut encoder = TransmissionFragmentEncoder::new(max_pkt_size);
+        let mut pacer = pacing.map(Pacer::new);
+
+        // If the adapter has been released, close the current thread
+        'a: while let Some(adapter) = adapter_.upgrade() {
+            if let Some((buf, kind, flags, timestamp)) = adapter.next() {
+                if buf.is_empty() {
+                    continue;
+                }
 
-                    // Packaging audio and video information
-                    let payload = Package::pack(
-                        PacketInfo {
-                            kind,
-                            flags,
-                            timestamp,
-                        },
-                        buf,
-                    );
+                // Packaging audio and video information
+                let payload = Package::pack(
+                    PacketInfo {
+                        kind,
+                        flags,
+                        timestamp,
+                        stream,
+                        fec_group: 0,
+                    },
+                    buf,
+                );
+
+                if let Some(dumper) = dumper.as_ref() {
+                    dumper.sent(&payload);
+                }
+
+                if let Some(pacing) = adapter.take_pacing() {
+                    pacer = pacing.map(Pacer::new);
+                }
 
-                    // SRT does not perform data fragmentation. It needs to be split
-                    // into fragments that do not exceed
-                    // the MTU size.
-                    for chunk in encoder.encode(&payload) {
-                        if let Err(e) = server.send(chunk) {
-                            log::error!("failed to send buf in srt, err={:?}", e);
+                if let Some(pacer) = pacer.as_mut() {
+                    pacer.begin(kind, flags, timestamp, payload.len(), adapter.pending() > 0);
+                }
 
-                            break 'a;
-                        }
+                // SRT does not perform data fragmentation. It needs to be split
+                // into fragments that do not exceed
+                // the MTU size.
+                for chunk in encoder.encode(&payload) {
+                    if let Some(pacer) = pacer.as_mut() {
+                        pacer.wait(chunk.len());
+                    }
+
+                    if let Err(e) = server.send(chunk) {
+                        log::error!("failed to send buf in srt, err={:?}", e);
+
+                        break 'a;
                     }
-                } else {
-                    break;
                 }
+            } else {
+                break;
             }
+        }
 
-            log::info!("srt relay sender is closed, id={}, addr={}", id, addr);
+        log::info!("srt relay sender is closed, id={}, addr={}", id, addr);
 
-            if let Some(adapter) = adapter_.upgrade() {
-                adapter.close();
-            }
-        })?;
+        server.close();
+
+        if let Some(adapter) = adapter_.upgrade() {
+            adapter.close();
+        }
+    })?;
 
     Ok(sender)
 }
 
-fn create_direct_sender(addr: SocketAddr, mtu: usize) -> Result<Sender, Error> {
-    let sender = Sender::default();
-    let sockets = Arc::new(RwLock::new(
-        HashMap::<SocketAddr, TransmissionSocket>::with_capacity(10),
-    ));
+fn create_direct_sender(
+    id: String,
+    addr: SocketAddr,
+    mtu: usize,
+    dscp: Option<DscpOptions>,
+    pacing: Option<PacketPacingOptions>,
+    keepalive: Option<KeepaliveOptions>,
+    latency: Option<Duration>,
+) -> Result<Sender, Error> {
+    let mut sender = Sender::new(id);
+    let sockets: Arc<DirectSockets> = Arc::new(RwLock::new(HashMap::with_capacity(10)));
+    sender.links = Links::Direct(Arc::downgrade(&sockets));
 
     // Configuration of the srt server. Since this suite only works within the LAN,
     // the delay is set to the minimum delay without considering network factors.
     let mut opt = TransmissionOptions::default();
     opt.mtu = mtu as u32;
-    opt.latency = 20;
+    opt.latency = srt_latency(latency);
+    opt.peer_latency = opt.latency;
     opt.fc = 32;
+    // The accepted sockets take the options of the server.
+    opt.dscp = dscp.map(|it| it.video);
+    opt.linger = GOODBYE_LINGER;
+
+    // The receivers are checked in the handshake, the rejected receivers do not
+    // take a place in the list of the sockets.
+    let pairing = sender.pairing.clone();
+    let pairing_handler = sender.pairing_handler.clone();
+    let handler = Box::new(move |peer: Option<SocketAddr>, stream_id: Option<&str>| {
+        let code = stream_id
+            .and_then(|it| StreamInfo::from_str(it).ok())
+            .and_then(|it| it.pairing_code);
+
+        let result = match (pairing.write().as_mut(), code) {
+            (None, _) => return true,
+            // The receiver asks the user for the code after the rejection.
+            (Some(_), None) => Err(None),
+            (Some(pairing), Some(code)) => pairing.verify(peer.map(|it| it.ip()), &code),
+        };
+
+        match result {
+            Ok(_) => true,
+            Err(code) => {
+                log::warn!("srt direct server reject an unpaired socket");
+
+                if let Some(code) = code {
+                    log::info!("srt direct server generated a new pairing code");
+
+                    if let Some(handler) = pairing_handler.read().as_ref() {
+                        handler(&code);
+                    }
+                }
+
+                false
+            }
+        }
+    });
 
     // Start the srt server
-    let server = Arc::new(TransmissionServer::bind(addr, opt.clone(), 100)?);
+    let server = Arc::new(TransmissionServer::bind_with_handler(
+        addr,
+        opt.clone(),
+        100,
+        handler,
+    )?);
 
     log::info!("sender create srt server, addr={}", addr);
 
     let id = sender.id.clone();
     let server_ = server.clone();
+    let handler = sender.handler.clone();
     let sockets_ = Arc::downgrade(&sockets);
-    thread::Builder::new()
-        .name("HylaranaStreamDirectSrtServerThread".to_string())
-        .spawn(move || {
-            while let Ok((socket, addr)) = server_.accept() {
-                if let Some(sockets) = sockets_.upgrade() {
-                    sockets.write().insert(addr, socket);
-
-                    log::info!("srt direct server accept a socket, addr={}", addr);
-                } else {
-                    break;
+    runtime::spawn(ThreadKind::Transport, "StreamDirectSrtServer", move || {
+        let mut sequence = 0;
+
+        while let Ok((socket, addr)) = server_.accept() {
+            if let Some(sockets) = sockets_.upgrade() {
+                // The receivers of other versions cannot decode the packages, they
+                // are rejected instead of receiving a corrupted stream.
+                let info = socket
+                    .get_stream_id()
+                    .and_then(|it| StreamInfo::from_str(&it).ok());
+                if !info
+                    .as_ref()
+                    .map(StreamInfo::is_compatible)
+                    .unwrap_or(false)
+                {
+                    log::warn!(
+                        "srt direct server reject an incompatible socket, addr={}, info={:?}",
+                        addr,
+                        info
+                    );
+
+                    socket.close();
+                    continue;
+                }
+
+                let socket = Arc::new(socket);
+                let heartbeat = Heartbeat::new();
+                if let Err(e) =
+                    spawn_message_reader(socket.clone(), handler.clone(), heartbeat.clone(), addr)
+                {
+                    log::error!("failed to start srt message reader, err={:?}", e);
+
+                    continue;
                 }
+
+                // The receiver has discovered the MTU of the path, the connection
+                // takes the smaller MTU of the two sides.
+                let max_pkt_size = socket.max_pkt_size().unwrap_or(opt.max_pkt_size());
+                let tracks = info.map(|it| it.tracks).unwrap_or_default();
+
+                sequence += 1;
+                sockets.write().insert(
+                    addr,
+                    DirectSocket {
+                        id: sequence,
+                        joined: SystemTime::now(),
+                        socket,
+                        tracks,
+                        max_pkt_size,
+                        heartbeat,
+                    },
+                );
+
+                log::info!(
+                    "srt direct server accept a socket, addr={}, id={}, tracks={:?}, max_pkt_size={}",
+                    addr,
+                    sequence,
+                    tracks,
+                    max_pkt_size
+                );
+            } else {
+                break;
             }
+        }
+
+        log::info!("srt direct server is closed, id={}, addr={}", id, addr);
+    })?;
 
-            log::info!("srt direct server is closed, id={}, addr={}", id, addr);
-        })?;
+    if let Some(keepalive) = keepalive {
+        keepalive::spawn_pinger(Arc::downgrade(&sender.adapter), keepalive)?;
+    }
 
     let id = sender.id.clone();
+    let stream = Package::stream_id(&id);
+    let dumper = PacketDumper::from_directory(&id, DumpSide::Sender);
     let adapter_ = Arc::downgrade(&sender.adapter);
-    thread::Builder::new()
-        .name("HylaranaStreamDirectSenderThread".to_string())
-        .spawn(move || {
-            let mut encoder = TransmissionFragmentEncoder::new(opt.max_pkt_size());
-            let mut closed = Vec::with_capacity(10);
-
-            // If the adapter has been released, close the current thread
-            while let Some(adapter) = adapter_.upgrade() {
-                if let Some((buf, kind, flags, timestamp)) = adapter.next() {
-                    if buf.is_empty() {
+    runtime::spawn(ThreadKind::Transport, "StreamDirectSender", move || {
+        let mut encoders: HashMap<(StreamTracks, usize), TransmissionFragmentEncoder> =
+            HashMap::with_capacity(3);
+        let mut closed = Vec::with_capacity(10);
+        let mut pacer = pacing.map(Pacer::new);
+
+        // If the adapter has been released, close the current thread
+        while let Some(adapter) = adapter_.upgrade() {
+            if let Some((buf, kind, flags, timestamp)) = adapter.next() {
+                if buf.is_empty() {
+                    continue;
+                }
+
+                // Packaging audio and video information
+                let payload = Package::pack(
+                    PacketInfo {
+                        kind,
+                        flags,
+                        timestamp,
+                        stream,
+                        fec_group: 0,
+                    },
+                    buf,
+                );
+
+                if let Some(dumper) = dumper.as_ref() {
+                    dumper.sent(&payload);
+                }
+
+                if let Some(pacing) = adapter.take_pacing() {
+                    pacer = pacing.map(Pacer::new);
+                }
+
+                if let Some(pacer) = pacer.as_mut() {
+                    pacer.begin(kind, flags, timestamp, payload.len(), adapter.pending() > 0);
+                }
+
+                // The receivers of each tracks have their own sequence of the packets,
+                // so the packets of the other track are not taken as lost.
+                for tracks in [StreamTracks::All, StreamTracks::Video, StreamTracks::Audio] {
+                    if !tracks.contains(kind, flags) {
                         continue;
                     }
 
-                    // Packaging audio and video information
-                    let payload = Package::pack(
-                        PacketInfo {
-                            kind,
-                            flags,
-                            timestamp,
-                        },
-                        buf,
-                    );
+                    let sockets = sockets.read();
+
+                    // The receivers with a smaller MTU take the fragments of their own
+                    // encoder.
+                    let mut sizes = sockets
+                        .values()
+                        .filter(|it| it.tracks == tracks)
+                        .map(|it| it.max_pkt_size)
+                        .collect::<Vec<_>>();
+
+                    sizes.sort_unstable();
+                    sizes.dedup();
+
+                    for size in sizes {
+                        let encoder = encoders
+                            .entry((tracks, size))
+                            .or_insert_with(|| TransmissionFragmentEncoder::new(size));
+
+                        // SRT does not perform data fragmentation. It needs to be split
+                        // into fragments that do not exceed
+                        // the MTU size.
+                        for chunk in encoder.encode(&payload) {
+                            if let Some(pacer) = pacer.as_mut() {
+                                pacer.wait(chunk.len());
+                            }
 
-                    // SRT does not perform data fragmentation. It needs to be split
-                    // into fragments that do not exceed
-                    // the MTU size.
-                    for chunk in encoder.encode(&payload) {
-                        {
-                            for (addr, socket) in sockets.read().iter() {
-                                if socket.send(chunk).is_err() {
+                            for (addr, it) in sockets
+                                .iter()
+                                .filter(|(_, it)| it.tracks == tracks && it.max_pkt_size == size)
+                            {
+                                if it.socket.send(chunk).is_err() && !closed.contains(addr) {
                                     log::info!(
                                         "srt direct server send to socket failed, addr={}",
                                         addr
@@ -258,38 +812,114 @@ fn create_direct_sender(addr: SocketAddr, mtu: usize) -> Result<Sender, Error> {
                                 }
                             }
                         }
+                    }
+                }
 
-                        if !closed.is_empty() {
-                            for addr in &closed {
-                                sockets.write().remove(addr);
-                            }
+                // The receivers that have not answered the keepalives in time are
+                // only open on this side.
+                if let Some(keepalive) = keepalive {
+                    for (addr, it) in sockets.read().iter() {
+                        if it.heartbeat.is_expired(keepalive.timeout) && !closed.contains(addr) {
+                            log::info!(
+                                "srt direct server lost a socket by keepalive, addr={}",
+                                addr
+                            );
+
+                            closed.push(*addr);
+                        }
+                    }
+                }
 
-                            closed.clear();
+                // The message reader also holds the socket, so the socket needs
+                // to be closed explicitly.
+                if !closed.is_empty() {
+                    for addr in &closed {
+                        if let Some(it) = sockets.write().remove(addr) {
+                            it.socket.close();
                         }
                     }
-                } else {
-                    break;
+
+                    closed.clear();
                 }
+            } else {
+                break;
             }
+        }
 
-            log::info!("srt direct sender is closed, id={}, addr={}", id, addr);
+        log::info!("srt direct sender is closed, id={}, addr={}", id, addr);
 
-            server.close();
-            if let Some(adapter) = adapter_.upgrade() {
-                adapter.close();
-            }
-        })?;
+        for (_, it) in sockets.write().drain() {
+            it.socket.close();
+        }
+
+        server.close();
+        if let Some(adapter) = adapter_.upgrade() {
+            adapter.close();
+        }
+    })?;
 
     Ok(sender)
 }
 
-/// Create a sender, the sender only sends data and does not receive data, and
-/// no sender has a separate ID, you can get the ID of the current sender by
-/// `get_id`.
+/// Create a sender, the sender only sends data and receives only the messages
+/// of the back channel, and no sender has a separate ID, you can get the ID of
+/// the current sender by `get_id`.
 pub fn create_sender(options: TransportOptions) -> Result<Sender, Error> {
+    create_sender_with_id(Uuid::new_v4().to_string(), options)
+}
+
+/// Create a sender with an ID of the application, which stays the same when
+/// the sender is restarted, so that the receivers that wait for the sender
+/// reattach to it, see `TransportOptions::resume`.
+///
+/// The ID cannot be empty, longer than 256 bytes or contain commas.
+pub fn create_sender_with_id(id: String, options: TransportOptions) -> Result<Sender, Error> {
+    // The fields of the stream info in the srt handshake are separated by
+    // commas.
+    if id.is_empty() || id.len() > 256 || id.contains(',') {
+        return Err(Error::new(ErrorKind::InvalidInput, "invalid stream id"));
+    }
+
     match options.strategy {
-        TransportStrategy::Multicast(addr) => create_multicast_sender(addr, options.mtu),
-        TransportStrategy::Direct(addr) => create_direct_sender(addr, options.mtu),
-        TransportStrategy::Relay(addr) => create_relay_sender(addr, options.mtu),
+        TransportStrategy::Multicast(addr) => create_multicast_sender(
+            id,
+            addr,
+            mtu::resolve(options.mtu, addr),
+            options.multicast,
+            options.dscp,
+            options.pacing,
+        ),
+        // The receivers discover the path when they connect, the listener takes the
+        // largest MTU and the handshake agrees on the smaller one.
+        TransportStrategy::Direct(addr) => create_direct_sender(
+            id,
+            addr,
+            mtu::clamp(options.mtu, mtu::MAX_MTU),
+            options.dscp,
+            options.pacing,
+            options.keepalive,
+            options.latency,
+        ),
+        TransportStrategy::Relay(addr) => create_relay_sender(
+            id,
+            addr,
+            mtu::resolve(options.mtu, addr),
+            options.dscp,
+            options.pacing,
+            options.keepalive,
+            options.latency,
+        ),
+        TransportStrategy::Loopback(_) => {
+            let sender = Sender::new(id);
+            loopback::create_sender(&sender.id, &sender.adapter, sender.handler.clone())?;
+
+            Ok(sender)
+        }
+        TransportStrategy::SharedMemory(shared_memory) => {
+            let sender = Sender::new(id);
+            shm::create_sender(&sender.id, shared_memory, &sender.adapter)?;
+
+            Ok(sender)
+        }
     }
 }
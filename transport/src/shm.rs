@@ -0,0 +1,471 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{Error, ErrorKind},
+    path::PathBuf,
+    ptr,
+    sync::{
+        atomic::{fence, AtomicU32, AtomicU64, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use hylarana_common::runtime::{self, ThreadKind};
+use memmap2::MmapMut;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    adapter::StreamReceiverAdapterAbstract, receiver::Resume, Package, StreamKind,
+    StreamSenderAdapter, StreamTracks,
+};
+
+/// Options of the shared memory transport.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SharedMemoryOptions {
+    /// The size of the ring buffer in bytes, which has to hold a few of the
+    /// raw frames, a frame of 1080p takes about 3 MB. Only the option of the
+    /// sender is used.
+    pub capacity: usize,
+}
+
+impl Default for SharedMemoryOptions {
+    fn default() -> Self {
+        Self {
+            capacity: 64 * 1024 * 1024,
+        }
+    }
+}
+
+const MAGIC: u32 = 0x4853_4D52;
+
+// | magic (u32) | padding | reserved (u64) | committed (u64) | last (u64) | closed (u32) |
+//
+// The positions count all the bytes that have been written. The writer moves
+// `reserved` to the end of a record before it writes the record, and
+// `committed` after, `last` is the start of the last complete record.
+const HEADER: usize = 64;
+
+// | size (u32) | kind (u8) | padding | flags (i32) | timestamp (u64) | payload |
+const RECORD_HEADER: usize = 20;
+
+// How long a receiver waits before it looks at the ring again when the ring
+// is empty.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+fn shared_memory_path(id: &str) -> PathBuf {
+    // The files of tmpfs are not written back to the disk.
+    #[cfg(target_os = "linux")]
+    {
+        let path = PathBuf::from("/dev/shm");
+        if path.is_dir() {
+            return path.join(format!("hylarana-{:08x}", Package::stream_id(id)));
+        }
+    }
+
+    std::env::temp_dir().join(format!("hylarana-{:08x}.shm", Package::stream_id(id)))
+}
+
+// A ring buffer that one sender writes and any number of receivers read, none
+// of them waits for another. The sender overwrites the oldest records, a
+// receiver that falls behind finds out that its records have been overwritten
+// and continues from the latest record.
+struct Ring {
+    // The memory is accessed through the pointer, the map only keeps it alive.
+    #[allow(dead_code)]
+    map: MmapMut,
+    ptr: *mut u8,
+    capacity: u64,
+}
+
+unsafe impl Send for Ring {}
+unsafe impl Sync for Ring {}
+
+impl Ring {
+    fn map(file: &File) -> Result<Self, Error> {
+        let mut map = unsafe { MmapMut::map_mut(file)? };
+        if map.len() < HEADER {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid shared memory"));
+        }
+
+        let ptr = map.as_mut_ptr();
+        let capacity = (map.len() - HEADER) as u64;
+        Ok(Self { map, ptr, capacity })
+    }
+
+    fn atomic32(&self, offset: usize) -> &AtomicU32 {
+        unsafe { &*(self.ptr.add(offset) as *const AtomicU32) }
+    }
+
+    fn atomic64(&self, offset: usize) -> &AtomicU64 {
+        unsafe { &*(self.ptr.add(offset) as *const AtomicU64) }
+    }
+
+    fn magic(&self) -> &AtomicU32 {
+        self.atomic32(0)
+    }
+
+    fn reserved(&self) -> &AtomicU64 {
+        self.atomic64(8)
+    }
+
+    fn committed(&self) -> &AtomicU64 {
+        self.atomic64(16)
+    }
+
+    fn last(&self) -> &AtomicU64 {
+        self.atomic64(24)
+    }
+
+    fn closed(&self) -> &AtomicU32 {
+        self.atomic32(32)
+    }
+
+    fn copy_in(&self, position: u64, data: &[u8]) {
+        let offset = (position % self.capacity) as usize;
+        let first = data.len().min(self.capacity as usize - offset);
+
+        unsafe {
+            let base = self.ptr.add(HEADER);
+            ptr::copy_nonoverlapping(data.as_ptr(), base.add(offset), first);
+            ptr::copy_nonoverlapping(data.as_ptr().add(first), base, data.len() - first);
+        }
+    }
+
+    fn copy_out(&self, position: u64, data: &mut [u8]) {
+        let offset = (position % self.capacity) as usize;
+        let first = data.len().min(self.capacity as usize - offset);
+
+        unsafe {
+            let base = self.ptr.add(HEADER);
+            ptr::copy_nonoverlapping(base.add(offset), data.as_mut_ptr(), first);
+            ptr::copy_nonoverlapping(base, data.as_mut_ptr().add(first), data.len() - first);
+        }
+    }
+}
+
+struct Writer {
+    ring: Ring,
+    path: PathBuf,
+}
+
+impl Writer {
+    fn create(id: &str, capacity: usize) -> Result<Self, Error> {
+        let path = shared_memory_path(id);
+
+        // The file of a sender with the same id that has not been cleaned up, such
+        // as after a crash, is removed, a link is removed and not followed. The file
+        // of another user cannot be removed and the creation fails.
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+
+        // The ring holds the raw frames, so only the user can open it, and the file
+        // is created anew, a file that appears at the path in the meantime is an
+        // error.
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create_new(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            options.mode(0o600).custom_flags(libc::O_NOFOLLOW);
+        }
+
+        let file = options.open(&path)?;
+        file.set_len((HEADER + capacity) as u64)?;
+
+        let ring = Ring::map(&file)?;
+        ring.magic().store(MAGIC, Ordering::Release);
+
+        Ok(Self { ring, path })
+    }
+
+    // Returns false if the record does not fit in the ring, a record cannot take
+    // more than half of the ring, so that the receivers can read it while the
+    // next one is written.
+    fn write(&self, kind: StreamKind, flags: i32, timestamp: u64, payload: &[u8]) -> bool {
+        let size = (RECORD_HEADER + payload.len()) as u64;
+        if size > self.ring.capacity / 2 {
+            return false;
+        }
+
+        let start = self.ring.committed().load(Ordering::Relaxed);
+
+        // The receivers check `reserved` after copying a record, the fence keeps
+        // the new value in front of the bytes that overwrite the old records.
+        self.ring.reserved().store(start + size, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        let mut header = [0u8; RECORD_HEADER];
+        header[0..4].copy_from_slice(&(payload.len() as u32).to_le_bytes());
+        header[4] = kind as u8;
+        header[8..12].copy_from_slice(&flags.to_le_bytes());
+        header[12..20].copy_from_slice(&timestamp.to_le_bytes());
+
+        self.ring.copy_in(start, &header);
+        self.ring.copy_in(start + RECORD_HEADER as u64, payload);
+
+        self.ring.last().store(start, Ordering::Release);
+        self.ring.committed().store(start + size, Ordering::Release);
+        true
+    }
+
+    fn close(self) {
+        self.ring.closed().store(1, Ordering::Release);
+
+        // The file is removed after it has been unmapped, the receivers that have
+        // mapped it keep the memory.
+        let path = self.path;
+        drop(self.ring);
+
+        if let Err(e) = fs::remove_file(&path) {
+            log::warn!(
+                "failed to remove shared memory, path={:?}, err={:?}",
+                path,
+                e
+            );
+        }
+    }
+}
+
+enum ReadResult {
+    Record(StreamKind, i32, u64),
+    // The records have been overwritten before they were read.
+    Lost,
+    Empty,
+    Closed,
+}
+
+struct Reader {
+    ring: Ring,
+    position: u64,
+}
+
+impl Reader {
+    fn open(id: &str) -> Result<Self, Error> {
+        let path = shared_memory_path(id);
+
+        let mut options = OpenOptions::new();
+        options.read(true).write(true);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+
+            options.custom_flags(libc::O_NOFOLLOW);
+        }
+
+        let file = options.open(&path).map_err(|e| {
+            if e.kind() == ErrorKind::NotFound {
+                Error::new(ErrorKind::NotFound, "shared memory sender is not found")
+            } else {
+                e
+            }
+        })?;
+
+        // Only the senders of the same user are trusted, the directory is shared
+        // with the other users.
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            if file.metadata()?.uid() != unsafe { libc::geteuid() } {
+                return Err(Error::new(
+                    ErrorKind::PermissionDenied,
+                    "shared memory belongs to another user",
+                ));
+            }
+        }
+
+        let ring = Ring::map(&file)?;
+        if ring.magic().load(Ordering::Acquire) != MAGIC {
+            return Err(Error::new(ErrorKind::InvalidData, "invalid shared memory"));
+        }
+
+        // The receiver starts with the next record, the sender repeats the
+        // configuration in front of the key frames.
+        let position = ring.committed().load(Ordering::Acquire);
+        Ok(Self { ring, position })
+    }
+
+    fn read(&mut self, payload: &mut Vec<u8>) -> ReadResult {
+        let committed = self.ring.committed().load(Ordering::Acquire);
+        if self.position >= committed {
+            return if self.is_closed() {
+                ReadResult::Closed
+            } else {
+                ReadResult::Empty
+            };
+        }
+
+        if committed - self.position > self.ring.capacity {
+            self.skip();
+            return ReadResult::Lost;
+        }
+
+        let mut header = [0u8; RECORD_HEADER];
+        self.ring.copy_out(self.position, &mut header);
+
+        let size = u32::from_le_bytes(header[0..4].try_into().unwrap()) as usize;
+        if (size + RECORD_HEADER) as u64 <= self.ring.capacity / 2 {
+            payload.resize(size, 0);
+            self.ring
+                .copy_out(self.position + RECORD_HEADER as u64, payload);
+        }
+
+        // The record is only valid if the sender has not started to overwrite it
+        // while it was copied, the size itself may be garbage otherwise.
+        fence(Ordering::Acquire);
+        let reserved = self.ring.reserved().load(Ordering::Relaxed);
+        if reserved - self.position > self.ring.capacity
+            || (size + RECORD_HEADER) as u64 > self.ring.capacity / 2
+        {
+            self.skip();
+            return ReadResult::Lost;
+        }
+
+        self.position += (RECORD_HEADER + size) as u64;
+
+        match StreamKind::try_from(header[4]) {
+            Ok(kind) => ReadResult::Record(
+                kind,
+                i32::from_le_bytes(header[8..12].try_into().unwrap()),
+                u64::from_le_bytes(header[12..20].try_into().unwrap()),
+            ),
+            Err(_) => ReadResult::Lost,
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.ring.closed().load(Ordering::Acquire) != 0
+    }
+
+    // Continue from the latest record, or try the current record again if the
+    // sender has not finished the record that overwrites it.
+    fn skip(&mut self) {
+        self.position = self.position.max(self.ring.last().load(Ordering::Acquire));
+    }
+}
+
+pub(crate) fn create_sender(
+    id: &str,
+    options: SharedMemoryOptions,
+    adapter: &Arc<StreamSenderAdapter>,
+) -> Result<(), Error> {
+    let writer = Writer::create(id, options.capacity)?;
+
+    log::info!(
+        "create shared memory sender, id={}, path={:?}",
+        id,
+        writer.path
+    );
+
+    let id = id.to_string();
+    let adapter_ = Arc::downgrade(adapter);
+    runtime::spawn(
+        ThreadKind::Transport,
+        "StreamSharedMemorySender",
+        move || {
+            // If the adapter has been released, close the current thread
+            while let Some(adapter) = adapter_.upgrade() {
+                if let Some((buf, kind, flags, timestamp)) = adapter.next() {
+                    if buf.is_empty() {
+                        continue;
+                    }
+
+                    if !writer.write(kind, flags, timestamp, &buf) {
+                        log::warn!("packet is too large for shared memory, size={}", buf.len());
+                    }
+                } else {
+                    break;
+                }
+            }
+
+            log::info!("shared memory sender is closed, id={}", id);
+
+            writer.close();
+            if let Some(adapter) = adapter_.upgrade() {
+                adapter.close();
+            }
+        },
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn create_receiver<T: StreamReceiverAdapterAbstract + 'static>(
+    id: &str,
+    tracks: StreamTracks,
+    resume: Resume,
+    adapter: &Arc<T>,
+) -> Result<(), Error> {
+    let mut reader = Reader::open(id)?;
+
+    log::info!("create shared memory receiver, id={}", id);
+
+    let id = id.to_string();
+    let adapter_ = Arc::downgrade(adapter);
+    runtime::spawn(
+        ThreadKind::Transport,
+        "StreamSharedMemoryReceiver",
+        move || {
+            let mut payload = Vec::with_capacity(4 * 1024 * 1024);
+
+            loop {
+                let Some(adapter) = adapter_.upgrade() else {
+                    break;
+                };
+
+                match reader.read(&mut payload) {
+                    ReadResult::Record(kind, flags, _) if !tracks.contains(kind, flags) => (),
+                    ReadResult::Record(kind, flags, timestamp) => {
+                        if !adapter.send(Bytes::copy_from_slice(&payload), kind, flags, timestamp) {
+                            log::error!("adapter on buf failed.");
+
+                            break;
+                        }
+                    }
+                    ReadResult::Lost => {
+                        log::warn!("shared memory receiver is behind the sender, id={}", id);
+
+                        adapter.lose();
+                    }
+                    ReadResult::Empty => thread::sleep(POLL_INTERVAL),
+                    ReadResult::Closed => {
+                        adapter.lose();
+                        drop(adapter);
+
+                        // The file of the old sender may not have been removed yet, its
+                        // ring is closed.
+                        match resume.wait(|| Reader::open(&id).ok().filter(|it| !it.is_closed())) {
+                            Some(it) => {
+                                reader = it;
+
+                                log::info!(
+                                    "shared memory receiver reattached to sender, id={}",
+                                    id
+                                );
+
+                                resume.reattached();
+                            }
+                            None => break,
+                        }
+                    }
+                }
+            }
+
+            log::warn!("shared memory receiver is closed, id={}", id);
+
+            if let Some(adapter) = adapter_.upgrade() {
+                adapter.close();
+            }
+        },
+    )?;
+
+    Ok(())
+}
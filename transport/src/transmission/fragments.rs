@@ -78,13 +78,7 @@ impl FragmentDecoder {
             let sequence = bytes.get_u64() as i128;
             let size = bytes.get_u32() as usize;
             if sequence != self.sequence {
-                if !self.bytes.is_empty() && self.bytes.len() >= self.size {
-                    result = Some((
-                        self.sequence as u64,
-                        Bytes::copy_from_slice(&self.bytes[..self.size]),
-                    ));
-                }
-
+                // The rest of the previous packet has been lost.
                 self.bytes.clear();
             }
 
@@ -92,6 +86,19 @@ impl FragmentDecoder {
             self.size = size;
 
             self.bytes.put(bytes);
+
+            // The packet is passed on as soon as its last fragment arrives, the last
+            // packet of the stream, such as the goodbye, does not wait for a next one.
+            // The packet is split off instead of copied, the buffer is reclaimed by the
+            // next write once the packet has been dropped.
+            if self.bytes.len() >= self.size {
+                result = Some((
+                    self.sequence as u64,
+                    self.bytes.split_to(self.size).freeze(),
+                ));
+
+                self.bytes.clear();
+            }
         }
 
         result
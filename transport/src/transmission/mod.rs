@@ -6,7 +6,7 @@ mod socket;
 pub use self::{
     fragments::{FragmentDecoder, FragmentEncoder},
     options::Options,
-    server::Server,
+    server::{ConnectHandler, Server},
     socket::Socket,
     SRT_TRACEBSTATS as TraceStats,
 };
@@ -80,6 +80,20 @@ pub fn cleanup() {
 pub(crate) type SRTSOCKET = i32;
 pub(crate) const SRT_INVALID_SOCK: i32 = -1;
 
+// The reject reasons that are not from SRT itself start here, the predefined
+// reasons follow the status codes of HTTP.
+pub(crate) const SRT_REJC_PREDEFINED: c_int = 1000;
+pub(crate) const SRT_REJC_UNAUTHORIZED: c_int = SRT_REJC_PREDEFINED + 401;
+
+#[allow(non_camel_case_types)]
+pub(crate) type srt_listen_callback_fn = extern "C" fn(
+    opaque: *mut c_void,
+    ns: SRTSOCKET,
+    hs_version: c_int,
+    peer: *const sockaddr,
+    stream_id: *const c_char,
+) -> c_int;
+
 #[repr(C)]
 #[allow(unused)]
 #[allow(non_camel_case_types)]
@@ -460,6 +474,22 @@ extern "C" {
     ///   allows
     /// the listener socket to accept group connections
     pub(crate) fn srt_listen(s: SRTSOCKET, backlog: c_int) -> c_int;
+    /// This call installs a callback hook, which will be executed on a socket
+    /// that is automatically created to handle the incoming connection on
+    /// the listening socket (and is about to be returned by `srt_accept`),
+    /// but before the connection has been accepted. The hook returns -1 to
+    /// reject the connection.
+    pub(crate) fn srt_listen_callback(
+        lsn: SRTSOCKET,
+        hook: srt_listen_callback_fn,
+        opaque: *mut c_void,
+    ) -> c_int;
+    /// Sets the reason of the rejection on the socket of a connection that is
+    /// rejected by the listen callback, the caller gets it with
+    /// `srt_getrejectreason`.
+    pub(crate) fn srt_setrejectreason(s: SRTSOCKET, value: c_int) -> c_int;
+    /// Provides a detailed reason for a failed connection attempt.
+    pub(crate) fn srt_getrejectreason(s: SRTSOCKET) -> c_int;
     /// Accepts a pending connection, then creates and returns a new socket or
     /// group ID that handles this connection. The group and socket can be
     /// distinguished by checking the SRTGROUP_MASK bit on the returned ID.
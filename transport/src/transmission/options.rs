@@ -14,10 +14,30 @@ pub struct Options {
     pub stream_id: Option<String>,
     pub max_bandwidth: i64,
     pub latency: u32,
+    /// The latency that the socket asks the peer to receive with, the peer
+    /// takes the larger of it and its own latency, zero leaves it to the peer.
+    pub peer_latency: u32,
     pub timeout: u32,
     pub fec: String,
     pub mtu: u32,
     pub fc: u32,
+    /// The DSCP value that the packets of the socket are marked with.
+    pub dscp: Option<u8>,
+    /// How long the socket keeps sending the packets that are still in its
+    /// buffer after it is closed, in seconds, zero drops them.
+    pub linger: u32,
+    /// How long a read waits for a packet before it fails, in milliseconds,
+    /// the read waits forever if this is `None`.
+    pub read_timeout: Option<u32>,
+}
+
+// The `linger` struct of the socket API, SRT takes the same layout on all the
+// platforms.
+#[repr(C)]
+#[derive(Debug, PartialEq)]
+struct Linger {
+    onoff: c_int,
+    linger: c_int,
 }
 
 impl Options {
@@ -30,9 +50,26 @@ impl Options {
         set_sock_opt(fd, SRT_SOCKOPT::SRTO_FC, &self.fc)?;
         set_sock_opt(fd, SRT_SOCKOPT::SRTO_MSS, &self.mtu)?;
         set_sock_opt(fd, SRT_SOCKOPT::SRTO_RCVLATENCY, &self.latency)?;
+        set_sock_opt(fd, SRT_SOCKOPT::SRTO_PEERLATENCY, &self.peer_latency)?;
         set_sock_opt(fd, SRT_SOCKOPT::SRTO_MAXBW, &self.max_bandwidth)?;
         set_sock_opt(fd, SRT_SOCKOPT::SRTO_PEERIDLETIMEO, &self.timeout)?;
         set_sock_opt_str(fd, SRT_SOCKOPT::SRTO_PACKETFILTER, &self.fec)?;
+        set_sock_opt(
+            fd,
+            SRT_SOCKOPT::SRTO_LINGER,
+            &Linger {
+                onoff: (self.linger > 0) as c_int,
+                linger: self.linger as c_int,
+            },
+        )?;
+
+        if let Some(timeout) = self.read_timeout {
+            set_sock_opt(fd, SRT_SOCKOPT::SRTO_RCVTIMEO, &(timeout as i32))?;
+        }
+
+        if let Some(dscp) = self.dscp {
+            set_sock_opt(fd, SRT_SOCKOPT::SRTO_IPTOS, &((dscp as i32) << 2))?;
+        }
 
         if let Some(stream_id) = &self.stream_id {
             set_sock_opt_str(fd, SRT_SOCKOPT::SRTO_STREAMID, stream_id)?;
@@ -42,10 +79,16 @@ impl Options {
     }
 
     pub const fn max_pkt_size(&self) -> usize {
-        (self.mtu as usize) - (1500 - 1316)
+        max_pkt_size(self.mtu as usize)
     }
 }
 
+// The largest payload of a packet in the live mode, the headers of SRT, UDP and
+// IP take the rest of the MTU.
+pub(crate) const fn max_pkt_size(mtu: usize) -> usize {
+    mtu.saturating_sub(1500 - 1316)
+}
+
 impl Default for Options {
     fn default() -> Self {
         Self {
@@ -54,8 +97,12 @@ impl Default for Options {
             stream_id: None,
             timeout: 5000,
             latency: 120,
+            peer_latency: 0,
             mtu: 1500,
             fc: 25600,
+            dscp: None,
+            linger: 0,
+            read_timeout: None,
         }
     }
 }
@@ -1,16 +1,55 @@
-use std::{ffi::c_int, io::Error, net::SocketAddr};
+use std::{
+    ffi::{c_char, c_int, c_void},
+    io::Error,
+    net::SocketAddr,
+};
 
+use hylarana_common::strings::PSTR;
+use libc::sockaddr;
 use os_socketaddr::OsSocketAddr;
 
 use super::{srt_getsockstate, SRT_SOCKSTATUS};
 
 use super::{
     error, options::Options, socket::Socket, srt_accept, srt_bind, srt_bstats, srt_close,
-    srt_create_socket, srt_getsockname, srt_listen, TraceStats, SRTSOCKET, SRT_INVALID_SOCK,
+    srt_create_socket, srt_getsockname, srt_listen, srt_listen_callback, srt_setrejectreason,
+    TraceStats, SRTSOCKET, SRT_INVALID_SOCK, SRT_REJC_UNAUTHORIZED,
 };
 
+/// Checks the peer address and the stream id of a connection before it is
+/// accepted, the connections that it returns false for are rejected as
+/// unauthorized.
+pub type ConnectHandler = Box<dyn Fn(Option<SocketAddr>, Option<&str>) -> bool + Send + Sync>;
+
+extern "C" fn listen_callback(
+    opaque: *mut c_void,
+    ns: SRTSOCKET,
+    _hs_version: c_int,
+    peer: *const sockaddr,
+    stream_id: *const c_char,
+) -> c_int {
+    // SRT passes its own address type, which has the room of an ipv6 address.
+    let peer =
+        unsafe { OsSocketAddr::copy_from_raw(peer as *const _, OsSocketAddr::new().capacity()) };
+
+    let handler = unsafe { &*(opaque as *const ConnectHandler) };
+    if handler(
+        peer.into_addr(),
+        PSTR::from(stream_id).to_string().ok().as_deref(),
+    ) {
+        0
+    } else {
+        unsafe { srt_setrejectreason(ns, SRT_REJC_UNAUTHORIZED) };
+
+        -1
+    }
+}
+
 pub struct Server {
     fd: SRTSOCKET,
+    // SRT holds a pointer to the handler, it lives as long as the listener.
+    #[allow(dead_code)]
+    handler: Option<Box<ConnectHandler>>,
 }
 
 unsafe impl Send for Server {}
@@ -144,23 +183,54 @@ impl Server {
     /// will fail. In all other cases this option is meaningless. See
     /// `SRTO_IPV6ONLY` option for more information.
     pub fn bind(addr: SocketAddr, opt: Options, backlog: u32) -> Result<Self, Error> {
+        Self::listen(addr, opt, backlog, None)
+    }
+
+    /// Same as `bind`, the handler checks the connections before they are
+    /// accepted, the rejected callers fail to connect with
+    /// `ErrorKind::PermissionDenied`.
+    pub fn bind_with_handler(
+        addr: SocketAddr,
+        opt: Options,
+        backlog: u32,
+        handler: ConnectHandler,
+    ) -> Result<Self, Error> {
+        Self::listen(addr, opt, backlog, Some(Box::new(handler)))
+    }
+
+    fn listen(
+        addr: SocketAddr,
+        opt: Options,
+        backlog: u32,
+        handler: Option<Box<ConnectHandler>>,
+    ) -> Result<Self, Error> {
         let fd = unsafe { srt_create_socket() };
         if fd == SRT_INVALID_SOCK {
             return Err(error());
-        } else {
-            opt.apply_socket(fd)?;
         }
 
+        // The socket is closed when the server is dropped.
+        let server = Self { fd, handler };
+        opt.apply_socket(fd)?;
+
         let addr: OsSocketAddr = addr.into();
         if unsafe { srt_bind(fd, addr.as_ptr() as *const _, addr.len() as c_int) } == -1 {
             return Err(error());
         }
 
+        // The hook is installed before the listener takes any connection.
+        if let Some(handler) = server.handler.as_ref() {
+            let opaque = handler.as_ref() as *const ConnectHandler as *mut c_void;
+            if unsafe { srt_listen_callback(fd, listen_callback, opaque) } == -1 {
+                return Err(error());
+            }
+        }
+
         if unsafe { srt_listen(fd, backlog as c_int) } == -1 {
             return Err(error());
         }
 
-        Ok(Self { fd })
+        Ok(server)
     }
 
     /// Accepts a pending connection, then creates and returns a new socket or
@@ -1,12 +1,23 @@
-use std::{ffi::c_int, io::Error, net::SocketAddr};
+use std::{
+    ffi::c_int,
+    io::{Error, ErrorKind},
+    mem::size_of,
+    net::SocketAddr,
+    time::Duration,
+};
 
 use os_socketaddr::OsSocketAddr;
 
-use super::{options::get_sock_opt_str, SRT_SOCKOPT};
+use super::{
+    options::{get_sock_opt_str, max_pkt_size},
+    SRT_SOCKOPT,
+};
+use crate::BandwidthEstimate;
 
 use super::{
-    error, options::Options, srt_bstats, srt_close, srt_connect, srt_create_socket, srt_recv,
-    srt_send, TraceStats, SRTSOCKET, SRT_INVALID_SOCK,
+    error, options::Options, srt_bstats, srt_close, srt_connect, srt_create_socket,
+    srt_getrejectreason, srt_getsockflag, srt_recv, srt_send, TraceStats, SRTSOCKET,
+    SRT_INVALID_SOCK, SRT_REJC_UNAUTHORIZED,
 };
 
 pub struct Socket {
@@ -22,6 +33,26 @@ impl Socket {
         get_sock_opt_str(self.fd, SRT_SOCKOPT::SRTO_STREAMID)
     }
 
+    /// The largest payload that can be sent on the socket, the two sides of
+    /// the connection agree on the smaller MTU of them during the handshake.
+    pub fn max_pkt_size(&self) -> Result<usize, Error> {
+        let mut mss: c_int = 0;
+        let mut size = size_of::<c_int>() as c_int;
+        if unsafe {
+            srt_getsockflag(
+                self.fd,
+                SRT_SOCKOPT::SRTO_MSS,
+                &mut mss as *mut c_int as *mut _,
+                &mut size,
+            )
+        } != 0
+        {
+            return Err(error());
+        }
+
+        Ok(max_pkt_size(mss as usize))
+    }
+
     /// Reports the current statistics
     ///
     /// Arguments:
@@ -38,6 +69,31 @@ impl Socket {
         Ok(stats)
     }
 
+    /// The smoothed round trip time of the connection, the statistics are not
+    /// cleared, see `estimate_bandwidth`.
+    pub fn rtt(&self) -> Result<Duration, Error> {
+        let mut stats = TraceStats::default();
+        if unsafe { srt_bstats(self.fd, &mut stats, false as i32) } != 0 {
+            return Err(error());
+        }
+
+        Ok(Duration::from_secs_f64(stats.ms_rtt.max(0.0) / 1000.0))
+    }
+
+    /// Estimate the link from the statistics, the statistics are cleared, so
+    /// the rate is the average since the previous estimate.
+    pub fn estimate_bandwidth(&self) -> Result<BandwidthEstimate, Error> {
+        let stats = self.get_stats()?;
+
+        // One side of the stream only sends and the other side only receives.
+        Ok(BandwidthEstimate {
+            bandwidth: (stats.mbps_bandwidth * 1_000_000.0) as u64,
+            rate: (stats.mbps_send_rate.max(stats.mbps_recv_rate) * 1_000_000.0) as u64,
+            rtt: Duration::from_secs_f64(stats.ms_rtt.max(0.0) / 1000.0),
+            mtu: stats.byte_mss.max(0) as usize,
+        })
+    }
+
     /// Connects a socket or a group to a remote party with a specified address
     /// and port.
     ///
@@ -109,7 +165,16 @@ impl Socket {
 
         let addr: OsSocketAddr = addr.into();
         if unsafe { srt_connect(fd, addr.as_ptr() as *const _, addr.len() as c_int) } == -1 {
-            return Err(error());
+            // The listener has rejected the connection on purpose, such as a receiver
+            // without the pairing code of the sender.
+            let err = if unsafe { srt_getrejectreason(fd) } == SRT_REJC_UNAUTHORIZED {
+                Error::new(ErrorKind::PermissionDenied, "connection is unauthorized")
+            } else {
+                error()
+            };
+
+            unsafe { srt_close(fd) };
+            return Err(err);
         }
 
         Ok(Self::new(fd))